@@ -0,0 +1,350 @@
+use std::env;
+use std::sync::Arc;
+use actix_web::web::{self, Data};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
+use tracing::{info, warn};
+use crate::config::AppConfig;
+use crate::models::entitlement::FREE_TIER_PLAN_ID;
+use crate::services::{
+    attachments::AttachmentService,
+    clock::{Clock, SystemClock},
+    database::DatabaseService,
+    email::EmailService,
+    fx::FxService,
+    gateway::PaymentGateway,
+    health::{HealthService, TaskHeartbeat},
+    ozow::OzowPaymentService,
+    payment_events::PaymentEvents,
+    peach::{self, PeachEnvironment, PeachPaymentService},
+    rate_limit::RateLimiter,
+    request_signing::RequestSigner,
+    resilience::HttpSettings,
+    sandbox::SandboxGateway,
+    shutdown::{self, ShutdownSignal, ShutdownTrigger},
+    storage::Storage,
+    stripe::StripePaymentService,
+    tenant_gateway::TenantPeachGateway,
+};
+use crate::tasks;
+
+/// Every long-lived service the API and background tasks share, built once at
+/// startup. Fields are public so a test can swap one component (a fake
+/// gateway, a fixed clock) before calling `configure` or
+/// `spawn_background_tasks`; everything else stays as wired here.
+#[derive(Clone)]
+pub struct AppContainer {
+    pub config: Arc<AppConfig>,
+    pub database: DatabaseService,
+    /// The same database behind the storage traits, for handlers that don't
+    /// need SurrealDB specifics.
+    pub storage: Arc<dyn Storage>,
+    pub gateway: Arc<dyn PaymentGateway>,
+    /// EFT is routed to Ozow when it's configured.
+    pub ozow: Option<Arc<OzowPaymentService>>,
+    pub payment_events: PaymentEvents,
+    pub clock: Arc<dyn Clock>,
+    pub email: Arc<EmailService>,
+    pub fx: Arc<FxService>,
+    pub request_signer: Arc<RequestSigner>,
+    pub attachments: Arc<AttachmentService>,
+    pub rate_limiter: Arc<RateLimiter>,
+    /// Shared with the renewal task, which beats it after every pass.
+    pub renewal_heartbeat: TaskHeartbeat,
+    pub health: Arc<HealthService>,
+    shutdown: ShutdownTrigger,
+    shutdown_signal: ShutdownSignal,
+}
+
+/// Background tasks that write to the database, awaited on shutdown so a
+/// renewal batch or job run isn't cut off halfway.
+pub struct BackgroundTasks {
+    draining: Vec<(&'static str, JoinHandle<()>)>,
+}
+
+impl AppContainer {
+    /// Validates configuration and constructs every service from the
+    /// environment. Fails with every missing or invalid setting at once.
+    pub async fn from_env() -> Result<Self, String> {
+        // SurrealDB is the only database; fail loudly rather than ignore a
+        // DATABASE_URL meant for another one
+        if let Ok(url) = env::var("DATABASE_URL") {
+            if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+                return Err("DATABASE_URL selects Postgres, which isn't supported; unset it to use SurrealDB".to_string());
+            }
+        }
+
+        let config = AppConfig::from_env();
+        config.log_summary();
+        config.validate().map_err(|e| e.to_string())?;
+
+        let mut gateway = gateway_from_env()?;
+        info!("Using {} payment gateway ({})", gateway.name(), if gateway.is_live() { "live" } else { "test" });
+        refuse_live_in_test_mode(&config, gateway.as_ref())?;
+        if config.sandbox_mode {
+            info!("SANDBOX_MODE is on: mock card tokens are charged locally");
+            gateway = Arc::new(SandboxGateway::new(gateway));
+        }
+
+        let ozow = OzowPaymentService::from_env(currency_list("OZOW_SUPPORTED_CURRENCIES"))
+            .map_err(|e| format!("Failed to configure Ozow: {}", e))?
+            .map(Arc::new);
+        if let Some(ozow) = &ozow {
+            info!("EFT payments will use Ozow");
+            refuse_live_in_test_mode(&config, ozow.as_ref())?;
+        }
+
+        let fx = FxService::from_env().map_err(|e| format!("Failed to configure exchange rates: {}", e))?;
+        let email = EmailService::from_env().map_err(|e| format!("Failed to configure email service: {}", e))?;
+
+        let payment_events = PaymentEvents::new();
+        let database = DatabaseService::new(payment_events.clone())
+            .await
+            .map_err(|e| format!("Failed to initialize database service: {}", e))?;
+
+        let request_signer = RequestSigner::from_env(database.clone()).map_err(|e| format!("Failed to configure request signing: {}", e))?;
+        if !request_signer.is_enabled() {
+            warn!("HMAC_SIGNING_KEYS not set; signed requests are not enforced");
+        }
+        let rate_limiter = RateLimiter::new(database.clone(), &config);
+        let renewal_heartbeat = TaskHeartbeat::new();
+        let health = HealthService::new(gateway.clone(), renewal_heartbeat.clone());
+        let (shutdown, shutdown_signal) = shutdown::channel();
+
+        Ok(Self {
+            config: Arc::new(config),
+            storage: Arc::new(database.clone()),
+            database,
+            gateway,
+            ozow,
+            payment_events,
+            clock: Arc::new(SystemClock),
+            email: Arc::new(email),
+            fx: Arc::new(fx),
+            request_signer: Arc::new(request_signer),
+            attachments: Arc::new(AttachmentService::from_env()),
+            rate_limiter: Arc::new(rate_limiter),
+            renewal_heartbeat,
+            health: Arc::new(health),
+            shutdown,
+            shutdown_signal,
+        })
+    }
+
+    /// Startup dependency checks. The database must answer; a payment provider
+    /// that doesn't is only reported, so a provider outage doesn't also take
+    /// down everything that doesn't need it.
+    pub async fn check_dependencies(&self) -> Result<(), String> {
+        self.database.health_check().await?;
+        info!("Database reachable");
+
+        if let Some(plan_id) = &self.config.dunning_fallback_plan_id {
+            if plan_id != FREE_TIER_PLAN_ID && self.database.get_plan(plan_id).await.is_none() {
+                return Err(format!("DUNNING_FALLBACK_PLAN_ID refers to unknown plan {}", plan_id));
+            }
+        }
+
+        let mut gateways: Vec<&dyn PaymentGateway> = vec![self.gateway.as_ref()];
+        if let Some(ozow) = &self.ozow {
+            gateways.push(ozow.as_ref());
+        }
+        for gateway in gateways {
+            match gateway.health_check().await {
+                Ok(()) => info!("{} gateway check passed", gateway.name()),
+                Err(e) => warn!("{} gateway health check failed: {}", gateway.name(), e),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn spawn_background_tasks(&self) -> BackgroundTasks {
+        let db = Arc::new(self.database.clone());
+        let renewal = actix_rt::spawn(tasks::renewal_task::start_renewal_task(
+            db.clone(),
+            self.gateway.clone(),
+            self.config.clone(),
+            self.email.clone(),
+            self.clock.clone(),
+            self.renewal_heartbeat.clone(),
+            self.shutdown_signal.clone(),
+        ));
+        actix_rt::spawn(tasks::daily_summary_task::start_daily_summary_task(
+            db.clone(),
+            self.config.clone(),
+            self.email.clone(),
+            self.fx.clone(),
+            self.clock.clone(),
+        ));
+        let job_worker = actix_rt::spawn(tasks::job_worker_task::start_job_worker_task(
+            db.clone(),
+            self.config.clone(),
+            self.email.clone(),
+            self.shutdown_signal.clone(),
+        ));
+        actix_rt::spawn(tasks::export_task::start_export_task(
+            db.clone(),
+            self.config.clone(),
+            self.clock.clone(),
+        ));
+        let event_dispatcher = actix_rt::spawn(tasks::event_dispatcher_task::start_event_dispatcher_task(
+            db.clone(),
+            self.config.clone(),
+            self.shutdown_signal.clone(),
+        ));
+        let webhook_delivery = actix_rt::spawn(tasks::webhook_delivery_task::start_webhook_delivery_task(
+            db.clone(),
+            self.shutdown_signal.clone(),
+        ));
+        actix_rt::spawn(tasks::card_expiry_task::start_card_expiry_task(
+            db.clone(),
+            self.email.clone(),
+            self.clock.clone(),
+        ));
+        actix_rt::spawn(tasks::installment_task::start_installment_task(
+            db.clone(),
+            self.gateway.clone(),
+            self.config.clone(),
+            self.email.clone(),
+            self.clock.clone(),
+        ));
+        actix_rt::spawn(tasks::reconciliation_task::start_reconciliation_task(
+            db.clone(),
+            self.gateway.clone(),
+            self.clock.clone(),
+        ));
+        actix_rt::spawn(tasks::webhook_watchdog_task::start_webhook_watchdog_task(
+            db,
+            self.config.clone(),
+            self.email.clone(),
+            self.clock.clone(),
+            self.gateway.name().to_string(),
+        ));
+
+        BackgroundTasks {
+            draining: vec![
+                ("renewal", renewal),
+                ("job worker", job_worker),
+                ("event dispatcher", event_dispatcher),
+                ("webhook delivery", webhook_delivery),
+            ],
+        }
+    }
+
+    /// Call once the HTTP server has stopped: tells the background tasks to
+    /// stop and waits for each to finish its current batch. The other tasks
+    /// only read or run on a daily schedule and end with the runtime.
+    pub async fn shutdown(&self, tasks: BackgroundTasks) {
+        self.shutdown.trigger();
+        let timeout = self.config.shutdown_timeout_secs;
+        let deadline = Instant::now() + Duration::from_secs(timeout.into());
+        for (name, handle) in tasks.draining {
+            match tokio::time::timeout_at(deadline, handle).await {
+                Ok(Ok(())) => info!("{} task stopped", name),
+                Ok(Err(e)) => warn!("{} task ended abnormally: {}", name, e),
+                Err(_) => warn!("{} task still running after {}s; exiting anyway", name, timeout),
+            }
+        }
+    }
+
+    /// Registers the services as app data; call once per worker.
+    pub fn configure(&self, cfg: &mut web::ServiceConfig) {
+        cfg.app_data(Data::new(self.database.clone()))
+            .app_data(Data::from(self.storage.clone()))
+            .app_data(Data::from(self.gateway.clone()))
+            .app_data(Data::from(self.config.clone()))
+            .app_data(Data::new(self.payment_events.clone()))
+            .app_data(Data::from(self.clock.clone()))
+            .app_data(Data::from(self.email.clone()))
+            .app_data(Data::from(self.request_signer.clone()))
+            .app_data(Data::from(self.fx.clone()))
+            .app_data(Data::from(self.attachments.clone()))
+            .app_data(Data::from(self.rate_limiter.clone()))
+            .app_data(Data::from(self.health.clone()));
+        if let Some(ozow) = &self.ozow {
+            cfg.app_data(Data::from(ozow.clone()));
+        }
+    }
+}
+
+/// Picks the payment provider; only the selected one's settings are required.
+fn gateway_from_env() -> Result<Arc<dyn PaymentGateway>, String> {
+    let required = |key: &str| env::var(key).map_err(|_| format!("{} must be set", key));
+
+    match env::var("PAYMENT_GATEWAY").unwrap_or_else(|_| "peach".to_string()).as_str() {
+        "peach" => {
+            // Sandbox unless live is asked for, so a missing setting can't charge real cards
+            let environment = match env::var("PEACH_ENVIRONMENT") {
+                Ok(value) if !value.trim().is_empty() => {
+                    PeachEnvironment::parse(&value).map_err(|e| format!("PEACH_ENVIRONMENT: {}", e))?
+                }
+                _ => PeachEnvironment::Sandbox,
+            };
+            let endpoint = |key: &str, default: &str| -> Result<String, String> {
+                let url = env::var(key).ok().filter(|u| !u.trim().is_empty()).unwrap_or_else(|| default.to_string());
+                environment.check_url(key, &url)?;
+                Ok(url)
+            };
+            let auth_url = endpoint("PEACH_AUTH_SERVICE_URL", environment.default_auth_url())?;
+            let checkout_url = endpoint("PEACH_CHECKOUT_V2_ENDPOINT", environment.default_checkout_url())?;
+            let script_url = env::var("PEACH_CHECKOUT_SCRIPT_URL").ok().filter(|u| !u.trim().is_empty());
+            if let Some(url) = &script_url {
+                environment.check_url("PEACH_CHECKOUT_SCRIPT_URL", url)?;
+            }
+            let decryption_key = env::var("PEACH_WEBHOOK_DECRYPTION_KEY")
+                .ok()
+                .filter(|k| !k.trim().is_empty())
+                .map(|k| peach::parse_webhook_decryption_key(&k))
+                .transpose()?;
+            // Tenants with their own Peach account are charged through it
+            Ok(Arc::new(TenantPeachGateway::new(PeachPaymentService::new(
+                auth_url,
+                checkout_url,
+                required("PEACH_ENTITY_ID_V2")?,
+                required("PEACH_CLIENT_ID")?,
+                required("PEACH_CLIENT_SECRET")?,
+                required("PEACH_MERCHANT_ID")?,
+                required("PEACH_NOTIFICATION_URL")?,
+                required("PEACH_SHOPPER_RESULT_URL")?,
+                required("PEACH_SECRET_KEY")?,
+                currency_list("PEACH_SUPPORTED_CURRENCIES"),
+            ).with_environment(environment).with_http_settings(HttpSettings::from_env("PEACH")?).with_checkout_widget(
+                script_url,
+                env::var("PEACH_PAYMENT_BRANDS")
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(|b| b.trim().to_uppercase())
+                    .filter(|b| !b.is_empty())
+                    .collect(),
+            ).with_webhook_decryption_key(decryption_key))))
+        }
+        "stripe" => StripePaymentService::from_env(currency_list("STRIPE_SUPPORTED_CURRENCIES"))
+            .map(|stripe| Arc::new(stripe) as Arc<dyn PaymentGateway>)
+            .map_err(|e| format!("Failed to configure Stripe: {}", e)),
+        other => Err(format!("Unknown PAYMENT_GATEWAY '{}'; expected peach or stripe", other)),
+    }
+}
+
+/// Test mode (`SANDBOX_MODE`) settles mock cards locally; pairing it with
+/// live credentials risks real charges from development traffic.
+fn refuse_live_in_test_mode(config: &AppConfig, gateway: &dyn PaymentGateway) -> Result<(), String> {
+    if config.sandbox_mode && gateway.is_live() {
+        return Err(format!(
+            "SANDBOX_MODE is on but the {} gateway has live credentials; use test credentials or turn SANDBOX_MODE off",
+            gateway.name()
+        ));
+    }
+    if !config.production && gateway.is_live() {
+        warn!("The {} gateway is live but APP_ENV is not production; payments will charge real cards", gateway.name());
+    }
+    Ok(())
+}
+
+/// Comma-separated ISO currency codes, ZAR when unset.
+fn currency_list(key: &str) -> Vec<String> {
+    env::var(key)
+        .unwrap_or_else(|_| "ZAR".to_string())
+        .split(',')
+        .map(|c| c.trim().to_uppercase())
+        .filter(|c| !c.is_empty())
+        .collect()
+}
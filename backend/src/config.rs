@@ -0,0 +1,472 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use reqwest::Url;
+use tracing::info;
+use crate::cors::{self, DEVELOPMENT_ORIGINS};
+use crate::models::merchant::{validate_address, validate_vat_number};
+use crate::models::plan::validate_statement_descriptor;
+use crate::models::subscription::SuspensionPolicy;
+use crate::services::rate_limit::RateLimit;
+
+/// Billing behaviour knobs read from the environment at startup.
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    /// Days of continued access after `end_date` when a plan doesn't set its own.
+    pub grace_period_days: u32,
+    /// How many days before `end_date` users are reminded of an upcoming renewal.
+    pub notification_days: u32,
+    /// What happens once grace runs out, for plans without their own policy.
+    pub suspension_policy: SuspensionPolicy,
+    /// Total auto-renewal charge attempts before a subscription is suspended.
+    pub max_renewal_attempts: u32,
+    /// Plan that subscriptions are moved to once renewal retries run out,
+    /// instead of lapsing; `free` for the free tier. Unset keeps the
+    /// subscription's suspension policy.
+    pub dunning_fallback_plan_id: Option<String>,
+    /// Days to wait before each renewal retry (1d/3d/5d by default).
+    pub renewal_retry_schedule_days: Vec<i64>,
+    /// UTC hour at which the end-of-day billing summary is sent.
+    pub daily_summary_hour_utc: u32,
+    /// Directory the nightly data-lake export writes to; unset turns it off.
+    pub data_export_dir: Option<String>,
+    /// UTC hour at which the data-lake export runs.
+    pub data_export_hour_utc: u32,
+    /// Endpoint that domain events (`payment.completed`, ...) are POSTed to.
+    pub event_webhook_url: Option<String>,
+    /// Key for signing event webhook bodies; unsigned when unset.
+    pub event_webhook_secret: Option<String>,
+    /// Spool file domain events are appended to as JSON lines, for
+    /// forwarding to a message queue.
+    pub event_queue_file: Option<String>,
+    /// Recipients of operational emails such as the daily summary.
+    pub operator_emails: Vec<String>,
+    /// Incoming webhook for posting operational messages to Slack.
+    pub slack_webhook_url: Option<String>,
+    /// Alert operators when the main gateway hasn't delivered a processable
+    /// webhook for this many hours; 0 turns the check off.
+    pub webhook_silence_alert_hours: u32,
+    /// UTC hours `[start, end)` on weekdays in which silence alerts are sent.
+    pub business_hours_utc: (u32, u32),
+    /// VAT rate applied to plan prices, payments and invoices.
+    pub vat_rate_percent: u32,
+    /// Supplier details printed on tax invoices until merchant details are
+    /// saved through the admin API.
+    pub invoice_seller_name: String,
+    pub invoice_seller_vat_number: Option<String>,
+    pub invoice_seller_registration_number: Option<String>,
+    /// Postal address; `\n` separates lines.
+    pub invoice_seller_address: Option<String>,
+    /// First payments only reserve the amount at signup; an administrator
+    /// captures it once the service is provisioned. Only for gateways that
+    /// support authorisations.
+    pub authorize_at_signup: bool,
+    /// Card statement descriptor for plans without their own.
+    pub statement_descriptor: Option<String>,
+    /// Test deployment: mock card tokens are charged locally and the billing
+    /// smoke run is enabled. Never set in production.
+    pub sandbox_mode: bool,
+    /// Per-user budget for starting payments.
+    pub payment_initiation_rate_limit: RateLimit,
+    /// Per-user budget for polling payment status.
+    pub payment_status_rate_limit: RateLimit,
+    /// On SIGTERM, how long in-flight requests get to finish, and then how
+    /// long background tasks get to finish their current batch.
+    pub shutdown_timeout_secs: u32,
+    /// `APP_ENV=production`: URLs shoppers and gateways are sent to must be
+    /// HTTPS and sandbox mode is refused.
+    pub production: bool,
+    /// Domain tenants' subdomains sit under, e.g. `pay.example.com` so that
+    /// `acme.pay.example.com` serves the `acme` tenant. Unset, tenants are
+    /// only resolved from API keys.
+    pub tenant_base_domain: Option<String>,
+    /// Browser origins allowed to call the API, from the comma-separated
+    /// `CORS_ALLOWED_ORIGINS`; `https://*.example.com` allows any subdomain.
+    /// Empty means the defaults, see `cors_origins`.
+    pub cors_allowed_origins: Vec<String>,
+    /// Win-back coupons for restarting an expired or cancelled subscription:
+    /// percent off its first new period, by upper-case code; each works once
+    /// per user. From `WINBACK_COUPONS`, e.g. `COMEBACK20=20,HALFBACK=50`.
+    pub winback_coupons: BTreeMap<String, u32>,
+    /// Numeric settings that didn't parse and fell back to their default,
+    /// reported by `validate`.
+    invalid_values: Vec<ConfigError>,
+}
+
+/// Secrets shorter than this are rejected; they're either placeholders or
+/// guessable.
+const MIN_SECRET_LENGTH: usize = 16;
+
+/// One setting that failed validation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    /// The environment variable at fault.
+    pub key: String,
+    pub message: String,
+}
+
+/// Every problem found at startup, so a deployment can fix them in one go.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigErrors(pub Vec<ConfigError>);
+
+impl ConfigErrors {
+    fn push(&mut self, key: &str, message: impl Into<String>) {
+        self.0.push(ConfigError { key: key.to_string(), message: message.into() });
+    }
+}
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} configuration problem(s):", self.0.len())?;
+        for error in &self.0 {
+            write!(f, "\n  {}: {}", error.key, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+impl AppConfig {
+    pub fn from_env() -> Self {
+        let mut invalid = ConfigErrors::default();
+        Self {
+            grace_period_days: env_u32("GRACE_PERIOD_DAYS", 3, &mut invalid),
+            notification_days: env_u32("NOTIFICATION_DAYS", 3, &mut invalid),
+            // `downgrade` keeps users on the free tier instead of suspending them
+            suspension_policy: match env::var("SUSPENSION_POLICY").unwrap_or_default().trim() {
+                "downgrade" => SuspensionPolicy::Downgrade,
+                _ => SuspensionPolicy::Suspend {
+                    days_after_grace: env_u32("SUSPEND_AFTER_GRACE_DAYS", 0, &mut invalid),
+                    reactivation_discount_percent: env_u32("REACTIVATION_DISCOUNT_PERCENT", 0, &mut invalid),
+                },
+            },
+            // One initial attempt plus one retry per schedule entry
+            max_renewal_attempts: env_u32("MAX_RENEWAL_ATTEMPTS", 4, &mut invalid),
+            renewal_retry_schedule_days: env::var("RENEWAL_RETRY_SCHEDULE_DAYS")
+                .ok()
+                .map(|v| v.split(',').filter_map(|d| d.trim().parse().ok()).collect::<Vec<i64>>())
+                .filter(|days| !days.is_empty())
+                .unwrap_or_else(|| vec![1, 3, 5]),
+            dunning_fallback_plan_id: env::var("DUNNING_FALLBACK_PLAN_ID")
+                .ok()
+                .map(|id| id.trim().trim_start_matches("plans:").to_string())
+                .filter(|id| !id.is_empty()),
+            daily_summary_hour_utc: env_u32("DAILY_SUMMARY_HOUR_UTC", 22, &mut invalid).min(23),
+            data_export_dir: env::var("DATA_EXPORT_DIR").ok().filter(|v| !v.trim().is_empty()),
+            data_export_hour_utc: env_u32("DATA_EXPORT_HOUR_UTC", 2, &mut invalid).min(23),
+            event_webhook_url: env::var("EVENT_WEBHOOK_URL").ok().filter(|v| !v.trim().is_empty()),
+            event_webhook_secret: env::var("EVENT_WEBHOOK_SECRET").ok().filter(|v| !v.is_empty()),
+            event_queue_file: env::var("EVENT_QUEUE_FILE").ok().filter(|v| !v.trim().is_empty()),
+            operator_emails: env::var("OPERATOR_EMAILS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|e| e.trim().to_string())
+                .filter(|e| !e.is_empty())
+                .collect(),
+            slack_webhook_url: env::var("SLACK_WEBHOOK_URL").ok().filter(|u| !u.trim().is_empty()),
+            webhook_silence_alert_hours: env_u32("WEBHOOK_SILENCE_ALERT_HOURS", 0, &mut invalid),
+            // 08:00-18:00 South African time
+            business_hours_utc: env::var("BUSINESS_HOURS_UTC")
+                .ok()
+                .and_then(|v| {
+                    let (start, end) = v.split_once('-')?;
+                    Some((start.trim().parse().ok()?, end.trim().parse().ok()?))
+                })
+                .unwrap_or((6, 16)),
+            vat_rate_percent: env_u32("VAT_RATE_PERCENT", 15, &mut invalid),
+            invoice_seller_name: env::var("INVOICE_SELLER_NAME").unwrap_or_else(|_| "PWA Payments".to_string()),
+            invoice_seller_vat_number: env::var("INVOICE_SELLER_VAT_NUMBER").ok().filter(|v| !v.trim().is_empty()),
+            invoice_seller_registration_number: env::var("INVOICE_SELLER_REGISTRATION_NUMBER").ok().filter(|v| !v.trim().is_empty()),
+            invoice_seller_address: env::var("INVOICE_SELLER_ADDRESS").ok()
+                .map(|v| v.replace("\\n", "\n"))
+                .filter(|v| !v.trim().is_empty()),
+            statement_descriptor: env::var("STATEMENT_DESCRIPTOR").ok().filter(|v| !v.trim().is_empty()),
+            authorize_at_signup: env::var("AUTHORIZE_AT_SIGNUP").map(|v| v.eq_ignore_ascii_case("true")).unwrap_or(false),
+            sandbox_mode: env::var("SANDBOX_MODE").map(|v| v.eq_ignore_ascii_case("true")).unwrap_or(false),
+            payment_initiation_rate_limit: env_rate_limit("RATE_LIMIT_PAYMENT_INITIATION", RateLimit { max_requests: 10, window_secs: 60 }, &mut invalid),
+            payment_status_rate_limit: env_rate_limit("RATE_LIMIT_PAYMENT_STATUS", RateLimit { max_requests: 60, window_secs: 60 }, &mut invalid),
+            shutdown_timeout_secs: env_u32("SHUTDOWN_TIMEOUT_SECONDS", 30, &mut invalid),
+            production: env::var("APP_ENV").map(|v| v.trim().eq_ignore_ascii_case("production")).unwrap_or(false),
+            tenant_base_domain: env::var("TENANT_BASE_DOMAIN").ok()
+                .map(|v| v.trim().trim_start_matches('.').to_lowercase())
+                .filter(|v| !v.is_empty()),
+            cors_allowed_origins: env::var("CORS_ALLOWED_ORIGINS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|origin| origin.trim().trim_end_matches('/').to_lowercase())
+                .filter(|origin| !origin.is_empty())
+                .collect(),
+            winback_coupons: env_coupons("WINBACK_COUPONS", &mut invalid),
+            invalid_values: invalid.0,
+        }
+    }
+
+    /// Percent off for win-back coupon `code`, in any case.
+    pub fn winback_discount_percent(&self, code: &str) -> Option<u32> {
+        self.winback_coupons.get(&code.trim().to_uppercase()).copied()
+    }
+
+    /// `CORS_ALLOWED_ORIGINS`, else the PWA's dev servers outside production.
+    /// Production defaults to tenants' subdomains under `TENANT_BASE_DOMAIN`
+    /// over https, or no cross-origin access at all.
+    pub fn cors_origins(&self) -> Vec<String> {
+        if !self.cors_allowed_origins.is_empty() {
+            return self.cors_allowed_origins.clone();
+        }
+        match (&self.tenant_base_domain, self.production) {
+            (Some(base), true) => vec![format!("https://*.{}", base)],
+            (None, true) => Vec::new(),
+            (_, false) => DEVELOPMENT_ORIGINS.iter().map(|origin| origin.to_string()).collect(),
+        }
+    }
+
+    /// Rejects settings that would make billing misbehave rather than fail,
+    /// including the URLs and secrets services read from the environment
+    /// themselves. Reports every problem, not just the first.
+    pub fn validate(&self) -> Result<(), ConfigErrors> {
+        let mut errors = ConfigErrors(self.invalid_values.clone());
+
+        if self.vat_rate_percent > 100 {
+            errors.push("VAT_RATE_PERCENT", format!("must be at most 100, got {}", self.vat_rate_percent));
+        }
+        if let Err(e) = self.suspension_policy.validate() {
+            errors.push("REACTIVATION_DISCOUNT_PERCENT", e);
+        }
+        if self.max_renewal_attempts == 0 {
+            errors.push("MAX_RENEWAL_ATTEMPTS", "must be at least 1");
+        }
+        if self.renewal_retry_schedule_days.iter().any(|days| *days <= 0) {
+            errors.push("RENEWAL_RETRY_SCHEDULE_DAYS", "must only contain positive day counts");
+        }
+        for (key, limit) in [
+            ("RATE_LIMIT_PAYMENT_INITIATION", self.payment_initiation_rate_limit),
+            ("RATE_LIMIT_PAYMENT_STATUS", self.payment_status_rate_limit),
+        ] {
+            if limit.max_requests == 0 || limit.window_secs <= 0 {
+                errors.push(key, "needs at least one request per window of at least one second");
+            }
+        }
+        let (start, end) = self.business_hours_utc;
+        if start >= end || end > 24 {
+            errors.push("BUSINESS_HOURS_UTC", format!("must be <start>-<end> with start before end, got {}-{}", start, end));
+        }
+        if self.invoice_seller_name.trim().is_empty() {
+            errors.push("INVOICE_SELLER_NAME", "must not be empty");
+        }
+        if let Some(vat_number) = &self.invoice_seller_vat_number {
+            if let Err(e) = validate_vat_number(vat_number) {
+                errors.push("INVOICE_SELLER_VAT_NUMBER", e);
+            }
+        }
+        if let Some(address) = &self.invoice_seller_address {
+            if let Err(e) = validate_address(address) {
+                errors.push("INVOICE_SELLER_ADDRESS", e);
+            }
+        }
+        if let Some(descriptor) = &self.statement_descriptor {
+            if let Err(e) = validate_statement_descriptor(descriptor) {
+                errors.push("STATEMENT_DESCRIPTOR", e);
+            }
+        }
+        if self.production && self.sandbox_mode {
+            errors.push("SANDBOX_MODE", "must not be on when APP_ENV=production");
+        }
+        for (code, percent) in &self.winback_coupons {
+            if *percent == 0 || *percent >= 100 {
+                errors.push("WINBACK_COUPONS", format!("{} must take between 1 and 99 percent off, got {}", code, percent));
+            }
+        }
+        for origin in &self.cors_allowed_origins {
+            if let Err(e) = cors::validate_origin(origin, self.production) {
+                errors.push("CORS_ALLOWED_ORIGINS", e);
+            }
+        }
+
+        for setting in env_settings() {
+            match env::var(setting.key).ok().filter(|v| !v.trim().is_empty()) {
+                Some(value) => {
+                    if let Err(e) = setting.kind.check(&value, self.production) {
+                        errors.push(setting.key, e);
+                    }
+                }
+                None if setting.required => errors.push(setting.key, "must be set"),
+                None => {}
+            }
+        }
+
+        if errors.0.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /// Logs the settings checked by `validate`, with secrets reduced to
+    /// their length and URL passwords masked.
+    pub fn log_summary(&self) {
+        let mut lines = vec![
+            format!("APP_ENV={}", if self.production { "production" } else { "development" }),
+            format!("SANDBOX_MODE={}", self.sandbox_mode),
+            format!("CORS origins: {}", match self.cors_origins() {
+                origins if origins.is_empty() => "<none>".to_string(),
+                origins => origins.join(", "),
+            }),
+        ];
+        for setting in env_settings() {
+            let shown = match env::var(setting.key).ok().filter(|v| !v.trim().is_empty()) {
+                Some(value) => setting.kind.redact(&value),
+                None => "<unset>".to_string(),
+            };
+            lines.push(format!("{}={}", setting.key, shown));
+        }
+        info!("Configuration:\n  {}", lines.join("\n  "));
+    }
+}
+
+/// How a setting that a service reads from the environment is checked and
+/// shown in the startup summary.
+#[derive(Debug, Clone, Copy)]
+enum SettingKind {
+    Plain,
+    /// Must be an http(s) URL; shoppers, gateways or outside services are
+    /// sent to the `https_in_production` ones.
+    Url { https_in_production: bool },
+    Secret,
+    /// A URL whose path is the credential, such as a Slack incoming webhook.
+    SecretUrl,
+}
+
+impl SettingKind {
+    fn check(&self, value: &str, production: bool) -> Result<(), String> {
+        match self {
+            SettingKind::Plain => Ok(()),
+            SettingKind::Url { https_in_production } => {
+                let url = Url::parse(value).map_err(|e| format!("is not a valid URL: {}", e))?;
+                match url.scheme() {
+                    "https" => Ok(()),
+                    "http" if production && *https_in_production => Err("must use https in production".to_string()),
+                    "http" => Ok(()),
+                    other => Err(format!("must be an http(s) URL, got scheme {}", other)),
+                }
+            }
+            SettingKind::SecretUrl => SettingKind::Url { https_in_production: true }.check(value, production),
+            SettingKind::Secret if value.len() < MIN_SECRET_LENGTH => {
+                Err(format!("must be at least {} characters", MIN_SECRET_LENGTH))
+            }
+            SettingKind::Secret => Ok(()),
+        }
+    }
+
+    fn redact(&self, value: &str) -> String {
+        match self {
+            SettingKind::Plain => value.to_string(),
+            SettingKind::Url { .. } => match Url::parse(value) {
+                Ok(mut url) => {
+                    if url.password().is_some() {
+                        let _ = url.set_password(Some("****"));
+                    }
+                    url.to_string()
+                }
+                Err(_) => "<invalid URL>".to_string(),
+            },
+            SettingKind::Secret => format!("<{} characters>", value.len()),
+            SettingKind::SecretUrl => match Url::parse(value) {
+                Ok(url) => format!("{}/<redacted>", url.origin().ascii_serialization()),
+                Err(_) => "<invalid URL>".to_string(),
+            },
+        }
+    }
+}
+
+struct EnvSetting {
+    key: &'static str,
+    kind: SettingKind,
+    required: bool,
+}
+
+/// Settings read outside `AppConfig`: the database, the selected gateway,
+/// Ozow when it's on, and the shared secrets.
+fn env_settings() -> Vec<EnvSetting> {
+    let setting = |key, kind, required| EnvSetting { key, kind, required };
+    let internal_url = SettingKind::Url { https_in_production: false };
+    let public_url = SettingKind::Url { https_in_production: true };
+
+    let mut settings = vec![
+        setting("DATABASE_URL", internal_url, false),
+        setting("PUBLIC_API_BASE_URL", public_url, false),
+        setting("EVENT_WEBHOOK_URL", public_url, false),
+        setting("EVENT_WEBHOOK_SECRET", SettingKind::Secret, false),
+        setting("SLACK_WEBHOOK_URL", SettingKind::SecretUrl, false),
+        setting("ADMIN_API_TOKEN", SettingKind::Secret, false),
+        setting("ATTACHMENT_URL_SECRET", SettingKind::Secret, false),
+        setting("PAYMENT_GATEWAY", SettingKind::Plain, false),
+    ];
+    // An unknown gateway is reported when the gateway is built
+    match env::var("PAYMENT_GATEWAY").unwrap_or_else(|_| "peach".to_string()).as_str() {
+        "peach" => settings.extend([
+            setting("PEACH_ENVIRONMENT", SettingKind::Plain, false),
+            setting("PEACH_AUTH_SERVICE_URL", public_url, false),
+            setting("PEACH_CHECKOUT_V2_ENDPOINT", public_url, false),
+            setting("PEACH_CHECKOUT_SCRIPT_URL", public_url, false),
+            setting("PEACH_ENTITY_ID_V2", SettingKind::Plain, true),
+            setting("PEACH_CLIENT_ID", SettingKind::Plain, true),
+            setting("PEACH_CLIENT_SECRET", SettingKind::Secret, true),
+            setting("PEACH_MERCHANT_ID", SettingKind::Plain, true),
+            setting("PEACH_NOTIFICATION_URL", public_url, true),
+            setting("PEACH_SHOPPER_RESULT_URL", public_url, true),
+            setting("PEACH_SECRET_KEY", SettingKind::Secret, true),
+            setting("PEACH_WEBHOOK_DECRYPTION_KEY", SettingKind::Secret, false),
+        ]),
+        "stripe" => settings.extend([
+            setting("STRIPE_SECRET_KEY", SettingKind::Secret, true),
+            setting("STRIPE_WEBHOOK_SECRET", SettingKind::Secret, true),
+            setting("STRIPE_SUCCESS_URL", public_url, false),
+            setting("STRIPE_CANCEL_URL", public_url, true),
+        ]),
+        _ => {}
+    }
+    if env::var("OZOW_SITE_CODE").map_or(false, |code| !code.trim().is_empty()) {
+        settings.extend([
+            setting("OZOW_SITE_CODE", SettingKind::Plain, true),
+            setting("OZOW_API_KEY", SettingKind::Secret, true),
+            setting("OZOW_PRIVATE_KEY", SettingKind::Secret, true),
+            setting("OZOW_NOTIFY_URL", public_url, true),
+            setting("OZOW_RESULT_URL", public_url, false),
+        ]);
+    }
+    settings
+}
+
+/// `default` when unset; a value that doesn't parse is recorded in
+/// `invalid` rather than silently replaced.
+fn env_u32(key: &str, default: u32, invalid: &mut ConfigErrors) -> u32 {
+    match env::var(key) {
+        Ok(value) if !value.trim().is_empty() => value.trim().parse().unwrap_or_else(|_| {
+            invalid.push(key, format!("must be a whole number, got '{}'", value));
+            default
+        }),
+        _ => default,
+    }
+}
+
+/// `CODE=percent` pairs separated by commas; entries that don't parse are
+/// recorded in `invalid`.
+fn env_coupons(key: &str, invalid: &mut ConfigErrors) -> BTreeMap<String, u32> {
+    let mut coupons = BTreeMap::new();
+    for entry in env::var(key).unwrap_or_default().split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        match entry.split_once('=').and_then(|(code, percent)| Some((code.trim(), percent.trim().parse().ok()?))) {
+            Some((code, percent)) if !code.is_empty() => {
+                coupons.insert(code.to_uppercase(), percent);
+            }
+            _ => invalid.push(key, format!("must be CODE=percent pairs, got '{}'", entry)),
+        }
+    }
+    coupons
+}
+
+fn env_rate_limit(key: &str, default: RateLimit, invalid: &mut ConfigErrors) -> RateLimit {
+    match env::var(key) {
+        Ok(value) => RateLimit::parse(&value).unwrap_or_else(|e| {
+            invalid.push(key, e);
+            default
+        }),
+        Err(_) => default,
+    }
+}
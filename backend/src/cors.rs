@@ -0,0 +1,62 @@
+use actix_cors::Cors;
+use crate::config::AppConfig;
+
+/// Allowed when `CORS_ALLOWED_ORIGINS` is unset outside production, for the
+/// PWA's dev servers.
+pub const DEVELOPMENT_ORIGINS: &[&str] = &[
+    "http://127.0.0.1:8080",
+    "http://localhost:8080",
+    "http://127.0.0.1:3000",
+    "http://localhost:3000",
+];
+
+/// The CORS policy for the API: origins from `AppConfig::cors_origins`, plus
+/// the headers the PWA and integrations send and read.
+pub fn build(config: &AppConfig) -> Cors {
+    let origins = config.cors_origins();
+    Cors::default()
+        .allowed_origin_fn(move |origin, _| {
+            origin.to_str().is_ok_and(|origin| origins.iter().any(|allowed| origin_matches(allowed, origin)))
+        })
+        .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"])
+        .allowed_headers(vec!["Content-Type", "Authorization", "Accept", "If-None-Match", "X-Admin-Token", "X-User-Id", "X-Key-Id", "X-Timestamp", "X-Signature", "X-Request-Id", "Accept-Language", "X-Impersonation-Token", "X-Api-Key"])
+        .expose_headers(vec!["ETag", "Content-Disposition", "X-Request-Id", "Content-Language", "X-Impersonation-Session"])
+        .supports_credentials()
+}
+
+/// Whether `origin` is `allowed`, an exact origin or `https://*.example.com`
+/// for any subdomain of `example.com` (not the domain itself).
+fn origin_matches(allowed: &str, origin: &str) -> bool {
+    let origin = origin.to_ascii_lowercase();
+    match allowed.split_once("://*.") {
+        Some((scheme, domain)) => origin
+            .strip_prefix(scheme)
+            .and_then(|rest| rest.strip_prefix("://"))
+            .and_then(|host| host.strip_suffix(domain))
+            .is_some_and(|subdomain| subdomain.len() > 1 && subdomain.ends_with('.')),
+        None => origin == allowed,
+    }
+}
+
+/// Checks one `CORS_ALLOWED_ORIGINS` entry: `scheme://host[:port]` with no
+/// path, where the host may start with `*.`. Production only allows https.
+pub fn validate_origin(origin: &str, production: bool) -> Result<(), String> {
+    let Some((scheme, host)) = origin.split_once("://") else {
+        return Err(format!("{} must be scheme://host[:port]", origin));
+    };
+    match scheme {
+        "https" => {}
+        "http" if production => return Err(format!("{} must use https in production", origin)),
+        "http" => {}
+        other => return Err(format!("{} must be http(s), got scheme {}", origin, other)),
+    }
+    let name = host.strip_prefix("*.").unwrap_or(host);
+    let valid = !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':'))
+        && !name.starts_with('.');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("{} must be scheme://host[:port], optionally with a leading *. for subdomains", origin))
+    }
+}
@@ -0,0 +1,293 @@
+use actix_web::{dev::Payload, error::InternalError, web::Json, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use serde::de::DeserializeOwned;
+use std::env;
+use std::fmt;
+use std::future::{ready, Future, Ready};
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::pin::Pin;
+use crate::services::tenant;
+use crate::models::{
+    api_key::{ApiKey, ApiKeyScope}, attachment::Attachment, bulk_operation::BulkOperation, domain_event::DomainEvent, impersonation::ImpersonationSession, invoice::Invoice, job::Job, membership::SubscriptionMember, notification::Notification,
+    payment::Payment, plan::Plan, reconciliation::ReconciliationReport, subscription::Subscription, support::SupportNote, tenant::Tenant, ticket::Ticket,
+    token_migration::TokenMigration, user::User, validation::{Validate, ValidationErrorResponse}, webhook_endpoint::WebhookEndpoint,
+    webhook_event::WebhookEvent,
+};
+
+/// Guards admin routes over deployment-wide data (plans, audit log, support,
+/// jobs, events, reports). Requests must send `X-Admin-Token` matching the
+/// `ADMIN_API_TOKEN` environment variable, or an `X-Api-Key` with the admin
+/// scope; without either every admin request is refused. Refused within a
+/// tenant, whose admins only get `TenantAdminAuth` routes.
+pub struct AdminAuth;
+
+impl FromRequest for AdminAuth {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        if let Err(e) = check_admin(req) {
+            return ready(Err(e));
+        }
+        if tenant::current_id().is_some() {
+            let response = HttpResponse::Forbidden().json(serde_json::json!({
+                "error": "Not available within a tenant"
+            }));
+            return ready(Err(InternalError::from_response("deployment admin route", response).into()));
+        }
+        ready(Ok(AdminAuth))
+    }
+}
+
+/// Guards admin routes whose records are scoped to the current tenant
+/// (users, payments, subscriptions, API keys, reconciliation reports), so a
+/// tenant's admin key may use them as well as the deployment's admins.
+pub struct TenantAdminAuth;
+
+impl FromRequest for TenantAdminAuth {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(check_admin(req).map(|_| TenantAdminAuth))
+    }
+}
+
+fn check_admin(req: &HttpRequest) -> Result<(), actix_web::Error> {
+    let expected = env::var("ADMIN_API_TOKEN").unwrap_or_default();
+    let provided = req
+        .headers()
+        .get("X-Admin-Token")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+
+    if !expected.is_empty() && provided == expected {
+        return Ok(());
+    }
+    // Keys are checked by the `api_key_auth` middleware
+    if req.extensions().get::<ApiKey>().is_some_and(|key| key.has_scope(ApiKeyScope::Admin)) {
+        return Ok(());
+    }
+
+    let response = HttpResponse::Unauthorized().json(serde_json::json!({
+        "error": "Admin authentication required"
+    }));
+    Err(InternalError::from_response("admin auth failed", response).into())
+}
+
+/// The `X-Api-Key` a server-to-server caller authenticated with.
+pub struct ApiKeyAuth {
+    pub api_key: ApiKey,
+}
+
+impl FromRequest for ApiKeyAuth {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        match req.extensions().get::<ApiKey>() {
+            Some(api_key) => ready(Ok(ApiKeyAuth { api_key: api_key.clone() })),
+            None => {
+                let response = HttpResponse::Unauthorized().json(serde_json::json!({
+                    "error": "API key required"
+                }));
+                ready(Err(InternalError::from_response("missing api key", response).into()))
+            }
+        }
+    }
+}
+
+/// Identifies the calling end user for `/me` routes.
+///
+/// There is no session auth yet, so the PWA sends the id it stored at
+/// registration in `X-User-Id`. Swap this for token verification once auth lands.
+pub struct CurrentUser {
+    pub user_id: String,
+}
+
+impl FromRequest for CurrentUser {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let user_id = req
+            .headers()
+            .get("X-User-Id")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
+
+        match user_id {
+            Some(user_id) => ready(Ok(CurrentUser { user_id })),
+            None => {
+                let response = HttpResponse::Unauthorized().json(serde_json::json!({
+                    "error": "User identification required"
+                }));
+                ready(Err(InternalError::from_response("missing user", response).into()))
+            }
+        }
+    }
+}
+
+/// A table whose record ids appear in URL paths.
+pub trait RecordTable {
+    /// SurrealDB table, accepted as an optional `table:` prefix on the id.
+    const TABLE: &'static str;
+    /// Path parameter holding the id, e.g. `subscription_id`.
+    const PARAM: &'static str;
+    /// Used in error messages.
+    const LABEL: &'static str;
+}
+
+macro_rules! record_table {
+    ($model:ty, $table:literal, $param:literal, $label:literal) => {
+        impl RecordTable for $model {
+            const TABLE: &'static str = $table;
+            const PARAM: &'static str = $param;
+            const LABEL: &'static str = $label;
+        }
+    };
+}
+
+record_table!(User, "users", "user_id", "user");
+record_table!(Subscription, "subscriptions", "subscription_id", "subscription");
+record_table!(Payment, "payments", "payment_id", "payment");
+record_table!(Notification, "notification", "notification_id", "notification");
+record_table!(Invoice, "invoices", "invoice_id", "invoice");
+record_table!(SupportNote, "support_notes", "note_id", "note");
+record_table!(Plan, "plans", "plan_id", "plan");
+record_table!(WebhookEvent, "webhook_events", "event_id", "webhook event");
+record_table!(Attachment, "attachments", "attachment_id", "attachment");
+record_table!(SubscriptionMember, "subscription_members", "member_id", "member");
+record_table!(TokenMigration, "token_migrations", "migration_id", "token migration");
+record_table!(BulkOperation, "bulk_operations", "operation_id", "bulk operation");
+record_table!(Ticket, "tickets", "ticket_id", "ticket");
+record_table!(Job, "jobs", "job_id", "job");
+record_table!(DomainEvent, "domain_events", "event_id", "event");
+record_table!(WebhookEndpoint, "webhook_endpoints", "endpoint_id", "webhook endpoint");
+record_table!(ApiKey, "api_keys", "key_id", "API key");
+record_table!(ImpersonationSession, "impersonation_sessions", "session_id", "impersonation session");
+record_table!(Tenant, "tenants", "tenant_id", "tenant");
+record_table!(ReconciliationReport, "reconciliation_reports", "report_id", "reconciliation report");
+
+/// Longest record key accepted from a URL.
+const MAX_RECORD_KEY_LEN: usize = 128;
+
+/// A record id taken from the `T::PARAM` path parameter, given either as the
+/// bare key or as `table:key`. Malformed ids are rejected with 400 before the
+/// handler runs, so handlers only ever see the bare key.
+pub struct RecordPath<T> {
+    key: String,
+    table: PhantomData<T>,
+}
+
+impl<T: RecordTable> RecordPath<T> {
+    /// The id without its table prefix.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn into_key(self) -> String {
+        self.key
+    }
+
+    /// The id in `table:key` form.
+    pub fn thing(&self) -> String {
+        format!("{}:{}", T::TABLE, self.key)
+    }
+}
+
+impl<T> fmt::Display for RecordPath<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.key)
+    }
+}
+
+/// Strips an optional `table:` prefix and checks the key is one SurrealDB
+/// would have generated or we would have chosen: letters, digits, `_` and `-`.
+pub fn parse_record_key(table: &str, raw: &str) -> Result<String, String> {
+    let key = match raw.split_once(':') {
+        Some((prefix, key)) if prefix == table => key,
+        Some((prefix, _)) => {
+            return Err(format!("expected a {} id but got a {} id", table, prefix));
+        }
+        None => raw,
+    };
+
+    if key.is_empty() {
+        return Err("id must not be empty".to_string());
+    }
+    if key.len() > MAX_RECORD_KEY_LEN {
+        return Err(format!("id must be at most {} characters", MAX_RECORD_KEY_LEN));
+    }
+    if !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err("id may only contain letters, digits, '_' and '-'".to_string());
+    }
+    Ok(key.to_string())
+}
+
+impl<T: RecordTable> FromRequest for RecordPath<T> {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let raw = req.match_info().get(T::PARAM).unwrap_or_default();
+
+        match parse_record_key(T::TABLE, raw.trim()) {
+            Ok(key) => ready(Ok(RecordPath { key, table: PhantomData })),
+            Err(reason) => {
+                let response = HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("Invalid {} id: {}", T::LABEL, reason)
+                }));
+                ready(Err(InternalError::from_response("invalid record id", response).into()))
+            }
+        }
+    }
+}
+
+/// A JSON body that passed `Validate`. Bodies that don't parse are refused
+/// as with `Json`; ones that parse but fail validation get 422 with the
+/// problems by field.
+pub struct ValidatedJson<T>(pub T);
+
+impl<T> ValidatedJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for ValidatedJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ValidatedJson<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: DeserializeOwned + Validate + 'static> FromRequest for ValidatedJson<T> {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let body = Json::<T>::from_request(req, payload);
+        Box::pin(async move {
+            let body = body.await?.into_inner();
+            match body.validate() {
+                Ok(()) => Ok(ValidatedJson(body)),
+                Err(fields) => {
+                    let response = HttpResponse::UnprocessableEntity().json(ValidationErrorResponse {
+                        error: "Validation failed".to_string(),
+                        fields,
+                    });
+                    Err(InternalError::from_response("invalid request body", response).into())
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,171 @@
+use actix_web::{HttpResponse, Result, delete, get, post};
+use actix_web::web::Data;
+use chrono::{Duration, Utc};
+use tracing::error;
+use uuid::Uuid;
+use crate::extractors::{TenantAdminAuth, ApiKeyAuth, RecordPath, ValidatedJson};
+use crate::models::api_key::{
+    hash_key, ApiKey, ApiKeyCreated, CreateApiKeyDto, API_KEY_PREFIX_LEN, ROTATION_OVERLAP_HOURS,
+};
+use crate::models::validation::ValidationErrorResponse;
+use crate::services::database::DatabaseService;
+use crate::services::tenant;
+
+fn server_error(message: &str, details: String) -> HttpResponse {
+    error!("{}: {}", message, details);
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "error": message,
+    }))
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "error": "No active API key with that id"
+    }))
+}
+
+/// A fresh secret and the prefix shown to recognise it by.
+fn generate_key() -> (String, String) {
+    let key = format!("pwa_{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let prefix = key[..API_KEY_PREFIX_LEN].to_string();
+    (key, prefix)
+}
+
+/// The key the caller authenticated with, so an integration can check its
+/// credentials and scopes.
+#[utoipa::path(
+    get,
+    path = "/api/v1/api-keys/current",
+    tag = "api-keys",
+    responses(
+        (status = 200, description = "The calling key", body = ApiKey),
+        (status = 401, description = "Missing or invalid credentials"),
+    ),
+    security(("api_key" = []))
+)]
+#[get("/current")]
+pub async fn get_current_api_key(auth: ApiKeyAuth) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(auth.api_key))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/api-keys",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Every key, newest first", body = [ApiKey]),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/api-keys")]
+pub async fn list_api_keys(
+    _admin: TenantAdminAuth,
+    db: Data<DatabaseService>,
+) -> Result<HttpResponse> {
+    match db.list_api_keys().await {
+        Ok(keys) => Ok(HttpResponse::Ok().json(keys)),
+        Err(e) => Ok(server_error("Failed to list API keys", e)),
+    }
+}
+
+/// Issues a key for a merchant backend. The secret is only returned here;
+/// send it as `X-Api-Key`.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/api-keys",
+    tag = "admin",
+    request_body = CreateApiKeyDto,
+    responses(
+        (status = 201, description = "Key created; the secret isn't shown again", body = ApiKeyCreated),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/api-keys")]
+pub async fn create_api_key(
+    _admin: TenantAdminAuth,
+    db: Data<DatabaseService>,
+    payload: ValidatedJson<CreateApiKeyDto>,
+) -> Result<HttpResponse> {
+    let dto = payload.into_inner();
+    let tenant_id = match tenant::current_id() {
+        Some(current) => Some(current),
+        None => match dto.tenant_id {
+            Some(id) => match db.get_tenant(&id).await {
+                Some(tenant) => Some(tenant.id),
+                None => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": "Unknown tenant" }))),
+            },
+            None => None,
+        },
+    };
+
+    let (key, prefix) = generate_key();
+    match db.create_api_key(&dto.name, dto.scopes, tenant_id, &prefix, &hash_key(&key)).await {
+        Ok(api_key) => Ok(HttpResponse::Created().json(ApiKeyCreated { api_key, key })),
+        Err(e) => Ok(server_error("Failed to create API key", e)),
+    }
+}
+
+/// Replaces a key's secret. The old secret keeps working for 24 hours so
+/// the integration can switch over without downtime.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/api-keys/{key_id}/rotate",
+    tag = "admin",
+    params(("key_id" = String, Path, description = "API key id")),
+    responses(
+        (status = 200, description = "The new secret, which isn't shown again", body = ApiKeyCreated),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found or revoked"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/api-keys/{key_id}/rotate")]
+pub async fn rotate_api_key(
+    _admin: TenantAdminAuth,
+    db: Data<DatabaseService>,
+    key_id: RecordPath<ApiKey>,
+) -> Result<HttpResponse> {
+    let (key, prefix) = generate_key();
+    let overlap_until = Utc::now() + Duration::hours(ROTATION_OVERLAP_HOURS);
+    match db.rotate_api_key(key_id.key(), &prefix, &hash_key(&key), overlap_until).await {
+        Ok(Some(api_key)) => Ok(HttpResponse::Ok().json(ApiKeyCreated { api_key, key })),
+        Ok(None) => Ok(not_found()),
+        Err(e) => Ok(server_error("Failed to rotate API key", e)),
+    }
+}
+
+/// Stops a key working straight away, including a secret it was rotated from.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/api-keys/{key_id}",
+    tag = "admin",
+    params(("key_id" = String, Path, description = "API key id")),
+    responses(
+        (status = 204, description = "Key revoked"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found or already revoked"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[delete("/api-keys/{key_id}")]
+pub async fn revoke_api_key(
+    _admin: TenantAdminAuth,
+    db: Data<DatabaseService>,
+    key_id: RecordPath<ApiKey>,
+) -> Result<HttpResponse> {
+    match db.revoke_api_key(key_id.key()).await {
+        Ok(Some(_)) => Ok(HttpResponse::NoContent().finish()),
+        Ok(None) => Ok(not_found()),
+        Err(e) => Ok(server_error("Failed to revoke API key", e)),
+    }
+}
@@ -0,0 +1,236 @@
+use actix_web::{HttpRequest, HttpResponse, Result, get, post};
+use actix_web::http::header;
+use actix_web::web::{Bytes, Data, Path, Query};
+use chrono::Utc;
+use serde::Deserialize;
+use utoipa::IntoParams;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use tracing::error;
+use crate::extractors::{AdminAuth, RecordPath};
+use crate::models::attachment::{
+    sanitize_filename, validate_attachment, Attachment, AttachmentOwner, UploadAttachmentQuery,
+};
+use crate::services::attachments::AttachmentService;
+use crate::services::database::DatabaseService;
+
+fn with_download_url(attachments: &AttachmentService, attachment: &Attachment) -> serde_json::Value {
+    serde_json::json!({
+        "attachment": attachment,
+        "download_url": attachments.download_url(&attachment.id),
+    })
+}
+
+/// Uploads a file as the raw request body, e.g. a scanned deposit slip.
+/// `owner_type`/`owner_id` link it straight away; otherwise it stays unlinked
+/// until a record (such as a manual payment) claims it.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/attachments",
+    tag = "admin",
+    params(UploadAttachmentQuery),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream", description = "The raw file"),
+    responses(
+        (status = 201, description = "Stored; body has `attachment` and a signed `download_url`"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/attachments")]
+pub async fn upload_attachment(
+    _admin: AdminAuth,
+    req: HttpRequest,
+    db: Data<DatabaseService>,
+    attachments: Data<AttachmentService>,
+    query: Query<UploadAttachmentQuery>,
+    body: Bytes,
+) -> Result<HttpResponse> {
+    let query = query.into_inner();
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or_default().trim().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    if let Err(e) = validate_attachment(&content_type, &body) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+    }
+
+    let owner = match (query.owner_type, query.owner_id) {
+        (Some(AttachmentOwner::Payment), Some(owner_id)) => {
+            if db.get_payment_by_merchant_id(&owner_id).await.is_none() {
+                return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                    "error": "Payment not found",
+                    "details": owner_id
+                })));
+            }
+            Some((AttachmentOwner::Payment, owner_id))
+        }
+        (None, None) => None,
+        _ => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "owner_type and owner_id must be given together"
+            })));
+        }
+    };
+
+    let id = Uuid::new_v4().simple().to_string();
+    let attachment = Attachment {
+        id: id.clone(),
+        owner_type: owner.as_ref().map(|(owner_type, _)| *owner_type),
+        owner_id: owner.map(|(_, owner_id)| owner_id),
+        filename: sanitize_filename(&query.filename),
+        content_type,
+        size_bytes: body.len() as u64,
+        sha256: hex::encode(Sha256::digest(&body)),
+        storage_key: id,
+        created_at: Utc::now(),
+    };
+
+    if let Err(e) = attachments.store().put(&attachment.storage_key, &body).await {
+        error!("Failed to store attachment {}: {}", attachment.id, e);
+        return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to store attachment"
+        })));
+    }
+
+    match db.create_attachment(&attachment).await {
+        Ok(attachment) => Ok(HttpResponse::Created().json(with_download_url(&attachments, &attachment))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to record attachment",
+            "details": e
+        }))),
+    }
+}
+
+/// Metadata plus a fresh download link.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/attachments/{attachment_id}",
+    tag = "admin",
+    params(("attachment_id" = String, Path, description = "Attachment id")),
+    responses(
+        (status = 200, description = "Body has `attachment` and a signed `download_url`"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/attachments/{attachment_id}")]
+pub async fn get_attachment(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    attachments: Data<AttachmentService>,
+    attachment_id: RecordPath<Attachment>,
+) -> Result<HttpResponse> {
+    match db.get_attachment(attachment_id.key()).await {
+        Some(attachment) => Ok(HttpResponse::Ok().json(with_download_url(&attachments, &attachment))),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Attachment not found"
+        }))),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/payments/{merchant_transaction_id}/attachments",
+    tag = "admin",
+    params(
+        ("merchant_transaction_id" = String, Path, description = "Merchant transaction id"),
+    ),
+    responses(
+        (status = 200, description = "Attachments of the payment, each with `attachment` and a signed `download_url`"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/payments/{merchant_transaction_id}/attachments")]
+pub async fn list_payment_attachments(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    attachments: Data<AttachmentService>,
+    path: Path<String>,
+) -> Result<HttpResponse> {
+    let merchant_transaction_id = path.into_inner();
+
+    if db.get_payment_by_merchant_id(&merchant_transaction_id).await.is_none() {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Payment not found"
+        })));
+    }
+
+    match db.list_attachments(AttachmentOwner::Payment, &merchant_transaction_id).await {
+        Ok(list) => Ok(HttpResponse::Ok().json(
+            list.iter().map(|a| with_download_url(&attachments, a)).collect::<Vec<_>>(),
+        )),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to list attachments",
+            "details": e
+        }))),
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DownloadQuery {
+    pub expires: i64,
+    pub signature: String,
+}
+
+/// Serves the file behind a signed link from one of the endpoints above. The
+/// signature is the only credential, so links are short-lived.
+#[utoipa::path(
+    get,
+    path = "/api/v1/attachments/{attachment_id}/download",
+    tag = "attachments",
+    params(("attachment_id" = String, Path, description = "Attachment id"), DownloadQuery),
+    responses(
+        (status = 200, description = "The file, served with its stored content type"),
+        (status = 400, description = "Invalid request"),
+        (status = 403, description = "Not allowed"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    )
+)]
+#[get("/{attachment_id}/download")]
+pub async fn download_attachment(
+    db: Data<DatabaseService>,
+    attachments: Data<AttachmentService>,
+    attachment_id: RecordPath<Attachment>,
+    query: Query<DownloadQuery>,
+) -> Result<HttpResponse> {
+    if let Err(e) = attachments.verify_download(attachment_id.key(), query.expires, &query.signature) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({ "error": e })));
+    }
+
+    let attachment = match db.get_attachment(attachment_id.key()).await {
+        Some(a) => a,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Attachment not found"
+        }))),
+    };
+
+    match attachments.store().get(&attachment.storage_key).await {
+        Ok(bytes) => Ok(HttpResponse::Ok()
+            .content_type(attachment.content_type.as_str())
+            .insert_header((
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", attachment.filename),
+            ))
+            .insert_header((header::CACHE_CONTROL, "private, no-store"))
+            .body(bytes)),
+        Err(e) => {
+            error!("Failed to read attachment {}: {}", attachment.id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to read attachment"
+            })))
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use actix_web::{HttpResponse, Result, get};
+use actix_web::web::{Data, Query};
+use chrono::{Duration, NaiveDate};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use tracing::error;
+use crate::extractors::AdminAuth;
+use crate::models::audit::{AuditActorType, AuditFilter};
+use crate::models::common::{page_and_limit, PaginatedResponse};
+use crate::services::database::DatabaseService;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditLogQuery {
+    /// From 1.
+    pub page: Option<u32>,
+    /// Up to 100; 20 by default.
+    pub limit: Option<u32>,
+    pub actor_type: Option<AuditActorType>,
+    pub actor_id: Option<String>,
+    /// e.g. `subscription.cancelled`.
+    pub action: Option<String>,
+    /// `users`, `subscriptions`, `payments` or `api_keys`.
+    pub entity_type: Option<String>,
+    pub entity_id: Option<String>,
+    /// Everything done during one impersonation session.
+    pub impersonation_session_id: Option<String>,
+    /// Changes made on or after this date (UTC).
+    pub from: Option<NaiveDate>,
+    /// Changes made on or before this date (UTC).
+    pub to: Option<NaiveDate>,
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// Who changed which user, subscription or payment, newest first, with the
+/// record before and after each change.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/audit-log",
+    tag = "admin",
+    params(AuditLogQuery),
+    responses(
+        (status = 200, description = "A page of audit entries", body = PaginatedAuditEntries),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/audit-log")]
+pub async fn list_audit_log(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    query: Query<AuditLogQuery>,
+) -> Result<HttpResponse> {
+    let query = query.into_inner();
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from > to {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "from must not be after to"
+            })));
+        }
+    }
+
+    let (page, limit) = page_and_limit(query.page, query.limit);
+    let filter = AuditFilter {
+        actor_type: query.actor_type,
+        actor_id: non_empty(query.actor_id),
+        action: non_empty(query.action),
+        entity_type: non_empty(query.entity_type),
+        entity_id: non_empty(query.entity_id),
+        impersonation_session_id: non_empty(query.impersonation_session_id),
+        from: query.from.map(|date| date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()),
+        // Whole days, so up to the start of the next one
+        to: query.to.map(|date| (date + Duration::days(1)).and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()),
+    };
+
+    match db.list_audit_entries(&filter, page, limit).await {
+        Ok((entries, total)) => Ok(HttpResponse::Ok().json(PaginatedResponse::new(entries, page, limit, total))),
+        Err(e) => {
+            error!("Error loading audit log: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to load audit log"
+            })))
+        }
+    }
+}
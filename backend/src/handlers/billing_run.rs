@@ -0,0 +1,57 @@
+use actix_web::{HttpResponse, Result, post};
+use actix_web::web::Data;
+use crate::config::AppConfig;
+use crate::extractors::{AdminAuth, ValidatedJson};
+use crate::models::billing_run::BillingRunDto;
+use crate::models::validation::ValidationErrorResponse;
+use crate::services::billing_run;
+use crate::services::clock::Clock;
+use crate::services::database::DatabaseService;
+use crate::services::email::EmailService;
+use crate::services::gateway::PaymentGateway;
+
+/// Pre-release smoke test of the billing engine. Creates synthetic due
+/// subscriptions with mock cards and renews them end to end, returning
+/// throughput and failures. Only available with `SANDBOX_MODE=true`; the
+/// records it creates are left in place, tagged with the run's tag.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/sandbox/billing-run",
+    tag = "admin",
+    request_body = BillingRunDto,
+    responses(
+        (status = 200, description = "Outcome of the sandbox billing run", body = BillingRunReport),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 403, description = "Not allowed"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/sandbox/billing-run")]
+pub async fn run_billing_smoke_test(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    gateway: Data<dyn PaymentGateway>,
+    config: Data<AppConfig>,
+    email: Data<EmailService>,
+    clock: Data<dyn Clock>,
+    payload: ValidatedJson<BillingRunDto>,
+) -> Result<HttpResponse> {
+    if !config.sandbox_mode {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Billing runs are only available with SANDBOX_MODE=true"
+        })));
+    }
+
+    let dto = payload.into_inner();
+    let report = billing_run::run_billing_smoke_test(
+        &db,
+        gateway.get_ref(),
+        &config,
+        &email,
+        clock.get_ref(),
+        &dto,
+    ).await;
+    Ok(HttpResponse::Ok().json(report))
+}
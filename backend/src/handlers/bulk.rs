@@ -0,0 +1,151 @@
+use actix_web::{HttpResponse, Result, get, post};
+use actix_web::web::Data;
+use chrono::Utc;
+use tracing::{error, info};
+use crate::extractors::{AdminAuth, RecordPath, ValidatedJson};
+use crate::models::bulk_operation::{BulkOperation, BulkOperationStatus, CreateBulkOperationDto, MAX_BULK_SUBSCRIPTIONS};
+use crate::services::bulk;
+use crate::services::database::DatabaseService;
+
+/// Previews a bulk change, e.g. extending every active subscription on a plan
+/// by a week. Returns the matching subscriptions; nothing changes until the
+/// operation is confirmed.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/bulk",
+    tag = "admin",
+    request_body = CreateBulkOperationDto,
+    responses(
+        (status = 201, description = "Previewed operation with the subscriptions it would change", body = BulkOperation),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 422, description = "Cannot be processed"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/bulk")]
+pub async fn preview_bulk_operation(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    payload: ValidatedJson<CreateBulkOperationDto>,
+) -> Result<HttpResponse> {
+    let dto = payload.into_inner();
+    // One over the cap tells us the filter matches too much
+    let targets = match db.find_bulk_targets(&dto.action, &dto.filter, MAX_BULK_SUBSCRIPTIONS + 1).await {
+        Ok(targets) => targets,
+        Err(e) => {
+            error!("Error matching bulk operation: {}", e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to match subscriptions"
+            })));
+        }
+    };
+    if targets.len() > MAX_BULK_SUBSCRIPTIONS {
+        return Ok(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": format!("Filter matches more than {} subscriptions; narrow it", MAX_BULK_SUBSCRIPTIONS)
+        })));
+    }
+
+    match db.create_bulk_operation(dto, targets).await {
+        Ok(operation) => {
+            info!("Previewed bulk operation {} ({} subscription(s))", operation.id, operation.matched);
+            Ok(HttpResponse::Created().json(operation))
+        }
+        Err(e) => {
+            error!("Error creating bulk operation: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to create bulk operation"
+            })))
+        }
+    }
+}
+
+/// Runs a previewed operation in the background over the subscriptions its
+/// preview listed. Poll the operation for progress.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/bulk/{operation_id}/confirm",
+    tag = "admin",
+    params(("operation_id" = String, Path, description = "Bulk operation id")),
+    responses(
+        (status = 202, description = "Operation started", body = BulkOperation),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Conflicts with the current state"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/bulk/{operation_id}/confirm")]
+pub async fn confirm_bulk_operation(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    operation_id: RecordPath<BulkOperation>,
+) -> Result<HttpResponse> {
+    let operation = match db.get_bulk_operation(operation_id.key()).await {
+        Some(operation) => operation,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Bulk operation not found"
+        }))),
+    };
+    if operation.status != BulkOperationStatus::Previewed {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Bulk operation has already been confirmed"
+        })));
+    }
+    if operation.expires_at <= Utc::now() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Preview has expired; preview the operation again"
+        })));
+    }
+
+    let operation = match db.confirm_bulk_operation(&operation.id).await {
+        Ok(Some(operation)) => operation,
+        Ok(None) => return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Bulk operation has already been confirmed or its preview expired"
+        }))),
+        Err(e) => {
+            error!("Error confirming bulk operation: {}", e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to confirm bulk operation"
+            })));
+        }
+    };
+
+    let db = db.get_ref().clone();
+    let running = operation.clone();
+    tokio::spawn(async move {
+        bulk::run_bulk_operation(&db, running).await;
+    });
+    Ok(HttpResponse::Accepted().json(operation))
+}
+
+/// An operation's preview, or its progress once confirmed.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/bulk/{operation_id}",
+    tag = "admin",
+    params(("operation_id" = String, Path, description = "Bulk operation id")),
+    responses(
+        (status = 200, description = "The operation", body = BulkOperation),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/bulk/{operation_id}")]
+pub async fn get_bulk_operation(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    operation_id: RecordPath<BulkOperation>,
+) -> Result<HttpResponse> {
+    match db.get_bulk_operation(operation_id.key()).await {
+        Some(operation) => Ok(HttpResponse::Ok().json(operation)),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Bulk operation not found"
+        }))),
+    }
+}
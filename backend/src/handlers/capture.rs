@@ -0,0 +1,180 @@
+use actix_web::{HttpResponse, Result, post};
+use actix_web::middleware::from_fn;
+use actix_web::web::Data;
+use chrono::Utc;
+use tracing::info;
+use crate::extractors::{TenantAdminAuth, RecordPath};
+use crate::handlers::payment::ApiResponseError;
+use crate::handlers::refund::find_payment;
+use crate::middleware::require_signed_request;
+use crate::models::job::JobPayload;
+use crate::models::payment::{Payment, PaymentMethod, PaymentStatus};
+use crate::models::state_reason;
+use crate::services::database::DatabaseService;
+use crate::services::gateway::{ChargeStatus, PaymentGateway};
+use crate::services::jobs;
+
+/// The authorised payment behind `payment_id` and the gateway's id for its
+/// authorisation, or the response explaining why there is none.
+async fn authorized_payment(db: &DatabaseService, payment_id: String) -> std::result::Result<(Payment, String), HttpResponse> {
+    let payment = find_payment(db, &payment_id).await.ok_or_else(|| HttpResponse::NotFound().json(ApiResponseError {
+        message: "Payment not found".to_string(),
+        details: Some(payment_id),
+    }))?;
+
+    if payment.status != PaymentStatus::Authorized {
+        return Err(HttpResponse::BadRequest().json(ApiResponseError {
+            message: "Only authorised payments can be captured or voided".to_string(),
+            details: Some(format!("Payment status is {:?}", payment.status)),
+        }));
+    }
+    let reference = payment.peach_payment_id.clone().ok_or_else(|| HttpResponse::BadRequest().json(ApiResponseError {
+        message: "Payment has no gateway reference for its authorisation".to_string(),
+        details: None,
+    }))?;
+    Ok((payment, reference))
+}
+
+/// Takes the amount reserved at signup, once the service is provisioned,
+/// and activates the subscription as a successful payment would.
+#[utoipa::path(
+    post,
+    path = "/api/v1/payments/{payment_id}/capture",
+    tag = "payments",
+    params(("payment_id" = String, Path, description = "Payment id")),
+    responses(
+        (status = 200, description = "`merchant_transaction_id`, `status`, `result_code` and `subscription_activated`"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Conflicts with the current state"),
+        (status = 500, description = "Internal error"),
+        (status = 502, description = "Gateway error"),
+    ),
+    security(("admin_token" = [], "request_signature" = []))
+)]
+#[post("/{payment_id}/capture", wrap = "from_fn(require_signed_request)")]
+pub async fn capture_payment(
+    _admin: TenantAdminAuth,
+    db: Data<DatabaseService>,
+    gateway: Data<dyn PaymentGateway>,
+    payment_id: RecordPath<Payment>,
+) -> Result<HttpResponse> {
+    let (payment, reference) = match authorized_payment(&db, payment_id.into_key()).await {
+        Ok(found) => found,
+        Err(response) => return Ok(response),
+    };
+
+    let transaction = match gateway.capture(&reference, payment.card_amount(), &payment.currency).await {
+        Ok(transaction) => transaction,
+        Err(e) => return Ok(HttpResponse::BadGateway().json(ApiResponseError {
+            message: format!("Failed to capture payment with {}", gateway.name()),
+            details: Some(e.to_string()),
+        })),
+    };
+    // Captures are answered synchronously; anything short of success leaves
+    // the authorisation in place to capture again or void
+    if transaction.status != ChargeStatus::Succeeded {
+        return Ok(HttpResponse::BadGateway().json(ApiResponseError {
+            message: "The gateway did not confirm the capture".to_string(),
+            details: Some(format!("{} ({:?})", transaction.code, transaction.status)),
+        }));
+    }
+
+    let payment_details = transaction.payment_brand.clone()
+        .and_then(|brand| PaymentMethod::from_brand(&brand).map(|method| (method, brand)));
+    let completion = match db.complete_payment_and_activate(
+        &payment,
+        Utc::now(),
+        transaction.gateway_reference.as_deref(),
+        payment_details,
+    ).await {
+        Ok(completion) => completion,
+        Err(e) => return Ok(HttpResponse::InternalServerError().json(ApiResponseError {
+            message: "Payment was captured but could not be completed".to_string(),
+            details: Some(e),
+        })),
+    };
+    if !completion.payment_updated {
+        return Ok(HttpResponse::Conflict().json(ApiResponseError {
+            message: "Payment changed while it was being captured".to_string(),
+            details: Some(payment.merchant_transaction_id),
+        }));
+    }
+    info!("Captured authorised payment {}", payment.merchant_transaction_id);
+
+    let merchant_transaction_id = payment.merchant_transaction_id.clone();
+    jobs::enqueue(&db, JobPayload::IssueInvoice { merchant_transaction_id: merchant_transaction_id.clone() }).await;
+    jobs::enqueue(&db, JobPayload::PaymentSucceededEmail { merchant_transaction_id: merchant_transaction_id.clone() }).await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "merchant_transaction_id": merchant_transaction_id,
+        "status": format!("{:?}", PaymentStatus::Completed),
+        "result_code": transaction.code,
+        "subscription_activated": completion.subscription_activated,
+    })))
+}
+
+/// Releases the amount reserved at signup when the service won't be
+/// provisioned. Wallet credit the payment used is given back.
+#[utoipa::path(
+    post,
+    path = "/api/v1/payments/{payment_id}/void",
+    tag = "payments",
+    params(("payment_id" = String, Path, description = "Payment id")),
+    responses(
+        (status = 200, description = "`merchant_transaction_id`, `status` and `result_code`"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Conflicts with the current state"),
+        (status = 500, description = "Internal error"),
+        (status = 502, description = "Gateway error"),
+    ),
+    security(("admin_token" = [], "request_signature" = []))
+)]
+#[post("/{payment_id}/void", wrap = "from_fn(require_signed_request)")]
+pub async fn void_payment(
+    _admin: TenantAdminAuth,
+    db: Data<DatabaseService>,
+    gateway: Data<dyn PaymentGateway>,
+    payment_id: RecordPath<Payment>,
+) -> Result<HttpResponse> {
+    let (payment, reference) = match authorized_payment(&db, payment_id.into_key()).await {
+        Ok(found) => found,
+        Err(response) => return Ok(response),
+    };
+
+    let transaction = match gateway.void(&reference, &payment.currency).await {
+        Ok(transaction) => transaction,
+        Err(e) => return Ok(HttpResponse::BadGateway().json(ApiResponseError {
+            message: format!("Failed to void payment with {}", gateway.name()),
+            details: Some(e.to_string()),
+        })),
+    };
+    if transaction.status != ChargeStatus::Succeeded {
+        return Ok(HttpResponse::BadGateway().json(ApiResponseError {
+            message: "The gateway did not confirm the void".to_string(),
+            details: Some(format!("{} ({:?})", transaction.code, transaction.status)),
+        }));
+    }
+
+    match db.apply_payment_event(&payment.merchant_transaction_id, &PaymentStatus::Cancelled, state_reason::PAYMENT_VOIDED, Utc::now()).await {
+        Ok(true) => {}
+        Ok(false) => return Ok(HttpResponse::Conflict().json(ApiResponseError {
+            message: "Payment changed while it was being voided".to_string(),
+            details: Some(payment.merchant_transaction_id),
+        })),
+        Err(e) => return Ok(HttpResponse::InternalServerError().json(ApiResponseError {
+            message: "Payment was voided but could not be updated".to_string(),
+            details: Some(e),
+        })),
+    }
+    info!("Voided authorised payment {}", payment.merchant_transaction_id);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "merchant_transaction_id": payment.merchant_transaction_id,
+        "status": format!("{:?}", PaymentStatus::Cancelled),
+        "result_code": transaction.code,
+    })))
+}
@@ -0,0 +1,152 @@
+use actix_web::{HttpResponse, Result, get, post};
+use actix_web::web::{Data, Path};
+use tracing::info;
+use crate::extractors::ValidatedJson;
+use crate::handlers::payment::ApiResponseError;
+use crate::models::card_update::{CardUpdateRequest, CardUpdateStatus};
+use crate::models::webhook_event::WebhookOutcome;
+use crate::services::database::DatabaseService;
+use crate::services::email::{EmailEvent, EmailService};
+use crate::services::gateway::{CardRegistrationRequest, ChargeStatus, GatewayTransaction, PaymentGateway};
+
+/// Starts a checkout that saves a new card for renewals without charging it.
+/// The current card keeps being used until the gateway confirms the new one.
+#[utoipa::path(
+    post,
+    path = "/api/v1/payments/card-update",
+    tag = "payments",
+    request_body = CardUpdateRequest,
+    responses(
+        (status = 200, description = "Checkout session: `gateway`, `checkoutId`, `merchantTransactionId`, `redirectUrl`"),
+        (status = 403, description = "Not allowed"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    )
+)]
+#[post("/card-update")]
+pub async fn start_card_update(
+    db: Data<DatabaseService>,
+    gateway: Data<dyn PaymentGateway>,
+    payload: ValidatedJson<CardUpdateRequest>,
+) -> Result<HttpResponse> {
+    let subscription = match db.get_subscription(&payload.subscription_id).await {
+        Some(s) if s.user_id == payload.user_id => s,
+        Some(_) => return Ok(HttpResponse::Forbidden().json(ApiResponseError {
+            message: "Subscription does not belong to this user".to_string(),
+            details: None,
+        })),
+        None => return Ok(HttpResponse::NotFound().json(ApiResponseError {
+            message: "Subscription not found".to_string(),
+            details: Some(payload.subscription_id.clone()),
+        })),
+    };
+
+    let card_update = match db.create_card_update(&payload.user_id, &subscription.id, gateway.name()).await {
+        Ok(update) => update,
+        Err(e) => return Ok(HttpResponse::InternalServerError().json(ApiResponseError {
+            message: "Error creating card update".to_string(),
+            details: Some(e),
+        })),
+    };
+
+    let current_token = db.get_recurring_token_by_user(&payload.user_id).await;
+    let request = CardRegistrationRequest {
+        user_id: &payload.user_id,
+        subscription_id: &subscription.id,
+        currency: &subscription.currency,
+        merchant_transaction_id: &card_update.merchant_transaction_id,
+        current_token: current_token.as_deref(),
+    };
+
+    match gateway.initiate_card_registration(&request).await {
+        Ok(session) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "gateway": gateway.name(),
+            "checkoutId": session.checkout_id,
+            "merchantTransactionId": card_update.merchant_transaction_id,
+            "redirectUrl": session.redirect_url
+        }))),
+        Err(e) => {
+            let _ = db.fail_card_update(&card_update.merchant_transaction_id, "initiate_failed").await;
+            Ok(HttpResponse::InternalServerError().json(ApiResponseError {
+                message: format!("Failed to start card update with {}", gateway.name()),
+                details: Some(e.to_string()),
+            }))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/payments/card-update/{merchant_transaction_id}",
+    tag = "payments",
+    params(
+        ("merchant_transaction_id" = String, Path, description = "Merchant transaction id"),
+    ),
+    responses(
+        (status = 200, description = "The card update", body = CardUpdate),
+        (status = 404, description = "Not found"),
+    )
+)]
+#[get("/card-update/{merchant_transaction_id}")]
+pub async fn get_card_update(
+    db: Data<DatabaseService>,
+    path: Path<String>,
+) -> Result<HttpResponse> {
+    let merchant_transaction_id = path.into_inner();
+    match db.get_card_update(&merchant_transaction_id).await {
+        Some(update) => Ok(HttpResponse::Ok().json(update)),
+        None => Ok(HttpResponse::NotFound().json(ApiResponseError {
+            message: "Card update not found".to_string(),
+            details: Some(merchant_transaction_id),
+        })),
+    }
+}
+
+/// Handles the webhook for a card-update checkout: on success the new token
+/// replaces the user's saved card; on failure the old card stays in place.
+pub async fn process_card_update_webhook(
+    db: &DatabaseService,
+    email: &EmailService,
+    transaction: &GatewayTransaction,
+) -> Result<WebhookOutcome, String> {
+    let merchant_transaction_id = transaction.merchant_transaction_id.clone().unwrap_or_default();
+    let card_update = db.get_card_update(&merchant_transaction_id).await
+        .ok_or_else(|| format!("No card update found for merchantTransactionId: {}", merchant_transaction_id))?;
+
+    if card_update.status != CardUpdateStatus::Pending {
+        info!("Card update {} already {:?}", merchant_transaction_id, card_update.status);
+        return Ok(WebhookOutcome::Ignored);
+    }
+
+    match transaction.status {
+        ChargeStatus::Succeeded => {
+            let token = transaction.registration_id.as_deref()
+                .ok_or_else(|| format!("Card update {} succeeded without a registration id", merchant_transaction_id))?;
+
+            db.complete_card_update(
+                &card_update,
+                token,
+                transaction.card_last4.clone(),
+                transaction.payment_brand.clone(),
+                transaction.card_expiry,
+                &transaction.code,
+            ).await?;
+
+            let card = match (&transaction.payment_brand, &transaction.card_last4) {
+                (Some(brand), Some(last4)) => format!("{} ending in {}", brand, last4),
+                (None, Some(last4)) => format!("The card ending in {}", last4),
+                _ => "Your new card".to_string(),
+            };
+            email.notify_user(db, &card_update.user_id, EmailEvent::CardUpdated { card }).await;
+            Ok(WebhookOutcome::Processed)
+        }
+        ChargeStatus::Failed => {
+            db.fail_card_update(&merchant_transaction_id, &transaction.code).await?;
+            Ok(WebhookOutcome::Processed)
+        }
+        ChargeStatus::Pending | ChargeStatus::AwaitingAuthentication => {
+            info!("Card update {} still pending", merchant_transaction_id);
+            Ok(WebhookOutcome::Ignored)
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use actix_web::{HttpResponse, Result, get, post};
+use actix_web::web::Data;
+use tracing::{error, info};
+use crate::extractors::{AdminAuth, RecordPath, TenantAdminAuth};
+use crate::models::subscription::Subscription;
+use crate::services::consistency::{check_subscription, expected_dates, ConsistencyFinding};
+use crate::services::database::DatabaseService;
+use crate::services::schema;
+
+/// Differences between the live SurrealDB schema and the one the models
+/// expect. Read-only; missing definitions are applied at startup.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/schema",
+    tag = "admin",
+    responses(
+        (status = 200, description = "`expected_version`, `live_version`, `drift` and `applied`"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/schema")]
+pub async fn get_schema_report(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+) -> Result<HttpResponse> {
+    match schema::inspect(&db.db).await {
+        Ok(report) => Ok(HttpResponse::Ok().json(report)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to inspect schema",
+            "details": e
+        }))),
+    }
+}
+
+/// Lists billed subscriptions whose dates disagree with their billing period
+/// or payment history, typically after manual edits in the database.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/billing-consistency",
+    tag = "admin",
+    responses(
+        (status = 200, description = "`checked`, `flagged` and the `findings`"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/billing-consistency")]
+pub async fn get_consistency_report(
+    _admin: TenantAdminAuth,
+    db: Data<DatabaseService>,
+) -> Result<HttpResponse> {
+    let subscriptions = match db.get_billed_subscriptions().await {
+        Ok(list) => list,
+        Err(e) => {
+            error!("Error fetching subscriptions for consistency report: {}", e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to build consistency report"
+            })));
+        }
+    };
+
+    let checked = subscriptions.len();
+    let mut findings: Vec<ConsistencyFinding> = Vec::new();
+    for sub in &subscriptions {
+        let payments = db.get_payments_by_subscription(&sub.id).await;
+        if let Some(finding) = check_subscription(sub, &payments) {
+            findings.push(finding);
+        }
+    }
+
+    info!("Consistency check: {} of {} subscriptions flagged", findings.len(), checked);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "checked": checked,
+        "flagged": findings.len(),
+        "findings": findings
+    })))
+}
+
+/// Rewrites start/end/grace dates from the subscription's anchor and payment
+/// history, using the same rules as the report.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/billing-consistency/{subscription_id}/recompute",
+    tag = "admin",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    responses(
+        (status = 200, description = "The corrected `subscription` and its `remaining_issues`"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 422, description = "Cannot be processed"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/billing-consistency/{subscription_id}/recompute")]
+pub async fn recompute_subscription_dates(
+    _admin: TenantAdminAuth,
+    db: Data<DatabaseService>,
+    subscription_id: RecordPath<Subscription>,
+) -> Result<HttpResponse> {
+    let sub = match db.get_subscription(subscription_id.key()).await {
+        Some(sub) => sub,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Subscription not found"
+        }))),
+    };
+
+    let payments = db.get_payments_by_subscription(&sub.id).await;
+    let expected = match expected_dates(&sub, &payments) {
+        Some(expected) => expected,
+        None => return Ok(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": "Subscription has no start date or completed payment to recompute from"
+        }))),
+    };
+
+    match db.set_subscription_billing_dates(&sub.id, expected.start_date, expected.end_date, expected.grace_end_date).await {
+        Ok(updated) => {
+            let payments = db.get_payments_by_subscription(&updated.id).await;
+            let remaining = check_subscription(&updated, &payments);
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "subscription": updated,
+                "remaining_issues": remaining.map(|f| f.issues).unwrap_or_default()
+            })))
+        }
+        Err(e) => {
+            error!("Failed to recompute dates for {}: {}", subscription_id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to recompute subscription dates",
+                "details": e
+            })))
+        }
+    }
+}
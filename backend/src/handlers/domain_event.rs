@@ -0,0 +1,120 @@
+use actix_web::{HttpResponse, Result, get, post};
+use actix_web::web::{Data, Query};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use tracing::error;
+use crate::extractors::{AdminAuth, RecordPath};
+use crate::models::domain_event::{DomainEvent, DomainEventStatus};
+use crate::services::database::DatabaseService;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DomainEventListQuery {
+    pub status: Option<DomainEventStatus>,
+    /// e.g. `payment.completed`.
+    pub event_type: Option<String>,
+    pub limit: Option<u32>,
+}
+
+fn server_error(message: &str, details: String) -> HttpResponse {
+    error!("{}: {}", message, details);
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "error": message,
+        "details": details
+    }))
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "error": "Event not found"
+    }))
+}
+
+/// Domain events in the outbox, newest first. Failed events ran out of
+/// delivery attempts and show the last sink error.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/events",
+    tag = "admin",
+    params(DomainEventListQuery),
+    responses(
+        (status = 200, description = "Matching events", body = [DomainEvent]),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/events")]
+pub async fn list_domain_events(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    query: Query<DomainEventListQuery>,
+) -> Result<HttpResponse> {
+    let query = query.into_inner();
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let event_type = query.event_type.map(|t| t.trim().to_string()).filter(|t| !t.is_empty());
+
+    match db.list_domain_events(query.status, event_type, limit).await {
+        Ok(events) => Ok(HttpResponse::Ok().json(events)),
+        Err(e) => Ok(server_error("Failed to list events", e)),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/events/{event_id}",
+    tag = "admin",
+    params(("event_id" = String, Path, description = "Event id")),
+    responses(
+        (status = 200, description = "The event", body = DomainEvent),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/events/{event_id}")]
+pub async fn get_domain_event(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    event_id: RecordPath<DomainEvent>,
+) -> Result<HttpResponse> {
+    match db.get_domain_event(event_id.key()).await {
+        Some(event) => Ok(HttpResponse::Ok().json(event)),
+        None => Ok(not_found()),
+    }
+}
+
+/// Queues a failed event for delivery to every sink again, with a fresh set
+/// of attempts.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/events/{event_id}/redeliver",
+    tag = "admin",
+    params(("event_id" = String, Path, description = "Event id")),
+    responses(
+        (status = 200, description = "The requeued event", body = DomainEvent),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "The event hasn't failed"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/events/{event_id}/redeliver")]
+pub async fn redeliver_domain_event(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    event_id: RecordPath<DomainEvent>,
+) -> Result<HttpResponse> {
+    match db.redeliver_domain_event(event_id.key()).await {
+        Ok(event) => Ok(HttpResponse::Ok().json(event)),
+        Err(e) if e.starts_with("Event not found") => Ok(not_found()),
+        Err(e) if e.contains("only failed events can be redelivered") => {
+            Ok(HttpResponse::Conflict().json(serde_json::json!({ "error": e })))
+        }
+        Err(e) => Ok(server_error("Failed to redeliver event", e)),
+    }
+}
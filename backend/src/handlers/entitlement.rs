@@ -0,0 +1,203 @@
+use actix_web::{HttpResponse, Result, delete, get, put};
+use actix_web::web::{Data, Path, Query};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use tracing::error;
+use crate::extractors::{AdminAuth, RecordPath, ValidatedJson};
+use crate::models::entitlement::{validate_feature_key, SetPlanFeatureDto, FREE_TIER_PLAN_ID};
+use crate::models::plan::Plan;
+use crate::models::user::User;
+use crate::services::database::DatabaseService;
+use crate::services::entitlements::{check_feature, check_feature_limit, user_entitlements};
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/entitlements/{user_id}/features",
+    tag = "entitlements",
+    params(("user_id" = String, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Access level and features of the user", body = UserEntitlements),
+        (status = 400, description = "Invalid request"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    )
+)]
+#[get("/{user_id}/features")]
+pub async fn get_user_features(
+    db: Data<DatabaseService>,
+    user_id: RecordPath<User>,
+) -> Result<HttpResponse> {
+    if db.get_user(user_id.key()).await.is_none() {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "User not found"
+        })));
+    }
+
+    match user_entitlements(&db, user_id.key()).await {
+        Ok(entitlements) => Ok(HttpResponse::Ok().json(entitlements)),
+        Err(e) => {
+            error!("Error loading entitlements for {}: {}", user_id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to load entitlements"
+            })))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FeatureCheckQuery {
+    /// Total usage after the intended action; omit to check access only.
+    pub usage: Option<u64>,
+}
+
+/// 200 with the entitlement when the user may use the feature, 403 otherwise.
+#[utoipa::path(
+    get,
+    path = "/api/v1/entitlements/{user_id}/features/{feature_key}",
+    tag = "entitlements",
+    params(
+        ("user_id" = String, Path, description = "User id"),
+        ("feature_key" = String, Path, description = "Feature key"),
+        FeatureCheckQuery,
+    ),
+    responses(
+        (status = 200, description = "The user may use the feature", body = FeatureEntitlement),
+        (status = 400, description = "Invalid request"),
+        (status = 403, description = "Not allowed"),
+        (status = 500, description = "Internal error"),
+    )
+)]
+#[get("/{user_id}/features/{feature_key}")]
+pub async fn check_user_feature(
+    db: Data<DatabaseService>,
+    user_id: RecordPath<User>,
+    path: Path<(String, String)>,
+    query: Query<FeatureCheckQuery>,
+) -> Result<HttpResponse> {
+    let (_, feature_key) = path.into_inner();
+
+    let result = match query.usage {
+        Some(usage) => check_feature_limit(&db, user_id.key(), &feature_key, usage).await,
+        None => check_feature(&db, user_id.key(), &feature_key).await,
+    };
+
+    match result {
+        Ok(feature) => Ok(HttpResponse::Ok().json(feature)),
+        Err(e) => Ok(e.to_response()),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/plans/{plan_id}/features",
+    tag = "admin",
+    params(("plan_id" = String, Path, description = "Plan id")),
+    responses(
+        (status = 200, description = "Features of the plan", body = [PlanFeature]),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/plans/{plan_id}/features")]
+pub async fn admin_list_plan_features(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    plan_id: RecordPath<Plan>,
+) -> Result<HttpResponse> {
+    match db.get_plan_features(&[plan_id.into_key()]).await {
+        Ok(features) => Ok(HttpResponse::Ok().json(features)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to list plan features",
+            "details": e
+        }))),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/plans/{plan_id}/features/{feature_key}",
+    tag = "admin",
+    params(
+        ("plan_id" = String, Path, description = "Plan id"),
+        ("feature_key" = String, Path, description = "Feature key"),
+    ),
+    request_body = SetPlanFeatureDto,
+    responses(
+        (status = 200, description = "The stored feature", body = PlanFeature),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[put("/plans/{plan_id}/features/{feature_key}")]
+pub async fn admin_set_plan_feature(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    plan_id: RecordPath<Plan>,
+    path: Path<(String, String)>,
+    payload: ValidatedJson<SetPlanFeatureDto>,
+) -> Result<HttpResponse> {
+    let plan_id = plan_id.into_key();
+    let (_, feature_key) = path.into_inner();
+
+    if let Err(e) = validate_feature_key(&feature_key) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+    }
+    // The free tier has no plan row of its own
+    if plan_id != FREE_TIER_PLAN_ID && db.get_plan(&plan_id).await.is_none() {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Plan not found"
+        })));
+    }
+
+    match db.set_plan_feature(&plan_id, &feature_key, payload.limit).await {
+        Ok(feature) => Ok(HttpResponse::Ok().json(feature)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to set plan feature",
+            "details": e
+        }))),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/plans/{plan_id}/features/{feature_key}",
+    tag = "admin",
+    params(
+        ("plan_id" = String, Path, description = "Plan id"),
+        ("feature_key" = String, Path, description = "Feature key"),
+    ),
+    responses(
+        (status = 204, description = "Feature removed"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[delete("/plans/{plan_id}/features/{feature_key}")]
+pub async fn admin_delete_plan_feature(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    plan_id: RecordPath<Plan>,
+    path: Path<(String, String)>,
+) -> Result<HttpResponse> {
+    let (_, feature_key) = path.into_inner();
+
+    match db.delete_plan_feature(plan_id.key(), &feature_key).await {
+        Ok(()) => Ok(HttpResponse::NoContent().finish()),
+        Err(e) if e.starts_with("Feature not found") => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Feature not found"
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to remove plan feature",
+            "details": e
+        }))),
+    }
+}
@@ -0,0 +1,44 @@
+use actix_web::{HttpResponse, Result, get};
+use actix_web::web::Data;
+use serde_json::json;
+use crate::services::database::DatabaseService;
+use crate::services::health::{HealthService, ProbeStatus, ReadinessReport};
+
+/// Liveness: the process is up and serving requests. Checks no
+/// dependencies, so an outage elsewhere doesn't get the pod restarted.
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "health",
+    responses(
+        (status = 200, description = "The process is serving requests"),
+    )
+)]
+#[get("/live")]
+pub async fn liveness() -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(json!({ "status": ProbeStatus::Up })))
+}
+
+/// Readiness: per-dependency status for load balancers. Only an unreachable
+/// database takes the instance out of rotation; the gateway probe is cached
+/// for a minute.
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "Ready; `status` is `degraded` if the gateway or renewal task is unhealthy", body = ReadinessReport),
+        (status = 503, description = "The database is unreachable", body = ReadinessReport),
+    )
+)]
+#[get("/ready")]
+pub async fn readiness(
+    db: Data<DatabaseService>,
+    health: Data<HealthService>,
+) -> Result<HttpResponse> {
+    let report = health.readiness(&db).await;
+    if report.status == ProbeStatus::Down {
+        return Ok(HttpResponse::ServiceUnavailable().json(report));
+    }
+    Ok(HttpResponse::Ok().json(report))
+}
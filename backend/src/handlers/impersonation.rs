@@ -0,0 +1,93 @@
+use actix_web::{HttpResponse, Result, delete, post};
+use actix_web::web::Data;
+use chrono::{Duration, Utc};
+use tracing::error;
+use uuid::Uuid;
+use crate::extractors::{AdminAuth, RecordPath, ValidatedJson};
+use crate::models::impersonation::{hash_token, ImpersonationSession, ImpersonationStarted, StartImpersonationDto};
+use crate::models::user::User;
+use crate::models::validation::ValidationErrorResponse;
+use crate::services::database::DatabaseService;
+
+fn server_error(message: &str, details: String) -> HttpResponse {
+    error!("{}: {}", message, details);
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "error": message,
+    }))
+}
+
+/// Opens a short-lived session for support to see the app as the user does.
+/// Send the returned token as `X-Impersonation-Token` in place of
+/// `X-User-Id`; read-only sessions can only make GET requests. Everything
+/// done in the session is tagged with its id in the audit log.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/impersonate/{user_id}",
+    tag = "admin",
+    params(("user_id" = String, Path, description = "User id")),
+    request_body = StartImpersonationDto,
+    responses(
+        (status = 201, description = "Session opened; the token isn't shown again", body = ImpersonationStarted),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/impersonate/{user_id}")]
+pub async fn start_impersonation(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    user_id: RecordPath<User>,
+    payload: ValidatedJson<StartImpersonationDto>,
+) -> Result<HttpResponse> {
+    let dto = payload.into_inner();
+    let minutes = dto.duration_minutes();
+
+    match db.get_user(user_id.key()).await {
+        Some(user) if user.deleted_at.is_none() => {}
+        _ => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "User not found"
+            })));
+        }
+    }
+
+    let token = format!("imp_{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let expires_at = Utc::now() + Duration::minutes(minutes as i64);
+    match db.create_impersonation_session(user_id.key(), &dto.reason, dto.scope, &hash_token(&token), expires_at).await {
+        Ok(session) => Ok(HttpResponse::Created().json(ImpersonationStarted { session, token })),
+        Err(e) => Ok(server_error("Failed to start impersonation", e)),
+    }
+}
+
+/// Ends a session before it expires.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/impersonate/sessions/{session_id}",
+    tag = "admin",
+    params(("session_id" = String, Path, description = "Impersonation session id")),
+    responses(
+        (status = 204, description = "Session ended"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found or already ended"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[delete("/impersonate/sessions/{session_id}")]
+pub async fn end_impersonation(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    session_id: RecordPath<ImpersonationSession>,
+) -> Result<HttpResponse> {
+    match db.revoke_impersonation_session(session_id.key()).await {
+        Ok(Some(_)) => Ok(HttpResponse::NoContent().finish()),
+        Ok(None) => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No active impersonation session with that id"
+        }))),
+        Err(e) => Ok(server_error("Failed to end impersonation", e)),
+    }
+}
@@ -0,0 +1,53 @@
+use actix_web::{HttpResponse, Result, get};
+use actix_web::web::Data;
+use tracing::error;
+use crate::extractors::{CurrentUser, RecordPath};
+use crate::handlers::subscription::load_owned_subscription;
+use crate::models::installment::InstallmentSchedule;
+use crate::models::subscription::Subscription;
+use crate::services::database::DatabaseService;
+
+/// Where the installments of the caller's subscription stand: what has been
+/// paid, what is left and when the next one is charged.
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscriptions/{subscription_id}/installments",
+    tag = "subscriptions",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    responses(
+        (status = 200, description = "The subscription's latest installment schedule", body = InstallmentSchedule),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found, or not paid in installments"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[get("/{subscription_id}/installments")]
+pub async fn get_installments(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    subscription_id: RecordPath<Subscription>,
+) -> Result<HttpResponse> {
+    let subscription = match load_owned_subscription(&db, &user, subscription_id.key()).await {
+        Some(s) => s,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Subscription not found"
+        }))),
+    };
+
+    match db.get_installments(&subscription.id).await {
+        Ok(installments) => match InstallmentSchedule::from_installments(installments) {
+            Some(schedule) => Ok(HttpResponse::Ok().json(schedule)),
+            None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "No installments"
+            }))),
+        },
+        Err(e) => {
+            error!("Error loading installments for {}: {}", subscription.id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to load installments"
+            })))
+        }
+    }
+}
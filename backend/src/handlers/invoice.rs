@@ -0,0 +1,178 @@
+use actix_web::{HttpRequest, HttpResponse, Result, get, put};
+use actix_web::http::header;
+use actix_web::web::Data;
+use tracing::error;
+use crate::config::AppConfig;
+use crate::extractors::{AdminAuth, CurrentUser, RecordPath, ValidatedJson};
+use crate::models::invoice::Invoice;
+use crate::models::merchant::{validate_address, validate_vat_number, MerchantDetails, UpdateMerchantDetailsDto};
+use crate::models::user::{UpdateBillingDetailsDto, User};
+use crate::models::validation::ValidationErrorResponse;
+use crate::services::database::DatabaseService;
+use crate::services::formatting::Formatting;
+use crate::services::invoicing::{merchant_details, render_invoice_pdf};
+
+/// Loads an invoice owned by the caller. Other users' invoices are reported as
+/// missing so ids can't be probed.
+async fn load_owned_invoice(db: &DatabaseService, user: &CurrentUser, invoice_id: &str) -> Option<Invoice> {
+    db.get_invoice(invoice_id).await.filter(|invoice| invoice.user_id == user.user_id)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/invoices/{invoice_id}",
+    tag = "invoices",
+    params(("invoice_id" = String, Path, description = "Invoice id")),
+    responses(
+        (status = 200, description = "The invoice", body = Invoice),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+    ),
+    security(("user_id" = []))
+)]
+#[get("/{invoice_id}")]
+pub async fn get_invoice(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    invoice_id: RecordPath<Invoice>,
+) -> Result<HttpResponse> {
+    match load_owned_invoice(&db, &user, invoice_id.key()).await {
+        Some(invoice) => Ok(HttpResponse::Ok().json(invoice)),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Invoice not found"
+        }))),
+    }
+}
+
+/// Labels, dates and amounts follow the language on the caller's profile,
+/// else Accept-Language.
+#[utoipa::path(
+    get,
+    path = "/api/v1/invoices/{invoice_id}/pdf",
+    tag = "invoices",
+    params(("invoice_id" = String, Path, description = "Invoice id")),
+    responses(
+        (status = 200, description = "The invoice as `application/pdf`"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+    ),
+    security(("user_id" = []))
+)]
+#[get("/{invoice_id}/pdf")]
+pub async fn get_invoice_pdf(
+    req: HttpRequest,
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    invoice_id: RecordPath<Invoice>,
+) -> Result<HttpResponse> {
+    let invoice = match load_owned_invoice(&db, &user, invoice_id.key()).await {
+        Some(invoice) => invoice,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Invoice not found"
+        }))),
+    };
+
+    let profile_locale = db.get_user(&user.user_id).await.and_then(|u| u.locale);
+    let pdf = render_invoice_pdf(&invoice, &Formatting::for_profile(profile_locale.as_deref(), &req));
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/pdf")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.pdf\"", invoice.invoice_number),
+        ))
+        .body(pdf))
+}
+
+/// Sets the address and VAT number printed on the caller's future invoices;
+/// invoices already issued keep the details they were issued with.
+#[utoipa::path(
+    put,
+    path = "/api/v1/me/billing-details",
+    tag = "me",
+    request_body = UpdateBillingDetailsDto,
+    responses(
+        (status = 200, description = "The updated user", body = User),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[put("/billing-details")]
+pub async fn update_billing_details(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    payload: ValidatedJson<UpdateBillingDetailsDto>,
+) -> Result<HttpResponse> {
+    let dto = payload.into_inner();
+    let billing_address = dto.billing_address.as_deref().and_then(|a| validate_address(a).ok().flatten());
+    let vat_number = dto.vat_number.as_deref().and_then(|v| validate_vat_number(v).ok().flatten());
+
+    match db.set_billing_details(&user.user_id, billing_address, vat_number).await {
+        Ok(user) => Ok(HttpResponse::Ok().json(user)),
+        Err(e) => {
+            error!("Failed to update billing details for {}: {}", user.user_id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to update billing details"
+            })))
+        }
+    }
+}
+
+/// The supplier details new invoices are issued under. `updated_at` is
+/// empty while they still come from the `INVOICE_SELLER_*` settings.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/merchant-details",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Current merchant details", body = MerchantDetails),
+        (status = 401, description = "Missing or invalid credentials"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/merchant-details")]
+pub async fn get_merchant_details(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    config: Data<AppConfig>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(merchant_details(&db, &config).await))
+}
+
+/// Replaces the supplier details; from then on they take precedence over
+/// the `INVOICE_SELLER_*` settings.
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/merchant-details",
+    tag = "admin",
+    request_body = UpdateMerchantDetailsDto,
+    responses(
+        (status = 200, description = "The saved merchant details", body = MerchantDetails),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[put("/merchant-details")]
+pub async fn update_merchant_details(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    payload: ValidatedJson<UpdateMerchantDetailsDto>,
+) -> Result<HttpResponse> {
+    match db.set_merchant_details(payload.into_inner().into_details()).await {
+        Ok(details) => Ok(HttpResponse::Ok().json(details)),
+        Err(e) => {
+            error!("Failed to save merchant details: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to save merchant details"
+            })))
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use actix_web::{HttpResponse, Result, get, post};
+use actix_web::web::{Data, Query};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use tracing::error;
+use crate::extractors::{AdminAuth, RecordPath};
+use crate::models::job::{Job, JobStatus};
+use crate::services::database::DatabaseService;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct JobListQuery {
+    pub status: Option<JobStatus>,
+    /// e.g. `issue_invoice` or `payment_succeeded_email`.
+    pub kind: Option<String>,
+    pub limit: Option<u32>,
+}
+
+fn server_error(message: &str, details: String) -> HttpResponse {
+    error!("{}: {}", message, details);
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "error": message,
+        "details": details
+    }))
+}
+
+fn not_found(what: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "error": format!("{} not found", what)
+    }))
+}
+
+/// Queued payment side-effects, newest first. Failed jobs have run out of
+/// attempts and show the last error.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/jobs",
+    tag = "admin",
+    params(JobListQuery),
+    responses(
+        (status = 200, description = "Matching jobs", body = [Job]),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/jobs")]
+pub async fn list_jobs(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    query: Query<JobListQuery>,
+) -> Result<HttpResponse> {
+    let query = query.into_inner();
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let kind = query.kind.map(|k| k.trim().to_string()).filter(|k| !k.is_empty());
+
+    match db.list_jobs(query.status, kind, limit).await {
+        Ok(jobs) => Ok(HttpResponse::Ok().json(jobs)),
+        Err(e) => Ok(server_error("Failed to list jobs", e)),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/jobs/{job_id}",
+    tag = "admin",
+    params(("job_id" = String, Path, description = "Job id")),
+    responses(
+        (status = 200, description = "The job", body = Job),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/jobs/{job_id}")]
+pub async fn get_job(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    job_id: RecordPath<Job>,
+) -> Result<HttpResponse> {
+    match db.get_job(job_id.key()).await {
+        Some(job) => Ok(HttpResponse::Ok().json(job)),
+        None => Ok(not_found("Job")),
+    }
+}
+
+/// Queues a failed job to run again straight away, with a fresh set of attempts.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/jobs/{job_id}/retry",
+    tag = "admin",
+    params(("job_id" = String, Path, description = "Job id")),
+    responses(
+        (status = 200, description = "The requeued job", body = Job),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "The job hasn't failed"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/jobs/{job_id}/retry")]
+pub async fn retry_job(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    job_id: RecordPath<Job>,
+) -> Result<HttpResponse> {
+    match db.retry_job(job_id.key()).await {
+        Ok(job) => Ok(HttpResponse::Ok().json(job)),
+        Err(e) if e.starts_with("Job not found") => Ok(not_found("Job")),
+        Err(e) if e.contains("only failed jobs can be retried") => {
+            Ok(HttpResponse::Conflict().json(serde_json::json!({ "error": e })))
+        }
+        Err(e) => Ok(server_error("Failed to retry job", e)),
+    }
+}
@@ -0,0 +1,148 @@
+use actix_web::{HttpResponse, Result, post};
+use actix_web::middleware::from_fn;
+use actix_web::web::Data;
+use tracing::error;
+use crate::config::AppConfig;
+use crate::extractors::{AdminAuth, ValidatedJson};
+use crate::handlers::payment::ApiResponseError;
+use crate::middleware::require_signed_request;
+use crate::models::attachment::AttachmentOwner;
+use crate::models::payment::{ManualPaymentDto, PaymentMethod};
+use crate::models::validation::ValidationErrorResponse;
+use crate::services::database::DatabaseService;
+use crate::services::email::{EmailEvent, EmailService};
+use crate::services::invoicing::issue_invoice;
+use crate::services::tax::TaxBreakdown;
+
+fn bad_request(message: &str, details: Option<String>) -> HttpResponse {
+    HttpResponse::BadRequest().json(ApiResponseError {
+        message: message.to_string(),
+        details,
+    })
+}
+
+/// Records money received outside the gateways (typically an EFT straight
+/// into the bank account) and extends the subscription it pays for.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/payments/manual",
+    tag = "admin",
+    request_body = ManualPaymentDto,
+    responses(
+        (status = 201, description = "The recorded `payment` and the activated `subscription`"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Conflicts with the current state"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = [], "request_signature" = []))
+)]
+#[post("/payments/manual", wrap = "from_fn(require_signed_request)")]
+pub async fn record_manual_payment(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    config: Data<AppConfig>,
+    email: Data<EmailService>,
+    payload: ValidatedJson<ManualPaymentDto>,
+) -> Result<HttpResponse> {
+    let dto = payload.into_inner();
+
+    let reference = dto.reference.trim();
+
+    let subscription = match db.get_subscription(&dto.subscription_id).await {
+        Some(s) => s,
+        None => return Ok(HttpResponse::NotFound().json(ApiResponseError {
+            message: "Subscription not found".to_string(),
+            details: Some(dto.subscription_id),
+        })),
+    };
+
+    if let Some(currency) = &dto.currency {
+        if !currency.eq_ignore_ascii_case(&subscription.currency) {
+            return Ok(bad_request(
+                "Currency does not match the subscription",
+                Some(format!("Subscription is billed in {}", subscription.currency)),
+            ));
+        }
+    }
+    let amount = dto.amount.unwrap_or(subscription.total_price());
+    if !amount.is_finite() || amount <= 0.0 {
+        return Ok(bad_request("Amount must be greater than zero", None));
+    }
+
+    // The same bank deposit must not extend a subscription twice
+    if let Some(existing) = db.get_manual_payment_by_reference(reference).await {
+        return Ok(HttpResponse::Conflict().json(ApiResponseError {
+            message: "A manual payment with this reference was already recorded".to_string(),
+            details: Some(existing.merchant_transaction_id),
+        }));
+    }
+
+    // Proof is uploaded first via /admin/attachments and referenced by id
+    let proof_attachment = match dto.proof_attachment.as_deref().map(str::trim).filter(|id| !id.is_empty()) {
+        Some(id) => match db.get_attachment(id).await {
+            Some(attachment) if attachment.owner_id.is_none() => Some(attachment.id),
+            Some(_) => return Ok(bad_request("Proof attachment is already linked to another record", Some(id.to_string()))),
+            None => return Ok(bad_request("Proof attachment not found", Some(id.to_string()))),
+        },
+        None => None,
+    };
+
+    let tax = TaxBreakdown::from_inclusive(amount, config.vat_rate_percent);
+    let value_at = dto.value_date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+
+    let (payment, subscription) = match db.record_manual_payment(
+        &subscription,
+        tax,
+        &subscription.currency,
+        dto.payment_method.unwrap_or(PaymentMethod::EFT),
+        value_at,
+        reference,
+        proof_attachment.clone(),
+    ).await {
+        Ok(recorded) => recorded,
+        Err(e) => return Ok(HttpResponse::InternalServerError().json(ApiResponseError {
+            message: "Error recording manual payment".to_string(),
+            details: Some(e),
+        })),
+    };
+
+    if let Some(attachment_id) = &proof_attachment {
+        if let Err(e) = db.link_attachment(attachment_id, AttachmentOwner::Payment, &payment.merchant_transaction_id).await {
+            error!("Failed to link attachment {} to {}: {}", attachment_id, payment.merchant_transaction_id, e);
+        }
+    }
+
+    // Invoicing failures are logged; the payment itself has been recorded
+    let invoice = match issue_invoice(
+        &db,
+        &config,
+        &payment.user_id,
+        payment.subscription_id.as_deref(),
+        &payment.merchant_transaction_id,
+        &payment.currency,
+        tax,
+    ).await {
+        Ok(invoice) => Some(Box::new(invoice)),
+        Err(e) => {
+            error!("Failed to issue invoice for {}: {}", payment.merchant_transaction_id, e);
+            None
+        }
+    };
+
+    email.notify_user(&db, &payment.user_id, EmailEvent::PaymentSucceeded {
+        plan: subscription.plan_name.clone(),
+        amount: payment.amount,
+        currency: payment.currency.clone(),
+        reference: reference.to_string(),
+        descriptor: None,
+        invoice,
+    }).await;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "payment": payment,
+        "subscription": subscription,
+    })))
+}
@@ -0,0 +1,390 @@
+use actix_web::{HttpRequest, HttpResponse, Result, delete, get, post};
+use actix_web::web::{Data, Json, Query};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{ToSchema, IntoParams};
+use tracing::error;
+use crate::config::AppConfig;
+use crate::extractors::CurrentUser;
+use crate::handlers::payment::{enforce_rate_limit, gateway_for_method, preflight_payment, start_checkout, ApiResponseError};
+use crate::handlers::subscription::{load_owned_subscription, SubscriptionResponse};
+use crate::models::activity::ActivityCategory;
+use crate::models::payment::CreatePaymentDto;
+use crate::models::subscription::{ReactivateSubscriptionDto, SkipRenewalDto, Subscription, SubscriptionStatus};
+use crate::services::database::DatabaseService;
+use crate::services::formatting::Formatting;
+use crate::services::gateway::PaymentGateway;
+use crate::services::ozow::OzowPaymentService;
+use crate::services::rate_limit::{RateLimitScope, RateLimiter};
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ActivityQuery {
+    pub page: Option<u32>,
+    pub limit: Option<u32>,
+    pub category: Option<ActivityCategory>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ActivityItem {
+    pub category: ActivityCategory,
+    pub kind: String,
+    pub description: String,
+    pub reference_id: Option<String>,
+    pub amount: Option<f64>,
+    pub status: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub display_date: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ActivityFeedResponse {
+    pub items: Vec<ActivityItem>,
+    pub page: u32,
+    pub limit: u32,
+    pub has_more: bool,
+}
+
+/// Merged feed of the caller's payments, subscription changes and security events,
+/// newest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/activity",
+    tag = "me",
+    params(ActivityQuery),
+    responses(
+        (status = 200, description = "A page of the caller's activity", body = ActivityFeedResponse),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[get("/activity")]
+pub async fn get_my_activity(
+    req: HttpRequest,
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    query: Query<ActivityQuery>,
+) -> Result<HttpResponse> {
+    let page = query.page.unwrap_or(1).max(1);
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    let fmt = Formatting::from_request(&req);
+
+    // Each source is fetched up to the end of the requested page (+1 to detect more),
+    // then merged and sliced.
+    let window = page * limit + 1;
+    let mut items: Vec<ActivityItem> = Vec::new();
+
+    if query.category.is_none() || query.category == Some(ActivityCategory::Payment) {
+        match db.get_recent_payments_by_user(&user.user_id, window).await {
+            Ok(payments) => items.extend(payments.into_iter().map(|p| {
+                let status = format!("{:?}", p.status);
+                ActivityItem {
+                    category: ActivityCategory::Payment,
+                    kind: format!("payment_{}", status.to_lowercase()),
+                    description: format!("Payment of {} ({})", fmt.amount(p.amount, &p.currency), status),
+                    reference_id: Some(p.merchant_transaction_id),
+                    amount: Some(p.amount),
+                    status: Some(status),
+                    created_at: p.created_at,
+                    display_date: fmt.datetime(&p.created_at),
+                }
+            })),
+            Err(e) => {
+                error!("Error fetching payments for activity feed: {}", e);
+                return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": "Failed to load activity"
+                })));
+            }
+        }
+    }
+
+    if query.category != Some(ActivityCategory::Payment) {
+        match db.get_activity_events(&user.user_id, query.category.clone(), window).await {
+            Ok(events) => items.extend(events.into_iter().map(|e| ActivityItem {
+                category: e.category,
+                kind: e.kind,
+                description: e.description,
+                reference_id: e.reference_id,
+                amount: None,
+                status: None,
+                created_at: e.created_at,
+                display_date: fmt.datetime(&e.created_at),
+            })),
+            Err(e) => {
+                error!("Error fetching activity events: {}", e);
+                return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": "Failed to load activity"
+                })));
+            }
+        }
+    }
+
+    items.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    let start = ((page - 1) * limit) as usize;
+    let has_more = items.len() > (page * limit) as usize;
+    let items: Vec<ActivityItem> = items.into_iter().skip(start).take(limit as usize).collect();
+
+    Ok(HttpResponse::Ok().json(ActivityFeedResponse {
+        items,
+        page,
+        limit,
+        has_more,
+    }))
+}
+
+fn conflict(error: &str) -> HttpResponse {
+    HttpResponse::Conflict().json(serde_json::json!({ "error": error }))
+}
+
+/// The caller's subscription named in the request, or their only active one.
+async fn find_my_subscription(
+    db: &DatabaseService,
+    user: &CurrentUser,
+    subscription_id: Option<&str>,
+) -> std::result::Result<Subscription, HttpResponse> {
+    if let Some(subscription_id) = subscription_id {
+        return load_owned_subscription(db, user, subscription_id).await.ok_or_else(|| {
+            HttpResponse::NotFound().json(serde_json::json!({ "error": "Subscription not found" }))
+        });
+    }
+
+    let mut active: Vec<Subscription> = db.get_subscriptions_by_user(&user.user_id).await
+        .into_iter()
+        .filter(|s| matches!(s.status, SubscriptionStatus::Active | SubscriptionStatus::Grace))
+        .collect();
+    match active.len() {
+        0 => Err(HttpResponse::NotFound().json(serde_json::json!({ "error": "No active subscription" }))),
+        1 => Ok(active.remove(0)),
+        _ => Err(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "subscription_id is required when you have more than one active subscription"
+        }))),
+    }
+}
+
+/// Skips the next billing cycle: when the subscription falls due its period
+/// is extended by one cycle without a charge. How many renewals can be
+/// skipped in a year is set per plan.
+#[utoipa::path(
+    post,
+    path = "/api/v1/me/subscription/skip-next",
+    tag = "me",
+    request_body(content = Option<SkipRenewalDto>, description = "Omit to use the caller's only active subscription"),
+    responses(
+        (status = 200, description = "Next renewal will be skipped", body = SubscriptionResponse),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 409, description = "Conflicts with the current state"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[post("/subscription/skip-next")]
+pub async fn skip_next_renewal(
+    req: HttpRequest,
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    payload: Option<Json<SkipRenewalDto>>,
+) -> Result<HttpResponse> {
+    let dto = payload.map(Json::into_inner).unwrap_or_default();
+    let subscription = match find_my_subscription(&db, &user, dto.subscription_id.as_deref()).await {
+        Ok(s) => s,
+        Err(response) => return Ok(response),
+    };
+
+    if subscription.status != SubscriptionStatus::Active {
+        return Ok(conflict("Only an active subscription can skip a renewal"));
+    }
+    if subscription.skip_next_renewal {
+        return Ok(conflict("The next renewal is already skipped"));
+    }
+    if subscription.cancel_at_period_end {
+        return Ok(conflict("The subscription is cancelled at the end of this period"));
+    }
+    if subscription.next_renewal_attempt_at.is_some() {
+        return Ok(conflict("The current renewal has to be paid before skipping the next one"));
+    }
+    // Usage in the skipped period would never be billed
+    if subscription.usage_pricing.is_some() {
+        return Ok(conflict("Renewals of metered subscriptions can't be skipped"));
+    }
+
+    let plan = match subscription.plan_id.as_deref() {
+        Some(plan_id) => db.get_plan(plan_id).await,
+        None => None,
+    };
+    let max_skips = plan.map_or(0, |p| p.max_skips_per_year);
+    if max_skips == 0 {
+        return Ok(conflict("Your plan doesn't allow skipping renewals"));
+    }
+    let skipped = match db.count_renewal_skips_since(&subscription.id, Utc::now() - Duration::days(365)).await {
+        Ok(count) => count,
+        Err(e) => {
+            error!("Error counting renewal skips for {}: {}", subscription.id, e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to skip renewal"
+            })));
+        }
+    };
+    if skipped >= max_skips as u64 {
+        return Ok(conflict(&format!(
+            "Your plan allows {} skipped renewal(s) a year and you've used them all",
+            max_skips
+        )));
+    }
+
+    match db.set_skip_next_renewal(&subscription, true).await {
+        Ok(updated) => Ok(HttpResponse::Ok().json(
+            SubscriptionResponse::from_subscription(updated, &Formatting::from_request(&req)),
+        )),
+        Err(e) if e.contains("not active") => Ok(conflict(&e)),
+        Err(e) => {
+            error!("Error skipping renewal of {}: {}", subscription.id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to skip renewal"
+            })))
+        }
+    }
+}
+
+/// Undoes a skip that hasn't happened yet.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/me/subscription/skip-next",
+    tag = "me",
+    request_body(content = Option<SkipRenewalDto>, description = "Omit to use the caller's only active subscription"),
+    responses(
+        (status = 200, description = "Next renewal will be charged again", body = SubscriptionResponse),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 409, description = "Conflicts with the current state"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[delete("/subscription/skip-next")]
+pub async fn cancel_skip_next_renewal(
+    req: HttpRequest,
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    payload: Option<Json<SkipRenewalDto>>,
+) -> Result<HttpResponse> {
+    let dto = payload.map(Json::into_inner).unwrap_or_default();
+    let subscription = match find_my_subscription(&db, &user, dto.subscription_id.as_deref()).await {
+        Ok(s) => s,
+        Err(response) => return Ok(response),
+    };
+    if !subscription.skip_next_renewal {
+        return Ok(conflict("The next renewal isn't skipped"));
+    }
+
+    match db.set_skip_next_renewal(&subscription, false).await {
+        Ok(updated) => Ok(HttpResponse::Ok().json(
+            SubscriptionResponse::from_subscription(updated, &Formatting::from_request(&req)),
+        )),
+        Err(e) if e.contains("not active") => Ok(conflict(&e)),
+        Err(e) => {
+            error!("Error cancelling skip of {}: {}", subscription.id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to cancel the skip"
+            })))
+        }
+    }
+}
+
+/// The caller's subscription named in the request, or their only one
+/// suspended for non-payment.
+async fn find_my_suspended_subscription(
+    db: &DatabaseService,
+    user: &CurrentUser,
+    subscription_id: Option<&str>,
+) -> std::result::Result<Subscription, HttpResponse> {
+    if let Some(subscription_id) = subscription_id {
+        return load_owned_subscription(db, user, subscription_id).await.ok_or_else(|| {
+            HttpResponse::NotFound().json(serde_json::json!({ "error": "Subscription not found" }))
+        });
+    }
+
+    let mut suspended: Vec<Subscription> = db.get_subscriptions_by_user(&user.user_id).await
+        .into_iter()
+        .filter(|s| s.reactivation_price().is_some())
+        .collect();
+    match suspended.len() {
+        0 => Err(HttpResponse::NotFound().json(serde_json::json!({ "error": "No suspended subscription" }))),
+        1 => Ok(suspended.remove(0)),
+        _ => Err(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "subscription_id is required when you have more than one suspended subscription"
+        }))),
+    }
+}
+
+/// Starts the catch-up payment for a subscription suspended for non-payment:
+/// one period's price, less the plan's reactivation discount and any part
+/// payments already made. Once it's paid the subscription is active again
+/// for a new period, its features are back and its dunning state is cleared.
+#[utoipa::path(
+    post,
+    path = "/api/v1/me/subscription/reactivate-after-suspension",
+    tag = "me",
+    request_body(content = Option<ReactivateSubscriptionDto>, description = "Omit to use the caller's only suspended subscription"),
+    responses(
+        (status = 200, description = "Checkout session, as from `POST /payments/initiate`; `amount` is the catch-up charge"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Conflicts with the current state"),
+        (status = 429, description = "Too many requests"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[post("/subscription/reactivate-after-suspension")]
+#[allow(clippy::too_many_arguments)]
+pub async fn reactivate_after_suspension(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    gateway: Data<dyn PaymentGateway>,
+    ozow: Option<Data<OzowPaymentService>>,
+    config: Data<AppConfig>,
+    limiter: Data<RateLimiter>,
+    req: HttpRequest,
+    payload: Option<Json<ReactivateSubscriptionDto>>,
+) -> Result<HttpResponse> {
+    if let Some(limited) = enforce_rate_limit(&limiter, RateLimitScope::PaymentInitiation, &user.user_id).await {
+        return Ok(limited);
+    }
+    let dto = payload.map(Json::into_inner).unwrap_or_default();
+    let subscription = match find_my_suspended_subscription(&db, &user, dto.subscription_id.as_deref()).await {
+        Ok(s) => s,
+        Err(response) => return Ok(response),
+    };
+
+    if subscription.status != SubscriptionStatus::Suspended {
+        return Ok(conflict("Only a suspended subscription can be reactivated"));
+    }
+    // Less whatever has already been paid towards it
+    let amount = match subscription.reactivation_price() {
+        Some(_) => subscription.outstanding_balance(),
+        None => return Ok(conflict("This subscription can't be reactivated here; please contact support")),
+    };
+
+    let mut payment_dto = CreatePaymentDto {
+        user_id: user.user_id.clone(),
+        subscription_id: subscription.id.clone(),
+        amount,
+        currency: None,
+        payment_method: dto.payment_method,
+        region: dto.region,
+        installments: None,
+    };
+    let gateway = gateway_for_method(&gateway, &ozow, payment_dto.payment_method.as_ref());
+    let preflight = preflight_payment(&db, gateway, &payment_dto, &Formatting::from_request(&req)).await;
+    if !preflight.valid {
+        return Ok(HttpResponse::BadRequest().json(ApiResponseError {
+            message: preflight.errors[0].message.clone(),
+            details: Some(preflight.errors.iter().map(|e| e.code).collect::<Vec<_>>().join(",")),
+        }));
+    }
+
+    let plan_id = preflight.charge.and_then(|c| c.plan_id);
+    payment_dto.currency = Some(subscription.currency.clone());
+    Ok(start_checkout(&db, gateway, &config, payment_dto, plan_id.as_deref(), false).await)
+}
@@ -0,0 +1,224 @@
+use actix_web::{HttpRequest, HttpResponse, Result, delete, get, post, put};
+use actix_web::web::Data;
+use tracing::{error, warn};
+use crate::extractors::{CurrentUser, RecordPath, ValidatedJson};
+use crate::handlers::subscription::{load_owned_subscription, SubscriptionResponse};
+use crate::models::membership::{InviteMemberDto, SubscriptionMember, UpdateSeatsDto};
+use crate::models::notification::CreateNotificationDto;
+use crate::models::subscription::{Subscription, SubscriptionStatus};
+use crate::models::validation::ValidationErrorResponse;
+use crate::services::database::DatabaseService;
+use crate::services::formatting::Formatting;
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "error": "Subscription not found"
+    }))
+}
+
+/// Invites someone onto a free seat of the caller's subscription. Registered
+/// users are linked and notified straight away.
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscriptions/{subscription_id}/members",
+    tag = "subscriptions",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    request_body = InviteMemberDto,
+    responses(
+        (status = 201, description = "Member added", body = SubscriptionMember),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Conflicts with the current state"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[post("/{subscription_id}/members")]
+pub async fn invite_member(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    subscription_id: RecordPath<Subscription>,
+    payload: ValidatedJson<InviteMemberDto>,
+) -> Result<HttpResponse> {
+    let subscription = match load_owned_subscription(&db, &user, subscription_id.key()).await {
+        Some(s) => s,
+        None => return Ok(not_found()),
+    };
+    if matches!(subscription.status, SubscriptionStatus::Cancelled | SubscriptionStatus::Expired) {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Members can't be added to a cancelled or expired subscription"
+        })));
+    }
+
+    let email = payload.email.trim().to_lowercase();
+    let invitee = db.get_user_by_email(&email).await;
+    if invitee.as_ref().is_some_and(|u| u.id == subscription.user_id) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "The owner already holds a seat"
+        })));
+    }
+
+    match db.add_subscription_member(&subscription, &email, invitee.map(|u| u.id)).await {
+        Ok(member) => {
+            if let Some(member_user_id) = &member.user_id {
+                let notification = CreateNotificationDto::member_added(
+                    member_user_id.clone(),
+                    subscription.id.clone(),
+                    &subscription.plan_name,
+                );
+                if let Err(e) = db.create_notification(notification).await {
+                    warn!("Failed to notify {} about their seat: {}", member_user_id, e);
+                }
+            }
+            Ok(HttpResponse::Created().json(member))
+        }
+        Err(e) if e.contains("No seats left") || e.contains("already a member") => {
+            Ok(HttpResponse::Conflict().json(serde_json::json!({ "error": e })))
+        }
+        Err(e) => {
+            error!("Error adding member to {}: {}", subscription.id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to add member"
+            })))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscriptions/{subscription_id}/members",
+    tag = "subscriptions",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    responses(
+        (status = 200, description = "`seat_count`, `seats_available` and the `members`"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[get("/{subscription_id}/members")]
+pub async fn list_members(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    subscription_id: RecordPath<Subscription>,
+) -> Result<HttpResponse> {
+    let subscription = match load_owned_subscription(&db, &user, subscription_id.key()).await {
+        Some(s) => s,
+        None => return Ok(not_found()),
+    };
+
+    match db.list_subscription_members(&subscription.id).await {
+        Ok(members) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "seat_count": subscription.seat_count,
+            // The owner's seat is never free
+            "seats_available": subscription.seat_count.saturating_sub(members.len() as u32 + 1),
+            "members": members
+        }))),
+        Err(e) => {
+            error!("Error listing members of {}: {}", subscription.id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to list members"
+            })))
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/subscriptions/{subscription_id}/members/{member_id}",
+    tag = "subscriptions",
+    params(
+        ("subscription_id" = String, Path, description = "Subscription id"),
+        ("member_id" = String, Path, description = "Member id"),
+    ),
+    responses(
+        (status = 204, description = "Member removed"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[delete("/{subscription_id}/members/{member_id}")]
+pub async fn remove_member(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    subscription_id: RecordPath<Subscription>,
+    member_id: RecordPath<SubscriptionMember>,
+) -> Result<HttpResponse> {
+    let subscription = match load_owned_subscription(&db, &user, subscription_id.key()).await {
+        Some(s) => s,
+        None => return Ok(not_found()),
+    };
+
+    match db.remove_subscription_member(&subscription.id, member_id.key()).await {
+        Ok(true) => Ok(HttpResponse::NoContent().finish()),
+        Ok(false) => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Member not found"
+        }))),
+        Err(e) => {
+            error!("Error removing member {} from {}: {}", member_id, subscription.id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to remove member"
+            })))
+        }
+    }
+}
+
+/// Changes the number of seats. The new count is billed from the next
+/// renewal; it can't drop below the seats already taken.
+#[utoipa::path(
+    put,
+    path = "/api/v1/subscriptions/{subscription_id}/seats",
+    tag = "subscriptions",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    request_body = UpdateSeatsDto,
+    responses(
+        (status = 200, description = "Seat count updated", body = SubscriptionResponse),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Conflicts with the current state"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[put("/{subscription_id}/seats")]
+pub async fn update_seats(
+    req: HttpRequest,
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    subscription_id: RecordPath<Subscription>,
+    payload: ValidatedJson<UpdateSeatsDto>,
+) -> Result<HttpResponse> {
+    let subscription = match load_owned_subscription(&db, &user, subscription_id.key()).await {
+        Some(s) => s,
+        None => return Ok(not_found()),
+    };
+    if matches!(subscription.status, SubscriptionStatus::Cancelled | SubscriptionStatus::Expired) {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Seats can't be changed on a cancelled or expired subscription"
+        })));
+    }
+
+    match db.update_seat_count(&subscription.id, payload.seat_count).await {
+        Ok(updated) => Ok(HttpResponse::Ok().json(
+            SubscriptionResponse::from_subscription(updated, &Formatting::from_request(&req)),
+        )),
+        Err(e) if e.contains("Remove members") => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": e
+        }))),
+        Err(e) => {
+            error!("Error updating seats of {}: {}", subscription.id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to update seats"
+            })))
+        }
+    }
+}
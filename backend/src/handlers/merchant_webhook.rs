@@ -0,0 +1,185 @@
+use actix_web::{HttpResponse, Result, delete, get, post};
+use actix_web::web::{Data, Query};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use uuid::Uuid;
+use tracing::error;
+use crate::config::AppConfig;
+use crate::extractors::{CurrentUser, RecordPath, ValidatedJson};
+use crate::models::validation::ValidationErrorResponse;
+use crate::models::webhook_endpoint::{
+    CreateWebhookEndpointDto, WebhookDelivery, WebhookEndpoint, WebhookEndpointCreated, MAX_WEBHOOK_ENDPOINTS_PER_USER,
+};
+use crate::services::database::DatabaseService;
+use crate::services::event_sinks::check_public_host;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeliveryListQuery {
+    pub limit: Option<u32>,
+}
+
+fn server_error(message: &str, details: String) -> HttpResponse {
+    error!("{}: {}", message, details);
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "error": message,
+        "details": details
+    }))
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "error": "Webhook endpoint not found"
+    }))
+}
+
+/// Loads an endpoint owned by the caller; other users' endpoints are
+/// reported as missing.
+async fn load_owned_endpoint(db: &DatabaseService, user: &CurrentUser, endpoint_id: &str) -> Option<WebhookEndpoint> {
+    db.get_webhook_endpoint(endpoint_id).await.filter(|endpoint| endpoint.user_id == user.user_id)
+}
+
+/// Registers a URL to receive the caller's payment and subscription events.
+/// Each delivery is signed: `X-Event-Signature` is `sha256=` and the hex
+/// HMAC-SHA256, keyed with the returned secret, of `{X-Event-Timestamp}.{body}`.
+#[utoipa::path(
+    post,
+    path = "/api/v1/me/webhook-endpoints",
+    tag = "me",
+    request_body = CreateWebhookEndpointDto,
+    responses(
+        (status = 201, description = "Endpoint registered; the secret isn't shown again", body = WebhookEndpointCreated),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 409, description = "Conflicts with the current state"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[post("/webhook-endpoints")]
+pub async fn create_webhook_endpoint(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    config: Data<AppConfig>,
+    payload: ValidatedJson<CreateWebhookEndpointDto>,
+) -> Result<HttpResponse> {
+    let dto = payload.into_inner();
+    if let Err(e) = dto.check_scheme(config.sandbox_mode) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+    }
+    if let Err(e) = check_public_host(&dto.url).await {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Webhook URLs must point to a public host",
+            "details": e
+        })));
+    }
+
+    let existing = match db.list_webhook_endpoints(&user.user_id).await {
+        Ok(endpoints) => endpoints,
+        Err(e) => return Ok(server_error("Failed to register webhook endpoint", e)),
+    };
+    if existing.len() >= MAX_WEBHOOK_ENDPOINTS_PER_USER {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("At most {} webhook endpoints can be registered", MAX_WEBHOOK_ENDPOINTS_PER_USER)
+        })));
+    }
+
+    let secret = format!("whsec_{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    match db.create_webhook_endpoint(&user.user_id, dto, &secret).await {
+        Ok(endpoint) => Ok(HttpResponse::Created().json(WebhookEndpointCreated { endpoint, secret })),
+        Err(e) => Ok(server_error("Failed to register webhook endpoint", e)),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/webhook-endpoints",
+    tag = "me",
+    responses(
+        (status = 200, description = "The caller's endpoints", body = [WebhookEndpoint]),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[get("/webhook-endpoints")]
+pub async fn list_webhook_endpoints(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+) -> Result<HttpResponse> {
+    match db.list_webhook_endpoints(&user.user_id).await {
+        Ok(endpoints) => Ok(HttpResponse::Ok().json(endpoints)),
+        Err(e) => Ok(server_error("Failed to list webhook endpoints", e)),
+    }
+}
+
+/// Removes the endpoint. Deliveries still queued for it fail; the log is kept.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/me/webhook-endpoints/{endpoint_id}",
+    tag = "me",
+    params(("endpoint_id" = String, Path, description = "Webhook endpoint id")),
+    responses(
+        (status = 204, description = "Endpoint removed"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[delete("/webhook-endpoints/{endpoint_id}")]
+pub async fn delete_webhook_endpoint(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    endpoint_id: RecordPath<WebhookEndpoint>,
+) -> Result<HttpResponse> {
+    let endpoint = match load_owned_endpoint(&db, &user, endpoint_id.key()).await {
+        Some(endpoint) => endpoint,
+        None => return Ok(not_found()),
+    };
+
+    match db.delete_webhook_endpoint(&endpoint.id).await {
+        Ok(()) => Ok(HttpResponse::NoContent().finish()),
+        Err(e) => Ok(server_error("Failed to remove webhook endpoint", e)),
+    }
+}
+
+/// The endpoint's delivery log, newest first, with each attempt's response
+/// status or error.
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/webhook-endpoints/{endpoint_id}/deliveries",
+    tag = "me",
+    params(
+        ("endpoint_id" = String, Path, description = "Webhook endpoint id"),
+        DeliveryListQuery,
+    ),
+    responses(
+        (status = 200, description = "Deliveries to the endpoint", body = [WebhookDelivery]),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[get("/webhook-endpoints/{endpoint_id}/deliveries")]
+pub async fn list_webhook_deliveries(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    endpoint_id: RecordPath<WebhookEndpoint>,
+    query: Query<DeliveryListQuery>,
+) -> Result<HttpResponse> {
+    let endpoint = match load_owned_endpoint(&db, &user, endpoint_id.key()).await {
+        Some(endpoint) => endpoint,
+        None => return Ok(not_found()),
+    };
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+
+    match db.list_webhook_deliveries(&endpoint.id, limit).await {
+        Ok(deliveries) => Ok(HttpResponse::Ok().json(deliveries)),
+        Err(e) => Ok(server_error("Failed to list webhook deliveries", e)),
+    }
+}
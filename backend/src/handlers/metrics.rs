@@ -0,0 +1,149 @@
+use actix_web::{HttpResponse, Result, get};
+use actix_web::web::{Data, Query};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use tracing::error;
+use crate::extractors::TenantAdminAuth;
+use crate::models::metrics::{bucket_ranges, MetricsBucket};
+use crate::services::database::DatabaseService;
+use crate::services::fx::FxService;
+use crate::services::metrics;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MetricsQuery {
+    /// First UTC date as YYYY-MM-DD, widened back to the start of its
+    /// bucket; defaults to 30 days, 12 weeks or 12 months before `to`.
+    pub from: Option<NaiveDate>,
+    /// Last UTC date, inclusive; defaults to today.
+    pub to: Option<NaiveDate>,
+    #[serde(default)]
+    pub bucket: MetricsBucket,
+}
+
+impl MetricsQuery {
+    /// Bucket ranges for the window, or the reason it is invalid.
+    fn ranges(&self) -> Result<Vec<(DateTime<Utc>, DateTime<Utc>)>, String> {
+        let to = self.to.unwrap_or_else(|| Utc::now().date_naive());
+        let from = self.from.unwrap_or_else(|| self.bucket.default_from(to));
+        bucket_ranges(from, to, self.bucket)
+    }
+}
+
+fn window(ranges: &[(DateTime<Utc>, DateTime<Utc>)]) -> (DateTime<Utc>, DateTime<Utc>) {
+    // bucket_ranges never returns an empty list
+    (ranges[0].0, ranges[ranges.len() - 1].1)
+}
+
+/// Monthly recurring revenue at the end of each bucket.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/metrics/mrr",
+    tag = "admin",
+    params(MetricsQuery),
+    responses(
+        (status = 200, description = "MRR per bucket", body = MrrSeries),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/metrics/mrr")]
+pub async fn get_mrr_metrics(
+    _admin: TenantAdminAuth,
+    db: Data<DatabaseService>,
+    fx: Data<FxService>,
+    query: Query<MetricsQuery>,
+) -> Result<HttpResponse> {
+    let ranges = match query.ranges() {
+        Ok(ranges) => ranges,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))),
+    };
+    let (from, to) = window(&ranges);
+
+    match db.get_subscriptions_paying_between(from, to).await {
+        Ok(subscriptions) => Ok(HttpResponse::Ok().json(metrics::mrr_series(&subscriptions, &ranges, query.bucket, &fx))),
+        Err(e) => {
+            error!("Error building MRR metrics: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to build metrics"
+            })))
+        }
+    }
+}
+
+/// New subscriptions, cancellations and churn per bucket.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/metrics/subscriptions",
+    tag = "admin",
+    params(MetricsQuery),
+    responses(
+        (status = 200, description = "Subscription movements per bucket", body = SubscriptionMetricsSeries),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/metrics/subscriptions")]
+pub async fn get_subscription_metrics(
+    _admin: TenantAdminAuth,
+    db: Data<DatabaseService>,
+    query: Query<MetricsQuery>,
+) -> Result<HttpResponse> {
+    let ranges = match query.ranges() {
+        Ok(ranges) => ranges,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))),
+    };
+    let (from, to) = window(&ranges);
+
+    match db.get_subscriptions_paying_between(from, to).await {
+        Ok(subscriptions) => Ok(HttpResponse::Ok().json(metrics::subscription_series(&subscriptions, &ranges, query.bucket))),
+        Err(e) => {
+            error!("Error building subscription metrics: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to build metrics"
+            })))
+        }
+    }
+}
+
+/// Succeeded and failed payments, and the failure rate, per bucket.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/metrics/payments",
+    tag = "admin",
+    params(MetricsQuery),
+    responses(
+        (status = 200, description = "Payment outcomes per bucket", body = PaymentMetricsSeries),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/metrics/payments")]
+pub async fn get_payment_metrics(
+    _admin: TenantAdminAuth,
+    db: Data<DatabaseService>,
+    query: Query<MetricsQuery>,
+) -> Result<HttpResponse> {
+    let ranges = match query.ranges() {
+        Ok(ranges) => ranges,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))),
+    };
+    let (from, to) = window(&ranges);
+
+    match db.get_payment_outcomes(from, to).await {
+        Ok(outcomes) => Ok(HttpResponse::Ok().json(metrics::payment_series(&outcomes, &ranges, query.bucket))),
+        Err(e) => {
+            error!("Error building payment metrics: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to build metrics"
+            })))
+        }
+    }
+}
@@ -1,4 +1,41 @@
 pub mod payment;
 pub mod user;
 pub mod subscription;
-pub mod notification;
\ No newline at end of file
+pub mod notification;
+pub mod plan;
+pub mod webhook;
+pub mod refund;
+pub mod capture;
+pub mod me;
+pub mod consistency;
+pub mod support;
+pub mod report;
+pub mod invoice;
+pub mod card_update;
+pub mod outbox;
+pub mod entitlement;
+pub mod manual_payment;
+pub mod attachment;
+pub mod billing_run;
+pub mod plan_change;
+pub mod wallet;
+pub mod usage;
+pub mod token_migration;
+pub mod membership;
+pub mod bulk;
+pub mod payment_history;
+pub mod metrics;
+pub mod ticket;
+pub mod job;
+pub mod domain_event;
+pub mod merchant_webhook;
+pub mod scenario;
+pub mod payment_method;
+pub mod health;
+pub mod template;
+pub mod audit;
+pub mod impersonation;
+pub mod api_key;
+pub mod tenant;
+pub mod installment;
+pub mod reconciliation;
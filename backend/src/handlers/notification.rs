@@ -1,28 +1,78 @@
-use actix_web::{HttpResponse, Result, get, post};
-use actix_web::web::{Data, Path, Json};
+use actix_web::{HttpResponse, Result, get, post, put};
+use actix_web::web::{Data, Query};
 use serde::{Serialize, Deserialize};
+use utoipa::{IntoParams, ToSchema};
+use tracing::error;
+use crate::extractors::{CurrentUser, RecordPath, ValidatedJson};
+use crate::models::common::{page_and_limit, PaginatedResponse};
 use crate::services::database::DatabaseService;
+use crate::models::notification::{Notification, NotificationAction, NotificationEvent, UpdateNotificationPreferencesDto};
+use crate::models::user::User;
+use crate::models::validation::{Validate, ValidationErrorResponse};
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct NotificationResponse {
     pub id: String,
     pub user_id: String,
     pub subscription_id: String,
     pub message: String,
     pub acknowledged: bool,
+    pub action_type: Option<NotificationAction>,
+    pub action_payload: Option<serde_json::Value>,
+    pub event: Option<NotificationEvent>,
     pub created_at: String,
 }
 
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct NotificationsQuery {
+    /// From 1.
+    pub page: Option<u32>,
+    /// Up to 100; 20 by default.
+    pub limit: Option<u32>,
+    /// Only notifications that haven't been acknowledged.
+    #[serde(default)]
+    pub unread: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UnreadCountResponse {
+    pub unread: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AcknowledgeAllResponse {
+    /// Notifications that were unread before the call.
+    pub acknowledged: usize,
+}
+
+/// The user's notifications, newest first, a page at a time.
+#[utoipa::path(
+    get,
+    path = "/api/v1/notifications/user/{user_id}",
+    tag = "notifications",
+    params(
+        ("user_id" = String, Path, description = "User id"),
+        NotificationsQuery,
+    ),
+    responses(
+        (status = 200, description = "A page of notifications", body = PaginatedNotifications),
+        (status = 400, description = "Invalid request"),
+        (status = 500, description = "Internal error"),
+    )
+)]
 #[get("/user/{user_id}")]
 pub async fn get_notifications(
     db: Data<DatabaseService>,
-    path: Path<String>,
+    user_id: RecordPath<User>,
+    query: Query<NotificationsQuery>,
 ) -> Result<HttpResponse> {
-    let user_id = path.into_inner();
-    
-    match db.get_user_notifications(user_id).await {
-        Ok(notifications) => {
-            let response: Vec<NotificationResponse> = notifications
+    let query = query.into_inner();
+    let (page, limit) = page_and_limit(query.page, query.limit);
+
+    match db.get_user_notifications(user_id.into_key(), query.unread, page, limit).await {
+        Ok((notifications, total)) => {
+            let items: Vec<NotificationResponse> = notifications
                 .into_iter()
                 .map(|n| NotificationResponse {
                     id: n.id,
@@ -30,14 +80,17 @@ pub async fn get_notifications(
                     subscription_id: n.subscription_id,
                     message: n.message,
                     acknowledged: n.acknowledged,
+                    action_type: n.action_type,
+                    action_payload: n.action_payload,
+                    event: n.event,
                     created_at: n.created_at.to_rfc3339(),
                 })
                 .collect();
-            
-            Ok(HttpResponse::Ok().json(response))
+
+            Ok(HttpResponse::Ok().json(PaginatedResponse::new(items, page, limit, total)))
         }
         Err(e) => {
-            eprintln!("Error fetching notifications: {}", e);
+            error!("Error fetching notifications: {}", e);
             Ok(HttpResponse::InternalServerError().json(serde_json::json!({
                 "error": "Failed to fetch notifications"
             })))
@@ -45,19 +98,28 @@ pub async fn get_notifications(
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/v1/notifications/{notification_id}/acknowledge",
+    tag = "notifications",
+    params(("notification_id" = String, Path, description = "Notification id")),
+    responses(
+        (status = 200, description = "Acknowledged; body has a `message`"),
+        (status = 400, description = "Invalid request"),
+        (status = 500, description = "Internal error"),
+    )
+)]
 #[post("/{notification_id}/acknowledge")]
 pub async fn mark_notification_read(
     db: Data<DatabaseService>,
-    path: Path<String>,
+    notification_id: RecordPath<Notification>,
 ) -> Result<HttpResponse> {
-    let notification_id = path.into_inner();
-    
-    match db.acknowledge_notification(notification_id).await {
+    match db.acknowledge_notification(notification_id.into_key()).await {
         Ok(_) => Ok(HttpResponse::Ok().json(serde_json::json!({
             "message": "Notification marked as read"
         }))),
         Err(e) => {
-            eprintln!("Error acknowledging notification: {}", e);
+            error!("Error acknowledging notification: {}", e);
             Ok(HttpResponse::InternalServerError().json(serde_json::json!({
                 "error": "Failed to acknowledge notification"
             })))
@@ -65,26 +127,157 @@ pub async fn mark_notification_read(
     }
 }
 
-#[derive(Deserialize)]
+/// How many of the caller's notifications are unread, for the app badge.
+#[utoipa::path(
+    get,
+    path = "/api/v1/notifications/unread-count",
+    tag = "notifications",
+    responses(
+        (status = 200, description = "Unread notifications", body = UnreadCountResponse),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[get("/unread-count")]
+pub async fn get_unread_count(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+) -> Result<HttpResponse> {
+    match db.count_unread_notifications(&user.user_id).await {
+        Ok(unread) => Ok(HttpResponse::Ok().json(UnreadCountResponse { unread })),
+        Err(e) => {
+            error!("Error counting unread notifications for {}: {}", user.user_id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to count notifications"
+            })))
+        }
+    }
+}
+
+/// Marks all of the caller's notifications as read.
+#[utoipa::path(
+    post,
+    path = "/api/v1/notifications/acknowledge-all",
+    tag = "notifications",
+    responses(
+        (status = 200, description = "Notifications acknowledged", body = AcknowledgeAllResponse),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[post("/acknowledge-all")]
+pub async fn acknowledge_all_notifications(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+) -> Result<HttpResponse> {
+    match db.acknowledge_all_notifications(&user.user_id).await {
+        Ok(acknowledged) => Ok(HttpResponse::Ok().json(AcknowledgeAllResponse { acknowledged })),
+        Err(e) => {
+            error!("Error acknowledging notifications for {}: {}", user.user_id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to acknowledge notifications"
+            })))
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
 pub struct TestNotificationRequest {
     pub user_id: String,
     pub message: String,
 }
 
+impl Validate for TestNotificationRequest {}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/notifications/test",
+    tag = "notifications",
+    request_body = TestNotificationRequest,
+    responses(
+        (status = 200, description = "Created; body has a `message`"),
+        (status = 500, description = "Internal error"),
+    )
+)]
 #[post("/test")]
 pub async fn create_test_notification(
     db: Data<DatabaseService>,
-    payload: Json<TestNotificationRequest>,
+    payload: ValidatedJson<TestNotificationRequest>,
 ) -> Result<HttpResponse> {
     match db.create_test_notification(payload.user_id.clone(), payload.message.clone()).await {
         Ok(_) => Ok(HttpResponse::Ok().json(serde_json::json!({
             "message": "Test notification created successfully"
         }))),
         Err(e) => {
-            eprintln!("Error creating test notification: {}", e);
+            error!("Error creating test notification: {}", e);
             Ok(HttpResponse::InternalServerError().json(serde_json::json!({
                 "error": "Failed to create test notification"
             })))
         }
     }
 }
+
+/// The caller's delivery channels for each kind of notification.
+#[utoipa::path(
+    get,
+    path = "/api/v1/notifications/preferences",
+    tag = "notifications",
+    responses(
+        (status = 200, description = "Channels per event", body = NotificationPreferences),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[get("/preferences")]
+pub async fn get_notification_preferences(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+) -> Result<HttpResponse> {
+    match db.get_notification_preferences(&user.user_id).await {
+        Ok(preferences) => Ok(HttpResponse::Ok().json(preferences)),
+        Err(e) => {
+            error!("Error loading notification preferences for {}: {}", user.user_id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to load notification preferences"
+            })))
+        }
+    }
+}
+
+/// Sets the channels for the listed events; the renewal task and other
+/// senders check them before emailing or writing in-app notifications.
+#[utoipa::path(
+    put,
+    path = "/api/v1/notifications/preferences",
+    tag = "notifications",
+    request_body = UpdateNotificationPreferencesDto,
+    responses(
+        (status = 200, description = "Channels per event after the update", body = NotificationPreferences),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[put("/preferences")]
+pub async fn update_notification_preferences(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    payload: ValidatedJson<UpdateNotificationPreferencesDto>,
+) -> Result<HttpResponse> {
+    let dto = payload.into_inner();
+
+    match db.set_notification_preferences(&user.user_id, dto.events).await {
+        Ok(preferences) => Ok(HttpResponse::Ok().json(preferences)),
+        Err(e) => {
+            error!("Error saving notification preferences for {}: {}", user.user_id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to save notification preferences"
+            })))
+        }
+    }
+}
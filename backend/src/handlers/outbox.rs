@@ -0,0 +1,60 @@
+use actix_web::{HttpResponse, Result, delete, get};
+use actix_web::web::{Data, Query};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use crate::extractors::AdminAuth;
+use crate::services::email::EmailService;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OutboxQuery {
+    /// Only emails sent to this address.
+    pub to: Option<String>,
+}
+
+/// Emails captured by `EMAIL_PROVIDER=memory`, so integration tests can check
+/// what was sent without a mail server. Only mounted with that provider.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/outbox/emails",
+    tag = "admin",
+    params(OutboxQuery),
+    responses(
+        (status = 200, description = "Captured emails, oldest first", body = [SentEmail]),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/outbox/emails")]
+pub async fn list_sent_emails(
+    _admin: AdminAuth,
+    email: Data<EmailService>,
+    query: Query<OutboxQuery>,
+) -> Result<HttpResponse> {
+    match email.sent_emails(query.to.as_deref()) {
+        Some(sent) => Ok(HttpResponse::Ok().json(sent)),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Outbox is only available with EMAIL_PROVIDER=memory"
+        }))),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/outbox/emails",
+    tag = "admin",
+    responses(
+        (status = 204, description = "Outbox cleared"),
+        (status = 401, description = "Missing or invalid credentials"),
+    ),
+    security(("admin_token" = []))
+)]
+#[delete("/outbox/emails")]
+pub async fn clear_sent_emails(
+    _admin: AdminAuth,
+    email: Data<EmailService>,
+) -> Result<HttpResponse> {
+    email.clear_sent_emails();
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,119 +1,682 @@
 use actix_web::{HttpResponse, Result, post, get};
-use actix_web::web::{Data, Json, Path, Query};
+use actix_web::web::{Data, Path, Query};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use utoipa::{ToSchema, IntoParams};
 use actix_web::HttpRequest;
-use crate::services::peach::PeachPaymentService;
+use chrono::{DateTime, Utc};
+use tracing::{debug, error, info, warn};
+use crate::services::gateway::{CheckoutRequest, ChargeStatus, GatewayTransaction, PaymentGateway, WebhookKind, WebhookNotification};
+use crate::services::ozow::OzowPaymentService;
+use crate::services::formatting::Formatting;
+use crate::services::email::EmailService;
+use crate::services::jobs;
+use crate::services::qr::{render_qr, QrFormat};
+use crate::services::storage::{PaymentRepo, Storage};
+use crate::services::rate_limit::{RateDecision, RateLimitScope, RateLimiter};
+use crate::services::resilience::GatewayUnavailable;
+use crate::services::tickets;
+use crate::config::AppConfig;
+use crate::extractors::ValidatedJson;
 use actix_web::web;
+use actix_web::middleware::from_fn;
+use crate::middleware::require_signed_request;
+use crate::telemetry;
 use crate::{
     models::{
-        payment::{PaymentStatus, CreatePaymentDto, PaymentMethod},
+        activity::ActivityCategory,
+        card_update::CARD_UPDATE_PREFIX,
+        installment::{split_installments, INSTALLMENT_PREFIX, MAX_INSTALLMENTS},
+        job::JobPayload,
+        plan_change::PLAN_CHANGE_PREFIX,
+        state_reason,
+        payment::{default_currency, Payment, PaymentStatus, CreatePaymentDto, PaymentMethod},
+        payment_method_rule::{method_availability, normalize_region},
         subscription::SubscriptionStatus,
+        validation::{FieldErrors, Validate, ValidationErrorResponse},
+        wallet::{WalletEntrySource, WALLET_GATEWAY},
+        webhook_event::{ResultCodeCategory, WebhookEventUpdate, WebhookOutcome},
     },
-    services::database::DatabaseService,
+    services::{database::DatabaseService, installments, peach},
 };
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ApiResponseError {
     pub message: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub details: Option<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct RecurringChargeRequest {
     pub user_id: String,
     pub amount: f64,
+    #[serde(default = "default_currency")]
+    pub currency: String,
     pub initial_transaction_id: String,
 }
 
-#[derive(Debug, Deserialize)]
+impl Validate for RecurringChargeRequest {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        if !self.amount.is_finite() || self.amount <= 0.0 {
+            errors.add("amount", "must be greater than zero");
+        }
+        errors.into_result()
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct PaymentCallbackQuery {
     pub resource_path: Option<String>,
 }
 
+/// A single reason a payment can't be initiated.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PreflightError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// What initiating the payment would charge, derived from the subscription.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PreflightCharge {
+    pub subscription_id: String,
+    pub plan_id: Option<String>,
+    pub plan_name: String,
+    /// What's still due for the period, or its first installment; a part
+    /// payment may be less.
+    pub amount: f64,
+    pub currency: String,
+    pub display_amount: String,
+}
+
+/// How much of the period a subscription is waiting on has been paid, while
+/// it is paid for in parts.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SubscriptionBalance {
+    pub amount_due: f64,
+    pub amount_paid: f64,
+    pub outstanding_balance: f64,
+    pub currency: String,
+}
+
+/// The balance of the payment's subscription; `None` once the subscription
+/// has nothing left to pay.
+async fn subscription_balance(db: &DatabaseService, payment: &Payment) -> Option<SubscriptionBalance> {
+    let subscription = db.get_subscription(payment.subscription_id.as_deref()?).await?;
+    if !subscription.accepts_part_payments() {
+        return None;
+    }
+    Some(SubscriptionBalance {
+        amount_due: subscription.period_amount_due(),
+        amount_paid: subscription.amount_paid,
+        outstanding_balance: subscription.outstanding_balance(),
+        currency: subscription.currency,
+    })
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PreflightResult {
+    pub valid: bool,
+    pub errors: Vec<PreflightError>,
+    pub warnings: Vec<PreflightError>,
+    pub charge: Option<PreflightCharge>,
+}
+
+impl PreflightResult {
+    fn has_error(&self, code: &str) -> bool {
+        self.errors.iter().any(|e| e.code == code)
+    }
+}
+
+/// EFT goes through Ozow when it's configured; everything else, and EFT
+/// without Ozow, goes through the main gateway.
+pub fn gateway_for_method<'a>(
+    gateway: &'a Data<dyn PaymentGateway>,
+    ozow: &'a Option<Data<OzowPaymentService>>,
+    method: Option<&PaymentMethod>,
+) -> &'a dyn PaymentGateway {
+    match ozow {
+        Some(ozow) if method == Some(&PaymentMethod::EFT) => ozow.get_ref(),
+        _ => gateway.get_ref(),
+    }
+}
+
+/// The gateway a stored payment or webhook event went through, by name.
+pub fn gateway_named<'a>(
+    gateway: &'a Data<dyn PaymentGateway>,
+    ozow: &'a Option<Data<OzowPaymentService>>,
+    name: Option<&str>,
+) -> &'a dyn PaymentGateway {
+    match ozow {
+        Some(ozow) if name == Some(ozow.name()) => ozow.get_ref(),
+        _ => gateway.get_ref(),
+    }
+}
+
+/// Pending payments newer than this are treated as a checkout still in progress.
+const IN_FLIGHT_PAYMENT_MINUTES: i64 = 30;
+
+/// How long a shopper has to finish a 3-D Secure challenge before a status
+/// poll that still finds it open cancels the payment.
+const AUTHENTICATION_TIMEOUT_MINUTES: i64 = 15;
+
+/// Runs every check `initiate_payment` applies, without writing anything.
+pub async fn preflight_payment(
+    db: &DatabaseService,
+    gateway: &dyn PaymentGateway,
+    payload: &CreatePaymentDto,
+    fmt: &Formatting,
+) -> PreflightResult {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    let mut charge = None;
+
+    if !payload.amount.is_finite() || payload.amount <= 0.0 {
+        errors.push(PreflightError {
+            code: "invalid_amount",
+            message: "Amount must be greater than zero".to_string(),
+        });
+    }
+
+    if db.get_user(&payload.user_id).await.is_none() {
+        errors.push(PreflightError {
+            code: "user_not_found",
+            message: "User not found".to_string(),
+        });
+    }
+
+    match db.get_subscription(&payload.subscription_id).await {
+        None => errors.push(PreflightError {
+            code: "subscription_not_found",
+            message: "Subscription not found".to_string(),
+        }),
+        Some(subscription) => {
+            if subscription.user_id != payload.user_id {
+                errors.push(PreflightError {
+                    code: "subscription_not_owned",
+                    message: "Subscription does not belong to this user".to_string(),
+                });
+            }
+
+            // Subscriptions suspended for non-payment can be paid for again
+            // at their reactivation price
+            let reactivation_price = subscription.reactivation_price();
+            if subscription.status != SubscriptionStatus::Pending && reactivation_price.is_none() {
+                errors.push(PreflightError {
+                    code: "subscription_not_pending",
+                    message: "Subscription is not pending".to_string(),
+                });
+            }
+            // The period can be paid in parts, e.g. a deposit then the
+            // balance; only paying more than is still due is refused
+            let mut price = subscription.outstanding_balance();
+
+            if let Some(count) = payload.installments {
+                let by_card = payload.payment_method.as_ref().is_none_or(|m| *m == PaymentMethod::Card);
+                if !(2..=MAX_INSTALLMENTS).contains(&count) {
+                    errors.push(PreflightError {
+                        code: "invalid_installments",
+                        message: format!("Installments must be between 2 and {}", MAX_INSTALLMENTS),
+                    });
+                } else if !by_card || subscription.amount_paid > 0.0 {
+                    errors.push(PreflightError {
+                        code: "installments_unavailable",
+                        message: "Installments are only available for card payments of the full amount".to_string(),
+                    });
+                } else {
+                    // Only the first installment is paid at checkout
+                    let first = split_installments(price, count)[0];
+                    if (payload.amount - first).abs() >= 0.005 {
+                        errors.push(PreflightError {
+                            code: "amount_mismatch",
+                            message: format!(
+                                "Amount {} does not match the first of {} installments, {}",
+                                fmt.amount(payload.amount, &subscription.currency),
+                                count,
+                                fmt.amount(first, &subscription.currency)
+                            ),
+                        });
+                    }
+                    price = first;
+                }
+            }
+
+            if payload.amount - price >= 0.005 {
+                errors.push(PreflightError {
+                    code: "amount_mismatch",
+                    message: format!(
+                        "Amount {} is more than the {} due on the {} plan",
+                        fmt.amount(payload.amount, &subscription.currency),
+                        fmt.amount(price, &subscription.currency),
+                        subscription.plan_name
+                    ),
+                });
+            }
+
+            if let Some(currency) = &payload.currency {
+                if !currency.eq_ignore_ascii_case(&subscription.currency) {
+                    errors.push(PreflightError {
+                        code: "currency_mismatch",
+                        message: format!(
+                            "Currency {} does not match the {} plan currency {}",
+                            currency, subscription.plan_name, subscription.currency
+                        ),
+                    });
+                }
+            }
+
+            if !gateway.supports_currency(&subscription.currency) {
+                errors.push(PreflightError {
+                    code: "unsupported_currency",
+                    message: format!("The payment gateway does not accept {}", subscription.currency),
+                });
+            }
+
+            match payload.region.as_deref().map(normalize_region).transpose() {
+                Err(message) => errors.push(PreflightError { code: "invalid_region", message }),
+                Ok(region) => {
+                    if let Some(method) = &payload.payment_method {
+                        match db.list_payment_method_rules().await {
+                            Ok(rules) => {
+                                let availability = method_availability(
+                                    &rules,
+                                    method,
+                                    region.as_deref(),
+                                    &subscription.currency,
+                                    Some(price),
+                                );
+                                if let Some(message) = availability.reason {
+                                    errors.push(PreflightError { code: "payment_method_unavailable", message });
+                                }
+                            }
+                            // Rules only narrow what the gateways accept; don't block checkout on them
+                            Err(e) => warn!("Payment method rules unavailable, allowing {}: {}", method, e),
+                        }
+                    }
+                }
+            }
+
+            let cutoff = chrono::Utc::now() - chrono::Duration::minutes(IN_FLIGHT_PAYMENT_MINUTES);
+            let in_flight = db.get_payments_by_subscription(&subscription.id).await
+                .into_iter()
+                .any(|p| !p.status.is_final() && p.created_at > cutoff);
+            if in_flight {
+                warnings.push(PreflightError {
+                    code: "payment_in_progress",
+                    message: "A checkout for this subscription was started recently".to_string(),
+                });
+            }
+
+            charge = Some(PreflightCharge {
+                subscription_id: subscription.id.clone(),
+                plan_id: subscription.plan_id.clone(),
+                plan_name: subscription.plan_name.clone(),
+                amount: price,
+                currency: subscription.currency.clone(),
+                display_amount: fmt.amount(price, &subscription.currency),
+            });
+        }
+    }
+
+    PreflightResult {
+        valid: errors.is_empty(),
+        errors,
+        warnings,
+        charge,
+    }
+}
+
+/// Optional checkout behaviour the frontend should offer.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CheckoutFeatures {
+    /// Cards are stored for renewals when paid.
+    pub save_card: bool,
+    pub scan_to_pay: bool,
+    /// EFT goes to Ozow's hosted page rather than the widget.
+    pub ozow_eft: bool,
+    /// Mock card tokens are accepted; never on in production.
+    pub sandbox_mode: bool,
+}
+
+/// Everything the embedded checkout needs, so the frontend doesn't
+/// hard-code entity ids or script URLs per environment.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CheckoutConfigResponse {
+    pub gateway: String,
+    pub entity_id: String,
+    pub script_url: String,
+    /// `sandbox` or `live`.
+    pub environment: String,
+    pub payment_brands: Vec<String>,
+    pub supported_currencies: Vec<String>,
+    pub features: CheckoutFeatures,
+    /// Notice to show above the checkout when payments don't charge real
+    /// money; absent when live.
+    pub banner: Option<String>,
+}
+
+/// The test-mode notice for checkouts through `gateway`, if any.
+pub(crate) fn test_mode_banner(gateway: &dyn PaymentGateway, config: &AppConfig) -> Option<String> {
+    if config.sandbox_mode {
+        Some("Sandbox mode: mock cards are accepted and no real money is charged".to_string())
+    } else if !gateway.is_live() {
+        Some(format!("Test mode: {} is using test credentials; no real money is charged", gateway.name()))
+    } else {
+        None
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/payments/checkout-config",
+    tag = "payments",
+    responses(
+        (status = 200, description = "Settings for the embedded checkout", body = CheckoutConfigResponse),
+        (status = 404, description = "Not found"),
+    )
+)]
+#[get("/checkout-config")]
+pub async fn get_checkout_config(
+    gateway: Data<dyn PaymentGateway>,
+    ozow: Option<Data<OzowPaymentService>>,
+    config: Data<AppConfig>,
+) -> Result<HttpResponse> {
+    // Hosted-page providers redirect instead; see `redirectUrl` on initiate
+    let widget = match gateway.checkout_widget() {
+        Some(widget) => widget,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("The {} gateway has no embedded checkout", gateway.name())
+        }))),
+    };
+
+    let scan_to_pay = widget.payment_brands.iter().any(|b| b == "SCANTOPAY");
+    Ok(HttpResponse::Ok().json(CheckoutConfigResponse {
+        gateway: gateway.name().to_string(),
+        entity_id: widget.entity_id,
+        script_url: widget.script_url,
+        environment: widget.environment,
+        payment_brands: widget.payment_brands,
+        supported_currencies: gateway.supported_currencies().to_vec(),
+        features: CheckoutFeatures {
+            save_card: true,
+            scan_to_pay,
+            ozow_eft: ozow.is_some(),
+            sandbox_mode: config.sandbox_mode,
+        },
+        banner: test_mode_banner(gateway.get_ref(), &config),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/payments/validate",
+    tag = "payments",
+    request_body = CreatePaymentDto,
+    responses(
+        (status = 200, description = "Whether the payment can be initiated, and what it would charge", body = PreflightResult),
+    )
+)]
+#[post("/validate")]
+pub async fn validate_payment(
+    req: HttpRequest,
+    db: Data<DatabaseService>,
+    gateway: Data<dyn PaymentGateway>,
+    ozow: Option<Data<OzowPaymentService>>,
+    payload: ValidatedJson<CreatePaymentDto>,
+) -> Result<HttpResponse> {
+    let fmt = Formatting::from_request(&req);
+    let gateway = gateway_for_method(&gateway, &ozow, payload.payment_method.as_ref());
+    Ok(HttpResponse::Ok().json(preflight_payment(&db, gateway, &payload, &fmt).await))
+}
+
+/// Answers 429 once `user_id` has used up its budget for `scope`. Lets the
+/// request through if the counter can't be reached, so a database hiccup
+/// doesn't also block payments.
+pub(crate) async fn enforce_rate_limit(limiter: &RateLimiter, scope: RateLimitScope, user_id: &str) -> Option<HttpResponse> {
+    match limiter.check(scope, user_id, Utc::now()).await {
+        Ok(RateDecision::Allowed) => None,
+        Ok(RateDecision::Limited { retry_after_secs }) => {
+            info!("User {} rate limited on {}", user_id, scope.as_str());
+            Some(HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", retry_after_secs.to_string()))
+                .json(ApiResponseError {
+                    message: "Too many requests".to_string(),
+                    details: Some(format!("Retry after {} seconds", retry_after_secs)),
+                }))
+        }
+        Err(e) => {
+            warn!("Rate limit check failed for user {}: {}", user_id, e);
+            None
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/payments/initiate",
+    tag = "payments",
+    request_body = CreatePaymentDto,
+    responses(
+        (status = 200, description = "Checkout session: `gateway`, `checkoutId`, `merchantTransactionId`, `registrationId`, `redirectUrl`, `statementDescriptor`, `amount`, `walletAmount` and `banner`"),
+        (status = 400, description = "Invalid request"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+        (status = 503, description = "The gateway is failing; retry after `Retry-After` seconds"),
+    )
+)]
 #[post("/initiate")]
 pub async fn initiate_payment(
     db: Data<DatabaseService>,
-    peach_service: Data<PeachPaymentService>,
-    payload: Json<CreatePaymentDto>,
+    gateway: Data<dyn PaymentGateway>,
+    ozow: Option<Data<OzowPaymentService>>,
+    config: Data<AppConfig>,
+    limiter: Data<RateLimiter>,
+    payload: ValidatedJson<CreatePaymentDto>,
 ) -> Result<HttpResponse> {
-    let subscription_id = &payload.subscription_id;
-    let subscription = match db.get_subscription(subscription_id).await {  // ✅ Added .await
-        Some(sub) => sub,
-        None => return Ok(HttpResponse::NotFound().json(ApiResponseError {
-            message: "Subscription not found".to_string(),
-            details: None,
-        })),
-    };
-    
-    if subscription.status != SubscriptionStatus::Pending {
-        return Ok(HttpResponse::BadRequest().json(ApiResponseError {
-            message: "Subscription is not pending".to_string(),
-            details: None,
-        }));
+    if let Some(limited) = enforce_rate_limit(&limiter, RateLimitScope::PaymentInitiation, &payload.user_id).await {
+        return Ok(limited);
     }
-    
+
+    let gateway = gateway_for_method(&gateway, &ozow, payload.payment_method.as_ref());
+    let preflight = preflight_payment(&db, gateway, &payload, &Formatting::default()).await;
+    if !preflight.valid {
+        let first = &preflight.errors[0];
+        let response = if preflight.has_error("subscription_not_found") {
+            HttpResponse::NotFound()
+        } else {
+            HttpResponse::BadRequest()
+        }
+        .json(ApiResponseError {
+            message: first.message.clone(),
+            details: Some(preflight.errors.iter().map(|e| e.code).collect::<Vec<_>>().join(",")),
+        });
+        return Ok(response);
+    }
+
+    // Preflight passed, so the charge is known; its currency is the plan's
+    let (currency, plan_id) = preflight.charge
+        .map(|c| (c.currency, c.plan_id))
+        .unwrap_or_else(|| (default_currency(), None));
     let payment_dto = CreatePaymentDto {
         user_id: payload.user_id.clone(),
         subscription_id: payload.subscription_id.clone(),
         amount: payload.amount,
+        currency: Some(currency),
         payment_method: payload.payment_method.clone(),
+        region: payload.region.clone(),
+        installments: payload.installments,
     };
-    
-    let payment_record = match db.create_payment(payment_dto).await {  // ✅ Added .await
+    Ok(start_checkout(&db, gateway, &config, payment_dto, plan_id.as_deref(), config.authorize_at_signup).await)
+}
+
+/// Records a payment that has passed its checks and starts its checkout.
+/// With `authorize_only` the gateway only reserves the amount, where it can.
+pub(crate) async fn start_checkout(
+    db: &DatabaseService,
+    gateway: &dyn PaymentGateway,
+    config: &AppConfig,
+    payment_dto: CreatePaymentDto,
+    plan_id: Option<&str>,
+    authorize_only: bool,
+) -> HttpResponse {
+    let currency = payment_dto.currency.clone().unwrap_or_else(default_currency);
+    let (user_id, subscription_id) = (payment_dto.user_id.clone(), payment_dto.subscription_id.clone());
+    let installments = payment_dto.installments;
+    let payment_record = match db.create_payment(payment_dto, config.vat_rate_percent, gateway.name()).await {  // ✅ Added .await
         Ok(payment) => payment,
-        Err(e) => return Ok(HttpResponse::InternalServerError().json(ApiResponseError {
+        Err(e) => return HttpResponse::InternalServerError().json(ApiResponseError {
             message: "Error creating payment record".to_string(),
             details: Some(e.to_string()),
-        })),
+        }),
     };
-    
-    let user_id_str = payload.user_id.to_string();
-    let subscription_id_str = payload.subscription_id.to_string();
-    
-    match peach_service
-        .initiate_checkout_api_v2_with_tokenization(
-            &user_id_str,
-            &subscription_id_str,
-            payload.amount,
-            &payment_record.merchant_transaction_id,
-        )
-        .await
-    {
-        Ok(peach_response) => {
-            if let Some(checkout_id) = peach_response.get("checkoutId").and_then(|v| v.as_str()) {
-                let _ = db.update_payment_checkout_id(&payment_record.merchant_transaction_id, checkout_id).await;  // ✅ Added .await
-                
-                if let Some(token) = peach_response.get("registrationId").and_then(|v| v.as_str()) {
-                    let _ = db.update_payment_recurring_token(&payment_record.merchant_transaction_id, token).await;  // ✅ Added .await
-                }
-                
-                Ok(HttpResponse::Ok().json(serde_json::json!({
-                    "checkoutId": checkout_id,
-                    "merchantTransactionId": payment_record.merchant_transaction_id,
-                    "registrationId": peach_response.get("registrationId").unwrap_or(&serde_json::Value::Null)
-                })))
-            } else {
-                Ok(HttpResponse::InternalServerError().json(ApiResponseError {
-                    message: "Peach Payments response missing 'checkoutId'".to_string(),
-                    details: Some(format!("Full response: {:?}", peach_response)),
-                }))
+    telemetry::record_payment(&payment_record.merchant_transaction_id);
+
+    // Scheduled before anything is charged, so paying the first installment
+    // (even from the wallet) finds the rest
+    if let Some(count) = installments {
+        if let Err(e) = db.create_installment_schedule(&payment_record, count).await {
+            return HttpResponse::InternalServerError().json(ApiResponseError {
+                message: "Error creating installment schedule".to_string(),
+                details: Some(e),
+            });
+        }
+    }
+
+    // Wallet credit is spent first; the gateway only charges what's left
+    let debited = db.debit_wallet(
+        &payment_record.user_id,
+        &currency,
+        payment_record.amount,
+        WalletEntrySource::Payment,
+        &payment_record.merchant_transaction_id,
+    ).await;
+    let payment_record = match debited {
+        Ok(Some(_)) => db.get_payment_by_merchant_id(&payment_record.merchant_transaction_id).await.unwrap_or(payment_record),
+        Ok(None) => payment_record,
+        Err(e) => {
+            warn!("Charging {} without wallet credit: {}", payment_record.merchant_transaction_id, e);
+            payment_record
+        }
+    };
+    if payment_record.card_amount() <= 0.0 {
+        return settle_from_wallet(db, payment_record).await;
+    }
+
+    let descriptor = if gateway.supports_statement_descriptor() {
+        db.statement_descriptor(plan_id, config.statement_descriptor.as_deref()).await
+    } else {
+        None
+    };
+    let checkout = CheckoutRequest {
+        user_id: &user_id,
+        subscription_id: &subscription_id,
+        amount: payment_record.card_amount(),
+        currency: &currency,
+        merchant_transaction_id: &payment_record.merchant_transaction_id,
+        payment_method: &payment_record.payment_method,
+        descriptor: descriptor.as_deref(),
+        authorize_only: authorize_only && gateway.supports_authorization(),
+    };
+
+    match gateway.initiate_checkout(&checkout).await {
+        Ok(session) => {
+            let _ = db.update_payment_checkout_id(
+                &payment_record.merchant_transaction_id,
+                &session.checkout_id,
+                session.redirect_url.as_deref(),
+                descriptor.as_deref(),
+            ).await;  // ✅ Added .await
+
+            if let Some(token) = &session.registration_id {
+                let _ = db.update_payment_recurring_token(&payment_record.merchant_transaction_id, token).await;  // ✅ Added .await
             }
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "gateway": gateway.name(),
+                "checkoutId": session.checkout_id,
+                "merchantTransactionId": payment_record.merchant_transaction_id,
+                "registrationId": session.registration_id,
+                "redirectUrl": session.redirect_url,
+                "statementDescriptor": descriptor,
+                "amount": payment_record.amount,
+                "walletAmount": payment_record.wallet_amount,
+                "banner": test_mode_banner(gateway, config)
+            }))
+        }
+        Err(e) => {
+            // Failing the payment gives back any wallet credit it took
+            if payment_record.wallet_amount > 0.0 {
+                let _ = db.update_payment_status(&payment_record.merchant_transaction_id, &PaymentStatus::Failed).await;
+            }
+            if let Some(response) = unavailable_response(e.as_ref()) {
+                return response;
+            }
+            HttpResponse::InternalServerError().json(ApiResponseError {
+                message: format!("Failed to initiate payment with {}", gateway.name()),
+                details: Some(e.to_string()),
+            })
         }
-        Err(e) => Ok(HttpResponse::InternalServerError().json(ApiResponseError {
-            message: "Failed to initiate payment with Peach Payments".to_string(),
-            details: Some(e.to_string()),
-        })),
     }
 }
 
-#[post("/charge-recurring")]
+/// Completes a payment that wallet credit covered in full, the way a
+/// successful webhook would, without going to a gateway.
+async fn settle_from_wallet(db: &DatabaseService, payment: Payment) -> HttpResponse {
+    if let Err(e) = db.complete_payment_and_activate(&payment, Utc::now(), None, None).await {
+        let _ = db.update_payment_status(&payment.merchant_transaction_id, &PaymentStatus::Failed).await;
+        return HttpResponse::InternalServerError().json(ApiResponseError {
+            message: "Error completing wallet payment".to_string(),
+            details: Some(e),
+        });
+    }
+    info!("Payment {} settled from wallet credit", payment.merchant_transaction_id);
+
+    let merchant_transaction_id = payment.merchant_transaction_id.clone();
+    jobs::enqueue(db, JobPayload::IssueInvoice { merchant_transaction_id: merchant_transaction_id.clone() }).await;
+    jobs::enqueue(db, JobPayload::PaymentSucceededEmail { merchant_transaction_id }).await;
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "gateway": WALLET_GATEWAY,
+        "checkoutId": null,
+        "merchantTransactionId": payment.merchant_transaction_id,
+        "registrationId": null,
+        "redirectUrl": null,
+        "amount": payment.amount,
+        "walletAmount": payment.wallet_amount,
+        "status": "Completed"
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/payments/charge-recurring",
+    tag = "payments",
+    request_body = RecurringChargeRequest,
+    responses(
+        (status = 200, description = "Result of the charge", body = GatewayTransaction),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("request_signature" = []))
+)]
+#[post("/charge-recurring", wrap = "from_fn(require_signed_request)")]
 pub async fn charge_recurring_payment(
     db: Data<DatabaseService>,
-    peach: Data<PeachPaymentService>,
-    payload: Json<RecurringChargeRequest>,
+    gateway: Data<dyn PaymentGateway>,
+    config: Data<AppConfig>,
+    payload: ValidatedJson<RecurringChargeRequest>,
 ) -> Result<HttpResponse> {
+    if !gateway.supports_currency(&payload.currency) {
+        return Ok(HttpResponse::BadRequest().json(ApiResponseError {
+            message: "Unsupported currency".to_string(),
+            details: Some(format!("The payment gateway does not accept {}", payload.currency)),
+        }));
+    }
+
     let token = match db.get_recurring_token_by_user(&payload.user_id).await {  // ✅ Added .await
         Some(t) => t,
         None => {
@@ -124,11 +687,17 @@ pub async fn charge_recurring_payment(
         }
     };
     
-    match peach
-        .execute_recurring_payment(&token, payload.amount, &payload.initial_transaction_id)
+    match gateway
+        .charge_token(
+            &token,
+            payload.amount,
+            &payload.currency,
+            &payload.initial_transaction_id,
+            config.statement_descriptor.as_deref(),
+        )
         .await
     {
-        Ok(response) => Ok(HttpResponse::Ok().json(response)),
+        Ok(transaction) => Ok(HttpResponse::Ok().json(transaction)),
         Err(e) => Ok(HttpResponse::InternalServerError().json(ApiResponseError {
             message: "Failed to execute recurring payment".to_string(),
             details: Some(e.to_string()),
@@ -136,14 +705,31 @@ pub async fn charge_recurring_payment(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/payments/status/{merchant_transaction_id}",
+    tag = "payments",
+    params(
+        ("merchant_transaction_id" = String, Path, description = "Merchant transaction id"),
+    ),
+    responses(
+        (status = 200, description = "Stored payment status and `state_reason`, refreshed from the gateway while pending, and the subscription's `balance` while it is part-paid"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    )
+)]
 #[get("/status/{merchant_transaction_id}")]
 pub async fn check_payment_status(
+    req: HttpRequest,
     db: Data<DatabaseService>,
-    peach_service: Data<PeachPaymentService>,
+    gateway: Data<dyn PaymentGateway>,
+    ozow: Option<Data<OzowPaymentService>>,
+    limiter: Data<RateLimiter>,
     path: Path<String>,
 ) -> Result<HttpResponse> {
     let merchant_transaction_id = path.into_inner();
-    
+    telemetry::record_payment(&merchant_transaction_id);
+
     let payment = match db.get_payment_by_merchant_id(&merchant_transaction_id).await {  // ✅ Added .await
         Some(p) => p,
         None => {
@@ -153,6 +739,17 @@ pub async fn check_payment_status(
             }));
         }
     };
+
+    if let Some(limited) = enforce_rate_limit(&limiter, RateLimitScope::PaymentStatus, &payment.user_id).await {
+        return Ok(limited);
+    }
+
+    let fmt = Formatting::from_request(&req);
+    let display = serde_json::json!({
+        "locale": fmt.locale.tag(),
+        "amount": fmt.amount(payment.amount, &payment.currency),
+        "created_at": fmt.datetime(&payment.created_at),
+    });
     
     let checkout_id = match &payment.checkout_id {
         Some(id) => id,
@@ -164,46 +761,62 @@ pub async fn check_payment_status(
                 "merchant_transaction_id": payment.merchant_transaction_id,
                 "payment_method": format!("{:?}", payment.payment_method),
                 "status": format!("{:?}", payment.status),
-                "amount": payment.amount
+                "state_reason": payment.state_reason,
+                "amount": payment.amount,
+                "created_at": payment.created_at.to_rfc3339(),
+                "balance": subscription_balance(&db, &payment).await,
+                "display": display
             })));
         }
     };
     
-    match peach_service.check_payment_status(checkout_id).await {
-        Ok(status_response) => {
-            let new_status = status_response
-                .get("result")
-                .and_then(|r| r.get("code"))
-                .and_then(|c| c.as_str())
-                .map(|code| {
-                    if code.starts_with("000.000") || code.starts_with("000.100") {
-                        PaymentStatus::Completed
-                    } else if code.starts_with("000.200") {
-                        PaymentStatus::Pending
-                    } else {
-                        PaymentStatus::Failed
-                    }
-                });
-            
-            if let Some(status) = new_status.clone() {
-                let _ = db.update_payment_status(&merchant_transaction_id, &status).await;  // ✅ Added .await
-                
-                if status == PaymentStatus::Completed {
-                    if let Some(subscription_id) = payment.subscription_id {
-                        let _ = db.activate_subscription(&subscription_id).await;  // ✅ Added .await
-                    }
-                }
+    let gateway = gateway_named(&gateway, &ozow, payment.gateway.as_deref());
+    match gateway.check_status(checkout_id).await {
+        Ok(transaction) => {
+            let (new_status, reason) = apply_polled_status(&db, &payment, &transaction).await;
+
+            if new_status == PaymentStatus::Completed {
+                let _ = db.settle_subscription_payment(&payment).await;
             }
-            
+            let balance = subscription_balance(&db, &payment).await;
+
             Ok(HttpResponse::Ok().json(serde_json::json!({
-                "peach_response": status_response,
-                "updated_status": new_status.map(|s| format!("{:?}", s)).unwrap_or("unknown".to_string()),
+                "gateway": gateway.name(),
+                "subscription_id": payment.subscription_id,
+                "gateway_status": transaction.status,
+                "result_code": transaction.code,
+                "result_description": transaction.description,
+                "gateway_response": transaction.raw,
+                "updated_status": format!("{:?}", new_status),
+                "state_reason": reason,
+                "authentication_url": transaction.authentication_url.clone().or(payment.authentication_url.clone())
+                    .filter(|_| new_status == PaymentStatus::AwaitingAuthentication),
                 "payment_id": payment.id,
                 "merchant_transaction_id": payment.merchant_transaction_id,
                 "payment_method": format!("{:?}", payment.payment_method),
-                "amount": payment.amount
+                "amount": payment.amount,
+                "created_at": payment.created_at.to_rfc3339(),
+                "balance": balance,
+                "display": display
             })))
         }
+        // The stored status is the best answer while the gateway is failing
+        Err(e) if e.downcast_ref::<GatewayUnavailable>().is_some() => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "gateway": gateway.name(),
+            "gateway_unavailable": true,
+            "message": e.to_string(),
+            "subscription_id": payment.subscription_id,
+            "updated_status": format!("{:?}", payment.status),
+            "state_reason": payment.state_reason,
+            "authentication_url": payment.authentication_url,
+            "payment_id": payment.id,
+            "merchant_transaction_id": payment.merchant_transaction_id,
+            "payment_method": format!("{:?}", payment.payment_method),
+            "amount": payment.amount,
+            "created_at": payment.created_at.to_rfc3339(),
+            "balance": subscription_balance(&db, &payment).await,
+            "display": display
+        }))),
         Err(e) => Ok(HttpResponse::InternalServerError().json(ApiResponseError {
             message: "Error checking payment status".to_string(),
             details: Some(e.to_string()),
@@ -211,129 +824,356 @@ pub async fn check_payment_status(
     }
 }
 
+/// 503 with `Retry-After` when `error` says the gateway's circuit is open.
+fn unavailable_response(error: &(dyn std::error::Error + Send + Sync)) -> Option<HttpResponse> {
+    let unavailable = error.downcast_ref::<GatewayUnavailable>()?;
+    Some(HttpResponse::ServiceUnavailable()
+        .insert_header(("Retry-After", unavailable.retry_after.as_secs().max(1).to_string()))
+        .json(ApiResponseError {
+            message: format!("{} is temporarily unavailable", unavailable.gateway),
+            details: Some(unavailable.to_string()),
+        }))
+}
+
+/// Writes a polled gateway result back to the payment and returns the
+/// status and `state_reason` it ends up with. A 3-D Secure challenge still
+/// open after `AUTHENTICATION_TIMEOUT_MINUTES` is taken as abandoned and the
+/// payment cancelled; the shopper has to start a new checkout.
+async fn apply_polled_status(
+    db: &DatabaseService,
+    payment: &Payment,
+    transaction: &GatewayTransaction,
+) -> (PaymentStatus, String) {
+    let merchant_transaction_id = &payment.merchant_transaction_id;
+
+    if payment.status == PaymentStatus::AwaitingAuthentication && transaction.status.is_pending() {
+        let deadline = payment.updated_at + chrono::Duration::minutes(AUTHENTICATION_TIMEOUT_MINUTES);
+        if Utc::now() < deadline {
+            return (payment.status.clone(), state_reason::AWAITING_AUTHENTICATION.to_string());
+        }
+        // Not a gateway event, so it takes no later timestamp than the last
+        // one applied: a success webhook for a late challenge still wins
+        let event_at = payment.last_event_at.unwrap_or(payment.created_at);
+        return match db.apply_payment_event(merchant_transaction_id, &PaymentStatus::Cancelled, state_reason::AUTHENTICATION_ABANDONED, event_at).await {
+            Ok(true) => {
+                info!("3-D Secure challenge for {} abandoned; payment cancelled", merchant_transaction_id);
+                (PaymentStatus::Cancelled, state_reason::AUTHENTICATION_ABANDONED.to_string())
+            }
+            Ok(false) => (payment.status.clone(), state_reason::AWAITING_AUTHENTICATION.to_string()),
+            Err(e) => {
+                error!("Failed to cancel abandoned payment {}: {}", merchant_transaction_id, e);
+                (payment.status.clone(), state_reason::AWAITING_AUTHENTICATION.to_string())
+            }
+        };
+    }
+
+    // A reserved amount is only completed by an administrator's capture
+    if transaction.status == ChargeStatus::Succeeded && transaction.authorized_only {
+        if payment.status.stage() <= PaymentStatus::Authorized.stage() {
+            let event_at = payment.last_event_at.unwrap_or(payment.created_at);
+            let _ = db.authorize_payment(merchant_transaction_id, transaction.gateway_reference.as_deref(), event_at).await;
+            return (PaymentStatus::Authorized, state_reason::PAYMENT_AUTHORIZED.to_string());
+        }
+        return (payment.status.clone(), payment.state_reason.clone().unwrap_or_default());
+    }
+
+    let new_status = transaction.status.payment_status();
+    if new_status == PaymentStatus::AwaitingAuthentication {
+        let _ = db.await_payment_authentication(merchant_transaction_id, transaction.authentication_url.as_deref()).await;
+    } else {
+        let _ = db.update_payment_status(merchant_transaction_id, &new_status).await;
+    }
+    let reason = state_reason::for_payment_status(&new_status).to_string();
+    (new_status, reason)
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WaitQuery {
+    /// Seconds, optionally suffixed with `s` (`30` or `30s`).
+    pub timeout: Option<String>,
+}
+
+const DEFAULT_WAIT_SECONDS: u64 = 30;
+const MAX_WAIT_SECONDS: u64 = 60;
+/// Fallback re-read while waiting, for changes made by another instance.
+const WAIT_RECHECK_SECONDS: u64 = 5;
+
+fn parse_wait_timeout(raw: Option<&str>) -> Result<std::time::Duration, String> {
+    let seconds = match raw {
+        None => DEFAULT_WAIT_SECONDS,
+        Some(raw) => raw
+            .trim()
+            .trim_end_matches('s')
+            .parse::<u64>()
+            .map_err(|_| format!("Invalid timeout '{}': expected seconds such as 30s", raw))?,
+    };
+    Ok(std::time::Duration::from_secs(seconds.clamp(1, MAX_WAIT_SECONDS)))
+}
+
+fn wait_response(payment: &Payment, timed_out: bool) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({
+        "merchant_transaction_id": payment.merchant_transaction_id,
+        "subscription_id": payment.subscription_id,
+        "status": format!("{:?}", payment.status),
+        "state_reason": payment.state_reason,
+        "authentication_url": payment.authentication_url,
+        "final": payment.status.is_final(),
+        "timed_out": timed_out,
+    }))
+}
+
+/// Long-poll for the checkout return page: answers as soon as the payment's
+/// status changes (to a final one, or to AwaitingAuthentication with the
+/// 3-D Secure page to send the shopper to), or with the current status
+/// once `timeout` elapses.
+#[utoipa::path(
+    get,
+    path = "/api/v1/payments/{merchant_transaction_id}/wait",
+    tag = "payments",
+    params(
+        ("merchant_transaction_id" = String, Path, description = "Merchant transaction id"),
+        WaitQuery,
+    ),
+    responses(
+        (status = 200, description = "`merchant_transaction_id`, `subscription_id`, `status`, `state_reason`, `authentication_url`, `final` and `timed_out`"),
+        (status = 400, description = "Invalid request"),
+        (status = 404, description = "Not found"),
+    )
+)]
+#[get("/{merchant_transaction_id}/wait")]
+pub async fn wait_for_payment(
+    db: Data<DatabaseService>,
+    limiter: Data<RateLimiter>,
+    path: Path<String>,
+    query: Query<WaitQuery>,
+) -> Result<HttpResponse> {
+    let merchant_transaction_id = path.into_inner();
+    telemetry::record_payment(&merchant_transaction_id);
+    let timeout = match parse_wait_timeout(query.timeout.as_deref()) {
+        Ok(timeout) => timeout,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(ApiResponseError {
+            message: e,
+            details: None,
+        })),
+    };
+
+    // Subscribe before the first read so a change in between isn't missed
+    let mut events = db.payment_events.subscribe();
+    let mut payment = match db.get_payment_by_merchant_id(&merchant_transaction_id).await {
+        Some(p) => p,
+        None => return Ok(HttpResponse::NotFound().json(ApiResponseError {
+            message: "Payment not found".to_string(),
+            details: Some(merchant_transaction_id),
+        })),
+    };
+
+    if let Some(limited) = enforce_rate_limit(&limiter, RateLimitScope::PaymentStatus, &payment.user_id).await {
+        return Ok(limited);
+    }
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut recheck = tokio::time::interval(std::time::Duration::from_secs(WAIT_RECHECK_SECONDS));
+    recheck.tick().await;
+
+    let initial_status = payment.status.clone();
+    while !payment.status.is_final() && payment.status == initial_status {
+        tokio::select! {
+            _ = tokio::time::sleep_until(deadline) => return Ok(wait_response(&payment, true)),
+            _ = recheck.tick() => {}
+            event = events.recv() => match event {
+                Ok(change) if change.merchant_transaction_id != merchant_transaction_id => continue,
+                Ok(_) | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return Ok(wait_response(&payment, true)),
+            },
+        }
+
+        if let Some(latest) = db.get_payment_by_merchant_id(&merchant_transaction_id).await {
+            payment = latest;
+        }
+    }
+
+    Ok(wait_response(&payment, false))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PaymentQrQuery {
+    #[serde(default)]
+    pub format: QrFormat,
+}
+
+/// Seconds between status polls the PWA should use while a QR is on screen.
+const QR_POLL_INTERVAL_SECONDS: u32 = 3;
+
+/// QR code for a pending ScanToPay checkout, so the PWA can show it inline
+/// and poll the status endpoint instead of redirecting the shopper away.
+#[utoipa::path(
+    get,
+    path = "/api/v1/payments/qr/{merchant_transaction_id}",
+    tag = "payments",
+    params(
+        ("merchant_transaction_id" = String, Path, description = "Merchant transaction id"),
+        PaymentQrQuery,
+    ),
+    responses(
+        (status = 200, description = "`content`, the encoded `qr` image and `poll` hints"),
+        (status = 400, description = "Invalid request"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    )
+)]
+#[get("/qr/{merchant_transaction_id}")]
+pub async fn get_payment_qr(
+    db: Data<dyn Storage>,
+    path: Path<String>,
+    query: Query<PaymentQrQuery>,
+) -> Result<HttpResponse> {
+    let merchant_transaction_id = path.into_inner();
+
+    let payment = match db.get_payment_by_merchant_id(&merchant_transaction_id).await {
+        Some(p) => p,
+        None => return Ok(HttpResponse::NotFound().json(ApiResponseError {
+            message: "Payment not found".to_string(),
+            details: Some(merchant_transaction_id),
+        })),
+    };
+
+    if payment.payment_method != PaymentMethod::ScanToPay {
+        return Ok(HttpResponse::BadRequest().json(ApiResponseError {
+            message: "QR codes are only available for ScanToPay payments".to_string(),
+            details: Some(format!("Payment method is {:?}", payment.payment_method)),
+        }));
+    }
+    if payment.status != PaymentStatus::Pending {
+        return Ok(HttpResponse::BadRequest().json(ApiResponseError {
+            message: "Payment is no longer awaiting a scan".to_string(),
+            details: Some(format!("Payment status is {:?}", payment.status)),
+        }));
+    }
+    let checkout_url = match &payment.checkout_url {
+        Some(url) => url,
+        None => return Ok(HttpResponse::BadRequest().json(ApiResponseError {
+            message: "Payment has no checkout link to encode".to_string(),
+            details: None,
+        })),
+    };
+
+    match render_qr(checkout_url, query.format) {
+        Ok(qr) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "merchant_transaction_id": payment.merchant_transaction_id,
+            "content": checkout_url,
+            "qr": qr,
+            "poll": {
+                "status_url": format!("/api/v1/payments/status/{}", payment.merchant_transaction_id),
+                "interval_seconds": QR_POLL_INTERVAL_SECONDS,
+                "expires_at": (payment.created_at + chrono::Duration::minutes(IN_FLIGHT_PAYMENT_MINUTES)).to_rfc3339(),
+            }
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(ApiResponseError {
+            message: "Failed to generate QR code".to_string(),
+            details: Some(e),
+        })),
+    }
+}
+
 #[get("/checkout-status/{checkout_id}")]
 pub async fn get_checkout_status_and_store(
-    peach_service: Data<PeachPaymentService>,
+    gateway: Data<dyn PaymentGateway>,
     db: Data<DatabaseService>,
     path: Path<String>,
 ) -> Result<HttpResponse> {
     let checkout_id = path.into_inner();
     
-    match peach_service.get_checkout_status(&checkout_id).await {
-        Ok(status_response) => {
-            let merchant_txn_id = status_response
-                .get("merchantTransactionId")
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-            
-            let result_code = status_response
-                .get("result")
-                .and_then(|r| r.get("code"))
-                .and_then(|c| c.as_str())
-                .unwrap_or_default();
-            
-            let payment_brand = status_response
-                .get("paymentBrand")
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-            
-            let payment_status = if result_code.starts_with("000.000") || result_code.starts_with("000.100") {
-                PaymentStatus::Completed
-            } else if result_code.starts_with("000.200") {
-                PaymentStatus::Pending
-            } else {
-                PaymentStatus::Failed
-            };
-            
-            if let Some(ref txn_id) = merchant_txn_id {
-                let _ = db.update_payment_status(txn_id, &payment_status).await;  // ✅ Added .await
-                
-                if payment_status == PaymentStatus::Completed {
-                    if let Some(payment) = db.get_payment_by_merchant_id(txn_id).await {  // ✅ Added .await
-                        if let Some(subscription_id) = payment.subscription_id {
-                            let _ = db.activate_subscription(&subscription_id).await;  // ✅ Added .await
-                            
-                            if let Some(brand_str) = payment_brand.clone() {
-                                let method = match brand_str.to_lowercase().as_str() {
-                                    "visa" | "mastercard" | "amex" => PaymentMethod::Card,
-                                                                        "eft" => PaymentMethod::EFT,
-                                    "1voucher" => PaymentMethod::Voucher,
-                                    "scan_to_pay" => PaymentMethod::ScanToPay,
-                                    _ => PaymentMethod::Card,
-                                };
-                                
-                                let _ = db.update_subscription_payment_details(&subscription_id, method, Some(brand_str)).await;  // ✅ Added .await
+    match gateway.check_status(&checkout_id).await {
+        Ok(transaction) => {
+            let mut payment_status = transaction.status.payment_status();
+
+            if let Some(ref txn_id) = transaction.merchant_transaction_id {
+                if let Some(payment) = db.get_payment_by_merchant_id(txn_id).await {  // ✅ Added .await
+                    payment_status = apply_polled_status(&db, &payment, &transaction).await.0;
+
+                    if payment_status == PaymentStatus::Completed {
+                        if let Some(subscription_id) = &payment.subscription_id {
+                            let _ = db.settle_subscription_payment(&payment).await;
+
+                            if let Some(brand_str) = transaction.payment_brand.clone() {
+                                let method = payment_method_for_brand(&brand_str);
+                                let _ = db.update_subscription_payment_details(subscription_id, method, Some(brand_str)).await;  // ✅ Added .await
                             }
                         }
                     }
                 }
             }
-            
+
             Ok(HttpResponse::Ok().json(serde_json::json!({
                 "checkout_id": checkout_id,
-                "merchant_transaction_id": merchant_txn_id,
-                "result_code": result_code,
-                "payment_brand": payment_brand,
+                "merchant_transaction_id": transaction.merchant_transaction_id,
+                "result_code": transaction.code,
+                "payment_brand": transaction.payment_brand,
                 "updated_status": format!("{:?}", payment_status),
-                "raw_response": status_response
+                "raw_response": transaction.raw
             })))
         }
-        Err(e) => Ok(HttpResponse::InternalServerError().json(ApiResponseError {
+        Err(e) => Ok(unavailable_response(e.as_ref()).unwrap_or_else(|| HttpResponse::InternalServerError().json(ApiResponseError {
             message: "Failed to get checkout status".to_string(),
             details: Some(e.to_string()),
-        })),
+        }))),
     }
 }
 
 pub async fn handle_payment_callback(query: Query<PaymentCallbackQuery>) -> HttpResponse {
     let query = query.into_inner();
     if let Some(resource_path) = query.resource_path {
-        println!("Resource path: {}", resource_path);
+        debug!("Resource path: {}", resource_path);
         // Process further...
     } else {
-        println!("No resource path provided");
+        debug!("No resource path provided");
     }
     HttpResponse::Ok().finish()
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/payments/callback",
+    tag = "payments",
+    params(PaymentCallbackQuery),
+    responses(
+        (status = 302, description = "Redirect to the PWA result page"),
+        (status = 400, description = "Invalid request"),
+    )
+)]
 #[get("/callback")]
 pub async fn handle_payment_callback_get(
     query: Query<PaymentCallbackQuery>,
-    peach_service: Data<PeachPaymentService>,
+    gateway: Data<dyn PaymentGateway>,
 ) -> Result<HttpResponse> {
     if let Some(resource_path) = &query.resource_path {
         let parts: Vec<&str> = resource_path.trim_start_matches('/').split('/').collect();
         if parts.len() >= 2 && parts[0] == "checkouts" {
             let checkout_id = parts[1];
-            match peach_service.check_payment_status(checkout_id).await {
-                Ok(status_response) => {
-                    let status_param = if status_response
-                        .get("result")
-                        .and_then(|r| r.get("code"))
-                        .and_then(|c| c.as_str())
-                        .map_or(false, |code| code.starts_with("000."))
-                    {
-                        "success"
-                    } else {
+            match gateway.check_status(checkout_id).await {
+                Ok(transaction) => {
+                    let status_param = if transaction.status == ChargeStatus::Failed {
                         "failure"
+                    } else {
+                        "success"
                     };
-                    
+
                     return Ok(HttpResponse::Found()
                         .insert_header((
                             "Location",
                             format!(
-                                "/payment-result.html?id={}&resourcePath={}",
-                                status_response
-                                    .get("merchantTransactionId")
-                                    .and_then(|v| v.as_str())
-                                    .unwrap_or("unknown"),
+                                "/payment-result.html?id={}&status={}&resourcePath={}",
+                                transaction.merchant_transaction_id.as_deref().unwrap_or("unknown"),
+                                status_param,
                                 resource_path
                             ),
                         ))
                         .finish());
                 }
                 Err(e) => {
-                    eprintln!("❌ Error processing GET callback: {}", e);
+                    error!("Error processing GET callback: {}", e);
                     return Ok(HttpResponse::Found()
                         .insert_header(("Location", "/payment-result.html?status=error"))
                         .finish());
@@ -348,142 +1188,408 @@ pub async fn handle_payment_callback_get(
     })))
 }
 
-// Helper function to create signature payload in the correct format for Peach Payments
-fn create_signature_payload(form_data: &HashMap<String, String>) -> String {
-    // Get all parameters except signature
-    let mut params: Vec<(&String, &String)> = form_data
-        .iter()
-        .filter(|(key, _)| *key != "signature")
-        .collect();
-    
-    // Sort alphabetically by key
-    params.sort_by(|a, b| a.0.cmp(b.0));
-    
-    // Concatenate key+value pairs (no separators)
-    params
-        .into_iter()
-        .map(|(key, value)| format!("{}{}", key, value))
-        .collect::<Vec<_>>()
-        .join("")
+/// Applies a signature-validated gateway webhook to payments and subscriptions.
+/// Shared by the live `/callback` endpoint and the admin replay endpoint.
+///
+/// Gateways don't guarantee delivery order, so events are ordered by payment
+/// stage and then by their gateway timestamp (`received_at` when it is missing).
+/// An event older than what has already been applied is recorded as skipped.
+pub async fn process_webhook(
+    db: &DatabaseService,
+    email: &EmailService,
+    config: &AppConfig,
+    notification: &WebhookNotification,
+    received_at: DateTime<Utc>,
+) -> Result<WebhookOutcome, String> {
+    let transaction = &notification.transaction;
+    let status_code = transaction.code.as_str();
+    let merchant_transaction_id = transaction.merchant_transaction_id.clone().unwrap_or_default();
+    telemetry::record_payment(&merchant_transaction_id);
+    let event_at = notification.occurred_at.unwrap_or(received_at);
+
+    debug!(
+        "Parsed: kind={:?}, status={:?}, code={}, transaction_id={}, subscription_id={:?}",
+        notification.kind, transaction.status, status_code, merchant_transaction_id, notification.subscription_id
+    );
+
+    match notification.kind {
+        WebhookKind::Refund => return crate::handlers::refund::process_refund_webhook(db, transaction).await,
+        WebhookKind::Chargeback => return tickets::process_dispute_webhook(db, transaction).await,
+        WebhookKind::Other => {
+            info!("Webhook {} needs no action", status_code);
+            return Ok(WebhookOutcome::Ignored);
+        }
+        WebhookKind::Payment => {}
+    }
+
+    if merchant_transaction_id.starts_with(CARD_UPDATE_PREFIX) {
+        return crate::handlers::card_update::process_card_update_webhook(db, email, transaction).await;
+    }
+    if merchant_transaction_id.starts_with(PLAN_CHANGE_PREFIX) {
+        return crate::handlers::plan_change::process_plan_change_webhook(db, config, email, transaction).await;
+    }
+    if merchant_transaction_id.starts_with(INSTALLMENT_PREFIX) {
+        return installments::process_installment_webhook(db, config, email, transaction).await;
+    }
+
+    match transaction.status {
+        ChargeStatus::Succeeded => {
+            info!("Payment successful");
+            let payment = db.get_payment_by_merchant_id(&merchant_transaction_id).await
+                .ok_or_else(|| format!("No payment found for merchantTransactionId: {}", merchant_transaction_id))?;
+
+            if transaction.authorized_only {
+                return authorize_payment(db, &payment, transaction, event_at).await;
+            }
+            if let Some(reason) = stale_event_reason(&payment, &PaymentStatus::Completed, event_at) {
+                return Ok(skip_stale_event(db, &payment, reason).await);
+            }
+            let payment_details = transaction.payment_brand.clone().map(|payment_brand_str| {
+                (payment_method_for_brand(&payment_brand_str), payment_brand_str)
+            });
+
+            // Payment, subscription and payment details are written together
+            let completion = db.complete_payment_and_activate(
+                &payment,
+                event_at,
+                transaction.gateway_reference.as_deref(),
+                payment_details.clone(),
+            ).await?;
+            if !completion.payment_updated {
+                return Ok(skip_stale_event(db, &payment, "a newer event was applied concurrently".to_string()).await);
+            }
+
+            if let Some(ref sub_id) = payment.subscription_id {
+                if completion.subscription_part_paid {
+                    info!("Subscription {} is part-paid; it activates once the rest is paid", sub_id);
+                } else if !completion.subscription_activated {
+                    info!("Subscription {} already advanced past this event; leaving it unchanged", sub_id);
+                } else if let Some((method, brand)) = &payment_details {
+                    info!(
+                        "Updated subscription {} with payment method {:?} and brand {}",
+                        sub_id, method, brand
+                    );
+                } else {
+                    info!("No paymentBrand found in webhook for subscription {}", sub_id);
+                }
+            }
+
+            save_card_token(db, &payment, transaction).await;
+
+            // Invoice and email are queued so they are retried if they fail;
+            // the payment itself has succeeded either way
+            jobs::enqueue(db, JobPayload::IssueInvoice { merchant_transaction_id: merchant_transaction_id.clone() }).await;
+            jobs::enqueue(db, JobPayload::PaymentSucceededEmail { merchant_transaction_id: merchant_transaction_id.clone() }).await;
+            Ok(WebhookOutcome::Processed)
+        }
+        ChargeStatus::Failed => {
+            warn!("Payment failed or was cancelled: {}", status_code);
+            let payment = db.get_payment_by_merchant_id(&merchant_transaction_id).await
+                .ok_or_else(|| format!("No payment found for merchantTransactionId: {}", merchant_transaction_id))?;
+
+            if let Some(reason) = stale_event_reason(&payment, &PaymentStatus::Failed, event_at) {
+                return Ok(skip_stale_event(db, &payment, reason).await);
+            }
+            let reason = state_reason::payment_failed(status_code);
+            if !db.apply_payment_event(&merchant_transaction_id, &PaymentStatus::Failed, &reason, event_at).await? {
+                return Ok(skip_stale_event(db, &payment, "a newer event was applied concurrently".to_string()).await);
+            }
+
+            jobs::enqueue(db, JobPayload::OpenFailedPaymentTicket {
+                merchant_transaction_id: merchant_transaction_id.clone(),
+                reason,
+            }).await;
+            jobs::enqueue(db, JobPayload::PaymentFailedEmail { merchant_transaction_id: merchant_transaction_id.clone() }).await;
+            Ok(WebhookOutcome::Processed)
+        }
+        ChargeStatus::AwaitingAuthentication => {
+            info!("Payment awaiting 3-D Secure authentication");
+            let payment = db.get_payment_by_merchant_id(&merchant_transaction_id).await
+                .ok_or_else(|| format!("No payment found for merchantTransactionId: {}", merchant_transaction_id))?;
+
+            if let Some(reason) = stale_event_reason(&payment, &PaymentStatus::AwaitingAuthentication, event_at) {
+                return Ok(skip_stale_event(db, &payment, reason).await);
+            }
+            if db.await_payment_authentication(&merchant_transaction_id, transaction.authentication_url.as_deref()).await? {
+                Ok(WebhookOutcome::Processed)
+            } else {
+                Ok(WebhookOutcome::Ignored)
+            }
+        }
+        ChargeStatus::Pending => {
+            info!("Payment pending - no action needed");
+            Ok(WebhookOutcome::Ignored)
+        }
+    }
+}
+
+/// Stores the card token a checkout created so renewals can auto-debit.
+async fn save_card_token(db: &DatabaseService, payment: &Payment, transaction: &GatewayTransaction) {
+    if let (Some(registration_id), Some(sub_id)) = (&transaction.registration_id, payment.subscription_id.as_ref()) {
+        if db.get_recurring_token_by_user(&payment.user_id).await.as_deref() != Some(registration_id.as_str()) {
+            db.create_recurring_payment(
+                payment.user_id.clone(),
+                sub_id.clone(),
+                registration_id.clone(),
+                transaction.card_last4.clone(),
+                transaction.payment_brand.clone(),
+                transaction.card_expiry,
+            ).await;
+        }
+    }
+}
+
+/// A successful authorise-only checkout: the amount is reserved and the card
+/// saved, but the subscription waits for an administrator to capture it.
+async fn authorize_payment(
+    db: &DatabaseService,
+    payment: &Payment,
+    transaction: &GatewayTransaction,
+    event_at: DateTime<Utc>,
+) -> Result<WebhookOutcome, String> {
+    if let Some(reason) = stale_event_reason(payment, &PaymentStatus::Authorized, event_at) {
+        return Ok(skip_stale_event(db, payment, reason).await);
+    }
+    if !db.authorize_payment(&payment.merchant_transaction_id, transaction.gateway_reference.as_deref(), event_at).await? {
+        return Ok(skip_stale_event(db, payment, "a newer event was applied concurrently".to_string()).await);
+    }
+    save_card_token(db, payment, transaction).await;
+    Ok(WebhookOutcome::Processed)
+}
+
+/// Unknown brands are logged and treated as cards, by far the most common.
+fn payment_method_for_brand(brand: &str) -> PaymentMethod {
+    PaymentMethod::from_brand(brand).unwrap_or_else(|| {
+        warn!("Unknown paymentBrand: '{}', defaulting to Card", brand);
+        PaymentMethod::Card
+    })
+}
+
+/// Why a payment webhook must not be applied, if it arrived out of order:
+/// it would move the payment back a stage, or it predates the last event applied.
+fn stale_event_reason(payment: &Payment, incoming: &PaymentStatus, event_at: DateTime<Utc>) -> Option<String> {
+    if incoming.stage() < payment.status.stage() {
+        return Some(format!(
+            "payment is already {:?}; late {:?} event from {} ignored",
+            payment.status, incoming, event_at.to_rfc3339()
+        ));
+    }
+    match payment.last_event_at {
+        Some(last) if last > event_at => Some(format!(
+            "{:?} event from {} is older than the last applied event from {}",
+            incoming, event_at.to_rfc3339(), last.to_rfc3339()
+        )),
+        _ => None,
+    }
+}
+
+/// Logs a skipped event on the user's activity feed so support can see why a
+/// webhook had no effect; the webhook event itself is stored as `Skipped`.
+async fn skip_stale_event(db: &DatabaseService, payment: &Payment, reason: String) -> WebhookOutcome {
+    info!("Skipping webhook for {}: {}", payment.merchant_transaction_id, reason);
+    db.record_activity(
+        &payment.user_id,
+        ActivityCategory::Payment,
+        "payment_event_skipped",
+        format!("Out-of-order payment notification ignored: {}", reason),
+        Some(payment.merchant_transaction_id.clone()),
+    ).await;
+    WebhookOutcome::Skipped
 }
 
+/// Extracts the parsed-field summary recorded on a webhook event.
+pub fn webhook_event_update(notification: &WebhookNotification) -> WebhookEventUpdate {
+    WebhookEventUpdate {
+        signature: notification.signature.clone(),
+        parsed: Some(notification.fields.clone()),
+        result_code: Some(notification.transaction.code.clone()),
+        merchant_transaction_id: notification.transaction.merchant_transaction_id.clone(),
+        event_timestamp: notification.occurred_at,
+        error: None,
+    }
+}
+
+async fn record_webhook_outcome(
+    db: &DatabaseService,
+    event_id: &Option<String>,
+    outcome: WebhookOutcome,
+    update: WebhookEventUpdate,
+) {
+    if let Some(id) = event_id {
+        if let Err(e) = db.update_webhook_event(id, outcome, update).await {
+            error!("Failed to record webhook outcome for {}: {}", id, e);
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/payments/callback",
+    tag = "payments",
+    responses(
+        (status = 200, description = "Webhook accepted"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+    )
+)]
 #[post("/callback")]
 pub async fn payment_callback(
-    _req: HttpRequest,
+    req: HttpRequest,
+    body: web::Bytes,
+    gateway: web::Data<dyn PaymentGateway>,
+    db: web::Data<DatabaseService>,
+    email: web::Data<EmailService>,
+    config: web::Data<AppConfig>,
+) -> HttpResponse {
+    info!("Webhook received at /callback");
+    receive_webhook(&req, &body, gateway.get_ref(), &db, &email, &config).await
+}
+
+/// Ozow's `NotifyUrl`; only routed when Ozow is configured.
+#[utoipa::path(
+    post,
+    path = "/api/v1/payments/ozow/notify",
+    tag = "payments",
+    responses(
+        (status = 200, description = "Webhook accepted"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+    )
+)]
+#[post("/ozow/notify")]
+pub async fn ozow_notify(
+    req: HttpRequest,
     body: web::Bytes,
-    peach_service: web::Data<PeachPaymentService>,
+    ozow: Option<web::Data<OzowPaymentService>>,
     db: web::Data<DatabaseService>,
+    email: web::Data<EmailService>,
+    config: web::Data<AppConfig>,
+) -> HttpResponse {
+    info!("Webhook received at /ozow/notify");
+    match ozow {
+        Some(ozow) => receive_webhook(&req, &body, ozow.get_ref(), &db, &email, &config).await,
+        None => HttpResponse::NotFound().body("Ozow is not configured"),
+    }
+}
+
+/// Stores, verifies and applies one webhook delivery from `gateway`.
+async fn receive_webhook(
+    req: &HttpRequest,
+    body: &[u8],
+    gateway: &dyn PaymentGateway,
+    db: &DatabaseService,
+    email: &EmailService,
+    config: &AppConfig,
 ) -> HttpResponse {
-    println!("🔔 Webhook received at /callback");
+    // 0. Persist the raw body before anything can fail
+    let event_id = match db.create_webhook_event(&String::from_utf8_lossy(body), gateway.name()).await {
+        Ok(id) => Some(id),
+        Err(e) => {
+            error!("Failed to persist webhook event: {}", e);
+            None
+        }
+    };
     
     // 1. Log raw incoming data
-    let body_str = match std::str::from_utf8(&body) {
+    let body_str = match std::str::from_utf8(body) {
         Ok(s) => s,
         Err(e) => {
-            eprintln!("❌ Invalid UTF-8 body: {}", e);
+            error!("Invalid UTF-8 body: {}", e);
+            record_webhook_outcome(db, &event_id, WebhookOutcome::Rejected, WebhookEventUpdate {
+                error: Some(format!("Invalid UTF-8: {}", e)),
+                ..Default::default()
+            }).await;
             return HttpResponse::BadRequest().body("Invalid UTF-8");
         }
     };
     
-    println!("📩 Raw webhook body: {}", body_str);
-    println!("Body length: {} bytes", body.len());
+    debug!("Raw webhook body: {}", body_str);
+    debug!("Body length: {} bytes", body.len());
     
-    // 2. Parse form data
-    let form_map: HashMap<String, String> = match serde_urlencoded::from_bytes(&body) {
-        Ok(map) => map,
+    // 2. Decrypt encrypted deliveries; AES-GCM authenticates them, so a body
+    // that decrypts has a valid signature
+    let signature = gateway.webhook_signature(req.headers(), body);
+    let decrypted = match signature.as_deref().and_then(|s| gateway.decrypt_webhook(body, s)).transpose() {
+        Ok(decrypted) => decrypted,
         Err(e) => {
-            eprintln!("❌ Failed to parse form body: {}", e);
-            return HttpResponse::BadRequest().body("Invalid form data");
+            error!("Failed to decrypt webhook: {}", e);
+            record_webhook_outcome(db, &event_id, WebhookOutcome::Rejected, WebhookEventUpdate {
+                signature: signature.clone(),
+                error: Some(e),
+                ..Default::default()
+            }).await;
+            return HttpResponse::Unauthorized().body("Invalid encrypted webhook");
+        }
+    };
+
+    // 3. Parse into the gateway-neutral shape, form-encoded or decrypted JSON alike
+    let mut notification = match gateway.parse_webhook(decrypted.as_deref().unwrap_or(body)) {
+        Ok(notification) => notification,
+        Err(e) => {
+            error!("Failed to parse webhook body: {}", e);
+            record_webhook_outcome(db, &event_id, WebhookOutcome::Rejected, WebhookEventUpdate {
+                error: Some(e),
+                ..Default::default()
+            }).await;
+            return HttpResponse::BadRequest().body("Invalid webhook body");
         }
     };
     
-    let provided_signature = form_map.get("signature").map(|s| s.as_str()).unwrap_or("");
-    if provided_signature.is_empty() {
-        eprintln!("❌ No signature provided in webhook");
-        return HttpResponse::BadRequest().body("Missing signature");
-    }
+    let provided_signature = match signature {
+        Some(signature) => signature,
+        None => {
+            error!("No signature provided in webhook");
+            record_webhook_outcome(db, &event_id, WebhookOutcome::Rejected, WebhookEventUpdate {
+                error: Some("Missing signature".to_string()),
+                ..webhook_event_update(&notification)
+            }).await;
+            return HttpResponse::BadRequest().body("Missing signature");
+        }
+    };
+    // Kept on the stored event so replays can be re-verified
+    notification.signature = Some(provided_signature.clone());
     
-    // 3. Create and validate signature
-    let signature_payload = create_signature_payload(&form_map);
-    println!("🔍 Signature payload: {}", signature_payload);
-    println!("🔍 Provided signature: {}", provided_signature);
+    // 4. Validate signature
+    debug!("Provided signature: {}", provided_signature);
     
-    if !peach_service.validate_webhook_signature(signature_payload.as_bytes(), provided_signature) {
-        eprintln!("❌ Signature validation failed");
+    if decrypted.is_none() && !gateway.validate_webhook(body, &provided_signature) {
+        error!("Signature validation failed");
+        record_webhook_outcome(db, &event_id, WebhookOutcome::Rejected, WebhookEventUpdate {
+            error: Some("Invalid signature".to_string()),
+            ..webhook_event_update(&notification)
+        }).await;
         return HttpResponse::Unauthorized().body("Invalid signature");
     }
     
-    println!("✅ Webhook signature validated successfully");
-    
-    // 4. Extract fields
-    let status_code = form_map.get("result.code").cloned().unwrap_or_default();
-    let merchant_transaction_id = form_map
-        .get("merchantTransactionId")
-        .cloned()
-        .unwrap_or_default();
-    let subscription_id = form_map
-        .get("customParameters[subscription_id]")
-        .or_else(|| form_map.get("customParameters%5Bsubscription_id%5D"))
-        .cloned();
-    
-    println!(
-        "🧾 Parsed: result.code={}, transaction_id={}, subscription_id={:?}",
-        status_code, merchant_transaction_id, subscription_id
-    );
-    
-    // 5. Process based on status code
-    match status_code.as_str() {
-        "000.000.000" | "000.100.110" => {
-            println!("✅ Payment successful");
-            if let Some(payment) = db.get_payment_by_merchant_id(&merchant_transaction_id).await {  // ✅ Added .await
-                let _ = db.update_payment_status(&merchant_transaction_id, &PaymentStatus::Completed).await;  // ✅ Added .await
-                
-                if let Some(ref sub_id) = payment.subscription_id {
-                    let _ = db.activate_subscription(sub_id).await;  // ✅ Added .await
-                    
-                    if let Some(payment_brand_str) = form_map.get("paymentBrand").cloned() {
-                        let brand_lc = payment_brand_str.to_lowercase();
-                        let method = match brand_lc.as_str() {
-                            "visa" | "mastercard" | "amex" => PaymentMethod::Card,
-                            "eft" | "ozow" => PaymentMethod::EFT,
-                            "1voucher" | "1foryou" => PaymentMethod::Voucher,
-                            "scan_to_pay" | "scantopay" => PaymentMethod::ScanToPay,
-                            _ => {
-                                eprintln!("⚠️ Unknown paymentBrand: '{}', defaulting to Card", brand_lc);
-                                PaymentMethod::Card
-                            }
-                        };
-                        
-                        let _ = db.update_subscription_payment_details(
-                            sub_id,
-                            method.clone(),
-                            Some(payment_brand_str.clone()),
-                        ).await;  // ✅ Added .await
-                        
-                        println!(
-                            "🔄 Updated subscription {} with payment method {:?} and brand {}",
-                            sub_id, method, payment_brand_str
-                        );
-                    } else {
-                        println!("ℹ️ No paymentBrand found in webhook for subscription {}", sub_id);
-                    }
-                }
-            } else {
-                println!("⚠️ No payment found for merchantTransactionId: {}", merchant_transaction_id);
-            }
-        }
-        "100.396.104" => {
-            println!("⚠️ Payment uncertain/cancelled by user");
-            let _ = db.update_payment_status(&merchant_transaction_id, &PaymentStatus::Failed).await;  // ✅ Added .await
+    info!("Webhook signature validated successfully");
+
+    if gateway.name() == "peach" {
+        let code = &notification.transaction.code;
+        let category = peach::result_code_category(code);
+        if category == ResultCodeCategory::Unhandled {
+            warn!("Unhandled Peach result code {}", code);
         }
-        "000.200.100" => {
-            println!("ℹ️ Checkout created - no action needed");
+        if let Err(e) = db.record_webhook_result_code(code, category).await {
+            warn!("{}", e);
         }
-        "000.200.000" => {
-            println!("ℹ️ Payment pending - no action needed");
+    }
+    
+    // 5. Process and record the outcome
+    match process_webhook(db, email, config, &notification, Utc::now()).await {
+        Ok(outcome) => {
+            record_webhook_outcome(db, &event_id, outcome, webhook_event_update(&notification)).await;
+            // Watched by the silence alert; replays don't count as deliveries
+            if let Err(e) = db.record_webhook_heartbeat(gateway.name()).await {
+                warn!("{}", e);
+            }
         }
-        _ => {
-            println!("⚠️ Unhandled result.code: {}", status_code);
+        Err(e) => {
+            error!("Webhook processing failed: {}", e);
+            record_webhook_outcome(db, &event_id, WebhookOutcome::Failed, WebhookEventUpdate {
+                error: Some(e),
+                ..webhook_event_update(&notification)
+            }).await;
         }
     }
     
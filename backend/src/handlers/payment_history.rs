@@ -0,0 +1,87 @@
+use actix_web::{HttpResponse, Result, get};
+use actix_web::web::{Data, Query};
+use chrono::{Duration, NaiveDate};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use tracing::error;
+use crate::extractors::{CurrentUser, RecordPath};
+use crate::models::common::{page_and_limit, PaginatedResponse};
+use crate::models::payment::{PaymentHistoryFilter, PaymentSort, PaymentStatus};
+use crate::models::user::User;
+use crate::services::database::DatabaseService;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PaymentHistoryQuery {
+    /// From 1.
+    pub page: Option<u32>,
+    /// Up to 100; 20 by default.
+    pub limit: Option<u32>,
+    pub status: Option<PaymentStatus>,
+    /// Payments created on or after this date (UTC).
+    pub from: Option<NaiveDate>,
+    /// Payments created on or before this date (UTC).
+    pub to: Option<NaiveDate>,
+    #[serde(default)]
+    pub sort: PaymentSort,
+}
+
+/// The caller's payments, a page at a time. Other users' payments are
+/// reported as missing.
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/{user_id}/payments",
+    tag = "users",
+    params(
+        ("user_id" = String, Path, description = "User id"),
+        PaymentHistoryQuery,
+    ),
+    responses(
+        (status = 200, description = "A page of payments", body = PaginatedPayments),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[get("/{user_id}/payments")]
+pub async fn get_payment_history(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    user_id: RecordPath<User>,
+    query: Query<PaymentHistoryQuery>,
+) -> Result<HttpResponse> {
+    if user_id.key() != user.user_id || db.get_user(user_id.key()).await.is_none() {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "User not found"
+        })));
+    }
+
+    let query = query.into_inner();
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from > to {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "from must not be after to"
+            })));
+        }
+    }
+
+    let (page, limit) = page_and_limit(query.page, query.limit);
+    let filter = PaymentHistoryFilter {
+        status: query.status,
+        from: query.from.map(|date| date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()),
+        // Whole days, so up to the start of the next one
+        to: query.to.map(|date| (date + Duration::days(1)).and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()),
+    };
+
+    match db.get_payment_history(user_id.key(), &filter, query.sort, page, limit).await {
+        Ok((payments, total)) => Ok(HttpResponse::Ok().json(PaginatedResponse::new(payments, page, limit, total))),
+        Err(e) => {
+            error!("Error loading payment history for {}: {}", user_id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to load payments"
+            })))
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use actix_web::{HttpResponse, Result, delete, get, put};
+use actix_web::web::{Data, Path, Query};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use tracing::error;
+use crate::extractors::{AdminAuth, ValidatedJson};
+use crate::models::payment::{default_currency, PaymentMethod};
+use crate::models::payment_method_rule::{
+    method_availability, normalize_region, MethodAvailability, PaymentMethodRule, SetPaymentMethodRuleDto, PAYMENT_METHODS,
+};
+use crate::models::validation::ValidationErrorResponse;
+use crate::services::database::DatabaseService;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MethodAvailabilityQuery {
+    /// Shopper's country code, e.g. `ZA`; only rules for every region apply without it.
+    pub region: Option<String>,
+    /// Defaults to the API's default currency.
+    pub currency: Option<String>,
+    /// Checked against each method's limits when given.
+    pub amount: Option<f64>,
+}
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({ "error": message }))
+}
+
+fn server_error(message: &str, details: String) -> HttpResponse {
+    error!("{}: {}", message, details);
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "error": message,
+        "details": details
+    }))
+}
+
+/// A method as written in paths, e.g. `EFT` or `ScanToPay`.
+fn parse_method(method: &str) -> Result<PaymentMethod, String> {
+    serde_json::from_value(serde_json::Value::String(method.to_string()))
+        .map_err(|_| format!("Unknown payment method {}", method))
+}
+
+/// Which payment methods a shopper can use, so the PWA only offers those.
+/// Initiating with an unavailable method fails with the same reason.
+#[utoipa::path(
+    get,
+    path = "/api/v1/payments/methods",
+    tag = "payments",
+    params(MethodAvailabilityQuery),
+    responses(
+        (status = 200, description = "Every payment method and whether it is available", body = [MethodAvailability]),
+        (status = 400, description = "Invalid request"),
+        (status = 500, description = "Internal error"),
+    )
+)]
+#[get("/methods")]
+pub async fn list_payment_methods(
+    db: Data<DatabaseService>,
+    query: Query<MethodAvailabilityQuery>,
+) -> Result<HttpResponse> {
+    let query = query.into_inner();
+    let region = match query.region.as_deref().map(normalize_region).transpose() {
+        Ok(region) => region,
+        Err(e) => return Ok(bad_request(e)),
+    };
+    if query.amount.is_some_and(|a| !a.is_finite() || a < 0.0) {
+        return Ok(bad_request("amount must be zero or more".to_string()));
+    }
+    let currency = query.currency.map(|c| c.trim().to_uppercase()).unwrap_or_else(default_currency);
+
+    let rules = match db.list_payment_method_rules().await {
+        Ok(rules) => rules,
+        Err(e) => return Ok(server_error("Failed to load payment methods", e)),
+    };
+    let methods: Vec<MethodAvailability> = PAYMENT_METHODS
+        .iter()
+        .map(|method| method_availability(&rules, method, region.as_deref(), &currency, query.amount))
+        .collect();
+    Ok(HttpResponse::Ok().json(methods))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/payment-methods/rules",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Every availability rule", body = [PaymentMethodRule]),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/payment-methods/rules")]
+pub async fn admin_list_payment_method_rules(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+) -> Result<HttpResponse> {
+    match db.list_payment_method_rules().await {
+        Ok(rules) => Ok(HttpResponse::Ok().json(rules)),
+        Err(e) => Ok(server_error("Failed to list payment method rules", e)),
+    }
+}
+
+/// Sets where and for how much a method is offered in a region; `*` sets
+/// the rule for regions without their own.
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/payment-methods/{payment_method}/regions/{region}",
+    tag = "admin",
+    params(
+        ("payment_method" = String, Path, description = "`Card`, `EFT`, `Voucher` or `ScanToPay`"),
+        ("region" = String, Path, description = "Two-letter country code, or `*`"),
+    ),
+    request_body = SetPaymentMethodRuleDto,
+    responses(
+        (status = 200, description = "The stored rule", body = PaymentMethodRule),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[put("/payment-methods/{payment_method}/regions/{region}")]
+pub async fn admin_set_payment_method_rule(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    path: Path<(String, String)>,
+    payload: ValidatedJson<SetPaymentMethodRuleDto>,
+) -> Result<HttpResponse> {
+    let (method, region) = path.into_inner();
+    let method = match parse_method(&method) {
+        Ok(method) => method,
+        Err(e) => return Ok(bad_request(e)),
+    };
+    let region = match normalize_region(&region) {
+        Ok(region) => region,
+        Err(e) => return Ok(bad_request(e)),
+    };
+    match db.set_payment_method_rule(&method, &region, payload.into_inner().normalize()).await {
+        Ok(rule) => Ok(HttpResponse::Ok().json(rule)),
+        Err(e) => Ok(server_error("Failed to set payment method rule", e)),
+    }
+}
+
+/// Removes the rule; the method falls back to the `*` rule in that region.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/payment-methods/{payment_method}/regions/{region}",
+    tag = "admin",
+    params(
+        ("payment_method" = String, Path, description = "`Card`, `EFT`, `Voucher` or `ScanToPay`"),
+        ("region" = String, Path, description = "Two-letter country code, or `*`"),
+    ),
+    responses(
+        (status = 204, description = "Rule removed"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[delete("/payment-methods/{payment_method}/regions/{region}")]
+pub async fn admin_delete_payment_method_rule(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    path: Path<(String, String)>,
+) -> Result<HttpResponse> {
+    let (method, region) = path.into_inner();
+    let method = match parse_method(&method) {
+        Ok(method) => method,
+        Err(e) => return Ok(bad_request(e)),
+    };
+    let region = match normalize_region(&region) {
+        Ok(region) => region,
+        Err(e) => return Ok(bad_request(e)),
+    };
+
+    match db.delete_payment_method_rule(&method, &region).await {
+        Ok(()) => Ok(HttpResponse::NoContent().finish()),
+        Err(e) if e.starts_with("Rule not found") => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Rule not found"
+        }))),
+        Err(e) => Ok(server_error("Failed to remove payment method rule", e)),
+    }
+}
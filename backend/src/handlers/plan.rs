@@ -0,0 +1,243 @@
+use actix_web::{HttpRequest, HttpResponse, Result, delete, get, post, put};
+use actix_web::http::header;
+use actix_web::web::Data;
+use tracing::error;
+use crate::config::AppConfig;
+use crate::extractors::{AdminAuth, RecordPath, ValidatedJson};
+use crate::models::plan::{
+    plan_id_from_name, validate_interval, BillingInterval, CreatePlanDto, Plan, PlanCatalog, UpdatePlanDto,
+};
+use crate::models::validation::ValidationErrorResponse;
+use crate::services::database::DatabaseService;
+use crate::services::gateway::PaymentGateway;
+
+// Plans change rarely and every PWA session fetches them, so let clients and
+// proxies keep them for a day and revalidate with the ETag afterwards.
+const PLAN_CACHE_CONTROL: &str = "public, max-age=86400, stale-while-revalidate=3600";
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/plans",
+    tag = "plans",
+    responses(
+        (status = 200, description = "Active plans, with an `ETag`", body = PlanCatalog),
+        (status = 304, description = "Catalog unchanged since `If-None-Match`"),
+        (status = 500, description = "Internal error"),
+    )
+)]
+#[get("")]
+pub async fn get_plans(
+    req: HttpRequest,
+    db: Data<DatabaseService>,
+    config: Data<AppConfig>,
+) -> Result<HttpResponse> {
+    let catalog = match db.list_plans().await {
+        Ok(plans) => PlanCatalog::new(plans, config.vat_rate_percent),
+        Err(e) => {
+            error!("Error loading plans: {}", e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to load plans"
+            })));
+        }
+    };
+    let etag = format!("\"{}\"", catalog.version);
+
+    let not_modified = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map_or(false, |v| v.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"));
+
+    if not_modified {
+        return Ok(HttpResponse::NotModified()
+            .insert_header((header::ETAG, etag))
+            .insert_header((header::CACHE_CONTROL, PLAN_CACHE_CONTROL))
+            .finish());
+    }
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::CACHE_CONTROL, PLAN_CACHE_CONTROL))
+        .json(catalog))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/plans",
+    tag = "admin",
+    responses(
+        (status = 200, description = "All plans, including inactive ones", body = [Plan]),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/plans")]
+pub async fn admin_list_plans(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+) -> Result<HttpResponse> {
+    match db.list_plans().await {
+        Ok(plans) => Ok(HttpResponse::Ok().json(plans)),
+        Err(e) => {
+            error!("Error listing plans: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to list plans"
+            })))
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/plans",
+    tag = "admin",
+    request_body = CreatePlanDto,
+    responses(
+        (status = 201, description = "Plan created", body = Plan),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 409, description = "Conflicts with the current state"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/plans")]
+pub async fn admin_create_plan(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    gateway: Data<dyn PaymentGateway>,
+    payload: ValidatedJson<CreatePlanDto>,
+) -> Result<HttpResponse> {
+    let dto = payload.into_inner();
+
+    if let Some(response) = unsupported_currency(gateway.get_ref(), Some(&dto.currency)) {
+        return Ok(response);
+    }
+
+    let plan_id = dto.id.as_deref().map(plan_id_from_name).unwrap_or_else(|| plan_id_from_name(&dto.name));
+    if plan_id.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Plan id must contain letters or digits"
+        })));
+    }
+
+    if db.get_plan(&plan_id).await.is_some() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("Plan already exists: {}", plan_id)
+        })));
+    }
+
+    match db.create_plan(&plan_id, dto).await {
+        Ok(plan) => Ok(HttpResponse::Created().json(plan)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to create plan",
+            "details": e
+        }))),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/plans/{plan_id}",
+    tag = "admin",
+    params(("plan_id" = String, Path, description = "Plan id")),
+    request_body = UpdatePlanDto,
+    responses(
+        (status = 200, description = "Plan updated", body = Plan),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[put("/plans/{plan_id}")]
+pub async fn admin_update_plan(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    gateway: Data<dyn PaymentGateway>,
+    plan_id: RecordPath<Plan>,
+    payload: ValidatedJson<UpdatePlanDto>,
+) -> Result<HttpResponse> {
+    let plan_id = plan_id.into_key();
+    let dto = payload.into_inner();
+
+    if let Some(response) = unsupported_currency(gateway.get_ref(), dto.currency.as_deref()) {
+        return Ok(response);
+    }
+
+    let existing = match db.get_plan(&plan_id).await {
+        Some(plan) => plan,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Plan not found"
+        }))),
+    };
+
+    // Check the interval the plan ends up with; a stored length only carries
+    // over while the plan stays Custom
+    let interval = dto.interval.as_ref().unwrap_or(&existing.interval);
+    let interval_days = dto.interval_days
+        .or(existing.interval_days.filter(|_| *interval == BillingInterval::Custom));
+    if let Err(e) = validate_interval(interval, interval_days) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+    }
+
+    match db.update_plan(&plan_id, dto).await {
+        Ok(plan) => Ok(HttpResponse::Ok().json(plan)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to update plan",
+            "details": e
+        }))),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/plans/{plan_id}",
+    tag = "admin",
+    params(("plan_id" = String, Path, description = "Plan id")),
+    responses(
+        (status = 204, description = "Plan deleted"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[delete("/plans/{plan_id}")]
+pub async fn admin_delete_plan(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    plan_id: RecordPath<Plan>,
+) -> Result<HttpResponse> {
+    match db.delete_plan(plan_id.key()).await {
+        Ok(()) => Ok(HttpResponse::NoContent().finish()),
+        Err(e) if e.starts_with("Plan not found") => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Plan not found"
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to delete plan",
+            "details": e
+        }))),
+    }
+}
+
+/// Plans can only be priced in a currency the gateway can actually charge.
+fn unsupported_currency(gateway: &dyn PaymentGateway, currency: Option<&str>) -> Option<HttpResponse> {
+    let currency = currency?;
+    if gateway.supports_currency(currency) {
+        return None;
+    }
+    Some(HttpResponse::BadRequest().json(serde_json::json!({
+        "error": format!(
+            "Currency {} is not supported by {} (supported: {})",
+            currency.to_uppercase(),
+            gateway.name(),
+            gateway.supported_currencies().join(", ")
+        )
+    })))
+}
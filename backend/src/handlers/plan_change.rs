@@ -0,0 +1,469 @@
+use actix_web::{HttpRequest, HttpResponse, Result, delete, get, post};
+use actix_web::web::Data;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use tracing::{error, info};
+use crate::config::AppConfig;
+use crate::extractors::{CurrentUser, RecordPath, ValidatedJson};
+use crate::handlers::subscription::{load_owned_subscription, SubscriptionResponse};
+use crate::models::invoice::CreditNote;
+use crate::models::plan::Plan;
+use crate::models::plan_change::{
+    ChangeAt, ChangePlanDto, PlanChange, PlanChangeStatus, ProrationCalculation, PLAN_CHANGE_PREFIX,
+};
+use crate::models::subscription::{Subscription, SubscriptionStatus};
+use crate::models::webhook_event::WebhookOutcome;
+use crate::services::database::DatabaseService;
+use crate::services::email::{EmailEvent, EmailService};
+use crate::services::formatting::Formatting;
+use crate::services::gateway::{ChargeStatus, GatewayTransaction, PaymentGateway};
+use crate::services::invoicing::issue_invoice;
+use crate::services::tax::TaxBreakdown;
+
+#[derive(Serialize, ToSchema)]
+pub struct PlanChangePreview {
+    pub plan: Plan,
+    pub proration: ProrationCalculation,
+    /// When the change would wait for the next renewal: the end of the
+    /// current period.
+    pub effective_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PlanChangeResponse {
+    pub plan_change: PlanChange,
+    pub subscription: SubscriptionResponse,
+    pub credit_note: Option<CreditNote>,
+}
+
+fn conflict(error: &str) -> HttpResponse {
+    HttpResponse::Conflict().json(serde_json::json!({ "error": error }))
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "error": "Subscription not found"
+    }))
+}
+
+/// A checked plan change, prorated from when it takes effect.
+struct PreparedPlanChange {
+    subscription: Subscription,
+    plan: Plan,
+    proration: ProrationCalculation,
+    /// Set when the change waits for the next renewal.
+    effective_at: Option<DateTime<Utc>>,
+}
+
+/// Checks that the caller's subscription can move to `plan_id` and prorates
+/// the switch. Unless `when` says otherwise, downgrades wait for the next
+/// renewal so nothing is refunded mid-period; those prorate to nothing.
+async fn prepare_plan_change(
+    db: &DatabaseService,
+    config: &AppConfig,
+    user: &CurrentUser,
+    subscription_id: &str,
+    plan_id: &str,
+    when: Option<ChangeAt>,
+) -> Result<PreparedPlanChange, HttpResponse> {
+    let subscription = load_owned_subscription(db, user, subscription_id).await
+        .ok_or_else(not_found)?;
+
+    let in_paid_period = subscription.end_date.is_some_and(|end| end > Utc::now());
+    if subscription.status != SubscriptionStatus::Active || !in_paid_period || subscription.next_renewal_attempt_at.is_some() {
+        return Err(conflict("Only an active, paid-up subscription can change plan"));
+    }
+    if subscription.cancel_at_period_end {
+        return Err(conflict("Subscription is cancelled at the end of its period"));
+    }
+
+    let plan = db.get_plan(plan_id).await
+        .ok_or_else(|| HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Unknown plan: {}", plan_id)
+        })))?;
+
+    if subscription.plan_id.as_deref() == Some(plan.id.as_str()) {
+        return Err(conflict("Subscription is already on this plan"));
+    }
+    if plan.currency != subscription.currency {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Plan is priced in {}, the subscription in {}", plan.currency, subscription.currency)
+        })));
+    }
+    // The current period keeps its end date, so both plans must bill over the same length
+    if plan.period_days() != subscription.billing_period_days {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Switching to a plan with a different billing interval is not supported"
+        })));
+    }
+
+    let to_price = plan.tax(config.vat_rate_percent).gross;
+    let proration = ProrationCalculation::calculate(&subscription, to_price, Utc::now())
+        .map_err(|e| conflict(&e))?;
+    let scheduled = match when {
+        Some(ChangeAt::Immediately) => false,
+        Some(ChangeAt::NextRenewal) => true,
+        None => proration.credit_amount > 0.0,
+    };
+    if !scheduled {
+        return Ok(PreparedPlanChange { subscription, plan, proration, effective_at: None });
+    }
+
+    let effective_at = proration.period_end;
+    let proration = ProrationCalculation::calculate(&subscription, to_price, effective_at)
+        .map_err(|e| conflict(&e))?;
+    Ok(PreparedPlanChange { subscription, plan, proration, effective_at: Some(effective_at) })
+}
+
+/// What switching the caller's subscription to another plan would cost or
+/// credit, and when it would take effect, without changing anything.
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscriptions/{subscription_id}/change-plan/preview",
+    tag = "subscriptions",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    request_body = ChangePlanDto,
+    responses(
+        (status = 200, description = "Proration for the switch", body = PlanChangePreview),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Conflicts with the current state"),
+    ),
+    security(("user_id" = []))
+)]
+#[post("/{subscription_id}/change-plan/preview")]
+pub async fn preview_plan_change(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    config: Data<AppConfig>,
+    subscription_id: RecordPath<Subscription>,
+    payload: ValidatedJson<ChangePlanDto>,
+) -> Result<HttpResponse> {
+    match prepare_plan_change(&db, &config, &user, subscription_id.key(), &payload.plan_id, payload.when).await {
+        Ok(prepared) => Ok(HttpResponse::Ok().json(PlanChangePreview {
+            plan: prepared.plan,
+            proration: prepared.proration,
+            effective_at: prepared.effective_at,
+        })),
+        Err(response) => Ok(response),
+    }
+}
+
+/// Moves the caller's subscription to another plan. An immediate upgrade
+/// charges the prorated difference to the saved card and only switches once
+/// that charge succeeds; an immediate downgrade credits the difference as a
+/// credit note. A change at the next renewal is scheduled instead, replacing
+/// any change already scheduled, and the renewal is charged at the new price.
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscriptions/{subscription_id}/change-plan",
+    tag = "subscriptions",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    request_body = ChangePlanDto,
+    responses(
+        (status = 200, description = "Plan changed, or scheduled for the next renewal", body = PlanChangeResponse),
+        (status = 202, description = "Upgrade charge pending; the change applies once it succeeds", body = PlanChangeResponse),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 402, description = "Upgrade charge declined"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Conflicts with the current state"),
+        (status = 500, description = "Internal error"),
+        (status = 502, description = "Gateway error"),
+    ),
+    security(("user_id" = []))
+)]
+#[post("/{subscription_id}/change-plan")]
+#[allow(clippy::too_many_arguments)]
+pub async fn change_plan(
+    req: HttpRequest,
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    config: Data<AppConfig>,
+    email: Data<EmailService>,
+    gateway: Data<dyn PaymentGateway>,
+    subscription_id: RecordPath<Subscription>,
+    payload: ValidatedJson<ChangePlanDto>,
+) -> Result<HttpResponse> {
+    let PreparedPlanChange { subscription, plan, proration, effective_at } =
+        match prepare_plan_change(&db, &config, &user, subscription_id.key(), &payload.plan_id, payload.when).await {
+            Ok(prepared) => prepared,
+            Err(response) => return Ok(response),
+        };
+
+    let token = if proration.amount_due > 0.0 {
+        match db.get_recurring_token_by_user(&subscription.user_id).await {
+            Some(token) => Some(token),
+            None => return Ok(conflict("A saved card is needed to pay for the upgrade")),
+        }
+    } else {
+        None
+    };
+
+    let change = PlanChange {
+        id: Uuid::new_v4().simple().to_string(),
+        user_id: subscription.user_id.clone(),
+        subscription_id: subscription.id.clone(),
+        from_plan_id: subscription.plan_id.clone(),
+        from_plan_name: subscription.plan_name.clone(),
+        to_plan_id: plan.id.clone(),
+        to_plan_name: plan.name.clone(),
+        to_price: proration.to_price,
+        merchant_transaction_id: token.as_ref().map(|_| format!(
+            "{}{}",
+            PLAN_CHANGE_PREFIX,
+            Uuid::new_v4().simple().to_string().to_uppercase().get(..16).unwrap_or("0000000000000000")
+        )),
+        proration,
+        credit_note_id: None,
+        status: if effective_at.is_some() { PlanChangeStatus::Scheduled } else { PlanChangeStatus::Pending },
+        failure_reason: None,
+        effective_at,
+        created_at: Utc::now(),
+        completed_at: None,
+    };
+
+    if change.status == PlanChangeStatus::Scheduled {
+        if let Err(e) = db.schedule_plan_change(&change, &subscription).await {
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to schedule plan change",
+                "details": e
+            })));
+        }
+        info!("Plan change {} scheduled for {}", change.id, change.proration.period_end);
+        return Ok(HttpResponse::Ok().json(PlanChangeResponse {
+            plan_change: change,
+            subscription: SubscriptionResponse::from_subscription(subscription, &Formatting::from_request(&req)),
+            credit_note: None,
+        }));
+    }
+
+    if let Err(e) = db.create_plan_change(&change).await {
+        return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to record plan change",
+            "details": e
+        })));
+    }
+
+    let result = match (&token, &change.merchant_transaction_id) {
+        (Some(token), Some(merchant_transaction_id)) => {
+            let amount = change.proration.amount_due;
+            let tax = TaxBreakdown::from_inclusive(amount, config.vat_rate_percent);
+            if let Err(e) = db.create_proration_payment(&change, tax, gateway.name()).await {
+                let _ = db.fail_plan_change(&change, &e).await;
+                return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": "Failed to record proration payment",
+                    "details": e
+                })));
+            }
+
+            info!("Charging {} {} for plan change {}", amount, change.proration.currency, change.id);
+            let descriptor = plan.statement_descriptor.as_deref()
+                .or(config.statement_descriptor.as_deref())
+                .filter(|_| gateway.supports_statement_descriptor());
+            match gateway.charge_token(token, amount, &change.proration.currency, merchant_transaction_id, descriptor).await {
+                Ok(transaction) if transaction.status.is_pending() => {
+                    // The webhook completes or fails the change; until then the plan is unchanged
+                    return Ok(HttpResponse::Accepted().json(PlanChangeResponse {
+                        plan_change: change,
+                        subscription: SubscriptionResponse::from_subscription(subscription, &Formatting::from_request(&req)),
+                        credit_note: None,
+                    }));
+                }
+                Ok(transaction) => apply_charge_result(&db, &config, &email, &change, &transaction).await,
+                Err(e) => {
+                    let reason = e.to_string();
+                    if let Err(e) = db.fail_plan_change(&change, &reason).await {
+                        error!("{}", e);
+                    }
+                    return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                        "error": "Payment for the upgrade could not be processed",
+                        "details": reason
+                    })));
+                }
+            }
+        }
+        _ => {
+            let credit = (change.proration.credit_amount > 0.0)
+                .then(|| TaxBreakdown::from_inclusive(change.proration.credit_amount, config.vat_rate_percent));
+            db.complete_plan_change(&change, None, credit).await
+        }
+    };
+
+    let updated = match result {
+        Ok(updated) => updated,
+        Err(e) => return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to change plan",
+            "details": e
+        }))),
+    };
+
+    if updated.status == PlanChangeStatus::Failed {
+        return Ok(HttpResponse::PaymentRequired().json(serde_json::json!({
+            "error": "Payment for the upgrade was declined",
+            "details": updated.failure_reason,
+            "plan_change": updated,
+        })));
+    }
+
+    let subscription = db.get_subscription(&updated.subscription_id).await.unwrap_or(subscription);
+    let credit_note = match &updated.credit_note_id {
+        Some(id) => db.get_credit_note(id).await,
+        None => None,
+    };
+    Ok(HttpResponse::Ok().json(PlanChangeResponse {
+        plan_change: updated,
+        subscription: SubscriptionResponse::from_subscription(subscription, &Formatting::from_request(&req)),
+        credit_note,
+    }))
+}
+
+/// The plan change waiting for the caller's next renewal.
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscriptions/{subscription_id}/change-plan",
+    tag = "subscriptions",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    responses(
+        (status = 200, description = "The scheduled change", body = PlanChange),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found, or nothing scheduled"),
+    ),
+    security(("user_id" = []))
+)]
+#[get("/{subscription_id}/change-plan")]
+pub async fn get_scheduled_plan_change(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    subscription_id: RecordPath<Subscription>,
+) -> Result<HttpResponse> {
+    let Some(subscription) = load_owned_subscription(&db, &user, subscription_id.key()).await else {
+        return Ok(not_found());
+    };
+    match db.get_scheduled_plan_change(&subscription.id).await {
+        Some(change) => Ok(HttpResponse::Ok().json(change)),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No plan change is scheduled"
+        }))),
+    }
+}
+
+/// Withdraws the plan change waiting for the caller's next renewal; the
+/// subscription renews on its current plan.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/subscriptions/{subscription_id}/change-plan",
+    tag = "subscriptions",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    responses(
+        (status = 200, description = "The cancelled change", body = PlanChange),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "No plan change is scheduled"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[delete("/{subscription_id}/change-plan")]
+pub async fn cancel_scheduled_plan_change(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    subscription_id: RecordPath<Subscription>,
+) -> Result<HttpResponse> {
+    let Some(subscription) = load_owned_subscription(&db, &user, subscription_id.key()).await else {
+        return Ok(not_found());
+    };
+    let Some(change) = db.get_scheduled_plan_change(&subscription.id).await else {
+        return Ok(conflict("No plan change is scheduled"));
+    };
+
+    match db.cancel_scheduled_plan_change(&change, &subscription).await {
+        Ok(true) => Ok(HttpResponse::Ok().json(db.get_plan_change(&change.id).await.unwrap_or(change))),
+        // Applied by a renewal in the meantime
+        Ok(false) => Ok(conflict("No plan change is scheduled")),
+        Err(e) => {
+            error!("Error cancelling plan change {}: {}", change.id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to cancel plan change"
+            })))
+        }
+    }
+}
+
+/// Completes or fails a plan change from the result of its proration charge,
+/// invoicing and emailing the user when it goes through.
+async fn apply_charge_result(
+    db: &DatabaseService,
+    config: &AppConfig,
+    email: &EmailService,
+    change: &PlanChange,
+    transaction: &GatewayTransaction,
+) -> Result<PlanChange, String> {
+    let merchant_transaction_id = change.merchant_transaction_id.clone().unwrap_or_default();
+
+    if transaction.status != ChargeStatus::Succeeded {
+        db.fail_plan_change(change, &format!("declined with code {}", transaction.code)).await?;
+        return db.get_plan_change(&change.id).await
+            .ok_or_else(|| format!("Plan change {} missing after update", change.id));
+    }
+
+    let updated = db.complete_plan_change(change, transaction.gateway_reference.as_deref(), None).await?;
+    if updated.status == PlanChangeStatus::Completed {
+        let amount = change.proration.amount_due;
+        let descriptor = db.statement_descriptor(Some(change.to_plan_id.as_str()), config.statement_descriptor.as_deref()).await;
+        let invoice = match issue_invoice(
+            db,
+            config,
+            &change.user_id,
+            Some(&change.subscription_id),
+            &merchant_transaction_id,
+            &change.proration.currency,
+            TaxBreakdown::from_inclusive(amount, config.vat_rate_percent),
+        ).await {
+            Ok(invoice) => Some(Box::new(invoice)),
+            Err(e) => {
+                error!("Failed to issue invoice for plan change {}: {}", merchant_transaction_id, e);
+                None
+            }
+        };
+        email.notify_user(db, &change.user_id, EmailEvent::PaymentSucceeded {
+            plan: change.to_plan_name.clone(),
+            amount,
+            currency: change.proration.currency.clone(),
+            reference: merchant_transaction_id,
+            descriptor,
+            invoice,
+        }).await;
+    }
+    Ok(updated)
+}
+
+/// Handles the webhook for a proration charge that was still pending when
+/// the plan change was requested.
+pub async fn process_plan_change_webhook(
+    db: &DatabaseService,
+    config: &AppConfig,
+    email: &EmailService,
+    transaction: &GatewayTransaction,
+) -> Result<WebhookOutcome, String> {
+    let merchant_transaction_id = transaction.merchant_transaction_id.clone().unwrap_or_default();
+    let change = db.get_plan_change_by_transaction(&merchant_transaction_id).await
+        .ok_or_else(|| format!("No plan change found for merchantTransactionId: {}", merchant_transaction_id))?;
+
+    if change.status != PlanChangeStatus::Pending {
+        info!("Plan change {} already {:?}", change.id, change.status);
+        return Ok(WebhookOutcome::Ignored);
+    }
+    if transaction.status.is_pending() {
+        info!("Plan change {} still pending", change.id);
+        return Ok(WebhookOutcome::Ignored);
+    }
+
+    apply_charge_result(db, config, email, &change, transaction).await?;
+    Ok(WebhookOutcome::Processed)
+}
@@ -0,0 +1,126 @@
+use actix_web::{HttpResponse, Result, get, post};
+use actix_web::web::{Bytes, Data, Query};
+use tracing::error;
+use crate::extractors::{TenantAdminAuth, RecordPath};
+use crate::models::reconciliation::{
+    ReconciliationReport, ReconciliationReportQuery, ReconciliationSource, ReconciliationUploadQuery,
+};
+use crate::services::database::DatabaseService;
+use crate::services::gateway::PaymentGateway;
+use crate::services::reconciliation::{parse_settlement_csv, reconcile};
+
+fn bad_request(error: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({ "error": error }))
+}
+
+/// Reconciles a settlement CSV from the gateway's dashboard against the
+/// payments table, as the daily task does with the gateway's own reports.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/reconciliation/upload",
+    tag = "admin",
+    params(ReconciliationUploadQuery),
+    request_body(content = String, content_type = "text/csv", description = "The settlement file, with a header line"),
+    responses(
+        (status = 201, description = "The stored report", body = ReconciliationReport),
+        (status = 400, description = "Invalid request or unreadable file"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/reconciliation/upload")]
+pub async fn upload_settlement_file(
+    _admin: TenantAdminAuth,
+    db: Data<DatabaseService>,
+    gateway: Data<dyn PaymentGateway>,
+    query: Query<ReconciliationUploadQuery>,
+    body: Bytes,
+) -> Result<HttpResponse> {
+    let window = match (query.from, query.to) {
+        (Some(from), Some(to)) if from > to => return Ok(bad_request("from must not be after to")),
+        (Some(from), Some(to)) => Some((from, to)),
+        (None, None) => None,
+        _ => return Ok(bad_request("from and to must be given together")),
+    };
+    let text = match std::str::from_utf8(&body) {
+        Ok(text) => text,
+        Err(_) => return Ok(bad_request("The file must be UTF-8 text")),
+    };
+    let records = match parse_settlement_csv(text) {
+        Ok(records) => records,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Unreadable settlement file",
+            "details": e
+        }))),
+    };
+
+    match reconcile(&db, gateway.name(), ReconciliationSource::Upload, records, window).await {
+        Ok(report) => Ok(HttpResponse::Created().json(report)),
+        Err(e) => {
+            error!("Error reconciling settlement file: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to reconcile settlement file"
+            })))
+        }
+    }
+}
+
+/// Recent reconciliation reports, newest first, with their mismatches.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/reconciliation/reports",
+    tag = "admin",
+    params(ReconciliationReportQuery),
+    responses(
+        (status = 200, description = "Reconciliation reports", body = [ReconciliationReport]),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/reconciliation/reports")]
+pub async fn list_reconciliation_reports(
+    _admin: TenantAdminAuth,
+    db: Data<DatabaseService>,
+    query: Query<ReconciliationReportQuery>,
+) -> Result<HttpResponse> {
+    let limit = query.limit.unwrap_or(30).clamp(1, 365);
+
+    match db.list_reconciliation_reports(limit).await {
+        Ok(reports) => Ok(HttpResponse::Ok().json(reports)),
+        Err(e) => {
+            error!("Error listing reconciliation reports: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to list reconciliation reports"
+            })))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/reconciliation/reports/{report_id}",
+    tag = "admin",
+    params(("report_id" = String, Path, description = "Report id")),
+    responses(
+        (status = 200, description = "The report", body = ReconciliationReport),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/reconciliation/reports/{report_id}")]
+pub async fn get_reconciliation_report(
+    _admin: TenantAdminAuth,
+    db: Data<DatabaseService>,
+    report_id: RecordPath<ReconciliationReport>,
+) -> Result<HttpResponse> {
+    match db.get_reconciliation_report(report_id.key()).await {
+        Some(report) => Ok(HttpResponse::Ok().json(report)),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Report not found"
+        }))),
+    }
+}
@@ -0,0 +1,238 @@
+use actix_web::{HttpResponse, Result, get, post};
+use actix_web::middleware::from_fn;
+use actix_web::web::Data;
+use tracing::info;
+use crate::extractors::{TenantAdminAuth, RecordPath, ValidatedJson};
+use crate::handlers::payment::ApiResponseError;
+use crate::middleware::require_signed_request;
+use crate::models::payment::{Payment, PaymentStatus};
+use crate::models::refund::{CreateRefundDto, RefundStatus};
+use crate::models::validation::ValidationErrorResponse;
+use crate::models::wallet::WalletEntrySource;
+use crate::models::webhook_event::WebhookOutcome;
+use crate::services::database::DatabaseService;
+use crate::services::gateway::{GatewayTransaction, PaymentGateway};
+
+pub(crate) async fn find_payment(db: &DatabaseService, payment_id: &str) -> Option<Payment> {
+    match db.get_payment_by_merchant_id(payment_id).await {
+        Some(payment) => Some(payment),
+        None => db.get_payment(payment_id).await,
+    }
+}
+
+/// Recomputes Refunded / PartiallyRefunded on the payment from its completed refunds.
+pub async fn sync_payment_refund_status(db: &DatabaseService, merchant_transaction_id: &str) -> Result<(), String> {
+    let payment = db.get_payment_by_merchant_id(merchant_transaction_id).await
+        .ok_or_else(|| format!("Payment not found for merchant_transaction_id: {}", merchant_transaction_id))?;
+
+    let refunded: f64 = db.get_refunds_for_payment(merchant_transaction_id).await
+        .iter()
+        .filter(|r| r.status == RefundStatus::Completed)
+        .map(|r| r.amount)
+        .sum();
+
+    let status = if refunded + 0.005 >= payment.amount {
+        PaymentStatus::Refunded
+    } else if refunded > 0.0 {
+        PaymentStatus::PartiallyRefunded
+    } else {
+        return Ok(());
+    };
+
+    if status != payment.status {
+        db.update_payment_status(merchant_transaction_id, &status).await?;
+    }
+    Ok(())
+}
+
+/// Handles refund webhooks.
+pub async fn process_refund_webhook(
+    db: &DatabaseService,
+    transaction: &GatewayTransaction,
+) -> Result<WebhookOutcome, String> {
+    let refund_txn_id = transaction.merchant_transaction_id.clone().unwrap_or_default();
+
+    let refund = db.get_refund_by_transaction_id(&refund_txn_id).await
+        .ok_or_else(|| format!("No refund found for merchantTransactionId: {}", refund_txn_id))?;
+
+    let status = transaction.status.refund_status();
+    if status == RefundStatus::Pending {
+        info!("Refund {} still pending", refund_txn_id);
+        return Ok(WebhookOutcome::Ignored);
+    }
+
+    db.update_refund_status(&refund_txn_id, &status, Some(transaction.code.clone()), transaction.gateway_reference.clone()).await?;
+    sync_payment_refund_status(db, &refund.payment_merchant_transaction_id).await?;
+
+    info!("Refund {} resolved as {:?}", refund_txn_id, status);
+    Ok(WebhookOutcome::Processed)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/payments/{payment_id}/refund",
+    tag = "payments",
+    params(("payment_id" = String, Path, description = "Payment id")),
+    request_body = CreateRefundDto,
+    responses(
+        (status = 200, description = "`refund_transaction_id`, `merchant_transaction_id`, `amount` and `status`"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+        (status = 502, description = "Gateway error"),
+    ),
+    security(("admin_token" = [], "request_signature" = []))
+)]
+#[post("/{payment_id}/refund", wrap = "from_fn(require_signed_request)")]
+pub async fn refund_payment(
+    _admin: TenantAdminAuth,
+    db: Data<DatabaseService>,
+    gateway: Data<dyn PaymentGateway>,
+    payment_id: RecordPath<Payment>,
+    payload: ValidatedJson<CreateRefundDto>,
+) -> Result<HttpResponse> {
+    let payment_id = payment_id.into_key();
+
+    let payment = match find_payment(&db, &payment_id).await {
+        Some(p) => p,
+        None => return Ok(HttpResponse::NotFound().json(ApiResponseError {
+            message: "Payment not found".to_string(),
+            details: Some(payment_id),
+        })),
+    };
+
+    if payment.status != PaymentStatus::Completed && payment.status != PaymentStatus::PartiallyRefunded {
+        return Ok(HttpResponse::BadRequest().json(ApiResponseError {
+            message: "Only completed payments can be refunded".to_string(),
+            details: Some(format!("Payment status is {:?}", payment.status)),
+        }));
+    }
+
+    let gateway_reference = match (&payment.peach_payment_id, payload.to_wallet) {
+        (_, true) => None,
+        (Some(id), false) => Some(id.clone()),
+        (None, false) => return Ok(HttpResponse::BadRequest().json(ApiResponseError {
+            message: "Payment has no gateway reference to refund against".to_string(),
+            details: None,
+        })),
+    };
+
+    // Pending refunds count against the balance; `create_refund` checks it
+    // again atomically, so two concurrent requests can't over-refund.
+    let already_refunded: f64 = db.get_refunds_for_payment(&payment.merchant_transaction_id).await
+        .iter()
+        .filter(|r| r.status != RefundStatus::Failed)
+        .map(|r| r.amount)
+        .sum();
+    let mut refundable = ((payment.amount - already_refunded) * 100.0).round() / 100.0;
+    // Only what went through the gateway can go back to the card
+    if !payload.to_wallet {
+        refundable = refundable.min(payment.card_amount());
+    }
+
+    let amount = payload.amount.unwrap_or(refundable);
+    if amount <= 0.0 || amount > refundable + 0.005 {
+        return Ok(HttpResponse::BadRequest().json(ApiResponseError {
+            message: "Invalid refund amount".to_string(),
+            details: Some(format!("Refundable balance is {:.2}", refundable)),
+        }));
+    }
+
+    let refund = match db.create_refund(&payment, amount, payload.reason.clone()).await {
+        Ok(r) => r,
+        Err(e) if e.contains("exceeds the refundable balance") => return Ok(HttpResponse::BadRequest().json(ApiResponseError {
+            message: "Invalid refund amount".to_string(),
+            details: Some("Another refund of this payment was made meanwhile".to_string()),
+        })),
+        Err(e) => return Ok(HttpResponse::InternalServerError().json(ApiResponseError {
+            message: "Error creating refund record".to_string(),
+            details: Some(e),
+        })),
+    };
+
+    let gateway_reference = match gateway_reference {
+        Some(reference) => reference,
+        None => return Ok(refund_to_wallet(&db, &payment, &refund.refund_transaction_id, amount).await),
+    };
+
+    match gateway.refund(&gateway_reference, amount, &payment.currency, &refund.refund_transaction_id).await {
+        Ok(transaction) => {
+            let code = transaction.code;
+            let status = transaction.status.refund_status();
+
+            let _ = db.update_refund_status(&refund.refund_transaction_id, &status, Some(code.clone()), transaction.gateway_reference).await;
+            if status == RefundStatus::Completed {
+                let _ = sync_payment_refund_status(&db, &payment.merchant_transaction_id).await;
+            }
+
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "refund_transaction_id": refund.refund_transaction_id,
+                "merchant_transaction_id": payment.merchant_transaction_id,
+                "amount": amount,
+                "status": format!("{:?}", status),
+                "result_code": code
+            })))
+        }
+        Err(e) => {
+            let _ = db.update_refund_status(&refund.refund_transaction_id, &RefundStatus::Failed, None, None).await;
+            Ok(HttpResponse::BadGateway().json(ApiResponseError {
+                message: format!("Failed to process refund with {}", gateway.name()),
+                details: Some(e.to_string()),
+            }))
+        }
+    }
+}
+
+/// Settles a refund by crediting the payer's wallet.
+async fn refund_to_wallet(db: &DatabaseService, payment: &Payment, refund_transaction_id: &str, amount: f64) -> HttpResponse {
+    if let Err(e) = db.credit_wallet(&payment.user_id, &payment.currency, amount, WalletEntrySource::Refund, refund_transaction_id).await {
+        let _ = db.update_refund_status(refund_transaction_id, &RefundStatus::Failed, None, None).await;
+        return HttpResponse::InternalServerError().json(ApiResponseError {
+            message: "Failed to credit wallet".to_string(),
+            details: Some(e),
+        });
+    }
+
+    let _ = db.update_refund_status(refund_transaction_id, &RefundStatus::Completed, None, None).await;
+    let _ = sync_payment_refund_status(db, &payment.merchant_transaction_id).await;
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "refund_transaction_id": refund_transaction_id,
+        "merchant_transaction_id": payment.merchant_transaction_id,
+        "amount": amount,
+        "status": format!("{:?}", RefundStatus::Completed),
+        "to_wallet": true
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/payments/{payment_id}/refunds",
+    tag = "payments",
+    params(("payment_id" = String, Path, description = "Payment id")),
+    responses(
+        (status = 200, description = "Refunds of the payment", body = [Refund]),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/{payment_id}/refunds")]
+pub async fn get_payment_refunds(
+    _admin: TenantAdminAuth,
+    db: Data<DatabaseService>,
+    payment_id: RecordPath<Payment>,
+) -> Result<HttpResponse> {
+    let payment_id = payment_id.into_key();
+
+    match find_payment(&db, &payment_id).await {
+        Some(payment) => Ok(HttpResponse::Ok().json(db.get_refunds_for_payment(&payment.merchant_transaction_id).await)),
+        None => Ok(HttpResponse::NotFound().json(ApiResponseError {
+            message: "Payment not found".to_string(),
+            details: Some(payment_id),
+        })),
+    }
+}
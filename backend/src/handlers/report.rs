@@ -0,0 +1,91 @@
+use actix_web::{HttpResponse, Result, get};
+use actix_web::web::{Data, Query};
+use chrono::{NaiveDate, Utc};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use tracing::error;
+use crate::extractors::AdminAuth;
+use crate::models::export::ExportRun;
+use crate::services::database::DatabaseService;
+use crate::services::fx::FxService;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DailyReportQuery {
+    /// UTC date as YYYY-MM-DD; defaults to today.
+    pub date: Option<NaiveDate>,
+}
+
+/// Same totals as the end-of-day operator email.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/reports/daily",
+    tag = "admin",
+    params(DailyReportQuery),
+    responses(
+        (status = 200, description = "The `summary` (see `DailySummary`) and `net_revenue`"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/reports/daily")]
+pub async fn get_daily_report(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    fx: Data<FxService>,
+    query: Query<DailyReportQuery>,
+) -> Result<HttpResponse> {
+    let date = query.date.unwrap_or_else(|| Utc::now().date_naive());
+
+    match db.get_daily_summary(date, &fx).await {
+        Ok(summary) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "summary": summary,
+            "net_revenue": summary.net_revenue()
+        }))),
+        Err(e) => {
+            error!("Error building daily report for {}: {}", date, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to build daily report"
+            })))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportRunQuery {
+    pub limit: Option<u32>,
+}
+
+/// Recent data-lake export runs, newest first, with the files each wrote.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/reports/exports",
+    tag = "admin",
+    params(ExportRunQuery),
+    responses(
+        (status = 200, description = "Export runs", body = [ExportRun]),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/reports/exports")]
+pub async fn list_export_runs(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    query: Query<ExportRunQuery>,
+) -> Result<HttpResponse> {
+    let limit = query.limit.unwrap_or(30).clamp(1, 365);
+
+    match db.list_export_runs(limit).await {
+        Ok(runs) => Ok(HttpResponse::Ok().json(runs)),
+        Err(e) => {
+            error!("Error listing export runs: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to list export runs"
+            })))
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use actix_web::{HttpResponse, Result, post};
+use actix_web::web::Data;
+use crate::config::AppConfig;
+use crate::extractors::{AdminAuth, ValidatedJson};
+use crate::models::scenario::ScenarioDto;
+use crate::models::validation::ValidationErrorResponse;
+use crate::services::database::DatabaseService;
+use crate::services::email::EmailService;
+use crate::services::scenario;
+
+/// Runs a scripted QA scenario, e.g. initiate a payment, deliver its success
+/// webhook after 5s and a duplicate after 10s, then a refund webhook, and
+/// reports how each step was handled. Steps due at the same `at_ms` run
+/// concurrently. The request waits for the last step. Only available with
+/// `SANDBOX_MODE=true`.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/sandbox/scenarios",
+    tag = "admin",
+    request_body = ScenarioDto,
+    responses(
+        (status = 200, description = "Outcome of every step", body = ScenarioReport),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 403, description = "Not allowed"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/sandbox/scenarios")]
+pub async fn run_scenario(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    config: Data<AppConfig>,
+    email: Data<EmailService>,
+    payload: ValidatedJson<ScenarioDto>,
+) -> Result<HttpResponse> {
+    if !config.sandbox_mode {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Scenarios are only available with SANDBOX_MODE=true"
+        })));
+    }
+
+    let dto = payload.into_inner();
+    let report = scenario::run_scenario(
+        db.get_ref().clone(),
+        email.into_inner(),
+        config.into_inner(),
+        dto,
+    ).await;
+    Ok(HttpResponse::Ok().json(report))
+}
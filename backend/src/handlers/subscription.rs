@@ -1,80 +1,349 @@
-use actix_web::{HttpResponse, Result, get, post};
-use actix_web::web::{Data, Json, Path};
+use actix_web::{HttpRequest, HttpResponse, Result, get, post};
+use actix_web::web::{Data, Json, Query};
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 use crate::services::database::DatabaseService;
-use crate::models::subscription::CreateSubscriptionDto;
+use crate::services::email::{EmailEvent, EmailService};
+use crate::services::formatting::Formatting;
+use crate::services::i18n;
+use crate::services::gateway::{ChargeStatus, PaymentGateway};
+use crate::services::jobs;
+use crate::services::ozow::OzowPaymentService;
+use crate::services::rate_limit::{RateLimitScope, RateLimiter};
+use crate::services::storage::{Storage, SubscriptionRepo};
+use crate::config::AppConfig;
+use crate::extractors::{CurrentUser, RecordPath, ValidatedJson};
+use crate::handlers::payment::{enforce_rate_limit, gateway_for_method, start_checkout};
+use crate::models::job::JobPayload;
+use crate::models::payment::{CreatePaymentDto, PaymentMethod, PaymentStatus};
+use crate::models::subscription::{
+    default_seat_count, validate_seat_count, AccessLevel, CancelAt, CancelSubscriptionDto, CreateSubscriptionDto,
+    RestartSubscriptionDto, Subscription, SubscriptionStatus,
+};
+use crate::models::validation::{FieldErrors, Validate, ValidationErrorResponse};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct CreateSubscriptionRequest {
     pub user_id: String,
-    pub plan_name: String,
-    pub price: f64,
+    pub plan_id: String,
+    #[serde(default = "default_seat_count")]
+    pub seat_count: u32,
+}
+
+impl Validate for CreateSubscriptionRequest {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        errors.check("seat_count", validate_seat_count(self.seat_count));
+        errors.into_result()
+    }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct SubscriptionResponse {
     pub id: String,
     pub user_id: String,
     pub plan_name: String,
+    /// Per seat.
     pub price: f64,
+    pub seat_count: u32,
+    /// `price` for every seat; what each period is billed.
+    pub total_price: f64,
+    pub currency: String,
     pub status: String,
+    /// Paid plan, free tier after a downgrade, or nothing.
+    pub access: AccessLevel,
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    /// Day of the month monthly, quarterly and annual plans renew on (the
+    /// last day of shorter months); `None` for plans billed every so many days.
+    pub billing_anchor_day: Option<u32>,
+    /// Renews at the end of the period; false once a cancellation is
+    /// scheduled or has taken effect.
+    pub auto_renew: bool,
+    /// Still active, but ends at `cancellation_effective_at` instead of
+    /// renewing. Can be undone with `reactivate` until then.
+    pub cancel_at_period_end: bool,
+    /// When the subscription was cancelled.
+    pub cancelled_at: Option<String>,
+    /// When the cancellation takes (or took) effect.
+    pub cancellation_effective_at: Option<String>,
+    pub cancellation_reason: Option<String>,
+    pub paused_at: Option<String>,
+    /// The next renewal extends the period without a charge.
+    pub skip_next_renewal: bool,
+    /// Why the subscription has its current status, e.g. `paused_by_user`
+    /// or `payment_failed_insufficient_funds`.
+    pub state_reason: Option<String>,
+    /// Plan whose features a downgraded subscription still grants; `None`
+    /// means the free tier.
+    pub fallback_plan_id: Option<String>,
+    /// Why the next renewal is likely to fail, e.g. `card_expiring`.
+    pub at_risk_reason: Option<String>,
+    pub display: SubscriptionDisplay,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SubscriptionDisplay {
+    pub locale: String,
+    pub price: String,
+    pub total_price: String,
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    /// "Cancels on …", set while a cancellation is scheduled.
+    pub cancels_on: Option<String>,
+}
+
+impl SubscriptionResponse {
+    pub fn from_subscription(subscription: Subscription, fmt: &Formatting) -> Self {
+        let display = SubscriptionDisplay {
+            locale: fmt.locale.tag().to_string(),
+            price: fmt.amount(subscription.price, &subscription.currency),
+            total_price: fmt.amount(subscription.total_price(), &subscription.currency),
+            start_date: subscription.start_date.as_ref().map(|d| fmt.date(d)),
+            end_date: subscription.end_date.as_ref().map(|d| fmt.date(d)),
+            cancels_on: subscription.cancels_at()
+                .filter(|_| subscription.cancel_at_period_end)
+                .map(|d| i18n::translate_with(fmt.locale, "Cancels on {{date}}", &[("date", fmt.date(&d))])),
+        };
+        let access = subscription.access_level();
+        let total_price = subscription.total_price();
+        let auto_renew = !subscription.cancel_at_period_end
+            && !matches!(subscription.status, SubscriptionStatus::Cancelled | SubscriptionStatus::Expired);
+        let cancellation_effective_at = subscription.cancels_at().map(|d| d.to_rfc3339());
+
+        Self {
+            id: subscription.id,
+            user_id: subscription.user_id,
+            plan_name: subscription.plan_name,
+            price: subscription.price,
+            seat_count: subscription.seat_count,
+            total_price,
+            currency: subscription.currency,
+            status: format!("{:?}", subscription.status),
+            access,
+            start_date: subscription.start_date.map(|d| d.to_rfc3339()),
+            end_date: subscription.end_date.map(|d| d.to_rfc3339()),
+            billing_anchor_day: subscription.billing_anchor_day,
+            auto_renew,
+            cancel_at_period_end: subscription.cancel_at_period_end,
+            cancelled_at: subscription.cancelled_at.map(|d| d.to_rfc3339()),
+            cancellation_effective_at,
+            cancellation_reason: subscription.cancellation_reason,
+            paused_at: subscription.paused_at.map(|d| d.to_rfc3339()),
+            skip_next_renewal: subscription.skip_next_renewal,
+            state_reason: subscription.state_reason,
+            fallback_plan_id: subscription.fallback_plan_id,
+            at_risk_reason: subscription.at_risk_reason,
+            display,
+        }
+    }
 }
 
+/// Upcoming invoices listed when `count` isn't given, and the most listed.
+const DEFAULT_UPCOMING_INVOICES: usize = 3;
+const MAX_UPCOMING_INVOICES: usize = 12;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UpcomingInvoicesQuery {
+    /// Up to 12; 3 by default.
+    pub count: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UpcomingInvoice {
+    /// When the renewal is charged: the start of the period it pays for.
+    pub billed_on: String,
+    pub period_start: String,
+    pub period_end: String,
+    /// Every seat at the current price, or that of a scheduled plan change,
+    /// VAT included; metered usage is added when the invoice is issued.
+    pub amount: f64,
+    pub currency: String,
+    /// The user skipped this renewal, so nothing is charged.
+    pub skipped: bool,
+    pub display: UpcomingInvoiceDisplay,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UpcomingInvoiceDisplay {
+    pub billed_on: String,
+    pub amount: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscriptions/create",
+    tag = "subscriptions",
+    request_body = CreateSubscriptionRequest,
+    responses(
+        (status = 200, description = "Subscription created, pending its first payment", body = SubscriptionResponse),
+        (status = 400, description = "Invalid request"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+    )
+)]
 #[post("/create")]
 pub async fn create_subscription(
+    req: HttpRequest,
     db: Data<DatabaseService>,
-    payload: Json<CreateSubscriptionRequest>,
+    config: Data<AppConfig>,
+    gateway: Data<dyn PaymentGateway>,
+    payload: ValidatedJson<CreateSubscriptionRequest>,
 ) -> Result<HttpResponse> {
+    // Price and terms come from the plan, never from the client
+    let plan = match db.get_plan(&payload.plan_id).await {
+        Some(plan) => plan,
+        None => return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Unknown plan: {}", payload.plan_id)
+        }))),
+    };
+
+    if !gateway.supports_currency(&plan.currency) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Plan currency {} is not supported by the payment gateway", plan.currency)
+        })));
+    }
+
     let dto = CreateSubscriptionDto {
         user_id: payload.user_id.clone(),
-        plan_name: payload.plan_name.clone(),
-        price: payload.price,
+        plan_id: Some(plan.id.clone()),
+        plan_name: plan.name.clone(),
+        // Subscriptions store the amount actually charged, VAT included
+        price: plan.tax(config.vat_rate_percent).gross,
+        currency: plan.currency.clone(),
         payment_method: None, // Will be set during payment
+        grace_period_days: plan.grace_period_days.unwrap_or(config.grace_period_days),
+        billing_period_days: plan.period_days(),
+        billing_period_months: plan.period_months(),
+        suspension_policy: plan.suspension_policy.unwrap_or(config.suspension_policy),
+        usage_pricing: plan.usage_pricing.clone(),
+        seat_count: payload.seat_count,
     };
 
     match db.create_subscription(dto).await {
-        Ok(subscription) => Ok(HttpResponse::Ok().json(SubscriptionResponse {
-            id: subscription.id,
-            user_id: subscription.user_id,
-            plan_name: subscription.plan_name,
-            price: subscription.price,
-            status: format!("{:?}", subscription.status),
-        })),
+        Ok(subscription) => Ok(HttpResponse::Ok().json(
+            SubscriptionResponse::from_subscription(subscription, &Formatting::from_request(&req)),
+        )),
         Err(e) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
             "error": e
         }))),
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscriptions/{subscription_id}",
+    tag = "subscriptions",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    responses(
+        (status = 200, description = "The subscription", body = SubscriptionResponse),
+        (status = 400, description = "Invalid request"),
+        (status = 404, description = "Not found"),
+    )
+)]
 #[get("/{subscription_id}")]
 pub async fn get_subscription(
-    db: Data<DatabaseService>,
-    path: Path<String>,
+    req: HttpRequest,
+    db: Data<dyn Storage>,
+    subscription_id: RecordPath<Subscription>,
 ) -> Result<HttpResponse> {
-    let subscription_id = path.into_inner();
-    
-    match db.get_subscription(&subscription_id).await {
-        Some(subscription) => Ok(HttpResponse::Ok().json(SubscriptionResponse {
-            id: subscription.id,
-            user_id: subscription.user_id,
-            plan_name: subscription.plan_name,
-            price: subscription.price,
-            status: format!("{:?}", subscription.status),
-        })),
+    match db.get_subscription(subscription_id.key()).await {
+        Some(subscription) => Ok(HttpResponse::Ok().json(
+            SubscriptionResponse::from_subscription(subscription, &Formatting::from_request(&req)),
+        )),
         None => Ok(HttpResponse::NotFound().json(serde_json::json!({
             "error": "Subscription not found"
         }))),
     }
 }
 
+/// The caller's next renewals, with the period each pays for. Monthly,
+/// quarterly and annual plans renew on the subscription's anchor day (the
+/// last day of shorter months). Empty unless the subscription will renew.
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscriptions/{subscription_id}/upcoming-invoices",
+    tag = "subscriptions",
+    params(
+        ("subscription_id" = String, Path, description = "Subscription id"),
+        UpcomingInvoicesQuery,
+    ),
+    responses(
+        (status = 200, description = "Upcoming invoices, soonest first", body = [UpcomingInvoice]),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+    ),
+    security(("user_id" = []))
+)]
+#[get("/{subscription_id}/upcoming-invoices")]
+pub async fn get_upcoming_invoices(
+    req: HttpRequest,
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    subscription_id: RecordPath<Subscription>,
+    query: Query<UpcomingInvoicesQuery>,
+) -> Result<HttpResponse> {
+    let subscription = match load_owned_subscription(&db, &user, subscription_id.key()).await {
+        Some(s) => s,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Subscription not found"
+        }))),
+    };
+
+    let renews = matches!(subscription.status, SubscriptionStatus::Active | SubscriptionStatus::Grace)
+        && !subscription.cancel_at_period_end;
+    if !renews {
+        return Ok(HttpResponse::Ok().json(Vec::<UpcomingInvoice>::new()));
+    }
+
+    // A scheduled plan change applies from the first renewal that is charged
+    let price = match db.get_scheduled_plan_change(&subscription.id).await {
+        Some(change) => ((change.to_price * subscription.seat_count as f64) * 100.0).round() / 100.0,
+        None => subscription.total_price(),
+    };
+    let fmt = Formatting::from_request(&req);
+    let count = query.count.unwrap_or(DEFAULT_UPCOMING_INVOICES).clamp(1, MAX_UPCOMING_INVOICES);
+    let invoices: Vec<UpcomingInvoice> = subscription.upcoming_periods(count)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (start, end))| {
+            let skipped = i == 0 && subscription.skip_next_renewal;
+            let amount = if skipped { 0.0 } else { price };
+            UpcomingInvoice {
+                billed_on: start.to_rfc3339(),
+                period_start: start.to_rfc3339(),
+                period_end: end.to_rfc3339(),
+                amount,
+                currency: subscription.currency.clone(),
+                skipped,
+                display: UpcomingInvoiceDisplay {
+                    billed_on: fmt.date(&start),
+                    amount: fmt.amount(amount, &subscription.currency),
+                },
+            }
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(invoices))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscriptions/{subscription_id}/renew",
+    tag = "subscriptions",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    responses(
+        (status = 200, description = "Renewal result with a `message`"),
+        (status = 400, description = "Invalid request"),
+    )
+)]
 #[post("/{subscription_id}/renew")]
 pub async fn renew_subscription(
-    db: Data<DatabaseService>,
-    path: Path<String>,
+    db: Data<dyn Storage>,
+    subscription_id: RecordPath<Subscription>,
 ) -> Result<HttpResponse> {
-    let subscription_id = path.into_inner();
-    
-    match db.activate_subscription(&subscription_id).await {
+    match db.activate_subscription(subscription_id.key()).await {
         Ok(_) => Ok(HttpResponse::Ok().json(serde_json::json!({
             "message": "Subscription renewed successfully",
             "status": "Active"
@@ -84,3 +353,418 @@ pub async fn renew_subscription(
         }))),
     }
 }
+
+/// Cancels the caller's subscription, by default at the end of the period
+/// already paid for: access continues until then and the subscription can be
+/// reactivated. Cancelling again can move a scheduled cancellation to now.
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscriptions/{subscription_id}/cancel",
+    tag = "subscriptions",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    request_body = CancelSubscriptionDto,
+    responses(
+        (status = 200, description = "Subscription cancelled", body = SubscriptionResponse),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Conflicts with the current state"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[post("/{subscription_id}/cancel")]
+pub async fn cancel_subscription(
+    req: HttpRequest,
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    email: Data<EmailService>,
+    subscription_id: RecordPath<Subscription>,
+    payload: ValidatedJson<CancelSubscriptionDto>,
+) -> Result<HttpResponse> {
+    let dto = payload.into_inner();
+
+    let subscription = match load_owned_subscription(&db, &user, subscription_id.key()).await {
+        Some(s) => s,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Subscription not found"
+        }))),
+    };
+
+    if matches!(subscription.status, SubscriptionStatus::Cancelled | SubscriptionStatus::Expired) {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Subscription is already cancelled or expired"
+        })));
+    }
+
+    let reason = dto.reason.map(|r| r.trim().to_string()).filter(|r| !r.is_empty());
+
+    let at_period_end = dto.when == CancelAt::PeriodEnd;
+    match db.cancel_subscription(&subscription, at_period_end, reason).await {
+        Ok(cancelled) => {
+            if let Some(ends_at) = cancelled.cancels_at() {
+                email.notify_user(&db, &cancelled.user_id, EmailEvent::SubscriptionCancelled {
+                    plan: cancelled.plan_name.clone(),
+                    ends_at,
+                }).await;
+            }
+            Ok(HttpResponse::Ok().json(
+                SubscriptionResponse::from_subscription(cancelled, &Formatting::from_request(&req)),
+            ))
+        }
+        Err(e) if e.contains("already cancelled") => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": e
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to cancel subscription",
+            "details": e
+        }))),
+    }
+}
+
+/// Withdraws a cancellation scheduled for the end of the period, before the
+/// period ends. The subscription renews as if it had never been cancelled.
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscriptions/{subscription_id}/reactivate",
+    tag = "subscriptions",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    responses(
+        (status = 200, description = "Subscription renews again", body = SubscriptionResponse),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Conflicts with the current state"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[post("/{subscription_id}/reactivate")]
+pub async fn reactivate_subscription(
+    req: HttpRequest,
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    subscription_id: RecordPath<Subscription>,
+) -> Result<HttpResponse> {
+    let subscription = match load_owned_subscription(&db, &user, subscription_id.key()).await {
+        Some(s) => s,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Subscription not found"
+        }))),
+    };
+
+    let in_paid_period = subscription.end_date.is_some_and(|end| end > Utc::now());
+    if subscription.status != SubscriptionStatus::Active || !subscription.cancel_at_period_end || !in_paid_period {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Only a cancellation scheduled for the end of the current period can be undone"
+        })));
+    }
+
+    match db.reactivate_subscription(&subscription).await {
+        Ok(reactivated) => Ok(HttpResponse::Ok().json(
+            SubscriptionResponse::from_subscription(reactivated, &Formatting::from_request(&req)),
+        )),
+        Err(e) if e.contains("no cancellation") => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Only a cancellation scheduled for the end of the current period can be undone"
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to reactivate subscription",
+            "details": e
+        }))),
+    }
+}
+
+/// Starts a fresh billing period for the caller's expired or cancelled
+/// subscription, at its last price less any win-back coupon. Each coupon
+/// works once per user; it is given back if the payment can't be started or
+/// is declined straight away. The saved card is charged when there is one;
+/// otherwise (or with `new_card`) a checkout is started, and paying it
+/// activates the subscription. The restart is recorded in the audit log.
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscriptions/{subscription_id}/restart",
+    tag = "subscriptions",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    request_body(content = Option<RestartSubscriptionDto>, description = "Omit to charge the saved card at the full price"),
+    responses(
+        (status = 200, description = "Saved card charged and subscription active, or checkout started"),
+        (status = 202, description = "Saved card charge pending; the subscription activates once it succeeds"),
+        (status = 400, description = "Invalid request or unknown coupon"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 402, description = "Saved card declined"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Conflicts with the current state"),
+        (status = 429, description = "Too many requests"),
+        (status = 500, description = "Internal error"),
+        (status = 502, description = "Gateway error"),
+    ),
+    security(("user_id" = []))
+)]
+#[post("/{subscription_id}/restart")]
+#[allow(clippy::too_many_arguments)]
+pub async fn restart_subscription(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    gateway: Data<dyn PaymentGateway>,
+    ozow: Option<Data<OzowPaymentService>>,
+    config: Data<AppConfig>,
+    limiter: Data<RateLimiter>,
+    subscription_id: RecordPath<Subscription>,
+    payload: Option<Json<RestartSubscriptionDto>>,
+) -> Result<HttpResponse> {
+    if let Some(limited) = enforce_rate_limit(&limiter, RateLimitScope::PaymentInitiation, &user.user_id).await {
+        return Ok(limited);
+    }
+    let dto = payload.map(Json::into_inner).unwrap_or_default();
+
+    let subscription = match load_owned_subscription(&db, &user, subscription_id.key()).await {
+        Some(s) => s,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Subscription not found"
+        }))),
+    };
+    if !matches!(subscription.status, SubscriptionStatus::Expired | SubscriptionStatus::Cancelled) {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Only an expired or cancelled subscription can be restarted"
+        })));
+    }
+
+    let coupon = match dto.coupon.as_deref().map(str::trim).filter(|c| !c.is_empty()) {
+        Some(code) => match config.winback_discount_percent(code) {
+            Some(percent) => Some((code.to_uppercase(), percent)),
+            None => return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Unknown coupon"
+            }))),
+        },
+        None => None,
+    };
+    let discount = coupon.as_ref().map_or(0, |(_, percent)| *percent);
+    let amount = (subscription.total_price() * (100 - discount) as f64).round() / 100.0;
+
+    let method = dto.payment_method.clone().unwrap_or(PaymentMethod::Card);
+    let token = if method == PaymentMethod::Card && !dto.new_card {
+        db.get_recurring_token_by_user(&subscription.user_id).await
+    } else {
+        None
+    };
+    let gateway = match token {
+        Some(_) => gateway.get_ref(),
+        None => gateway_for_method(&gateway, &ozow, Some(&method)),
+    };
+    if !gateway.supports_currency(&subscription.currency) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("The payment gateway does not accept {}", subscription.currency)
+        })));
+    }
+
+    if let Some((code, _)) = &coupon {
+        match db.redeem_winback_coupon(&subscription.user_id, code, &subscription.id).await {
+            Ok(true) => {}
+            Ok(false) => return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": "This coupon has already been used"
+            }))),
+            Err(e) => return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to apply coupon",
+                "details": e
+            }))),
+        }
+    }
+
+    db.record_subscription_restart(
+        &subscription,
+        amount,
+        coupon.as_ref().map(|(code, percent)| (code.as_str(), *percent)),
+        token.is_some(),
+    ).await;
+
+    let payment_dto = CreatePaymentDto {
+        user_id: subscription.user_id.clone(),
+        subscription_id: subscription.id.clone(),
+        amount,
+        currency: Some(subscription.currency.clone()),
+        payment_method: Some(method),
+        region: dto.region,
+        installments: None,
+    };
+    let Some(token) = token else {
+        let response = start_checkout(&db, gateway, &config, payment_dto, subscription.plan_id.as_deref(), false).await;
+        if !response.status().is_success() {
+            release_coupon(&db, &subscription.user_id, &coupon).await;
+        }
+        return Ok(response);
+    };
+
+    let payment = match db.create_payment(payment_dto, config.vat_rate_percent, gateway.name()).await {
+        Ok(payment) => payment,
+        Err(e) => {
+            release_coupon(&db, &subscription.user_id, &coupon).await;
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to record payment",
+                "details": e
+            })));
+        }
+    };
+    let merchant_transaction_id = payment.merchant_transaction_id.clone();
+    let descriptor = if gateway.supports_statement_descriptor() {
+        db.statement_descriptor(subscription.plan_id.as_deref(), config.statement_descriptor.as_deref()).await
+    } else {
+        None
+    };
+    let receipt = |status: &str| serde_json::json!({
+        "gateway": gateway.name(),
+        "checkoutId": null,
+        "merchantTransactionId": merchant_transaction_id,
+        "amount": amount,
+        "status": status,
+    });
+
+    match gateway.charge_token(&token, amount, &subscription.currency, &merchant_transaction_id, descriptor.as_deref()).await {
+        Ok(transaction) if transaction.status == ChargeStatus::Succeeded => {
+            if let Err(e) = db.complete_payment_and_activate(&payment, Utc::now(), transaction.gateway_reference.as_deref(), None).await {
+                return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": "Charged but the restart could not be recorded",
+                    "details": e
+                })));
+            }
+            jobs::enqueue(&db, JobPayload::IssueInvoice { merchant_transaction_id: merchant_transaction_id.clone() }).await;
+            jobs::enqueue(&db, JobPayload::PaymentSucceededEmail { merchant_transaction_id: merchant_transaction_id.clone() }).await;
+            Ok(HttpResponse::Ok().json(receipt("Completed")))
+        }
+        // The webhook completes the payment and activates the subscription
+        Ok(transaction) if transaction.status.is_pending() => Ok(HttpResponse::Accepted().json(receipt("Pending"))),
+        Ok(transaction) => {
+            let _ = db.update_payment_status(&merchant_transaction_id, &PaymentStatus::Failed).await;
+            release_coupon(&db, &subscription.user_id, &coupon).await;
+            Ok(HttpResponse::PaymentRequired().json(serde_json::json!({
+                "error": "Payment for the restart was declined",
+                "details": transaction.code,
+                "merchantTransactionId": merchant_transaction_id,
+            })))
+        }
+        Err(e) => {
+            let _ = db.update_payment_status(&merchant_transaction_id, &PaymentStatus::Failed).await;
+            release_coupon(&db, &subscription.user_id, &coupon).await;
+            Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                "error": "Payment for the restart could not be processed",
+                "details": e.to_string()
+            })))
+        }
+    }
+}
+
+/// Gives back the restart's coupon, if it had one, when no payment went ahead.
+async fn release_coupon(db: &DatabaseService, user_id: &str, coupon: &Option<(String, u32)>) {
+    if let Some((code, _)) = coupon {
+        db.release_winback_coupon(user_id, code).await;
+    }
+}
+
+pub(crate) async fn load_owned_subscription(db: &DatabaseService, user: &CurrentUser, subscription_id: &str) -> Option<Subscription> {
+    db.get_subscription(subscription_id)
+        .await
+        .filter(|s| s.user_id == user.user_id)
+}
+
+/// Puts the caller's subscription on hold. Only a subscription inside a paid
+/// period can be paused; the unused part of the period is kept for resume.
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscriptions/{subscription_id}/pause",
+    tag = "subscriptions",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    responses(
+        (status = 200, description = "Subscription paused", body = SubscriptionResponse),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Conflicts with the current state"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[post("/{subscription_id}/pause")]
+pub async fn pause_subscription(
+    req: HttpRequest,
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    subscription_id: RecordPath<Subscription>,
+) -> Result<HttpResponse> {
+    let subscription = match load_owned_subscription(&db, &user, subscription_id.key()).await {
+        Some(s) => s,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Subscription not found"
+        }))),
+    };
+
+    let in_paid_period = subscription.end_date.is_some_and(|end| end > Utc::now());
+    if subscription.status != SubscriptionStatus::Active || !in_paid_period || subscription.next_renewal_attempt_at.is_some() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Only an active, paid-up subscription can be paused"
+        })));
+    }
+
+    match db.pause_subscription(&subscription).await {
+        Ok(paused) => Ok(HttpResponse::Ok().json(
+            SubscriptionResponse::from_subscription(paused, &Formatting::from_request(&req)),
+        )),
+        Err(e) if e.contains("not active") => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": e
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to pause subscription",
+            "details": e
+        }))),
+    }
+}
+
+/// Reactivates a paused subscription with its end date moved back by the
+/// time spent paused.
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscriptions/{subscription_id}/resume",
+    tag = "subscriptions",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    responses(
+        (status = 200, description = "Subscription resumed", body = SubscriptionResponse),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Conflicts with the current state"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[post("/{subscription_id}/resume")]
+pub async fn resume_subscription(
+    req: HttpRequest,
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    subscription_id: RecordPath<Subscription>,
+) -> Result<HttpResponse> {
+    let subscription = match load_owned_subscription(&db, &user, subscription_id.key()).await {
+        Some(s) => s,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Subscription not found"
+        }))),
+    };
+
+    if subscription.status != SubscriptionStatus::Paused {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Subscription is not paused"
+        })));
+    }
+
+    match db.resume_subscription(&subscription).await {
+        Ok(resumed) => Ok(HttpResponse::Ok().json(
+            SubscriptionResponse::from_subscription(resumed, &Formatting::from_request(&req)),
+        )),
+        Err(e) if e.contains("not paused") => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": e
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to resume subscription",
+            "details": e
+        }))),
+    }
+}
@@ -0,0 +1,446 @@
+use actix_web::{HttpResponse, Result, delete, get, post, put};
+use actix_web::web::{Data, Query};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{ToSchema, IntoParams};
+use tracing::error;
+use crate::extractors::{AdminAuth, RecordPath, ValidatedJson};
+use crate::models::subscription::Subscription;
+use crate::models::support::{normalize_tags, CreateNoteDto, NoteTarget, SetTagsDto, SupportNote, UpdateNoteDto};
+use crate::models::ticket::TicketFilter;
+use crate::models::user::User;
+use crate::models::validation::ValidationErrorResponse;
+use crate::services::database::DatabaseService;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SupportSearchQuery {
+    /// Text to find in note bodies.
+    pub q: Option<String>,
+    /// Tag to find on users and subscriptions.
+    pub tag: Option<String>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TimelineQuery {
+    pub limit: Option<u32>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TimelineItem {
+    /// `payment`, `activity`, `note` or `ticket`.
+    pub source: &'static str,
+    pub kind: String,
+    pub description: String,
+    pub reference_id: Option<String>,
+    pub author: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+fn server_error(message: &str, details: String) -> HttpResponse {
+    error!("{}: {}", message, details);
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "error": message,
+        "details": details
+    }))
+}
+
+fn not_found(what: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "error": format!("{} not found", what)
+    }))
+}
+
+// ---------------------
+// Notes
+// ---------------------
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/users/{user_id}/notes",
+    tag = "admin",
+    params(("user_id" = String, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Notes on the user", body = [SupportNote]),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/users/{user_id}/notes")]
+pub async fn get_user_notes(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    user_id: RecordPath<User>,
+) -> Result<HttpResponse> {
+    match db.get_support_notes(NoteTarget::User, user_id.key()).await {
+        Ok(notes) => Ok(HttpResponse::Ok().json(notes)),
+        Err(e) => Ok(server_error("Failed to load notes", e)),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/users/{user_id}/notes",
+    tag = "admin",
+    params(("user_id" = String, Path, description = "User id")),
+    request_body = CreateNoteDto,
+    responses(
+        (status = 201, description = "Note added", body = SupportNote),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/users/{user_id}/notes")]
+pub async fn add_user_note(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    user_id: RecordPath<User>,
+    payload: ValidatedJson<CreateNoteDto>,
+) -> Result<HttpResponse> {
+    let user_id = user_id.into_key();
+    let dto = payload.into_inner();
+
+    if db.get_user(&user_id).await.is_none() {
+        return Ok(not_found("User"));
+    }
+
+    match db.create_support_note(NoteTarget::User, &user_id, &user_id, &dto.body, dto.author).await {
+        Ok(note) => Ok(HttpResponse::Created().json(note)),
+        Err(e) => Ok(server_error("Failed to add note", e)),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/subscriptions/{subscription_id}/notes",
+    tag = "admin",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    responses(
+        (status = 200, description = "Notes on the subscription", body = [SupportNote]),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/subscriptions/{subscription_id}/notes")]
+pub async fn get_subscription_notes(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    subscription_id: RecordPath<Subscription>,
+) -> Result<HttpResponse> {
+    match db.get_support_notes(NoteTarget::Subscription, subscription_id.key()).await {
+        Ok(notes) => Ok(HttpResponse::Ok().json(notes)),
+        Err(e) => Ok(server_error("Failed to load notes", e)),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/subscriptions/{subscription_id}/notes",
+    tag = "admin",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    request_body = CreateNoteDto,
+    responses(
+        (status = 201, description = "Note added", body = SupportNote),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/subscriptions/{subscription_id}/notes")]
+pub async fn add_subscription_note(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    subscription_id: RecordPath<Subscription>,
+    payload: ValidatedJson<CreateNoteDto>,
+) -> Result<HttpResponse> {
+    let subscription_id = subscription_id.into_key();
+    let dto = payload.into_inner();
+
+    let subscription = match db.get_subscription(&subscription_id).await {
+        Some(sub) => sub,
+        None => return Ok(not_found("Subscription")),
+    };
+
+    match db.create_support_note(NoteTarget::Subscription, &subscription_id, &subscription.user_id, &dto.body, dto.author).await {
+        Ok(note) => Ok(HttpResponse::Created().json(note)),
+        Err(e) => Ok(server_error("Failed to add note", e)),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/notes/{note_id}",
+    tag = "admin",
+    params(("note_id" = String, Path, description = "Support note id")),
+    request_body = UpdateNoteDto,
+    responses(
+        (status = 200, description = "Note updated", body = SupportNote),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+    ),
+    security(("admin_token" = []))
+)]
+#[put("/notes/{note_id}")]
+pub async fn update_note(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    note_id: RecordPath<SupportNote>,
+    payload: ValidatedJson<UpdateNoteDto>,
+) -> Result<HttpResponse> {
+    match db.update_support_note(note_id.key(), &payload.body).await {
+        Ok(note) => Ok(HttpResponse::Ok().json(note)),
+        Err(e) if e.starts_with("Note not found") => Ok(not_found("Note")),
+        Err(e) => Ok(server_error("Failed to update note", e)),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/notes/{note_id}",
+    tag = "admin",
+    params(("note_id" = String, Path, description = "Support note id")),
+    responses(
+        (status = 204, description = "Note deleted"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+    ),
+    security(("admin_token" = []))
+)]
+#[delete("/notes/{note_id}")]
+pub async fn delete_note(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    note_id: RecordPath<SupportNote>,
+) -> Result<HttpResponse> {
+    match db.delete_support_note(note_id.key()).await {
+        Ok(()) => Ok(HttpResponse::NoContent().finish()),
+        Err(e) if e.starts_with("Note not found") => Ok(not_found("Note")),
+        Err(e) => Ok(server_error("Failed to delete note", e)),
+    }
+}
+
+// ---------------------
+// Tags
+// ---------------------
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/users/{user_id}/tags",
+    tag = "admin",
+    params(("user_id" = String, Path, description = "User id")),
+    request_body = SetTagsDto,
+    responses(
+        (status = 200, description = "Tags replaced", body = User),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+    ),
+    security(("admin_token" = []))
+)]
+#[put("/users/{user_id}/tags")]
+pub async fn set_user_tags(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    user_id: RecordPath<User>,
+    payload: ValidatedJson<SetTagsDto>,
+) -> Result<HttpResponse> {
+    let tags = normalize_tags(payload.into_inner().tags);
+
+    match db.set_user_tags(user_id.key(), tags).await {
+        Ok(user) => Ok(HttpResponse::Ok().json(user)),
+        Err(e) if e.starts_with("User not found") => Ok(not_found("User")),
+        Err(e) => Ok(server_error("Failed to update tags", e)),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/subscriptions/{subscription_id}/tags",
+    tag = "admin",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    request_body = SetTagsDto,
+    responses(
+        (status = 200, description = "Tags replaced", body = Subscription),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+    ),
+    security(("admin_token" = []))
+)]
+#[put("/subscriptions/{subscription_id}/tags")]
+pub async fn set_subscription_tags(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    subscription_id: RecordPath<Subscription>,
+    payload: ValidatedJson<SetTagsDto>,
+) -> Result<HttpResponse> {
+    let tags = normalize_tags(payload.into_inner().tags);
+
+    match db.set_subscription_tags(subscription_id.key(), tags).await {
+        Ok(subscription) => Ok(HttpResponse::Ok().json(subscription)),
+        Err(e) if e.starts_with("Subscription not found") => Ok(not_found("Subscription")),
+        Err(e) => Ok(server_error("Failed to update tags", e)),
+    }
+}
+
+// ---------------------
+// Search & timeline
+// ---------------------
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/support/search",
+    tag = "admin",
+    params(SupportSearchQuery),
+    responses(
+        (status = 200, description = "Matching `notes`, `users` and `subscriptions`"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/support/search")]
+pub async fn search_support(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    query: Query<SupportSearchQuery>,
+) -> Result<HttpResponse> {
+    let text = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty());
+    let tag = query.tag.as_deref().map(str::trim).filter(|t| !t.is_empty());
+    if text.is_none() && tag.is_none() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Provide q and/or tag"
+        })));
+    }
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+
+    let notes = match text {
+        Some(text) => match db.search_support_notes(text, limit).await {
+            Ok(notes) => notes,
+            Err(e) => return Ok(server_error("Failed to search notes", e)),
+        },
+        None => Vec::new(),
+    };
+
+    let (users, subscriptions) = match tag {
+        Some(tag) => {
+            let users = match db.find_users_by_tag(tag).await {
+                Ok(users) => users,
+                Err(e) => return Ok(server_error("Failed to search users", e)),
+            };
+            let subscriptions = match db.find_subscriptions_by_tag(tag).await {
+                Ok(subs) => subs,
+                Err(e) => return Ok(server_error("Failed to search subscriptions", e)),
+            };
+            (users, subscriptions)
+        }
+        None => (Vec::new(), Vec::new()),
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "notes": notes,
+        "users": users,
+        "subscriptions": subscriptions
+    })))
+}
+
+/// Support view of a user: payments, account activity, internal notes and
+/// tickets (at their last update, with their current status), newest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/users/{user_id}/timeline",
+    tag = "admin",
+    params(("user_id" = String, Path, description = "User id"), TimelineQuery),
+    responses(
+        (status = 200, description = "Payments, activity, notes and tickets, newest first", body = [TimelineItem]),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/users/{user_id}/timeline")]
+pub async fn get_user_timeline(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    user_id: RecordPath<User>,
+    query: Query<TimelineQuery>,
+) -> Result<HttpResponse> {
+    let user_id = user_id.into_key();
+    let limit = query.limit.unwrap_or(100).clamp(1, 500);
+
+    if db.get_user(&user_id).await.is_none() {
+        return Ok(not_found("User"));
+    }
+
+    let mut items: Vec<TimelineItem> = Vec::new();
+
+    match db.get_recent_payments_by_user(&user_id, limit).await {
+        Ok(payments) => items.extend(payments.into_iter().map(|p| TimelineItem {
+            source: "payment",
+            kind: format!("payment_{}", format!("{:?}", p.status).to_lowercase()),
+            description: format!("Payment of {:.2} ({:?})", p.amount, p.status),
+            reference_id: Some(p.merchant_transaction_id),
+            author: None,
+            created_at: p.created_at,
+        })),
+        Err(e) => return Ok(server_error("Failed to load timeline", e)),
+    }
+
+    match db.get_activity_events(&user_id, None, limit).await {
+        Ok(events) => items.extend(events.into_iter().map(|e| TimelineItem {
+            source: "activity",
+            kind: e.kind,
+            description: e.description,
+            reference_id: e.reference_id,
+            author: None,
+            created_at: e.created_at,
+        })),
+        Err(e) => return Ok(server_error("Failed to load timeline", e)),
+    }
+
+    match db.get_support_notes_for_user(&user_id, limit).await {
+        Ok(notes) => items.extend(notes.into_iter().map(|n| TimelineItem {
+            source: "note",
+            kind: format!("{:?}_note", n.target_type).to_lowercase(),
+            description: n.body,
+            reference_id: Some(n.target_id),
+            author: n.author,
+            created_at: n.created_at,
+        })),
+        Err(e) => return Ok(server_error("Failed to load timeline", e)),
+    }
+
+    let tickets = TicketFilter { user_id: Some(user_id.clone()), ..Default::default() };
+    match db.list_tickets(&tickets, limit).await {
+        Ok(tickets) => items.extend(tickets.into_iter().map(|t| TimelineItem {
+            source: "ticket",
+            kind: format!("ticket_{}", t.status.as_str()),
+            description: match t.occurrences {
+                1 => t.subject,
+                n => format!("{} ({} events)", t.subject, n),
+            },
+            reference_id: Some(t.id),
+            author: t.assignee,
+            created_at: t.updated_at,
+        })),
+        Err(e) => return Ok(server_error("Failed to load timeline", e)),
+    }
+
+    items.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    items.truncate(limit as usize);
+
+    Ok(HttpResponse::Ok().json(items))
+}
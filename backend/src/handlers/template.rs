@@ -0,0 +1,163 @@
+use actix_web::{HttpResponse, Result, delete, get, put};
+use actix_web::web::{Data, Path};
+use tracing::error;
+use crate::extractors::{AdminAuth, ValidatedJson};
+use crate::models::template::{MessageTemplate, SetMessageTemplateDto, TemplateChannel, TemplateSummary};
+use crate::services::database::DatabaseService;
+use crate::services::formatting::Locale;
+use crate::services::templates::{self, TemplateSpec, TEMPLATES};
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({ "error": message }))
+}
+
+fn server_error(message: &str, details: String) -> HttpResponse {
+    error!("{}: {}", message, details);
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "error": message,
+    }))
+}
+
+/// The template and canonical locale tag named by a `{channel}/{key}/{locale}` path.
+fn parse_path(channel: &str, key: &str, locale: &str) -> std::result::Result<(&'static TemplateSpec, Locale), HttpResponse> {
+    let channel = TemplateChannel::parse(channel)
+        .ok_or_else(|| bad_request(format!("Unknown channel {}; use email or in_app", channel)))?;
+    let spec = templates::spec(channel, key).ok_or_else(|| {
+        HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("No {} template named {}", channel.as_str(), key)
+        }))
+    })?;
+    let locale = Locale::from_tag(locale)
+        .ok_or_else(|| bad_request(format!("Unsupported locale {}", locale)))?;
+    Ok((spec, locale))
+}
+
+/// Every customisable message with its variables, built-in copy and
+/// translations, and the merchant's saved copy per locale.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/templates",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Templates", body = [TemplateSummary]),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/templates")]
+pub async fn list_templates(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+) -> Result<HttpResponse> {
+    let saved = match db.list_message_templates().await {
+        Ok(saved) => saved,
+        Err(e) => return Ok(server_error("Failed to load message templates", e)),
+    };
+
+    let summaries: Vec<TemplateSummary> = TEMPLATES
+        .iter()
+        .map(|spec| TemplateSummary {
+            channel: spec.channel,
+            key: spec.key.to_string(),
+            variables: spec.variables.iter().map(|v| v.to_string()).collect(),
+            default_body: spec.default_body.to_string(),
+            translations: spec
+                .translations
+                .iter()
+                .map(|(locale, body)| (locale.tag().to_string(), body.to_string()))
+                .collect(),
+            overrides: saved
+                .iter()
+                .filter(|t| t.channel == spec.channel && t.key == spec.key)
+                .cloned()
+                .collect(),
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(summaries))
+}
+
+/// Saves the merchant's copy for a message in one locale. Other locales
+/// without their own copy use the built-in translation, then en-ZA's copy,
+/// then the built-in English text.
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/templates/{channel}/{key}/{locale}",
+    tag = "admin",
+    params(
+        ("channel" = String, Path, description = "`email` or `in_app`"),
+        ("key" = String, Path, description = "Template key, e.g. `renewal_upcoming`"),
+        ("locale" = String, Path, description = "e.g. `en-ZA` or `af-ZA`"),
+    ),
+    request_body = SetMessageTemplateDto,
+    responses(
+        (status = 200, description = "The saved template", body = MessageTemplate),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[put("/templates/{channel}/{key}/{locale}")]
+pub async fn set_template(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    path: Path<(String, String, String)>,
+    payload: ValidatedJson<SetMessageTemplateDto>,
+) -> Result<HttpResponse> {
+    let (channel, key, locale) = path.into_inner();
+    let (spec, locale) = match parse_path(&channel, &key, &locale) {
+        Ok(parsed) => parsed,
+        Err(response) => return Ok(response),
+    };
+    let body = match templates::validate(spec, &payload.body) {
+        Ok(body) => body,
+        Err(e) => return Ok(bad_request(e)),
+    };
+
+    match db.set_message_template(spec.channel, spec.key, locale.tag(), body).await {
+        Ok(template) => Ok(HttpResponse::Ok().json(template)),
+        Err(e) => Ok(server_error("Failed to save message template", e)),
+    }
+}
+
+/// Removes the merchant's copy so the message falls back again.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/templates/{channel}/{key}/{locale}",
+    tag = "admin",
+    params(
+        ("channel" = String, Path, description = "`email` or `in_app`"),
+        ("key" = String, Path, description = "Template key, e.g. `renewal_upcoming`"),
+        ("locale" = String, Path, description = "e.g. `en-ZA` or `af-ZA`"),
+    ),
+    responses(
+        (status = 204, description = "Template removed"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[delete("/templates/{channel}/{key}/{locale}")]
+pub async fn delete_template(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    path: Path<(String, String, String)>,
+) -> Result<HttpResponse> {
+    let (channel, key, locale) = path.into_inner();
+    let (spec, locale) = match parse_path(&channel, &key, &locale) {
+        Ok(parsed) => parsed,
+        Err(response) => return Ok(response),
+    };
+
+    match db.delete_message_template(spec.channel, spec.key, locale.tag()).await {
+        Ok(true) => Ok(HttpResponse::NoContent().finish()),
+        Ok(false) => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No saved copy for that template and locale"
+        }))),
+        Err(e) => Ok(server_error("Failed to remove message template", e)),
+    }
+}
@@ -0,0 +1,160 @@
+use actix_web::{HttpResponse, Result, get, patch, post};
+use actix_web::web::Data;
+use tracing::error;
+use crate::extractors::{AdminAuth, RecordPath, ValidatedJson};
+use crate::config::AppConfig;
+use crate::models::tenant::{CreateTenantDto, PeachCredentials, Tenant, UpdateTenantDto};
+use crate::models::validation::ValidationErrorResponse;
+use crate::services::database::DatabaseService;
+
+fn server_error(message: &str, details: String) -> HttpResponse {
+    error!("{}: {}", message, details);
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "error": message,
+    }))
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "error": "Tenant not found"
+    }))
+}
+
+/// Peach webhooks carry no API key, so a tenant's only reach it through its
+/// subdomain; refuses credentials whose `notification_url` isn't on it.
+fn check_notification_url(config: &AppConfig, peach: Option<&PeachCredentials>, subdomain: &str) -> Option<HttpResponse> {
+    let peach = peach?;
+    let error = match config.tenant_base_domain.as_deref() {
+        None => "Tenant Peach credentials need TENANT_BASE_DOMAIN, so their webhooks can be routed by subdomain".to_string(),
+        Some(base) if !peach.notifies_subdomain(subdomain, base) => {
+            format!("peach.notification_url must be on {}.{}", subdomain, base.trim_start_matches('.'))
+        }
+        Some(_) => return None,
+    };
+    Some(HttpResponse::BadRequest().json(serde_json::json!({ "error": error })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/tenants",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Every tenant, by subdomain", body = [Tenant]),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 403, description = "Called from within a tenant"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/tenants")]
+pub async fn list_tenants(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+) -> Result<HttpResponse> {
+    match db.list_tenants().await {
+        Ok(tenants) => Ok(HttpResponse::Ok().json(tenants)),
+        Err(e) => Ok(server_error("Failed to list tenants", e)),
+    }
+}
+
+/// Adds a merchant. Its requests are recognised by its subdomain or by API
+/// keys issued for it; Peach credentials are never returned.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/tenants",
+    tag = "admin",
+    request_body = CreateTenantDto,
+    responses(
+        (status = 201, description = "Tenant created", body = Tenant),
+        (status = 400, description = "Invalid request or subdomain taken"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 403, description = "Called from within a tenant"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/tenants")]
+pub async fn create_tenant(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    config: Data<AppConfig>,
+    payload: ValidatedJson<CreateTenantDto>,
+) -> Result<HttpResponse> {
+    let dto = payload.into_inner();
+    if let Some(refused) = check_notification_url(&config, dto.peach.as_ref(), &dto.subdomain) {
+        return Ok(refused);
+    }
+    match db.create_tenant(dto).await {
+        Ok(tenant) => Ok(HttpResponse::Created().json(tenant)),
+        Err(e) => Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/tenants/{tenant_id}",
+    tag = "admin",
+    params(("tenant_id" = String, Path, description = "Tenant id")),
+    responses(
+        (status = 200, description = "The tenant", body = Tenant),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 403, description = "Called from within a tenant"),
+        (status = 404, description = "Not found"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/tenants/{tenant_id}")]
+pub async fn get_tenant(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    tenant_id: RecordPath<Tenant>,
+) -> Result<HttpResponse> {
+    match db.get_tenant(tenant_id.key()).await {
+        Some(tenant) => Ok(HttpResponse::Ok().json(tenant)),
+        None => Ok(not_found()),
+    }
+}
+
+/// Renames a tenant, replaces its Peach credentials or disables it. A
+/// disabled tenant's requests are refused with 403.
+#[utoipa::path(
+    patch,
+    path = "/api/v1/admin/tenants/{tenant_id}",
+    tag = "admin",
+    params(("tenant_id" = String, Path, description = "Tenant id")),
+    request_body = UpdateTenantDto,
+    responses(
+        (status = 200, description = "The updated tenant", body = Tenant),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 403, description = "Called from within a tenant"),
+        (status = 404, description = "Not found"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[patch("/tenants/{tenant_id}")]
+pub async fn update_tenant(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    config: Data<AppConfig>,
+    tenant_id: RecordPath<Tenant>,
+    payload: ValidatedJson<UpdateTenantDto>,
+) -> Result<HttpResponse> {
+    let dto = payload.into_inner();
+    if dto.peach.is_some() {
+        let Some(existing) = db.get_tenant(tenant_id.key()).await else {
+            return Ok(not_found());
+        };
+        if let Some(refused) = check_notification_url(&config, dto.peach.as_ref(), &existing.subdomain) {
+            return Ok(refused);
+        }
+    }
+    match db.update_tenant(tenant_id.key(), dto).await {
+        Ok(Some(tenant)) => Ok(HttpResponse::Ok().json(tenant)),
+        Ok(None) => Ok(not_found()),
+        Err(e) => Ok(server_error("Failed to update tenant", e)),
+    }
+}
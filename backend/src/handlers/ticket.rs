@@ -0,0 +1,260 @@
+use actix_web::{HttpResponse, Result, get, post, put};
+use actix_web::web::{Data, Json, Path, Query};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use tracing::error;
+use crate::extractors::{AdminAuth, CurrentUser, RecordPath, ValidatedJson};
+use crate::models::activity::ActivityCategory;
+use crate::models::ticket::{
+    validate_ticket_text, CreateTicketDto, NewTicket, ReportChargeDto, Ticket, TicketFilter, TicketSource,
+    TicketStatus, UpdateTicketDto,
+};
+use crate::models::validation::ValidationErrorResponse;
+use crate::services::database::DatabaseService;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TicketListQuery {
+    pub status: Option<TicketStatus>,
+    pub source: Option<TicketSource>,
+    pub assignee: Option<String>,
+    pub user_id: Option<String>,
+    pub limit: Option<u32>,
+}
+
+fn server_error(message: &str, details: String) -> HttpResponse {
+    error!("{}: {}", message, details);
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "error": message,
+        "details": details
+    }))
+}
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({ "error": message }))
+}
+
+fn not_found(what: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "error": format!("{} not found", what)
+    }))
+}
+
+fn optional_text(field: &str, text: Option<String>) -> Result<Option<String>, String> {
+    match text {
+        Some(text) if !text.trim().is_empty() => validate_ticket_text(field, &text).map(Some),
+        _ => Ok(None),
+    }
+}
+
+// ---------------------
+// Admin
+// ---------------------
+
+/// Tickets matching the filters, most recently updated first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/tickets",
+    tag = "admin",
+    params(TicketListQuery),
+    responses(
+        (status = 200, description = "Matching tickets", body = [Ticket]),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/tickets")]
+pub async fn list_tickets(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    query: Query<TicketListQuery>,
+) -> Result<HttpResponse> {
+    let query = query.into_inner();
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let filter = TicketFilter {
+        status: query.status,
+        source: query.source,
+        assignee: query.assignee.map(|a| a.trim().to_string()).filter(|a| !a.is_empty()),
+        user_id: query.user_id.map(|u| u.trim().trim_start_matches("users:").to_string()).filter(|u| !u.is_empty()),
+    };
+
+    match db.list_tickets(&filter, limit).await {
+        Ok(tickets) => Ok(HttpResponse::Ok().json(tickets)),
+        Err(e) => Ok(server_error("Failed to list tickets", e)),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/tickets/{ticket_id}",
+    tag = "admin",
+    params(("ticket_id" = String, Path, description = "Ticket id")),
+    responses(
+        (status = 200, description = "The ticket", body = Ticket),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/tickets/{ticket_id}")]
+pub async fn get_ticket(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    ticket_id: RecordPath<Ticket>,
+) -> Result<HttpResponse> {
+    match db.get_ticket(ticket_id.key()).await {
+        Some(ticket) => Ok(HttpResponse::Ok().json(ticket)),
+        None => Ok(not_found("Ticket")),
+    }
+}
+
+/// Opens a ticket by hand, e.g. for an issue raised by phone.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/tickets",
+    tag = "admin",
+    request_body = CreateTicketDto,
+    responses(
+        (status = 201, description = "The new ticket", body = Ticket),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/tickets")]
+pub async fn create_ticket(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    payload: ValidatedJson<CreateTicketDto>,
+) -> Result<HttpResponse> {
+    let dto = payload.into_inner();
+    let subject = dto.subject.trim().to_string();
+    let description = dto.description.map(|d| d.trim().to_string()).filter(|d| !d.is_empty());
+
+    let user_id = dto.user_id.trim().trim_start_matches("users:").to_string();
+    if db.get_user(&user_id).await.is_none() {
+        return Ok(not_found("User"));
+    }
+
+    let ticket = NewTicket {
+        user_id,
+        source: TicketSource::Manual,
+        subject,
+        description,
+        payment_id: dto.payment_id.filter(|p| !p.trim().is_empty()),
+        subscription_id: dto.subscription_id.filter(|s| !s.trim().is_empty()),
+        assignee: dto.assignee.map(|a| a.trim().to_string()).filter(|a| !a.is_empty()),
+    };
+    match db.open_ticket(ticket).await {
+        Ok(ticket) => Ok(HttpResponse::Created().json(ticket)),
+        Err(e) => Ok(server_error("Failed to open ticket", e)),
+    }
+}
+
+/// Moves a ticket through its statuses and/or (un)assigns it.
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/tickets/{ticket_id}",
+    tag = "admin",
+    params(("ticket_id" = String, Path, description = "Ticket id")),
+    request_body = UpdateTicketDto,
+    responses(
+        (status = 200, description = "The updated ticket", body = Ticket),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[put("/tickets/{ticket_id}")]
+pub async fn update_ticket(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    ticket_id: RecordPath<Ticket>,
+    payload: ValidatedJson<UpdateTicketDto>,
+) -> Result<HttpResponse> {
+    let dto = payload.into_inner();
+    let assignee = dto.assignee.map(|a| Some(a.trim().to_string()).filter(|a| !a.is_empty()));
+
+    match db.update_ticket(ticket_id.key(), dto.status, assignee).await {
+        Ok(ticket) => Ok(HttpResponse::Ok().json(ticket)),
+        Err(e) if e.starts_with("Ticket not found") => Ok(not_found("Ticket")),
+        Err(e) => Ok(server_error("Failed to update ticket", e)),
+    }
+}
+
+// ---------------------
+// Subscriber
+// ---------------------
+
+/// Reports a charge the caller doesn't recognise; support follows up on the
+/// ticket this opens.
+#[utoipa::path(
+    post,
+    path = "/api/v1/me/payments/{merchant_transaction_id}/report",
+    tag = "me",
+    params(("merchant_transaction_id" = String, Path, description = "Merchant transaction id")),
+    request_body = ReportChargeDto,
+    responses(
+        (status = 201, description = "`ticket_id` and `status` of the ticket"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[post("/payments/{merchant_transaction_id}/report")]
+pub async fn report_unrecognized_charge(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    path: Path<String>,
+    payload: Option<Json<ReportChargeDto>>,
+) -> Result<HttpResponse> {
+    let merchant_transaction_id = path.into_inner();
+    let description = match optional_text("Description", payload.and_then(|p| p.into_inner().description)) {
+        Ok(description) => description,
+        Err(e) => return Ok(bad_request(e)),
+    };
+
+    // Someone else's payment is reported as missing, not forbidden
+    let payment = match db.get_payment_by_merchant_id(&merchant_transaction_id).await {
+        Some(payment) if payment.user_id == user.user_id => payment,
+        _ => return Ok(not_found("Payment")),
+    };
+
+    let ticket = NewTicket {
+        user_id: payment.user_id.clone(),
+        source: TicketSource::UnrecognizedCharge,
+        subject: format!("Unrecognised charge {}", payment.merchant_transaction_id),
+        description,
+        payment_id: Some(payment.merchant_transaction_id.clone()),
+        subscription_id: payment.subscription_id.clone(),
+        assignee: None,
+    };
+    let ticket = match db.open_ticket(ticket).await {
+        Ok(ticket) => ticket,
+        Err(e) => return Ok(server_error("Failed to report charge", e)),
+    };
+
+    db.record_activity(
+        &payment.user_id,
+        ActivityCategory::Payment,
+        "charge_reported",
+        format!("Reported payment of {:.2} {} as unrecognised", payment.amount, payment.currency),
+        Some(payment.merchant_transaction_id.clone()),
+    ).await;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "ticket_id": ticket.id,
+        "status": ticket.status
+    })))
+}
@@ -0,0 +1,142 @@
+use actix_web::{HttpResponse, Result, get, post};
+use actix_web::web::Data;
+use tracing::{error, warn};
+use crate::extractors::{AdminAuth, RecordPath, ValidatedJson};
+use crate::models::notification::CreateNotificationDto;
+use crate::models::token_migration::{CreateTokenMigrationDto, TokenMigration};
+use crate::models::validation::ValidationErrorResponse;
+use crate::services::database::DatabaseService;
+
+/// Flags every active card for re-authorisation after the gateway entity (or
+/// gateway) changed, and prompts their users to confirm their card. Renewals
+/// keep charging the old tokens until each user does.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/token-migrations",
+    tag = "admin",
+    request_body = CreateTokenMigrationDto,
+    responses(
+        (status = 201, description = "Migration started", body = TokenMigrationProgress),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/token-migrations")]
+pub async fn start_token_migration(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    payload: ValidatedJson<CreateTokenMigrationDto>,
+) -> Result<HttpResponse> {
+    let dto = payload.into_inner();
+
+    let (migration, flagged) = match db.start_token_migration(dto).await {
+        Ok(result) => result,
+        Err(e) => {
+            error!("Error starting token migration: {}", e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to start token migration"
+            })));
+        }
+    };
+
+    let prompts = flagged
+        .into_iter()
+        .map(|card| CreateNotificationDto::card_migration(card.user_id, card.subscription_id))
+        .collect();
+    if let Err(e) = db.create_notifications(prompts).await {
+        warn!("Failed to prompt users for token migration {}: {}", migration.id, e);
+    }
+
+    match db.get_token_migration_progress(migration).await {
+        Ok(progress) => Ok(HttpResponse::Created().json(progress)),
+        Err(e) => {
+            error!("Error loading token migration progress: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Token migration started but its progress could not be loaded"
+            })))
+        }
+    }
+}
+
+/// Progress of every migration, newest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/token-migrations",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Progress of every migration", body = [TokenMigrationProgress]),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/token-migrations")]
+pub async fn list_token_migrations(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+) -> Result<HttpResponse> {
+    let migrations = match db.list_token_migrations().await {
+        Ok(migrations) => migrations,
+        Err(e) => {
+            error!("Error listing token migrations: {}", e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to list token migrations"
+            })));
+        }
+    };
+
+    let mut report = Vec::with_capacity(migrations.len());
+    for migration in migrations {
+        match db.get_token_migration_progress(migration).await {
+            Ok(progress) => report.push(progress),
+            Err(e) => {
+                error!("Error loading token migration progress: {}", e);
+                return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": "Failed to list token migrations"
+                })));
+            }
+        }
+    }
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/token-migrations/{migration_id}",
+    tag = "admin",
+    params(("migration_id" = String, Path, description = "Token migration id")),
+    responses(
+        (status = 200, description = "Progress of the migration", body = TokenMigrationProgress),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/token-migrations/{migration_id}")]
+pub async fn get_token_migration(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    migration_id: RecordPath<TokenMigration>,
+) -> Result<HttpResponse> {
+    let migration = match db.get_token_migration(migration_id.key()).await {
+        Some(migration) => migration,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Token migration not found"
+        }))),
+    };
+
+    match db.get_token_migration_progress(migration).await {
+        Ok(progress) => Ok(HttpResponse::Ok().json(progress)),
+        Err(e) => {
+            error!("Error loading token migration progress: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to load token migration"
+            })))
+        }
+    }
+}
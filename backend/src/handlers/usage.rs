@@ -0,0 +1,82 @@
+use actix_web::{HttpResponse, Result, post};
+use actix_web::middleware::from_fn;
+use actix_web::web::Data;
+use chrono::Utc;
+use tracing::error;
+use crate::extractors::{ApiKeyAuth, RecordPath, ValidatedJson};
+use crate::middleware::require_signed_request;
+use crate::models::subscription::{Subscription, SubscriptionStatus};
+use crate::models::usage::ReportUsageDto;
+use crate::models::validation::ValidationErrorResponse;
+use crate::services::database::DatabaseService;
+
+fn bad_request(error: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({ "error": error }))
+}
+
+/// Reports metered usage for an active subscription, billed with its next
+/// renewal. Only merchant backends may report it: an API key that can write
+/// is required, as well as a signature when signing is enforced. Answers 201
+/// for new usage and 200 when the idempotency key was already reported.
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscriptions/{subscription_id}/usage",
+    tag = "subscriptions",
+    params(("subscription_id" = String, Path, description = "Subscription id")),
+    request_body = ReportUsageDto,
+    responses(
+        (status = 201, description = "Usage recorded", body = UsageRecord),
+        (status = 200, description = "Idempotency key already reported", body = UsageRecord),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Conflicts with the current state"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("api_key" = [], "request_signature" = []))
+)]
+#[post("/{subscription_id}/usage", wrap = "from_fn(require_signed_request)")]
+pub async fn report_usage(
+    // `api_key_auth` has already refused read-only keys for a POST
+    _auth: ApiKeyAuth,
+    db: Data<DatabaseService>,
+    subscription_id: RecordPath<Subscription>,
+    payload: ValidatedJson<ReportUsageDto>,
+) -> Result<HttpResponse> {
+    let dto = payload.into_inner();
+    let idempotency_key = dto.idempotency_key.as_deref().map(str::trim);
+    let recorded_at = dto.recorded_at.unwrap_or_else(Utc::now);
+
+    let subscription = match db.get_subscription(subscription_id.key()).await {
+        Some(subscription) => subscription,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Subscription not found"
+        }))),
+    };
+    if subscription.usage_pricing.is_none() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Subscription is not on a metered plan"
+        })));
+    }
+    if !matches!(subscription.status, SubscriptionStatus::Active | SubscriptionStatus::Grace) {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Usage can only be reported for active subscriptions"
+        })));
+    }
+    // Earlier periods have already been billed
+    if subscription.period_start().is_some_and(|start| recorded_at < start) {
+        return Ok(bad_request("Usage predates the current billing period"));
+    }
+
+    match db.record_usage(&subscription, dto.quantity, recorded_at, idempotency_key).await {
+        Ok((record, true)) => Ok(HttpResponse::Created().json(record)),
+        Ok((record, false)) => Ok(HttpResponse::Ok().json(record)),
+        Err(e) => {
+            error!("Error recording usage for {}: {}", subscription.id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to record usage"
+            })))
+        }
+    }
+}
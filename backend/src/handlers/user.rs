@@ -1,40 +1,68 @@
-use actix_web::{HttpResponse, Result, get, post, web};
-use actix_web::web::{Data, Json, Path};
+use actix_web::{HttpRequest, HttpResponse, Result, delete, get, patch, post, web};
+use sha2::{Digest, Sha256};
+use actix_web::web::{Data, Path};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use tracing::{debug, error, info};
 use crate::services::database::DatabaseService;
-use crate::models::user::CreateUserDto;
+use crate::services::formatting::Locale;
+use crate::services::storage::{Storage, UserRepo};
+use crate::extractors::{CurrentUser, RecordPath, ValidatedJson};
+use crate::models::user::{CreateUserDto, UpdateUserDto, User};
+use crate::services::privacy::{self, DeleteUserError};
+use crate::models::activity::ActivityCategory;
+use crate::models::validation::{FieldErrors, Validate, ValidationErrorResponse};
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 pub struct RegisterUserRequest {
     pub email: String,
     pub name: String,
 }
 
-#[derive(Serialize)]
+impl Validate for RegisterUserRequest {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        if self.email.is_empty() {
+            errors.add("email", "A valid email address is required");
+        }
+        if self.name.is_empty() {
+            errors.add("name", "Name can't be empty");
+        }
+        errors.into_result()
+    }
+}
+
+#[derive(Serialize, ToSchema)]
 pub struct UserResponse {
     pub id: String,
     pub email: String,
     pub name: String,
+    /// e.g. `af-ZA`; unset means Accept-Language decides.
+    pub locale: Option<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct ErrorResponse {
     pub error: String,
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/v1/users/register",
+    tag = "users",
+    request_body = RegisterUserRequest,
+    responses(
+        (status = 200, description = "User registered", body = UserResponse),
+        (status = 400, description = "Invalid request"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+    )
+)]
 #[post("/register")]
 pub async fn register_user(
-    db: Data<DatabaseService>,
-    payload: Json<RegisterUserRequest>,
+    db: Data<dyn Storage>,
+    payload: ValidatedJson<RegisterUserRequest>,
 ) -> Result<HttpResponse> {
-    println!("📝 Register request received: {:?}", payload);
-    
-    // Validate input
-    if payload.email.is_empty() || payload.name.is_empty() {
-        return Ok(HttpResponse::BadRequest().json(ErrorResponse {
-            error: "Email and name are required".to_string(),
-        }));
-    }
+    info!("Register request received: {:?}", payload);
 
     let dto = CreateUserDto {
         email: payload.email.clone(),
@@ -43,15 +71,16 @@ pub async fn register_user(
 
     match db.create_user(dto).await {
         Ok(user) => {
-            println!("✅ User created successfully: {}", user.email);
+            info!("User created successfully: {}", user.email);
             Ok(HttpResponse::Ok().json(UserResponse {
                 id: user.id,
                 email: user.email,
                 name: user.name,
+                locale: user.locale,
             }))
         },
         Err(e) => {
-            println!("❌ Failed to create user: {}", e);
+            error!("Failed to create user: {}", e);
             Ok(HttpResponse::BadRequest().json(ErrorResponse {
                 error: format!("Failed to create user: {}", e),
             }))
@@ -59,42 +88,300 @@ pub async fn register_user(
     }
 }
 
+/// Records a security event the first time a user signs in from a device we
+/// haven't seen, fingerprinted by User-Agent.
+async fn track_device_login(db: &DatabaseService, req: &HttpRequest, user_id: &str) {
+    let user_agent = req
+        .headers()
+        .get("User-Agent")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("unknown");
+    let device_hash = hex::encode(&Sha256::digest(user_agent.as_bytes())[..8]);
+
+    if !db.has_known_device(user_id, &device_hash).await {
+        let short_agent: String = user_agent.chars().take(80).collect();
+        db.record_activity(
+            user_id,
+            ActivityCategory::Security,
+            "new_device_login",
+            format!("New sign-in from {}", short_agent),
+            Some(device_hash),
+        ).await;
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/email/{email}",
+    tag = "users",
+    params(("email" = String, Path, description = "Email address")),
+    responses(
+        (status = 200, description = "The user", body = UserResponse),
+        (status = 404, description = "Not found"),
+    )
+)]
 #[get("/email/{email}")]
 pub async fn get_user_by_email(
+    req: HttpRequest,
     db: Data<DatabaseService>,
     path: Path<String>,
 ) -> Result<HttpResponse> {
     let email = path.into_inner();
-    println!("🔍 Looking up user by email: {}", email);
+    debug!("Looking up user by email: {}", email);
     
     match db.get_user_by_email(&email).await {
-        Some(user) => Ok(HttpResponse::Ok().json(UserResponse {
-            id: user.id,
-            email: user.email,
-            name: user.name,
-        })),
+        Some(user) => {
+            // Email lookup is how the PWA signs users in
+            track_device_login(&db, &req, &user.id).await;
+            Ok(HttpResponse::Ok().json(UserResponse {
+                id: user.id,
+                email: user.email,
+                name: user.name,
+                locale: user.locale,
+            }))
+        }
         None => Ok(HttpResponse::NotFound().json(ErrorResponse {
             error: "User not found".to_string(),
         })),
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/{user_id}",
+    tag = "users",
+    params(("user_id" = String, Path, description = "User id")),
+    responses(
+        (status = 200, description = "The user", body = UserResponse),
+        (status = 400, description = "Invalid request"),
+        (status = 404, description = "Not found"),
+    )
+)]
 #[get("/{user_id}")]
 pub async fn get_user(
-    db: Data<DatabaseService>,
-    path: Path<String>,
+    db: Data<dyn Storage>,
+    user_id: RecordPath<User>,
 ) -> Result<HttpResponse> {
-    let user_id = path.into_inner();
-    println!("🔍 Looking up user by ID: {}", user_id);
+    debug!("Looking up user by ID: {}", user_id);
     
-    match db.get_user(&user_id).await {
+    match db.get_user(user_id.key()).await.filter(|user| user.deleted_at.is_none()) {
         Some(user) => Ok(HttpResponse::Ok().json(UserResponse {
             id: user.id,
             email: user.email,
             name: user.name,
+            locale: user.locale,
         })),
         None => Ok(HttpResponse::NotFound().json(ErrorResponse {
             error: "User not found".to_string(),
         })),
     }
 }
+
+/// Looks up the caller's own account; anyone else's, or a deleted one, is
+/// reported as missing.
+async fn load_own_user(db: &DatabaseService, user: &CurrentUser, user_id: &RecordPath<User>) -> Option<User> {
+    if user_id.key() != user.user_id {
+        return None;
+    }
+    db.get_user(user_id.key()).await.filter(|u| u.deleted_at.is_none())
+}
+
+/// Updates the caller's name, email or language. An email change is
+/// recorded as a security event.
+#[utoipa::path(
+    patch,
+    path = "/api/v1/users/{user_id}",
+    tag = "users",
+    params(("user_id" = String, Path, description = "User id")),
+    request_body = UpdateUserDto,
+    responses(
+        (status = 200, description = "The updated user", body = UserResponse),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Email already in use"),
+        (status = 422, description = "Invalid fields", body = ValidationErrorResponse),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[patch("/{user_id}")]
+pub async fn update_user(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    user_id: RecordPath<User>,
+    payload: ValidatedJson<UpdateUserDto>,
+) -> Result<HttpResponse> {
+    let current = match load_own_user(&db, &user, &user_id).await {
+        Some(current) => current,
+        None => return Ok(HttpResponse::NotFound().json(ErrorResponse {
+            error: "User not found".to_string(),
+        })),
+    };
+
+    let dto = payload.into_inner();
+    let name = dto.name.map(|n| n.trim().to_string());
+    let email = dto.email.map(|e| e.trim().to_string()).filter(|e| *e != current.email);
+    if let Some(email) = &email {
+        if db.get_user_by_email(email).await.is_some() {
+            return Ok(HttpResponse::Conflict().json(ErrorResponse {
+                error: "Email already in use".to_string(),
+            }));
+        }
+    }
+    let locale = dto.locale.as_deref().map(str::trim).and_then(Locale::from_tag).map(|l| l.tag().to_string());
+
+    let email_changed = email.is_some();
+    match db.update_user(user_id.key(), UpdateUserDto { name, email, locale }).await {
+        Ok(updated) => {
+            if email_changed {
+                db.record_activity(
+                    user_id.key(),
+                    ActivityCategory::Security,
+                    "email_changed",
+                    format!("Email changed from {} to {}", current.email, updated.email),
+                    None,
+                ).await;
+            }
+            Ok(HttpResponse::Ok().json(UserResponse {
+                id: updated.id,
+                email: updated.email,
+                name: updated.name,
+                locale: updated.locale,
+            }))
+        }
+        Err(e) => {
+            error!("Error updating user {}: {}", user_id, e);
+            Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to update user".to_string(),
+            }))
+        }
+    }
+}
+
+/// Deletes the caller's account: personal data is erased and card tokens
+/// and webhooks removed, while payments and invoices are kept, anonymized,
+/// for accounting. Subscriptions must be cancelled first.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/users/{user_id}",
+    tag = "users",
+    params(("user_id" = String, Path, description = "User id")),
+    responses(
+        (status = 204, description = "Account deleted"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 409, description = "Subscriptions still open"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[delete("/{user_id}")]
+pub async fn delete_user(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    user_id: RecordPath<User>,
+) -> Result<HttpResponse> {
+    if load_own_user(&db, &user, &user_id).await.is_none() {
+        return Ok(HttpResponse::NotFound().json(ErrorResponse {
+            error: "User not found".to_string(),
+        }));
+    }
+
+    match privacy::delete_user(&db, user_id.key()).await {
+        Ok(_) => {
+            info!("Deleted user {} and erased personal data", user_id);
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(DeleteUserError::OpenSubscriptions(ids)) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Cancel your subscriptions before deleting your account",
+            "subscription_ids": ids,
+        }))),
+        Err(DeleteUserError::Failed(e)) => {
+            error!("Error deleting user {}: {}", user_id, e);
+            Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to delete user".to_string(),
+            }))
+        }
+    }
+}
+
+/// Everything stored about the caller, as JSON.
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/{user_id}/export",
+    tag = "users",
+    params(("user_id" = String, Path, description = "User id")),
+    responses(
+        (status = 200, description = "The user's data", body = UserDataExport),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[get("/{user_id}/export")]
+pub async fn export_user_data(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    user_id: RecordPath<User>,
+) -> Result<HttpResponse> {
+    let account = match load_own_user(&db, &user, &user_id).await {
+        Some(account) => account,
+        None => return Ok(HttpResponse::NotFound().json(ErrorResponse {
+            error: "User not found".to_string(),
+        })),
+    };
+
+    match privacy::export_user_data(&db, account).await {
+        Ok(export) => Ok(HttpResponse::Ok()
+            .insert_header(("Content-Disposition", format!("attachment; filename=\"user-data-{}.json\"", user_id.key())))
+            .json(export)),
+        Err(e) => {
+            error!("Error exporting data for user {}: {}", user_id, e);
+            Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to export user data".to_string(),
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use actix_web::{test, App};
+    use crate::services::storage::MemoryStorage;
+
+    #[actix_web::test]
+    async fn registered_user_can_be_fetched_by_id() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::from(storage))
+                .service(web::scope("/users").service(register_user).service(get_user)),
+        ).await;
+
+        let request = test::TestRequest::post()
+            .uri("/users/register")
+            .set_json(serde_json::json!({ "email": "sam@example.com", "name": "Sam" }))
+            .to_request();
+        let registered: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        let id = registered["id"].as_str().unwrap();
+
+        let request = test::TestRequest::get().uri(&format!("/users/users:{}", id)).to_request();
+        let fetched: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(fetched["email"], "sam@example.com");
+
+        let request = test::TestRequest::post()
+            .uri("/users/register")
+            .set_json(serde_json::json!({ "email": "sam@example.com", "name": "Sam again" }))
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 400);
+
+        let request = test::TestRequest::get().uri("/users/missing").to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 404);
+    }
+}
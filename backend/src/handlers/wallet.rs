@@ -0,0 +1,48 @@
+use actix_web::{HttpResponse, Result, get};
+use actix_web::web::Data;
+use tracing::error;
+use crate::extractors::{CurrentUser, RecordPath};
+use crate::models::user::User;
+use crate::services::database::DatabaseService;
+
+/// Newest ledger entries returned with the balances.
+const WALLET_LEDGER_ENTRIES: u32 = 100;
+
+/// The caller's credit balances and recent wallet ledger. Other users'
+/// wallets are reported as missing.
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/{user_id}/wallet",
+    tag = "users",
+    params(("user_id" = String, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Balances and recent entries", body = Wallet),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("user_id" = []))
+)]
+#[get("/{user_id}/wallet")]
+pub async fn get_wallet(
+    user: CurrentUser,
+    db: Data<DatabaseService>,
+    user_id: RecordPath<User>,
+) -> Result<HttpResponse> {
+    if user_id.key() != user.user_id || db.get_user(user_id.key()).await.is_none() {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "User not found"
+        })));
+    }
+
+    match db.get_wallet(user_id.key(), WALLET_LEDGER_ENTRIES).await {
+        Ok(wallet) => Ok(HttpResponse::Ok().json(wallet)),
+        Err(e) => {
+            error!("Error loading wallet for {}: {}", user_id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to load wallet"
+            })))
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use actix_web::{HttpResponse, Result, get, post};
+use actix_web::http::header::HeaderMap;
+use actix_web::web::{Data, Query};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use tracing::{error, info};
+use crate::config::AppConfig;
+use crate::extractors::{AdminAuth, RecordPath};
+use crate::handlers::payment::{gateway_named, process_webhook, webhook_event_update};
+use crate::models::webhook_event::{WebhookCodeMetrics, WebhookEvent, WebhookEventUpdate, WebhookOutcome};
+use crate::services::database::DatabaseService;
+use crate::services::gateway::PaymentGateway;
+use crate::services::ozow::OzowPaymentService;
+use crate::services::email::EmailService;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WebhookEventsQuery {
+    pub outcome: Option<WebhookOutcome>,
+    pub limit: Option<u32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks/events",
+    tag = "webhooks",
+    params(WebhookEventsQuery),
+    responses(
+        (status = 200, description = "Stored webhook deliveries, newest first", body = [WebhookEvent]),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/events")]
+pub async fn list_webhook_events(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    query: Query<WebhookEventsQuery>,
+) -> Result<HttpResponse> {
+    let limit = query.limit.unwrap_or(50).min(500);
+
+    match db.list_webhook_events(query.outcome.clone(), limit).await {
+        Ok(events) => Ok(HttpResponse::Ok().json(events)),
+        Err(e) => {
+            error!("Error listing webhook events: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to list webhook events"
+            })))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ResultCodeMetricsQuery {
+    /// Unhandled codes to list; 20 by default, at most 200.
+    pub limit: Option<u32>,
+}
+
+/// Verified Peach webhooks counted by how their result code was handled,
+/// with the unhandled codes seen most recently.
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks/result-codes",
+    tag = "webhooks",
+    params(ResultCodeMetricsQuery),
+    responses(
+        (status = 200, description = "Counts per category and recent unhandled codes", body = WebhookCodeMetrics),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 500, description = "Internal error"),
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/result-codes")]
+pub async fn get_result_code_metrics(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    query: Query<ResultCodeMetricsQuery>,
+) -> Result<HttpResponse> {
+    let limit = query.limit.unwrap_or(20).min(200);
+
+    match db.get_webhook_code_metrics(limit).await {
+        Ok(metrics) => Ok(HttpResponse::Ok().json(metrics)),
+        Err(e) => {
+            error!("Error loading webhook result code metrics: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to load webhook metrics"
+            })))
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/webhooks/events/{event_id}/replay",
+    tag = "webhooks",
+    params(("event_id" = String, Path, description = "Webhook event id")),
+    responses(
+        (status = 200, description = "`event_id` and the new `outcome`"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 404, description = "Not found"),
+        (status = 422, description = "Cannot be processed"),
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/events/{event_id}/replay")]
+pub async fn replay_webhook_event(
+    _admin: AdminAuth,
+    db: Data<DatabaseService>,
+    gateway: Data<dyn PaymentGateway>,
+    ozow: Option<Data<OzowPaymentService>>,
+    email: Data<EmailService>,
+    config: Data<AppConfig>,
+    event_id: RecordPath<WebhookEvent>,
+) -> Result<HttpResponse> {
+    let event_id = event_id.into_key();
+
+    let event = match db.get_webhook_event(&event_id).await {
+        Some(event) => event,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Webhook event not found"
+        }))),
+    };
+
+    let gateway = gateway_named(&gateway, &ozow, event.gateway.as_deref());
+    let raw_body = event.raw_body.as_bytes();
+
+    // Replays are re-verified so a rejected forgery can't be pushed through by hand.
+    // Header-signed gateways only have the signature we stored on the event.
+    let provided_signature = gateway
+        .webhook_signature(&HeaderMap::new(), raw_body)
+        .or_else(|| event.signature.clone())
+        .unwrap_or_default();
+    if provided_signature.is_empty() || !gateway.validate_webhook(raw_body, &provided_signature) {
+        return Ok(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": "Stored webhook signature is invalid; refusing to replay"
+        })));
+    }
+
+    // Encrypted deliveries are stored as received and decrypted again here
+    let decrypted = match gateway.decrypt_webhook(raw_body, &provided_signature).transpose() {
+        Ok(decrypted) => decrypted,
+        Err(e) => return Ok(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": "Stored webhook could not be decrypted",
+            "details": e
+        }))),
+    };
+    let mut notification = match gateway.parse_webhook(decrypted.as_deref().unwrap_or(raw_body)) {
+        Ok(notification) => notification,
+        Err(e) => return Ok(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": format!("Stored webhook body is not a valid {} webhook", gateway.name()),
+            "details": e
+        }))),
+    };
+
+    info!("Replaying webhook event {}", event_id);
+    let _ = db.increment_webhook_replay_count(&event_id).await;
+
+    notification.signature = Some(provided_signature);
+    let (outcome, error) = match process_webhook(&db, &email, &config, &notification, event.created_at).await {
+        Ok(outcome) => (outcome, None),
+        Err(e) => {
+            error!("Webhook replay failed for {}: {}", event_id, e);
+            (WebhookOutcome::Failed, Some(e))
+        }
+    };
+
+    let update = WebhookEventUpdate {
+        error: error.clone(),
+        ..webhook_event_update(&notification)
+    };
+    if let Err(e) = db.update_webhook_event(&event_id, outcome.clone(), update).await {
+        error!("Failed to record replay outcome for {}: {}", event_id, e);
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "event_id": event_id,
+        "outcome": outcome,
+        "error": error
+    })))
+}
@@ -2,101 +2,264 @@ mod models;
 mod handlers;
 mod services;
 mod tasks;
+mod extractors;
+mod config;
+mod middleware;
+mod bootstrap;
+mod openapi;
+mod telemetry;
+mod cors;
 
-use actix_web::{web, App, HttpServer, middleware::Logger};
-use actix_web::web::Data;
+use actix_web::{web, App, HttpServer, middleware::{from_fn, Logger}};
 use std::env;
-use std::sync::Arc;
 use dotenv::dotenv;
-use actix_cors::Cors;
-use services::{
-    database::DatabaseService,
-    peach::PeachPaymentService,
-};
+use bootstrap::AppContainer;
+use models::attachment::MAX_ATTACHMENT_BYTES;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+use tracing::info;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Load .env
     dotenv().ok();
-    env_logger::init();
+    telemetry::init();
 
-    // Initialize database service (now async)
-    let database_service = DatabaseService::new().await
-        .expect("Failed to initialize database service");
-
-    // Load Peach Payments configuration from .env
-    let webhook_secret_key = env::var("PEACH_SECRET_KEY")
-        .expect("PEACH_SECRET_KEY must be set in .env");
-    
-    let peach_service = PeachPaymentService::new(
-        env::var("PEACH_AUTH_SERVICE_URL").expect("PEACH_AUTH_SERVICE_URL must be set"),
-        env::var("PEACH_CHECKOUT_V2_ENDPOINT").expect("PEACH_CHECKOUT_V2_ENDPOINT must be set"),
-        env::var("PEACH_ENTITY_ID_V2").expect("PEACH_ENTITY_ID_V2 must be set"),
-        env::var("PEACH_CLIENT_ID").expect("PEACH_CLIENT_ID must be set"),
-        env::var("PEACH_CLIENT_SECRET").expect("PEACH_CLIENT_SECRET must be set"),
-        env::var("PEACH_MERCHANT_ID").expect("PEACH_MERCHANT_ID must be set"),
-        env::var("PEACH_NOTIFICATION_URL").expect("PEACH_NOTIFICATION_URL must be set"),
-        env::var("PEACH_SHOPPER_RESULT_URL").expect("PEACH_SHOPPER_RESULT_URL must be set"),
-        webhook_secret_key,
-    );
-
-    // ✅ Spawn the renewal task after both services are available
-    let db = Arc::new(database_service.clone());
-    let peach = Arc::new(peach_service.clone());
-    actix_rt::spawn(tasks::renewal_task::start_renewal_task(db, peach));
+    let container = AppContainer::from_env().await
+        .unwrap_or_else(|e| panic!("Startup failed: {}", e));
+    container.check_dependencies().await
+        .unwrap_or_else(|e| panic!("Dependency check failed: {}", e));
+    let background_tasks = container.spawn_background_tasks();
 
     // Start web server
     let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
     let bind_address = format!("0.0.0.0:{}", port);
 
-        println!("🚀 Starting server on {}", bind_address);
+        info!("Starting server on {}", bind_address);
+
+    let api_doc = openapi::ApiDoc::openapi();
+    let shutdown_timeout = container.config.shutdown_timeout_secs.into();
+    let app_container = container.clone();
 
+    // On SIGTERM/SIGINT the server stops accepting connections and gives
+    // in-flight requests (webhooks mid-payment included) the shutdown timeout
     HttpServer::new(move || {
+        let container = app_container.clone();
         App::new()
+            .wrap(from_fn(middleware::audit_context))
+            .wrap(from_fn(middleware::localize_errors))
+            .wrap(from_fn(middleware::impersonation))
+            .wrap(from_fn(middleware::tenant))
+            .wrap(from_fn(middleware::api_key_auth))
             .wrap(Logger::default())
-            .wrap(
-                Cors::default()
-                    .allowed_origin("http://127.0.0.1:8080")
-                    .allowed_origin("http://localhost:8080") 
-                    .allowed_origin("http://127.0.0.1:3000")  // Common dev server
-                    .allowed_origin("http://localhost:3000")   // Common dev server
-                    .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
-                    .allowed_headers(vec!["Content-Type", "Authorization", "Accept"])
-                    .supports_credentials()
+            .wrap(from_fn(middleware::request_id))
+            .wrap(cors::build(&container.config))
+            .configure(|cfg| container.configure(cfg))
+            .service(
+                SwaggerUi::new("/api/v1/docs/{_:.*}")
+                    .url("/api/v1/openapi.json", api_doc.clone())
+            )
+            .service(
+                web::scope("/health")
+                    .service(handlers::health::liveness)
+                    .service(handlers::health::readiness)
             )
-            .app_data(Data::new(database_service.clone()))
-            .app_data(Data::new(peach_service.clone()))
             .service(
                 web::scope("/api/v1")
                     .service(
                         web::scope("/users")
                               .service(handlers::user::register_user)
                                 .service(handlers::user::get_user_by_email)
+                            .service(handlers::user::export_user_data)
                             .service(handlers::user::get_user)
+                            .service(handlers::user::update_user)
+                            .service(handlers::user::delete_user)
+                            .service(handlers::wallet::get_wallet)
+                            .service(handlers::payment_history::get_payment_history)
                     )
                     .service(
                         web::scope("/payments")
+                            .service(handlers::payment::get_checkout_config)
+                            .service(handlers::payment_method::list_payment_methods)
+                            .service(handlers::payment::validate_payment)
                             .service(handlers::payment::initiate_payment)
                             .service(handlers::payment::check_payment_status)
+                            .service(handlers::payment::get_payment_qr)
+                            .service(handlers::payment::wait_for_payment)
                             .service(handlers::payment::handle_payment_callback_get)
                             .service(handlers::payment::payment_callback)
+                            .service(handlers::payment::ozow_notify)
                             .service(handlers::payment::charge_recurring_payment)
+                            .service(handlers::card_update::start_card_update)
+                            .service(handlers::card_update::get_card_update)
+                            .service(handlers::refund::refund_payment)
+                            .service(handlers::refund::get_payment_refunds)
+                            .service(handlers::capture::capture_payment)
+                            .service(handlers::capture::void_payment)
                     )
                     .service(
                         web::scope("/subscriptions")
                         .service(handlers::subscription::create_subscription)
+                            .service(handlers::subscription::get_upcoming_invoices)
                             .service(handlers::subscription::get_subscription)
                             .service(handlers::subscription::renew_subscription)
+                            .service(handlers::subscription::cancel_subscription)
+                            .service(handlers::subscription::reactivate_subscription)
+                            .service(handlers::subscription::restart_subscription)
+                            .service(handlers::subscription::pause_subscription)
+                            .service(handlers::subscription::resume_subscription)
+                            .service(handlers::plan_change::preview_plan_change)
+                            .service(handlers::plan_change::change_plan)
+                            .service(handlers::plan_change::get_scheduled_plan_change)
+                            .service(handlers::plan_change::cancel_scheduled_plan_change)
+                            .service(handlers::installment::get_installments)
+                            .service(handlers::usage::report_usage)
+                            .service(handlers::membership::invite_member)
+                            .service(handlers::membership::list_members)
+                            .service(handlers::membership::remove_member)
+                            .service(handlers::membership::update_seats)
+                    )
+                    .service(
+                        web::scope("/invoices")
+                            .service(handlers::invoice::get_invoice)
+                            .service(handlers::invoice::get_invoice_pdf)
+                    )
+                    .service(
+                        web::scope("/me")
+                            .service(handlers::me::get_my_activity)
+                            .service(handlers::me::skip_next_renewal)
+                            .service(handlers::me::cancel_skip_next_renewal)
+                            .service(handlers::me::reactivate_after_suspension)
+                            .service(handlers::ticket::report_unrecognized_charge)
+                            .service(handlers::invoice::update_billing_details)
+                            .service(handlers::merchant_webhook::create_webhook_endpoint)
+                            .service(handlers::merchant_webhook::list_webhook_endpoints)
+                            .service(handlers::merchant_webhook::delete_webhook_endpoint)
+                            .service(handlers::merchant_webhook::list_webhook_deliveries)
+                    )
+                    .service(
+                        web::scope("/plans")
+                            .service(handlers::plan::get_plans)
+                    )
+                    .service(
+                        web::scope("/entitlements")
+                            .service(handlers::entitlement::get_user_features)
+                            .service(handlers::entitlement::check_user_feature)
+                    )
+                    .service(
+                        web::scope("/attachments")
+                            .service(handlers::attachment::download_attachment)
+                    )
+                    .service(
+                        web::scope("/api-keys")
+                            .service(handlers::api_key::get_current_api_key)
+                    )
+                    .service(
+                        web::scope("/webhooks")
+                            .service(handlers::webhook::list_webhook_events)
+                            .service(handlers::webhook::replay_webhook_event)
+                            .service(handlers::webhook::get_result_code_metrics)
+                    )
+                    .service(
+                        web::scope("/admin")
+                            // Room for an attachment upload plus some slack
+                            .app_data(web::PayloadConfig::new(MAX_ATTACHMENT_BYTES + 1024))
+                            .service(handlers::consistency::get_consistency_report)
+                            .service(handlers::consistency::get_schema_report)
+                            .service(handlers::consistency::recompute_subscription_dates)
+                            .service(handlers::plan::admin_list_plans)
+                            .service(handlers::plan::admin_create_plan)
+                            .service(handlers::plan::admin_update_plan)
+                            .service(handlers::plan::admin_delete_plan)
+                            .service(handlers::entitlement::admin_list_plan_features)
+                            .service(handlers::entitlement::admin_set_plan_feature)
+                            .service(handlers::entitlement::admin_delete_plan_feature)
+                            .service(handlers::payment_method::admin_list_payment_method_rules)
+                            .service(handlers::payment_method::admin_set_payment_method_rule)
+                            .service(handlers::payment_method::admin_delete_payment_method_rule)
+                            .service(handlers::support::get_user_notes)
+                            .service(handlers::support::add_user_note)
+                            .service(handlers::support::get_subscription_notes)
+                            .service(handlers::support::add_subscription_note)
+                            .service(handlers::support::update_note)
+                            .service(handlers::support::delete_note)
+                            .service(handlers::support::set_user_tags)
+                            .service(handlers::support::set_subscription_tags)
+                            .service(handlers::support::search_support)
+                            .service(handlers::support::get_user_timeline)
+                            .service(handlers::ticket::list_tickets)
+                            .service(handlers::ticket::create_ticket)
+                            .service(handlers::ticket::get_ticket)
+                            .service(handlers::ticket::update_ticket)
+                            .service(handlers::job::list_jobs)
+                            .service(handlers::job::get_job)
+                            .service(handlers::job::retry_job)
+                            .service(handlers::domain_event::list_domain_events)
+                            .service(handlers::domain_event::get_domain_event)
+                            .service(handlers::domain_event::redeliver_domain_event)
+                            .service(handlers::invoice::get_merchant_details)
+                            .service(handlers::invoice::update_merchant_details)
+                            .service(handlers::template::list_templates)
+                            .service(handlers::template::set_template)
+                            .service(handlers::template::delete_template)
+                            .service(handlers::audit::list_audit_log)
+                            .service(handlers::impersonation::start_impersonation)
+                            .service(handlers::impersonation::end_impersonation)
+                            .service(handlers::api_key::list_api_keys)
+                            .service(handlers::api_key::create_api_key)
+                            .service(handlers::api_key::rotate_api_key)
+                            .service(handlers::api_key::revoke_api_key)
+                            .service(handlers::tenant::list_tenants)
+                            .service(handlers::tenant::create_tenant)
+                            .service(handlers::tenant::get_tenant)
+                            .service(handlers::tenant::update_tenant)
+                            .service(handlers::report::get_daily_report)
+                            .service(handlers::report::list_export_runs)
+                            .service(handlers::reconciliation::upload_settlement_file)
+                            .service(handlers::reconciliation::list_reconciliation_reports)
+                            .service(handlers::reconciliation::get_reconciliation_report)
+                            .service(handlers::metrics::get_mrr_metrics)
+                            .service(handlers::metrics::get_subscription_metrics)
+                            .service(handlers::metrics::get_payment_metrics)
+                            .service(handlers::manual_payment::record_manual_payment)
+                            .service(handlers::attachment::upload_attachment)
+                            .service(handlers::attachment::get_attachment)
+                            .service(handlers::attachment::list_payment_attachments)
+                            .configure(|cfg| {
+                                // Test deployments only; real providers have no outbox
+                                if container.email.is_in_memory() {
+                                    cfg.service(handlers::outbox::list_sent_emails)
+                                        .service(handlers::outbox::clear_sent_emails);
+                                }
+                            })
+                            .service(handlers::billing_run::run_billing_smoke_test)
+                            .service(handlers::scenario::run_scenario)
+                            .service(handlers::token_migration::start_token_migration)
+                            .service(handlers::token_migration::list_token_migrations)
+                            .service(handlers::token_migration::get_token_migration)
+                            .service(handlers::bulk::preview_bulk_operation)
+                            .service(handlers::bulk::confirm_bulk_operation)
+                            .service(handlers::bulk::get_bulk_operation)
                     )
                        .service(
                         web::scope("/notifications")
+                            .service(handlers::notification::get_notification_preferences)
+                            .service(handlers::notification::get_unread_count)
+                            .service(handlers::notification::acknowledge_all_notifications)
+                            .service(handlers::notification::update_notification_preferences)
                             .service(handlers::notification::get_notifications)
                             .service(handlers::notification::mark_notification_read)
                             .service(handlers::notification::create_test_notification)
                     )
             )
     })
+    .shutdown_timeout(shutdown_timeout)
     .bind(&bind_address)?
     .run()
-    .await
+    .await?;
+
+    info!("HTTP server stopped; waiting for background tasks");
+    container.shutdown(background_tasks).await;
+    info!("Shutdown complete");
+    Ok(())
 }
@@ -0,0 +1,292 @@
+use actix_web::body::{to_bytes, BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::{ErrorInternalServerError, InternalError};
+use actix_web::http::header::{HeaderName, HeaderValue, CONTENT_LANGUAGE, CONTENT_TYPE};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::web::{Bytes, Data};
+use actix_web::{Error, HttpMessage, HttpResponse};
+use chrono::{Duration, Utc};
+use tracing::{error, info_span, Instrument};
+use uuid::Uuid;
+use crate::config::AppConfig;
+use crate::models::api_key::{hash_key, ApiKey, API_KEY_HEADER};
+use crate::models::impersonation::{hash_token, IMPERSONATION_TOKEN_HEADER};
+use crate::services::audit::{self, AuditContext};
+use crate::services::database::DatabaseService;
+use crate::services::formatting::{Formatting, Locale};
+use crate::services::i18n;
+use crate::services::request_signing::RequestSigner;
+use crate::services::tenant;
+use crate::telemetry::{with_request_id, REQUEST_ID_HEADER};
+
+fn header<'a>(req: &'a ServiceRequest, name: &str) -> &'a str {
+    req.headers().get(name).and_then(|v| v.to_str().ok()).unwrap_or("")
+}
+
+fn unauthorized(reason: String) -> Error {
+    let response = HttpResponse::Unauthorized().json(serde_json::json!({
+        "error": "Invalid request signature",
+        "details": reason
+    }));
+    InternalError::from_response("request signature rejected", response).into()
+}
+
+/// Requires an HMAC signature on high-value routes (see `RequestSigner`).
+/// Wrap individual routes with `wrap = "from_fn(require_signed_request)"`.
+pub async fn require_signed_request(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let signer = req.app_data::<Data<RequestSigner>>().cloned();
+
+    if let Some(signer) = signer.filter(|s| s.is_enabled()) {
+        let body = req.extract::<Bytes>().await?;
+
+        let key_id = header(&req, "X-Key-Id");
+        let timestamp = header(&req, "X-Timestamp");
+        let signature = header(&req, "X-Signature");
+        if key_id.is_empty() || timestamp.is_empty() || signature.is_empty() {
+            return Err(unauthorized("X-Key-Id, X-Timestamp and X-Signature are required".to_string()));
+        }
+
+        let path_and_query = req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        if let Err(reason) = signer.verify(
+            key_id,
+            timestamp,
+            signature,
+            req.method().as_str(),
+            path_and_query,
+            &body,
+            Utc::now().timestamp(),
+        ).await {
+            error!("Rejected signed request to {} with key {}: {}", path_and_query, key_id, reason);
+            return Err(unauthorized(reason));
+        }
+
+        // The handler still needs the body we consumed
+        req.set_payload(Payload::from(body));
+    }
+
+    next.call(req).await
+}
+
+/// Gives every request a correlation ID: the caller's `X-Request-Id` when
+/// it sends a sensible one, otherwise a new UUID. Everything logged while
+/// handling the request, including database and Peach calls, runs inside a
+/// span carrying it, and the ID is echoed back on the response.
+pub async fn request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let id = Some(header(&req, REQUEST_ID_HEADER))
+        .filter(|id| !id.is_empty() && id.len() <= 128)
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let span = info_span!(
+        "request",
+        request_id = %id,
+        method = %req.method(),
+        path = %req.path(),
+        merchant_transaction_id = tracing::field::Empty,
+    );
+
+    let mut res = with_request_id(id.clone(), next.call(req)).instrument(span).await?;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        res.headers_mut().insert(HeaderName::from_static("x-request-id"), value);
+    }
+    Ok(res)
+}
+
+/// How stale an API key's `last_used_at` may get before a request updates it.
+const API_KEY_USE_RESOLUTION_MINUTES: i64 = 5;
+
+fn refused(status: StatusCode, message: &str) -> Error {
+    let response = HttpResponse::build(status).json(serde_json::json!({
+        "error": message
+    }));
+    InternalError::from_response("credentials refused", response).into()
+}
+
+/// Lets support act as a user with a token from `POST /admin/impersonate/{user_id}`.
+/// The request runs as that user: `X-User-Id` is replaced, any admin token
+/// is dropped and the session is kept for the audit log. Responses carry
+/// the session id so the PWA can show that support is viewing the account.
+pub async fn impersonation(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let token = header(&req, IMPERSONATION_TOKEN_HEADER).trim().to_string();
+    if token.is_empty() {
+        return next.call(req).await;
+    }
+
+    let db = req.app_data::<Data<DatabaseService>>().cloned()
+        .ok_or_else(|| ErrorInternalServerError("Database unavailable"))?;
+    let session = match db.get_impersonation_session_by_token(&hash_token(&token)).await {
+        Some(session) if session.is_active(Utc::now()) => session,
+        _ => return Err(refused(StatusCode::UNAUTHORIZED, "Impersonation session has expired or was revoked")),
+    };
+    if !session.allows(req.method().as_str()) {
+        return Err(refused(StatusCode::FORBIDDEN, "Impersonation session is read-only"));
+    }
+
+    let user_id = HeaderValue::from_str(&session.user_id).map_err(ErrorInternalServerError)?;
+    let session_id = HeaderValue::from_str(&session.id).map_err(ErrorInternalServerError)?;
+    req.headers_mut().insert(HeaderName::from_static("x-user-id"), user_id);
+    req.headers_mut().remove("X-Admin-Token");
+    req.extensions_mut().insert(session);
+
+    let mut res = next.call(req).await?;
+    res.headers_mut().insert(HeaderName::from_static("x-impersonation-session"), session_id);
+    Ok(res)
+}
+
+/// Authenticates `X-Api-Key` for server-to-server callers. Unknown and
+/// revoked keys get 401 and requests outside the key's scopes 403; a valid
+/// key is kept on the request for `AdminAuth`, `ApiKeyAuth` and the audit log.
+pub async fn api_key_auth(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let key = header(&req, API_KEY_HEADER).trim().to_string();
+    if key.is_empty() {
+        return next.call(req).await;
+    }
+
+    let db = req.app_data::<Data<DatabaseService>>().cloned()
+        .ok_or_else(|| ErrorInternalServerError("Database unavailable"))?;
+    let Some(api_key) = db.authenticate_api_key(&hash_key(&key)).await else {
+        return Err(refused(StatusCode::UNAUTHORIZED, "Invalid API key"));
+    };
+    if !api_key.allows(req.method().as_str(), req.path()) {
+        return Err(refused(StatusCode::FORBIDDEN, "API key scopes don't allow this request"));
+    }
+
+    let recently_used = api_key.last_used_at
+        .is_some_and(|at| Utc::now() - at < Duration::minutes(API_KEY_USE_RESOLUTION_MINUTES));
+    if !recently_used {
+        db.touch_api_key(&api_key.id).await;
+    }
+    req.extensions_mut().insert(api_key);
+    next.call(req).await
+}
+
+/// Resolves the tenant the request acts as, from its API key's tenant or
+/// else the Host subdomain under `TENANT_BASE_DOMAIN`, and runs the request
+/// within it (see `services::tenant`). Unknown subdomains get 404, disabled
+/// tenants and a key used on another tenant's subdomain 403. Requests with
+/// neither run outside tenants.
+pub async fn tenant(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let key_tenant = req.extensions().get::<ApiKey>().and_then(|key| key.tenant_id.clone());
+    let host_tenant = req.app_data::<Data<AppConfig>>()
+        .and_then(|config| config.tenant_base_domain.clone())
+        .and_then(|base| tenant::subdomain(req.connection_info().host(), &base));
+    if key_tenant.is_none() && host_tenant.is_none() {
+        return next.call(req).await;
+    }
+
+    let db = req.app_data::<Data<DatabaseService>>().cloned()
+        .ok_or_else(|| ErrorInternalServerError("Database unavailable"))?;
+    let by_host = match &host_tenant {
+        Some(subdomain) => match db.get_tenant_by_subdomain(subdomain).await {
+            Some(tenant) => Some(tenant),
+            None => return Err(refused(StatusCode::NOT_FOUND, "Unknown tenant")),
+        },
+        None => None,
+    };
+    let resolved = match (key_tenant, by_host) {
+        (Some(key_tenant), Some(by_host)) if key_tenant != by_host.id => {
+            return Err(refused(StatusCode::FORBIDDEN, "API key belongs to another tenant"));
+        }
+        (Some(key_tenant), None) => db.get_tenant(&key_tenant).await,
+        (_, by_host) => by_host,
+    };
+    let Some(current) = resolved else {
+        return Err(refused(StatusCode::FORBIDDEN, "Unknown tenant"));
+    };
+    if current.disabled_at.is_some() {
+        return Err(refused(StatusCode::FORBIDDEN, "Tenant is disabled"));
+    }
+
+    tenant::with_tenant(Some(current), next.call(req)).await
+}
+
+/// Captures the caller, IP address and User-Agent for audit entries written
+/// while the request is handled.
+pub async fn audit_context(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let context = AuditContext::from_request(req.request());
+    audit::with_context(context, next.call(req)).await
+}
+
+/// Translates the `error` message of JSON error responses into the caller's
+/// language: the one saved on their profile, else Accept-Language. Messages
+/// the catalog doesn't have are left in English.
+pub async fn localize_errors(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let db = req.app_data::<Data<DatabaseService>>().cloned();
+    let user_id = Some(header(&req, "X-User-Id").trim())
+        .filter(|id| !id.is_empty())
+        .map(str::to_string);
+    let requested = Formatting::from_request(req.request()).locale;
+
+    let res = next.call(req).await?;
+    let is_json = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if !is_json || !(res.status().is_client_error() || res.status().is_server_error()) {
+        return Ok(res.map_into_boxed_body());
+    }
+
+    let profile_locale = match (db, user_id) {
+        (Some(db), Some(user_id)) => db.get_user(&user_id).await.and_then(|u| u.locale),
+        _ => None,
+    };
+    let locale = profile_locale.as_deref().and_then(Locale::from_tag).unwrap_or(requested);
+
+    let (req, res) = res.into_parts();
+    let (res, body) = res.into_parts();
+    let bytes = to_bytes(body).await.map_err(|e| {
+        let e: Box<dyn std::error::Error> = e.into();
+        ErrorInternalServerError(e.to_string())
+    })?;
+
+    let translated = serde_json::from_slice::<serde_json::Value>(&bytes).ok().and_then(|mut json| {
+        let mut changed = false;
+        if let Some(translation) = json.get("error").and_then(|e| e.as_str()).and_then(|e| i18n::lookup(locale, e)) {
+            json["error"] = translation.into();
+            changed = true;
+        }
+        // Per-field messages of a 422 from `ValidatedJson`
+        if let Some(fields) = json.get_mut("fields").and_then(|f| f.as_object_mut()) {
+            for message in fields.values_mut().filter_map(|m| m.as_array_mut()).flatten() {
+                if let Some(translation) = message.as_str().and_then(|m| i18n::lookup(locale, m)) {
+                    *message = translation.into();
+                    changed = true;
+                }
+            }
+        }
+        if changed { serde_json::to_vec(&json).ok() } else { None }
+    });
+
+    let res = match translated {
+        Some(body) => {
+            let mut res = res.set_body(BoxBody::new(body));
+            res.headers_mut().insert(CONTENT_LANGUAGE, HeaderValue::from_static(locale.tag()));
+            res
+        }
+        None => res.set_body(BoxBody::new(bytes)),
+    };
+    Ok(ServiceResponse::new(req, res))
+}
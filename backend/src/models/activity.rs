@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityCategory {
+    Payment,
+    Subscription,
+    Security,
+}
+
+/// Append-only record of user-visible account activity (subscription changes,
+/// security events). Payments are read straight from the payments table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityEvent {
+    pub id: String,
+    pub user_id: String,
+    pub category: ActivityCategory,
+    pub kind: String,
+    pub description: String,
+    pub reference_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
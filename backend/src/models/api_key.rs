@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use crate::models::validation::{FieldErrors, Validate};
+
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// How long a rotated-out secret keeps working, so integrations can deploy
+/// the new one without downtime.
+pub const ROTATION_OVERLAP_HOURS: i64 = 24;
+
+/// Characters of the key kept in the clear to tell keys apart.
+pub const API_KEY_PREFIX_LEN: usize = 12;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeyScope {
+    /// GET and HEAD outside the admin API.
+    ReadOnly,
+    /// Anything outside the admin API: payments, subscriptions, refunds.
+    Payments,
+    /// Everything, including admin routes.
+    Admin,
+}
+
+/// A credential for a merchant backend calling the API server-to-server.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    /// The start of the key, e.g. `pwa_1a2b3c4d`, to recognise it by.
+    pub prefix: String,
+    pub scopes: Vec<ApiKeyScope>,
+    #[serde(skip_serializing, default)]
+    pub key_hash: String,
+    /// Hash of the secret replaced by the last rotation, accepted until
+    /// `previous_expires_at`.
+    #[serde(skip_serializing, default)]
+    pub previous_key_hash: Option<String>,
+    #[serde(default)]
+    pub previous_expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_used_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub rotated_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// The tenant every request made with the key acts as; unset for keys
+    /// of the deployment itself.
+    #[serde(default)]
+    pub tenant_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ApiKey {
+    pub fn has_scope(&self, scope: ApiKeyScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    /// Whether the key's scopes cover a request. `path` is the full path,
+    /// including `/api/v1`.
+    pub fn allows(&self, method: &str, path: &str) -> bool {
+        let admin_route = path.starts_with("/api/v1/admin");
+        self.scopes.iter().any(|scope| match scope {
+            ApiKeyScope::Admin => true,
+            ApiKeyScope::Payments => !admin_route,
+            ApiKeyScope::ReadOnly => !admin_route && matches!(method, "GET" | "HEAD"),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateApiKeyDto {
+    /// e.g. the integration it's for.
+    pub name: String,
+    pub scopes: Vec<ApiKeyScope>,
+    /// Issues the key for a tenant. Keys created within a tenant always
+    /// belong to it.
+    pub tenant_id: Option<String>,
+}
+
+impl Validate for CreateApiKeyDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        if self.name.trim().is_empty() {
+            errors.add("name", "Name can't be empty");
+        }
+        if self.scopes.is_empty() {
+            errors.add("scopes", "At least one scope is required");
+        }
+        errors.into_result()
+    }
+}
+
+/// A new or rotated key with its secret, which isn't shown again. Send it
+/// as `X-Api-Key`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiKeyCreated {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    pub key: String,
+}
+
+pub fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
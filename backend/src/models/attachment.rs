@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{ToSchema, IntoParams};
+use chrono::{DateTime, Utc};
+
+/// Largest file accepted by the upload endpoint.
+pub const MAX_ATTACHMENT_BYTES: usize = 5 * 1024 * 1024;
+
+/// Content types accepted for uploads; scans and bank confirmations are PDFs
+/// or photos.
+pub const ALLOWED_ATTACHMENT_TYPES: &[&str] = &["application/pdf", "image/png", "image/jpeg"];
+
+/// What an attachment belongs to. Unlinked uploads are allowed so proof can
+/// be uploaded before the record it supports exists.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachmentOwner {
+    /// Keyed by merchant transaction id.
+    Payment,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Attachment {
+    pub id: String,
+    #[serde(default)]
+    pub owner_type: Option<AttachmentOwner>,
+    #[serde(default)]
+    pub owner_id: Option<String>,
+    pub filename: String,
+    pub content_type: String,
+    pub size_bytes: u64,
+    /// Hex SHA-256 of the contents.
+    pub sha256: String,
+    /// Where the bytes live in the attachment store.
+    pub storage_key: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UploadAttachmentQuery {
+    pub filename: String,
+    #[serde(default)]
+    pub owner_type: Option<AttachmentOwner>,
+    #[serde(default)]
+    pub owner_id: Option<String>,
+}
+
+/// Checks the declared type is allowed and that the bytes actually look like it.
+pub fn validate_attachment(content_type: &str, bytes: &[u8]) -> Result<(), String> {
+    if bytes.is_empty() {
+        return Err("Attachment is empty".to_string());
+    }
+    if bytes.len() > MAX_ATTACHMENT_BYTES {
+        return Err(format!("Attachment is larger than {} bytes", MAX_ATTACHMENT_BYTES));
+    }
+
+    let magic: &[u8] = match content_type {
+        "application/pdf" => b"%PDF-",
+        "image/png" => b"\x89PNG\r\n\x1a\n",
+        "image/jpeg" => b"\xff\xd8\xff",
+        other => {
+            return Err(format!(
+                "Unsupported attachment type {} (allowed: {})",
+                other,
+                ALLOWED_ATTACHMENT_TYPES.join(", ")
+            ))
+        }
+    };
+    if !bytes.starts_with(magic) {
+        return Err(format!("File contents are not {}", content_type));
+    }
+    Ok(())
+}
+
+/// Strips directories and anything unsafe in a header from an uploaded name.
+pub fn sanitize_filename(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let cleaned: String = base
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | ' '))
+        .take(120)
+        .collect();
+    let cleaned = cleaned.trim().trim_start_matches('.').to_string();
+    if cleaned.is_empty() {
+        "attachment".to_string()
+    } else {
+        cleaned
+    }
+}
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditActorType {
+    User,
+    Admin,
+    /// A request without credentials, e.g. a gateway webhook.
+    Anonymous,
+    /// Background jobs and scheduled tasks.
+    System,
+    /// A server-to-server integration authenticated with `X-Api-Key`.
+    ApiKey,
+}
+
+/// One change to a user, subscription or payment: who made it, from where,
+/// and the record before and after.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuditEntry {
+    pub id: String,
+    pub actor_type: AuditActorType,
+    /// The user id for `user` actors, the key id for `api_key` actors.
+    pub actor_id: Option<String>,
+    /// e.g. `subscription.cancelled` or `payment.status_changed`.
+    pub action: String,
+    /// `users`, `subscriptions`, `payments` or `api_keys`.
+    pub entity_type: String,
+    pub entity_id: String,
+    /// `None` for records the change created.
+    #[schema(value_type = Option<Object>)]
+    pub old_value: Option<serde_json::Value>,
+    #[schema(value_type = Option<Object>)]
+    pub new_value: Option<serde_json::Value>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+    /// Set when support staff made the change while impersonating a user.
+    #[serde(default)]
+    pub impersonation_session_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Filters for the admin audit log query; unset fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub actor_type: Option<AuditActorType>,
+    pub actor_id: Option<String>,
+    pub action: Option<String>,
+    pub entity_type: Option<String>,
+    pub entity_id: Option<String>,
+    pub impersonation_session_id: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use std::collections::BTreeMap;
+use crate::models::validation::{FieldErrors, Validate};
+use crate::tasks::renewal_task::RenewalOutcome;
+
+/// Most synthetic subscriptions a single smoke run may create.
+pub const MAX_BILLING_RUN_SUBSCRIPTIONS: u32 = 1000;
+
+fn default_count() -> u32 {
+    50
+}
+
+fn default_price() -> f64 {
+    99.0
+}
+
+fn default_soft_decline_percent() -> u32 {
+    10
+}
+
+fn default_hard_decline_percent() -> u32 {
+    5
+}
+
+/// A sandbox billing run: `count` due subscriptions, a share of which carry
+/// cards that decline.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct BillingRunDto {
+    #[serde(default = "default_count")]
+    pub count: u32,
+    #[serde(default = "default_price")]
+    pub price: f64,
+    #[serde(default = "default_soft_decline_percent")]
+    pub soft_decline_percent: u32,
+    #[serde(default = "default_hard_decline_percent")]
+    pub hard_decline_percent: u32,
+}
+
+impl Validate for BillingRunDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        if self.count == 0 || self.count > MAX_BILLING_RUN_SUBSCRIPTIONS {
+            errors.add("count", format!("must be between 1 and {}", MAX_BILLING_RUN_SUBSCRIPTIONS));
+        }
+        if !self.price.is_finite() || self.price <= 0.0 {
+            errors.add("price", "must be positive");
+        }
+        if self.soft_decline_percent + self.hard_decline_percent > 100 {
+            errors.add("hard_decline_percent", "Decline percentages must add up to at most 100");
+        }
+        errors.into_result()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BillingRunFailure {
+    pub subscription_id: Option<String>,
+    pub error: String,
+}
+
+/// Outcome of a smoke run. Setup time is excluded from `duration_ms` and
+/// `throughput_per_sec`, which only cover the renewal pipeline.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BillingRunReport {
+    pub run_id: String,
+    /// Subscriptions tagged with this are the run's; it's also on the users' emails.
+    pub tag: String,
+    pub requested: u32,
+    pub processed: usize,
+    pub duration_ms: u128,
+    pub throughput_per_sec: f64,
+    pub outcomes: BTreeMap<RenewalOutcome, usize>,
+    /// Subscription statuses once the run finished, e.g. `Active` for renewed.
+    pub final_statuses: BTreeMap<String, usize>,
+    /// Setup errors and renewals that couldn't be recorded.
+    pub failures: Vec<BillingRunFailure>,
+}
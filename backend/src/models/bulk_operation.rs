@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use crate::models::subscription::SubscriptionStatus;
+use crate::models::validation::{FieldErrors, Validate};
+
+/// Most subscriptions a single bulk operation may touch; narrow the filter
+/// for more.
+pub const MAX_BULK_SUBSCRIPTIONS: usize = 10_000;
+
+/// How long a preview can be confirmed for. Confirming later needs a new
+/// preview, so what runs is what was looked at.
+pub const BULK_PREVIEW_TTL_MINUTES: i64 = 15;
+
+/// Longest extension a bulk operation may grant.
+pub const MAX_BULK_EXTENSION_DAYS: u32 = 365;
+
+/// What a bulk operation does to each subscription it matched.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BulkAction {
+    /// Push the end of the current period (and of grace) back by `days`,
+    /// without a charge.
+    ExtendPeriod { days: u32 },
+    /// Cancel straight away.
+    Cancel {
+        #[serde(default)]
+        reason: Option<String>,
+    },
+}
+
+/// Which subscriptions a bulk operation applies to. Every condition given must
+/// hold; at least one is required. Cancelled and expired subscriptions never
+/// match.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct BulkFilter {
+    #[serde(default)]
+    pub status: Option<SubscriptionStatus>,
+    #[serde(default)]
+    pub plan_id: Option<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Only subscriptions created more than this many days ago, e.g. pending
+    /// checkouts that were never paid.
+    #[serde(default)]
+    pub created_more_than_days_ago: Option<u32>,
+}
+
+impl BulkFilter {
+    fn is_empty(&self) -> bool {
+        self.status.is_none()
+            && self.plan_id.is_none()
+            && self.tag.is_none()
+            && self.created_more_than_days_ago.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct CreateBulkOperationDto {
+    pub action: BulkAction,
+    pub filter: BulkFilter,
+}
+
+impl Validate for CreateBulkOperationDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        if self.filter.is_empty() {
+            errors.add("filter", "must set at least one condition");
+        }
+        if matches!(self.filter.status, Some(SubscriptionStatus::Cancelled | SubscriptionStatus::Expired)) {
+            errors.add("filter.status", "Cancelled and expired subscriptions can't be changed in bulk");
+        }
+        if let BulkAction::ExtendPeriod { days } = self.action {
+            if days == 0 || days > MAX_BULK_EXTENSION_DAYS {
+                errors.add("action.days", format!("must be between 1 and {}", MAX_BULK_EXTENSION_DAYS));
+            }
+        }
+        errors.into_result()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub enum BulkOperationStatus {
+    /// Matched and waiting to be confirmed.
+    Previewed,
+    Running,
+    Completed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BulkFailure {
+    pub subscription_id: String,
+    pub error: String,
+}
+
+/// A bulk change to subscriptions. Creating one only previews it: the
+/// matching subscriptions are listed and nothing changes until it is
+/// confirmed. Each subscription is checked against the filter again when the
+/// operation runs, and skipped if it no longer matches.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BulkOperation {
+    pub id: String,
+    pub action: BulkAction,
+    pub filter: BulkFilter,
+    pub status: BulkOperationStatus,
+    /// Subscriptions matched by the preview; the operation only ever touches these.
+    pub subscription_ids: Vec<String>,
+    pub matched: u64,
+    #[serde(default)]
+    pub processed: u64,
+    #[serde(default)]
+    pub succeeded: u64,
+    /// No longer matched the filter when the operation reached them.
+    #[serde(default)]
+    pub skipped: u64,
+    #[serde(default)]
+    pub failures: Vec<BulkFailure>,
+    pub created_at: DateTime<Utc>,
+    /// Confirming after this needs a new preview.
+    pub expires_at: DateTime<Utc>,
+    #[serde(default)]
+    pub confirmed_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub completed_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use crate::models::validation::Validate;
+
+/// Merchant transaction ids of card-update checkouts start with this, so
+/// their webhooks can be told apart from payments.
+pub const CARD_UPDATE_PREFIX: &str = "CARD_";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub enum CardUpdateStatus {
+    Pending,
+    Completed,
+    Failed,
+}
+
+/// A registration-only checkout replacing a user's saved card. The current
+/// card stays active until the gateway confirms the new one.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CardUpdate {
+    pub id: String,
+    pub user_id: String,
+    pub subscription_id: String,
+    pub merchant_transaction_id: String,
+    pub gateway: String,
+    pub status: CardUpdateStatus,
+    pub result_code: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CardUpdateRequest {
+    pub user_id: String,
+    pub subscription_id: String,
+}
+
+impl Validate for CardUpdateRequest {}
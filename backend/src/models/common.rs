@@ -0,0 +1,41 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+use crate::handlers::notification::NotificationResponse;
+use crate::models::audit::AuditEntry;
+use crate::models::payment::Payment;
+
+/// Largest page any list endpoint returns.
+pub const MAX_PAGE_LIMIT: u32 = 100;
+
+pub const DEFAULT_PAGE_LIMIT: u32 = 20;
+
+/// One page of a list, numbered from 1.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[aliases(
+    PaginatedPayments = PaginatedResponse<Payment>,
+    PaginatedNotifications = PaginatedResponse<NotificationResponse>,
+    PaginatedAuditEntries = PaginatedResponse<AuditEntry>,
+)]
+pub struct PaginatedResponse<T> {
+    pub items: Vec<T>,
+    pub page: u32,
+    pub limit: u32,
+    /// Matching items across all pages.
+    pub total: u64,
+    pub has_more: bool,
+}
+
+impl<T> PaginatedResponse<T> {
+    pub fn new(items: Vec<T>, page: u32, limit: u32, total: u64) -> Self {
+        let has_more = (page as u64) * (limit as u64) < total;
+        Self { items, page, limit, total, has_more }
+    }
+}
+
+/// Page and limit from a query, defaulted and clamped to `MAX_PAGE_LIMIT`.
+pub fn page_and_limit(page: Option<u32>, limit: Option<u32>) -> (u32, u32) {
+    (
+        page.unwrap_or(1).max(1),
+        limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT),
+    )
+}
@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+
+// Event types; stable names that downstream consumers subscribe to
+pub const PAYMENT_COMPLETED: &str = "payment.completed";
+pub const SUBSCRIPTION_RENEWED: &str = "subscription.renewed";
+pub const SUBSCRIPTION_SUSPENDED: &str = "subscription.suspended";
+
+pub const EVENT_TYPES: &[&str] = &[PAYMENT_COMPLETED, SUBSCRIPTION_RENEWED, SUBSCRIPTION_SUSPENDED];
+
+/// Delivery attempts before an event is left failed for an admin to redeliver.
+pub const MAX_EVENT_ATTEMPTS: u32 = 10;
+
+/// How long a dispatcher holds an event it is delivering. An event still
+/// unpublished after this is assumed lost with its dispatcher and sent again.
+pub const EVENT_LEASE_SECONDS: i64 = 120;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DomainEventStatus {
+    /// Waiting for `next_attempt_at`, including between retries.
+    Pending,
+    /// Accepted by every configured sink.
+    Published,
+    /// Out of attempts; redelivered only by an admin.
+    Failed,
+}
+
+/// A change to a payment or subscription, written to the outbox in the same
+/// transaction as the change itself and published to the configured sinks
+/// by the dispatcher. Delivery is at least once: consumers dedupe on `id`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DomainEvent {
+    pub id: String,
+    /// e.g. `payment.completed`.
+    pub event_type: String,
+    /// Id of the payment or subscription the event is about.
+    pub aggregate_id: String,
+    pub payload: serde_json::Value,
+    pub status: DomainEventStatus,
+    /// Delivery attempts started so far.
+    #[serde(default)]
+    pub attempts: u32,
+    pub next_attempt_at: DateTime<Utc>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub published_at: Option<DateTime<Utc>>,
+    /// When the change happened.
+    pub created_at: DateTime<Utc>,
+}
+
+impl DomainEvent {
+    /// The body sent to sinks.
+    pub fn envelope(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "type": self.event_type,
+            "aggregate_id": self.aggregate_id,
+            "occurred_at": self.created_at,
+            "data": self.payload,
+        })
+    }
+}
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use crate::models::subscription::AccessLevel;
+use crate::models::validation::Validate;
+
+/// Pseudo plan id whose features apply to downgraded subscriptions. It has
+/// no row in `plans`; its features are managed like any other plan's.
+pub const FREE_TIER_PLAN_ID: &str = "free";
+
+/// Feature keys the API itself knows about. Plans may carry other keys too.
+pub const FEATURE_MAX_PROJECTS: &str = "max_projects";
+pub const FEATURE_STORAGE_GB: &str = "storage_gb";
+
+/// One cell of the plan/feature matrix.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PlanFeature {
+    pub plan_id: String,
+    pub feature_key: String,
+    /// Upper bound on usage; `None` grants the feature without a limit.
+    pub limit: Option<u64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct SetPlanFeatureDto {
+    #[serde(default)]
+    pub limit: Option<u64>,
+}
+
+impl Validate for SetPlanFeatureDto {}
+
+/// A feature as granted to a user, after combining their subscriptions.
+#[derive(Debug, Clone, Serialize, PartialEq, ToSchema)]
+pub struct FeatureEntitlement {
+    pub key: String,
+    /// `None` means unlimited.
+    pub limit: Option<u64>,
+}
+
+impl FeatureEntitlement {
+    /// Whether `usage` more units may be held in total.
+    pub fn allows(&self, usage: u64) -> bool {
+        self.limit.map_or(true, |limit| usage <= limit)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct UserEntitlements {
+    pub user_id: String,
+    /// Best access level across the user's subscriptions.
+    pub access: AccessLevel,
+    /// Plans whose features were granted, including `free` for downgrades.
+    pub plan_ids: Vec<String>,
+    pub features: Vec<FeatureEntitlement>,
+}
+
+/// Feature keys are lowercase snake_case so they can be used in URLs as-is.
+pub fn validate_feature_key(key: &str) -> Result<(), String> {
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+        return Err(format!("Invalid feature key '{}': use lowercase letters, digits and underscores", key));
+    }
+    Ok(())
+}
+
+/// Features seeded alongside the default plans on first start.
+pub fn default_plan_features() -> Vec<(&'static str, &'static str, Option<u64>)> {
+    vec![
+        (FREE_TIER_PLAN_ID, FEATURE_MAX_PROJECTS, Some(1)),
+        (FREE_TIER_PLAN_ID, FEATURE_STORAGE_GB, Some(1)),
+        ("basic", FEATURE_MAX_PROJECTS, Some(3)),
+        ("basic", FEATURE_STORAGE_GB, Some(5)),
+        ("premium", FEATURE_MAX_PROJECTS, Some(20)),
+        ("premium", FEATURE_STORAGE_GB, Some(50)),
+        ("elite", FEATURE_MAX_PROJECTS, None),
+        ("elite", FEATURE_STORAGE_GB, Some(500)),
+    ]
+}
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportStatus {
+    Completed,
+    /// Nothing was recorded as exported; the next run covers this window too.
+    Failed,
+}
+
+/// One CSV file of an export run.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ExportFile {
+    /// `payments`, `subscriptions` or `activity_events`.
+    pub table: String,
+    /// Storage key of the file.
+    pub key: String,
+    pub rows: u64,
+    pub columns: Vec<String>,
+    /// Hex SHA-256 of the file, for load-time integrity checks.
+    pub sha256: String,
+}
+
+/// Written next to the files of each run, last, so a warehouse loader that
+/// finds a manifest knows every file it lists is complete.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ExportManifest {
+    pub run_id: String,
+    pub format: String,
+    /// Records changed after this instant are included; `None` on the first,
+    /// full run.
+    pub window_start: Option<DateTime<Utc>>,
+    /// ...up to and including this one.
+    pub window_end: DateTime<Utc>,
+    pub schema_version: u32,
+    pub files: Vec<ExportFile>,
+    pub generated_at: DateTime<Utc>,
+}
+
+/// Bookkeeping for one export run; completed runs set the next run's window.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ExportRun {
+    pub id: String,
+    pub status: ExportStatus,
+    #[serde(default)]
+    pub window_start: Option<DateTime<Utc>>,
+    pub window_end: DateTime<Utc>,
+    #[serde(default)]
+    pub manifest_key: Option<String>,
+    #[serde(default)]
+    pub files: Vec<ExportFile>,
+    #[serde(default)]
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub completed_at: DateTime<Utc>,
+}
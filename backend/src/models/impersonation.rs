@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use crate::models::validation::{FieldErrors, Validate};
+
+/// Header carrying an impersonation token in place of `X-User-Id`.
+pub const IMPERSONATION_TOKEN_HEADER: &str = "X-Impersonation-Token";
+
+pub const DEFAULT_IMPERSONATION_MINUTES: u32 = 15;
+
+pub const MAX_IMPERSONATION_MINUTES: u32 = 60;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImpersonationScope {
+    /// Only GET and HEAD requests: support can look but not change anything.
+    #[default]
+    ReadOnly,
+    /// Anything the user could do themselves.
+    Full,
+}
+
+/// Support staff acting as one user for a limited time.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ImpersonationSession {
+    pub id: String,
+    pub user_id: String,
+    /// Why support needed to see the account, e.g. a ticket reference.
+    pub reason: String,
+    pub scope: ImpersonationScope,
+    /// SHA-256 of the token; the token itself is only returned once.
+    #[serde(skip_serializing, default)]
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    #[serde(default)]
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ImpersonationSession {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none() && self.expires_at > now
+    }
+
+    pub fn allows(&self, method: &str) -> bool {
+        match self.scope {
+            ImpersonationScope::ReadOnly => matches!(method, "GET" | "HEAD"),
+            ImpersonationScope::Full => true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct StartImpersonationDto {
+    pub reason: String,
+    /// `read_only` by default.
+    #[serde(default)]
+    pub scope: ImpersonationScope,
+    /// 15 by default, at most 60.
+    pub duration_minutes: Option<u32>,
+}
+
+impl StartImpersonationDto {
+    pub fn duration_minutes(&self) -> u32 {
+        self.duration_minutes.unwrap_or(DEFAULT_IMPERSONATION_MINUTES)
+    }
+}
+
+impl Validate for StartImpersonationDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        if self.reason.trim().is_empty() {
+            errors.add("reason", "A reason is required to impersonate a user");
+        }
+        match self.duration_minutes() {
+            0 => errors.add("duration_minutes", "must be at least 1"),
+            minutes if minutes > MAX_IMPERSONATION_MINUTES => {
+                errors.add("duration_minutes", format!("must be at most {}", MAX_IMPERSONATION_MINUTES))
+            }
+            _ => {}
+        }
+        errors.into_result()
+    }
+}
+
+/// What's stored in place of a token, so a leaked database can't be used
+/// to impersonate anyone.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// A new session with its token, which isn't shown again. Send the token
+/// as `X-Impersonation-Token` instead of `X-User-Id`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImpersonationStarted {
+    #[serde(flatten)]
+    pub session: ImpersonationSession,
+    pub token: String,
+}
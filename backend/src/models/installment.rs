@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+
+/// Merchant transaction ids of installment charges start with this, so their
+/// webhooks settle the installment instead of activating a new period.
+pub const INSTALLMENT_PREFIX: &str = "INSTALL_";
+
+/// Most installments a period can be split into, one a month.
+pub const MAX_INSTALLMENTS: u32 = 12;
+
+/// Daily attempts at charging an installment before it is marked `Failed`.
+pub const MAX_INSTALLMENT_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub enum InstallmentStatus {
+    /// Waiting for the first installment's checkout; nothing is charged
+    /// unless it is paid.
+    Pending,
+    /// Charged to the saved card once `due_at` has passed.
+    Scheduled,
+    Paid,
+    /// Declined `MAX_INSTALLMENT_ATTEMPTS` times, or no card was saved.
+    Failed,
+    /// Replaced by a later checkout, or the subscription ended first.
+    Cancelled,
+}
+
+/// One of the monthly charges a subscription period is paid in. The first is
+/// paid at checkout and activates the subscription; the rest are charged to
+/// the card saved then.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Installment {
+    pub id: String,
+    /// Shared by the installments of one checkout.
+    pub schedule_id: String,
+    pub user_id: String,
+    pub subscription_id: String,
+    /// 1 for the installment paid at checkout.
+    pub sequence: u32,
+    /// Installments in the schedule.
+    pub count: u32,
+    /// VAT-inclusive.
+    pub amount: f64,
+    pub currency: String,
+    pub status: InstallmentStatus,
+    /// Set once the first installment is paid; a month apart from then on.
+    #[serde(default)]
+    pub due_at: Option<DateTime<Utc>>,
+    /// The checkout payment for the first installment, the latest charge
+    /// for the others.
+    #[serde(default)]
+    pub merchant_transaction_id: Option<String>,
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub paid_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Splits `total` into `count` amounts to the cent; the first carries any
+/// rounding remainder, so R100 over 3 is R33.34 then two of R33.33.
+pub fn split_installments(total: f64, count: u32) -> Vec<f64> {
+    let count = count.max(1);
+    let cents = (total * 100.0).round() as i64;
+    let each = cents / count as i64;
+    let first = cents - each * (count as i64 - 1);
+    std::iter::once(first)
+        .chain(std::iter::repeat_n(each, count as usize - 1))
+        .map(|c| c as f64 / 100.0)
+        .collect()
+}
+
+/// Where a subscription's installments stand.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct InstallmentSchedule {
+    pub schedule_id: String,
+    pub subscription_id: String,
+    pub currency: String,
+    pub total_amount: f64,
+    pub paid_amount: f64,
+    pub remaining_amount: f64,
+    /// Installments not paid yet, failed ones included.
+    pub remaining_count: u32,
+    pub next_due_at: Option<DateTime<Utc>>,
+    pub installments: Vec<Installment>,
+}
+
+impl InstallmentSchedule {
+    /// Summarises the installments of one schedule, in sequence order.
+    pub fn from_installments(mut installments: Vec<Installment>) -> Option<Self> {
+        installments.sort_by_key(|i| i.sequence);
+        let first = installments.first()?;
+        let round = |amount: f64| (amount * 100.0).round() / 100.0;
+        let owed = |i: &&Installment| !matches!(i.status, InstallmentStatus::Paid | InstallmentStatus::Cancelled);
+
+        let total_amount = round(installments.iter().map(|i| i.amount).sum());
+        let paid_amount = round(installments.iter()
+            .filter(|i| i.status == InstallmentStatus::Paid)
+            .map(|i| i.amount)
+            .sum());
+        Some(Self {
+            schedule_id: first.schedule_id.clone(),
+            subscription_id: first.subscription_id.clone(),
+            currency: first.currency.clone(),
+            total_amount,
+            paid_amount,
+            remaining_amount: round(installments.iter().filter(owed).map(|i| i.amount).sum()),
+            remaining_count: installments.iter().filter(owed).count() as u32,
+            next_due_at: installments.iter()
+                .filter(|i| i.status == InstallmentStatus::Scheduled)
+                .filter_map(|i| i.due_at)
+                .min(),
+            installments,
+        })
+    }
+}
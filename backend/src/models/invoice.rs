@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct InvoiceLineItem {
+    pub description: String,
+    pub quantity: u32,
+    /// VAT-inclusive unit price.
+    pub unit_price: f64,
+    /// VAT-inclusive line total.
+    pub amount: f64,
+}
+
+/// Tax invoice issued once per successful payment. Numbers are sequential
+/// and never reused; `payment_reference` ties the invoice to the charge.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Invoice {
+    pub id: String,
+    pub invoice_number: String,
+    pub user_id: String,
+    pub subscription_id: Option<String>,
+    pub payment_reference: String,
+    pub seller_name: String,
+    pub seller_vat_number: Option<String>,
+    #[serde(default)]
+    pub seller_registration_number: Option<String>,
+    #[serde(default)]
+    pub seller_address: Option<String>,
+    pub customer_name: String,
+    pub customer_email: String,
+    /// Required on full tax invoices over R5,000.
+    #[serde(default)]
+    pub customer_address: Option<String>,
+    /// Set when the customer is a VAT vendor.
+    #[serde(default)]
+    pub customer_vat_number: Option<String>,
+    pub line_items: Vec<InvoiceLineItem>,
+    pub currency: String,
+    /// Total excluding VAT.
+    pub subtotal: f64,
+    pub vat_rate_percent: u32,
+    pub vat_amount: f64,
+    /// Total including VAT, i.e. the amount charged.
+    pub total: f64,
+    pub issued_at: DateTime<Utc>,
+}
+
+/// Everything needed to issue an invoice; number and id are assigned on insert.
+#[derive(Debug, Clone)]
+pub struct NewInvoice {
+    pub user_id: String,
+    pub subscription_id: Option<String>,
+    pub payment_reference: String,
+    pub seller_name: String,
+    pub seller_vat_number: Option<String>,
+    pub seller_registration_number: Option<String>,
+    pub seller_address: Option<String>,
+    pub customer_name: String,
+    pub customer_email: String,
+    pub customer_address: Option<String>,
+    pub customer_vat_number: Option<String>,
+    pub line_items: Vec<InvoiceLineItem>,
+    pub currency: String,
+    pub subtotal: f64,
+    pub vat_rate_percent: u32,
+    pub vat_amount: f64,
+    pub total: f64,
+}
+
+/// Credit owed to a customer, e.g. the unused part of a plan they downgraded
+/// from. Amounts are VAT-inclusive, split like an invoice's.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreditNote {
+    pub id: String,
+    /// Sequential like invoice numbers, in its own `CN-` series.
+    pub credit_note_number: String,
+    pub user_id: String,
+    pub subscription_id: Option<String>,
+    pub reason: String,
+    pub currency: String,
+    pub subtotal: f64,
+    pub vat_rate_percent: u32,
+    pub vat_amount: f64,
+    pub total: f64,
+    pub issued_at: DateTime<Utc>,
+}
@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Duration, Utc};
+
+/// Attempts a job gets before it is left failed for an admin to retry.
+pub const MAX_JOB_ATTEMPTS: u32 = 8;
+
+/// A job still running this long after it was claimed is assumed lost with
+/// its worker and handed out again.
+pub const JOB_LEASE_MINUTES: i64 = 10;
+
+/// A side-effect of a payment event, run by the job worker so failures are
+/// retried instead of only logged.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobPayload {
+    /// Email the payer that their payment went through.
+    PaymentSucceededEmail { merchant_transaction_id: String },
+    /// Email the payer that their payment failed.
+    PaymentFailedEmail { merchant_transaction_id: String },
+    /// Issue the tax invoice for a completed payment.
+    IssueInvoice { merchant_transaction_id: String },
+    /// Open (or add to) the failed-payment support ticket.
+    OpenFailedPaymentTicket { merchant_transaction_id: String, reason: String },
+}
+
+impl JobPayload {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            JobPayload::PaymentSucceededEmail { .. } => "payment_succeeded_email",
+            JobPayload::PaymentFailedEmail { .. } => "payment_failed_email",
+            JobPayload::IssueInvoice { .. } => "issue_invoice",
+            JobPayload::OpenFailedPaymentTicket { .. } => "open_failed_payment_ticket",
+        }
+    }
+
+    /// The payment the job is about, for filtering and log correlation.
+    pub fn reference(&self) -> &str {
+        match self {
+            JobPayload::PaymentSucceededEmail { merchant_transaction_id }
+            | JobPayload::PaymentFailedEmail { merchant_transaction_id }
+            | JobPayload::IssueInvoice { merchant_transaction_id }
+            | JobPayload::OpenFailedPaymentTicket { merchant_transaction_id, .. } => merchant_transaction_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    /// Waiting for `run_at`, including between retries.
+    Pending,
+    Running,
+    Succeeded,
+    /// Out of attempts; retried only by an admin.
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Job {
+    pub id: String,
+    /// `JobPayload::kind` of the payload, stored for filtering.
+    pub kind: String,
+    pub payload: JobPayload,
+    pub status: JobStatus,
+    /// Merchant transaction id of the payment the job is about.
+    #[serde(default)]
+    pub reference: Option<String>,
+    /// Attempts started so far, including one in progress.
+    #[serde(default)]
+    pub attempts: u32,
+    pub max_attempts: u32,
+    /// When the job is next due.
+    pub run_at: DateTime<Utc>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub completed_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Wait before the next attempt after `attempts` have failed: 30 seconds,
+/// doubling each time, capped at an hour.
+pub fn retry_delay(attempts: u32) -> Duration {
+    let seconds = 30i64 << attempts.saturating_sub(1).min(7);
+    Duration::seconds(seconds.min(3600))
+}
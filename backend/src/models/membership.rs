@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use crate::models::subscription::validate_seat_count;
+use crate::models::validation::{FieldErrors, Validate};
+
+/// Someone the owner invited onto one of their subscription's seats. The
+/// owner holds a seat of their own, so a subscription has room for
+/// `seat_count - 1` members.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SubscriptionMember {
+    pub id: String,
+    pub subscription_id: String,
+    /// Stored lowercased; unique per subscription.
+    pub email: String,
+    /// Set when the email belongs to a registered user.
+    #[serde(default)]
+    pub user_id: Option<String>,
+    /// Owner who sent the invitation.
+    pub invited_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct InviteMemberDto {
+    pub email: String,
+}
+
+impl Validate for InviteMemberDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        let email = self.email.trim();
+        if email.is_empty() || !email.contains('@') {
+            errors.add("email", "A valid email address is required");
+        }
+        errors.into_result()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct UpdateSeatsDto {
+    pub seat_count: u32,
+}
+
+impl Validate for UpdateSeatsDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        errors.check("seat_count", validate_seat_count(self.seat_count));
+        errors.into_result()
+    }
+}
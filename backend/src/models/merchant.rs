@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use crate::models::validation::{FieldErrors, Validate};
+
+/// Key of the merchant profile record. There is a single merchant today;
+/// each tenant gets its own record once tenants exist.
+pub const DEFAULT_MERCHANT_ID: &str = "default";
+
+/// Longest address stored for a merchant or customer.
+pub const MAX_ADDRESS_LEN: usize = 500;
+
+/// Supplier details printed on tax invoices and receipts. Stored in the
+/// database so they can change without a deploy; the `INVOICE_SELLER_*`
+/// settings apply until they are first saved.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct MerchantDetails {
+    pub legal_name: String,
+    #[serde(default)]
+    pub vat_number: Option<String>,
+    /// Company registration number.
+    #[serde(default)]
+    pub registration_number: Option<String>,
+    /// Postal address, lines separated by newlines.
+    #[serde(default)]
+    pub address: Option<String>,
+    /// When the details were last saved; `None` while they come from config.
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Replaces the merchant details; omitted optional fields are cleared.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateMerchantDetailsDto {
+    pub legal_name: String,
+    #[serde(default)]
+    pub vat_number: Option<String>,
+    #[serde(default)]
+    pub registration_number: Option<String>,
+    #[serde(default)]
+    pub address: Option<String>,
+}
+
+impl Validate for UpdateMerchantDetailsDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        if self.legal_name.trim().is_empty() {
+            errors.add("legal_name", "must not be empty");
+        }
+        if let Some(vat_number) = &self.vat_number {
+            errors.check("vat_number", validate_vat_number(vat_number));
+        }
+        if let Some(address) = &self.address {
+            errors.check("address", validate_address(address));
+        }
+        errors.into_result()
+    }
+}
+
+impl UpdateMerchantDetailsDto {
+    /// Trims every field, dropping empty optional ones. Call once validated.
+    pub fn into_details(self) -> MerchantDetails {
+        MerchantDetails {
+            legal_name: self.legal_name.trim().to_string(),
+            vat_number: self.vat_number.as_deref().and_then(|v| validate_vat_number(v).ok().flatten()),
+            registration_number: self.registration_number.map(|r| r.trim().to_string()).filter(|r| !r.is_empty()),
+            address: self.address.as_deref().and_then(|a| validate_address(a).ok().flatten()),
+            updated_at: None,
+        }
+    }
+}
+
+/// A South African VAT number is ten digits starting with 4. Spaces are
+/// dropped; an empty value means none.
+pub fn validate_vat_number(vat_number: &str) -> Result<Option<String>, String> {
+    let digits: String = vat_number.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty() {
+        return Ok(None);
+    }
+    if digits.len() != 10 || !digits.starts_with('4') || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("{} is not a valid VAT number (10 digits starting with 4)", vat_number.trim()));
+    }
+    Ok(Some(digits))
+}
+
+/// Trims an address and rejects it when over `MAX_ADDRESS_LEN`; an empty
+/// value means none.
+pub fn validate_address(address: &str) -> Result<Option<String>, String> {
+    let address = address.trim();
+    if address.chars().count() > MAX_ADDRESS_LEN {
+        return Err(format!("Address must be at most {} characters", MAX_ADDRESS_LEN));
+    }
+    Ok(Some(address.to_string()).filter(|a| !a.is_empty()))
+}
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+
+/// Most buckets one metrics request may return; a year of days.
+pub const MAX_METRICS_BUCKETS: usize = 366;
+
+/// Width of each point in a metrics series. Weeks start on Monday; all
+/// buckets are aligned to UTC.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricsBucket {
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+impl MetricsBucket {
+    /// Start of the bucket containing `date`.
+    pub fn floor(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            MetricsBucket::Day => date,
+            MetricsBucket::Week => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+            MetricsBucket::Month => date.with_day(1).unwrap_or(date),
+        }
+    }
+
+    /// Start of the bucket after the one starting at `start`.
+    pub fn next(&self, start: NaiveDate) -> NaiveDate {
+        match self {
+            MetricsBucket::Day => start + Duration::days(1),
+            MetricsBucket::Week => start + Duration::days(7),
+            MetricsBucket::Month => start
+                .checked_add_months(chrono::Months::new(1))
+                .unwrap_or(start + Duration::days(31)),
+        }
+    }
+
+    /// Window used when the caller gives no `from`: 30 days, 12 weeks or
+    /// 12 months back from `to`.
+    pub fn default_from(&self, to: NaiveDate) -> NaiveDate {
+        match self {
+            MetricsBucket::Day => to - Duration::days(29),
+            MetricsBucket::Week => to - Duration::weeks(11),
+            MetricsBucket::Month => to
+                .checked_sub_months(chrono::Months::new(11))
+                .unwrap_or(to - Duration::days(334)),
+        }
+    }
+}
+
+/// Half-open `[start, end)` time ranges covering `from` to `to` (inclusive
+/// dates), the first widened back to a bucket boundary. Errors when the
+/// window is reversed or needs more than `MAX_METRICS_BUCKETS` buckets.
+pub fn bucket_ranges(
+    from: NaiveDate,
+    to: NaiveDate,
+    bucket: MetricsBucket,
+) -> Result<Vec<(DateTime<Utc>, DateTime<Utc>)>, String> {
+    if from > to {
+        return Err("from must not be after to".to_string());
+    }
+
+    let mut ranges = Vec::new();
+    let mut start = bucket.floor(from);
+    while start <= to {
+        if ranges.len() == MAX_METRICS_BUCKETS {
+            return Err(format!(
+                "Window needs more than {} buckets; use a wider bucket or a shorter window",
+                MAX_METRICS_BUCKETS
+            ));
+        }
+        let end = bucket.next(start);
+        ranges.push((
+            start.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc(),
+            end.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc(),
+        ));
+        start = end;
+    }
+    Ok(ranges)
+}
+
+/// Monthly recurring revenue at the end of a bucket.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MrrPoint {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Recurring base prices of the subscriptions paying at `end`, scaled
+    /// to 30 days and converted to `currency`.
+    pub mrr: f64,
+    pub paying_subscriptions: u64,
+}
+
+/// MRR over time. Usage charges are left out; subscriptions whose currency
+/// has no exchange rate are left out of `mrr` and listed instead.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MrrSeries {
+    pub bucket: MetricsBucket,
+    pub currency: String,
+    pub unconverted_currencies: Vec<String>,
+    pub points: Vec<MrrPoint>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SubscriptionMetricsPoint {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Paying subscriptions when the bucket started.
+    pub active_at_start: u64,
+    /// Subscriptions whose first period started in the bucket.
+    pub new_subscriptions: u64,
+    /// Cancellations that took effect in the bucket.
+    pub cancellations: u64,
+    /// `cancellations / active_at_start`; 0 when nothing was active.
+    pub churn_rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SubscriptionMetricsSeries {
+    pub bucket: MetricsBucket,
+    pub points: Vec<SubscriptionMetricsPoint>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PaymentMetricsPoint {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub succeeded: u64,
+    pub failed: u64,
+    /// `failed / (succeeded + failed)`; 0 when nothing was settled.
+    pub failed_rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PaymentMetricsSeries {
+    pub bucket: MetricsBucket,
+    pub points: Vec<PaymentMetricsPoint>,
+}
+
+/// A settled payment, for metrics.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PaymentOutcome {
+    pub succeeded: bool,
+    pub settled_at: DateTime<Utc>,
+}
@@ -2,4 +2,40 @@ pub mod payment;
 pub mod user;
 pub mod subscription;
 pub mod notification;
-pub mod recurring_payment;
\ No newline at end of file
+pub mod recurring_payment;
+pub mod plan;
+pub mod webhook_event;
+pub mod refund;
+pub mod activity;
+pub mod support;
+pub mod report;
+pub mod invoice;
+pub mod card_update;
+pub mod entitlement;
+pub mod attachment;
+pub mod billing_run;
+pub mod plan_change;
+pub mod wallet;
+pub mod usage;
+pub mod token_migration;
+pub mod membership;
+pub mod state_reason;
+pub mod bulk_operation;
+pub mod common;
+pub mod metrics;
+pub mod ticket;
+pub mod job;
+pub mod merchant;
+pub mod export;
+pub mod domain_event;
+pub mod scenario;
+pub mod webhook_endpoint;
+pub mod payment_method_rule;
+pub mod template;
+pub mod audit;
+pub mod impersonation;
+pub mod api_key;
+pub mod tenant;
+pub mod validation;
+pub mod installment;
+pub mod reconciliation;
@@ -1,5 +1,135 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use chrono::{DateTime, Utc};
+use crate::models::template::TemplateChannel;
+use crate::models::validation::{FieldErrors, Validate};
+use crate::services::formatting::Locale;
+use crate::services::templates::{TemplateRegistry, DEFAULT_LOCALE};
+
+/// Where the PWA should route when the user taps a notification.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationAction {
+    OpenRenewal,
+    UpdateCard,
+    ViewPayment,
+}
+
+/// Kinds of subscriber notification. Users choose the channels each kind is
+/// delivered on.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationEvent {
+    /// Receipts for successful payments.
+    PaymentSucceeded,
+    PaymentFailed,
+    /// Renewal reminders and requests to pay a renewal manually.
+    RenewalDue,
+    /// Suspensions, downgrades and cancellations.
+    SubscriptionChanged,
+    /// Cards that were declined, are expiring, need confirming or were replaced.
+    CardAction,
+    /// Seats on someone else's subscription.
+    Membership,
+}
+
+impl NotificationEvent {
+    pub const ALL: [NotificationEvent; 6] = [
+        NotificationEvent::PaymentSucceeded,
+        NotificationEvent::PaymentFailed,
+        NotificationEvent::RenewalDue,
+        NotificationEvent::SubscriptionChanged,
+        NotificationEvent::CardAction,
+        NotificationEvent::Membership,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationChannel {
+    Email,
+    Push,
+    InApp,
+    Sms,
+}
+
+/// The channels one kind of notification is delivered on. Push and SMS are
+/// saved for when those channels get providers; nothing is sent on them yet.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct EventPreference {
+    pub event: NotificationEvent,
+    pub email: bool,
+    pub push: bool,
+    pub in_app: bool,
+    pub sms: bool,
+}
+
+impl EventPreference {
+    /// What users get until they choose: email and in-app only.
+    pub fn default_for(event: NotificationEvent) -> Self {
+        Self { event, email: true, push: false, in_app: true, sms: false }
+    }
+
+    pub fn allows(&self, channel: NotificationChannel) -> bool {
+        match channel {
+            NotificationChannel::Email => self.email,
+            NotificationChannel::Push => self.push,
+            NotificationChannel::InApp => self.in_app,
+            NotificationChannel::Sms => self.sms,
+        }
+    }
+}
+
+/// A user's channel choices for every kind of notification.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct NotificationPreferences {
+    pub user_id: String,
+    /// One entry per event, in `NotificationEvent::ALL` order.
+    pub events: Vec<EventPreference>,
+}
+
+impl NotificationPreferences {
+    /// Fills in defaults for events the user hasn't saved a choice for.
+    pub fn from_saved(user_id: String, saved: Vec<EventPreference>) -> Self {
+        let events = NotificationEvent::ALL
+            .iter()
+            .map(|event| {
+                saved.iter()
+                    .find(|p| p.event == *event)
+                    .cloned()
+                    .unwrap_or_else(|| EventPreference::default_for(*event))
+            })
+            .collect();
+        Self { user_id, events }
+    }
+
+    pub fn allows(&self, event: NotificationEvent, channel: NotificationChannel) -> bool {
+        self.events
+            .iter()
+            .find(|p| p.event == event)
+            .is_none_or(|p| p.allows(channel))
+    }
+}
+
+/// Replaces the choices for the listed events; others are left as they are.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateNotificationPreferencesDto {
+    pub events: Vec<EventPreference>,
+}
+
+impl Validate for UpdateNotificationPreferencesDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        let mut seen = Vec::new();
+        for preference in &self.events {
+            if seen.contains(&preference.event) {
+                errors.add("events", format!("{:?} is listed more than once", preference.event));
+            }
+            seen.push(preference.event);
+        }
+        errors.into_result()
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Notification {
@@ -8,12 +138,160 @@ pub struct Notification {
     pub subscription_id: String,
     pub message: String,
     pub acknowledged: bool,
+    #[serde(default)]
+    pub action_type: Option<NotificationAction>,
+    /// Action parameters, e.g. `{"subscription_id": "..."}`.
+    #[serde(default)]
+    pub action_payload: Option<serde_json::Value>,
+    /// Which preference routed it; `None` for test notifications.
+    #[serde(default)]
+    pub event: Option<NotificationEvent>,
     pub created_at: DateTime<Utc>,
 }
 
+/// The in-app template a message was rendered from, kept so it can be
+/// rendered again with the merchant's copy when the notification is written.
+#[derive(Debug, Clone)]
+pub struct NotificationTemplate {
+    pub key: &'static str,
+    pub variables: Vec<(&'static str, String)>,
+}
+
+impl NotificationTemplate {
+    pub fn render(&self, templates: &TemplateRegistry, locale: Locale) -> Option<String> {
+        templates
+            .render(TemplateChannel::InApp, self.key, locale, &self.variables)
+            .map(|message| message.trim().to_string())
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateNotificationDto {
     pub user_id: String,
     pub subscription_id: String,
+    /// Rendered with the built-in copy; replaced with the merchant's copy
+    /// when `template` is set and they saved one.
     pub message: String,
+    pub action_type: Option<NotificationAction>,
+    pub action_payload: Option<serde_json::Value>,
+    /// Checked against the user's in-app preference before the notification
+    /// is written; `None` is always delivered.
+    #[serde(default)]
+    pub event: Option<NotificationEvent>,
+    #[serde(skip)]
+    pub template: Option<NotificationTemplate>,
+}
+
+impl CreateNotificationDto {
+    fn from_template(
+        user_id: String,
+        subscription_id: String,
+        key: &'static str,
+        variables: Vec<(&'static str, String)>,
+        action_type: NotificationAction,
+        action_payload: serde_json::Value,
+        event: NotificationEvent,
+    ) -> Self {
+        let template = NotificationTemplate { key, variables };
+        Self {
+            message: template.render(&TemplateRegistry::builtin(), DEFAULT_LOCALE).unwrap_or_default(),
+            action_type: Some(action_type),
+            action_payload: Some(action_payload),
+            event: Some(event),
+            template: Some(template),
+            user_id,
+            subscription_id,
+        }
+    }
+
+    /// Asks the user to pay for a renewal that couldn't be charged automatically.
+    pub fn manual_renewal(user_id: String, subscription_id: String) -> Self {
+        Self::from_template(
+            user_id,
+            subscription_id.clone(),
+            "manual_renewal",
+            vec![("subscription_id", subscription_id.clone())],
+            NotificationAction::OpenRenewal,
+            serde_json::json!({ "subscription_id": subscription_id }),
+            NotificationEvent::RenewalDue,
+        )
+    }
+
+    /// Tells the user their subscription was moved to `fallback_plan` after
+    /// renewal retries ran out, with a link to renew the paid plan.
+    pub fn downgrade(user_id: String, subscription_id: String, plan: &str, fallback_plan: &str) -> Self {
+        Self::from_template(
+            user_id,
+            subscription_id.clone(),
+            "downgrade",
+            vec![
+                ("subscription_id", subscription_id.clone()),
+                ("plan", plan.to_string()),
+                ("fallback_plan", fallback_plan.to_string()),
+            ],
+            NotificationAction::OpenRenewal,
+            serde_json::json!({ "subscription_id": subscription_id }),
+            NotificationEvent::SubscriptionChanged,
+        )
+    }
+
+    pub fn card_update(user_id: String, subscription_id: String) -> Self {
+        Self::from_template(
+            user_id,
+            subscription_id.clone(),
+            "card_update",
+            vec![("subscription_id", subscription_id.clone())],
+            NotificationAction::UpdateCard,
+            serde_json::json!({ "subscription_id": subscription_id }),
+            NotificationEvent::CardAction,
+        )
+    }
+
+    /// Asks the user to replace a saved card that expires at the end of
+    /// `expiry` (e.g. `05/2027`), before a renewal is charged to it.
+    pub fn card_expiring(user_id: String, subscription_id: String, expiry: &str) -> Self {
+        Self::from_template(
+            user_id,
+            subscription_id.clone(),
+            "card_expiring",
+            vec![
+                ("subscription_id", subscription_id.clone()),
+                ("expiry", expiry.to_string()),
+            ],
+            NotificationAction::UpdateCard,
+            serde_json::json!({ "subscription_id": subscription_id }),
+            NotificationEvent::CardAction,
+        )
+    }
+
+    /// Asks the user to re-authorise their card while a token migration is
+    /// running. Their current card keeps working until they do.
+    pub fn card_migration(user_id: String, subscription_id: String) -> Self {
+        Self::from_template(
+            user_id,
+            subscription_id.clone(),
+            "card_migration",
+            vec![("subscription_id", subscription_id.clone())],
+            NotificationAction::UpdateCard,
+            serde_json::json!({ "subscription_id": subscription_id, "reason": "token_migration" }),
+            NotificationEvent::CardAction,
+        )
+    }
+
+    /// Tells a user they were given a seat on someone else's subscription.
+    pub fn member_added(user_id: String, subscription_id: String, plan: &str) -> Self {
+        let template = NotificationTemplate {
+            key: "member_added",
+            variables: vec![("plan", plan.to_string())],
+        };
+        Self {
+            message: template.render(&TemplateRegistry::builtin(), DEFAULT_LOCALE).unwrap_or_default(),
+            action_type: None,
+            action_payload: None,
+            event: Some(NotificationEvent::Membership),
+            template: Some(template),
+            user_id,
+            subscription_id,
+        }
+    }
 }
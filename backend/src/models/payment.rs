@@ -1,25 +1,91 @@
 use serde::{Deserialize, Serialize};
-use chrono::{DateTime, Utc};
+use utoipa::ToSchema;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
 use std::fmt;
+use uuid::Uuid;
+use crate::models::state_reason;
+use crate::models::usage::UsageCharge;
+use crate::models::validation::{FieldErrors, Validate};
+use crate::services::tax::TaxBreakdown;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// Currency of rows created before currency was stored, and of new plans by default.
+pub const DEFAULT_CURRENCY: &str = "ZAR";
+
+pub fn default_currency() -> String {
+    DEFAULT_CURRENCY.to_string()
+}
+
+/// Upper-cases and checks a three-letter ISO 4217 code.
+pub fn normalize_currency(code: &str) -> Result<String, String> {
+    let code = code.trim().to_uppercase();
+    if code.len() != 3 || !code.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err("Currency must be a three-letter ISO code".to_string());
+    }
+    Ok(code)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
 pub enum PaymentStatus {
     Pending,
+    /// The shopper has to complete a 3-D Secure challenge at the payment's
+    /// `authentication_url` before the bank decides.
+    AwaitingAuthentication,
+    /// The amount is reserved on the card but not yet taken; an
+    /// administrator captures or voids it.
+    Authorized,
     Completed,
     Failed,
     Cancelled,
     Refunded,
+    PartiallyRefunded,
+}
+
+impl PaymentStatus {
+    /// How far along its lifecycle a payment is. A webhook that would move a
+    /// payment back to an earlier stage arrived out of order.
+    pub fn stage(&self) -> u8 {
+        match self {
+            PaymentStatus::Pending | PaymentStatus::AwaitingAuthentication => 0,
+            PaymentStatus::Authorized => 1,
+            PaymentStatus::Failed | PaymentStatus::Cancelled => 2,
+            PaymentStatus::Completed => 3,
+            PaymentStatus::PartiallyRefunded | PaymentStatus::Refunded => 4,
+        }
+    }
+
+    /// Whether the checkout has been decided one way or the other.
+    pub fn is_final(&self) -> bool {
+        self.stage() > 0
+    }
 }
 
 
-#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+/// How a payment is made. Gateways report a brand instead (`VISA`, `OZOW`,
+/// `1FORYOU`, ...); see `from_brand`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, ToSchema)]
 pub enum PaymentMethod {
     Card,
+    #[serde(alias = "Eft")]
     EFT,
+    #[serde(alias = "OneVoucher")]
     Voucher,
     ScanToPay,
 }
 
+impl PaymentMethod {
+    /// Method for a gateway's payment brand, matched case-insensitively;
+    /// `None` for brands we don't know.
+    pub fn from_brand(brand: &str) -> Option<Self> {
+        match brand.trim().to_lowercase().as_str() {
+            "visa" | "master" | "mastercard" | "amex" | "diners" | "discover" | "jcb" | "unionpay" | "card" => Some(PaymentMethod::Card),
+            "eft" | "ozow" | "instant_eft" => Some(PaymentMethod::EFT),
+            "1voucher" | "onevoucher" | "1foryou" | "voucher" => Some(PaymentMethod::Voucher),
+            "scan_to_pay" | "scantopay" | "masterpass" => Some(PaymentMethod::ScanToPay),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for PaymentMethod {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let s = match self {
@@ -32,29 +98,226 @@ impl fmt::Display for PaymentMethod {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Payment {
     pub id: String,
     pub user_id: String,
     pub subscription_id: Option<String>,
+    /// Amount charged, VAT included.
     pub amount: f64,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    #[serde(default)]
+    pub amount_excl_vat: Option<f64>,
+    #[serde(default)]
+    pub vat_amount: Option<f64>,
+    #[serde(default)]
+    pub vat_rate_percent: Option<u32>,
     pub status: PaymentStatus,
     pub payment_method: PaymentMethod,
+    /// Gateway the checkout went through; `None` for payments from before
+    /// EFT routing, which all used the main gateway.
+    #[serde(default)]
+    pub gateway: Option<String>,
      pub recurring_token: Option<String>,
     pub merchant_transaction_id: String,
     pub checkout_id: Option<String>,
+    /// Hosted checkout page, for gateways that return one. ScanToPay QR
+    /// codes encode this link.
+    #[serde(default)]
+    pub checkout_url: Option<String>,
+    #[serde(default)]
+    pub peach_payment_id: Option<String>,
+    /// Where the shopper completes a 3-D Secure challenge, while the payment
+    /// is `AwaitingAuthentication`.
+    #[serde(default)]
+    pub authentication_url: Option<String>,
+    /// Peach timestamp of the last webhook applied to this payment.
+    #[serde(default)]
+    pub last_event_at: Option<DateTime<Utc>>,
+    /// When the money actually arrived, for manually recorded payments.
+    #[serde(default)]
+    pub value_date: Option<DateTime<Utc>>,
+    /// Bank or remittance reference of a manually recorded payment.
+    #[serde(default)]
+    pub external_reference: Option<String>,
+    /// Attachment id of the proof of payment (e.g. a bank confirmation) for a
+    /// manual payment.
+    #[serde(default)]
+    pub proof_attachment: Option<String>,
+    /// Part of `amount` paid from wallet credit; the rest goes through the gateway.
+    #[serde(default)]
+    pub wallet_amount: f64,
+    /// Card statement text sent with the checkout, repeated on the receipt.
+    #[serde(default)]
+    pub statement_descriptor: Option<String>,
+    /// Usage billed on top of the plan price, for metered renewals.
+    #[serde(default)]
+    pub usage: Option<UsageCharge>,
+    /// Why the payment has its current status, e.g.
+    /// `payment_failed_insufficient_funds`; see `models::state_reason`.
+    #[serde(default)]
+    pub state_reason: Option<String>,
+    #[serde(default)]
+    pub tenant_id: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Deserialize)]
+impl Payment {
+    /// A new payment awaiting checkout, with a fresh merchant transaction id.
+    /// `amount` is taken to include VAT at `vat_rate_percent`. The id is left
+    /// for storage to assign.
+    pub fn pending(dto: CreatePaymentDto, vat_rate_percent: u32, gateway: &str, tenant_id: Option<String>) -> Self {
+        let merchant_transaction_id = format!(
+            "TXN_{}",
+            Uuid::new_v4()
+                .simple()
+                .to_string()
+                .to_uppercase()
+                .get(..16)
+                .unwrap_or("0000000000000000")
+        );
+        let tax = TaxBreakdown::from_inclusive(dto.amount, vat_rate_percent);
+        let now = Utc::now();
+
+        Payment {
+            id: String::new(),
+            user_id: dto.user_id,
+            subscription_id: Some(dto.subscription_id),
+            amount: tax.gross,
+            currency: dto.currency.unwrap_or_else(default_currency),
+            amount_excl_vat: Some(tax.net),
+            vat_amount: Some(tax.vat),
+            vat_rate_percent: Some(tax.vat_rate_percent),
+            recurring_token: None,
+            status: PaymentStatus::Pending,
+            payment_method: dto.payment_method.unwrap_or(PaymentMethod::Card),
+            gateway: Some(gateway.to_string()),
+            merchant_transaction_id,
+            checkout_id: None,
+            checkout_url: None,
+            peach_payment_id: None,
+            authentication_url: None,
+            last_event_at: None,
+            value_date: None,
+            external_reference: None,
+            proof_attachment: None,
+            wallet_amount: 0.0,
+            statement_descriptor: None,
+            usage: None,
+            state_reason: Some(state_reason::AWAITING_PAYMENT.to_string()),
+            tenant_id,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// When the payment counts as paid: its value date when recorded by hand,
+    /// otherwise when it last changed status.
+    pub fn paid_at(&self) -> DateTime<Utc> {
+        self.value_date.unwrap_or(self.updated_at)
+    }
+
+    /// What the gateway charges once wallet credit is taken off.
+    pub fn card_amount(&self) -> f64 {
+        (((self.amount - self.wallet_amount) * 100.0).round() / 100.0).max(0.0)
+    }
+
+    /// VAT split recorded when the payment was created. Payments from before
+    /// VAT was tracked are split at `fallback_rate_percent`.
+    pub fn tax(&self, fallback_rate_percent: u32) -> TaxBreakdown {
+        match (self.amount_excl_vat, self.vat_amount, self.vat_rate_percent) {
+            (Some(net), Some(vat), Some(rate)) => TaxBreakdown {
+                vat_rate_percent: rate,
+                net,
+                vat,
+                gross: self.amount,
+            },
+            _ => TaxBreakdown::from_inclusive(self.amount, fallback_rate_percent),
+        }
+    }
+}
+
+/// What a successful-payment event changed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PaymentCompletion {
+    /// False when a later event had already been applied to the payment, in
+    /// which case nothing was written.
+    pub payment_updated: bool,
+    /// False when the payment has no subscription or a later event had
+    /// already activated it.
+    pub subscription_activated: bool,
+    /// True when the payment was counted towards its subscription's period
+    /// without paying it off, so the subscription stays as it was.
+    pub subscription_part_paid: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreatePaymentDto {
     pub user_id: String,
     pub subscription_id: String,
     pub amount: f64,
+    /// Checked against the subscription's currency when given.
+    #[serde(default)]
+    pub currency: Option<String>,
+    pub payment_method: Option<PaymentMethod>,
+    /// Shopper's country code, e.g. `ZA`, for payment method availability.
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Pay by card in this many monthly installments; `amount` is then the
+    /// first, and the rest are charged to the card saved at checkout.
+    #[serde(default)]
+    pub installments: Option<u32>,
+}
+
+// Checked by `preflight_payment` instead, which reports each problem with a code
+impl Validate for CreatePaymentDto {}
+
+/// Gateway name stored on payments recorded by an administrator.
+pub const MANUAL_GATEWAY: &str = "manual";
+
+/// How far ahead a manual payment's value date may be.
+pub const MAX_FORWARD_VALUE_DAYS: i64 = 31;
+
+/// A payment received outside the gateways, e.g. an EFT straight into the
+/// bank account, recorded against a subscription by an administrator.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ManualPaymentDto {
+    pub subscription_id: String,
+    /// Defaults to the subscription's price.
+    #[serde(default)]
+    pub amount: Option<f64>,
+    /// Defaults to, and must match, the subscription's currency.
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Date the funds cleared; may be in the past or up to
+    /// `MAX_FORWARD_VALUE_DAYS` ahead.
+    pub value_date: NaiveDate,
+    pub reference: String,
+    #[serde(default)]
+    pub proof_attachment: Option<String>,
+    #[serde(default)]
     pub payment_method: Option<PaymentMethod>,
 }
 
+impl Validate for ManualPaymentDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        if self.reference.trim().is_empty() {
+            errors.add("reference", "A payment reference is required");
+        }
+        let latest_value_date = Utc::now().date_naive() + Duration::days(MAX_FORWARD_VALUE_DAYS);
+        if self.value_date > latest_value_date {
+            errors.add("value_date", format!("Latest allowed value date is {}", latest_value_date));
+        }
+        if self.amount.is_some_and(|amount| !amount.is_finite() || amount <= 0.0) {
+            errors.add("amount", "Amount must be greater than zero");
+        }
+        errors.into_result()
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct PaymentCallbackDto {
     pub id: String,
@@ -68,3 +331,34 @@ pub struct PaymentResult {
     pub code: String,
     pub description: String,
 }
+
+/// Order of a payment history page.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentSort {
+    #[default]
+    NewestFirst,
+    OldestFirst,
+    LargestFirst,
+    SmallestFirst,
+}
+
+impl PaymentSort {
+    pub fn order_by(&self) -> &'static str {
+        match self {
+            PaymentSort::NewestFirst => "created_at DESC",
+            PaymentSort::OldestFirst => "created_at ASC",
+            PaymentSort::LargestFirst => "amount DESC, created_at DESC",
+            PaymentSort::SmallestFirst => "amount ASC, created_at DESC",
+        }
+    }
+}
+
+/// Which of a user's payments a history page covers; `from` inclusive, `to`
+/// exclusive.
+#[derive(Debug, Clone, Default)]
+pub struct PaymentHistoryFilter {
+    pub status: Option<PaymentStatus>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
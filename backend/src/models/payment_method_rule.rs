@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use crate::models::payment::PaymentMethod;
+use crate::models::validation::{FieldErrors, Validate};
+
+/// Region of the rule that applies wherever no rule names the shopper's region.
+pub const ANY_REGION: &str = "*";
+
+/// Every method a shopper may pick, in the order the PWA lists them.
+pub const PAYMENT_METHODS: &[PaymentMethod] = &[
+    PaymentMethod::Card,
+    PaymentMethod::EFT,
+    PaymentMethod::Voucher,
+    PaymentMethod::ScanToPay,
+];
+
+/// Where and for how much a payment method may be used. A method with no
+/// rule for the shopper's region falls back to its `*` rule, and with
+/// neither it is available everywhere.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PaymentMethodRule {
+    pub payment_method: PaymentMethod,
+    /// ISO 3166 country code, e.g. `ZA`, or `*`.
+    pub region: String,
+    /// Off hides the method in the region.
+    pub enabled: bool,
+    /// Currencies the method takes there; empty means any.
+    #[serde(default)]
+    pub currencies: Vec<String>,
+    #[serde(default)]
+    pub min_amount: Option<f64>,
+    #[serde(default)]
+    pub max_amount: Option<f64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct SetPaymentMethodRuleDto {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub currencies: Vec<String>,
+    #[serde(default)]
+    pub min_amount: Option<f64>,
+    #[serde(default)]
+    pub max_amount: Option<f64>,
+}
+
+impl Validate for SetPaymentMethodRuleDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        for (field, amount) in [("min_amount", self.min_amount), ("max_amount", self.max_amount)] {
+            if amount.is_some_and(|amount| !amount.is_finite() || amount < 0.0) {
+                errors.add(field, "Amount limits must be zero or more");
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min_amount, self.max_amount) {
+            if min > max {
+                errors.add("min_amount", "min_amount must not be above max_amount");
+            }
+        }
+        for currency in &self.currencies {
+            let currency = currency.trim();
+            if currency.len() != 3 || !currency.chars().all(|ch| ch.is_ascii_alphabetic()) {
+                errors.add("currencies", format!("{} is not an ISO currency code", currency.to_uppercase()));
+            }
+        }
+        errors.into_result()
+    }
+}
+
+impl SetPaymentMethodRuleDto {
+    /// Upper-cases the currencies.
+    pub fn normalize(mut self) -> Self {
+        self.currencies = self.currencies.iter().map(|c| c.trim().to_uppercase()).collect();
+        self
+    }
+}
+
+/// `*`, or a two-letter country code in upper case.
+pub fn normalize_region(region: &str) -> Result<String, String> {
+    let region = region.trim();
+    if region == ANY_REGION {
+        return Ok(region.to_string());
+    }
+    if region.len() == 2 && region.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(region.to_uppercase())
+    } else {
+        Err(format!("Region {} must be a two-letter country code or *", region))
+    }
+}
+
+/// Whether a method can be offered to a shopper, and why not.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MethodAvailability {
+    pub payment_method: PaymentMethod,
+    pub available: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Bounds the method has in the region, whether or not the amount was given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_amount: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_amount: Option<f64>,
+}
+
+/// Applies the rule for `method` in `region` (`None` when the shopper's
+/// region is unknown, which only the `*` rule covers) to a payment in
+/// `currency`; `amount` is only checked when given.
+pub fn method_availability(
+    rules: &[PaymentMethodRule],
+    method: &PaymentMethod,
+    region: Option<&str>,
+    currency: &str,
+    amount: Option<f64>,
+) -> MethodAvailability {
+    let rule_for = |r: &str| rules.iter().find(|rule| rule.payment_method == *method && rule.region == r);
+    let rule = region.and_then(rule_for).or_else(|| rule_for(ANY_REGION));
+    let place = region.map(|r| format!(" in {}", r)).unwrap_or_default();
+
+    let reason = rule.and_then(|rule| {
+        if !rule.enabled {
+            return Some(format!("{} is not available{}", method, place));
+        }
+        if !rule.currencies.is_empty() && !rule.currencies.iter().any(|c| c.eq_ignore_ascii_case(currency)) {
+            return Some(format!("{} does not accept {}{}", method, currency, place));
+        }
+        let amount = amount?;
+        match (rule.min_amount, rule.max_amount) {
+            (Some(min), _) if amount < min => Some(format!("{} needs at least {:.2} {}", method, min, currency)),
+            (_, Some(max)) if amount > max => Some(format!("{} takes at most {:.2} {}", method, max, currency)),
+            _ => None,
+        }
+    });
+
+    MethodAvailability {
+        payment_method: method.clone(),
+        available: reason.is_none(),
+        reason,
+        min_amount: rule.and_then(|r| r.min_amount),
+        max_amount: rule.and_then(|r| r.max_amount),
+    }
+}
@@ -0,0 +1,353 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use crate::models::payment::{default_currency, normalize_currency};
+use crate::models::subscription::SuspensionPolicy;
+use crate::models::usage::UsagePricing;
+use crate::models::validation::{FieldErrors, Validate};
+use crate::services::tax::TaxBreakdown;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub enum BillingInterval {
+    Weekly,
+    Monthly,
+    Quarterly,
+    Annual,
+    /// Every `interval_days` days, set on the plan.
+    Custom,
+}
+
+impl BillingInterval {
+    /// Days in one period; `None` for `Custom`, whose length is set per plan.
+    pub fn period_days(&self) -> Option<u32> {
+        match self {
+            BillingInterval::Weekly => Some(7),
+            BillingInterval::Monthly => Some(30),
+            BillingInterval::Quarterly => Some(90),
+            BillingInterval::Annual => Some(365),
+            BillingInterval::Custom => None,
+        }
+    }
+
+    /// Calendar months in one period, for the intervals renewed on the same
+    /// day each month; `None` for `Weekly` and `Custom`.
+    pub fn period_months(&self) -> Option<u32> {
+        match self {
+            BillingInterval::Monthly => Some(1),
+            BillingInterval::Quarterly => Some(3),
+            BillingInterval::Annual => Some(12),
+            BillingInterval::Weekly | BillingInterval::Custom => None,
+        }
+    }
+}
+
+/// Bounds for the length of a `Custom` interval.
+pub const MIN_INTERVAL_DAYS: u32 = 1;
+pub const MAX_INTERVAL_DAYS: u32 = 730;
+
+/// Checks that `interval_days` is set for `Custom` intervals, and only for them.
+pub fn validate_interval(interval: &BillingInterval, interval_days: Option<u32>) -> Result<(), String> {
+    match (interval, interval_days) {
+        (BillingInterval::Custom, Some(days)) if (MIN_INTERVAL_DAYS..=MAX_INTERVAL_DAYS).contains(&days) => Ok(()),
+        (BillingInterval::Custom, Some(days)) => Err(format!(
+            "Interval must be {}-{} days, got {}",
+            MIN_INTERVAL_DAYS, MAX_INTERVAL_DAYS, days
+        )),
+        (BillingInterval::Custom, None) => Err("interval_days is required for a Custom interval".to_string()),
+        (_, Some(_)) => Err("interval_days is only allowed with a Custom interval".to_string()),
+        (_, None) => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Plan {
+    pub id: String,
+    pub name: String,
+    pub price: f64,
+    /// Whether `price` already includes VAT; otherwise VAT is added on top.
+    #[serde(default = "default_prices_include_vat")]
+    pub prices_include_vat: bool,
+    pub currency: String,
+    pub interval: BillingInterval,
+    /// Length of a `Custom` interval; `None` for the fixed intervals.
+    #[serde(default)]
+    pub interval_days: Option<u32>,
+    #[serde(default)]
+    pub trial_days: u32,
+    /// Overrides the global grace period; `None` falls back to `AppConfig`.
+    pub grace_period_days: Option<u32>,
+    /// Overrides the global suspension policy; `None` falls back to `AppConfig`.
+    #[serde(default)]
+    pub suspension_policy: Option<SuspensionPolicy>,
+    /// Text shown on the customer's card statement for charges on this plan;
+    /// `None` falls back to `AppConfig`, then to the gateway account's default.
+    #[serde(default)]
+    pub statement_descriptor: Option<String>,
+    /// Metered plans also charge for usage beyond what the price includes.
+    #[serde(default)]
+    pub usage_pricing: Option<UsagePricing>,
+    /// Renewals a subscriber may skip in any 365 days; 0 disables skipping.
+    #[serde(default)]
+    pub max_skips_per_year: u32,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct CreatePlanDto {
+    /// Stable identifier used by clients; derived from the name when omitted.
+    pub id: Option<String>,
+    pub name: String,
+    pub price: f64,
+    #[serde(default = "default_prices_include_vat")]
+    pub prices_include_vat: bool,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub interval: BillingInterval,
+    #[serde(default)]
+    pub interval_days: Option<u32>,
+    #[serde(default)]
+    pub trial_days: u32,
+    pub grace_period_days: Option<u32>,
+    #[serde(default)]
+    pub suspension_policy: Option<SuspensionPolicy>,
+    #[serde(default)]
+    pub statement_descriptor: Option<String>,
+    #[serde(default)]
+    pub usage_pricing: Option<UsagePricing>,
+    #[serde(default)]
+    pub max_skips_per_year: u32,
+}
+
+/// Partial update; omitted fields are left unchanged.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, ToSchema)]
+pub struct UpdatePlanDto {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prices_include_vat: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval: Option<BillingInterval>,
+    /// Cleared automatically when `interval` changes to a fixed interval.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval_days: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trial_days: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grace_period_days: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suspension_policy: Option<SuspensionPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statement_descriptor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage_pricing: Option<UsagePricing>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_skips_per_year: Option<u32>,
+}
+
+/// Plans created before VAT handling were priced VAT-inclusive.
+fn default_prices_include_vat() -> bool {
+    true
+}
+
+impl Plan {
+    /// Days in one billing period, copied onto new subscriptions.
+    pub fn period_days(&self) -> u32 {
+        self.interval.period_days()
+            .or(self.interval_days)
+            .unwrap_or(MIN_INTERVAL_DAYS)
+    }
+
+    /// Calendar months in one billing period, copied onto new subscriptions.
+    pub fn period_months(&self) -> Option<u32> {
+        self.interval.period_months()
+    }
+
+    /// VAT split of one billing period at `vat_rate_percent`.
+    pub fn tax(&self, vat_rate_percent: u32) -> TaxBreakdown {
+        TaxBreakdown::for_price(self.price, self.prices_include_vat, vat_rate_percent)
+    }
+}
+
+/// Card networks truncate statement descriptors beyond this many characters.
+pub const STATEMENT_DESCRIPTOR_MAX_LEN: usize = 22;
+pub const STATEMENT_DESCRIPTOR_MIN_LEN: usize = 5;
+
+/// Checks a statement descriptor against the rules shared by Peach and
+/// Stripe: 5-22 printable ASCII characters, at least one letter, and none of
+/// `< > \ ' " *`.
+pub fn validate_statement_descriptor(descriptor: &str) -> Result<(), String> {
+    let len = descriptor.chars().count();
+    if !(STATEMENT_DESCRIPTOR_MIN_LEN..=STATEMENT_DESCRIPTOR_MAX_LEN).contains(&len) {
+        return Err(format!(
+            "Statement descriptor must be {}-{} characters, got {}",
+            STATEMENT_DESCRIPTOR_MIN_LEN, STATEMENT_DESCRIPTOR_MAX_LEN, len
+        ));
+    }
+    if let Some(c) = descriptor.chars().find(|c| (!c.is_ascii_graphic() && *c != ' ') || "<>\\'\"*".contains(*c)) {
+        return Err(format!("Statement descriptor must not contain '{}'", c));
+    }
+    if !descriptor.chars().any(|c| c.is_ascii_alphabetic()) {
+        return Err("Statement descriptor must contain at least one letter".to_string());
+    }
+    Ok(())
+}
+
+/// Checks the fields shared by create and update.
+fn check_plan_fields(
+    errors: &mut FieldErrors,
+    name: Option<&str>,
+    price: Option<f64>,
+    currency: Option<&str>,
+) {
+    if name.is_some_and(|name| name.trim().is_empty()) {
+        errors.add("name", "Plan name must not be empty");
+    }
+    if price.is_some_and(|price| !price.is_finite() || price <= 0.0) {
+        errors.add("price", "Plan price must be greater than zero");
+    }
+    if let Some(currency) = currency {
+        errors.check("currency", normalize_currency(currency));
+    }
+}
+
+/// Checks the optional settings shared by create and update.
+fn check_plan_settings(
+    errors: &mut FieldErrors,
+    statement_descriptor: Option<&str>,
+    usage_pricing: Option<&UsagePricing>,
+    suspension_policy: Option<&SuspensionPolicy>,
+) {
+    if let Some(descriptor) = statement_descriptor {
+        errors.check("statement_descriptor", validate_statement_descriptor(descriptor));
+    }
+    if let Some(pricing) = usage_pricing {
+        errors.check("usage_pricing", pricing.validate());
+    }
+    if let Some(policy) = suspension_policy {
+        errors.check("suspension_policy", policy.validate());
+    }
+}
+
+impl Validate for CreatePlanDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        check_plan_fields(&mut errors, Some(&self.name), Some(self.price), Some(&self.currency));
+        errors.check("interval_days", validate_interval(&self.interval, self.interval_days));
+        check_plan_settings(
+            &mut errors,
+            self.statement_descriptor.as_deref(),
+            self.usage_pricing.as_ref(),
+            self.suspension_policy.as_ref(),
+        );
+        errors.into_result()
+    }
+}
+
+/// The interval is checked by the handler, against the plan's current one.
+impl Validate for UpdatePlanDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        check_plan_fields(&mut errors, self.name.as_deref(), self.price, self.currency.as_deref());
+        check_plan_settings(
+            &mut errors,
+            self.statement_descriptor.as_deref(),
+            self.usage_pricing.as_ref(),
+            self.suspension_policy.as_ref(),
+        );
+        errors.into_result()
+    }
+}
+
+/// Lowercase, dash-separated identifier derived from a plan name.
+pub fn plan_id_from_name(name: &str) -> String {
+    name.trim()
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PlanCatalog {
+    pub version: String,
+    /// Rate used to turn VAT-exclusive plan prices into the amount charged.
+    pub vat_rate_percent: u32,
+    pub plans: Vec<Plan>,
+}
+
+impl PlanCatalog {
+    /// Builds a catalog whose version is a content hash, so any change to the
+    /// plans produces a new version (and therefore a new ETag).
+    pub fn new(plans: Vec<Plan>, vat_rate_percent: u32) -> Self {
+        let serialized = serde_json::to_vec(&(&plans, vat_rate_percent)).unwrap_or_default();
+        let digest = Sha256::digest(&serialized);
+        let version = hex::encode(&digest[..8]);
+        Self { version, vat_rate_percent, plans }
+    }
+
+    /// Plans seeded into an empty `plans` table on first start.
+    pub fn default_plans() -> Vec<Plan> {
+        vec![
+            Plan {
+                id: "basic".to_string(),
+                name: "Basic".to_string(),
+                price: 10.0,
+                prices_include_vat: true,
+                currency: default_currency(),
+                interval: BillingInterval::Monthly,
+                interval_days: None,
+                trial_days: 0,
+                grace_period_days: None,
+                suspension_policy: None,
+                statement_descriptor: None,
+                usage_pricing: None,
+                max_skips_per_year: 0,
+                created_at: None,
+                updated_at: None,
+            },
+            Plan {
+                id: "premium".to_string(),
+                name: "Premium".to_string(),
+                price: 250.0,
+                prices_include_vat: true,
+                currency: default_currency(),
+                interval: BillingInterval::Monthly,
+                interval_days: None,
+                trial_days: 0,
+                grace_period_days: None,
+                suspension_policy: None,
+                statement_descriptor: None,
+                usage_pricing: None,
+                max_skips_per_year: 0,
+                created_at: None,
+                updated_at: None,
+            },
+            Plan {
+                id: "elite".to_string(),
+                name: "Elite".to_string(),
+                price: 500.0,
+                prices_include_vat: true,
+                currency: default_currency(),
+                interval: BillingInterval::Monthly,
+                interval_days: None,
+                trial_days: 0,
+                grace_period_days: None,
+                suspension_policy: None,
+                statement_descriptor: None,
+                usage_pricing: None,
+                max_skips_per_year: 0,
+                created_at: None,
+                updated_at: None,
+            },
+        ]
+    }
+}
@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use crate::models::subscription::Subscription;
+use crate::models::validation::Validate;
+
+/// Merchant transaction ids of proration charges start with this, so their
+/// webhooks complete the plan change instead of activating a new period.
+pub const PLAN_CHANGE_PREFIX: &str = "PLANCHG_";
+
+fn round_cents(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+/// Price difference for switching plans part-way through a paid period: the
+/// unused part of the current price is credited against the new plan's price
+/// for the same remaining time. Prices are per seat; the credit and charge
+/// cover every seat. Amounts are VAT-inclusive.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ProrationCalculation {
+    pub from_price: f64,
+    pub to_price: f64,
+    pub currency: String,
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    pub calculated_at: DateTime<Utc>,
+    /// Share of the current period still to run, between 0 and 1.
+    pub remaining_fraction: f64,
+    /// Value of the current plan for the rest of the period.
+    pub unused_credit: f64,
+    /// Cost of the new plan for the rest of the period.
+    pub new_plan_charge: f64,
+    /// Charged to the stored card when upgrading.
+    pub amount_due: f64,
+    /// Issued as a credit note when downgrading.
+    pub credit_amount: f64,
+}
+
+impl ProrationCalculation {
+    /// Prorates a switch of `subscription` to a plan costing `to_price` per
+    /// period, effective `now`. The period keeps its end date.
+    pub fn calculate(subscription: &Subscription, to_price: f64, now: DateTime<Utc>) -> Result<Self, String> {
+        let (period_start, period_end) = match (subscription.start_date, subscription.end_date) {
+            (Some(start), Some(end)) if end > start => (start, end),
+            _ => return Err("Subscription has no current billing period".to_string()),
+        };
+
+        let total = (period_end - period_start).num_seconds() as f64;
+        let remaining = (period_end - now).num_seconds().max(0) as f64;
+        let remaining_fraction = (remaining / total).clamp(0.0, 1.0);
+
+        let seats = subscription.seat_count as f64;
+        let unused_credit = round_cents(subscription.price * seats * remaining_fraction);
+        let new_plan_charge = round_cents(to_price * seats * remaining_fraction);
+        let difference = round_cents(new_plan_charge - unused_credit);
+
+        Ok(Self {
+            from_price: subscription.price,
+            to_price,
+            currency: subscription.currency.clone(),
+            period_start,
+            period_end,
+            calculated_at: now,
+            remaining_fraction,
+            unused_credit,
+            new_plan_charge,
+            amount_due: difference.max(0.0),
+            credit_amount: (-difference).max(0.0),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub enum PlanChangeStatus {
+    /// Waiting for the proration charge to be confirmed.
+    Pending,
+    Completed,
+    /// The charge failed; the subscription kept its plan.
+    Failed,
+    /// Waiting for the current period to end; the renewal task switches the
+    /// plan before charging the next period. Nothing is charged or credited.
+    Scheduled,
+    /// A scheduled change the user withdrew, or that a later change replaced.
+    Cancelled,
+}
+
+/// A switch of a subscription to another plan. Upgrades stay `Pending` until
+/// the proration charge succeeds, and changes at the next renewal stay
+/// `Scheduled` until then; the subscription only moves to the new plan when
+/// the change completes.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PlanChange {
+    pub id: String,
+    pub user_id: String,
+    pub subscription_id: String,
+    pub from_plan_id: Option<String>,
+    pub from_plan_name: String,
+    pub to_plan_id: String,
+    pub to_plan_name: String,
+    /// The new plan's VAT-inclusive price per period.
+    pub to_price: f64,
+    pub proration: ProrationCalculation,
+    /// The proration payment, when something was charged.
+    pub merchant_transaction_id: Option<String>,
+    /// The credit note, when the unused part of the old plan was credited.
+    pub credit_note_id: Option<String>,
+    pub status: PlanChangeStatus,
+    pub failure_reason: Option<String>,
+    /// When a scheduled change takes effect: the end of the period it was
+    /// requested in.
+    #[serde(default)]
+    pub effective_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// When a plan change takes effect.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeAt {
+    /// Switch now, prorating the rest of the period.
+    Immediately,
+    /// Keep the current plan until `end_date`, then renew on the new one.
+    NextRenewal,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ChangePlanDto {
+    pub plan_id: String,
+    /// Omit to upgrade immediately and downgrade at the next renewal.
+    #[serde(default)]
+    pub when: Option<ChangeAt>,
+}
+
+impl Validate for ChangePlanDto {}
@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use chrono::{DateTime, Utc};
+use crate::models::payment::PaymentStatus;
+
+/// Where the gateway's side of a reconciliation came from.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReconciliationSource {
+    /// Pulled from the gateway's transaction reports by the daily task.
+    Gateway,
+    /// A settlement CSV uploaded by an administrator.
+    Upload,
+}
+
+/// One payment as the gateway reports it.
+#[derive(Debug, Clone)]
+pub struct SettlementRecord {
+    pub merchant_transaction_id: String,
+    pub gateway_reference: Option<String>,
+    /// What the gateway charged, so wallet credit is not included.
+    pub amount: f64,
+    pub currency: Option<String>,
+    /// `Completed`, `Authorized`, `Pending` or `Failed`.
+    pub status: PaymentStatus,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MismatchKind {
+    /// The gateway reports a payment we have no record of.
+    MissingLocally,
+    /// We took a payment in the report's window that the gateway doesn't list.
+    MissingAtGateway,
+    AmountDiffers,
+    StatusDiffers,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReconciliationMismatch {
+    pub merchant_transaction_id: String,
+    pub kind: MismatchKind,
+    /// The part of the payment charged through the gateway.
+    #[serde(default)]
+    pub local_amount: Option<f64>,
+    #[serde(default)]
+    pub gateway_amount: Option<f64>,
+    #[serde(default)]
+    pub currency: Option<String>,
+    #[serde(default)]
+    pub local_status: Option<PaymentStatus>,
+    #[serde(default)]
+    pub gateway_status: Option<PaymentStatus>,
+}
+
+/// The result of comparing one gateway report or settlement file with the
+/// payments table.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReconciliationReport {
+    pub id: String,
+    pub source: ReconciliationSource,
+    /// The tenant whose Peach account was reconciled; `None` for the
+    /// deployment's own.
+    #[serde(default)]
+    pub tenant_id: Option<String>,
+    /// Payments taken in `[window_start, window_end)` are expected in the
+    /// report. Uploads without a window are only checked line by line.
+    #[serde(default)]
+    pub window_start: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub window_end: Option<DateTime<Utc>>,
+    /// Payments the gateway reported.
+    pub records: u32,
+    /// Of those, how many agree with ours.
+    pub matched: u32,
+    #[serde(default)]
+    pub mismatches: Vec<ReconciliationMismatch>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReconciliationUploadQuery {
+    /// With `to`, payments we took in `[from, to)` that the file doesn't
+    /// list are flagged as missing at the gateway.
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReconciliationReportQuery {
+    pub limit: Option<u32>,
+}
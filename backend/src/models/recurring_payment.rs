@@ -1,5 +1,9 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, TimeZone, Utc};
 use serde::{Serialize, Deserialize}; // Import Serialize and Deserialize
+use utoipa::ToSchema;
+
+/// Users are told this long before their saved card expires.
+pub const CARD_EXPIRY_NOTICE_DAYS: i64 = 30;
 
 #[derive(Clone, Debug, Serialize, Deserialize)] // Added Serialize and Deserialize derives
 pub struct RecurringPayment {
@@ -10,6 +14,19 @@ pub struct RecurringPayment {
     pub card_last_four: Option<String>,
     pub card_brand: Option<String>,
     pub status: RecurringPaymentStatus,
+    /// Token migration this card was flagged by; the user is asked to
+    /// re-authorise until it is replaced.
+    #[serde(default)]
+    pub migration_id: Option<String>,
+    /// Unknown for cards saved before expiry was recorded, and for gateways
+    /// that don't report it.
+    #[serde(default)]
+    pub expiry_month: Option<u32>,
+    #[serde(default)]
+    pub expiry_year: Option<i32>,
+    /// When the user was told the card is about to expire.
+    #[serde(default)]
+    pub expiry_notified_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -19,4 +36,49 @@ pub enum RecurringPaymentStatus {
     Active,
     Cancelled,
     Failed,
-}
\ No newline at end of file
+    /// Superseded by a card the user added through a card update.
+    Replaced,
+}
+impl RecurringPayment {
+    pub fn expiry(&self) -> Option<CardExpiry> {
+        Some(CardExpiry { month: self.expiry_month?, year: self.expiry_year? })
+    }
+
+    /// The card expires within the notice period of `now` (or already has)
+    /// and the user hasn't been told yet.
+    pub fn needs_expiry_notice(&self, now: DateTime<Utc>) -> bool {
+        self.expiry_notified_at.is_none()
+            && self.expiry()
+                .and_then(|expiry| expiry.expires_at())
+                .is_some_and(|at| at <= now + Duration::days(CARD_EXPIRY_NOTICE_DAYS))
+    }
+}
+
+/// Month and year printed on a card.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+pub struct CardExpiry {
+    pub month: u32,
+    pub year: i32,
+}
+
+impl CardExpiry {
+    /// Parses gateway fields such as `"05"` and `"2027"`; two-digit years
+    /// are taken as 20xx.
+    pub fn parse(month: &str, year: &str) -> Option<Self> {
+        let month: u32 = month.trim().parse().ok().filter(|m| (1..=12).contains(m))?;
+        let year: i32 = year.trim().parse().ok()?;
+        let year = if (0..100).contains(&year) { 2000 + year } else { year };
+        Some(Self { month, year })
+    }
+
+    /// Cards work until the end of their expiry month.
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        let (year, month) = if self.month == 12 { (self.year + 1, 1) } else { (self.year, self.month + 1) };
+        Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single()
+    }
+
+    /// E.g. `05/2027`.
+    pub fn label(&self) -> String {
+        format!("{:02}/{}", self.month, self.year)
+    }
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use crate::models::payment::default_currency;
+use crate::models::validation::{FieldErrors, Validate};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub enum RefundStatus {
+    Pending,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Refund {
+    pub id: String,
+    pub payment_id: String,
+    pub payment_merchant_transaction_id: String,
+    pub refund_transaction_id: String,
+    pub amount: f64,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub reason: Option<String>,
+    pub status: RefundStatus,
+    pub peach_refund_id: Option<String>,
+    pub result_code: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateRefundDto {
+    pub amount: Option<f64>,
+    pub reason: Option<String>,
+    /// Credit the user's wallet instead of refunding through the gateway.
+    #[serde(default)]
+    pub to_wallet: bool,
+}
+
+impl Validate for CreateRefundDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        if self.amount.is_some_and(|amount| !amount.is_finite() || amount <= 0.0) {
+            errors.add("amount", "must be greater than zero");
+        }
+        errors.into_result()
+    }
+}
@@ -0,0 +1,36 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+use chrono::{DateTime, NaiveDate, Utc};
+use std::collections::BTreeMap;
+
+/// Billing totals for one UTC day, sent to operators each evening and
+/// available on demand at `/admin/reports/daily`. Money totals are in
+/// `currency`, the FX base; per-currency totals are kept as charged.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DailySummary {
+    pub date: NaiveDate,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub payments_succeeded: u64,
+    pub payments_failed: u64,
+    pub currency: String,
+    pub revenue: f64,
+    pub refunded: f64,
+    pub revenue_by_currency: BTreeMap<String, f64>,
+    pub refunded_by_currency: BTreeMap<String, f64>,
+    /// Currencies with no exchange rate; excluded from `revenue` and `refunded`.
+    pub unconverted_currencies: Vec<String>,
+    pub renewals_succeeded: u64,
+    pub renewals_failed: u64,
+    pub new_subscriptions: u64,
+    pub cancelled_subscriptions: u64,
+    pub suspended_subscriptions: u64,
+    pub webhook_errors: u64,
+}
+
+impl DailySummary {
+    /// Revenue net of refunds completed the same day.
+    pub fn net_revenue(&self) -> f64 {
+        self.revenue - self.refunded
+    }
+}
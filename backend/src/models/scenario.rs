@@ -0,0 +1,194 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use crate::models::payment::PaymentStatus;
+use crate::models::subscription::SubscriptionStatus;
+use crate::models::validation::{FieldErrors, Validate};
+use crate::models::webhook_event::WebhookOutcome;
+
+/// Most steps a single scenario may have.
+pub const MAX_SCENARIO_STEPS: usize = 50;
+
+/// Latest `at_ms` a step may have; the request waits for the whole run.
+pub const MAX_SCENARIO_DURATION_MS: u64 = 120_000;
+
+fn default_payment_label() -> String {
+    "payment".to_string()
+}
+
+/// A scripted sequence of payment events for QA, run against the sandbox.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ScenarioDto {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub steps: Vec<ScenarioStep>,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ScenarioStep {
+    /// Milliseconds after the scenario starts. Steps due at the same time
+    /// run concurrently, to reproduce races.
+    #[serde(default)]
+    pub at_ms: u64,
+    pub action: ScenarioAction,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SimulatedResult {
+    Success,
+    Pending,
+    Failure,
+}
+
+/// What a step does. Payments are referred to by the `label` given when they
+/// were initiated, `payment` unless set.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScenarioAction {
+    /// Creates a user with a pending subscription and a pending payment for
+    /// it, as initiating a checkout would, without calling the gateway.
+    InitiatePayment {
+        #[serde(default = "default_payment_label")]
+        label: String,
+        #[serde(default)]
+        amount: Option<f64>,
+    },
+    /// Delivers a payment webhook. `occurred_offset_ms` sets the gateway's
+    /// event time relative to the scenario start, to deliver events out of
+    /// order; unset, the event happened on delivery.
+    PaymentWebhook {
+        #[serde(default = "default_payment_label")]
+        payment: String,
+        result: SimulatedResult,
+        #[serde(default)]
+        occurred_offset_ms: Option<i64>,
+    },
+    /// Delivers a refund webhook. The first one for a payment opens a pending
+    /// refund of `amount` (all of it by default); later ones repeat it.
+    RefundWebhook {
+        #[serde(default = "default_payment_label")]
+        payment: String,
+        result: SimulatedResult,
+        #[serde(default)]
+        amount: Option<f64>,
+    },
+    /// Delivers a chargeback webhook for the payment.
+    ChargebackWebhook {
+        #[serde(default = "default_payment_label")]
+        payment: String,
+    },
+    /// Checks the payment's and its subscription's status at this point.
+    Expect {
+        #[serde(default = "default_payment_label")]
+        payment: String,
+        #[serde(default)]
+        payment_status: Option<PaymentStatus>,
+        #[serde(default)]
+        subscription_status: Option<SubscriptionStatus>,
+    },
+}
+
+impl ScenarioAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScenarioAction::InitiatePayment { .. } => "initiate_payment",
+            ScenarioAction::PaymentWebhook { .. } => "payment_webhook",
+            ScenarioAction::RefundWebhook { .. } => "refund_webhook",
+            ScenarioAction::ChargebackWebhook { .. } => "chargeback_webhook",
+            ScenarioAction::Expect { .. } => "expect",
+        }
+    }
+
+    /// Label of the payment the step acts on.
+    pub fn label(&self) -> &str {
+        match self {
+            ScenarioAction::InitiatePayment { label, .. } => label,
+            ScenarioAction::PaymentWebhook { payment, .. }
+            | ScenarioAction::RefundWebhook { payment, .. }
+            | ScenarioAction::ChargebackWebhook { payment }
+            | ScenarioAction::Expect { payment, .. } => payment,
+        }
+    }
+}
+
+impl Validate for ScenarioDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        errors.check("steps", self.check_steps());
+        errors.into_result()
+    }
+}
+
+impl ScenarioDto {
+    /// Every payment must be initiated once, by a step due before any step
+    /// that uses it.
+    fn check_steps(&self) -> Result<(), String> {
+        if self.steps.is_empty() || self.steps.len() > MAX_SCENARIO_STEPS {
+            return Err(format!("A scenario needs between 1 and {} steps", MAX_SCENARIO_STEPS));
+        }
+        let mut initiated: Vec<(&str, u64)> = Vec::new();
+        for step in &self.steps {
+            if step.at_ms > MAX_SCENARIO_DURATION_MS {
+                return Err(format!("at_ms must be at most {}", MAX_SCENARIO_DURATION_MS));
+            }
+            if let ScenarioAction::InitiatePayment { label, amount } = &step.action {
+                if label.trim().is_empty() {
+                    return Err("Payment labels must not be empty".to_string());
+                }
+                if initiated.iter().any(|(l, _)| *l == label.as_str()) {
+                    return Err(format!("Payment {} is initiated more than once", label));
+                }
+                if amount.is_some_and(|a| !a.is_finite() || a <= 0.0) {
+                    return Err("amount must be positive".to_string());
+                }
+                initiated.push((label, step.at_ms));
+            }
+        }
+        for step in &self.steps {
+            if matches!(step.action, ScenarioAction::InitiatePayment { .. }) {
+                continue;
+            }
+            let label = step.action.label();
+            match initiated.iter().find(|(l, _)| *l == label) {
+                Some((_, at_ms)) if *at_ms < step.at_ms => {}
+                Some(_) => return Err(format!("{} uses payment {} before it is initiated", step.action.name(), label)),
+                None => return Err(format!("{} uses payment {}, which is never initiated", step.action.name(), label)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What happened at one step.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ScenarioStepResult {
+    /// Position of the step in the request.
+    pub index: usize,
+    pub action: String,
+    pub at_ms: u64,
+    /// When the step finished, in milliseconds after the scenario started.
+    pub finished_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub merchant_transaction_id: Option<String>,
+    /// How the delivered webhook was handled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<WebhookOutcome>,
+    /// False when the step couldn't run or an expectation didn't hold. A
+    /// webhook that failed processing still passes, with the error in `detail`.
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ScenarioReport {
+    pub run_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub started_at: DateTime<Utc>,
+    /// In request order.
+    pub steps: Vec<ScenarioStepResult>,
+    /// Every step passed.
+    pub passed: bool,
+}
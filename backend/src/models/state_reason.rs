@@ -0,0 +1,68 @@
+//! Codes stored as `state_reason` on subscriptions and payments: why the
+//! record is in its current status, for clients to turn into messages.
+//! Subscriptions' `at_risk_reason` uses the same kind of codes.
+//! Codes are stable snake_case strings; new ones may be added.
+
+use crate::models::payment::PaymentStatus;
+use crate::services::dunning::decline_reason;
+
+// Subscriptions
+pub const AWAITING_FIRST_PAYMENT: &str = "awaiting_first_payment";
+pub const ACTIVATED: &str = "activated";
+pub const RENEWED: &str = "renewed";
+pub const RENEWAL_SKIPPED: &str = "renewal_skipped";
+pub const MANUAL_PAYMENT_RECORDED: &str = "manual_payment_recorded";
+pub const PLAN_CHANGED: &str = "plan_changed";
+pub const PAUSED_BY_USER: &str = "paused_by_user";
+pub const RESUMED_BY_USER: &str = "resumed_by_user";
+pub const CANCELLATION_SCHEDULED_BY_USER: &str = "cancellation_scheduled_by_user";
+pub const CANCELLED_BY_USER: &str = "cancelled_by_user";
+pub const CANCELLED_AT_PERIOD_END: &str = "cancelled_at_period_end";
+pub const CANCELLED_BY_ADMIN: &str = "cancelled_by_admin";
+/// A scheduled cancellation was withdrawn before the period ended.
+pub const REACTIVATED_BY_USER: &str = "reactivated_by_user";
+/// Entered grace without a renewal attempt failing, e.g. no card on file.
+/// Failed attempts keep their `payment_failed_*` code instead.
+pub const RENEWAL_OVERDUE: &str = "renewal_overdue";
+pub const SUSPENDED_AFTER_GRACE: &str = "suspended_after_grace";
+pub const SUSPENDED_AFTER_FAILED_RETRIES: &str = "suspended_after_failed_retries";
+pub const DOWNGRADED_AFTER_GRACE: &str = "downgraded_after_grace";
+pub const DOWNGRADED_AFTER_FAILED_RETRIES: &str = "downgraded_after_failed_retries";
+
+// Subscriptions at risk of failing their next renewal (`at_risk_reason`)
+pub const CARD_EXPIRING: &str = "card_expiring";
+
+// Payments (failed renewals also set these on the subscription)
+pub const AWAITING_PAYMENT: &str = "awaiting_payment";
+pub const AWAITING_AUTHENTICATION: &str = "awaiting_authentication";
+pub const PAYMENT_AUTHORIZED: &str = "payment_authorized";
+pub const PAYMENT_SUCCEEDED: &str = "payment_succeeded";
+pub const PAYMENT_FAILED: &str = "payment_failed";
+pub const PAYMENT_FAILED_GATEWAY_ERROR: &str = "payment_failed_gateway_error";
+pub const PAYMENT_CANCELLED: &str = "payment_cancelled";
+/// An authorisation released by an administrator instead of captured.
+pub const PAYMENT_VOIDED: &str = "payment_voided";
+/// The shopper never finished the 3-D Secure challenge.
+pub const AUTHENTICATION_ABANDONED: &str = "authentication_abandoned";
+pub const REFUNDED: &str = "refunded";
+pub const PARTIALLY_REFUNDED: &str = "partially_refunded";
+
+/// `payment_failed_<reason>` for a charge the gateway declined with
+/// `result_code`, e.g. `payment_failed_insufficient_funds`.
+pub fn payment_failed(result_code: &str) -> String {
+    format!("{}_{}", PAYMENT_FAILED, decline_reason(result_code))
+}
+
+/// Reason for a payment status change when nothing more specific is known.
+pub fn for_payment_status(status: &PaymentStatus) -> &'static str {
+    match status {
+        PaymentStatus::Pending => AWAITING_PAYMENT,
+        PaymentStatus::AwaitingAuthentication => AWAITING_AUTHENTICATION,
+        PaymentStatus::Authorized => PAYMENT_AUTHORIZED,
+        PaymentStatus::Completed => PAYMENT_SUCCEEDED,
+        PaymentStatus::Failed => PAYMENT_FAILED,
+        PaymentStatus::Cancelled => PAYMENT_CANCELLED,
+        PaymentStatus::Refunded => REFUNDED,
+        PaymentStatus::PartiallyRefunded => PARTIALLY_REFUNDED,
+    }
+}
@@ -1,35 +1,498 @@
 use serde::{Deserialize, Serialize};
-use chrono::{DateTime, Utc};
-use crate::models::payment::PaymentMethod; 
+use utoipa::ToSchema;
+use chrono::{DateTime, Datelike, Duration, Utc};
+use crate::models::payment::{default_currency, PaymentMethod};
+use crate::models::state_reason;
+use crate::models::usage::UsagePricing;
+use crate::models::validation::{FieldErrors, Validate};
+use crate::services::billing_calendar;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateSubscriptionDto {
     pub user_id: String,
+    pub plan_id: Option<String>,
     pub plan_name: String,
     pub price: f64,
+    pub currency: String,
     pub payment_method: Option<PaymentMethod>, 
+    pub grace_period_days: u32,
+    pub billing_period_days: u32,
+    pub billing_period_months: Option<u32>,
+    pub suspension_policy: SuspensionPolicy,
+    pub usage_pricing: Option<UsagePricing>,
+    pub seat_count: u32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Subscription {
      pub id: String,  // Changed from Uuid to String
     pub user_id: String,
+    #[serde(default)]
+    pub plan_id: Option<String>,
     pub plan_name: String,
+    /// Per seat; see `total_price`.
     pub price: f64,
+    #[serde(default = "default_currency")]
+    pub currency: String,
     pub status: SubscriptionStatus,
      pub payment_method: Option<PaymentMethod>, // ✅ Add this
       pub payment_brand: Option<String>,
     pub start_date: Option<DateTime<Utc>>,
     pub end_date: Option<DateTime<Utc>>,
+    #[serde(default = "legacy_grace_period_days")]
+    pub grace_period_days: u32,
+    #[serde(default = "default_billing_period_days")]
+    pub billing_period_days: u32,
+    /// Set for monthly, quarterly and annual plans, whose periods are whole
+    /// calendar months ending on `billing_anchor_day`. Other intervals, and
+    /// subscriptions from before calendar billing, last `billing_period_days`.
+    #[serde(default)]
+    pub billing_period_months: Option<u32>,
+    /// Day of the month periods end on (the last day in shorter months),
+    /// taken from the date the subscription was activated.
+    #[serde(default)]
+    pub billing_anchor_day: Option<u32>,
+    #[serde(default)]
+    pub grace_end_date: Option<DateTime<Utc>>,
+    /// Copied from the plan (or global config) when the subscription is created.
+    #[serde(default)]
+    pub suspension_policy: SuspensionPolicy,
+    #[serde(default)]
+    pub renewal_attempts: u32,
+    #[serde(default)]
+    pub last_renewal_attempt_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub next_renewal_attempt_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_renewal_error: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Peach timestamp of the payment webhook that last activated this subscription.
+    #[serde(default)]
+    pub last_event_at: Option<DateTime<Utc>>,
+    /// Set when the user cancels for the end of the paid period; the renewal
+    /// task cancels the subscription instead of charging for another one.
+    #[serde(default)]
+    pub cancel_at_period_end: bool,
+    /// When the user (or an admin) cancelled. Rows cancelled before
+    /// `cancellation_effective_at` existed hold the effective date here.
+    #[serde(default)]
+    pub cancelled_at: Option<DateTime<Utc>>,
+    /// When the cancellation takes (or took) effect: the end of the paid
+    /// period for a scheduled cancellation.
+    #[serde(default)]
+    pub cancellation_effective_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub cancellation_reason: Option<String>,
+    /// When the current pause started; `None` unless `Paused`.
+    #[serde(default)]
+    pub paused_at: Option<DateTime<Utc>>,
+    /// Time spent paused during the current period. `end_date` has already
+    /// been pushed back by this much.
+    #[serde(default)]
+    pub pause_duration_secs: i64,
+    /// Copied from the plan; `None` unless the subscription is metered.
+    #[serde(default)]
+    pub usage_pricing: Option<UsagePricing>,
+    /// Seats paid for, the owner's included. Changes are billed from the next
+    /// renewal.
+    #[serde(default = "default_seat_count")]
+    pub seat_count: u32,
+    /// Set by the user; the next renewal extends the period by one cycle
+    /// without charging, then clears it.
+    #[serde(default)]
+    pub skip_next_renewal: bool,
+    /// Why the subscription has its current status, e.g. `paused_by_user`;
+    /// one of the codes in `models::state_reason`.
+    #[serde(default)]
+    pub state_reason: Option<String>,
+    /// Plan whose features apply while `Downgraded`, set when dunning moved
+    /// the subscription to the configured fallback plan; the free tier when
+    /// `None`.
+    #[serde(default)]
+    pub fallback_plan_id: Option<String>,
+    /// Why the next renewal is likely to fail, e.g. `card_expiring`; cleared
+    /// when the user saves a new card.
+    #[serde(default)]
+    pub at_risk_reason: Option<String>,
+    /// What the period being paid for in parts costs, fixed when the first
+    /// part payment arrives; `None` while nothing has been part-paid.
+    #[serde(default)]
+    pub amount_due: Option<f64>,
+    /// Paid towards `amount_due` so far. Reset when the subscription is
+    /// activated.
+    #[serde(default)]
+    pub amount_paid: f64,
+    /// Merchant transaction ids of the payments counted in `amount_paid`, so
+    /// a payment seen by both its webhook and a status poll counts once.
+    #[serde(default)]
+    pub part_payments: Vec<String>,
+    #[serde(default)]
+    pub tenant_id: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
 pub enum SubscriptionStatus {
     Pending,
     Active,
+    /// Past `end_date` without a successful renewal. Access continues and
+    /// renewal is still attempted until the suspension policy takes effect
+    /// (see `lapses_at`); paying returns it to `Active`.
+    Grace,
     Expired,
     Cancelled,
     Suspended,
+    /// Lapsed under a `Downgrade` policy, or moved to the dunning fallback
+    /// plan: the record keeps its paid plan so it can be renewed, but the user
+    /// only gets the free tier (or `fallback_plan_id`) meanwhile.
+    Downgraded,
+    /// Put on hold by the user: no access and no renewals until resumed, when
+    /// the period is extended by the time spent paused.
+    Paused,
+}
+
+/// What happens to an unpaid subscription once its grace period is over.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum SuspensionPolicy {
+    /// Suspend `days_after_grace` days after the grace period ends. The user
+    /// can reactivate by paying a period's price less
+    /// `reactivation_discount_percent`.
+    Suspend {
+        days_after_grace: u32,
+        #[serde(default)]
+        reactivation_discount_percent: u32,
+    },
+    /// Never suspend; drop the user to the free tier instead.
+    Downgrade,
+}
+
+/// Subscriptions created before the policy was configurable were suspended as
+/// soon as grace ran out.
+impl Default for SuspensionPolicy {
+    fn default() -> Self {
+        SuspensionPolicy::Suspend { days_after_grace: 0, reactivation_discount_percent: 0 }
+    }
+}
+
+impl SuspensionPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            SuspensionPolicy::Suspend { reactivation_discount_percent, .. } if *reactivation_discount_percent > 100 => {
+                Err("reactivation_discount_percent must be at most 100".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// When a cancellation takes effect.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CancelAt {
+    /// Keep access until `end_date`, then cancel instead of renewing.
+    #[default]
+    PeriodEnd,
+    Immediately,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct CancelSubscriptionDto {
+    #[serde(default)]
+    pub when: CancelAt,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl Validate for CancelSubscriptionDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        if self.reason.as_ref().is_some_and(|r| r.trim().chars().count() > MAX_CANCELLATION_REASON_LEN) {
+            errors.add("reason", format!("Reason must be at most {} characters", MAX_CANCELLATION_REASON_LEN));
+        }
+        errors.into_result()
+    }
+}
+
+/// Subscriptions created before seats existed cover only their owner.
+pub fn default_seat_count() -> u32 {
+    1
+}
+
+/// Most seats a single subscription can hold.
+pub const MAX_SEAT_COUNT: u32 = 500;
+
+pub fn validate_seat_count(seat_count: u32) -> Result<(), String> {
+    if seat_count == 0 || seat_count > MAX_SEAT_COUNT {
+        return Err(format!("Seat count must be between 1 and {}", MAX_SEAT_COUNT));
+    }
+    Ok(())
+}
+
+/// A billing period the user skipped, used to enforce the plan's yearly limit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenewalSkip {
+    pub id: String,
+    pub subscription_id: String,
+    pub user_id: String,
+    /// The period given without charge.
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct SkipRenewalDto {
+    /// Needed only when the user has more than one active subscription.
+    #[serde(default)]
+    pub subscription_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct ReactivateSubscriptionDto {
+    /// Needed only when the user has more than one suspended subscription.
+    #[serde(default)]
+    pub subscription_id: Option<String>,
+    #[serde(default)]
+    pub payment_method: Option<PaymentMethod>,
+    /// Shopper's country code, for payment method availability.
+    #[serde(default)]
+    pub region: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct RestartSubscriptionDto {
+    /// Win-back coupon taking a percentage off the first new period.
+    #[serde(default)]
+    pub coupon: Option<String>,
+    /// Pay through a new checkout even when a card is saved.
+    #[serde(default)]
+    pub new_card: bool,
+    /// For a new checkout; a method other than card always uses one.
+    #[serde(default)]
+    pub payment_method: Option<PaymentMethod>,
+    /// Shopper's country code, for payment method availability.
+    #[serde(default)]
+    pub region: Option<String>,
+}
+
+/// Longest cancellation reason stored.
+pub const MAX_CANCELLATION_REASON_LEN: usize = 500;
+
+/// What a subscription currently unlocks for its owner.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessLevel {
+    Full,
+    Free,
+    None,
+}
+
+impl Subscription {
+    /// A new subscription awaiting its first payment. The id is left for
+    /// storage to assign.
+    pub fn pending(dto: CreateSubscriptionDto, tenant_id: Option<String>) -> Self {
+        let now = Utc::now();
+        Subscription {
+            id: String::new(),
+            user_id: dto.user_id,
+            plan_id: dto.plan_id,
+            plan_name: dto.plan_name,
+            price: dto.price,
+            currency: dto.currency,
+            status: SubscriptionStatus::Pending,
+            payment_method: dto.payment_method,
+            payment_brand: None,
+            start_date: None,
+            end_date: None,
+            grace_period_days: dto.grace_period_days,
+            billing_period_days: dto.billing_period_days,
+            billing_period_months: dto.billing_period_months,
+            billing_anchor_day: None,
+            grace_end_date: None,
+            suspension_policy: dto.suspension_policy,
+            renewal_attempts: 0,
+            last_renewal_attempt_at: None,
+            next_renewal_attempt_at: None,
+            last_renewal_error: None,
+            tags: Vec::new(),
+            last_event_at: None,
+            cancel_at_period_end: false,
+            cancelled_at: None,
+            cancellation_effective_at: None,
+            cancellation_reason: None,
+            paused_at: None,
+            pause_duration_secs: 0,
+            usage_pricing: dto.usage_pricing,
+            seat_count: dto.seat_count,
+            skip_next_renewal: false,
+            state_reason: Some(state_reason::AWAITING_FIRST_PAYMENT.to_string()),
+            fallback_plan_id: None,
+            at_risk_reason: None,
+            amount_due: None,
+            amount_paid: 0.0,
+            part_payments: Vec::new(),
+            tenant_id,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// What a period costs for every seat, VAT included.
+    pub fn total_price(&self) -> f64 {
+        ((self.price * self.seat_count as f64) * 100.0).round() / 100.0
+    }
+
+    /// When the cancellation takes (or took) effect, for rows that predate
+    /// `cancellation_effective_at` too.
+    pub fn cancels_at(&self) -> Option<DateTime<Utc>> {
+        self.cancellation_effective_at.or(self.cancelled_at)
+    }
+
+    /// End of the grace period, for rows that predate `grace_end_date` too.
+    pub fn grace_ends_at(&self) -> Option<DateTime<Utc>> {
+        self.grace_end_date.or_else(|| {
+            self.end_date.map(|end| end + Duration::days(self.grace_period_days as i64))
+        })
+    }
+
+    /// When the suspension policy takes effect; `None` while there is no
+    /// billing period yet.
+    pub fn lapses_at(&self) -> Option<DateTime<Utc>> {
+        let grace_end = self.grace_ends_at()?;
+        Some(match self.suspension_policy {
+            SuspensionPolicy::Suspend { days_after_grace, .. } => grace_end + Duration::days(days_after_grace as i64),
+            SuspensionPolicy::Downgrade => grace_end,
+        })
+    }
+
+    /// What the user pays to reactivate after being suspended for non-payment:
+    /// one period at the policy's reactivation discount. `None` when the
+    /// subscription isn't suspended or was suspended for another reason.
+    pub fn reactivation_price(&self) -> Option<f64> {
+        let unpaid = matches!(
+            self.state_reason.as_deref(),
+            Some(state_reason::SUSPENDED_AFTER_GRACE | state_reason::SUSPENDED_AFTER_FAILED_RETRIES)
+        );
+        if self.status != SubscriptionStatus::Suspended || !unpaid {
+            return None;
+        }
+        let discount = match self.suspension_policy {
+            SuspensionPolicy::Suspend { reactivation_discount_percent, .. } => reactivation_discount_percent.min(100),
+            SuspensionPolicy::Downgrade => 0,
+        };
+        Some((self.total_price() * (100 - discount) as f64).round() / 100.0)
+    }
+
+    /// Whether a payment can settle part of the next period: only while the
+    /// subscription is waiting for its first payment or for reactivation.
+    pub fn accepts_part_payments(&self) -> bool {
+        matches!(self.status, SubscriptionStatus::Pending | SubscriptionStatus::Suspended)
+    }
+
+    /// What paying for the next period costs in total: the amount fixed by
+    /// the first part payment, else the reactivation price or a full period.
+    pub fn period_amount_due(&self) -> f64 {
+        self.amount_due
+            .or_else(|| self.reactivation_price())
+            .unwrap_or_else(|| self.total_price())
+    }
+
+    /// What's still to pay before the subscription is activated.
+    pub fn outstanding_balance(&self) -> f64 {
+        ((self.period_amount_due() - self.amount_paid).max(0.0) * 100.0).round() / 100.0
+    }
+
+    /// Months per period and anchor day, for calendar-billed subscriptions
+    /// that have been activated.
+    fn calendar_period(&self) -> Option<(u32, u32)> {
+        Some((self.billing_period_months?, self.billing_anchor_day?))
+    }
+
+    /// End of the period that follows one ending at `end`.
+    pub fn next_period_end(&self, end: DateTime<Utc>) -> DateTime<Utc> {
+        match self.calendar_period() {
+            Some((months, anchor_day)) => billing_calendar::add_months(end, months, anchor_day),
+            None => end + Duration::days(self.billing_period_days as i64),
+        }
+    }
+
+    /// End of a fresh period starting at `start`, and the anchor day it sets:
+    /// the day of `start` for calendar billing, otherwise `None`.
+    pub fn period_from(&self, start: DateTime<Utc>) -> (DateTime<Utc>, Option<u32>) {
+        match self.billing_period_months {
+            Some(months) => (billing_calendar::add_months(start, months, start.day()), Some(start.day())),
+            None => (start + Duration::days(self.billing_period_days as i64), None),
+        }
+    }
+
+    /// The period a renewal at `now` pays for. Calendar periods run from one
+    /// anchor date to the next, so a late renewal pays for the period already
+    /// under way; other subscriptions start a new period at `now`.
+    pub fn renewal_period(&self, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        match (self.calendar_period(), self.end_date) {
+            (Some(_), Some(end)) => {
+                let (mut start, mut next) = (end, self.next_period_end(end));
+                while next <= now {
+                    start = next;
+                    next = self.next_period_end(next);
+                }
+                (start, next)
+            }
+            _ => (now, now + Duration::days(self.billing_period_days as i64)),
+        }
+    }
+
+    /// The next `count` periods renewals will pay for, each as its start and
+    /// end, beginning where the current period ends.
+    pub fn upcoming_periods(&self, count: usize) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        let first = self.end_date.map(|end| (end, self.next_period_end(end)));
+        std::iter::successors(first, |(_, end)| Some((*end, self.next_period_end(*end))))
+            .take(count)
+            .collect()
+    }
+
+    /// Start of the current billing period, allowing for time spent paused.
+    pub fn period_start(&self) -> Option<DateTime<Utc>> {
+        if self.calendar_period().is_some() {
+            return self.start_date;
+        }
+        self.end_date.map(|end| {
+            end - Duration::days(self.billing_period_days as i64) - Duration::seconds(self.pause_duration_secs)
+        })
+    }
+
+    /// How many days before renewal to send the reminder: `notification_days`,
+    /// capped at a third of the period so short (e.g. weekly) plans aren't
+    /// reminded straight after renewing.
+    pub fn reminder_days(&self, notification_days: u32) -> u32 {
+        notification_days.min((self.billing_period_days / 3).max(1))
+    }
+
+    /// Active subscriptions and those in grace get the paid plan;
+    /// downgraded ones fall back to the free tier.
+    pub fn access_level(&self) -> AccessLevel {
+        match self.status {
+            SubscriptionStatus::Active | SubscriptionStatus::Grace => AccessLevel::Full,
+            SubscriptionStatus::Downgraded => AccessLevel::Free,
+            _ => AccessLevel::None,
+        }
+    }
+}
+
+/// Subscriptions created before grace became per-plan were suspended one day
+/// after `end_date`; keep that behaviour for rows without a stored value.
+pub const LEGACY_GRACE_PERIOD_DAYS: u32 = 1;
+
+fn legacy_grace_period_days() -> u32 {
+    LEGACY_GRACE_PERIOD_DAYS
+}
+
+/// Billing period for subscriptions created before it was stored per subscription.
+pub const DEFAULT_BILLING_PERIOD_DAYS: u32 = 30;
+
+fn default_billing_period_days() -> u32 {
+    DEFAULT_BILLING_PERIOD_DAYS
 }
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use crate::models::validation::{FieldErrors, Validate};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteTarget {
+    User,
+    Subscription,
+}
+
+/// Internal support note. Notes are never shown to the subscriber; `user_id`
+/// is the owning user even for subscription notes so they appear on that
+/// user's timeline.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SupportNote {
+    pub id: String,
+    pub target_type: NoteTarget,
+    pub target_id: String,
+    pub user_id: String,
+    pub body: String,
+    pub author: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateNoteDto {
+    pub body: String,
+    pub author: Option<String>,
+}
+
+impl Validate for CreateNoteDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        check_note_body(&self.body)
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateNoteDto {
+    pub body: String,
+}
+
+impl Validate for UpdateNoteDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        check_note_body(&self.body)
+    }
+}
+
+fn check_note_body(body: &str) -> Result<(), FieldErrors> {
+    let mut errors = FieldErrors::new();
+    if body.trim().is_empty() {
+        errors.add("body", "Note body must not be empty");
+    }
+    errors.into_result()
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetTagsDto {
+    pub tags: Vec<String>,
+}
+
+impl Validate for SetTagsDto {}
+
+/// Lowercases, trims and de-duplicates tags so searches match regardless of
+/// how support typed them.
+pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut tags: Vec<String> = tags
+        .into_iter()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
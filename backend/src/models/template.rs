@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use crate::models::validation::Validate;
+
+/// Longest template body accepted, in characters.
+pub const MAX_TEMPLATE_LEN: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateChannel {
+    Email,
+    InApp,
+}
+
+impl TemplateChannel {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "email" => Some(TemplateChannel::Email),
+            "in_app" => Some(TemplateChannel::InApp),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TemplateChannel::Email => "email",
+            TemplateChannel::InApp => "in_app",
+        }
+    }
+}
+
+/// A merchant's copy for one message in one locale, used instead of the
+/// built-in text. Email bodies start with a `Subject:` line.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct MessageTemplate {
+    pub channel: TemplateChannel,
+    /// e.g. `renewal_upcoming` or `card_expiring`.
+    #[serde(alias = "message_key")]
+    pub key: String,
+    /// e.g. `en-ZA` or `af-ZA`.
+    pub locale: String,
+    pub body: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetMessageTemplateDto {
+    /// `{{variable}}` placeholders are replaced when the message is sent.
+    pub body: String,
+}
+
+// The placeholders allowed depend on the template, so the handler checks them
+impl Validate for SetMessageTemplateDto {}
+
+/// A message merchants can customise, with the variables it can use, its
+/// built-in text and any saved copy.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TemplateSummary {
+    pub channel: TemplateChannel,
+    pub key: String,
+    pub variables: Vec<String>,
+    pub default_body: String,
+    /// Built-in text in other languages, keyed by locale.
+    pub translations: BTreeMap<String, String>,
+    pub overrides: Vec<MessageTemplate>,
+}
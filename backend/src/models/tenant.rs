@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use crate::models::validation::{FieldErrors, Validate};
+use crate::services::tenant;
+
+/// Longest DNS label, and so the longest subdomain.
+const MAX_SUBDOMAIN_LEN: usize = 63;
+
+/// A tenant's own Peach merchant account. Endpoints, currencies and the
+/// checkout widget stay those of the deployment's `PEACH_*` settings.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PeachCredentials {
+    pub entity_id: String,
+    pub client_id: String,
+    #[serde(skip_serializing)]
+    #[schema(write_only)]
+    pub client_secret: String,
+    pub merchant_id: String,
+    /// Key Peach signs the tenant's webhooks with.
+    #[serde(skip_serializing)]
+    #[schema(write_only)]
+    pub webhook_secret_key: String,
+    /// Where Peach sends the tenant's webhooks. Required, and on the tenant's
+    /// subdomain: webhooks are verified with the key of the tenant they
+    /// arrive for, so the deployment's `PEACH_NOTIFICATION_URL` would reject
+    /// them. Only credentials saved before it was required lack one.
+    #[serde(default)]
+    pub notification_url: Option<String>,
+    /// Defaults to `PEACH_SHOPPER_RESULT_URL`.
+    #[serde(default)]
+    pub shopper_result_url: Option<String>,
+}
+
+impl Validate for PeachCredentials {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        let required = [
+            ("entity_id", &self.entity_id),
+            ("client_id", &self.client_id),
+            ("client_secret", &self.client_secret),
+            ("merchant_id", &self.merchant_id),
+            ("webhook_secret_key", &self.webhook_secret_key),
+        ];
+        for (name, value) in required {
+            if value.trim().is_empty() {
+                errors.add(name, "can't be empty");
+            }
+        }
+        match self.notification_url.as_deref().map(str::trim) {
+            None | Some("") => errors.add("notification_url", "can't be empty"),
+            Some(url) if reqwest::Url::parse(url).map_or(true, |url| url.host_str().is_none()) => {
+                errors.add("notification_url", format!("{} is not a valid URL", url))
+            }
+            Some(_) => {}
+        }
+        errors.into_result()
+    }
+}
+
+impl PeachCredentials {
+    /// Whether `notification_url` reaches the tenant at `subdomain` under
+    /// `base_domain`, where its webhooks are resolved to it.
+    pub fn notifies_subdomain(&self, subdomain: &str, base_domain: &str) -> bool {
+        self.notification_url
+            .as_deref()
+            .and_then(|url| reqwest::Url::parse(url.trim()).ok())
+            .and_then(|url| url.host_str().and_then(|host| tenant::subdomain(host, base_domain)))
+            .is_some_and(|found| found == subdomain)
+    }
+
+    /// Everything, secrets included, for writing to the database.
+    pub fn stored(&self) -> serde_json::Value {
+        serde_json::json!({
+            "entity_id": self.entity_id,
+            "client_id": self.client_id,
+            "client_secret": self.client_secret,
+            "merchant_id": self.merchant_id,
+            "webhook_secret_key": self.webhook_secret_key,
+            "notification_url": self.notification_url,
+            "shopper_result_url": self.shopper_result_url,
+        })
+    }
+}
+
+/// A merchant served by this deployment. Its users, subscriptions and
+/// payments are only visible to requests resolved to it, through an API key
+/// issued for it or its subdomain.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Tenant {
+    pub id: String,
+    pub name: String,
+    /// e.g. `acme` for `acme.pay.example.com` when `TENANT_BASE_DOMAIN` is
+    /// `pay.example.com`.
+    pub subdomain: String,
+    /// Charges go through the deployment's own Peach account when unset.
+    #[serde(default)]
+    pub peach: Option<PeachCredentials>,
+    /// Requests for a disabled tenant are refused.
+    #[serde(default)]
+    pub disabled_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateTenantDto {
+    pub name: String,
+    /// Lowercase letters, digits and hyphens.
+    pub subdomain: String,
+    pub peach: Option<PeachCredentials>,
+}
+
+impl Validate for CreateTenantDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        if self.name.trim().is_empty() {
+            errors.add("name", "Name can't be empty");
+        }
+        errors.check("subdomain", validate_subdomain(&self.subdomain));
+        if let Some(peach) = &self.peach {
+            errors.nested("peach", peach.validate());
+        }
+        errors.into_result()
+    }
+}
+
+/// Only the fields sent are changed.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateTenantDto {
+    pub name: Option<String>,
+    /// Replaces all of the tenant's Peach credentials.
+    pub peach: Option<PeachCredentials>,
+    /// `true` refuses the tenant's requests until set back to `false`.
+    pub disabled: Option<bool>,
+}
+
+impl Validate for UpdateTenantDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        if self.name.as_ref().is_some_and(|name| name.trim().is_empty()) {
+            errors.add("name", "Name can't be empty");
+        }
+        if let Some(peach) = &self.peach {
+            errors.nested("peach", peach.validate());
+        }
+        errors.into_result()
+    }
+}
+
+fn validate_subdomain(subdomain: &str) -> Result<(), String> {
+    let valid = !subdomain.is_empty()
+        && subdomain.len() <= MAX_SUBDOMAIN_LEN
+        && subdomain.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !subdomain.starts_with('-')
+        && !subdomain.ends_with('-');
+    if valid {
+        Ok(())
+    } else {
+        Err("must be 1-63 lowercase letters, digits or hyphens, not starting or ending with a hyphen".to_string())
+    }
+}
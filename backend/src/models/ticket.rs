@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use crate::models::validation::{FieldErrors, Validate};
+
+/// Longest subject or description stored on a ticket.
+pub const MAX_TICKET_TEXT_LEN: usize = 2000;
+
+/// What opened a ticket.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TicketSource {
+    /// A checkout or renewal charge failed.
+    FailedPayment,
+    /// The cardholder disputed a charge with their bank.
+    Dispute,
+    /// The user reported a charge they don't recognise.
+    UnrecognizedCharge,
+    /// Opened by support.
+    Manual,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TicketStatus {
+    Open,
+    InProgress,
+    Resolved,
+    Closed,
+}
+
+impl TicketStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TicketStatus::Open => "open",
+            TicketStatus::InProgress => "in_progress",
+            TicketStatus::Resolved => "resolved",
+            TicketStatus::Closed => "closed",
+        }
+    }
+
+    /// Open and in-progress tickets still need support's attention; new
+    /// events for the same payment or subscription are folded into them.
+    pub fn is_active(&self) -> bool {
+        matches!(self, TicketStatus::Open | TicketStatus::InProgress)
+    }
+}
+
+/// A support ticket linked to the payment and/or subscription it is about.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Ticket {
+    pub id: String,
+    pub user_id: String,
+    pub source: TicketSource,
+    pub status: TicketStatus,
+    pub subject: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Merchant transaction id of the payment concerned.
+    #[serde(default)]
+    pub payment_id: Option<String>,
+    #[serde(default)]
+    pub subscription_id: Option<String>,
+    /// Support staff member handling the ticket.
+    #[serde(default)]
+    pub assignee: Option<String>,
+    /// How many billing events were folded into this ticket, the first included.
+    #[serde(default = "default_occurrences")]
+    pub occurrences: u32,
+    #[serde(default)]
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+fn default_occurrences() -> u32 {
+    1
+}
+
+/// A ticket to open, from a billing event or support.
+#[derive(Debug, Clone)]
+pub struct NewTicket {
+    pub user_id: String,
+    pub source: TicketSource,
+    pub subject: String,
+    pub description: Option<String>,
+    pub payment_id: Option<String>,
+    pub subscription_id: Option<String>,
+    pub assignee: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateTicketDto {
+    pub user_id: String,
+    pub subject: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub payment_id: Option<String>,
+    #[serde(default)]
+    pub subscription_id: Option<String>,
+    #[serde(default)]
+    pub assignee: Option<String>,
+}
+
+impl Validate for CreateTicketDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        errors.check("subject", validate_ticket_text("Subject", &self.subject));
+        if let Some(description) = self.description.as_deref().filter(|d| !d.trim().is_empty()) {
+            errors.check("description", validate_ticket_text("Description", description));
+        }
+        errors.into_result()
+    }
+}
+
+/// Fields left out are unchanged; an empty `assignee` unassigns the ticket.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct UpdateTicketDto {
+    #[serde(default)]
+    pub status: Option<TicketStatus>,
+    #[serde(default)]
+    pub assignee: Option<String>,
+}
+
+impl Validate for UpdateTicketDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        if self.status.is_none() && self.assignee.is_none() {
+            errors.add("status", "Provide status and/or assignee");
+        }
+        errors.into_result()
+    }
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct ReportChargeDto {
+    /// What the user says about the charge.
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// Trims `text` and rejects it when empty or over `MAX_TICKET_TEXT_LEN`.
+pub fn validate_ticket_text(field: &str, text: &str) -> Result<String, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(format!("{} must not be empty", field));
+    }
+    if text.chars().count() > MAX_TICKET_TEXT_LEN {
+        return Err(format!("{} must be at most {} characters", field, MAX_TICKET_TEXT_LEN));
+    }
+    Ok(text.to_string())
+}
+
+/// Which tickets an admin listing covers.
+#[derive(Debug, Clone, Default)]
+pub struct TicketFilter {
+    pub status: Option<TicketStatus>,
+    pub source: Option<TicketSource>,
+    pub assignee: Option<String>,
+    pub user_id: Option<String>,
+}
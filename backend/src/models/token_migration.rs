@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use crate::models::validation::{FieldErrors, Validate};
+
+/// A move of stored card tokens to a new gateway entity (or gateway). Every
+/// card that was active when the migration started is flagged with its id,
+/// taking over cards still pending in an earlier migration. A card counts as
+/// migrated once the user re-authorises through a card update, which replaces
+/// it with a token from the new entity. Flagged cards keep being charged
+/// until then, so renewals don't stop mid-migration.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TokenMigration {
+    pub id: String,
+    /// Where tokens are moving to, e.g. the new Peach entity id.
+    pub target: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct CreateTokenMigrationDto {
+    pub target: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl Validate for CreateTokenMigrationDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        if self.target.trim().is_empty() {
+            errors.add("target", "Migration target must not be empty");
+        }
+        errors.into_result()
+    }
+}
+
+/// Where a migration stands, for the admin report.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TokenMigrationProgress {
+    pub migration: TokenMigration,
+    /// Cards flagged when the migration started.
+    pub flagged: u64,
+    /// Replaced by a re-authorised card.
+    pub migrated: u64,
+    /// Still active on the old entity; their users have been prompted.
+    pub pending: u64,
+    /// Retired (declined or cancelled) before being re-authorised.
+    pub dropped: u64,
+    /// Share of flagged cards no longer waiting on their user, 0-100.
+    pub percent_complete: f64,
+}
+
+impl TokenMigrationProgress {
+    pub fn new(migration: TokenMigration, migrated: u64, pending: u64, dropped: u64) -> Self {
+        let flagged = migrated + pending + dropped;
+        let percent_complete = if flagged == 0 {
+            100.0
+        } else {
+            (((migrated + dropped) as f64 / flagged as f64) * 1000.0).round() / 10.0
+        };
+        Self { migration, flagged, migrated, pending, dropped, percent_complete }
+    }
+}
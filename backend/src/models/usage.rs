@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Duration, Utc};
+use crate::models::validation::{FieldErrors, Validate};
+
+/// How a metered plan charges for usage on top of its price. Copied onto
+/// subscriptions like the rest of the plan's terms.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct UsagePricing {
+    /// What is being counted, e.g. "API call"; shown on receipts.
+    pub unit: String,
+    /// Units per billing period covered by the plan price.
+    #[serde(default)]
+    pub included_units: u64,
+    /// VAT-inclusive price of each unit beyond `included_units`.
+    pub unit_price: f64,
+}
+
+impl UsagePricing {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.unit.trim().is_empty() {
+            return Err("Usage unit must not be empty".to_string());
+        }
+        if !self.unit_price.is_finite() || self.unit_price < 0.0 {
+            return Err("Usage unit price must not be negative".to_string());
+        }
+        Ok(())
+    }
+
+    /// Overage for `units` used in one billing period.
+    pub fn charge(&self, units: u64, period_start: DateTime<Utc>, period_end: DateTime<Utc>) -> UsageCharge {
+        let billable_units = units.saturating_sub(self.included_units);
+        UsageCharge {
+            unit: self.unit.clone(),
+            units,
+            included_units: self.included_units,
+            billable_units,
+            unit_price: self.unit_price,
+            amount: ((billable_units as f64 * self.unit_price) * 100.0).round() / 100.0,
+            period_start,
+            period_end,
+        }
+    }
+}
+
+/// Usage reported for a subscription. Records are only ever added; the
+/// renewal task sums those whose `recorded_at` falls in the period it bills.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UsageRecord {
+    pub id: String,
+    pub subscription_id: String,
+    pub user_id: String,
+    pub quantity: u64,
+    /// When the usage happened, which decides the period it is billed in.
+    pub recorded_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ReportUsageDto {
+    pub quantity: u64,
+    /// Defaults to now; may not be in the future.
+    #[serde(default)]
+    pub recorded_at: Option<DateTime<Utc>>,
+    /// Reporting again with the same key returns the first record instead
+    /// of counting the usage twice.
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+}
+
+/// Longest idempotency key accepted with a usage report.
+pub const MAX_USAGE_IDEMPOTENCY_KEY_LEN: usize = 128;
+
+/// Clock skew tolerated on a reported `recorded_at`.
+const USAGE_CLOCK_SKEW_MINUTES: i64 = 5;
+
+impl Validate for ReportUsageDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        if self.quantity == 0 {
+            errors.add("quantity", "Quantity must be greater than zero");
+        }
+        if let Some(key) = self.idempotency_key.as_deref().map(str::trim) {
+            if key.is_empty() || key.len() > MAX_USAGE_IDEMPOTENCY_KEY_LEN {
+                errors.add("idempotency_key", format!("Idempotency key must be 1-{} characters", MAX_USAGE_IDEMPOTENCY_KEY_LEN));
+            }
+        }
+        if self.recorded_at.is_some_and(|at| at > Utc::now() + Duration::minutes(USAGE_CLOCK_SKEW_MINUTES)) {
+            errors.add("recorded_at", "Usage cannot be recorded in the future");
+        }
+        errors.into_result()
+    }
+}
+
+/// Breakdown of the usage billed with a renewal, stored on its payment.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct UsageCharge {
+    pub unit: String,
+    pub units: u64,
+    pub included_units: u64,
+    pub billable_units: u64,
+    pub unit_price: f64,
+    /// `billable_units * unit_price`, rounded to cents.
+    pub amount: f64,
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+}
@@ -1,23 +1,105 @@
+use std::collections::BTreeMap;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use chrono::{DateTime, Utc};
+use crate::models::merchant::{validate_address, validate_vat_number};
+use crate::models::validation::{FieldErrors, Validate};
+use crate::services::formatting::Locale;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct User {
     pub id: String,
     pub email: String,
     pub name: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Printed on tax invoices.
+    #[serde(default)]
+    pub billing_address: Option<String>,
+    /// Set when the user invoices as a VAT vendor.
+    #[serde(default)]
+    pub vat_number: Option<String>,
+    /// Language for emails, notifications, invoices and error messages, e.g.
+    /// `af-ZA`. Requests fall back to Accept-Language when it's not set.
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// Set when the account was deleted and its personal data erased. The
+    /// row is kept so payments and invoices still resolve.
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Merchant the record belongs to; `None` in single-merchant
+    /// deployments and for records from before tenants existed.
+    #[serde(default)]
+    pub tenant_id: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// Everything stored about a user, returned by the data export endpoint.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserDataExport {
+    pub exported_at: DateTime<Utc>,
+    pub user: User,
+    /// Rows referencing the user, keyed by table. Card tokens, checkout URLs
+    /// and signing secrets are left out.
+    #[schema(value_type = Object)]
+    pub records: BTreeMap<String, Vec<serde_json::Value>>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateUserDto {
     pub email: String,
     pub name: String,
 }
 
-#[derive(Debug, Deserialize)]
+/// Replaces the invoicing details; omitted fields are cleared.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct UpdateBillingDetailsDto {
+    #[serde(default)]
+    pub billing_address: Option<String>,
+    #[serde(default)]
+    pub vat_number: Option<String>,
+}
+
+impl Validate for UpdateBillingDetailsDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        if let Some(address) = &self.billing_address {
+            errors.check("billing_address", validate_address(address));
+        }
+        if let Some(vat_number) = &self.vat_number {
+            errors.check("vat_number", validate_vat_number(vat_number));
+        }
+        errors.into_result()
+    }
+}
+
+/// Profile changes; omitted fields are left as they are.
+#[derive(Debug, Default, Deserialize, ToSchema)]
 pub struct UpdateUserDto {
+    #[serde(default)]
     pub name: Option<String>,
+    #[serde(default)]
     pub email: Option<String>,
+    /// `en-ZA`, `en-GB`, `en-US` or `af-ZA`.
+    #[serde(default)]
+    pub locale: Option<String>,
+}
+
+impl Validate for UpdateUserDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        if self.name.as_ref().is_some_and(|n| n.trim().is_empty()) {
+            errors.add("name", "Name can't be empty");
+        }
+        if self.email.as_ref().map(|e| e.trim()).is_some_and(|e| e.is_empty() || !e.contains('@')) {
+            errors.add("email", "A valid email address is required");
+        }
+        if let Some(tag) = self.locale.as_deref().map(str::trim) {
+            if Locale::from_tag(tag).is_none() {
+                errors.add("locale", format!("Unsupported locale {}; use en-ZA, en-GB, en-US or af-ZA", tag));
+            }
+        }
+        errors.into_result()
+    }
 }
@@ -0,0 +1,55 @@
+use std::collections::BTreeMap;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// What's wrong with a request body, by field. Nested fields are dotted,
+/// e.g. `peach.client_id`.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct FieldErrors(BTreeMap<String, Vec<String>>);
+
+impl FieldErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.0.entry(field.to_string()).or_default().push(message.into());
+    }
+
+    /// Records the error of one of the field validators that return
+    /// `Result<_, String>`.
+    pub fn check<T>(&mut self, field: &str, result: Result<T, String>) {
+        if let Err(message) = result {
+            self.add(field, message);
+        }
+    }
+
+    /// Records the errors of a nested body under `prefix`.
+    pub fn nested(&mut self, prefix: &str, result: Result<(), FieldErrors>) {
+        if let Err(errors) = result {
+            for (field, messages) in errors.0 {
+                self.0.entry(format!("{}.{}", prefix, field)).or_default().extend(messages);
+            }
+        }
+    }
+
+    pub fn into_result(self) -> Result<(), FieldErrors> {
+        if self.0.is_empty() { Ok(()) } else { Err(self) }
+    }
+}
+
+/// Checks a request body can be acted on, before the handler runs (see
+/// `ValidatedJson`). Only what the body alone decides belongs here; checks
+/// against stored records or configuration stay in the handler.
+pub trait Validate {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        Ok(())
+    }
+}
+
+/// The 422 body for a request that failed `Validate`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ValidationErrorResponse {
+    pub error: String,
+    pub fields: FieldErrors,
+}
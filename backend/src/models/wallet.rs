@@ -0,0 +1,72 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+
+/// Gateway name stored on payments settled entirely from wallet credit.
+pub const WALLET_GATEWAY: &str = "wallet";
+
+/// Why a wallet balance moved.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+pub enum WalletEntrySource {
+    /// Credit for the unused part of a plan, from a downgrade's credit note.
+    Proration,
+    /// A refund paid into the wallet instead of back to the card.
+    Refund,
+    /// Credit spent on a checkout.
+    Payment,
+    /// Credit spent on an automatic renewal.
+    Renewal,
+    /// Credit returned after the payment or renewal it was spent on failed.
+    Reversal,
+}
+
+impl WalletEntrySource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WalletEntrySource::Proration => "proration",
+            WalletEntrySource::Refund => "refund",
+            WalletEntrySource::Payment => "payment",
+            WalletEntrySource::Renewal => "renewal",
+            WalletEntrySource::Reversal => "reversal",
+        }
+    }
+}
+
+/// One movement on a user's wallet. Credits are positive, debits negative.
+/// Entries are keyed by source and reference, so the same credit note,
+/// refund or payment never moves the balance twice.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WalletEntry {
+    pub id: String,
+    pub user_id: String,
+    pub currency: String,
+    pub amount: f64,
+    pub balance_after: f64,
+    pub source: WalletEntrySource,
+    /// Credit note id, refund transaction id or merchant transaction id.
+    pub reference: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl WalletEntry {
+    pub fn key(source: WalletEntrySource, reference: &str) -> String {
+        format!("{}_{}", source.as_str(), reference)
+    }
+}
+
+/// A user's credit in one currency.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WalletBalance {
+    pub user_id: String,
+    pub currency: String,
+    pub balance: f64,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Balances and ledger shown on `GET /users/{id}/wallet`, newest entries first.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Wallet {
+    pub user_id: String,
+    pub balances: Vec<WalletBalance>,
+    pub entries: Vec<WalletEntry>,
+}
@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+use crate::models::domain_event::EVENT_TYPES;
+use crate::models::validation::{FieldErrors, Validate};
+
+/// Most webhook endpoints one user may register.
+pub const MAX_WEBHOOK_ENDPOINTS_PER_USER: usize = 10;
+
+/// Attempts a delivery gets before it is left failed.
+pub const MAX_DELIVERY_ATTEMPTS: u32 = 8;
+
+/// How long a worker holds a delivery it is sending before another may
+/// pick it up.
+pub const DELIVERY_LEASE_SECONDS: i64 = 60;
+
+/// A URL registered by an API consumer to receive the domain events about
+/// their own payments and subscriptions.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WebhookEndpoint {
+    pub id: String,
+    pub user_id: String,
+    pub url: String,
+    /// Signing key; only returned when the endpoint is created.
+    #[serde(skip_serializing, default)]
+    pub secret: String,
+    /// Event types sent to the endpoint; empty means all of them.
+    #[serde(default)]
+    pub event_types: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl WebhookEndpoint {
+    pub fn wants(&self, event_type: &str) -> bool {
+        self.event_types.is_empty() || self.event_types.iter().any(|t| t == event_type)
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateWebhookEndpointDto {
+    pub url: String,
+    /// e.g. `payment.completed`; omit for every event type.
+    #[serde(default)]
+    pub event_types: Vec<String>,
+}
+
+impl Validate for CreateWebhookEndpointDto {
+    fn validate(&self) -> Result<(), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        match reqwest::Url::parse(self.url.trim()) {
+            Ok(url) if url.host_str().is_none() => errors.add("url", format!("{} has no host", self.url)),
+            Ok(_) => {}
+            Err(_) => errors.add("url", format!("{} is not a valid URL", self.url)),
+        }
+        if let Some(unknown) = self.event_types.iter().find(|t| !EVENT_TYPES.contains(&t.as_str())) {
+            errors.add("event_types", format!("Unknown event type {}; expected one of {}", unknown, EVENT_TYPES.join(", ")));
+        }
+        errors.into_result()
+    }
+}
+
+impl CreateWebhookEndpointDto {
+    /// Endpoints must be https, except in sandbox mode where plain http is
+    /// allowed too.
+    pub fn check_scheme(&self, allow_http: bool) -> Result<(), String> {
+        match reqwest::Url::parse(self.url.trim()).as_ref().map(reqwest::Url::scheme) {
+            Ok("https") => Ok(()),
+            Ok("http") if allow_http => Ok(()),
+            _ => Err("Webhook URLs must use https".to_string()),
+        }
+    }
+}
+
+/// A newly registered endpoint with its signing secret, which isn't shown again.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookEndpointCreated {
+    #[serde(flatten)]
+    pub endpoint: WebhookEndpoint,
+    /// Key for checking `X-Event-Signature` on deliveries.
+    pub secret: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookDeliveryStatus {
+    /// Waiting for `next_attempt_at`, including between retries.
+    Pending,
+    Succeeded,
+    /// Out of attempts, or the endpoint was removed.
+    Failed,
+}
+
+/// One event sent (or being sent) to one endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub endpoint_id: String,
+    pub event_id: String,
+    pub event_type: String,
+    pub status: WebhookDeliveryStatus,
+    /// Attempts started so far.
+    #[serde(default)]
+    pub attempts: u32,
+    pub next_attempt_at: DateTime<Utc>,
+    /// HTTP status of the last response, if there was one.
+    #[serde(default)]
+    pub response_status: Option<u16>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub enum WebhookOutcome {
+    Received,
+    Processed,
+    Ignored,
+    Failed,
+    Rejected,
+    /// Arrived after a newer event had already been applied.
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WebhookEvent {
+    pub id: String,
+    pub raw_body: String,
+    /// Gateway whose endpoint received the event; `None` means the main gateway.
+    #[serde(default)]
+    pub gateway: Option<String>,
+    pub signature: Option<String>,
+    pub parsed: Option<serde_json::Value>,
+    pub result_code: Option<String>,
+    pub merchant_transaction_id: Option<String>,
+    pub outcome: WebhookOutcome,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub event_timestamp: Option<DateTime<Utc>>,
+    pub replay_count: u32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Fields filled in once a webhook has been parsed and processed.
+#[derive(Debug, Default)]
+pub struct WebhookEventUpdate {
+    pub signature: Option<String>,
+    pub parsed: Option<serde_json::Value>,
+    pub result_code: Option<String>,
+    pub merchant_transaction_id: Option<String>,
+    pub event_timestamp: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+}
+
+/// How a Peach result code is handled; see `peach::result_code_category`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+pub enum ResultCodeCategory {
+    Success,
+    Pending,
+    Cancelled,
+    /// A documented rejection, e.g. a declined card.
+    Declined,
+    /// Outside every group we know; treated as a failure, but worth a look.
+    Unhandled,
+}
+
+/// How often one result code has been seen in verified webhooks.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ResultCodeStat {
+    pub code: String,
+    pub category: ResultCodeCategory,
+    pub count: u64,
+    pub first_seen_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+/// Webhooks seen per category since counting began.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct ResultCodeCounts {
+    pub success: u64,
+    pub pending: u64,
+    pub cancelled: u64,
+    pub declined: u64,
+    pub unhandled: u64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct WebhookCodeMetrics {
+    pub counts: ResultCodeCounts,
+    /// Distinct codes we don't handle.
+    pub unhandled_codes: u64,
+    /// Unhandled codes, most recently seen first.
+    pub recent_unhandled: Vec<ResultCodeStat>,
+}
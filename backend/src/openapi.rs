@@ -0,0 +1,341 @@
+//! OpenAPI description of the HTTP API, served at `/api/v1/openapi.json`
+//! with a Swagger UI at `/api/v1/docs/`.
+
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use crate::handlers;
+use crate::handlers::me::{ActivityItem, ActivityFeedResponse};
+use crate::handlers::notification::{
+    AcknowledgeAllResponse, NotificationResponse, TestNotificationRequest, UnreadCountResponse,
+};
+use crate::handlers::payment::{
+    ApiResponseError, RecurringChargeRequest, PreflightError, PreflightCharge, PreflightResult,
+    CheckoutFeatures, CheckoutConfigResponse, SubscriptionBalance,
+};
+use crate::handlers::plan_change::{PlanChangePreview, PlanChangeResponse};
+use crate::handlers::subscription::{
+    CreateSubscriptionRequest, SubscriptionResponse, SubscriptionDisplay, UpcomingInvoice, UpcomingInvoiceDisplay,
+};
+use crate::handlers::support::TimelineItem;
+use crate::handlers::user::{RegisterUserRequest, UserResponse, ErrorResponse};
+use crate::models::activity::ActivityCategory;
+use crate::models::attachment::{AttachmentOwner, Attachment};
+use crate::models::billing_run::{BillingRunDto, BillingRunFailure, BillingRunReport};
+use crate::models::bulk_operation::{
+    BulkAction, BulkFilter, CreateBulkOperationDto, BulkOperationStatus, BulkFailure, BulkOperation,
+};
+use crate::models::audit::{AuditActorType, AuditEntry};
+use crate::models::api_key::{ApiKeyCreated, ApiKeyScope, CreateApiKeyDto};
+use crate::models::impersonation::{ImpersonationScope, ImpersonationSession, ImpersonationStarted, StartImpersonationDto};
+use crate::models::tenant::{CreateTenantDto, PeachCredentials, Tenant, UpdateTenantDto};
+use crate::models::validation::{FieldErrors, ValidationErrorResponse};
+use crate::models::common::{PaginatedAuditEntries, PaginatedNotifications, PaginatedPayments};
+use crate::models::card_update::{CardUpdateStatus, CardUpdate, CardUpdateRequest};
+use crate::models::domain_event::{DomainEventStatus, DomainEvent};
+use crate::models::entitlement::{
+    PlanFeature, SetPlanFeatureDto, FeatureEntitlement, UserEntitlements,
+};
+use crate::models::export::{ExportStatus, ExportFile, ExportManifest, ExportRun};
+use crate::models::invoice::{InvoiceLineItem, Invoice, CreditNote};
+use crate::models::job::{JobPayload, JobStatus, Job};
+use crate::models::membership::{SubscriptionMember, InviteMemberDto, UpdateSeatsDto};
+use crate::models::merchant::{MerchantDetails, UpdateMerchantDetailsDto};
+use crate::models::metrics::{
+    MetricsBucket, MrrPoint, MrrSeries, SubscriptionMetricsPoint, SubscriptionMetricsSeries,
+    PaymentMetricsPoint, PaymentMetricsSeries,
+};
+use crate::models::notification::{
+    EventPreference, NotificationAction, NotificationChannel, NotificationEvent, NotificationPreferences,
+    UpdateNotificationPreferencesDto,
+};
+use crate::models::installment::{InstallmentStatus, Installment, InstallmentSchedule};
+use crate::models::payment::{
+    PaymentStatus, PaymentMethod, Payment, CreatePaymentDto, ManualPaymentDto, PaymentSort,
+};
+use crate::models::payment_method_rule::{PaymentMethodRule, SetPaymentMethodRuleDto, MethodAvailability};
+use crate::models::plan::{BillingInterval, Plan, CreatePlanDto, UpdatePlanDto, PlanCatalog};
+use crate::models::plan_change::{ProrationCalculation, PlanChangeStatus, PlanChange, ChangeAt, ChangePlanDto};
+use crate::models::recurring_payment::CardExpiry;
+use crate::models::reconciliation::{ReconciliationSource, MismatchKind, ReconciliationMismatch, ReconciliationReport};
+use crate::models::refund::{RefundStatus, Refund, CreateRefundDto};
+use crate::models::report::DailySummary;
+use crate::models::scenario::{
+    ScenarioDto, ScenarioStep, SimulatedResult, ScenarioAction, ScenarioStepResult, ScenarioReport,
+};
+use crate::models::subscription::{
+    Subscription, SubscriptionStatus, SuspensionPolicy, CancelAt, CancelSubscriptionDto,
+    SkipRenewalDto, ReactivateSubscriptionDto, RestartSubscriptionDto, AccessLevel,
+};
+use crate::models::support::{NoteTarget, SupportNote, CreateNoteDto, UpdateNoteDto, SetTagsDto};
+use crate::models::ticket::{
+    TicketSource, TicketStatus, Ticket, CreateTicketDto, UpdateTicketDto, ReportChargeDto,
+};
+use crate::models::token_migration::{
+    TokenMigration, CreateTokenMigrationDto, TokenMigrationProgress,
+};
+use crate::models::template::{MessageTemplate, SetMessageTemplateDto, TemplateChannel, TemplateSummary};
+use crate::models::usage::{UsagePricing, UsageRecord, ReportUsageDto, UsageCharge};
+use crate::models::user::{User, UpdateBillingDetailsDto, UpdateUserDto, UserDataExport};
+use crate::models::wallet::{WalletEntrySource, WalletEntry, WalletBalance, Wallet};
+use crate::models::webhook_endpoint::{
+    WebhookEndpoint, CreateWebhookEndpointDto, WebhookEndpointCreated, WebhookDeliveryStatus, WebhookDelivery,
+};
+use crate::models::webhook_event::{
+    WebhookOutcome, WebhookEvent, ResultCodeCategory, ResultCodeStat, ResultCodeCounts, WebhookCodeMetrics,
+};
+use crate::services::consistency::{ConsistencyIssue, ConsistencyFinding};
+use crate::services::email::SentEmail;
+use crate::services::gateway::{ChargeStatus, GatewayTransaction};
+use crate::services::health::{ProbeStatus, DependencyHealth, ReadinessReport};
+use crate::services::qr::QrFormat;
+use crate::tasks::renewal_task::RenewalOutcome;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "PWA Payment API", description = "Payments, subscriptions and billing for the PWA."),
+    paths(
+        handlers::attachment::upload_attachment,
+        handlers::attachment::get_attachment,
+        handlers::attachment::list_payment_attachments,
+        handlers::attachment::download_attachment,
+        handlers::billing_run::run_billing_smoke_test,
+        handlers::scenario::run_scenario,
+        handlers::bulk::preview_bulk_operation,
+        handlers::bulk::confirm_bulk_operation,
+        handlers::bulk::get_bulk_operation,
+        handlers::card_update::start_card_update,
+        handlers::card_update::get_card_update,
+        handlers::consistency::get_schema_report,
+        handlers::consistency::get_consistency_report,
+        handlers::consistency::recompute_subscription_dates,
+        handlers::entitlement::get_user_features,
+        handlers::entitlement::check_user_feature,
+        handlers::entitlement::admin_list_plan_features,
+        handlers::entitlement::admin_set_plan_feature,
+        handlers::entitlement::admin_delete_plan_feature,
+        handlers::payment_method::admin_list_payment_method_rules,
+        handlers::payment_method::admin_set_payment_method_rule,
+        handlers::payment_method::admin_delete_payment_method_rule,
+        handlers::invoice::get_invoice,
+        handlers::invoice::get_invoice_pdf,
+        handlers::manual_payment::record_manual_payment,
+        handlers::me::get_my_activity,
+        handlers::me::skip_next_renewal,
+        handlers::me::cancel_skip_next_renewal,
+        handlers::me::reactivate_after_suspension,
+        handlers::membership::invite_member,
+        handlers::membership::list_members,
+        handlers::membership::remove_member,
+        handlers::membership::update_seats,
+        handlers::notification::get_notifications,
+        handlers::notification::mark_notification_read,
+        handlers::notification::create_test_notification,
+        handlers::notification::get_notification_preferences,
+        handlers::notification::update_notification_preferences,
+        handlers::notification::get_unread_count,
+        handlers::notification::acknowledge_all_notifications,
+        handlers::outbox::list_sent_emails,
+        handlers::outbox::clear_sent_emails,
+        handlers::payment::get_checkout_config,
+        handlers::payment_method::list_payment_methods,
+        handlers::payment::validate_payment,
+        handlers::payment::initiate_payment,
+        handlers::payment::charge_recurring_payment,
+        handlers::payment::check_payment_status,
+        handlers::payment::wait_for_payment,
+        handlers::payment::get_payment_qr,
+        handlers::payment::handle_payment_callback_get,
+        handlers::payment::payment_callback,
+        handlers::payment::ozow_notify,
+        handlers::payment_history::get_payment_history,
+        handlers::plan::get_plans,
+        handlers::plan::admin_list_plans,
+        handlers::plan::admin_create_plan,
+        handlers::plan::admin_update_plan,
+        handlers::plan::admin_delete_plan,
+        handlers::plan_change::preview_plan_change,
+        handlers::plan_change::change_plan,
+        handlers::plan_change::get_scheduled_plan_change,
+        handlers::plan_change::cancel_scheduled_plan_change,
+        handlers::installment::get_installments,
+        handlers::refund::refund_payment,
+        handlers::refund::get_payment_refunds,
+        handlers::capture::capture_payment,
+        handlers::capture::void_payment,
+        handlers::report::get_daily_report,
+        handlers::report::list_export_runs,
+        handlers::reconciliation::upload_settlement_file,
+        handlers::reconciliation::list_reconciliation_reports,
+        handlers::reconciliation::get_reconciliation_report,
+        handlers::metrics::get_mrr_metrics,
+        handlers::metrics::get_subscription_metrics,
+        handlers::metrics::get_payment_metrics,
+        handlers::subscription::create_subscription,
+        handlers::subscription::get_subscription,
+        handlers::subscription::get_upcoming_invoices,
+        handlers::subscription::renew_subscription,
+        handlers::subscription::cancel_subscription,
+        handlers::subscription::reactivate_subscription,
+        handlers::subscription::restart_subscription,
+        handlers::subscription::pause_subscription,
+        handlers::subscription::resume_subscription,
+        handlers::support::get_user_notes,
+        handlers::support::add_user_note,
+        handlers::support::get_subscription_notes,
+        handlers::support::add_subscription_note,
+        handlers::support::update_note,
+        handlers::support::delete_note,
+        handlers::support::set_user_tags,
+        handlers::support::set_subscription_tags,
+        handlers::support::search_support,
+        handlers::support::get_user_timeline,
+        handlers::ticket::list_tickets,
+        handlers::ticket::get_ticket,
+        handlers::ticket::create_ticket,
+        handlers::ticket::update_ticket,
+        handlers::ticket::report_unrecognized_charge,
+        handlers::merchant_webhook::create_webhook_endpoint,
+        handlers::merchant_webhook::list_webhook_endpoints,
+        handlers::merchant_webhook::delete_webhook_endpoint,
+        handlers::merchant_webhook::list_webhook_deliveries,
+        handlers::job::list_jobs,
+        handlers::job::get_job,
+        handlers::job::retry_job,
+        handlers::domain_event::list_domain_events,
+        handlers::domain_event::get_domain_event,
+        handlers::domain_event::redeliver_domain_event,
+        handlers::invoice::update_billing_details,
+        handlers::invoice::get_merchant_details,
+        handlers::invoice::update_merchant_details,
+        handlers::template::list_templates,
+        handlers::template::set_template,
+        handlers::template::delete_template,
+        handlers::audit::list_audit_log,
+        handlers::impersonation::start_impersonation,
+        handlers::impersonation::end_impersonation,
+        handlers::api_key::get_current_api_key,
+        handlers::api_key::list_api_keys,
+        handlers::api_key::create_api_key,
+        handlers::api_key::rotate_api_key,
+        handlers::api_key::revoke_api_key,
+        handlers::tenant::list_tenants,
+        handlers::tenant::create_tenant,
+        handlers::tenant::get_tenant,
+        handlers::tenant::update_tenant,
+        handlers::token_migration::start_token_migration,
+        handlers::token_migration::list_token_migrations,
+        handlers::token_migration::get_token_migration,
+        handlers::usage::report_usage,
+        handlers::user::register_user,
+        handlers::user::get_user_by_email,
+        handlers::user::get_user,
+        handlers::user::update_user,
+        handlers::user::delete_user,
+        handlers::user::export_user_data,
+        handlers::wallet::get_wallet,
+        handlers::webhook::list_webhook_events,
+        handlers::webhook::get_result_code_metrics,
+        handlers::webhook::replay_webhook_event,
+        handlers::health::liveness,
+        handlers::health::readiness,
+    ),
+    components(schemas(
+        ActivityItem, ActivityFeedResponse, NotificationResponse, PaginatedNotifications, UnreadCountResponse,
+        AcknowledgeAllResponse, TestNotificationRequest,
+        ApiResponseError, RecurringChargeRequest, PreflightError, PreflightCharge,
+        PreflightResult, CheckoutFeatures, CheckoutConfigResponse, SubscriptionBalance, PlanChangePreview, PlanChangeResponse, CreateSubscriptionRequest,
+        SubscriptionResponse, SubscriptionDisplay, UpcomingInvoice, UpcomingInvoiceDisplay, TimelineItem, RegisterUserRequest,
+        UserResponse, ErrorResponse, ActivityCategory, AttachmentOwner, Attachment,
+        BillingRunDto, BillingRunFailure, BillingRunReport, BulkAction, BulkFilter,
+        CreateBulkOperationDto, BulkOperationStatus, BulkFailure, BulkOperation,
+        CardUpdateStatus, CardUpdate,
+        CardUpdateRequest, PlanFeature, SetPlanFeatureDto, FeatureEntitlement, UserEntitlements,
+        PaymentMethodRule, SetPaymentMethodRuleDto, MethodAvailability,
+        DomainEventStatus, DomainEvent, ExportStatus, ExportFile, ExportManifest, ExportRun, ReconciliationSource, MismatchKind, ReconciliationMismatch, ReconciliationReport, InvoiceLineItem, Invoice, CreditNote, JobPayload, JobStatus, Job, MerchantDetails, UpdateMerchantDetailsDto,
+        TemplateChannel, MessageTemplate, SetMessageTemplateDto, TemplateSummary, AuditActorType, AuditEntry, PaginatedAuditEntries, ImpersonationScope, ImpersonationSession, ImpersonationStarted, StartImpersonationDto,
+        crate::models::api_key::ApiKey, ApiKeyScope, CreateApiKeyDto, ApiKeyCreated, PeachCredentials, Tenant, CreateTenantDto, UpdateTenantDto,
+        FieldErrors, ValidationErrorResponse,
+        SubscriptionMember, InviteMemberDto,
+        UpdateSeatsDto, NotificationAction, NotificationEvent, NotificationChannel, EventPreference,
+        NotificationPreferences, UpdateNotificationPreferencesDto, PaymentStatus, PaymentMethod, Payment,
+        CreatePaymentDto, ManualPaymentDto, PaymentSort, PaginatedPayments, BillingInterval, Plan, CreatePlanDto, UpdatePlanDto,
+        PlanCatalog, ProrationCalculation, PlanChangeStatus, PlanChange, ChangeAt, ChangePlanDto,
+        InstallmentStatus, Installment, InstallmentSchedule,
+        RefundStatus, Refund, CreateRefundDto, DailySummary, ScenarioDto, ScenarioStep, SimulatedResult,
+        ScenarioAction, ScenarioStepResult, ScenarioReport, MetricsBucket, MrrPoint, MrrSeries,
+        SubscriptionMetricsPoint, SubscriptionMetricsSeries, PaymentMetricsPoint, PaymentMetricsSeries,
+        Subscription, SubscriptionStatus,
+        SuspensionPolicy, CancelAt, CancelSubscriptionDto, SkipRenewalDto, ReactivateSubscriptionDto, RestartSubscriptionDto,
+        AccessLevel,
+        NoteTarget, SupportNote, CreateNoteDto, UpdateNoteDto, SetTagsDto, TicketSource,
+        TicketStatus, Ticket, CreateTicketDto, UpdateTicketDto, ReportChargeDto, TokenMigration,
+        CreateTokenMigrationDto, TokenMigrationProgress, UsagePricing, UsageRecord,
+        ReportUsageDto, UsageCharge, User, UpdateBillingDetailsDto, UpdateUserDto, UserDataExport, WalletEntrySource, WalletEntry, WalletBalance, Wallet,
+        WebhookEndpoint, CreateWebhookEndpointDto, WebhookEndpointCreated, WebhookDeliveryStatus, WebhookDelivery,
+        WebhookOutcome, WebhookEvent, ResultCodeCategory, ResultCodeStat, ResultCodeCounts,
+        WebhookCodeMetrics, ConsistencyIssue, ConsistencyFinding, SentEmail,
+        ChargeStatus, GatewayTransaction, CardExpiry, QrFormat, RenewalOutcome,
+        ProbeStatus, DependencyHealth, ReadinessReport,
+    )),
+    modifiers(&SecurityAddon),
+    tags(
+        (name = "users", description = "Registration and lookup"),
+        (name = "payments", description = "Checkouts, gateway callbacks, card updates, refunds and captures"),
+        (name = "subscriptions", description = "Subscription lifecycle, plan changes, usage and seats"),
+        (name = "invoices", description = "Tax invoices"),
+        (name = "me", description = "The caller's own activity and subscription"),
+        (name = "plans", description = "Public plan catalog"),
+        (name = "entitlements", description = "Feature access by plan"),
+        (name = "attachments", description = "Signed attachment downloads"),
+        (name = "webhooks", description = "Stored webhook deliveries"),
+        (name = "api-keys", description = "Server-to-server credentials"),
+        (name = "admin", description = "Operator endpoints"),
+        (name = "notifications", description = "In-app notifications and delivery preferences"),
+        (name = "health", description = "Liveness and readiness probes"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Registers the headers the extractors and signing middleware check.
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "admin_token",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                "X-Admin-Token",
+                "Shared operator token (`ADMIN_API_TOKEN`)",
+            ))),
+        );
+        components.add_security_scheme(
+            "user_id",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                "X-User-Id",
+                "Id of the calling user",
+            ))),
+        );
+        components.add_security_scheme(
+            "impersonation_token",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                "X-Impersonation-Token",
+                "Support acting as a user, in place of `X-User-Id` (see `POST /admin/impersonate/{user_id}`)",
+            ))),
+        );
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                "X-Api-Key",
+                "Key for a merchant backend, limited by its scopes (see `POST /admin/api-keys`)",
+            ))),
+        );
+        components.add_security_scheme(
+            "request_signature",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                "X-Signature",
+                "HMAC of the request, sent together with `X-Key-Id` and `X-Timestamp`",
+            ))),
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
+use async_trait::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use uuid::Uuid;
+use tracing::warn;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Where attachment bytes are kept. Metadata lives in the database; stores
+/// only map opaque keys to contents.
+#[async_trait]
+pub trait AttachmentStore: Send + Sync {
+    async fn put(&self, key: &str, bytes: &[u8]) -> Result<(), String>;
+    async fn get(&self, key: &str) -> Result<Vec<u8>, String>;
+}
+
+/// Files under a local directory, one per key.
+pub struct LocalAttachmentStore {
+    root: PathBuf,
+}
+
+impl LocalAttachmentStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, String> {
+        // Keys are generated by us, but never let one escape the root
+        if key.is_empty() || key.starts_with('.') || !key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+            return Err(format!("Invalid attachment key: {}", key));
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl AttachmentStore for LocalAttachmentStore {
+    async fn put(&self, key: &str, bytes: &[u8]) -> Result<(), String> {
+        let path = self.path(key)?;
+        tokio::fs::create_dir_all(&self.root)
+            .await
+            .map_err(|e| format!("Failed to create attachment directory: {}", e))?;
+        tokio::fs::write(&path, bytes)
+            .await
+            .map_err(|e| format!("Failed to write attachment {}: {}", key, e))
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, String> {
+        let path = self.path(key)?;
+        tokio::fs::read(&path)
+            .await
+            .map_err(|e| format!("Failed to read attachment {}: {}", key, e))
+    }
+}
+
+/// Attachment storage plus signing of the time-limited download links handed
+/// to admins and users, so downloads need no other credentials.
+pub struct AttachmentService {
+    store: Arc<dyn AttachmentStore>,
+    url_secret: Vec<u8>,
+    url_ttl_secs: i64,
+    public_base_url: String,
+}
+
+impl AttachmentService {
+    /// Reads `ATTACHMENTS_DIR` (default `./data/attachments`),
+    /// `ATTACHMENT_URL_SECRET`, `ATTACHMENT_URL_TTL_SECS` (default 900) and
+    /// `PUBLIC_API_BASE_URL` for the links. Without a secret, links are signed
+    /// with a per-process key and stop working on restart.
+    pub fn from_env() -> Self {
+        let root = env::var("ATTACHMENTS_DIR").unwrap_or_else(|_| "./data/attachments".to_string());
+        let url_secret = match env::var("ATTACHMENT_URL_SECRET") {
+            Ok(secret) if !secret.trim().is_empty() => secret.into_bytes(),
+            _ => {
+                warn!("ATTACHMENT_URL_SECRET not set; download links will not survive a restart");
+                Uuid::new_v4().as_bytes().to_vec()
+            }
+        };
+
+        Self {
+            store: Arc::new(LocalAttachmentStore::new(PathBuf::from(root))),
+            url_secret,
+            url_ttl_secs: env::var("ATTACHMENT_URL_TTL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(900),
+            public_base_url: env::var("PUBLIC_API_BASE_URL")
+                .unwrap_or_else(|_| "http://127.0.0.1:8080/api/v1".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+        }
+    }
+
+    pub fn store(&self) -> &dyn AttachmentStore {
+        self.store.as_ref()
+    }
+
+    fn signature(&self, attachment_id: &str, expires: i64) -> String {
+        let mut mac = HmacSha256::new_from_slice(&self.url_secret).expect("HMAC accepts any key length");
+        mac.update(format!("{}\n{}", attachment_id, expires).as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    /// A download link valid for the configured TTL.
+    pub fn download_url(&self, attachment_id: &str) -> String {
+        let expires = Utc::now().timestamp() + self.url_ttl_secs;
+        format!(
+            "{}/attachments/{}/download?expires={}&signature={}",
+            self.public_base_url,
+            attachment_id,
+            expires,
+            self.signature(attachment_id, expires)
+        )
+    }
+
+    pub fn verify_download(&self, attachment_id: &str, expires: i64, signature: &str) -> Result<(), String> {
+        if expires < Utc::now().timestamp() {
+            return Err("Download link has expired".to_string());
+        }
+        let provided = hex::decode(signature.trim()).map_err(|_| "Signature must be hex")?;
+        let mut mac = HmacSha256::new_from_slice(&self.url_secret).map_err(|_| "Invalid signing key")?;
+        mac.update(format!("{}\n{}", attachment_id, expires).as_bytes());
+        mac.verify_slice(&provided).map_err(|_| "Invalid download signature".to_string())
+    }
+}
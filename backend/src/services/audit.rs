@@ -0,0 +1,80 @@
+use std::future::Future;
+use actix_web::{HttpMessage, HttpRequest};
+use crate::models::api_key::ApiKey;
+use crate::models::audit::AuditActorType;
+use crate::models::impersonation::ImpersonationSession;
+
+tokio::task_local! {
+    static AUDIT_CONTEXT: AuditContext;
+}
+
+/// Longest User-Agent kept in an audit entry.
+const MAX_USER_AGENT_LEN: usize = 256;
+
+/// Who is making changes and from where, set for each request by the
+/// `audit_context` middleware.
+#[derive(Debug, Clone)]
+pub struct AuditContext {
+    pub actor_type: AuditActorType,
+    pub actor_id: Option<String>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    /// Set while support staff act as a user.
+    pub impersonation_session_id: Option<String>,
+}
+
+impl AuditContext {
+    /// Admin when `X-Admin-Token` is sent or an impersonation session is
+    /// active, the key for a verified `X-Api-Key`, else the `X-User-Id` user,
+    /// else anonymous. Credentials are checked by the extractors, so a
+    /// request that claims to be someone it isn't is refused before it
+    /// changes anything.
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+
+        let impersonation_session_id = req.extensions().get::<ImpersonationSession>().map(|session| session.id.clone());
+        let api_key_id = req.extensions().get::<ApiKey>().map(|key| key.id.clone());
+        let (actor_type, actor_id) = match (header("X-Admin-Token"), header("X-User-Id")) {
+            _ if impersonation_session_id.is_some() => (AuditActorType::Admin, None),
+            _ if api_key_id.is_some() => (AuditActorType::ApiKey, api_key_id),
+            (Some(_), _) => (AuditActorType::Admin, None),
+            (None, Some(user_id)) => (AuditActorType::User, Some(user_id.to_string())),
+            (None, None) => (AuditActorType::Anonymous, None),
+        };
+
+        Self {
+            actor_type,
+            actor_id,
+            ip_address: req.connection_info().realip_remote_addr().map(str::to_string),
+            user_agent: header("User-Agent").map(|agent| agent.chars().take(MAX_USER_AGENT_LEN).collect()),
+            impersonation_session_id,
+        }
+    }
+
+    /// Used outside requests: scheduled tasks and background jobs.
+    pub fn system() -> Self {
+        Self {
+            actor_type: AuditActorType::System,
+            actor_id: None,
+            ip_address: None,
+            user_agent: None,
+            impersonation_session_id: None,
+        }
+    }
+}
+
+/// The context of the request being handled; `system` for background work.
+pub fn current_context() -> AuditContext {
+    AUDIT_CONTEXT.try_with(|context| context.clone()).unwrap_or_else(|_| AuditContext::system())
+}
+
+/// Runs `fut` with `context` as the actor for any changes it makes.
+pub async fn with_context<F: Future>(context: AuditContext, fut: F) -> F::Output {
+    AUDIT_CONTEXT.scope(context, fut).await
+}
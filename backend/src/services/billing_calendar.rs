@@ -0,0 +1,25 @@
+//! Calendar billing for monthly, quarterly and annual subscriptions: periods
+//! end on the same day of the month (the anchor day), or on the last day of
+//! shorter months, instead of drifting by a fixed number of days.
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+
+/// Days in `month` (1-12) of `year`.
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    NaiveDate::from_ymd_opt(next_year, next_month, 1)
+        .and_then(|first| first.pred_opt())
+        .map_or(28, |last| last.day())
+}
+
+/// `date` moved on by `months`, on `anchor_day` or the month's last day if
+/// that comes first. The time of day is kept, so a subscription anchored on
+/// the 31st ends on 28 (or 29) February and then on 31 March.
+pub fn add_months(date: DateTime<Utc>, months: u32, anchor_day: u32) -> DateTime<Utc> {
+    let total = date.year() * 12 + date.month0() as i32 + months as i32;
+    let (year, month) = (total.div_euclid(12), total.rem_euclid(12) as u32 + 1);
+    let day = anchor_day.clamp(1, days_in_month(year, month));
+    NaiveDate::from_ymd_opt(year, month, day)
+        .map(|day| day.and_time(date.time()).and_utc())
+        .unwrap_or(date)
+}
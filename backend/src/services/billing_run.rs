@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+use std::time::Instant;
+use chrono::Duration;
+use tracing::info;
+use crate::config::AppConfig;
+use crate::models::billing_run::{BillingRunDto, BillingRunFailure, BillingRunReport};
+use crate::models::payment::{PaymentMethod, DEFAULT_CURRENCY};
+use crate::models::subscription::{CreateSubscriptionDto, Subscription};
+use crate::models::user::CreateUserDto;
+use crate::services::clock::Clock;
+use crate::services::database::DatabaseService;
+use crate::services::dunning::DunningPolicy;
+use crate::services::email::EmailService;
+use crate::services::gateway::PaymentGateway;
+use crate::services::sandbox::SandboxCard;
+use crate::tasks::renewal_task::{flush_notifications, renew_due_subscription, RenewalOutcome};
+
+const SMOKE_TAG: &str = "smoke-test";
+const SMOKE_BILLING_PERIOD_DAYS: u32 = 30;
+
+/// Which mock card the `index`-th subscription gets: the first
+/// `hard_decline_percent` of the run declines hard, the next
+/// `soft_decline_percent` soft, the rest are approved.
+fn card_for(index: u32, dto: &BillingRunDto) -> SandboxCard {
+    let position = index * 100 / dto.count;
+    if position < dto.hard_decline_percent {
+        SandboxCard::HardDecline
+    } else if position < dto.hard_decline_percent + dto.soft_decline_percent {
+        SandboxCard::SoftDecline
+    } else {
+        SandboxCard::Approve
+    }
+}
+
+/// Creates a user with a subscription whose period has just ended and a mock
+/// card on file, i.e. one the renewal task would charge now.
+async fn create_due_subscription(
+    db: &DatabaseService,
+    config: &AppConfig,
+    clock: &dyn Clock,
+    dto: &BillingRunDto,
+    run_id: &str,
+    index: u32,
+) -> Result<Subscription, String> {
+    let user = db.create_user(CreateUserDto {
+        email: format!("smoke+{}-{}@example.invalid", run_id, index),
+        name: format!("Smoke Test {}", index),
+    }).await?;
+
+    let subscription = db.create_subscription(CreateSubscriptionDto {
+        user_id: user.id.clone(),
+        plan_id: None,
+        plan_name: "Smoke Test".to_string(),
+        price: dto.price,
+        currency: DEFAULT_CURRENCY.to_string(),
+        payment_method: Some(PaymentMethod::Card),
+        grace_period_days: config.grace_period_days,
+        billing_period_days: SMOKE_BILLING_PERIOD_DAYS,
+        billing_period_months: None,
+        suspension_policy: config.suspension_policy,
+        usage_pricing: None,
+        seat_count: 1,
+    }).await?;
+
+    let period_start = clock.now() - Duration::days(SMOKE_BILLING_PERIOD_DAYS as i64) - Duration::minutes(1);
+    db.start_subscription_at(&subscription.id, period_start).await?;
+    let subscription = db
+        .set_subscription_tags(&subscription.id, vec![SMOKE_TAG.to_string(), format!("{}-{}", SMOKE_TAG, run_id)])
+        .await?;
+
+    let card = card_for(index, dto);
+    db.create_recurring_payment(
+        user.id,
+        subscription.id.clone(),
+        card.token(&format!("{}_{}", run_id, index)),
+        Some("4242".to_string()),
+        Some("VISA".to_string()),
+        None,
+    ).await;
+
+    Ok(subscription)
+}
+
+/// Sandbox smoke test of the billing engine: sets up `dto.count` due
+/// subscriptions, then pushes each through the same renewal step the
+/// renewal task runs (charge, invoice, emails, dunning) and reports how it
+/// went. `gateway` must be a `SandboxGateway` so the mock cards resolve.
+pub async fn run_billing_smoke_test(
+    db: &DatabaseService,
+    gateway: &dyn PaymentGateway,
+    config: &AppConfig,
+    email: &EmailService,
+    clock: &dyn Clock,
+    dto: &BillingRunDto,
+) -> BillingRunReport {
+    let run_id = uuid::Uuid::new_v4().simple().to_string()[..8].to_string();
+    let mut failures = Vec::new();
+
+    info!("Billing smoke run {}: creating {} due subscriptions", run_id, dto.count);
+    let mut subscriptions = Vec::with_capacity(dto.count as usize);
+    for index in 0..dto.count {
+        match create_due_subscription(db, config, clock, dto, &run_id, index).await {
+            Ok(subscription) => subscriptions.push(subscription),
+            Err(error) => failures.push(BillingRunFailure { subscription_id: None, error }),
+        }
+    }
+
+    let policy = DunningPolicy::from_config(config);
+    let mut outcomes: BTreeMap<RenewalOutcome, usize> = BTreeMap::new();
+    let started = Instant::now();
+    let mut notifications = Vec::new();
+    for subscription in &subscriptions {
+        let outcome = renew_due_subscription(db, gateway, config, email, &policy, subscription, clock.now(), &mut notifications).await;
+        if outcome == RenewalOutcome::Failed {
+            failures.push(BillingRunFailure {
+                subscription_id: Some(subscription.id.clone()),
+                error: "Charged but the renewal could not be recorded".to_string(),
+            });
+        }
+        *outcomes.entry(outcome).or_default() += 1;
+    }
+    flush_notifications(db, notifications).await;
+    let elapsed = started.elapsed();
+
+    let mut final_statuses: BTreeMap<String, usize> = BTreeMap::new();
+    for subscription in &subscriptions {
+        let status = match db.get_subscription(&subscription.id).await {
+            Some(current) => format!("{:?}", current.status),
+            None => "Missing".to_string(),
+        };
+        *final_statuses.entry(status).or_default() += 1;
+    }
+
+    let throughput_per_sec = if elapsed.as_secs_f64() > 0.0 {
+        subscriptions.len() as f64 / elapsed.as_secs_f64()
+    } else {
+        0.0
+    };
+    info!(
+        "Billing smoke run {} finished: {} renewals in {} ms ({:.1}/s), {} failures",
+        run_id, subscriptions.len(), elapsed.as_millis(), throughput_per_sec, failures.len()
+    );
+
+    BillingRunReport {
+        tag: format!("{}-{}", SMOKE_TAG, run_id),
+        run_id,
+        requested: dto.count,
+        processed: subscriptions.len(),
+        duration_ms: elapsed.as_millis(),
+        throughput_per_sec,
+        outcomes,
+        final_statuses,
+        failures,
+    }
+}
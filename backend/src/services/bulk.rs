@@ -0,0 +1,44 @@
+use chrono::Utc;
+use tracing::{error, info, warn};
+use crate::models::bulk_operation::{BulkFailure, BulkOperation, BulkOperationStatus};
+use crate::services::database::DatabaseService;
+
+/// Subscriptions processed between progress writes.
+const PROGRESS_INTERVAL: u64 = 100;
+
+/// Applies a confirmed operation to each subscription its preview matched,
+/// one at a time, writing progress back as it goes. Subscriptions that no
+/// longer match the filter are skipped; failures are recorded and the rest
+/// carry on.
+pub async fn run_bulk_operation(db: &DatabaseService, mut operation: BulkOperation) {
+    info!("Running bulk operation {} over {} subscription(s)", operation.id, operation.matched);
+    let subscription_ids = std::mem::take(&mut operation.subscription_ids);
+
+    for subscription_id in &subscription_ids {
+        match db.apply_bulk_action(subscription_id, &operation.action, &operation.filter).await {
+            Ok(true) => operation.succeeded += 1,
+            Ok(false) => operation.skipped += 1,
+            Err(e) => {
+                error!("Bulk operation {} failed on subscription {}: {}", operation.id, subscription_id, e);
+                operation.failures.push(BulkFailure { subscription_id: subscription_id.clone(), error: e });
+            }
+        }
+        operation.processed += 1;
+
+        if operation.processed % PROGRESS_INTERVAL == 0 {
+            if let Err(e) = db.save_bulk_progress(&operation).await {
+                warn!("{}", e);
+            }
+        }
+    }
+
+    operation.status = BulkOperationStatus::Completed;
+    operation.completed_at = Some(Utc::now());
+    if let Err(e) = db.save_bulk_progress(&operation).await {
+        error!("{}", e);
+    }
+    info!(
+        "Bulk operation {} finished: {} changed, {} skipped, {} failed",
+        operation.id, operation.succeeded, operation.skipped, operation.failures.len()
+    );
+}
@@ -0,0 +1,16 @@
+use chrono::{DateTime, Utc};
+
+/// Source of the current time for code that schedules or compares dates, so
+/// tests can run it at a chosen instant.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The wall clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
@@ -0,0 +1,175 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+use crate::models::payment::{Payment, PaymentStatus};
+use crate::models::plan_change::PLAN_CHANGE_PREFIX;
+use crate::models::subscription::{Subscription, SubscriptionStatus};
+
+/// Dates within this window of each other are treated as equal; activation and
+/// renewal timestamps are taken a few moments apart from their payments.
+const TOLERANCE_HOURS: i64 = 24;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ConsistencyIssue {
+    pub code: &'static str,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ConsistencyFinding {
+    pub subscription_id: String,
+    pub user_id: String,
+    pub status: SubscriptionStatus,
+    pub start_date: Option<DateTime<Utc>>,
+    pub end_date: Option<DateTime<Utc>>,
+    pub expected_start_date: Option<DateTime<Utc>>,
+    pub expected_end_date: Option<DateTime<Utc>>,
+    pub issues: Vec<ConsistencyIssue>,
+}
+
+/// Billing dates derived from the subscription's anchor (start of the current
+/// period) and its payment history.
+#[derive(Debug, Clone, Copy)]
+pub struct ExpectedDates {
+    pub start_date: DateTime<Utc>,
+    pub end_date: DateTime<Utc>,
+    pub grace_end_date: DateTime<Utc>,
+}
+
+fn is_billed(status: &SubscriptionStatus) -> bool {
+    matches!(status, SubscriptionStatus::Active | SubscriptionStatus::Grace | SubscriptionStatus::Suspended)
+}
+
+/// Proration charges pay for a plan change inside the current period rather
+/// than starting a new one, so they don't count here.
+fn latest_completed_payment(payments: &[Payment]) -> Option<&Payment> {
+    payments
+        .iter()
+        .filter(|p| p.status == PaymentStatus::Completed && !p.merchant_transaction_id.starts_with(PLAN_CHANGE_PREFIX))
+        .max_by_key(|p| p.paid_at())
+}
+
+/// Whether a payment at `paid_at` came after the period starting at `start`
+/// began, and so was never applied to it. Calendar periods start on their
+/// anchor day even when renewed late, so a payment before the period ends
+/// counts as paying for it.
+fn paid_after_period_start(sub: &Subscription, start: DateTime<Utc>, paid_at: DateTime<Utc>) -> bool {
+    match (sub.billing_period_months, sub.billing_anchor_day, sub.end_date) {
+        (Some(_), Some(_), Some(end)) => paid_at >= end,
+        _ => paid_at > start + Duration::hours(TOLERANCE_HOURS),
+    }
+}
+
+/// The current period starts at the later of the stored anchor and the most
+/// recent completed payment; a payment newer than the anchor means it was never
+/// applied to the subscription.
+pub fn expected_dates(sub: &Subscription, payments: &[Payment]) -> Option<ExpectedDates> {
+    let paid_at = latest_completed_payment(payments).map(|p| p.paid_at());
+    let start_date = match (sub.start_date, paid_at) {
+        (Some(start), Some(paid)) if paid_after_period_start(sub, start, paid) => sub.renewal_period(paid).0,
+        (Some(start), _) => start,
+        (None, Some(paid)) => paid,
+        (None, None) => return None,
+    };
+    // Pauses push the end of the current period back
+    let end_date = sub.next_period_end(start_date) + Duration::seconds(sub.pause_duration_secs);
+
+    Some(ExpectedDates {
+        start_date,
+        end_date,
+        grace_end_date: end_date + Duration::days(sub.grace_period_days as i64),
+    })
+}
+
+/// Compares a subscription's stored dates against its billing period and
+/// payment history. Returns `None` when nothing disagrees.
+pub fn check_subscription(sub: &Subscription, payments: &[Payment]) -> Option<ConsistencyFinding> {
+    if !is_billed(&sub.status) {
+        return None;
+    }
+
+    let mut issues = Vec::new();
+    let expected = expected_dates(sub, payments);
+    let last_payment = latest_completed_payment(payments);
+
+    if last_payment.is_none() {
+        issues.push(ConsistencyIssue {
+            code: "no_completed_payment",
+            detail: "Subscription is billed but has no completed payment".to_string(),
+        });
+    }
+
+    match (sub.start_date, sub.end_date) {
+        (None, _) | (_, None) => issues.push(ConsistencyIssue {
+            code: "missing_dates",
+            detail: "Subscription is missing its start_date or end_date".to_string(),
+        }),
+        (Some(start), Some(end)) => {
+            if end <= start {
+                issues.push(ConsistencyIssue {
+                    code: "end_before_start",
+                    detail: format!("end_date {} is not after start_date {}", end, start),
+                });
+            } else if sub.billing_anchor_day.is_some() && sub.billing_period_months.is_some() {
+                let expected_end = sub.next_period_end(start) + Duration::seconds(sub.pause_duration_secs);
+                if (end - expected_end).num_days().abs() > 1 {
+                    issues.push(ConsistencyIssue {
+                        code: "period_mismatch",
+                        detail: format!(
+                            "Period ends {} but the plan bills calendar months, ending {}",
+                            end, expected_end
+                        ),
+                    });
+                }
+            } else {
+                let period_days = (end - start).num_days();
+                if (period_days - sub.billing_period_days as i64).abs() > 1 {
+                    issues.push(ConsistencyIssue {
+                        code: "period_mismatch",
+                        detail: format!(
+                            "Period is {} days but the plan bills every {} days",
+                            period_days, sub.billing_period_days
+                        ),
+                    });
+                }
+            }
+
+            if let Some(payment) = last_payment {
+                if paid_after_period_start(sub, start, payment.paid_at()) {
+                    issues.push(ConsistencyIssue {
+                        code: "payment_not_applied",
+                        detail: format!(
+                            "Payment {} completed at {} after the current period started at {}",
+                            payment.merchant_transaction_id, payment.paid_at(), start
+                        ),
+                    });
+                }
+            }
+        }
+    }
+
+    if let (Some(end), Some(grace_end)) = (sub.end_date, sub.grace_end_date) {
+        let expected_grace_end = end + Duration::days(sub.grace_period_days as i64);
+        if (grace_end - expected_grace_end).num_hours().abs() > TOLERANCE_HOURS {
+            issues.push(ConsistencyIssue {
+                code: "grace_end_mismatch",
+                detail: format!("grace_end_date {} should be {}", grace_end, expected_grace_end),
+            });
+        }
+    }
+
+    if issues.is_empty() {
+        return None;
+    }
+
+    Some(ConsistencyFinding {
+        subscription_id: sub.id.clone(),
+        user_id: sub.user_id.clone(),
+        status: sub.status.clone(),
+        start_date: sub.start_date,
+        end_date: sub.end_date,
+        expected_start_date: expected.map(|e| e.start_date),
+        expected_end_date: expected.map(|e| e.end_date),
+        issues,
+    })
+}
@@ -1,21 +1,93 @@
+use std::ops::Deref;
 use std::sync::Arc;
-use chrono::{Utc, Duration};
+use chrono::{DateTime, Datelike, NaiveDate, Utc, Duration};
 use uuid::Uuid;
-use surrealdb::{Surreal, engine::remote::http::Client};
+use surrealdb::{Surreal, engine::remote::http::Client, method::Query, opt::IntoQuery};
+use tracing::{debug, error, info, warn};
+use crate::services::audit;
+use crate::services::billing_calendar;
+use crate::services::formatting::Locale;
+use crate::services::fx::FxService;
+use crate::services::payment_events::PaymentEvents;
+use crate::services::schema::{self, SchemaDriftMode};
+use crate::services::tax::TaxBreakdown;
+use crate::services::templates::{TemplateRegistry, DEFAULT_LOCALE};
+use crate::services::tenant;
+use crate::telemetry::current_request_id;
+use std::collections::BTreeMap;
 use crate::models::{
-    user::{User, CreateUserDto},
-    payment::{Payment, CreatePaymentDto, PaymentStatus, PaymentMethod},
-    subscription::{Subscription, CreateSubscriptionDto, SubscriptionStatus},
-    recurring_payment::{RecurringPayment, RecurringPaymentStatus},
+    user::{User, CreateUserDto, UpdateUserDto},
+    api_key::{ApiKey, ApiKeyScope},
+    tenant::{CreateTenantDto, PeachCredentials, Tenant, UpdateTenantDto},
+    audit::{AuditEntry, AuditFilter},
+    impersonation::{ImpersonationScope, ImpersonationSession},
+    payment::{Payment, DEFAULT_CURRENCY, CreatePaymentDto, PaymentCompletion, PaymentHistoryFilter, PaymentSort, PaymentStatus, PaymentMethod, MANUAL_GATEWAY},
+    subscription::{Subscription, CreateSubscriptionDto, RenewalSkip, SubscriptionStatus, LEGACY_GRACE_PERIOD_DAYS, DEFAULT_BILLING_PERIOD_DAYS},
+    recurring_payment::{CardExpiry, RecurringPayment, RecurringPaymentStatus},
+    template::{MessageTemplate, TemplateChannel},
+    notification::{CreateNotificationDto, EventPreference, NotificationChannel, NotificationEvent, NotificationPreferences},
+    activity::{ActivityCategory, ActivityEvent},
+    plan::{Plan, PlanCatalog, CreatePlanDto, UpdatePlanDto},
+    support::{NoteTarget, SupportNote},
+    ticket::{NewTicket, Ticket, TicketFilter, TicketSource, TicketStatus},
+    job::{Job, JobPayload, JobStatus, MAX_JOB_ATTEMPTS},
+    domain_event::{self, DomainEvent, DomainEventStatus, EVENT_LEASE_SECONDS},
+    payment_method_rule::{PaymentMethodRule, SetPaymentMethodRuleDto},
+    webhook_endpoint::{CreateWebhookEndpointDto, WebhookDelivery, WebhookDeliveryStatus, WebhookEndpoint, DELIVERY_LEASE_SECONDS},
+    report::DailySummary,
+    metrics::PaymentOutcome,
+    invoice::{CreditNote, Invoice, NewInvoice},
+    merchant::{MerchantDetails, DEFAULT_MERCHANT_ID},
+    export::{ExportRun, ExportStatus},
+    reconciliation::ReconciliationReport,
+    plan_change::{PlanChange, PlanChangeStatus},
+    installment::{split_installments, Installment, InstallmentStatus},
+    webhook_event::{ResultCodeCategory, ResultCodeCounts, ResultCodeStat, WebhookCodeMetrics, WebhookEvent, WebhookEventUpdate, WebhookOutcome},
+    refund::{Refund, RefundStatus},
+    card_update::{CardUpdate, CardUpdateStatus, CARD_UPDATE_PREFIX},
+    entitlement::{default_plan_features, PlanFeature, FREE_TIER_PLAN_ID},
+    attachment::{Attachment, AttachmentOwner},
+    wallet::{Wallet, WalletBalance, WalletEntry, WalletEntrySource, WALLET_GATEWAY},
+    usage::{UsageCharge, UsageRecord},
+    token_migration::{CreateTokenMigrationDto, TokenMigration, TokenMigrationProgress},
+    membership::SubscriptionMember,
+    bulk_operation::{BulkAction, BulkFilter, BulkOperation, CreateBulkOperationDto, BULK_PREVIEW_TTL_MINUTES},
+    state_reason,
 };
 
+/// The SurrealDB client, binding the current tenant's id as `$tenant` on
+/// every query (NONE outside a tenant). Statements on `users`,
+/// `subscriptions` and `payments` only match the tenant's rows through
+/// `($tenant = NONE OR tenant_id = $tenant)` and stamp `tenant_id` on the
+/// rows they create.
+#[derive(Clone)]
+pub struct TenantScopedDb(Arc<Surreal<Client>>);
+
+impl TenantScopedDb {
+    pub fn query(&self, query: impl IntoQuery) -> Query<'_, Client> {
+        self.0.query(query).bind(("tenant", tenant::current_id()))
+    }
+}
+
+impl Deref for TenantScopedDb {
+    type Target = Surreal<Client>;
+
+    fn deref(&self) -> &Surreal<Client> {
+        &self.0
+    }
+}
+
 #[derive(Clone)]
 pub struct DatabaseService {
-    pub db: Arc<Surreal<Client>>,
+    pub db: TenantScopedDb,
+    /// Published to whenever a payment's status is written.
+    pub payment_events: PaymentEvents,
 }
 
 impl DatabaseService {
-    pub async fn new() -> Result<Self, Box<dyn std::error::Error>> {
+    /// Connects and prepares the schema. Payment status changes are published
+    /// to `payment_events`, which the caller shares with whoever listens.
+    pub async fn new(payment_events: PaymentEvents) -> Result<Self, Box<dyn std::error::Error>> {
         // Connect to SurrealDB using HTTP client (not WebSocket)
         let db = Surreal::new::<surrealdb::engine::remote::http::Http>("127.0.0.1:8000").await?;
         
@@ -29,85 +101,60 @@ impl DatabaseService {
         db.use_ns("payment_system").use_db("main").await?;
         
         // Initialize database schema
-        Self::init_schema(&db).await?;
+        schema::reconcile(&db, SchemaDriftMode::from_env()).await?;
+        Self::seed_default_plans(&db).await?;
         
         Ok(Self {
-            db: Arc::new(db),
+            db: TenantScopedDb(Arc::new(db)),
+            payment_events,
         })
     }
+
+    /// Round trip to SurrealDB, for startup checks.
+    pub async fn health_check(&self) -> Result<(), String> {
+        self.db
+            .query("RETURN true")
+            .await
+            .and_then(|response| response.check())
+            .map(|_| ())
+            .map_err(|e| format!("SurrealDB is not reachable: {}", e))
+    }
     
-    async fn init_schema(db: &Surreal<Client>) -> Result<(), Box<dyn std::error::Error>> {
-        // Create tables and define schema
-        let queries = vec![
-            // Users table
-            "DEFINE TABLE users SCHEMAFULL;",
-            "DEFINE FIELD id ON users TYPE string;",
-            "DEFINE FIELD email ON users TYPE string;",
-            "DEFINE FIELD name ON users TYPE string;",
-            "DEFINE FIELD created_at ON users TYPE datetime;",
-            "DEFINE FIELD updated_at ON users TYPE datetime;",
-            "DEFINE INDEX unique_email ON users COLUMNS email UNIQUE;",
-            
-            // Payments table
-            "DEFINE TABLE payments SCHEMAFULL;",
-            "DEFINE FIELD id ON payments TYPE string;",
-            "DEFINE FIELD user_id ON payments TYPE string;",
-            "DEFINE FIELD subscription_id ON payments TYPE option<string>;",
-            "DEFINE FIELD amount ON payments TYPE number;",
-            "DEFINE FIELD recurring_token ON payments TYPE option<string>;",
-            "DEFINE FIELD status ON payments TYPE string;",
-            "DEFINE FIELD payment_method ON payments TYPE string;",
-            "DEFINE FIELD merchant_transaction_id ON payments TYPE string;",
-            "DEFINE FIELD checkout_id ON payments TYPE option<string>;",
-            "DEFINE FIELD created_at ON payments TYPE datetime;",
-            "DEFINE FIELD updated_at ON payments TYPE datetime;",
-            "DEFINE INDEX unique_merchant_txn ON payments COLUMNS merchant_transaction_id UNIQUE;",
-            
-            // Subscriptions table
-            "DEFINE TABLE subscriptions SCHEMAFULL;",
-            "DEFINE FIELD id ON subscriptions TYPE string;",
-            "DEFINE FIELD user_id ON subscriptions TYPE string;",
-            "DEFINE FIELD plan_name ON subscriptions TYPE string;",
-            "DEFINE FIELD price ON subscriptions TYPE number;",
-            "DEFINE FIELD status ON subscriptions TYPE string;",
-            "DEFINE FIELD payment_method ON subscriptions TYPE option<string>;",
-            "DEFINE FIELD payment_brand ON subscriptions TYPE option<string>;",
-            "DEFINE FIELD start_date ON subscriptions TYPE option<datetime>;",
-            "DEFINE FIELD end_date ON subscriptions TYPE option<datetime>;",
-            "DEFINE FIELD created_at ON subscriptions TYPE datetime;",
-            "DEFINE FIELD updated_at ON subscriptions TYPE datetime;",
-            
-            // Recurring payments table
-            "DEFINE TABLE recurring_payments SCHEMAFULL;",
-            "DEFINE FIELD id ON recurring_payments TYPE string;",
-            "DEFINE FIELD user_id ON recurring_payments TYPE string;",
-            "DEFINE FIELD subscription_id ON recurring_payments TYPE string;",
-            "DEFINE FIELD recurring_token ON recurring_payments TYPE string;",
-            "DEFINE FIELD card_last_four ON recurring_payments TYPE option<string>;",
-            "DEFINE FIELD card_brand ON recurring_payments TYPE option<string>;",
-            "DEFINE FIELD status ON recurring_payments TYPE string;",
-            "DEFINE FIELD created_at ON recurring_payments TYPE datetime;",
-            "DEFINE FIELD updated_at ON recurring_payments TYPE datetime;",
-            
-            // Notifications table
-            "DEFINE TABLE notification SCHEMAFULL;",
-            "DEFINE FIELD id ON notification TYPE string;",
-            "DEFINE FIELD user_id ON notification TYPE string;",
-            "DEFINE FIELD subscription_id ON notification TYPE string;",
-            "DEFINE FIELD message ON notification TYPE string;",
-            "DEFINE FIELD acknowledged ON notification TYPE bool;",
-            "DEFINE FIELD created_at ON notification TYPE datetime;",
-        ];
-        
-        for query in queries {
-            let result = db.query(query).await;
-            match result {
-                Ok(_) => println!("✅ Executed: {}", query),
-                Err(e) => println!("❌ Failed to execute {}: {}", query, e),
-            }
+    /// Seeds the built-in plans the first time the service starts against an
+    /// empty database; afterwards plans are managed through /admin/plans.
+    async fn seed_default_plans(db: &Surreal<Client>) -> Result<(), Box<dyn std::error::Error>> {
+        let existing: Vec<serde_json::Value> = db
+            .query("SELECT id FROM plans LIMIT 1")
+            .await?
+            .take(0)?;
+        if !existing.is_empty() {
+            return Ok(());
         }
-        
-        println!("✅ Database schema initialization completed");
+
+        let now = Utc::now();
+        for plan in PlanCatalog::default_plans() {
+            db.query("CREATE type::thing('plans', $id) SET name = $name, price = $price, prices_include_vat = $prices_include_vat, currency = $currency, interval = $interval, trial_days = $trial_days, grace_period_days = $grace_period_days, created_at = $now, updated_at = $now")
+                .bind(("id", plan.id.clone()))
+                .bind(("name", plan.name))
+                .bind(("price", plan.price))
+                .bind(("prices_include_vat", plan.prices_include_vat))
+                .bind(("currency", plan.currency))
+                .bind(("interval", format!("{:?}", plan.interval)))
+                .bind(("trial_days", plan.trial_days))
+                .bind(("grace_period_days", plan.grace_period_days))
+                .bind(("now", now))
+                .await?;
+            info!("Seeded plan {}", plan.id);
+        }
+        for (plan_id, feature_key, limit) in default_plan_features() {
+            db.query("CREATE type::thing('plan_features', [$plan_id, $feature_key]) SET plan_id = $plan_id, feature_key = $feature_key, limit = $limit, created_at = $now, updated_at = $now")
+                .bind(("plan_id", plan_id))
+                .bind(("feature_key", feature_key))
+                .bind(("limit", limit))
+                .bind(("now", now))
+                .await?;
+        }
+        info!("Seeded plan features");
         Ok(())
     }
 
@@ -118,7 +165,7 @@ impl DatabaseService {
   pub async fn create_user(&self, user_dto: CreateUserDto) -> Result<User, String> {
     // Check if user already exists
     let existing: Vec<User> = self.db
-        .query("SELECT * FROM users WHERE email = $email")
+        .query("SELECT * FROM users WHERE email = $email AND tenant_id = $tenant")
         .bind(("email", user_dto.email.clone()))
         .await
         .map_err(|e| format!("Database error: {}", e))?
@@ -137,6 +184,7 @@ impl DatabaseService {
         CREATE users:{} SET
             email = $email,
             name = $name,
+            tenant_id = $tenant,
             created_at = time::now(),
             updated_at = time::now()
     "#, user_id);
@@ -153,11 +201,18 @@ impl DatabaseService {
         id: user_id.clone(),
         email: user_dto.email.clone(),
         name: user_dto.name.clone(),
+        tags: Vec::new(),
+        billing_address: None,
+        vat_number: None,
+        locale: None,
+        deleted_at: None,
+        tenant_id: tenant::current_id(),
         created_at: now,
         updated_at: now,
     };
-    
-    println!("✅ Created user: {} ({})", user.name, user.id);
+
+    self.audit_change("user.created", "users", &user_id, None).await;
+    info!("Created user: {} ({})", user.name, user.id);
     Ok(user)
 }
 
@@ -173,12 +228,21 @@ impl DatabaseService {
             .select(("users", id_part))
             .await;
         
-        result.ok().flatten()
+        result.ok().flatten().filter(|user| tenant::visible(user.tenant_id.as_deref()))
+    }
+
+    /// The tenant new rows for `user_id` belong to: the current one, else the
+    /// user's own, for work done outside a request such as renewals.
+    async fn owning_tenant(&self, user_id: &str) -> Option<String> {
+        match tenant::current_id() {
+            Some(id) => Some(id),
+            None => self.get_user(user_id).await.and_then(|user| user.tenant_id),
+        }
     }
 
     pub async fn get_user_by_email(&self, email: &str) -> Option<User> {
         let result: Result<Vec<User>, _> = self.db
-            .query("SELECT * FROM users WHERE email = $email LIMIT 1")
+            .query("SELECT * FROM users WHERE email = $email AND ($tenant = NONE OR tenant_id = $tenant) LIMIT 1")
             .bind(("email", email.to_string()))
             .await
             .and_then(|mut response| response.take(0));
@@ -204,42 +268,28 @@ impl DatabaseService {
     // ---------------------
     
    // Fix the create_payment method around line 219
-pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Payment, String> {
-    let merchant_transaction_id = format!(
-        "TXN_{}",
-        Uuid::new_v4()
-            .simple()
-            .to_string()
-            .to_uppercase()
-            .get(..16)
-            .unwrap_or("0000000000000000")
-    );
-
+pub async fn create_payment(&self, payment_dto: CreatePaymentDto, vat_rate_percent: u32, gateway: &str) -> Result<Payment, String> {
     let payment_id = Uuid::new_v4().simple().to_string();
+    let tenant_id = self.owning_tenant(&payment_dto.user_id).await;
     
-    // ✅ Don't set the id field in content
-    let payment = Payment {
-        id: String::new(), // Will be set by SurrealDB
-        user_id: payment_dto.user_id,
-        subscription_id: Some(payment_dto.subscription_id),
-        amount: payment_dto.amount,
-        recurring_token: None,
-        status: PaymentStatus::Pending,
-        payment_method: payment_dto.payment_method.unwrap_or(PaymentMethod::Card),
-        merchant_transaction_id,
-        checkout_id: None,
-        created_at: Utc::now(),
-        updated_at: Utc::now(),
-    };
+    let payment = Payment::pending(payment_dto, vat_rate_percent, gateway, tenant_id);
 
     // Use query method to properly handle record creation
     let query = r#"
         CREATE payments SET
             merchant_transaction_id = $merchant_transaction_id,
+            subscription_id = $subscription_id,
             amount = $amount,
+            currency = $currency,
+            amount_excl_vat = $amount_excl_vat,
+            vat_amount = $vat_amount,
+            vat_rate_percent = $vat_rate_percent,
             payment_method = $payment_method,
+            gateway = $gateway,
             user_id = $user_id,
             status = $status,
+            state_reason = $state_reason,
+            tenant_id = $tenant_id,
             created_at = $created_at,
             updated_at = $updated_at
     "#;
@@ -247,10 +297,18 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
     let mut result = self.db
         .query(query)
         .bind(("merchant_transaction_id", payment.merchant_transaction_id.clone()))
+        .bind(("subscription_id", payment.subscription_id.clone()))
         .bind(("amount", payment.amount))
+        .bind(("currency", payment.currency.clone()))
+        .bind(("amount_excl_vat", payment.amount_excl_vat))
+        .bind(("vat_amount", payment.vat_amount))
+        .bind(("vat_rate_percent", payment.vat_rate_percent))
         .bind(("payment_method", payment.payment_method.to_string()))
+        .bind(("gateway", payment.gateway.clone()))
         .bind(("user_id", payment.user_id.clone()))
         .bind(("status", payment.status.clone()))
+        .bind(("state_reason", payment.state_reason.clone()))
+        .bind(("tenant_id", payment.tenant_id.clone()))
         .bind(("created_at", payment.created_at))
         .bind(("updated_at", payment.updated_at))
         .await
@@ -262,10 +320,11 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
     let created_payment = created_payment
         .ok_or_else(|| "Failed to create payment: no result returned".to_string())?;
     
-    println!(
-        "✅ Created payment: ID={}, MerchantTxnId={}, Amount={}",
+    info!(
+        "Created payment: ID={}, MerchantTxnId={}, Amount={}",
         created_payment.id, created_payment.merchant_transaction_id, created_payment.amount
     );
+    self.audit_payment_change("payment.created", &created_payment.merchant_transaction_id, None).await;
     Ok(created_payment)
 }
     // ✅ Fixed: Changed parameter from &Uuid to &str
@@ -280,12 +339,12 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
             .select(("payments", id_part))
             .await;
         
-        result.ok().flatten()
+        result.ok().flatten().filter(|payment| tenant::visible(payment.tenant_id.as_deref()))
     }
 
     pub async fn get_payment_by_merchant_id(&self, merchant_transaction_id: &str) -> Option<Payment> {
         let result: Result<Vec<Payment>, _> = self.db
-            .query("SELECT * FROM payments WHERE merchant_transaction_id = $merchant_id LIMIT 1")
+            .query("SELECT * FROM payments WHERE merchant_transaction_id = $merchant_id AND ($tenant = NONE OR tenant_id = $tenant) LIMIT 1")
             .bind(("merchant_id", merchant_transaction_id.to_string()))
             .await
             .and_then(|mut response| response.take(0));
@@ -293,17 +352,31 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
         let found = result.ok().and_then(|payments| payments.into_iter().next());
         
         if found.is_none() {
-            println!("🔍 Payment not found for merchant_transaction_id: {}", merchant_transaction_id);
+            debug!("Payment not found for merchant_transaction_id: {}", merchant_transaction_id);
         }
         
         found
     }
 
+    /// Looks a payment up by the gateway's id for it, for notifications such
+    /// as disputes that don't carry our merchant transaction id.
+    pub async fn get_payment_by_gateway_reference(&self, gateway_reference: &str) -> Option<Payment> {
+        let result: Result<Vec<Payment>, _> = self.db
+            .query("SELECT * FROM payments WHERE peach_payment_id = $reference AND ($tenant = NONE OR tenant_id = $tenant) LIMIT 1")
+            .bind(("reference", gateway_reference.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|payments| payments.into_iter().next())
+    }
+
     pub async fn update_payment_status(&self, merchant_transaction_id: &str, status: &PaymentStatus) -> Result<(), String> {
         let status_str = format!("{:?}", status);
+        let before = self.audit_payment_snapshot(merchant_transaction_id).await;
         let result: Result<Vec<Payment>, _> = self.db
-            .query("UPDATE payments SET status = $status, updated_at = $now WHERE merchant_transaction_id = $merchant_id RETURN AFTER")
+            .query("UPDATE payments SET status = $status, state_reason = $state_reason, authentication_url = NONE, updated_at = $now WHERE merchant_transaction_id = $merchant_id AND ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
             .bind(("status", status_str))
+            .bind(("state_reason", state_reason::for_payment_status(status)))
             .bind(("now", Utc::now()))
             .bind(("merchant_id", merchant_transaction_id.to_string()))
             .await
@@ -311,7 +384,10 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
         
         match result {
             Ok(payments) if !payments.is_empty() => {
-                println!("✅ Updated payment status: {:?} (MerchantTxnId: {})", status, merchant_transaction_id);
+                info!("Updated payment status: {:?} (MerchantTxnId: {})", status, merchant_transaction_id);
+                self.audit_payment_change("payment.status_changed", merchant_transaction_id, before).await;
+                self.payment_events.publish(merchant_transaction_id, status);
+                self.restore_wallet_credit(&payments[0]).await;
                 Ok(())
             }
             Ok(_) => Err(format!("Payment not found for merchant_transaction_id: {}", merchant_transaction_id)),
@@ -319,10 +395,239 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
         }
     }
 
-    pub async fn update_payment_checkout_id(&self, merchant_transaction_id: &str, checkout_id: &str) -> Result<(), String> {
+    /// Moves a payment to `status` for a webhook that happened at `event_at`,
+    /// unless a later webhook has already been applied. Returns whether it changed.
+    pub async fn apply_payment_event(
+        &self,
+        merchant_transaction_id: &str,
+        status: &PaymentStatus,
+        reason: &str,
+        event_at: DateTime<Utc>,
+    ) -> Result<bool, String> {
+        let before = self.audit_payment_snapshot(merchant_transaction_id).await;
+        let result: Result<Vec<Payment>, _> = self.db
+            .query("UPDATE payments SET status = $status, state_reason = $state_reason, authentication_url = NONE, last_event_at = $event_at, updated_at = $now WHERE merchant_transaction_id = $merchant_id AND ($tenant = NONE OR tenant_id = $tenant) AND (last_event_at IS NONE OR last_event_at <= $event_at) RETURN AFTER")
+            .bind(("status", format!("{:?}", status)))
+            .bind(("state_reason", reason.to_string()))
+            .bind(("event_at", event_at))
+            .bind(("now", Utc::now()))
+            .bind(("merchant_id", merchant_transaction_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(payments) if !payments.is_empty() => {
+                info!("Applied {:?} event from {} (MerchantTxnId: {})", status, event_at, merchant_transaction_id);
+                self.audit_payment_change("payment.status_changed", merchant_transaction_id, before).await;
+                self.payment_events.publish(merchant_transaction_id, status);
+                self.restore_wallet_credit(&payments[0]).await;
+                Ok(true)
+            }
+            Ok(_) => Ok(false),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    /// Puts an undecided payment on hold for the shopper's 3-D Secure
+    /// challenge at `authentication_url`. Payments the gateway has already
+    /// decided are left alone. Returns whether it changed.
+    pub async fn await_payment_authentication(
+        &self,
+        merchant_transaction_id: &str,
+        authentication_url: Option<&str>,
+    ) -> Result<bool, String> {
+        let before = self.audit_payment_snapshot(merchant_transaction_id).await;
+        let result: Result<Vec<Payment>, _> = self.db
+            .query("UPDATE payments SET status = $status, state_reason = $state_reason, authentication_url = $authentication_url ?? authentication_url, updated_at = $now WHERE merchant_transaction_id = $merchant_id AND ($tenant = NONE OR tenant_id = $tenant) AND status IN ['Pending', 'AwaitingAuthentication'] RETURN AFTER")
+            .bind(("status", format!("{:?}", PaymentStatus::AwaitingAuthentication)))
+            .bind(("state_reason", state_reason::AWAITING_AUTHENTICATION))
+            .bind(("authentication_url", authentication_url.map(|u| u.to_string())))
+            .bind(("now", Utc::now()))
+            .bind(("merchant_id", merchant_transaction_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(payments) if !payments.is_empty() => {
+                info!("Payment awaiting 3-D Secure authentication (MerchantTxnId: {})", merchant_transaction_id);
+                self.audit_payment_change("payment.authentication_required", merchant_transaction_id, before).await;
+                self.payment_events.publish(merchant_transaction_id, &PaymentStatus::AwaitingAuthentication);
+                Ok(true)
+            }
+            Ok(_) => Ok(false),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    /// Marks a payment whose amount the gateway has reserved, keeping the
+    /// authorisation's `gateway_reference` for the capture or void. Like
+    /// `apply_payment_event`, older events change nothing. Returns whether it changed.
+    pub async fn authorize_payment(
+        &self,
+        merchant_transaction_id: &str,
+        gateway_reference: Option<&str>,
+        event_at: DateTime<Utc>,
+    ) -> Result<bool, String> {
+        let before = self.audit_payment_snapshot(merchant_transaction_id).await;
+        let result: Result<Vec<Payment>, _> = self.db
+            .query("UPDATE payments SET status = $status, state_reason = $state_reason, authentication_url = NONE, peach_payment_id = $gateway_reference ?? peach_payment_id, last_event_at = $event_at, updated_at = $now WHERE merchant_transaction_id = $merchant_id AND ($tenant = NONE OR tenant_id = $tenant) AND (last_event_at IS NONE OR last_event_at <= $event_at) RETURN AFTER")
+            .bind(("status", format!("{:?}", PaymentStatus::Authorized)))
+            .bind(("state_reason", state_reason::PAYMENT_AUTHORIZED))
+            .bind(("gateway_reference", gateway_reference.map(|r| r.to_string())))
+            .bind(("event_at", event_at))
+            .bind(("now", Utc::now()))
+            .bind(("merchant_id", merchant_transaction_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(payments) if !payments.is_empty() => {
+                info!("Applied Authorized event from {} (MerchantTxnId: {})", event_at, merchant_transaction_id);
+                self.audit_payment_change("payment.authorized", merchant_transaction_id, before).await;
+                self.payment_events.publish(merchant_transaction_id, &PaymentStatus::Authorized);
+                Ok(true)
+            }
+            Ok(_) => Ok(false),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    pub async fn get_manual_payment_by_reference(&self, reference: &str) -> Option<Payment> {
+        let result: Result<Vec<Payment>, _> = self.db
+            .query("SELECT * FROM payments WHERE gateway = $gateway AND external_reference = $reference AND ($tenant = NONE OR tenant_id = $tenant) LIMIT 1")
+            .bind(("gateway", MANUAL_GATEWAY))
+            .bind(("reference", reference.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|payments| payments.into_iter().next())
+    }
+
+    /// Records an out-of-band payment as completed and moves the subscription
+    /// onto the period it pays for, in one transaction. The period starts at
+    /// the value date, or where the current paid period ends if that is later;
+    /// the subscription is only (re)activated if that period hasn't already ended.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn record_manual_payment(
+        &self,
+        subscription: &Subscription,
+        tax: TaxBreakdown,
+        currency: &str,
+        payment_method: PaymentMethod,
+        value_at: DateTime<Utc>,
+        reference: &str,
+        proof_attachment: Option<String>,
+    ) -> Result<(Payment, Subscription), String> {
+        let now = Utc::now();
+        let subscription_id = subscription.id.strip_prefix("subscriptions:").unwrap_or(&subscription.id).to_string();
+        let merchant_transaction_id = format!("MANUAL_{}", Uuid::new_v4().simple().to_string().to_uppercase());
+
+        // Extending a running period keeps its anchor day; a new one sets it
+        let (period_start, period_end, anchor_day) = match (&subscription.status, subscription.end_date) {
+            (SubscriptionStatus::Active, Some(end)) if end > value_at => (end, subscription.next_period_end(end), subscription.billing_anchor_day),
+            _ => {
+                let (end, anchor_day) = subscription.period_from(value_at);
+                (value_at, end, anchor_day)
+            }
+        };
+        let grace_end = period_end + Duration::days(subscription.grace_period_days as i64);
+        let subscription_before = self.audit_snapshot("subscriptions", &subscription_id).await;
+
+        let query = r#"
+            BEGIN TRANSACTION;
+            CREATE payments SET
+                merchant_transaction_id = $merchant_transaction_id,
+                subscription_id = $subscription_id,
+                user_id = $user_id,
+                amount = $amount,
+                currency = $currency,
+                amount_excl_vat = $amount_excl_vat,
+                vat_amount = $vat_amount,
+                vat_rate_percent = $vat_rate_percent,
+                payment_method = $payment_method,
+                gateway = $gateway,
+                status = 'Completed',
+                state_reason = $payment_reason,
+                value_date = $value_date,
+                external_reference = $reference,
+                proof_attachment = $proof_attachment,
+                tenant_id = $tenant_id,
+                created_at = $now,
+                updated_at = $now;
+            UPDATE type::thing('subscriptions', $subscription_id) SET
+                start_date = $start,
+                end_date = $end,
+                billing_anchor_day = $anchor_day,
+                grace_end_date = $grace_end,
+                pause_duration_secs = 0,
+                status = IF $end > $now THEN 'Active' ELSE status END,
+                state_reason = IF $end > $now THEN $subscription_reason ELSE state_reason END,
+                renewal_attempts = 0,
+                next_renewal_attempt_at = NONE,
+                last_renewal_error = NONE,
+                renewal_reminder_sent_for = NONE,
+                updated_at = $now
+                WHERE ($tenant = NONE OR tenant_id = $tenant);
+            COMMIT TRANSACTION;
+        "#;
+
+        self.db
+            .query(query)
+            .bind(("merchant_transaction_id", merchant_transaction_id.clone()))
+            .bind(("subscription_id", subscription_id.clone()))
+            .bind(("user_id", subscription.user_id.clone()))
+            .bind(("amount", tax.gross))
+            .bind(("currency", currency.to_string()))
+            .bind(("amount_excl_vat", tax.net))
+            .bind(("vat_amount", tax.vat))
+            .bind(("vat_rate_percent", tax.vat_rate_percent))
+            .bind(("payment_method", payment_method.to_string()))
+            .bind(("gateway", MANUAL_GATEWAY))
+            .bind(("value_date", value_at))
+            .bind(("reference", reference.to_string()))
+            .bind(("proof_attachment", proof_attachment))
+            .bind(("tenant_id", subscription.tenant_id.clone()))
+            .bind(("start", period_start))
+            .bind(("end", period_end))
+            .bind(("anchor_day", anchor_day))
+            .bind(("grace_end", grace_end))
+            .bind(("payment_reason", state_reason::PAYMENT_SUCCEEDED))
+            .bind(("subscription_reason", state_reason::MANUAL_PAYMENT_RECORDED))
+            .bind(("now", now))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| format!("Failed to record manual payment: {}", e))?;
+
+        let payment = self.get_payment_by_merchant_id(&merchant_transaction_id).await
+            .ok_or_else(|| format!("Manual payment {} missing after create", merchant_transaction_id))?;
+        let updated = self.get_subscription(&subscription_id).await
+            .ok_or_else(|| format!("Subscription not found: {}", subscription_id))?;
+
+        self.audit_payment_change("payment.recorded_manually", &merchant_transaction_id, None).await;
+        self.audit_change("subscription.manual_payment_applied", "subscriptions", &subscription_id, subscription_before).await;
+        self.payment_events.publish(&merchant_transaction_id, &PaymentStatus::Completed);
+        self.record_subscription_activity(
+            &updated,
+            "manual_payment_recorded",
+            &format!("Payment reference {} applied", reference),
+        ).await;
+
+        info!("Recorded manual payment {} ({}) for subscription {}", merchant_transaction_id, reference, subscription_id);
+        Ok((payment, updated))
+    }
+
+    pub async fn update_payment_checkout_id(
+        &self,
+        merchant_transaction_id: &str,
+        checkout_id: &str,
+        checkout_url: Option<&str>,
+        statement_descriptor: Option<&str>,
+    ) -> Result<(), String> {
         let result: Result<Vec<Payment>, _> = self.db
-            .query("UPDATE payments SET checkout_id = $checkout_id, updated_at = $now WHERE merchant_transaction_id = $merchant_id RETURN AFTER")
+            .query("UPDATE payments SET checkout_id = $checkout_id, checkout_url = $checkout_url, statement_descriptor = $statement_descriptor, updated_at = $now WHERE merchant_transaction_id = $merchant_id AND ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
             .bind(("checkout_id", checkout_id.to_string()))
+            .bind(("checkout_url", checkout_url.map(|u| u.to_string())))
+            .bind(("statement_descriptor", statement_descriptor.map(|d| d.to_string())))
             .bind(("now", Utc::now()))
             .bind(("merchant_id", merchant_transaction_id.to_string()))
             .await
@@ -330,7 +635,7 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
         
         match result {
             Ok(payments) if !payments.is_empty() => {
-                println!("✅ Updated payment checkout_id: {} (MerchantTxnId: {})", checkout_id, merchant_transaction_id);
+                info!("Updated payment checkout_id: {} (MerchantTxnId: {})", checkout_id, merchant_transaction_id);
                 Ok(())
             }
             Ok(_) => Err(format!("Payment not found for merchant_transaction_id: {}", merchant_transaction_id)),
@@ -341,7 +646,7 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
     // ✅ Fixed: Changed parameter from &Uuid to &str
     pub async fn get_payments_by_user(&self, user_id: &str) -> Vec<Payment> {
         let result: Result<Vec<Payment>, _> = self.db
-            .query("SELECT * FROM payments WHERE user_id = $user_id")
+            .query("SELECT * FROM payments WHERE user_id = $user_id AND ($tenant = NONE OR tenant_id = $tenant)")
             .bind(("user_id", user_id.to_string()))
             .await
             .and_then(|mut response| response.take(0));
@@ -349,47 +654,90 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
         result.unwrap_or_default()
     }
 
+    /// One page of a user's payments matching `filter`, with the number that
+    /// match across all pages.
+    pub async fn get_payment_history(
+        &self,
+        user_id: &str,
+        filter: &PaymentHistoryFilter,
+        sort: PaymentSort,
+        page: u32,
+        limit: u32,
+    ) -> Result<(Vec<Payment>, u64), String> {
+        let conditions = "user_id = $user_id AND ($tenant = NONE OR tenant_id = $tenant) AND ($status = NONE OR status = $status) AND ($from = NONE OR created_at >= $from) AND ($to = NONE OR created_at < $to)";
+        let query = format!(
+            "SELECT * FROM payments WHERE {conditions} ORDER BY {} LIMIT $limit START $start; \
+             SELECT count() AS count FROM payments WHERE {conditions} GROUP ALL;",
+            sort.order_by(),
+        );
+
+        let mut response = self.db
+            .query(query)
+            .bind(("user_id", user_id.to_string()))
+            .bind(("status", filter.status.as_ref().map(|status| format!("{:?}", status))))
+            .bind(("from", filter.from))
+            .bind(("to", filter.to))
+            .bind(("limit", limit))
+            .bind(("start", (page.saturating_sub(1) as u64) * limit as u64))
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        let payments: Vec<Payment> = response.take(0).map_err(|e| format!("Database error: {}", e))?;
+        let counts: Vec<serde_json::Value> = response.take(1).map_err(|e| format!("Database error: {}", e))?;
+        // GROUP ALL returns no row when nothing matched
+        let total = counts.first().and_then(|row| row.get("count")).and_then(|v| v.as_u64()).unwrap_or(0);
+        Ok((payments, total))
+    }
+
     // ---------------------
     // Subscription operations
     // ---------------------
     
  pub async fn create_subscription(&self, dto: CreateSubscriptionDto) -> Result<Subscription, String> {
     let subscription_id = Uuid::new_v4().simple().to_string();
+    let tenant_id = self.owning_tenant(&dto.user_id).await;
     
-    // ✅ Don't set the id field in content
-    let subscription = Subscription {
-        id: String::new(), // Will be set by SurrealDB
-        user_id: dto.user_id,
-        plan_name: dto.plan_name,
-        price: dto.price,
-        status: SubscriptionStatus::Pending,
-        payment_method: dto.payment_method,
-        payment_brand: None,
-        start_date: None,
-        end_date: None,
-        created_at: Utc::now(),
-        updated_at: Utc::now(),
-    };
+    let subscription = Subscription::pending(dto, tenant_id);
 
     // Use query method to properly handle record creation
     let query = r#"
         CREATE subscriptions SET
             user_id = $user_id,
+            plan_id = $plan_id,
             plan_name = $plan_name,
             price = $price,
+            currency = $currency,
             payment_method = $payment_method,
             status = $status,
             start_date = $start_date,
             end_date = $end_date,
+            grace_period_days = $grace_period_days,
+            billing_period_days = $billing_period_days,
+            billing_period_months = $billing_period_months,
+            suspension_policy = $suspension_policy,
+            usage_pricing = $usage_pricing,
+            seat_count = $seat_count,
+            state_reason = $state_reason,
+            tenant_id = $tenant_id,
             created_at = $created_at,
             updated_at = $updated_at
     "#;
 
     let mut result = self.db
         .query(query)
+        .bind(("grace_period_days", subscription.grace_period_days))
+        .bind(("billing_period_days", subscription.billing_period_days))
+        .bind(("billing_period_months", subscription.billing_period_months))
+        .bind(("suspension_policy", subscription.suspension_policy))
+        .bind(("usage_pricing", subscription.usage_pricing.clone()))
+        .bind(("seat_count", subscription.seat_count))
+        .bind(("state_reason", subscription.state_reason.clone()))
+        .bind(("tenant_id", subscription.tenant_id.clone()))
         .bind(("user_id", subscription.user_id.clone()))
+        .bind(("plan_id", subscription.plan_id.clone()))
         .bind(("plan_name", subscription.plan_name.clone()))
         .bind(("price", subscription.price))
+        .bind(("currency", subscription.currency.clone()))
         .bind(("payment_method", subscription.payment_method.as_ref().map(|pm| pm.to_string())))
         .bind(("status", subscription.status.clone()))
         .bind(("start_date", subscription.start_date))
@@ -405,7 +753,15 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
     let created_subscription = created_subscription
         .ok_or_else(|| "Failed to create subscription: no result returned".to_string())?;
     
-    println!("✅ Created subscription: {} ({})", created_subscription.plan_name, created_subscription.id);
+    info!("Created subscription: {} ({})", created_subscription.plan_name, created_subscription.id);
+    self.audit_change("subscription.created", "subscriptions", &created_subscription.id, None).await;
+    self.record_activity(
+        &created_subscription.user_id,
+        ActivityCategory::Subscription,
+        "subscription_created",
+        format!("Subscribed to the {} plan", created_subscription.plan_name),
+        Some(created_subscription.id.clone()),
+    ).await;
     Ok(created_subscription)
 }
         
@@ -420,13 +776,13 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
             .select(("subscriptions", id_part))
             .await;
         
-        result.ok().flatten()
+        result.ok().flatten().filter(|subscription| tenant::visible(subscription.tenant_id.as_deref()))
     }
 
     // ✅ Fixed: Changed parameter from &Uuid to &str
     pub async fn get_subscriptions_by_user(&self, user_id: &str) -> Vec<Subscription> {
         let result: Result<Vec<Subscription>, _> = self.db
-            .query("SELECT * FROM subscriptions WHERE user_id = $user_id")
+            .query("SELECT * FROM subscriptions WHERE user_id = $user_id AND ($tenant = NONE OR tenant_id = $tenant)")
             .bind(("user_id", user_id.to_string()))
             .await
             .and_then(|mut response| response.take(0));
@@ -437,18 +793,24 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
     // ✅ Fixed: Changed parameter from &Uuid to &str
     pub async fn activate_subscription(&self, subscription_id: &str) -> Result<(), String> {
         let now = Utc::now();
-        let end_date = now + Duration::days(1);
         
         let id_part = if subscription_id.starts_with("subscriptions:") {
             subscription_id.strip_prefix("subscriptions:").unwrap_or(subscription_id)
         } else {
             subscription_id
         };
+        let subscription = self.get_subscription(id_part).await
+            .ok_or_else(|| format!("Subscription not found: {}", subscription_id))?;
+        let (end, anchor_day) = subscription.period_from(now);
+        let before = self.audit_snapshot("subscriptions", id_part).await;
 
         let result: Result<Vec<Subscription>, _> = self.db
-            .query("UPDATE subscriptions SET status = 'Active', start_date = $start, end_date = $end, updated_at = $now WHERE id = $id RETURN AFTER")
+            .query("UPDATE subscriptions SET status = 'Active', start_date = $start, end_date = $end, billing_anchor_day = $anchor_day, grace_end_date = end_date + duration::from::days(grace_period_days ?? $legacy_grace), cancel_at_period_end = false, cancelled_at = NONE, cancellation_effective_at = NONE, cancellation_reason = NONE, pause_duration_secs = 0, amount_due = NONE, amount_paid = 0, part_payments = [], state_reason = $state_reason, updated_at = $now WHERE id = $id AND ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
+            .bind(("state_reason", state_reason::ACTIVATED))
+            .bind(("legacy_grace", LEGACY_GRACE_PERIOD_DAYS))
             .bind(("start", now))
-            .bind(("end", end_date))
+            .bind(("end", end))
+            .bind(("anchor_day", anchor_day))
             .bind(("now", now))
             .bind(("id", format!("subscriptions:{}", id_part)))
             .await
@@ -456,7 +818,9 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
         
         match result {
             Ok(subscriptions) if !subscriptions.is_empty() => {
-                println!("✅ Activated subscription: Active (ID: {})", subscription_id);
+                info!("Activated subscription: Active (ID: {})", subscription_id);
+                self.audit_change("subscription.activated", "subscriptions", id_part, before).await;
+                self.record_subscription_activity(&subscriptions[0], "subscription_activated", "Subscription activated").await;
                 Ok(())
             }
             Ok(_) => Err(format!("Subscription not found: {}", subscription_id)),
@@ -464,6 +828,262 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
         }
     }
 
+    /// Counts a payment a status poll found completed towards its
+    /// subscription, then activates the subscription unless that leaves the
+    /// period only part-paid. Returns whether it was activated; a payment is
+    /// counted once however often it is polled.
+    pub async fn settle_subscription_payment(&self, payment: &Payment) -> Result<bool, String> {
+        let id_part = match payment.subscription_id.as_deref() {
+            Some(id) => id.strip_prefix("subscriptions:").unwrap_or(id),
+            None => return Ok(false),
+        };
+        let before = self.get_subscription(id_part).await
+            .ok_or_else(|| format!("Subscription not found: {}", id_part))?;
+        let audit_before = self.audit_snapshot("subscriptions", id_part).await;
+        // A first installment activates the subscription by itself
+        let first_installment = self.get_installment_by_transaction(&payment.merchant_transaction_id).await
+            .filter(|i| i.sequence == 1);
+        if first_installment.is_some() {
+            self.start_installment_schedule(&payment.merchant_transaction_id, Utc::now()).await?;
+        }
+
+        let counted: Vec<Subscription> = self.db
+            .query("UPDATE type::thing('subscriptions', $id) SET amount_due = amount_due ?? $amount_due, amount_paid = (amount_paid ?? 0) + $amount, part_payments = array::append(part_payments ?? [], $merchant_id), updated_at = time::now() WHERE status IN ['Pending', 'Suspended'] AND $merchant_id NOT IN (part_payments ?? []) AND ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
+            .bind(("id", id_part.to_string()))
+            .bind(("amount_due", if first_installment.is_some() { payment.amount } else { before.period_amount_due() }))
+            .bind(("amount", payment.amount))
+            .bind(("merchant_id", payment.merchant_transaction_id.clone()))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Database error: {}", e))?;
+        let subscription = counted.into_iter().next().unwrap_or_else(|| before.clone());
+
+        if subscription.accepts_part_payments() && subscription.outstanding_balance() >= 0.005 {
+            if !before.part_payments.contains(&payment.merchant_transaction_id) {
+                self.record_part_payment(&subscription, payment, audit_before).await;
+            }
+            return Ok(false);
+        }
+        self.activate_subscription(id_part).await.map(|_| true)
+    }
+
+    /// Logs a payment that settled only part of a subscription's period.
+    async fn record_part_payment(&self, subscription: &Subscription, payment: &Payment, before: Option<serde_json::Value>) {
+        let outstanding = subscription.outstanding_balance();
+        info!(
+            "Counted part payment {} towards subscription {}; {:.2} {} outstanding",
+            payment.merchant_transaction_id, subscription.id, outstanding, subscription.currency
+        );
+        self.audit_change("subscription.part_paid", "subscriptions", &subscription.id, before).await;
+        self.record_subscription_activity(
+            subscription,
+            "subscription_part_paid",
+            &format!(
+                "Part payment of {:.2} {} received; {:.2} {} outstanding",
+                payment.amount, subscription.currency, outstanding, subscription.currency
+            ),
+        ).await;
+    }
+
+    /// Activates a subscription with its current period starting at `start`,
+    /// so a period that started long enough ago is due for renewal straight
+    /// away. Only used to set up synthetic subscriptions in sandbox mode.
+    pub async fn start_subscription_at(&self, subscription_id: &str, start: DateTime<Utc>) -> Result<Subscription, String> {
+        let id_part = subscription_id.strip_prefix("subscriptions:").unwrap_or(subscription_id);
+        let before = self.audit_snapshot("subscriptions", id_part).await;
+
+        let result: Result<Vec<Subscription>, _> = self.db
+            .query("UPDATE type::thing('subscriptions', $id) SET status = 'Active', start_date = $start, end_date = $start + duration::from::days(billing_period_days ?? $default_period), grace_end_date = end_date + duration::from::days(grace_period_days ?? $legacy_grace), state_reason = $state_reason, updated_at = time::now() WHERE ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
+            .bind(("state_reason", state_reason::ACTIVATED))
+            .bind(("legacy_grace", LEGACY_GRACE_PERIOD_DAYS))
+            .bind(("default_period", DEFAULT_BILLING_PERIOD_DAYS))
+            .bind(("start", start))
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(mut subscriptions) if !subscriptions.is_empty() => {
+                self.audit_change("subscription.started", "subscriptions", id_part, before).await;
+                Ok(subscriptions.remove(0))
+            }
+            Ok(_) => Err(format!("Subscription not found: {}", subscription_id)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    /// Marks `payment` completed for an event that happened at `event_at` and
+    /// activates its subscription, recording the gateway reference and the
+    /// payment method/brand used, all in one transaction so a crash can't
+    /// leave a paid payment behind an inactive subscription. The subscription
+    /// starts a new period with its dunning state cleared. Events older than
+    /// ones already applied change nothing, as with `apply_payment_event`.
+    pub async fn complete_payment_and_activate(
+        &self,
+        payment: &Payment,
+        event_at: DateTime<Utc>,
+        gateway_reference: Option<&str>,
+        payment_details: Option<(PaymentMethod, String)>,
+    ) -> Result<PaymentCompletion, String> {
+        let now = Utc::now();
+        let subscription_id = payment.subscription_id
+            .as_deref()
+            .map(|id| id.strip_prefix("subscriptions:").unwrap_or(id).to_string());
+        let (method, brand) = match payment_details {
+            Some((method, brand)) => (Some(format!("{:?}", method)), Some(brand)),
+            None => (None, None),
+        };
+        let payment_before = self.audit_payment_snapshot(&payment.merchant_transaction_id).await;
+        let subscription_before = match &subscription_id {
+            Some(id) => self.audit_snapshot("subscriptions", id).await,
+            None => None,
+        };
+        let subscription = match &subscription_id {
+            Some(id) => self.get_subscription(id).await,
+            None => None,
+        };
+        let (period_end, anchor_day) = subscription.as_ref()
+            .map(|s| s.period_from(now))
+            .map_or((None, None), |(end, anchor_day)| (Some(end), anchor_day));
+        // A first installment activates the subscription by itself
+        let first_installment = self.get_installment_by_transaction(&payment.merchant_transaction_id).await
+            .filter(|i| i.sequence == 1);
+        let amount_due = match &first_installment {
+            Some(_) => Some(payment.amount),
+            None => subscription.as_ref().map(|s| s.period_amount_due()),
+        };
+
+        let query = r#"
+            BEGIN TRANSACTION;
+            LET $completed = (UPDATE payments SET
+                status = 'Completed',
+                state_reason = $payment_reason,
+                authentication_url = NONE,
+                last_event_at = $event_at,
+                peach_payment_id = $gateway_reference ?? peach_payment_id,
+                updated_at = $now
+                WHERE merchant_transaction_id = $merchant_id AND ($tenant = NONE OR tenant_id = $tenant)
+                    AND (last_event_at IS NONE OR last_event_at <= $event_at)
+                RETURN AFTER);
+            IF array::len($completed) > 0 AND $subscription_id != NONE {
+                UPDATE type::thing('subscriptions', $subscription_id) SET
+                    amount_due = amount_due ?? $amount_due,
+                    amount_paid = (amount_paid ?? 0) + $completed[0].amount,
+                    part_payments = array::append(part_payments ?? [], $merchant_id),
+                    updated_at = $now
+                    WHERE status IN ['Pending', 'Suspended'] AND $merchant_id NOT IN (part_payments ?? [])
+                        AND ($tenant = NONE OR tenant_id = $tenant);
+                UPDATE type::thing('subscriptions', $subscription_id) SET
+                    status = 'Active',
+                    start_date = $now,
+                    end_date = $period_end ?? ($now + duration::from::days(billing_period_days ?? $default_period)),
+                    billing_anchor_day = $anchor_day,
+                    grace_end_date = end_date + duration::from::days(grace_period_days ?? $legacy_grace),
+                    payment_method = $method ?? payment_method,
+                    payment_brand = $brand ?? payment_brand,
+                    renewal_attempts = 0,
+                    next_renewal_attempt_at = NONE,
+                    last_renewal_error = NONE,
+                    renewal_reminder_sent_for = NONE,
+                    fallback_plan_id = NONE,
+                    cancel_at_period_end = false,
+                    cancelled_at = NONE,
+                    cancellation_effective_at = NONE,
+                    cancellation_reason = NONE,
+                    pause_duration_secs = 0,
+                    amount_due = NONE,
+                    amount_paid = 0,
+                    part_payments = [],
+                    state_reason = $subscription_reason,
+                    last_event_at = $event_at,
+                    updated_at = $now
+                    WHERE (last_event_at IS NONE OR last_event_at < $event_at)
+                        AND (status NOT IN ['Pending', 'Suspended'] OR amount_paid + 0.005 >= (amount_due ?? $amount_due))
+                        AND ($tenant = NONE OR tenant_id = $tenant);
+            };
+            IF array::len($completed) > 0 {
+                CREATE type::thing('domain_events', $event_id) SET
+                    event_type = $event_type,
+                    aggregate_id = $merchant_id,
+                    payload = {
+                        merchant_transaction_id: $merchant_id,
+                        user_id: $completed[0].user_id,
+                        subscription_id: $subscription_id,
+                        amount: $completed[0].amount,
+                        currency: $completed[0].currency,
+                        gateway: $completed[0].gateway,
+                        completed_at: $event_at
+                    },
+                    status = $event_status,
+                    attempts = 0,
+                    next_attempt_at = $now,
+                    created_at = $now;
+            };
+            COMMIT TRANSACTION;
+        "#;
+
+        self.db
+            .query(query)
+            .bind(("event_id", Uuid::new_v4().simple().to_string()))
+            .bind(("event_type", domain_event::PAYMENT_COMPLETED))
+            .bind(("event_status", DomainEventStatus::Pending))
+            .bind(("merchant_id", payment.merchant_transaction_id.clone()))
+            .bind(("subscription_id", subscription_id.clone()))
+            .bind(("gateway_reference", gateway_reference.map(|r| r.to_string())))
+            .bind(("method", method))
+            .bind(("brand", brand))
+            .bind(("payment_reason", state_reason::PAYMENT_SUCCEEDED))
+            .bind(("subscription_reason", state_reason::ACTIVATED))
+            .bind(("event_at", event_at))
+            .bind(("period_end", period_end))
+            .bind(("anchor_day", anchor_day))
+            .bind(("amount_due", amount_due))
+            .bind(("legacy_grace", LEGACY_GRACE_PERIOD_DAYS))
+            .bind(("default_period", DEFAULT_BILLING_PERIOD_DAYS))
+            .bind(("now", now))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| format!("Failed to complete payment {}: {}", payment.merchant_transaction_id, e))?;
+
+        // Both writes carry this event's timestamp when they went through
+        let payment_updated = self.get_payment_by_merchant_id(&payment.merchant_transaction_id).await
+            .is_some_and(|p| p.status == PaymentStatus::Completed && p.last_event_at == Some(event_at));
+        let after = match &subscription_id {
+            Some(id) if payment_updated => self.get_subscription(id).await,
+            _ => None,
+        };
+        let activated = after.clone()
+            .filter(|s| s.status == SubscriptionStatus::Active && s.last_event_at == Some(event_at));
+        // Counted just now and not enough to activate
+        let part_paid = after
+            .filter(|s| s.accepts_part_payments() && s.part_payments.contains(&payment.merchant_transaction_id))
+            .filter(|_| subscription.as_ref().is_some_and(|s| !s.part_payments.contains(&payment.merchant_transaction_id)));
+
+        if payment_updated {
+            info!("Applied Completed event from {} (MerchantTxnId: {})", event_at, payment.merchant_transaction_id);
+            self.audit_payment_change("payment.completed", &payment.merchant_transaction_id, payment_before).await;
+            self.payment_events.publish(&payment.merchant_transaction_id, &PaymentStatus::Completed);
+            if first_installment.is_some() {
+                if let Err(e) = self.start_installment_schedule(&payment.merchant_transaction_id, now).await {
+                    error!("{}", e);
+                }
+            }
+        }
+        if let Some(subscription) = &activated {
+            info!("Activated subscription: Active (ID: {}, event at {})", subscription.id, event_at);
+            self.audit_change("subscription.activated", "subscriptions", &subscription.id, subscription_before).await;
+            self.record_subscription_activity(subscription, "subscription_activated", "Subscription activated").await;
+        } else if let Some(subscription) = &part_paid {
+            self.record_part_payment(subscription, payment, subscription_before).await;
+        }
+
+        Ok(PaymentCompletion {
+            payment_updated,
+            subscription_activated: activated.is_some(),
+            subscription_part_paid: part_paid.is_some(),
+        })
+    }
+
     // ✅ Fixed: Changed parameter from &Uuid to &str
     pub async fn update_subscription_status(&self, subscription_id: &str, status: SubscriptionStatus) -> Result<(), String> {
         let status_str = format!("{:?}", status);
@@ -472,9 +1092,10 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
         } else {
             subscription_id
         };
+        let before = self.audit_snapshot("subscriptions", id_part).await;
 
         let result: Result<Vec<Subscription>, _> = self.db
-            .query("UPDATE subscriptions SET status = $status, updated_at = $now WHERE id = $id RETURN AFTER")
+            .query("UPDATE subscriptions SET status = $status, updated_at = $now WHERE id = $id AND ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
             .bind(("status", status_str))
             .bind(("now", Utc::now()))
             .bind(("id", format!("subscriptions:{}", id_part)))
@@ -483,7 +1104,8 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
         
         match result {
             Ok(subscriptions) if !subscriptions.is_empty() => {
-                println!("✅ Updated subscription status: {:?} (ID: {})", status, subscription_id);
+                info!("Updated subscription status: {:?} (ID: {})", status, subscription_id);
+                self.audit_change("subscription.status_changed", "subscriptions", id_part, before).await;
                 Ok(())
             }
             Ok(_) => Err(format!("Subscription not found: {}", subscription_id)),
@@ -504,9 +1126,10 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
         } else {
             subscription_id
         };
+        let before = self.audit_snapshot("subscriptions", id_part).await;
 
         let result: Result<Vec<Subscription>, _> = self.db
-            .query("UPDATE subscriptions SET payment_method = $method, payment_brand = $brand, updated_at = $now WHERE id = $id RETURN AFTER")
+            .query("UPDATE subscriptions SET payment_method = $method, payment_brand = $brand, updated_at = $now WHERE id = $id AND ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
             .bind(("method", method_str))
             .bind(("brand", brand.clone()))
             .bind(("now", Utc::now()))
@@ -516,7 +1139,8 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
         
         match result {
             Ok(subscriptions) if !subscriptions.is_empty() => {
-                println!("✅ Updated subscription payment: {:?}, brand: {:?} (Subscription ID: {})", method, brand, subscription_id);
+                info!("Updated subscription payment: {:?}, brand: {:?} (Subscription ID: {})", method, brand, subscription_id);
+                self.audit_change("subscription.payment_method_updated", "subscriptions", id_part, before).await;
                 Ok(())
             }
             Ok(_) => Err(format!("Subscription not found: {}", subscription_id)),
@@ -536,6 +1160,7 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
         token: String,
         card_last_four: Option<String>,
         card_brand: Option<String>,
+        card_expiry: Option<CardExpiry>,
     ) -> RecurringPayment {
         let rec_payment_id = Uuid::new_v4().simple().to_string();
         let rec_payment = RecurringPayment {
@@ -546,10 +1171,15 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
             card_last_four,
             card_brand,
             status: RecurringPaymentStatus::Active,
+            migration_id: None,
+            expiry_month: card_expiry.map(|e| e.month),
+            expiry_year: card_expiry.map(|e| e.year),
+            expiry_notified_at: None,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         };
 
+        // A new card means renewals no longer depend on one about to expire
         let query = r#"
             CREATE recurring_payments SET
                 user_id = $user_id,
@@ -557,9 +1187,13 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
                 recurring_token = $recurring_token,
                 card_last_four = $card_last_four,
                 card_brand = $card_brand,
+                expiry_month = $expiry_month,
+                expiry_year = $expiry_year,
                 status = $status,
                 created_at = $created_at,
-                updated_at = $updated_at
+                updated_at = $updated_at;
+            UPDATE subscriptions SET at_risk_reason = NONE, updated_at = $updated_at
+                WHERE user_id = $user_id AND at_risk_reason = $card_expiring AND ($tenant = NONE OR tenant_id = $tenant);
         "#;
 
         let _: Result<Vec<RecurringPayment>, _> = self.db
@@ -569,13 +1203,28 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
             .bind(("recurring_token", rec_payment.recurring_token.clone()))
             .bind(("card_last_four", rec_payment.card_last_four.clone()))
             .bind(("card_brand", rec_payment.card_brand.clone()))
+            .bind(("expiry_month", rec_payment.expiry_month))
+            .bind(("expiry_year", rec_payment.expiry_year))
+            .bind(("card_expiring", state_reason::CARD_EXPIRING))
             .bind(("status", rec_payment.status.clone()))
             .bind(("created_at", rec_payment.created_at))
             .bind(("updated_at", rec_payment.updated_at))
             .await
             .and_then(|mut response| response.take(0));
         
-        println!("✅ Created recurring payment: {}", rec_payment.id);
+        info!("Created recurring payment: {}", rec_payment.id);
+        let card_label = match (&rec_payment.card_brand, &rec_payment.card_last_four) {
+            (Some(brand), Some(last4)) => format!("{} ending in {}", brand, last4),
+            (None, Some(last4)) => format!("Card ending in {}", last4),
+            _ => "A new card".to_string(),
+        };
+        self.record_activity(
+            &rec_payment.user_id,
+            ActivityCategory::Security,
+            "card_added",
+            format!("{} was saved for automatic renewals", card_label),
+            Some(rec_payment.subscription_id.clone()),
+        ).await;
         rec_payment
     }
 
@@ -588,13 +1237,60 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
         result.unwrap_or_default()
     }
 
+    /// Active cards that expire within the notice period and whose owners
+    /// haven't been told yet.
+    pub async fn list_cards_needing_expiry_notice(&self, now: DateTime<Utc>) -> Result<Vec<RecurringPayment>, String> {
+        let cards: Vec<RecurringPayment> = self.db
+            .query("SELECT *, record::id(id) AS id FROM recurring_payments WHERE status = 'Active' AND expiry_year != NONE AND expiry_notified_at = NONE")
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Database error: {}", e))?;
+        Ok(cards.into_iter().filter(|card| card.needs_expiry_notice(now)).collect())
+    }
+
+    /// Records that the card's owner was told it's expiring and flags their
+    /// live subscriptions as at risk.
+    pub async fn flag_card_expiring(&self, card: &RecurringPayment) -> Result<(), String> {
+        let id_part = card.id.strip_prefix("recurring_payments:").unwrap_or(&card.id);
+        let query = r#"
+            BEGIN TRANSACTION;
+            UPDATE type::thing('recurring_payments', $id) SET expiry_notified_at = $now, updated_at = $now;
+            UPDATE subscriptions SET at_risk_reason = $reason, updated_at = $now
+                WHERE user_id = $user_id AND status IN ['Active', 'Grace', 'Pending'] AND ($tenant = NONE OR tenant_id = $tenant);
+            COMMIT TRANSACTION;
+        "#;
+
+        self.db
+            .query(query)
+            .bind(("id", id_part.to_string()))
+            .bind(("user_id", card.user_id.clone()))
+            .bind(("reason", state_reason::CARD_EXPIRING))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| format!("Failed to flag expiring card {}: {}", card.id, e))?;
+        Ok(())
+    }
+
+    pub async fn mark_recurring_token_failed(&self, token: &str) -> Result<(), String> {
+        self.db
+            .query("UPDATE recurring_payments SET status = 'Failed', updated_at = $now WHERE recurring_token = $token")
+            .bind(("token", token.to_string()))
+            .bind(("now", Utc::now()))
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        warn!("Recurring token retired after hard decline");
+        Ok(())
+    }
+
     pub async fn update_payment_recurring_token(
         &self,
         merchant_transaction_id: &str,
         token: &str,
     ) -> Result<(), String> {
         let result: Result<Vec<Payment>, _> = self.db
-            .query("UPDATE payments SET recurring_token = $token, updated_at = $now WHERE merchant_transaction_id = $merchant_id RETURN AFTER")
+            .query("UPDATE payments SET recurring_token = $token, updated_at = $now WHERE merchant_transaction_id = $merchant_id AND ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
             .bind(("token", token.to_string()))
             .bind(("now", Utc::now()))
             .bind(("merchant_id", merchant_transaction_id.to_string()))
@@ -603,7 +1299,7 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
         
         match result {
             Ok(payments) if !payments.is_empty() => {
-                println!("✅ Updated payment recurring_token (TxnId: {}, Token: {})", merchant_transaction_id, token);
+                info!("Updated payment recurring_token (TxnId: {})", merchant_transaction_id);
                 Ok(())
             }
             Ok(_) => Err(format!("Payment not found for merchant_transaction_id: {}", merchant_transaction_id)),
@@ -611,10 +1307,12 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
         }
     }
 
+    /// Subscriptions whose period has ended, or whose retry is due, including
+    /// those already in grace.
     pub async fn get_due_subscriptions(&self) -> Result<Vec<crate::models::subscription::Subscription>, String> {
         let now = Utc::now();
         let result: Result<Vec<crate::models::subscription::Subscription>, _> = self.db
-            .query("SELECT * FROM subscriptions WHERE status = 'Active' AND end_date <= $now")
+            .query("SELECT * FROM subscriptions WHERE status IN ['Active', 'Grace'] AND cancel_at_period_end != true AND end_date <= $now AND (next_renewal_attempt_at IS NONE OR next_renewal_attempt_at <= $now) AND ($tenant = NONE OR tenant_id = $tenant)")
             .bind(("now", now))
             .await
             .and_then(|mut response| response.take(0));
@@ -622,166 +1320,5153 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
         result.map_err(|e| format!("Database error: {}", e))
     }
 
+    /// Subscriptions in grace whose grace window (per-plan, stored on the row)
+    /// has elapsed and whose suspension policy is now due. Subscriptions with
+    /// a scheduled renewal retry are left to the dunning process.
     pub async fn get_expired_unpaid_subscriptions(&self) -> Result<Vec<crate::models::subscription::Subscription>, String> {
-        let cutoff_date = Utc::now() - chrono::Duration::days(1);
+        let now = Utc::now();
         let result: Result<Vec<crate::models::subscription::Subscription>, _> = self.db
-            .query("SELECT * FROM subscriptions WHERE status = 'Active' AND end_date < $cutoff")
-            .bind(("cutoff", cutoff_date))
+            .query("SELECT * FROM subscriptions WHERE status = 'Grace' AND cancel_at_period_end != true AND next_renewal_attempt_at IS NONE AND (grace_end_date ?? (end_date + duration::from::days(grace_period_days ?? $legacy_grace))) < $now AND ($tenant = NONE OR tenant_id = $tenant)")
+            .bind(("legacy_grace", LEGACY_GRACE_PERIOD_DAYS))
+            .bind(("now", now))
             .await
             .and_then(|mut response| response.take(0));
-        
-        result.map_err(|e| format!("Database error: {}", e))
+
+        // Days after grace vary per subscription, so that part is checked here
+        result
+            .map(|subs| subs.into_iter().filter(|s| s.lapses_at().is_some_and(|at| at < now)).collect())
+            .map_err(|e| format!("Database error: {}", e))
     }
 
-    // ✅ Fixed: Changed parameter from &uuid::Uuid to &str
-    pub async fn mark_subscription_renewed(&self, subscription_id: &str) -> Result<(), String> {
+    /// Cancels a subscription now, or schedules it to cancel when its paid
+    /// period ends. Scheduling needs an active subscription with time left;
+    /// otherwise it cancels immediately.
+    pub async fn cancel_subscription(
+        &self,
+        subscription: &Subscription,
+        at_period_end: bool,
+        reason: Option<String>,
+    ) -> Result<Subscription, String> {
         let now = Utc::now();
-        let end_date = now + chrono::Duration::days(30);
-        
-        let id_part = if subscription_id.starts_with("subscriptions:") {
-            subscription_id.strip_prefix("subscriptions:").unwrap_or(subscription_id)
-        } else {
-            subscription_id
+        let id_part = subscription.id.strip_prefix("subscriptions:").unwrap_or(&subscription.id).to_string();
+        let period_end = subscription.end_date
+            .filter(|end| at_period_end && subscription.status == SubscriptionStatus::Active && *end > now);
+        let before = self.audit_snapshot("subscriptions", &id_part).await;
+
+        let query = match period_end {
+            Some(_) => "UPDATE type::thing('subscriptions', $id) SET cancel_at_period_end = true, cancelled_at = $now, cancellation_effective_at = $effective_at, cancellation_reason = $reason, state_reason = $state_reason, updated_at = $now WHERE status = 'Active' AND ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER",
+            None => "UPDATE type::thing('subscriptions', $id) SET status = 'Cancelled', cancel_at_period_end = false, cancelled_at = $now, cancellation_effective_at = $effective_at, cancellation_reason = $reason, state_reason = $state_reason, next_renewal_attempt_at = NONE, updated_at = $now WHERE status NOT IN ['Cancelled', 'Expired'] AND ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER",
+        };
+        let cancel_reason = match period_end {
+            Some(_) => state_reason::CANCELLATION_SCHEDULED_BY_USER,
+            None => state_reason::CANCELLED_BY_USER,
         };
 
-        let result: Result<Vec<crate::models::subscription::Subscription>, _> = self.db
-            .query("UPDATE subscriptions SET start_date = $start, end_date = $end, updated_at = $now, status = 'Active' WHERE id = $id RETURN AFTER")
-            .bind(("start", now))
-            .bind(("end", end_date))
+        let result: Result<Vec<Subscription>, _> = self.db
+            .query(query)
+            .bind(("id", id_part.clone()))
+            .bind(("effective_at", period_end.unwrap_or(now)))
+            .bind(("reason", reason))
+            .bind(("state_reason", cancel_reason))
             .bind(("now", now))
-            .bind(("id", format!("subscriptions:{}", id_part)))
             .await
             .and_then(|mut response| response.take(0));
-        
+
         match result {
             Ok(subscriptions) if !subscriptions.is_empty() => {
-                println!("🔁 Subscription {} renewed successfully", subscription_id);
-                Ok(())
+                let updated = subscriptions.into_iter().next().unwrap_or_else(|| subscription.clone());
+                let description = match period_end {
+                    Some(end) => format!("Cancellation scheduled for {}", end.format("%Y-%m-%d")),
+                    None => "Subscription cancelled".to_string(),
+                };
+                warn!("{} (ID: {})", description, id_part);
+                self.audit_change("subscription.cancelled", "subscriptions", &id_part, before).await;
+                self.record_subscription_activity(&updated, "subscription_cancelled", &description).await;
+                Ok(updated)
             }
-            Ok(_) => Err(format!("Sub not found {}", subscription_id)),
+            Ok(_) => Err(format!("Subscription {} is already cancelled or expired", id_part)),
             Err(e) => Err(format!("Database error: {}", e)),
         }
     }
 
-    // ✅ Fixed: Changed parameter from &uuid::Uuid to &str
-    pub async fn suspend_subscription(&self, subscription_id: &str) -> Result<(), String> {
-        let id_part = if subscription_id.starts_with("subscriptions:") {
-            subscription_id.strip_prefix("subscriptions:").unwrap_or(subscription_id)
-        } else {
-            subscription_id
-        };
-
-        let result: Result<Vec<crate::models::subscription::Subscription>, _> = self.db
-            .query("UPDATE subscriptions SET status = 'Suspended', updated_at = $now WHERE id = $id RETURN AFTER")
+    /// Cancels subscriptions scheduled to cancel whose paid period has ended.
+    pub async fn complete_period_end_cancellations(&self) -> Result<Vec<Subscription>, String> {
+        let result: Result<Vec<Subscription>, _> = self.db
+            .query("UPDATE subscriptions SET status = 'Cancelled', cancel_at_period_end = false, state_reason = $state_reason, updated_at = $now WHERE status = 'Active' AND cancel_at_period_end = true AND end_date <= $now AND ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
+            .bind(("state_reason", state_reason::CANCELLED_AT_PERIOD_END))
             .bind(("now", Utc::now()))
-            .bind(("id", format!("subscriptions:{}", id_part)))
             .await
             .and_then(|mut response| response.take(0));
-        
+
+        let cancelled = result.map_err(|e| format!("Database error: {}", e))?;
+        for subscription in &cancelled {
+            info!("Subscription {} cancelled at the end of its period", subscription.id);
+            self.audit_change("subscription.cancelled", "subscriptions", &subscription.id, None).await;
+            self.record_subscription_activity(subscription, "subscription_cancelled", "Subscription ended as scheduled").await;
+        }
+        Ok(cancelled)
+    }
+
+    /// Withdraws a scheduled cancellation while the paid period is still
+    /// running, so the subscription renews as before.
+    pub async fn reactivate_subscription(&self, subscription: &Subscription) -> Result<Subscription, String> {
+        let now = Utc::now();
+        let id_part = subscription.id.strip_prefix("subscriptions:").unwrap_or(&subscription.id).to_string();
+        let before = self.audit_snapshot("subscriptions", &id_part).await;
+
+        let result: Result<Vec<Subscription>, _> = self.db
+            .query("UPDATE type::thing('subscriptions', $id) SET cancel_at_period_end = false, cancelled_at = NONE, cancellation_effective_at = NONE, cancellation_reason = NONE, state_reason = $state_reason, updated_at = $now WHERE status = 'Active' AND cancel_at_period_end = true AND end_date > $now AND ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
+            .bind(("id", id_part.clone()))
+            .bind(("state_reason", state_reason::REACTIVATED_BY_USER))
+            .bind(("now", now))
+            .await
+            .and_then(|mut response| response.take(0));
+
         match result {
             Ok(subscriptions) if !subscriptions.is_empty() => {
-                println!("🛑 Subscription {} suspended", subscription_id);
-                Ok(())
+                let updated = subscriptions.into_iter().next().unwrap_or_else(|| subscription.clone());
+                info!("Reactivated subscription {} before its scheduled cancellation", id_part);
+                self.audit_change("subscription.reactivated", "subscriptions", &id_part, before).await;
+                self.record_subscription_activity(&updated, "subscription_reactivated", "Scheduled cancellation withdrawn").await;
+                Ok(updated)
             }
-            Ok(_) => Err(format!("Sub not found {}", subscription_id)),
+            Ok(_) => Err(format!("Subscription {} has no cancellation to withdraw", id_part)),
             Err(e) => Err(format!("Database error: {}", e)),
         }
     }
 
-    // ✅ Fixed: Changed parameters from Uuid to String
-    pub async fn create_manual_renewal_notification(
+    /// Records in the audit log and the user's activity that an expired or
+    /// cancelled subscription is being restarted, with what the first new
+    /// period costs. Its payment activates it like any other.
+    pub async fn record_subscription_restart(
         &self,
-        user_id: String,
-        subscription_id: String,
-    ) -> Result<(), String> {
-        let notification_id = Uuid::new_v4().simple().to_string();
-        let message = format!("Your subscription {} is due for renewal", subscription_id);
-        let now = Utc::now();
+        subscription: &Subscription,
+        amount: f64,
+        coupon: Option<(&str, u32)>,
+        saved_card: bool,
+    ) {
+        let id_part = subscription.id.strip_prefix("subscriptions:").unwrap_or(&subscription.id);
+        let before = self.audit_snapshot("subscriptions", id_part).await;
+        let restart = serde_json::json!({
+            "status": format!("{:?}", subscription.status),
+            "plan_id": subscription.plan_id,
+            "amount": amount,
+            "currency": subscription.currency,
+            "coupon": coupon.map(|(code, _)| code),
+            "discount_percent": coupon.map(|(_, percent)| percent),
+            "saved_card": saved_card,
+        });
+        self.record_audit("subscription.restarted", "subscriptions", id_part, before, Some(restart)).await;
+
+        let description = match coupon {
+            Some((code, percent)) => format!("Restart requested with coupon {} ({}% off)", code, percent),
+            None => "Restart requested".to_string(),
+        };
+        self.record_subscription_activity(subscription, "subscription_restarted", &description).await;
+        info!("Restarting subscription {} for {} {}", id_part, amount, subscription.currency);
+    }
 
+    /// Records that `user_id` used win-back coupon `code` on a restart of
+    /// `subscription_id`. `Ok(false)` when they already have: each coupon works
+    /// once per user.
+    pub async fn redeem_winback_coupon(&self, user_id: &str, code: &str, subscription_id: &str) -> Result<bool, String> {
         let query = r#"
-            CREATE notification SET
+            BEGIN TRANSACTION;
+            IF (SELECT VALUE id FROM type::thing('coupon_redemptions', [$user_id, $code])) != [] {
+                THROW "Coupon already redeemed";
+            };
+            CREATE type::thing('coupon_redemptions', [$user_id, $code]) SET
                 user_id = $user_id,
+                code = $code,
                 subscription_id = $subscription_id,
-                message = $message,
-                acknowledged = false,
-                created_at = $created_at
+                created_at = $now
+                RETURN NONE;
+            COMMIT TRANSACTION;
         "#;
 
-        let mut result = self.db
+        let result = self.db
             .query(query)
-            .bind(("user_id", user_id.clone()))
-            .bind(("subscription_id", subscription_id.clone()))
-            .bind(("message", message.clone()))
-            .bind(("created_at", now))
-            .await
-            .map_err(|e| format!("Failed to create notification: {}", e))?;
-        
-        let _: Option<serde_json::Value> = result.take(0)
+            .bind(("user_id", user_id.to_string()))
+            .bind(("code", code.to_string()))
+            .bind(("subscription_id", subscription_id.to_string()))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|response| response.check());
+
+        match result {
+            Ok(_) => Ok(true),
+            // A concurrent redemption of the same coupon fails on the record id
+            Err(e) if e.to_string().contains("Coupon already redeemed") || e.to_string().contains("already exists") => Ok(false),
+            Err(e) => Err(format!("Failed to redeem coupon: {}", e)),
+        }
+    }
+
+    /// Gives a coupon back when the restart it was redeemed for didn't go ahead.
+    pub async fn release_winback_coupon(&self, user_id: &str, code: &str) {
+        let result = self.db
+            .query("DELETE type::thing('coupon_redemptions', [$user_id, $code])")
+            .bind(("user_id", user_id.to_string()))
+            .bind(("code", code.to_string()))
+            .await
+            .and_then(|response| response.check());
+        if let Err(e) = result {
+            error!("Failed to release coupon {} for {}: {}", code, user_id, e);
+        }
+    }
+
+    /// Moves active subscriptions whose period ended without a renewal into
+    /// grace. A failed renewal's `payment_failed_*` reason is kept; otherwise
+    /// the reason is `renewal_overdue`.
+    pub async fn start_grace_periods(&self) -> Result<Vec<Subscription>, String> {
+        let result: Result<Vec<Subscription>, _> = self.db
+            .query("UPDATE subscriptions SET status = 'Grace', state_reason = IF renewal_attempts > 0 THEN state_reason ELSE $state_reason END, updated_at = $now WHERE status = 'Active' AND cancel_at_period_end != true AND end_date <= $now AND ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
+            .bind(("state_reason", state_reason::RENEWAL_OVERDUE))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        let in_grace = result.map_err(|e| format!("Database error: {}", e))?;
+        for subscription in &in_grace {
+            let description = match subscription.lapses_at() {
+                Some(at) => format!("Payment overdue; access continues until {}", at.format("%Y-%m-%d")),
+                None => "Payment overdue".to_string(),
+            };
+            info!("Subscription {} entered its grace period", subscription.id);
+            self.audit_change("subscription.grace_started", "subscriptions", &subscription.id, None).await;
+            self.record_subscription_activity(subscription, "grace_started", &description).await;
+        }
+        Ok(in_grace)
+    }
+
+    /// Puts an active subscription on hold. Renewals, reminders and lapsing
+    /// all skip it until it is resumed.
+    pub async fn pause_subscription(&self, subscription: &Subscription) -> Result<Subscription, String> {
+        let now = Utc::now();
+        let id_part = subscription.id.strip_prefix("subscriptions:").unwrap_or(&subscription.id).to_string();
+        let before = self.audit_snapshot("subscriptions", &id_part).await;
+
+        let result: Result<Vec<Subscription>, _> = self.db
+            .query("UPDATE type::thing('subscriptions', $id) SET status = 'Paused', paused_at = $now, state_reason = $state_reason, updated_at = $now WHERE status = 'Active' AND ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
+            .bind(("id", id_part.clone()))
+            .bind(("state_reason", state_reason::PAUSED_BY_USER))
+            .bind(("now", now))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(subscriptions) if !subscriptions.is_empty() => {
+                let updated = subscriptions.into_iter().next().unwrap_or_else(|| subscription.clone());
+                info!("Paused subscription {}", id_part);
+                self.audit_change("subscription.paused", "subscriptions", &id_part, before).await;
+                self.record_subscription_activity(&updated, "subscription_paused", "Subscription paused").await;
+                Ok(updated)
+            }
+            Ok(_) => Err(format!("Subscription {} is not active", id_part)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    /// Reactivates a paused subscription, pushing its end (and grace) date back
+    /// by the time it spent paused so no paid time is lost.
+    pub async fn resume_subscription(&self, subscription: &Subscription) -> Result<Subscription, String> {
+        let now = Utc::now();
+        let id_part = subscription.id.strip_prefix("subscriptions:").unwrap_or(&subscription.id).to_string();
+        let paused_at = subscription.paused_at
+            .ok_or_else(|| format!("Subscription {} is not paused", id_part))?;
+        let paused_for = (now - paused_at).max(Duration::zero());
+        let end_date = subscription.end_date.map(|end| end + paused_for);
+        let grace_end = subscription.grace_ends_at().map(|grace_end| grace_end + paused_for);
+        let before = self.audit_snapshot("subscriptions", &id_part).await;
+
+        // Matching paused_at keeps two concurrent resumes from both extending the period
+        let result: Result<Vec<Subscription>, _> = self.db
+            .query("UPDATE type::thing('subscriptions', $id) SET status = 'Active', end_date = $end, grace_end_date = $grace_end, paused_at = NONE, pause_duration_secs = (pause_duration_secs ?? 0) + $paused_secs, state_reason = $state_reason, updated_at = $now WHERE status = 'Paused' AND paused_at = $paused_at AND ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
+            .bind(("id", id_part.clone()))
+            .bind(("state_reason", state_reason::RESUMED_BY_USER))
+            .bind(("end", end_date))
+            .bind(("grace_end", grace_end))
+            .bind(("paused_secs", paused_for.num_seconds()))
+            .bind(("paused_at", paused_at))
+            .bind(("now", now))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(subscriptions) if !subscriptions.is_empty() => {
+                let updated = subscriptions.into_iter().next().unwrap_or_else(|| subscription.clone());
+                info!("Resumed subscription {} after {} paused", id_part, format_paused(paused_for));
+                self.audit_change("subscription.resumed", "subscriptions", &id_part, before).await;
+                self.record_subscription_activity(
+                    &updated,
+                    "subscription_resumed",
+                    &format!("Subscription resumed; period extended by {}", format_paused(paused_for)),
+                ).await;
+                Ok(updated)
+            }
+            Ok(_) => Err(format!("Subscription {} is not paused", id_part)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    /// Flags (or unflags) an active subscription's next renewal to be skipped.
+    pub async fn set_skip_next_renewal(&self, subscription: &Subscription, skip: bool) -> Result<Subscription, String> {
+        let id_part = subscription.id.strip_prefix("subscriptions:").unwrap_or(&subscription.id).to_string();
+        let before = self.audit_snapshot("subscriptions", &id_part).await;
+
+        let result: Result<Vec<Subscription>, _> = self.db
+            .query("UPDATE type::thing('subscriptions', $id) SET skip_next_renewal = $skip, updated_at = $now WHERE status = 'Active' AND skip_next_renewal != $skip AND ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
+            .bind(("id", id_part.clone()))
+            .bind(("skip", skip))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(subscriptions) if !subscriptions.is_empty() => {
+                let updated = subscriptions.into_iter().next().unwrap_or_else(|| subscription.clone());
+                let (kind, description) = if skip {
+                    ("renewal_skip_scheduled", "Next renewal will be skipped")
+                } else {
+                    ("renewal_skip_cancelled", "Next renewal will be charged as usual")
+                };
+                self.audit_change(&format!("subscription.{}", kind), "subscriptions", &id_part, before).await;
+                self.record_subscription_activity(&updated, kind, description).await;
+                Ok(updated)
+            }
+            Ok(_) if skip => Err(format!("Subscription {} is not active or its next renewal is already skipped", id_part)),
+            Ok(_) => Err(format!("Subscription {} is not active or has no skip scheduled", id_part)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    /// Renewals of a subscription skipped since `since`.
+    pub async fn count_renewal_skips_since(&self, subscription_id: &str, since: DateTime<Utc>) -> Result<u64, String> {
+        let rows: Vec<serde_json::Value> = self.db
+            .query("SELECT count() AS count FROM renewal_skips WHERE subscription_id = $subscription_id AND created_at >= $since GROUP ALL")
+            .bind(("subscription_id", subscription_id.to_string()))
+            .bind(("since", since))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        // GROUP ALL returns no row when nothing matched
+        Ok(rows.first().and_then(|row| row.get("count")).and_then(|v| v.as_u64()).unwrap_or(0))
+    }
+
+    /// Gives a due subscription flagged to skip its next period for free: the
+    /// period moves on by one cycle, the flag is cleared and the skip recorded.
+    pub async fn skip_renewal(&self, subscription: &Subscription) -> Result<RenewalSkip, String> {
+        let id_part = subscription.id.strip_prefix("subscriptions:").unwrap_or(&subscription.id).to_string();
+        let period_start = subscription.end_date
+            .ok_or_else(|| format!("Subscription {} has no end date", id_part))?;
+        let period_end = subscription.next_period_end(period_start);
+        let grace_end = period_end + Duration::days(subscription.grace_period_days as i64);
+        let skip_id = Uuid::new_v4().simple().to_string();
+        let before = self.audit_snapshot("subscriptions", &id_part).await;
+
+        // Matching the old end date keeps a second run from skipping twice
+        let query = r#"
+            BEGIN TRANSACTION;
+            LET $skipped = (UPDATE type::thing('subscriptions', $id) SET
+                start_date = $period_start,
+                end_date = $period_end,
+                grace_end_date = $grace_end,
+                skip_next_renewal = false,
+                renewal_attempts = 0,
+                next_renewal_attempt_at = NONE,
+                last_renewal_error = NONE,
+                pause_duration_secs = 0,
+                status = 'Active',
+                state_reason = $state_reason,
+                updated_at = $now
+                WHERE status IN ['Active', 'Grace'] AND skip_next_renewal = true AND end_date = $period_start AND ($tenant = NONE OR tenant_id = $tenant)
+                RETURN AFTER);
+            IF array::len($skipped) = 0 {
+                THROW "Subscription is not due for a skipped renewal";
+            };
+            CREATE type::thing('renewal_skips', $skip_id) SET
+                subscription_id = $id,
+                user_id = $user_id,
+                period_start = $period_start,
+                period_end = $period_end,
+                created_at = $now
+                RETURN NONE;
+            COMMIT TRANSACTION;
+        "#;
+
+        self.db
+            .query(query)
+            .bind(("id", id_part.clone()))
+            .bind(("skip_id", skip_id.clone()))
+            .bind(("user_id", subscription.user_id.clone()))
+            .bind(("period_start", period_start))
+            .bind(("period_end", period_end))
+            .bind(("grace_end", grace_end))
+            .bind(("state_reason", state_reason::RENEWAL_SKIPPED))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| format!("Failed to skip renewal of {}: {}", id_part, e))?;
+
+        let skip: Option<RenewalSkip> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('renewal_skips', $id)")
+            .bind(("id", skip_id))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Database error: {}", e))?;
+        let skip = skip.ok_or_else(|| format!("Failed to skip renewal of {}: no result returned", id_part))?;
+
+        info!("Skipped renewal of subscription {} until {}", id_part, period_end);
+        self.audit_change("subscription.renewal_skipped", "subscriptions", &id_part, before).await;
+        self.record_subscription_activity(
+            subscription,
+            "renewal_skipped",
+            &format!("Renewal skipped; next charge on {}", period_end.format("%Y-%m-%d")),
+        ).await;
+        Ok(skip)
+    }
+
+    /// Starts the billing period a renewal pays for (see
+    /// `Subscription::renewal_period`) and queues `subscription.renewed` in
+    /// the same transaction.
+    pub async fn mark_subscription_renewed(&self, subscription: &Subscription) -> Result<(), String> {
+        let now = Utc::now();
+        let subscription_id = subscription.id.as_str();
+        let id_part = subscription_id.strip_prefix("subscriptions:").unwrap_or(subscription_id);
+        let (start, end) = subscription.renewal_period(now);
+        let before = self.audit_snapshot("subscriptions", id_part).await;
+
+        let query = r#"
+            BEGIN TRANSACTION;
+            LET $renewed = (UPDATE type::thing('subscriptions', $id) SET
+                start_date = $start,
+                end_date = $end,
+                grace_end_date = end_date + duration::from::days(grace_period_days ?? $legacy_grace),
+                renewal_attempts = 0,
+                next_renewal_attempt_at = NONE,
+                last_renewal_error = NONE,
+                last_renewal_attempt_at = $now,
+                pause_duration_secs = 0,
+                fallback_plan_id = NONE,
+                updated_at = $now,
+                status = 'Active',
+                state_reason = $state_reason
+                WHERE ($tenant = NONE OR tenant_id = $tenant)
+                RETURN AFTER);
+            IF array::len($renewed) > 0 {
+                CREATE type::thing('domain_events', $event_id) SET
+                    event_type = $event_type,
+                    aggregate_id = $id,
+                    payload = {
+                        subscription_id: $id,
+                        user_id: $renewed[0].user_id,
+                        plan_id: $renewed[0].plan_id,
+                        start_date: $renewed[0].start_date,
+                        end_date: $renewed[0].end_date
+                    },
+                    status = $event_status,
+                    attempts = 0,
+                    next_attempt_at = $now,
+                    created_at = $now;
+            };
+            COMMIT TRANSACTION;
+        "#;
+
+        self.db
+            .query(query)
+            .bind(("state_reason", state_reason::RENEWED))
+            .bind(("legacy_grace", LEGACY_GRACE_PERIOD_DAYS))
+            .bind(("start", start))
+            .bind(("end", end))
+            .bind(("now", now))
+            .bind(("id", id_part.to_string()))
+            .bind(("event_id", Uuid::new_v4().simple().to_string()))
+            .bind(("event_type", domain_event::SUBSCRIPTION_RENEWED))
+            .bind(("event_status", DomainEventStatus::Pending))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        match self.get_subscription(id_part).await {
+            Some(subscription) => {
+                info!("Subscription {} renewed successfully", subscription_id);
+                self.audit_change("subscription.renewed", "subscriptions", id_part, before).await;
+                self.record_subscription_activity(&subscription, "subscription_renewed", "Subscription renewed").await;
+                Ok(())
+            }
+            None => Err(format!("Sub not found {}", subscription_id)),
+        }
+    }
+
+    /// Active subscriptions ending within their reminder window (at most `days`,
+    /// see `Subscription::reminder_days`) that haven't been reminded about this
+    /// period yet.
+    pub async fn get_subscriptions_needing_renewal_reminder(&self, days: u32) -> Result<Vec<Subscription>, String> {
+        let now = Utc::now();
+        let result: Result<Vec<Subscription>, _> = self.db
+            .query("SELECT * FROM subscriptions WHERE status = 'Active' AND cancel_at_period_end != true AND skip_next_renewal != true AND end_date > $now AND end_date <= $horizon AND (renewal_reminder_sent_for IS NONE OR renewal_reminder_sent_for != end_date) AND ($tenant = NONE OR tenant_id = $tenant)")
+            .bind(("now", now))
+            .bind(("horizon", now + Duration::days(days as i64)))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        let due = result.map_err(|e| format!("Database error: {}", e))?;
+        Ok(due
+            .into_iter()
+            .filter(|sub| sub.end_date.is_some_and(|end| end <= now + Duration::days(sub.reminder_days(days) as i64)))
+            .collect())
+    }
+
+    pub async fn mark_renewal_reminder_sent(&self, subscription_id: &str) -> Result<(), String> {
+        let id_part = subscription_id.strip_prefix("subscriptions:").unwrap_or(subscription_id);
+
+        self.db
+            .query("UPDATE subscriptions SET renewal_reminder_sent_for = end_date WHERE id = $id AND ($tenant = NONE OR tenant_id = $tenant)")
+            .bind(("id", format!("subscriptions:{}", id_part)))
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Records a failed auto-renewal charge. `next_attempt_at` is `None` once
+    /// dunning is exhausted; `reason` becomes the subscription's `state_reason`.
+    pub async fn record_renewal_failure(
+        &self,
+        subscription_id: &str,
+        attempts: u32,
+        next_attempt_at: Option<chrono::DateTime<Utc>>,
+        error: &str,
+        reason: &str,
+    ) -> Result<(), String> {
+        let id_part = subscription_id.strip_prefix("subscriptions:").unwrap_or(subscription_id);
+        let before = self.audit_snapshot("subscriptions", id_part).await;
+
+        let result: Result<Vec<Subscription>, _> = self.db
+            .query("UPDATE subscriptions SET renewal_attempts = $attempts, last_renewal_attempt_at = $now, next_renewal_attempt_at = $next, last_renewal_error = $error, state_reason = $state_reason, updated_at = $now WHERE id = $id AND ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
+            .bind(("attempts", attempts))
+            .bind(("state_reason", reason.to_string()))
+            .bind(("now", Utc::now()))
+            .bind(("next", next_attempt_at))
+            .bind(("error", error.to_string()))
+            .bind(("id", format!("subscriptions:{}", id_part)))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(subscriptions) if !subscriptions.is_empty() => {
+                info!("Renewal attempt {} failed for subscription {} (next: {:?})", attempts, subscription_id, next_attempt_at);
+                self.audit_change("subscription.renewal_failed", "subscriptions", id_part, before).await;
+                self.record_subscription_activity(&subscriptions[0], "renewal_failed", "Automatic renewal payment failed").await;
+                Ok(())
+            }
+            Ok(_) => Err(format!("Sub not found {}", subscription_id)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    /// Suspends the subscription with `reason` as its `state_reason` and
+    /// queues `subscription.suspended` in the same transaction.
+    pub async fn suspend_subscription(&self, subscription_id: &str, reason: &str) -> Result<(), String> {
+        let now = Utc::now();
+        let id_part = subscription_id.strip_prefix("subscriptions:").unwrap_or(subscription_id);
+        let before = self.audit_snapshot("subscriptions", id_part).await;
+
+        let query = r#"
+            BEGIN TRANSACTION;
+            LET $suspended = (UPDATE type::thing('subscriptions', $id) SET
+                status = 'Suspended',
+                state_reason = $state_reason,
+                updated_at = $now
+                WHERE ($tenant = NONE OR tenant_id = $tenant)
+                RETURN AFTER);
+            IF array::len($suspended) > 0 {
+                CREATE type::thing('domain_events', $event_id) SET
+                    event_type = $event_type,
+                    aggregate_id = $id,
+                    payload = {
+                        subscription_id: $id,
+                        user_id: $suspended[0].user_id,
+                        plan_id: $suspended[0].plan_id,
+                        reason: $state_reason
+                    },
+                    status = $event_status,
+                    attempts = 0,
+                    next_attempt_at = $now,
+                    created_at = $now;
+            };
+            COMMIT TRANSACTION;
+        "#;
+
+        self.db
+            .query(query)
+            .bind(("state_reason", reason.to_string()))
+            .bind(("now", now))
+            .bind(("id", id_part.to_string()))
+            .bind(("event_id", Uuid::new_v4().simple().to_string()))
+            .bind(("event_type", domain_event::SUBSCRIPTION_SUSPENDED))
+            .bind(("event_status", DomainEventStatus::Pending))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        match self.get_subscription(id_part).await {
+            Some(subscription) => {
+                info!("Subscription {} suspended", subscription_id);
+                self.audit_change("subscription.suspended", "subscriptions", id_part, before).await;
+                self.record_subscription_activity(&subscription, "subscription_suspended", "Subscription suspended").await;
+                Ok(())
+            }
+            None => Err(format!("Sub not found {}", subscription_id)),
+        }
+    }
+
+    /// Lapses a subscription under a `Downgrade` policy, or moves it to the
+    /// dunning fallback plan when `fallback_plan_id` is set. The paid plan is
+    /// kept on the record so renewing restores it; until then access is the
+    /// free tier or the fallback plan.
+    pub async fn downgrade_subscription(
+        &self,
+        subscription_id: &str,
+        reason: &str,
+        fallback_plan_id: Option<&str>,
+    ) -> Result<(), String> {
+        let id_part = subscription_id.strip_prefix("subscriptions:").unwrap_or(subscription_id);
+        let before = self.audit_snapshot("subscriptions", id_part).await;
+
+        let result: Result<Vec<Subscription>, _> = self.db
+            .query("UPDATE type::thing('subscriptions', $id) SET status = 'Downgraded', state_reason = $state_reason, fallback_plan_id = $fallback_plan_id, updated_at = $now WHERE ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
+            .bind(("state_reason", reason.to_string()))
+            .bind(("fallback_plan_id", fallback_plan_id.map(str::to_string)))
+            .bind(("now", Utc::now()))
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(subscriptions) if !subscriptions.is_empty() => {
+                let plan = fallback_plan_id.unwrap_or(FREE_TIER_PLAN_ID);
+                info!("Subscription {} downgraded to {}", subscription_id, plan);
+                self.audit_change("subscription.downgraded", "subscriptions", id_part, before).await;
+                self.record_subscription_activity(
+                    &subscriptions[0],
+                    "subscription_downgraded",
+                    &format!("Subscription downgraded to the {} plan", plan),
+                ).await;
+                Ok(())
+            }
+            Ok(_) => Err(format!("Sub not found {}", subscription_id)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    /// Writes the notification unless the user turned off in-app delivery
+    /// for its event.
+    pub async fn create_notification(&self, dto: CreateNotificationDto) -> Result<(), String> {
+        let mut dto = match self.route_in_app(vec![dto]).await.pop() {
+            Some(dto) => dto,
+            None => return Ok(()),
+        };
+        self.render_messages(std::slice::from_mut(&mut dto)).await;
+        let query = r#"
+            CREATE notification SET
+                user_id = $user_id,
+                subscription_id = $subscription_id,
+                message = $message,
+                acknowledged = false,
+                action_type = $action_type,
+                action_payload = $action_payload,
+                event = $event,
+                created_at = $created_at
+        "#;
+
+        self.db
+            .query(query)
+            .bind(("user_id", dto.user_id))
+            .bind(("subscription_id", dto.subscription_id))
+            .bind(("message", dto.message))
+            .bind(("action_type", dto.action_type))
+            .bind(("action_payload", dto.action_payload))
+            .bind(("event", dto.event))
+            .bind(("created_at", Utc::now()))
+            .await
             .map_err(|e| format!("Failed to create notification: {}", e))?;
-        
-        println!("🔔 Notification created for user {} to manually renew subscription {}", user_id, subscription_id);
+
         Ok(())
     }
 
-    pub async fn get_user_notifications(
-        &self,
-        user_id: String,
-    ) -> Result<Vec<crate::models::notification::Notification>, String> {
-        let query = "SELECT * FROM notification WHERE user_id = $user_id ORDER BY created_at DESC";
-        
-        match self.db.query(query).bind(("user_id", user_id)).await {
-            Ok(mut result) => {
-                let notifications: Vec<crate::models::notification::Notification> = result
-                    .take(0)
-                    .map_err(|e| format!("Failed to extract notifications: {}", e))?;
-                Ok(notifications)
+    /// Creates all the notifications in one round trip, for tasks that
+    /// notify many users in a run. Either every notification in a chunk of
+    /// `NOTIFICATION_BATCH_SIZE` is written or none are. Notifications for
+    /// events a user turned off in-app delivery for are dropped, and not
+    /// counted.
+    pub async fn create_notifications(&self, dtos: Vec<CreateNotificationDto>) -> Result<usize, String> {
+        let mut created = 0;
+        let mut dtos = self.route_in_app(dtos).await;
+        self.render_messages(&mut dtos).await;
+        let mut dtos = dtos.into_iter().peekable();
+        while dtos.peek().is_some() {
+            let chunk: Vec<CreateNotificationDto> = dtos.by_ref().take(NOTIFICATION_BATCH_SIZE).collect();
+            let count = chunk.len();
+            self.db
+                .query(r#"
+                    BEGIN TRANSACTION;
+                    FOR $row IN $rows {
+                        CREATE notification SET
+                            user_id = $row.user_id,
+                            subscription_id = $row.subscription_id,
+                            message = $row.message,
+                            acknowledged = false,
+                            action_type = $row.action_type,
+                            action_payload = $row.action_payload,
+                            event = $row.event,
+                            created_at = time::now();
+                    };
+                    COMMIT TRANSACTION;
+                "#)
+                .bind(("rows", chunk))
+                .await
+                .and_then(|r| r.check())
+                .map_err(|e| format!("Failed to create {} notifications after {}: {}", count, created, e))?;
+            created += count;
+        }
+        Ok(created)
+    }
+
+    /// Re-renders templated messages in each user's language, with the
+    /// merchant's saved copy.
+    async fn render_messages(&self, dtos: &mut [CreateNotificationDto]) {
+        if dtos.iter().all(|dto| dto.template.is_none()) {
+            return;
+        }
+        let templates = TemplateRegistry::load(self).await;
+        let mut locales: BTreeMap<String, Locale> = BTreeMap::new();
+        for dto in dtos.iter_mut() {
+            if dto.template.is_none() {
+                continue;
+            }
+            let locale = match locales.get(&dto.user_id) {
+                Some(locale) => *locale,
+                None => {
+                    let locale = self.get_user(&dto.user_id).await
+                        .and_then(|user| user.locale)
+                        .and_then(|tag| Locale::from_tag(&tag))
+                        .unwrap_or(DEFAULT_LOCALE);
+                    locales.insert(dto.user_id.clone(), locale);
+                    locale
+                }
+            };
+            if let Some(message) = dto.template.as_ref().and_then(|t| t.render(&templates, locale)) {
+                dto.message = message;
+            }
+        }
+    }
+
+    /// The notifications whose users accept them in-app. If preferences
+    /// can't be loaded everything is delivered, since a missed renewal
+    /// notice costs more than an unwanted one.
+    async fn route_in_app(&self, dtos: Vec<CreateNotificationDto>) -> Vec<CreateNotificationDto> {
+        let mut user_ids: Vec<String> = dtos.iter()
+            .filter(|dto| dto.event.is_some())
+            .map(|dto| dto.user_id.clone())
+            .collect();
+        if user_ids.is_empty() {
+            return dtos;
+        }
+        user_ids.sort();
+        user_ids.dedup();
+
+        let saved = match self.get_saved_event_preferences(user_ids).await {
+            Ok(saved) => saved,
+            Err(e) => {
+                warn!("Delivering notifications without checking preferences: {}", e);
+                return dtos;
+            }
+        };
+
+        dtos.into_iter()
+            .filter(|dto| {
+                let Some(event) = dto.event else { return true };
+                let allowed = saved.iter()
+                    .find(|row| row.user_id == dto.user_id && row.event == event)
+                    .is_none_or(|row| row.preference().allows(NotificationChannel::InApp));
+                if !allowed {
+                    debug!("Skipping {:?} notification for {}: turned off in-app", event, dto.user_id);
+                }
+                allowed
+            })
+            .collect()
+    }
+
+    async fn get_saved_event_preferences(&self, user_ids: Vec<String>) -> Result<Vec<SavedEventPreference>, String> {
+        let result: Result<Vec<SavedEventPreference>, _> = self.db
+            .query("SELECT user_id, event, email, push, in_app, sms FROM notification_preferences WHERE user_id IN $user_ids")
+            .bind(("user_ids", user_ids))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Failed to load notification preferences: {}", e))
+    }
+
+    /// The user's channel choices, with defaults for events they haven't set.
+    pub async fn get_notification_preferences(&self, user_id: &str) -> Result<NotificationPreferences, String> {
+        let saved = self.get_saved_event_preferences(vec![user_id.to_string()]).await?;
+        Ok(NotificationPreferences::from_saved(
+            user_id.to_string(),
+            saved.iter().map(SavedEventPreference::preference).collect(),
+        ))
+    }
+
+    /// Saves the choices for the given events, leaving the others as they are.
+    pub async fn set_notification_preferences(
+        &self,
+        user_id: &str,
+        events: Vec<EventPreference>,
+    ) -> Result<NotificationPreferences, String> {
+        self.db
+            .query(r#"
+                BEGIN TRANSACTION;
+                FOR $pref IN $events {
+                    UPSERT type::thing('notification_preferences', [$user_id, $pref.event]) SET
+                        user_id = $user_id,
+                        event = $pref.event,
+                        email = $pref.email,
+                        push = $pref.push,
+                        in_app = $pref.in_app,
+                        sms = $pref.sms,
+                        updated_at = time::now();
+                };
+                COMMIT TRANSACTION;
+            "#)
+            .bind(("user_id", user_id.to_string()))
+            .bind(("events", events))
+            .await
+            .and_then(|r| r.check())
+            .map_err(|e| format!("Failed to save notification preferences for {}: {}", user_id, e))?;
+
+        self.get_notification_preferences(user_id).await
+    }
+
+    /// One page of the user's notifications, newest first, with the total
+    /// matching. `unread_only` leaves out acknowledged ones.
+    pub async fn get_user_notifications(
+        &self,
+        user_id: String,
+        unread_only: bool,
+        page: u32,
+        limit: u32,
+    ) -> Result<(Vec<crate::models::notification::Notification>, u64), String> {
+        let conditions = "user_id = $user_id AND ($unread_only = false OR acknowledged = false)";
+        let query = format!(
+            "SELECT * FROM notification WHERE {conditions} ORDER BY created_at DESC LIMIT $limit START $start; \
+             SELECT count() AS count FROM notification WHERE {conditions} GROUP ALL;",
+        );
+
+        let mut response = self.db
+            .query(query)
+            .bind(("user_id", user_id))
+            .bind(("unread_only", unread_only))
+            .bind(("limit", limit))
+            .bind(("start", (page.saturating_sub(1) as u64) * limit as u64))
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        let notifications: Vec<crate::models::notification::Notification> = response
+            .take(0)
+            .map_err(|e| format!("Failed to extract notifications: {}", e))?;
+        let counts: Vec<serde_json::Value> = response.take(1).map_err(|e| format!("Database error: {}", e))?;
+        // GROUP ALL returns no row when nothing matched
+        let total = counts.first().and_then(|row| row.get("count")).and_then(|v| v.as_u64()).unwrap_or(0);
+        Ok((notifications, total))
+    }
+
+    /// Notifications the user hasn't acknowledged, for the PWA badge.
+    pub async fn count_unread_notifications(&self, user_id: &str) -> Result<u64, String> {
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query("SELECT count() AS count FROM notification WHERE user_id = $user_id AND acknowledged = false GROUP ALL")
+            .bind(("user_id", user_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        let counts = result.map_err(|e| format!("Database error: {}", e))?;
+        Ok(counts.first().and_then(|row| row.get("count")).and_then(|v| v.as_u64()).unwrap_or(0))
+    }
+
+    /// Acknowledges all of the user's unread notifications and returns how
+    /// many there were.
+    pub async fn acknowledge_all_notifications(&self, user_id: &str) -> Result<usize, String> {
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query("UPDATE notification SET acknowledged = true WHERE user_id = $user_id AND acknowledged = false RETURN id")
+            .bind(("user_id", user_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        let acknowledged = result.map_err(|e| format!("Database error: {}", e))?.len();
+        info!("Acknowledged {} notifications for user {}", acknowledged, user_id);
+        Ok(acknowledged)
+    }
+
+    pub async fn acknowledge_notification(&self, notification_id: String) -> Result<(), String> {
+        let query = "UPDATE type::thing('notification', $notification_id) SET acknowledged = true";
+        
+        let notification_id_clone = notification_id.clone();
+        match self.db.query(query).bind(("notification_id", notification_id)).await {
+            Ok(_) => {
+                info!("Notification {} marked as acknowledged", notification_id_clone);
+                Ok(())
+            }
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    pub async fn create_test_notification(&self, user_id: String, message: String) -> Result<(), String> {
+        let notification_id = Uuid::new_v4().simple().to_string();
+        let query = format!(r#"
+            CREATE notification:{} SET
+                user_id = $user_id,
+                subscription_id = "test-subscription",
+                message = $message,
+                acknowledged = false,
+                created_at = time::now()
+        "#, notification_id);
+
+        self.db
+            .query(query)
+            .bind(("user_id", user_id.clone()))
+            .bind(("message", message.clone()))
+            .await
+            .map_err(|e| format!("Failed to create test notification: {}", e))?;
+        
+        info!("Test notification created for user {}: {}", user_id, message);
+        Ok(())
+    }
+
+    // ---------------------
+    // Activity feed operations
+    // ---------------------
+
+    /// Appends to the user's activity feed. Failures are logged, never returned,
+    /// so feed bookkeeping can't break the operation being recorded.
+    pub async fn record_activity(
+        &self,
+        user_id: &str,
+        category: ActivityCategory,
+        kind: &str,
+        description: String,
+        reference_id: Option<String>,
+    ) {
+        let result = self.db
+            .query(r#"
+                CREATE activity_events SET
+                    user_id = $user_id,
+                    category = $category,
+                    kind = $kind,
+                    description = $description,
+                    reference_id = $reference_id,
+                    created_at = time::now()
+            "#)
+            .bind(("user_id", user_id.to_string()))
+            .bind(("category", category))
+            .bind(("kind", kind.to_string()))
+            .bind(("description", description))
+            .bind(("reference_id", reference_id))
+            .await;
+
+        if let Err(e) = result {
+            error!("Failed to record activity {} for user {}: {}", kind, user_id, e);
+        }
+    }
+
+    /// A `users`, `subscriptions` or `payments` row as JSON, for the audit log.
+    async fn audit_snapshot(&self, table: &str, id: &str) -> Option<serde_json::Value> {
+        let prefix = format!("{}:", table);
+        let id_part = id.strip_prefix(&prefix).unwrap_or(id);
+
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing($table, $id)")
+            .bind(("table", table.to_string()))
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|rows| rows.into_iter().next())
+    }
+
+    async fn audit_payment_snapshot(&self, merchant_transaction_id: &str) -> Option<serde_json::Value> {
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM payments WHERE merchant_transaction_id = $merchant_id AND ($tenant = NONE OR tenant_id = $tenant) LIMIT 1")
+            .bind(("merchant_id", merchant_transaction_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|rows| rows.into_iter().next())
+    }
+
+    /// Appends to the audit log, attributed to the caller of the request
+    /// being handled (or `system` outside requests). Failures are logged,
+    /// never returned, like activity.
+    pub async fn record_audit(
+        &self,
+        action: &str,
+        entity_type: &str,
+        entity_id: &str,
+        old_value: Option<serde_json::Value>,
+        new_value: Option<serde_json::Value>,
+    ) {
+        let context = audit::current_context();
+        let result = self.db
+            .query(r#"
+                CREATE audit_log SET
+                    actor_type = $actor_type,
+                    actor_id = $actor_id,
+                    action = $action,
+                    entity_type = $entity_type,
+                    entity_id = $entity_id,
+                    old_value = $old_value,
+                    new_value = $new_value,
+                    ip_address = $ip_address,
+                    user_agent = $user_agent,
+                    request_id = $request_id,
+                    impersonation_session_id = $impersonation_session_id,
+                    created_at = time::now()
+            "#)
+            .bind(("actor_type", context.actor_type))
+            .bind(("actor_id", context.actor_id))
+            .bind(("action", action.to_string()))
+            .bind(("entity_type", entity_type.to_string()))
+            .bind(("entity_id", entity_id.to_string()))
+            .bind(("old_value", old_value))
+            .bind(("new_value", new_value))
+            .bind(("ip_address", context.ip_address))
+            .bind(("user_agent", context.user_agent))
+            .bind(("request_id", current_request_id()))
+            .bind(("impersonation_session_id", context.impersonation_session_id))
+            .await;
+
+        if let Err(e) = result {
+            error!("Failed to record audit entry {} for {}:{}: {}", action, entity_type, entity_id, e);
+        }
+    }
+
+    /// Audits a change to a `users` or `subscriptions` row, reading it again
+    /// for the new value. `before` is its `audit_snapshot` from before the change.
+    async fn audit_change(&self, action: &str, table: &str, id: &str, before: Option<serde_json::Value>) {
+        let prefix = format!("{}:", table);
+        let id_part = id.strip_prefix(&prefix).unwrap_or(id);
+        let after = self.audit_snapshot(table, id_part).await;
+        self.record_audit(action, table, id_part, before, after).await;
+    }
+
+    /// Like `audit_change`, for a payment known by its merchant transaction id.
+    async fn audit_payment_change(&self, action: &str, merchant_transaction_id: &str, before: Option<serde_json::Value>) {
+        let after = self.audit_payment_snapshot(merchant_transaction_id).await;
+        let payment_id = after.as_ref()
+            .or(before.as_ref())
+            .and_then(|payment| payment.get("id"))
+            .and_then(|id| id.as_str())
+            .unwrap_or(merchant_transaction_id)
+            .to_string();
+        self.record_audit(action, "payments", &payment_id, before, after).await;
+    }
+
+    /// One page of audit entries matching `filter`, newest first, with the
+    /// number that match across all pages.
+    pub async fn list_audit_entries(
+        &self,
+        filter: &AuditFilter,
+        page: u32,
+        limit: u32,
+    ) -> Result<(Vec<AuditEntry>, u64), String> {
+        let conditions = "($actor_type = NONE OR actor_type = $actor_type) AND ($actor_id = NONE OR actor_id = $actor_id) \
+            AND ($action = NONE OR action = $action) AND ($entity_type = NONE OR entity_type = $entity_type) \
+            AND ($entity_id = NONE OR entity_id = $entity_id) \
+            AND ($impersonation_session_id = NONE OR impersonation_session_id = $impersonation_session_id) AND ($from = NONE OR created_at >= $from) AND ($to = NONE OR created_at < $to)";
+        let query = format!(
+            "SELECT *, record::id(id) AS id FROM audit_log WHERE {conditions} ORDER BY created_at DESC LIMIT $limit START $start; \
+             SELECT count() AS count FROM audit_log WHERE {conditions} GROUP ALL;",
+        );
+
+        let mut response = self.db
+            .query(query)
+            .bind(("actor_type", filter.actor_type))
+            .bind(("actor_id", filter.actor_id.clone()))
+            .bind(("action", filter.action.clone()))
+            .bind(("entity_type", filter.entity_type.clone()))
+            .bind(("entity_id", filter.entity_id.clone()))
+            .bind(("impersonation_session_id", filter.impersonation_session_id.clone()))
+            .bind(("from", filter.from))
+            .bind(("to", filter.to))
+            .bind(("limit", limit))
+            .bind(("start", (page.saturating_sub(1) as u64) * limit as u64))
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        let entries: Vec<AuditEntry> = response.take(0).map_err(|e| format!("Database error: {}", e))?;
+        let counts: Vec<serde_json::Value> = response.take(1).map_err(|e| format!("Database error: {}", e))?;
+        // GROUP ALL returns no row when nothing matched
+        let total = counts.first().and_then(|row| row.get("count")).and_then(|v| v.as_u64()).unwrap_or(0);
+        Ok((entries, total))
+    }
+
+    // ---------------------
+    // API keys
+    // ---------------------
+
+    pub async fn create_api_key(
+        &self,
+        name: &str,
+        scopes: Vec<ApiKeyScope>,
+        tenant_id: Option<String>,
+        prefix: &str,
+        key_hash: &str,
+    ) -> Result<ApiKey, String> {
+        let key_id = Uuid::new_v4().simple().to_string();
+        self.db
+            .query(r#"
+                CREATE type::thing('api_keys', $id) SET
+                    name = $name,
+                    prefix = $prefix,
+                    scopes = $scopes,
+                    key_hash = $key_hash,
+                    tenant_id = $tenant_id,
+                    created_at = time::now()
+            "#)
+            .bind(("id", key_id.clone()))
+            .bind(("name", name.trim().to_string()))
+            .bind(("prefix", prefix.to_string()))
+            .bind(("scopes", scopes))
+            .bind(("tenant_id", tenant_id))
+            .bind(("key_hash", key_hash.to_string()))
+            .await
+            .and_then(|r| r.check())
+            .map_err(|e| format!("Failed to create API key: {}", e))?;
+
+        let api_key = self.get_api_key(&key_id).await
+            .ok_or_else(|| format!("API key {} missing after create", key_id))?;
+        info!("Created API key {} ({})", api_key.id, api_key.prefix);
+        self.record_audit("api_key.created", "api_keys", &api_key.id, None, serde_json::to_value(&api_key).ok()).await;
+        Ok(api_key)
+    }
+
+    pub async fn get_api_key(&self, key_id: &str) -> Option<ApiKey> {
+        let id_part = key_id.strip_prefix("api_keys:").unwrap_or(key_id);
+
+        let result: Result<Vec<ApiKey>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('api_keys', $id) WHERE ($tenant = NONE OR tenant_id = $tenant)")
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|keys| keys.into_iter().next())
+    }
+
+    /// Every key, revoked ones included, newest first.
+    pub async fn list_api_keys(&self) -> Result<Vec<ApiKey>, String> {
+        let result: Result<Vec<ApiKey>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM api_keys WHERE ($tenant = NONE OR tenant_id = $tenant) ORDER BY created_at DESC")
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// The unrevoked key whose current secret, or secret replaced within the
+    /// rotation overlap, hashes to `key_hash`.
+    pub async fn authenticate_api_key(&self, key_hash: &str) -> Option<ApiKey> {
+        let result: Result<Vec<ApiKey>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM api_keys WHERE revoked_at = NONE AND (key_hash = $key_hash OR (previous_key_hash = $key_hash AND previous_expires_at > time::now())) LIMIT 1")
+            .bind(("key_hash", key_hash.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|keys| keys.into_iter().next())
+    }
+
+    pub async fn touch_api_key(&self, key_id: &str) {
+        let result = self.db
+            .query("UPDATE type::thing('api_keys', $id) SET last_used_at = time::now() RETURN NONE")
+            .bind(("id", key_id.to_string()))
+            .await
+            .and_then(|response| response.check());
+
+        if let Err(e) = result {
+            error!("Failed to record use of API key {}: {}", key_id, e);
+        }
+    }
+
+    /// Gives a key a new secret. The old one keeps working until
+    /// `previous_expires_at`. Returns `None` if the key is revoked or missing.
+    pub async fn rotate_api_key(
+        &self,
+        key_id: &str,
+        prefix: &str,
+        key_hash: &str,
+        previous_expires_at: DateTime<Utc>,
+    ) -> Result<Option<ApiKey>, String> {
+        let id_part = key_id.strip_prefix("api_keys:").unwrap_or(key_id);
+        // Serialized through the model so the audit log never holds key hashes
+        let before = self.get_api_key(id_part).await.and_then(|key| serde_json::to_value(key).ok());
+
+        let rotated: Vec<serde_json::Value> = self.db
+            .query(r#"
+                UPDATE type::thing('api_keys', $id) SET
+                    previous_key_hash = key_hash,
+                    previous_expires_at = $previous_expires_at,
+                    key_hash = $key_hash,
+                    prefix = $prefix,
+                    rotated_at = time::now()
+                WHERE revoked_at = NONE AND ($tenant = NONE OR tenant_id = $tenant)
+                RETURN AFTER
+            "#)
+            .bind(("id", id_part.to_string()))
+            .bind(("key_hash", key_hash.to_string()))
+            .bind(("prefix", prefix.to_string()))
+            .bind(("previous_expires_at", previous_expires_at))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Database error: {}", e))?;
+        if rotated.is_empty() {
+            return Ok(None);
+        }
+
+        info!("Rotated API key {}", id_part);
+        let api_key = self.get_api_key(id_part).await;
+        self.record_audit("api_key.rotated", "api_keys", id_part, before, api_key.as_ref().and_then(|key| serde_json::to_value(key).ok())).await;
+        Ok(api_key)
+    }
+
+    /// Stops a key working, including any secret still in its rotation
+    /// overlap. Returns `None` if it was already revoked or doesn't exist.
+    pub async fn revoke_api_key(&self, key_id: &str) -> Result<Option<ApiKey>, String> {
+        let id_part = key_id.strip_prefix("api_keys:").unwrap_or(key_id);
+        let before = self.get_api_key(id_part).await.and_then(|key| serde_json::to_value(key).ok());
+
+        let revoked: Vec<serde_json::Value> = self.db
+            .query("UPDATE type::thing('api_keys', $id) SET revoked_at = time::now() WHERE revoked_at = NONE AND ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Database error: {}", e))?;
+        if revoked.is_empty() {
+            return Ok(None);
+        }
+
+        warn!("Revoked API key {}", id_part);
+        let api_key = self.get_api_key(id_part).await;
+        self.record_audit("api_key.revoked", "api_keys", id_part, before, api_key.as_ref().and_then(|key| serde_json::to_value(key).ok())).await;
+        Ok(api_key)
+    }
+
+    // ---------------------
+    // Tenants
+    // ---------------------
+
+    pub async fn create_tenant(&self, dto: CreateTenantDto) -> Result<Tenant, String> {
+        if self.get_tenant_by_subdomain(&dto.subdomain).await.is_some() {
+            return Err(format!("Subdomain {} is already taken", dto.subdomain));
+        }
+
+        let tenant_id = Uuid::new_v4().simple().to_string();
+        self.db
+            .query(r#"
+                CREATE type::thing('tenants', $id) SET
+                    name = $name,
+                    subdomain = $subdomain,
+                    peach = $peach,
+                    created_at = time::now(),
+                    updated_at = time::now()
+            "#)
+            .bind(("id", tenant_id.clone()))
+            .bind(("name", dto.name.trim().to_string()))
+            .bind(("subdomain", dto.subdomain.clone()))
+            .bind(("peach", dto.peach.as_ref().map(PeachCredentials::stored)))
+            .await
+            .and_then(|r| r.check())
+            .map_err(|e| format!("Failed to create tenant: {}", e))?;
+
+        let tenant = self.get_tenant(&tenant_id).await
+            .ok_or_else(|| format!("Tenant {} missing after create", tenant_id))?;
+        info!("Created tenant {} ({})", tenant.id, tenant.subdomain);
+        self.record_audit("tenant.created", "tenants", &tenant.id, None, serde_json::to_value(&tenant).ok()).await;
+        Ok(tenant)
+    }
+
+    pub async fn get_tenant(&self, tenant_id: &str) -> Option<Tenant> {
+        let id_part = tenant_id.strip_prefix("tenants:").unwrap_or(tenant_id);
+
+        let result: Result<Vec<Tenant>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('tenants', $id)")
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|tenants| tenants.into_iter().next())
+    }
+
+    pub async fn get_tenant_by_subdomain(&self, subdomain: &str) -> Option<Tenant> {
+        let result: Result<Vec<Tenant>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM tenants WHERE subdomain = $subdomain LIMIT 1")
+            .bind(("subdomain", subdomain.to_ascii_lowercase()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|tenants| tenants.into_iter().next())
+    }
+
+    /// Every tenant, disabled ones included, by subdomain.
+    pub async fn list_tenants(&self) -> Result<Vec<Tenant>, String> {
+        let result: Result<Vec<Tenant>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM tenants ORDER BY subdomain")
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Returns `None` if the tenant doesn't exist.
+    pub async fn update_tenant(&self, tenant_id: &str, dto: UpdateTenantDto) -> Result<Option<Tenant>, String> {
+        let id_part = tenant_id.strip_prefix("tenants:").unwrap_or(tenant_id);
+        let Some(before) = self.get_tenant(id_part).await else {
+            return Ok(None);
+        };
+
+        let now = Utc::now();
+        let disabled_at = match dto.disabled {
+            Some(true) => before.disabled_at.or(Some(now)),
+            Some(false) => None,
+            None => before.disabled_at,
+        };
+
+        self.db
+            .query(r#"
+                UPDATE type::thing('tenants', $id) SET
+                    name = $name ?? name,
+                    peach = $peach ?? peach,
+                    disabled_at = $disabled_at,
+                    updated_at = $now
+            "#)
+            .bind(("id", id_part.to_string()))
+            .bind(("name", dto.name.map(|name| name.trim().to_string())))
+            .bind(("peach", dto.peach.as_ref().map(PeachCredentials::stored)))
+            .bind(("disabled_at", disabled_at))
+            .bind(("now", now))
+            .await
+            .and_then(|r| r.check())
+            .map_err(|e| format!("Failed to update tenant: {}", e))?;
+
+        let tenant = self.get_tenant(id_part).await;
+        info!("Updated tenant {}", id_part);
+        self.record_audit("tenant.updated", "tenants", id_part, serde_json::to_value(&before).ok(), tenant.as_ref().and_then(|t| serde_json::to_value(t).ok())).await;
+        Ok(tenant)
+    }
+
+    // ---------------------
+    // Impersonation
+    // ---------------------
+
+    /// Opens a session for support to act as `user_id` until `expires_at`,
+    /// and audits that it was opened.
+    pub async fn create_impersonation_session(
+        &self,
+        user_id: &str,
+        reason: &str,
+        scope: ImpersonationScope,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<ImpersonationSession, String> {
+        let session_id = Uuid::new_v4().simple().to_string();
+        self.db
+            .query(r#"
+                CREATE type::thing('impersonation_sessions', $id) SET
+                    user_id = $user_id,
+                    reason = $reason,
+                    scope = $scope,
+                    token_hash = $token_hash,
+                    expires_at = $expires_at,
+                    created_at = time::now()
+            "#)
+            .bind(("id", session_id.clone()))
+            .bind(("user_id", user_id.to_string()))
+            .bind(("reason", reason.trim().to_string()))
+            .bind(("scope", scope))
+            .bind(("token_hash", token_hash.to_string()))
+            .bind(("expires_at", expires_at))
+            .await
+            .and_then(|r| r.check())
+            .map_err(|e| format!("Failed to create impersonation session: {}", e))?;
+
+        let session = self.get_impersonation_session(&session_id).await
+            .ok_or_else(|| format!("Impersonation session {} missing after create", session_id))?;
+        warn!("Impersonation session {} opened for user {} until {}", session.id, user_id, expires_at);
+        self.record_audit("user.impersonation_started", "users", user_id, None, serde_json::to_value(&session).ok()).await;
+        Ok(session)
+    }
+
+    pub async fn get_impersonation_session(&self, session_id: &str) -> Option<ImpersonationSession> {
+        let id_part = session_id.strip_prefix("impersonation_sessions:").unwrap_or(session_id);
+
+        let result: Result<Vec<ImpersonationSession>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('impersonation_sessions', $id)")
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|sessions| sessions.into_iter().next())
+    }
+
+    pub async fn get_impersonation_session_by_token(&self, token_hash: &str) -> Option<ImpersonationSession> {
+        let result: Result<Vec<ImpersonationSession>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM impersonation_sessions WHERE token_hash = $token_hash LIMIT 1")
+            .bind(("token_hash", token_hash.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|sessions| sessions.into_iter().next())
+    }
+
+    /// Ends a session before it expires. Returns `None` if it was already
+    /// revoked or doesn't exist.
+    pub async fn revoke_impersonation_session(&self, session_id: &str) -> Result<Option<ImpersonationSession>, String> {
+        let id_part = session_id.strip_prefix("impersonation_sessions:").unwrap_or(session_id);
+
+        let revoked: Vec<serde_json::Value> = self.db
+            .query("UPDATE type::thing('impersonation_sessions', $id) SET revoked_at = time::now() WHERE revoked_at = NONE RETURN AFTER")
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Database error: {}", e))?;
+        if revoked.is_empty() {
+            return Ok(None);
+        }
+
+        let session = self.get_impersonation_session(id_part).await
+            .ok_or_else(|| format!("Impersonation session {} missing after update", id_part))?;
+        info!("Impersonation session {} revoked", session.id);
+        self.record_audit("user.impersonation_ended", "users", &session.user_id, None, serde_json::to_value(&session).ok()).await;
+        Ok(Some(session))
+    }
+
+    async fn record_subscription_activity(&self, subscription: &Subscription, kind: &str, description: &str) {
+        self.record_activity(
+            &subscription.user_id,
+            ActivityCategory::Subscription,
+            kind,
+            format!("{} ({} plan)", description, subscription.plan_name),
+            Some(subscription.id.clone()),
+        ).await;
+    }
+
+    pub async fn get_activity_events(
+        &self,
+        user_id: &str,
+        category: Option<ActivityCategory>,
+        limit: u32,
+    ) -> Result<Vec<ActivityEvent>, String> {
+        let query = match category {
+            Some(_) => "SELECT * FROM activity_events WHERE user_id = $user_id AND category = $category ORDER BY created_at DESC LIMIT $limit",
+            None => "SELECT * FROM activity_events WHERE user_id = $user_id ORDER BY created_at DESC LIMIT $limit",
+        };
+
+        let result: Result<Vec<ActivityEvent>, _> = self.db
+            .query(query)
+            .bind(("user_id", user_id.to_string()))
+            .bind(("category", category))
+            .bind(("limit", limit))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn get_recent_payments_by_user(&self, user_id: &str, limit: u32) -> Result<Vec<Payment>, String> {
+        let result: Result<Vec<Payment>, _> = self.db
+            .query("SELECT * FROM payments WHERE user_id = $user_id AND ($tenant = NONE OR tenant_id = $tenant) ORDER BY created_at DESC LIMIT $limit")
+            .bind(("user_id", user_id.to_string()))
+            .bind(("limit", limit))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Whether a `new_device_login` event already exists for this device fingerprint.
+    pub async fn has_known_device(&self, user_id: &str, device_hash: &str) -> bool {
+        let result: Result<Vec<ActivityEvent>, _> = self.db
+            .query("SELECT * FROM activity_events WHERE user_id = $user_id AND kind = 'new_device_login' AND reference_id = $device LIMIT 1")
+            .bind(("user_id", user_id.to_string()))
+            .bind(("device", device_hash.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map(|events| !events.is_empty()).unwrap_or(false)
+    }
+
+    // ---------------------
+    // Refund operations
+    // ---------------------
+
+    pub async fn create_refund(
+        &self,
+        payment: &Payment,
+        amount: f64,
+        reason: Option<String>,
+    ) -> Result<Refund, String> {
+        let refund_id = Uuid::new_v4().simple().to_string();
+        let refund_transaction_id = format!(
+            "RFD_{}",
+            Uuid::new_v4().simple().to_string().to_uppercase().get(..16).unwrap_or("0000000000000000")
+        );
+        let now = Utc::now();
+
+        let refund = Refund {
+            id: refund_id.clone(),
+            payment_id: payment.id.clone(),
+            payment_merchant_transaction_id: payment.merchant_transaction_id.clone(),
+            refund_transaction_id,
+            amount,
+            currency: payment.currency.clone(),
+            reason,
+            status: RefundStatus::Pending,
+            peach_refund_id: None,
+            result_code: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        // The sum and the insert run in one transaction. Touching the payment
+        // makes concurrent refunds of it conflict, so one of them fails rather
+        // than both spending the same balance.
+        let query = r#"
+            BEGIN TRANSACTION;
+            LET $refunded = math::sum(SELECT VALUE amount FROM refunds WHERE payment_merchant_transaction_id = $payment_merchant_transaction_id AND status != 'Failed');
+            IF $refunded + $amount > $payment_amount + 0.005 {
+                THROW "Refund exceeds the refundable balance";
+            };
+            UPDATE type::thing('payments', $payment_key) SET updated_at = $updated_at RETURN NONE;
+            CREATE type::thing('refunds', $id) SET
+                payment_id = $payment_id,
+                payment_merchant_transaction_id = $payment_merchant_transaction_id,
+                refund_transaction_id = $refund_transaction_id,
+                amount = $amount,
+                currency = $currency,
+                reason = $reason,
+                status = $status,
+                created_at = $created_at,
+                updated_at = $updated_at
+                RETURN NONE;
+            COMMIT TRANSACTION;
+        "#;
+
+        self.db
+            .query(query)
+            .bind(("id", refund_id))
+            .bind(("payment_key", payment.id.strip_prefix("payments:").unwrap_or(&payment.id).to_string()))
+            .bind(("payment_amount", payment.amount))
+            .bind(("payment_id", refund.payment_id.clone()))
+            .bind(("payment_merchant_transaction_id", refund.payment_merchant_transaction_id.clone()))
+            .bind(("refund_transaction_id", refund.refund_transaction_id.clone()))
+            .bind(("amount", refund.amount))
+            .bind(("currency", refund.currency.clone()))
+            .bind(("reason", refund.reason.clone()))
+            .bind(("status", format!("{:?}", refund.status)))
+            .bind(("created_at", refund.created_at))
+            .bind(("updated_at", refund.updated_at))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| {
+                let e = e.to_string();
+                if e.contains("Refund exceeds the refundable balance") {
+                    "Refund exceeds the refundable balance".to_string()
+                } else {
+                    format!("Failed to create refund: {}", e)
+                }
+            })?;
+
+        info!("Created refund {} for payment {} (amount {})", refund.refund_transaction_id, payment.merchant_transaction_id, amount);
+        Ok(refund)
+    }
+
+    pub async fn get_refunds_for_payment(&self, merchant_transaction_id: &str) -> Vec<Refund> {
+        let result: Result<Vec<Refund>, _> = self.db
+            .query("SELECT * FROM refunds WHERE payment_merchant_transaction_id = $merchant_id ORDER BY created_at ASC")
+            .bind(("merchant_id", merchant_transaction_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.unwrap_or_default()
+    }
+
+    pub async fn get_refund_by_transaction_id(&self, refund_transaction_id: &str) -> Option<Refund> {
+        let result: Result<Vec<Refund>, _> = self.db
+            .query("SELECT * FROM refunds WHERE refund_transaction_id = $txn_id LIMIT 1")
+            .bind(("txn_id", refund_transaction_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|refunds| refunds.into_iter().next())
+    }
+
+    pub async fn update_refund_status(
+        &self,
+        refund_transaction_id: &str,
+        status: &RefundStatus,
+        result_code: Option<String>,
+        peach_refund_id: Option<String>,
+    ) -> Result<(), String> {
+        let result: Result<Vec<Refund>, _> = self.db
+            .query("UPDATE refunds SET status = $status, result_code = $result_code ?? result_code, peach_refund_id = $peach_refund_id ?? peach_refund_id, updated_at = $now WHERE refund_transaction_id = $txn_id RETURN AFTER")
+            .bind(("status", format!("{:?}", status)))
+            .bind(("result_code", result_code))
+            .bind(("peach_refund_id", peach_refund_id))
+            .bind(("now", Utc::now()))
+            .bind(("txn_id", refund_transaction_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(refunds) if !refunds.is_empty() => {
+                info!("Updated refund status: {:?} (RefundTxnId: {})", status, refund_transaction_id);
+                Ok(())
+            }
+            Ok(_) => Err(format!("Refund not found: {}", refund_transaction_id)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    // ---------------------
+    // Card update operations
+    // ---------------------
+
+    pub async fn create_card_update(&self, user_id: &str, subscription_id: &str, gateway: &str) -> Result<CardUpdate, String> {
+        let card_update_id = Uuid::new_v4().simple().to_string();
+        let now = Utc::now();
+        let card_update = CardUpdate {
+            id: card_update_id.clone(),
+            user_id: user_id.to_string(),
+            subscription_id: subscription_id.to_string(),
+            merchant_transaction_id: format!(
+                "{}{}",
+                CARD_UPDATE_PREFIX,
+                Uuid::new_v4().simple().to_string().to_uppercase().get(..16).unwrap_or("0000000000000000")
+            ),
+            gateway: gateway.to_string(),
+            status: CardUpdateStatus::Pending,
+            result_code: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let query = format!(r#"
+            CREATE card_updates:{} SET
+                user_id = $user_id,
+                subscription_id = $subscription_id,
+                merchant_transaction_id = $merchant_transaction_id,
+                gateway = $gateway,
+                status = $status,
+                created_at = $created_at,
+                updated_at = $updated_at
+        "#, card_update_id);
+
+        self.db
+            .query(query)
+            .bind(("user_id", card_update.user_id.clone()))
+            .bind(("subscription_id", card_update.subscription_id.clone()))
+            .bind(("merchant_transaction_id", card_update.merchant_transaction_id.clone()))
+            .bind(("gateway", card_update.gateway.clone()))
+            .bind(("status", format!("{:?}", card_update.status)))
+            .bind(("created_at", card_update.created_at))
+            .bind(("updated_at", card_update.updated_at))
+            .await
+            .map_err(|e| format!("Failed to create card update: {}", e))?;
+
+        info!("Created card update {} for user {}", card_update.merchant_transaction_id, user_id);
+        Ok(card_update)
+    }
+
+    pub async fn get_card_update(&self, merchant_transaction_id: &str) -> Option<CardUpdate> {
+        let result: Result<Vec<CardUpdate>, _> = self.db
+            .query("SELECT * FROM card_updates WHERE merchant_transaction_id = $merchant_id LIMIT 1")
+            .bind(("merchant_id", merchant_transaction_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|updates| updates.into_iter().next())
+    }
+
+    /// Swaps in the verified card in one transaction: the user's active cards
+    /// are marked `Replaced`, the new one becomes active and the card update
+    /// is completed. Renewals never see the user without a card.
+    pub async fn complete_card_update(
+        &self,
+        card_update: &CardUpdate,
+        token: &str,
+        card_last_four: Option<String>,
+        card_brand: Option<String>,
+        card_expiry: Option<CardExpiry>,
+        result_code: &str,
+    ) -> Result<(), String> {
+        let query = r#"
+            BEGIN TRANSACTION;
+            UPDATE recurring_payments SET status = 'Replaced', updated_at = $now
+                WHERE user_id = $user_id AND status = 'Active';
+            CREATE recurring_payments SET
+                user_id = $user_id,
+                subscription_id = $subscription_id,
+                recurring_token = $token,
+                card_last_four = $card_last_four,
+                card_brand = $card_brand,
+                expiry_month = $expiry_month,
+                expiry_year = $expiry_year,
+                status = 'Active',
+                created_at = $now,
+                updated_at = $now;
+            UPDATE subscriptions SET at_risk_reason = NONE, updated_at = $now
+                WHERE user_id = $user_id AND at_risk_reason = $card_expiring AND ($tenant = NONE OR tenant_id = $tenant);
+            UPDATE card_updates SET status = 'Completed', result_code = $result_code, updated_at = $now
+                WHERE merchant_transaction_id = $merchant_id;
+            COMMIT TRANSACTION;
+        "#;
+
+        self.db
+            .query(query)
+            .bind(("user_id", card_update.user_id.clone()))
+            .bind(("subscription_id", card_update.subscription_id.clone()))
+            .bind(("token", token.to_string()))
+            .bind(("card_last_four", card_last_four.clone()))
+            .bind(("card_brand", card_brand.clone()))
+            .bind(("expiry_month", card_expiry.map(|e| e.month)))
+            .bind(("expiry_year", card_expiry.map(|e| e.year)))
+            .bind(("card_expiring", state_reason::CARD_EXPIRING))
+            .bind(("result_code", result_code.to_string()))
+            .bind(("merchant_id", card_update.merchant_transaction_id.clone()))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| format!("Failed to swap recurring token: {}", e))?;
+
+        let card_label = match (&card_brand, &card_last_four) {
+            (Some(brand), Some(last4)) => format!("{} ending in {}", brand, last4),
+            (None, Some(last4)) => format!("Card ending in {}", last4),
+            _ => "A new card".to_string(),
+        };
+        self.record_activity(
+            &card_update.user_id,
+            ActivityCategory::Security,
+            "card_replaced",
+            format!("{} replaced your saved card for automatic renewals", card_label),
+            Some(card_update.subscription_id.clone()),
+        ).await;
+
+        info!("Card update {} completed; recurring token swapped", card_update.merchant_transaction_id);
+        Ok(())
+    }
+
+    /// Marks a card update failed; the user's current card is left untouched.
+    pub async fn fail_card_update(&self, merchant_transaction_id: &str, result_code: &str) -> Result<(), String> {
+        self.db
+            .query("UPDATE card_updates SET status = 'Failed', result_code = $result_code, updated_at = $now WHERE merchant_transaction_id = $merchant_id")
+            .bind(("result_code", result_code.to_string()))
+            .bind(("now", Utc::now()))
+            .bind(("merchant_id", merchant_transaction_id.to_string()))
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        warn!("Card update {} failed: {}", merchant_transaction_id, result_code);
+        Ok(())
+    }
+
+    // ---------------------
+    // Webhook event operations
+    // ---------------------
+
+    /// Stores the raw webhook body as soon as it arrives and returns the event id.
+    pub async fn create_webhook_event(&self, raw_body: &str, gateway: &str) -> Result<String, String> {
+        let event_id = Uuid::new_v4().simple().to_string();
+        let query = format!(r#"
+            CREATE webhook_events:{} SET
+                raw_body = $raw_body,
+                gateway = $gateway,
+                outcome = 'Received',
+                replay_count = 0,
+                created_at = time::now(),
+                updated_at = time::now()
+        "#, event_id);
+
+        self.db
+            .query(query)
+            .bind(("raw_body", raw_body.to_string()))
+            .bind(("gateway", gateway.to_string()))
+            .await
+            .map_err(|e| format!("Failed to create webhook event: {}", e))?;
+
+        Ok(event_id)
+    }
+
+    pub async fn update_webhook_event(
+        &self,
+        event_id: &str,
+        outcome: WebhookOutcome,
+        update: WebhookEventUpdate,
+    ) -> Result<(), String> {
+        let id_part = event_id.strip_prefix("webhook_events:").unwrap_or(event_id);
+
+        self.db
+            .query(r#"
+                UPDATE type::thing('webhook_events', $id) SET
+                    signature = $signature ?? signature,
+                    parsed = $parsed ?? parsed,
+                    result_code = $result_code ?? result_code,
+                    merchant_transaction_id = $merchant_transaction_id ?? merchant_transaction_id,
+                    event_timestamp = $event_timestamp ?? event_timestamp,
+                    outcome = $outcome,
+                    error = $error,
+                    updated_at = time::now()
+            "#)
+            .bind(("id", id_part.to_string()))
+            .bind(("signature", update.signature))
+            .bind(("parsed", update.parsed))
+            .bind(("result_code", update.result_code))
+            .bind(("merchant_transaction_id", update.merchant_transaction_id))
+            .bind(("event_timestamp", update.event_timestamp))
+            .bind(("outcome", format!("{:?}", outcome)))
+            .bind(("error", update.error))
+            .await
+            .map_err(|e| format!("Failed to update webhook event: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Notes that `gateway` just delivered a webhook that was processed.
+    pub async fn record_webhook_heartbeat(&self, gateway: &str) -> Result<(), String> {
+        self.db
+            .query("UPSERT type::thing('webhook_heartbeats', $gateway) SET gateway = $gateway, last_processed_at = time::now()")
+            .bind(("gateway", gateway.to_string()))
+            .await
+            .and_then(|response| response.check())
+            .map(|_| ())
+            .map_err(|e| format!("Failed to record webhook heartbeat: {}", e))
+    }
+
+    /// When `gateway` last delivered a webhook that was processed, if ever.
+    pub async fn last_webhook_processed_at(&self, gateway: &str) -> Result<Option<DateTime<Utc>>, String> {
+        let result: Result<Vec<DateTime<Utc>>, _> = self.db
+            .query("SELECT VALUE last_processed_at FROM type::thing('webhook_heartbeats', $gateway)")
+            .bind(("gateway", gateway.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result
+            .map(|times| times.into_iter().next())
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Counts one verified webhook carrying `code`.
+    pub async fn record_webhook_result_code(&self, code: &str, category: ResultCodeCategory) -> Result<(), String> {
+        let query = r#"
+            UPSERT type::thing('webhook_result_codes', $code) SET
+                code = $code,
+                category = $category,
+                count = (count ?? 0) + 1,
+                first_seen_at = first_seen_at ?? time::now(),
+                last_seen_at = time::now()
+            RETURN NONE
+        "#;
+
+        self.db
+            .query(query)
+            .bind(("code", code.to_string()))
+            .bind(("category", format!("{:?}", category)))
+            .await
+            .and_then(|response| response.check())
+            .map(|_| ())
+            .map_err(|e| format!("Failed to record webhook result code {}: {}", code, e))
+    }
+
+    /// Webhook counts per result code category, plus the `limit` unhandled
+    /// codes seen most recently.
+    pub async fn get_webhook_code_metrics(&self, limit: u32) -> Result<WebhookCodeMetrics, String> {
+        let query = r#"
+            SELECT category, math::sum(count) AS count, count() AS codes FROM webhook_result_codes GROUP BY category;
+            SELECT code, category, count, first_seen_at, last_seen_at FROM webhook_result_codes
+                WHERE category = 'Unhandled' ORDER BY last_seen_at DESC LIMIT $limit;
+        "#;
+
+        let mut response = self.db
+            .query(query)
+            .bind(("limit", limit))
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        let rows: Vec<serde_json::Value> = response.take(0).map_err(|e| format!("Database error: {}", e))?;
+        let recent_unhandled: Vec<ResultCodeStat> = response.take(1).map_err(|e| format!("Database error: {}", e))?;
+
+        let mut counts = ResultCodeCounts::default();
+        let mut unhandled_codes = 0;
+        for row in rows {
+            let count = row.get("count").and_then(|v| v.as_u64()).unwrap_or(0);
+            match row.get("category").and_then(|v| v.as_str()).unwrap_or_default() {
+                "Success" => counts.success += count,
+                "Pending" => counts.pending += count,
+                "Cancelled" => counts.cancelled += count,
+                "Declined" => counts.declined += count,
+                _ => {
+                    counts.unhandled += count;
+                    unhandled_codes += row.get("codes").and_then(|v| v.as_u64()).unwrap_or(0);
+                }
+            }
+        }
+
+        Ok(WebhookCodeMetrics { counts, unhandled_codes, recent_unhandled })
+    }
+
+    pub async fn increment_webhook_replay_count(&self, event_id: &str) -> Result<(), String> {
+        let id_part = event_id.strip_prefix("webhook_events:").unwrap_or(event_id);
+
+        self.db
+            .query("UPDATE type::thing('webhook_events', $id) SET replay_count += 1, updated_at = time::now()")
+            .bind(("id", id_part.to_string()))
+            .await
+            .map_err(|e| format!("Failed to update webhook event: {}", e))?;
+
+        Ok(())
+    }
+
+    pub async fn get_webhook_event(&self, event_id: &str) -> Option<WebhookEvent> {
+        let id_part = event_id.strip_prefix("webhook_events:").unwrap_or(event_id);
+
+        let result: Result<Option<WebhookEvent>, _> = self.db
+            .select(("webhook_events", id_part))
+            .await;
+
+        result.ok().flatten()
+    }
+
+    pub async fn list_webhook_events(
+        &self,
+        outcome: Option<WebhookOutcome>,
+        limit: u32,
+    ) -> Result<Vec<WebhookEvent>, String> {
+        let query = match outcome {
+            Some(_) => "SELECT * FROM webhook_events WHERE outcome = $outcome ORDER BY created_at DESC LIMIT $limit",
+            None => "SELECT * FROM webhook_events ORDER BY created_at DESC LIMIT $limit",
+        };
+
+        let result: Result<Vec<WebhookEvent>, _> = self.db
+            .query(query)
+            .bind(("outcome", outcome.map(|o| format!("{:?}", o))))
+            .bind(("limit", limit))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    // ---------------------
+    // Plans
+    // ---------------------
+
+    pub async fn list_plans(&self) -> Result<Vec<Plan>, String> {
+        let result: Result<Vec<Plan>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM plans ORDER BY price ASC")
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn get_plan(&self, plan_id: &str) -> Option<Plan> {
+        let id_part = plan_id.strip_prefix("plans:").unwrap_or(plan_id);
+
+        let result: Result<Vec<Plan>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('plans', $id)")
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|plans| plans.into_iter().next())
+    }
+
+    /// Card statement text for charges on `plan_id`: the plan's own
+    /// descriptor, else `fallback` (the account-wide `STATEMENT_DESCRIPTOR`).
+    pub async fn statement_descriptor(&self, plan_id: Option<&str>, fallback: Option<&str>) -> Option<String> {
+        let plan = match plan_id {
+            Some(plan_id) => self.get_plan(plan_id).await,
+            None => None,
+        };
+        plan.and_then(|plan| plan.statement_descriptor)
+            .or_else(|| fallback.map(str::to_string))
+    }
+
+    pub async fn create_plan(&self, plan_id: &str, dto: CreatePlanDto) -> Result<Plan, String> {
+        if self.get_plan(plan_id).await.is_some() {
+            return Err(format!("Plan already exists: {}", plan_id));
+        }
+
+        let now = Utc::now();
+        self.db
+            .query("CREATE type::thing('plans', $id) SET name = $name, price = $price, prices_include_vat = $prices_include_vat, currency = $currency, interval = $interval, interval_days = $interval_days, trial_days = $trial_days, grace_period_days = $grace_period_days, suspension_policy = $suspension_policy, statement_descriptor = $statement_descriptor, usage_pricing = $usage_pricing, max_skips_per_year = $max_skips_per_year, created_at = $now, updated_at = $now")
+            .bind(("id", plan_id.to_string()))
+            .bind(("name", dto.name.trim().to_string()))
+            .bind(("price", dto.price))
+            .bind(("prices_include_vat", dto.prices_include_vat))
+            .bind(("currency", dto.currency.to_uppercase()))
+            .bind(("interval", format!("{:?}", dto.interval)))
+            .bind(("interval_days", dto.interval_days))
+            .bind(("trial_days", dto.trial_days))
+            .bind(("grace_period_days", dto.grace_period_days))
+            .bind(("suspension_policy", dto.suspension_policy))
+            .bind(("statement_descriptor", dto.statement_descriptor))
+            .bind(("usage_pricing", dto.usage_pricing))
+            .bind(("max_skips_per_year", dto.max_skips_per_year))
+            .bind(("now", now))
+            .await
+            .map_err(|e| format!("Failed to create plan: {}", e))?;
+
+        info!("Created plan {}", plan_id);
+        self.get_plan(plan_id).await.ok_or_else(|| format!("Plan {} missing after create", plan_id))
+    }
+
+    pub async fn update_plan(&self, plan_id: &str, dto: UpdatePlanDto) -> Result<Plan, String> {
+        let id_part = plan_id.strip_prefix("plans:").unwrap_or(plan_id);
+        let mut changes = serde_json::to_value(&dto).map_err(|e| format!("Invalid plan update: {}", e))?;
+        if let Some(currency) = changes.get_mut("currency") {
+            *currency = serde_json::Value::String(currency.as_str().unwrap_or_default().to_uppercase());
+        }
+
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query("UPDATE type::thing('plans', $id) MERGE $changes RETURN NONE; UPDATE type::thing('plans', $id) SET interval_days = IF interval = 'Custom' THEN interval_days ELSE NONE END, updated_at = $now RETURN AFTER;")
+            .bind(("id", id_part.to_string()))
+            .bind(("changes", changes))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|mut response| response.take(1));
+
+        match result {
+            Ok(updated) if !updated.is_empty() => {
+                info!("Updated plan {}", id_part);
+                self.get_plan(id_part).await.ok_or_else(|| format!("Plan {} missing after update", id_part))
+            }
+            Ok(_) => Err(format!("Plan not found: {}", id_part)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    /// Existing subscriptions keep their snapshot of the plan's price and terms.
+    pub async fn delete_plan(&self, plan_id: &str) -> Result<(), String> {
+        let id_part = plan_id.strip_prefix("plans:").unwrap_or(plan_id);
+
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query("DELETE type::thing('plans', $id) RETURN BEFORE; DELETE plan_features WHERE plan_id = $id;")
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(deleted) if !deleted.is_empty() => {
+                info!("Deleted plan {}", id_part);
+                Ok(())
+            }
+            Ok(_) => Err(format!("Plan not found: {}", id_part)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    // ---------------------
+    // Attachments
+    // ---------------------
+
+    pub async fn create_attachment(&self, attachment: &Attachment) -> Result<Attachment, String> {
+        self.db
+            .query("CREATE type::thing('attachments', $id) SET owner_type = $owner_type, owner_id = $owner_id, filename = $filename, content_type = $content_type, size_bytes = $size_bytes, sha256 = $sha256, storage_key = $storage_key, created_at = $created_at")
+            .bind(("id", attachment.id.clone()))
+            .bind(("owner_type", attachment.owner_type))
+            .bind(("owner_id", attachment.owner_id.clone()))
+            .bind(("filename", attachment.filename.clone()))
+            .bind(("content_type", attachment.content_type.clone()))
+            .bind(("size_bytes", attachment.size_bytes))
+            .bind(("sha256", attachment.sha256.clone()))
+            .bind(("storage_key", attachment.storage_key.clone()))
+            .bind(("created_at", attachment.created_at))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| format!("Failed to create attachment: {}", e))?;
+
+        info!("Stored attachment {} ({}, {} bytes)", attachment.id, attachment.content_type, attachment.size_bytes);
+        self.get_attachment(&attachment.id).await
+            .ok_or_else(|| format!("Attachment {} missing after create", attachment.id))
+    }
+
+    pub async fn get_attachment(&self, attachment_id: &str) -> Option<Attachment> {
+        let id_part = attachment_id.strip_prefix("attachments:").unwrap_or(attachment_id);
+
+        let result: Result<Vec<Attachment>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('attachments', $id)")
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|attachments| attachments.into_iter().next())
+    }
+
+    pub async fn list_attachments(&self, owner_type: AttachmentOwner, owner_id: &str) -> Result<Vec<Attachment>, String> {
+        let result: Result<Vec<Attachment>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM attachments WHERE owner_type = $owner_type AND owner_id = $owner_id ORDER BY created_at")
+            .bind(("owner_type", owner_type))
+            .bind(("owner_id", owner_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Links an unlinked upload to its owner. Fails if it already belongs to
+    /// something, so one file can't be attached as proof twice.
+    pub async fn link_attachment(&self, attachment_id: &str, owner_type: AttachmentOwner, owner_id: &str) -> Result<(), String> {
+        let id_part = attachment_id.strip_prefix("attachments:").unwrap_or(attachment_id);
+
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query("UPDATE type::thing('attachments', $id) SET owner_type = $owner_type, owner_id = $owner_id WHERE owner_id IS NONE RETURN AFTER")
+            .bind(("id", id_part.to_string()))
+            .bind(("owner_type", owner_type))
+            .bind(("owner_id", owner_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(updated) if !updated.is_empty() => Ok(()),
+            Ok(_) => Err(format!("Attachment {} not found or already linked", id_part)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    // ---------------------
+    // Plan features
+    // ---------------------
+
+    pub async fn get_plan_features(&self, plan_ids: &[String]) -> Result<Vec<PlanFeature>, String> {
+        let result: Result<Vec<PlanFeature>, _> = self.db
+            .query("SELECT * OMIT id FROM plan_features WHERE plan_id IN $plan_ids ORDER BY plan_id, feature_key")
+            .bind(("plan_ids", plan_ids.to_vec()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Creates or replaces one cell of the plan/feature matrix.
+    pub async fn set_plan_feature(&self, plan_id: &str, feature_key: &str, limit: Option<u64>) -> Result<PlanFeature, String> {
+        let result: Result<Vec<PlanFeature>, _> = self.db
+            .query("UPSERT type::thing('plan_features', [$plan_id, $feature_key]) SET plan_id = $plan_id, feature_key = $feature_key, limit = $limit, created_at = created_at ?? $now, updated_at = $now RETURN AFTER")
+            .bind(("plan_id", plan_id.to_string()))
+            .bind(("feature_key", feature_key.to_string()))
+            .bind(("limit", limit))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(features) => {
+                info!("Set feature {} on plan {} to {:?}", feature_key, plan_id, limit);
+                features.into_iter().next().ok_or_else(|| format!("Feature {} missing after upsert", feature_key))
+            }
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    pub async fn delete_plan_feature(&self, plan_id: &str, feature_key: &str) -> Result<(), String> {
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query("DELETE type::thing('plan_features', [$plan_id, $feature_key]) RETURN BEFORE")
+            .bind(("plan_id", plan_id.to_string()))
+            .bind(("feature_key", feature_key.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(deleted) if !deleted.is_empty() => {
+                info!("Removed feature {} from plan {}", feature_key, plan_id);
+                Ok(())
+            }
+            Ok(_) => Err(format!("Feature not found: {} on plan {}", feature_key, plan_id)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    // ---------------------
+    // Support notes & tags
+    // ---------------------
+
+    pub async fn create_support_note(
+        &self,
+        target_type: NoteTarget,
+        target_id: &str,
+        user_id: &str,
+        body: &str,
+        author: Option<String>,
+    ) -> Result<SupportNote, String> {
+        let note_id = Uuid::new_v4().simple().to_string();
+
+        self.db
+            .query(r#"
+                CREATE type::thing('support_notes', $id) SET
+                    target_type = $target_type,
+                    target_id = $target_id,
+                    user_id = $user_id,
+                    body = $body,
+                    author = $author,
+                    created_at = time::now(),
+                    updated_at = time::now()
+            "#)
+            .bind(("id", note_id.clone()))
+            .bind(("target_type", target_type))
+            .bind(("target_id", target_id.to_string()))
+            .bind(("user_id", user_id.to_string()))
+            .bind(("body", body.trim().to_string()))
+            .bind(("author", author))
+            .await
+            .map_err(|e| format!("Failed to create note: {}", e))?;
+
+        info!("Added support note {} on {}", note_id, target_id);
+        self.get_support_note(&note_id).await.ok_or_else(|| format!("Note {} missing after create", note_id))
+    }
+
+    pub async fn get_support_note(&self, note_id: &str) -> Option<SupportNote> {
+        let id_part = note_id.strip_prefix("support_notes:").unwrap_or(note_id);
+
+        let result: Result<Vec<SupportNote>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('support_notes', $id)")
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|notes| notes.into_iter().next())
+    }
+
+    pub async fn get_support_notes(&self, target_type: NoteTarget, target_id: &str) -> Result<Vec<SupportNote>, String> {
+        let result: Result<Vec<SupportNote>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM support_notes WHERE target_type = $target_type AND target_id = $target_id ORDER BY created_at DESC")
+            .bind(("target_type", target_type))
+            .bind(("target_id", target_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// All notes for a user and their subscriptions, newest first.
+    pub async fn get_support_notes_for_user(&self, user_id: &str, limit: u32) -> Result<Vec<SupportNote>, String> {
+        let result: Result<Vec<SupportNote>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM support_notes WHERE user_id = $user_id ORDER BY created_at DESC LIMIT $limit")
+            .bind(("user_id", user_id.to_string()))
+            .bind(("limit", limit))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn update_support_note(&self, note_id: &str, body: &str) -> Result<SupportNote, String> {
+        let id_part = note_id.strip_prefix("support_notes:").unwrap_or(note_id);
+
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query("UPDATE type::thing('support_notes', $id) SET body = $body, updated_at = time::now() RETURN AFTER")
+            .bind(("id", id_part.to_string()))
+            .bind(("body", body.trim().to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(updated) if !updated.is_empty() => {
+                self.get_support_note(id_part).await.ok_or_else(|| format!("Note {} missing after update", id_part))
+            }
+            Ok(_) => Err(format!("Note not found: {}", id_part)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    pub async fn delete_support_note(&self, note_id: &str) -> Result<(), String> {
+        let id_part = note_id.strip_prefix("support_notes:").unwrap_or(note_id);
+
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query("DELETE type::thing('support_notes', $id) RETURN BEFORE")
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(deleted) if !deleted.is_empty() => Ok(()),
+            Ok(_) => Err(format!("Note not found: {}", id_part)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    /// Case-insensitive substring search over note bodies.
+    pub async fn search_support_notes(&self, text: &str, limit: u32) -> Result<Vec<SupportNote>, String> {
+        let result: Result<Vec<SupportNote>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM support_notes WHERE string::contains(string::lowercase(body), $text) ORDER BY created_at DESC LIMIT $limit")
+            .bind(("text", text.to_lowercase()))
+            .bind(("limit", limit))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn set_user_tags(&self, user_id: &str, tags: Vec<String>) -> Result<User, String> {
+        let id_part = user_id.strip_prefix("users:").unwrap_or(user_id);
+        let before = self.audit_snapshot("users", id_part).await;
+
+        let result: Result<Vec<User>, _> = self.db
+            .query("UPDATE type::thing('users', $id) SET tags = $tags, updated_at = time::now() WHERE ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
+            .bind(("id", id_part.to_string()))
+            .bind(("tags", tags))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(mut users) if !users.is_empty() => {
+                self.audit_change("user.tags_updated", "users", id_part, before).await;
+                Ok(users.remove(0))
+            }
+            Ok(_) => Err(format!("User not found: {}", user_id)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    /// Replaces the address and VAT number printed on the user's invoices.
+    pub async fn set_billing_details(
+        &self,
+        user_id: &str,
+        billing_address: Option<String>,
+        vat_number: Option<String>,
+    ) -> Result<User, String> {
+        let id_part = user_id.strip_prefix("users:").unwrap_or(user_id);
+        let before = self.audit_snapshot("users", id_part).await;
+
+        let result: Result<Vec<User>, _> = self.db
+            .query("UPDATE type::thing('users', $id) SET billing_address = $billing_address, vat_number = $vat_number, updated_at = time::now() WHERE ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
+            .bind(("id", id_part.to_string()))
+            .bind(("billing_address", billing_address))
+            .bind(("vat_number", vat_number))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(mut users) if !users.is_empty() => {
+                self.audit_change("user.billing_details_updated", "users", id_part, before).await;
+                Ok(users.remove(0))
+            }
+            Ok(_) => Err(format!("User not found: {}", user_id)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    /// Applies the fields set in `dto`, leaving the rest as they are.
+    pub async fn update_user(&self, user_id: &str, dto: UpdateUserDto) -> Result<User, String> {
+        let id_part = user_id.strip_prefix("users:").unwrap_or(user_id);
+        let before = self.audit_snapshot("users", id_part).await;
+
+        let result: Result<Vec<User>, _> = self.db
+            .query("UPDATE type::thing('users', $id) SET name = $name ?? name, email = $email ?? email, locale = $locale ?? locale, updated_at = time::now() WHERE deleted_at IS NONE AND ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
+            .bind(("id", id_part.to_string()))
+            .bind(("name", dto.name))
+            .bind(("email", dto.email))
+            .bind(("locale", dto.locale))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(mut users) if !users.is_empty() => {
+                self.audit_change("user.updated", "users", id_part, before).await;
+                Ok(users.remove(0))
+            }
+            Ok(_) => Err(format!("User not found: {}", user_id)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    /// Erases the user's personal data. Payments, invoices, credit notes and
+    /// wallet entries are kept for the accounting record, without card
+    /// tokens or checkout links; the user row stays so they still resolve.
+    /// The user's audit entries keep who did what but lose their values.
+    pub async fn anonymize_user(&self, user_id: &str) -> Result<User, String> {
+        let id_part = user_id.strip_prefix("users:").unwrap_or(user_id);
+
+        let query = r#"
+            BEGIN TRANSACTION;
+            LET $erased = (UPDATE type::thing('users', $id) SET
+                email = $email,
+                name = 'Deleted user',
+                tags = [],
+                billing_address = NONE,
+                vat_number = NONE,
+                deleted_at = $now,
+                updated_at = $now
+                WHERE deleted_at IS NONE AND ($tenant = NONE OR tenant_id = $tenant)
+                RETURN AFTER);
+            IF array::len($erased) = 0 {
+                THROW "User not found or already deleted";
+            };
+            DELETE recurring_payments WHERE user_id = $id;
+            DELETE webhook_endpoints WHERE user_id = $id;
+            DELETE notification WHERE user_id = $id;
+            DELETE notification_preferences WHERE user_id = $id;
+            DELETE activity_events WHERE user_id = $id;
+            DELETE support_notes WHERE user_id = $id;
+            DELETE subscription_members WHERE user_id = $id;
+            UPDATE audit_log SET old_value = NONE, new_value = NONE
+                WHERE entity_type = 'users' AND entity_id = $id;
+            UPDATE payments SET
+                recurring_token = NONE,
+                checkout_url = NONE,
+                authentication_url = NONE,
+                updated_at = $now
+                WHERE user_id = $id AND ($tenant = NONE OR tenant_id = $tenant);
+            COMMIT TRANSACTION;
+        "#;
+
+        self.db
+            .query(query)
+            .bind(("id", id_part.to_string()))
+            // Unique per user so the email index still holds
+            .bind(("email", format!("deleted-{}@deleted.invalid", id_part)))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| format!("Failed to delete user {}: {}", id_part, e))?;
+
+        // Values are left out so the log doesn't keep what was just erased
+        self.record_audit("user.deleted", "users", id_part, None, None).await;
+        self.get_user(id_part).await
+            .ok_or_else(|| format!("User not found: {}", user_id))
+    }
+
+    /// Every `table` row belonging to the user, as raw JSON without the
+    /// `omit` fields.
+    pub async fn get_user_rows(
+        &self,
+        table: &str,
+        omit: &[&str],
+        user_id: &str,
+    ) -> Result<Vec<serde_json::Value>, String> {
+        // `table` and `omit` are our own names, never user input
+        let omit = if omit.is_empty() {
+            String::new()
+        } else {
+            format!(" OMIT {}", omit.join(", "))
+        };
+        let query = format!(
+            "SELECT *, record::id(id) AS id{omit} FROM {table} WHERE user_id = $user_id ORDER BY id ASC",
+            omit = omit,
+            table = table,
+        );
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query(query)
+            .bind(("user_id", user_id.strip_prefix("users:").unwrap_or(user_id).to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn set_subscription_tags(&self, subscription_id: &str, tags: Vec<String>) -> Result<Subscription, String> {
+        let id_part = subscription_id.strip_prefix("subscriptions:").unwrap_or(subscription_id);
+        let before = self.audit_snapshot("subscriptions", id_part).await;
+
+        let result: Result<Vec<Subscription>, _> = self.db
+            .query("UPDATE type::thing('subscriptions', $id) SET tags = $tags, updated_at = time::now() WHERE ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
+            .bind(("id", id_part.to_string()))
+            .bind(("tags", tags))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(mut subscriptions) if !subscriptions.is_empty() => {
+                self.audit_change("subscription.tags_updated", "subscriptions", id_part, before).await;
+                Ok(subscriptions.remove(0))
+            }
+            Ok(_) => Err(format!("Subscription not found: {}", subscription_id)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    pub async fn find_users_by_tag(&self, tag: &str) -> Result<Vec<User>, String> {
+        let result: Result<Vec<User>, _> = self.db
+            .query("SELECT * FROM users WHERE tags CONTAINS $tag AND ($tenant = NONE OR tenant_id = $tenant)")
+            .bind(("tag", tag.to_lowercase()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn find_subscriptions_by_tag(&self, tag: &str) -> Result<Vec<Subscription>, String> {
+        let result: Result<Vec<Subscription>, _> = self.db
+            .query("SELECT * FROM subscriptions WHERE tags CONTAINS $tag AND ($tenant = NONE OR tenant_id = $tenant)")
+            .bind(("tag", tag.to_lowercase()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    // ---------------------
+    // Support tickets
+    // ---------------------
+
+    /// Opens a ticket, or folds the event into the active ticket already open
+    /// for it: failed payments are grouped per subscription, disputes and
+    /// charge reports per payment. Manual tickets are always new.
+    pub async fn open_ticket(&self, ticket: NewTicket) -> Result<Ticket, String> {
+        let existing = match (ticket.source, &ticket.subscription_id, &ticket.payment_id) {
+            (TicketSource::Manual, _, _) => None,
+            (TicketSource::FailedPayment, Some(subscription_id), _) => {
+                self.find_active_ticket(ticket.source, "subscription_id", subscription_id).await?
+            }
+            (_, _, Some(payment_id)) => self.find_active_ticket(ticket.source, "payment_id", payment_id).await?,
+            (_, Some(subscription_id), None) => {
+                self.find_active_ticket(ticket.source, "subscription_id", subscription_id).await?
+            }
+            (_, None, None) => None,
+        };
+
+        if let Some(existing) = existing {
+            self.db
+                .query("UPDATE type::thing('tickets', $id) SET occurrences += 1, payment_id = $payment_id ?? payment_id, updated_at = time::now()")
+                .bind(("id", existing.id.clone()))
+                .bind(("payment_id", ticket.payment_id))
+                .await
+                .and_then(|r| r.check())
+                .map_err(|e| format!("Database error: {}", e))?;
+
+            info!("Added {:?} event to ticket {}", ticket.source, existing.id);
+            return self.get_ticket(&existing.id).await.ok_or_else(|| format!("Ticket {} missing after update", existing.id));
+        }
+
+        let ticket_id = Uuid::new_v4().simple().to_string();
+        self.db
+            .query(r#"
+                CREATE type::thing('tickets', $id) SET
+                    user_id = $user_id,
+                    source = $source,
+                    status = $status,
+                    subject = $subject,
+                    description = $description,
+                    payment_id = $payment_id,
+                    subscription_id = $subscription_id,
+                    assignee = $assignee,
+                    occurrences = 1,
+                    created_at = time::now(),
+                    updated_at = time::now()
+            "#)
+            .bind(("id", ticket_id.clone()))
+            .bind(("user_id", ticket.user_id))
+            .bind(("source", ticket.source))
+            .bind(("status", TicketStatus::Open))
+            .bind(("subject", ticket.subject))
+            .bind(("description", ticket.description))
+            .bind(("payment_id", ticket.payment_id))
+            .bind(("subscription_id", ticket.subscription_id))
+            .bind(("assignee", ticket.assignee))
+            .await
+            .and_then(|r| r.check())
+            .map_err(|e| format!("Failed to open ticket: {}", e))?;
+
+        info!("Opened {:?} ticket {}", ticket.source, ticket_id);
+        self.get_ticket(&ticket_id).await.ok_or_else(|| format!("Ticket {} missing after create", ticket_id))
+    }
+
+    async fn find_active_ticket(&self, source: TicketSource, field: &str, value: &str) -> Result<Option<Ticket>, String> {
+        // `field` is one of our own column names, never user input
+        let query = format!(
+            "SELECT *, record::id(id) AS id FROM tickets WHERE source = $source AND {} = $value AND status IN [$open, $in_progress] ORDER BY created_at DESC LIMIT 1",
+            field
+        );
+        let result: Result<Vec<Ticket>, _> = self.db
+            .query(query)
+            .bind(("source", source))
+            .bind(("value", value.to_string()))
+            .bind(("open", TicketStatus::Open))
+            .bind(("in_progress", TicketStatus::InProgress))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result
+            .map(|tickets| tickets.into_iter().next())
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn get_ticket(&self, ticket_id: &str) -> Option<Ticket> {
+        let id_part = ticket_id.strip_prefix("tickets:").unwrap_or(ticket_id);
+
+        let result: Result<Vec<Ticket>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('tickets', $id)")
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|tickets| tickets.into_iter().next())
+    }
+
+    /// Tickets matching `filter`, most recently updated first.
+    pub async fn list_tickets(&self, filter: &TicketFilter, limit: u32) -> Result<Vec<Ticket>, String> {
+        let result: Result<Vec<Ticket>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM tickets WHERE ($status = NONE OR status = $status) AND ($source = NONE OR source = $source) AND ($assignee = NONE OR assignee = $assignee) AND ($user_id = NONE OR user_id = $user_id) ORDER BY updated_at DESC LIMIT $limit")
+            .bind(("status", filter.status))
+            .bind(("source", filter.source))
+            .bind(("assignee", filter.assignee.clone()))
+            .bind(("user_id", filter.user_id.clone()))
+            .bind(("limit", limit))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Sets the status and/or assignee. `assignee` of `Some(None)` unassigns;
+    /// moving to resolved or closed stamps `resolved_at`, reopening clears it.
+    pub async fn update_ticket(
+        &self,
+        ticket_id: &str,
+        status: Option<TicketStatus>,
+        assignee: Option<Option<String>>,
+    ) -> Result<Ticket, String> {
+        let id_part = ticket_id.strip_prefix("tickets:").unwrap_or(ticket_id);
+
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query(r#"
+                UPDATE type::thing('tickets', $id) SET
+                    resolved_at = IF $status = NONE THEN resolved_at ELSE IF $resolved THEN resolved_at ?? time::now() ELSE NONE END,
+                    status = $status ?? status,
+                    assignee = IF $set_assignee THEN $assignee ELSE assignee END,
+                    updated_at = time::now()
+                RETURN AFTER
+            "#)
+            .bind(("id", id_part.to_string()))
+            .bind(("status", status))
+            .bind(("resolved", status.is_some_and(|s| !s.is_active())))
+            .bind(("set_assignee", assignee.is_some()))
+            .bind(("assignee", assignee.flatten()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(updated) if !updated.is_empty() => {
+                let ticket = self.get_ticket(id_part).await.ok_or_else(|| format!("Ticket {} missing after update", id_part))?;
+                info!("Ticket {} is {:?}, assigned to {:?}", id_part, ticket.status, ticket.assignee);
+                Ok(ticket)
+            }
+            Ok(_) => Err(format!("Ticket not found: {}", id_part)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    // ---------------------
+    // Background jobs
+    // ---------------------
+
+    /// Queues `payload` to run as soon as a worker picks it up.
+    pub async fn enqueue_job(&self, payload: JobPayload) -> Result<Job, String> {
+        let job_id = Uuid::new_v4().simple().to_string();
+        self.db
+            .query(r#"
+                CREATE type::thing('jobs', $id) SET
+                    kind = $kind,
+                    payload = $payload,
+                    status = $status,
+                    reference = $reference,
+                    attempts = 0,
+                    max_attempts = $max_attempts,
+                    run_at = time::now(),
+                    created_at = time::now(),
+                    updated_at = time::now()
+            "#)
+            .bind(("id", job_id.clone()))
+            .bind(("kind", payload.kind()))
+            .bind(("reference", payload.reference().to_string()))
+            .bind(("payload", payload))
+            .bind(("status", JobStatus::Pending))
+            .bind(("max_attempts", MAX_JOB_ATTEMPTS))
+            .await
+            .and_then(|r| r.check())
+            .map_err(|e| format!("Failed to enqueue job: {}", e))?;
+
+        self.get_job(&job_id).await.ok_or_else(|| format!("Job {} missing after create", job_id))
+    }
+
+    pub async fn get_job(&self, job_id: &str) -> Option<Job> {
+        let id_part = job_id.strip_prefix("jobs:").unwrap_or(job_id);
+
+        let result: Result<Vec<Job>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('jobs', $id)")
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|jobs| jobs.into_iter().next())
+    }
+
+    /// Jobs matching the filters, newest first.
+    pub async fn list_jobs(&self, status: Option<JobStatus>, kind: Option<String>, limit: u32) -> Result<Vec<Job>, String> {
+        let result: Result<Vec<Job>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM jobs WHERE ($status = NONE OR status = $status) AND ($kind = NONE OR kind = $kind) ORDER BY created_at DESC LIMIT $limit")
+            .bind(("status", status))
+            .bind(("kind", kind))
+            .bind(("limit", limit))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Pending jobs whose `run_at` has passed, oldest first.
+    pub async fn get_due_jobs(&self, limit: u32) -> Result<Vec<Job>, String> {
+        let result: Result<Vec<Job>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM jobs WHERE status = $pending AND run_at <= time::now() ORDER BY run_at ASC LIMIT $limit")
+            .bind(("pending", JobStatus::Pending))
+            .bind(("limit", limit))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Marks a pending job running and counts the attempt. Returns false when
+    /// another worker claimed it first.
+    pub async fn claim_job(&self, job_id: &str) -> Result<bool, String> {
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query(r#"
+                UPDATE type::thing('jobs', $id) SET
+                    status = $running,
+                    attempts += 1,
+                    started_at = time::now(),
+                    updated_at = time::now()
+                WHERE status = $pending
+                RETURN AFTER
+            "#)
+            .bind(("id", job_id.to_string()))
+            .bind(("running", JobStatus::Running))
+            .bind(("pending", JobStatus::Pending))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result
+            .map(|updated| !updated.is_empty())
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn complete_job(&self, job_id: &str) -> Result<(), String> {
+        self.db
+            .query("UPDATE type::thing('jobs', $id) SET status = $succeeded, completed_at = time::now(), updated_at = time::now()")
+            .bind(("id", job_id.to_string()))
+            .bind(("succeeded", JobStatus::Succeeded))
+            .await
+            .and_then(|r| r.check())
+            .map(|_| ())
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Records a failed attempt: the job runs again at `retry_at`, or is left
+    /// failed when that is `None`.
+    pub async fn fail_job(&self, job_id: &str, error: &str, retry_at: Option<DateTime<Utc>>) -> Result<(), String> {
+        let status = if retry_at.is_some() { JobStatus::Pending } else { JobStatus::Failed };
+        self.db
+            .query(r#"
+                UPDATE type::thing('jobs', $id) SET
+                    status = $status,
+                    last_error = $error,
+                    run_at = $retry_at ?? run_at,
+                    completed_at = IF $retry_at = NONE THEN time::now() ELSE NONE END,
+                    updated_at = time::now()
+            "#)
+            .bind(("id", job_id.to_string()))
+            .bind(("status", status))
+            .bind(("error", error.to_string()))
+            .bind(("retry_at", retry_at))
+            .await
+            .and_then(|r| r.check())
+            .map(|_| ())
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Hands jobs claimed before `claimed_before` that never finished back to
+    /// the queue, e.g. after a worker was killed mid-job. Returns how many.
+    pub async fn requeue_stale_jobs(&self, claimed_before: DateTime<Utc>) -> Result<usize, String> {
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query("UPDATE jobs SET status = $pending, run_at = time::now(), updated_at = time::now() WHERE status = $running AND started_at < $cutoff RETURN AFTER")
+            .bind(("pending", JobStatus::Pending))
+            .bind(("running", JobStatus::Running))
+            .bind(("cutoff", claimed_before))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result
+            .map(|requeued| requeued.len())
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Puts a failed job back in the queue with a fresh set of attempts.
+    pub async fn retry_job(&self, job_id: &str) -> Result<Job, String> {
+        let id_part = job_id.strip_prefix("jobs:").unwrap_or(job_id);
+
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query(r#"
+                UPDATE type::thing('jobs', $id) SET
+                    status = $pending,
+                    max_attempts = attempts + $max_attempts,
+                    run_at = time::now(),
+                    completed_at = NONE,
+                    updated_at = time::now()
+                WHERE status = $failed
+                RETURN AFTER
+            "#)
+            .bind(("id", id_part.to_string()))
+            .bind(("pending", JobStatus::Pending))
+            .bind(("failed", JobStatus::Failed))
+            .bind(("max_attempts", MAX_JOB_ATTEMPTS))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(updated) if !updated.is_empty() => {
+                info!("Job {} queued for retry", id_part);
+                self.get_job(id_part).await.ok_or_else(|| format!("Job {} missing after update", id_part))
+            }
+            Ok(_) => match self.get_job(id_part).await {
+                Some(job) => Err(format!("Job {} is {:?}; only failed jobs can be retried", id_part, job.status)),
+                None => Err(format!("Job not found: {}", id_part)),
+            },
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    // ---------------------
+    // Domain events (outbox)
+    // ---------------------
+
+    pub async fn get_domain_event(&self, event_id: &str) -> Option<DomainEvent> {
+        let id_part = event_id.strip_prefix("domain_events:").unwrap_or(event_id);
+
+        let result: Result<Vec<DomainEvent>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('domain_events', $id)")
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|events| events.into_iter().next())
+    }
+
+    /// Events matching the filters, newest first.
+    pub async fn list_domain_events(
+        &self,
+        status: Option<DomainEventStatus>,
+        event_type: Option<String>,
+        limit: u32,
+    ) -> Result<Vec<DomainEvent>, String> {
+        let result: Result<Vec<DomainEvent>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM domain_events WHERE ($status = NONE OR status = $status) AND ($event_type = NONE OR event_type = $event_type) ORDER BY created_at DESC LIMIT $limit")
+            .bind(("status", status))
+            .bind(("event_type", event_type))
+            .bind(("limit", limit))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Unpublished events whose `next_attempt_at` has passed, oldest first.
+    pub async fn get_due_domain_events(&self, limit: u32) -> Result<Vec<DomainEvent>, String> {
+        let result: Result<Vec<DomainEvent>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM domain_events WHERE status = $pending AND next_attempt_at <= time::now() ORDER BY created_at ASC LIMIT $limit")
+            .bind(("pending", DomainEventStatus::Pending))
+            .bind(("limit", limit))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Counts a delivery attempt and holds the event for `EVENT_LEASE_SECONDS`.
+    /// Returns false when another dispatcher claimed it first.
+    pub async fn claim_domain_event(&self, event_id: &str) -> Result<bool, String> {
+        let now = Utc::now();
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query(r#"
+                UPDATE type::thing('domain_events', $id) SET
+                    attempts += 1,
+                    next_attempt_at = $lease_until
+                WHERE status = $pending AND next_attempt_at <= $now
+                RETURN AFTER
+            "#)
+            .bind(("id", event_id.to_string()))
+            .bind(("pending", DomainEventStatus::Pending))
+            .bind(("now", now))
+            .bind(("lease_until", now + Duration::seconds(EVENT_LEASE_SECONDS)))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result
+            .map(|updated| !updated.is_empty())
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn mark_domain_event_published(&self, event_id: &str) -> Result<(), String> {
+        self.db
+            .query("UPDATE type::thing('domain_events', $id) SET status = $published, published_at = time::now(), last_error = NONE")
+            .bind(("id", event_id.to_string()))
+            .bind(("published", DomainEventStatus::Published))
+            .await
+            .and_then(|r| r.check())
+            .map(|_| ())
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Records a failed delivery: the event is sent again at `retry_at`, or
+    /// left failed when that is `None`.
+    pub async fn fail_domain_event(&self, event_id: &str, error: &str, retry_at: Option<DateTime<Utc>>) -> Result<(), String> {
+        let status = if retry_at.is_some() { DomainEventStatus::Pending } else { DomainEventStatus::Failed };
+        self.db
+            .query("UPDATE type::thing('domain_events', $id) SET status = $status, last_error = $error, next_attempt_at = $retry_at ?? next_attempt_at")
+            .bind(("id", event_id.to_string()))
+            .bind(("status", status))
+            .bind(("error", error.to_string()))
+            .bind(("retry_at", retry_at))
+            .await
+            .and_then(|r| r.check())
+            .map(|_| ())
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Queues a failed event for delivery again with a fresh set of attempts.
+    pub async fn redeliver_domain_event(&self, event_id: &str) -> Result<DomainEvent, String> {
+        let id_part = event_id.strip_prefix("domain_events:").unwrap_or(event_id);
+
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query("UPDATE type::thing('domain_events', $id) SET status = $pending, attempts = 0, next_attempt_at = time::now() WHERE status = $failed RETURN AFTER")
+            .bind(("id", id_part.to_string()))
+            .bind(("pending", DomainEventStatus::Pending))
+            .bind(("failed", DomainEventStatus::Failed))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(updated) if !updated.is_empty() => {
+                info!("Domain event {} queued for redelivery", id_part);
+                self.get_domain_event(id_part).await.ok_or_else(|| format!("Event {} missing after update", id_part))
+            }
+            Ok(_) => match self.get_domain_event(id_part).await {
+                Some(event) => Err(format!("Event {} is {:?}; only failed events can be redelivered", id_part, event.status)),
+                None => Err(format!("Event not found: {}", id_part)),
+            },
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    // ---------------------
+    // Payment method availability
+    // ---------------------
+
+    pub async fn list_payment_method_rules(&self) -> Result<Vec<PaymentMethodRule>, String> {
+        let result: Result<Vec<PaymentMethodRule>, _> = self.db
+            .query("SELECT * OMIT id FROM payment_method_rules ORDER BY payment_method, region")
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn set_payment_method_rule(
+        &self,
+        method: &PaymentMethod,
+        region: &str,
+        dto: SetPaymentMethodRuleDto,
+    ) -> Result<PaymentMethodRule, String> {
+        let result: Result<Vec<PaymentMethodRule>, _> = self.db
+            .query(r#"
+                UPSERT type::thing('payment_method_rules', [$method, $region]) SET
+                    payment_method = $method,
+                    region = $region,
+                    enabled = $enabled,
+                    currencies = $currencies,
+                    min_amount = $min_amount,
+                    max_amount = $max_amount,
+                    created_at = created_at ?? $now,
+                    updated_at = $now
+                RETURN AFTER
+            "#)
+            .bind(("method", method.clone()))
+            .bind(("region", region.to_string()))
+            .bind(("enabled", dto.enabled))
+            .bind(("currencies", dto.currencies))
+            .bind(("min_amount", dto.min_amount))
+            .bind(("max_amount", dto.max_amount))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(rules) => {
+                info!("Set availability of {} in {}", method, region);
+                rules.into_iter().next().ok_or_else(|| format!("Rule for {} in {} missing after upsert", method, region))
+            }
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    pub async fn delete_payment_method_rule(&self, method: &PaymentMethod, region: &str) -> Result<(), String> {
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query("DELETE type::thing('payment_method_rules', [$method, $region]) RETURN BEFORE")
+            .bind(("method", method.clone()))
+            .bind(("region", region.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(deleted) if !deleted.is_empty() => {
+                info!("Removed availability rule for {} in {}", method, region);
+                Ok(())
+            }
+            Ok(_) => Err(format!("Rule not found: {} in {}", method, region)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
+    }
+
+    // ---------------------
+    // Merchant webhooks
+    // ---------------------
+
+    pub async fn create_webhook_endpoint(
+        &self,
+        user_id: &str,
+        dto: CreateWebhookEndpointDto,
+        secret: &str,
+    ) -> Result<WebhookEndpoint, String> {
+        let endpoint_id = Uuid::new_v4().simple().to_string();
+        self.db
+            .query(r#"
+                CREATE type::thing('webhook_endpoints', $id) SET
+                    user_id = $user_id,
+                    url = $url,
+                    secret = $secret,
+                    event_types = $event_types,
+                    created_at = time::now()
+            "#)
+            .bind(("id", endpoint_id.clone()))
+            .bind(("user_id", user_id.to_string()))
+            .bind(("url", dto.url.trim().to_string()))
+            .bind(("secret", secret.to_string()))
+            .bind(("event_types", dto.event_types))
+            .await
+            .and_then(|r| r.check())
+            .map_err(|e| format!("Failed to create webhook endpoint: {}", e))?;
+
+        info!("Registered webhook endpoint {} for user {}", endpoint_id, user_id);
+        self.get_webhook_endpoint(&endpoint_id).await
+            .ok_or_else(|| format!("Webhook endpoint {} missing after create", endpoint_id))
+    }
+
+    pub async fn get_webhook_endpoint(&self, endpoint_id: &str) -> Option<WebhookEndpoint> {
+        let id_part = endpoint_id.strip_prefix("webhook_endpoints:").unwrap_or(endpoint_id);
+
+        let result: Result<Vec<WebhookEndpoint>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('webhook_endpoints', $id)")
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|endpoints| endpoints.into_iter().next())
+    }
+
+    pub async fn list_webhook_endpoints(&self, user_id: &str) -> Result<Vec<WebhookEndpoint>, String> {
+        let result: Result<Vec<WebhookEndpoint>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM webhook_endpoints WHERE user_id = $user_id ORDER BY created_at ASC")
+            .bind(("user_id", user_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Removes the endpoint. Its delivery log is kept; pending deliveries
+    /// fail when they next come up.
+    pub async fn delete_webhook_endpoint(&self, endpoint_id: &str) -> Result<(), String> {
+        self.db
+            .query("DELETE type::thing('webhook_endpoints', $id)")
+            .bind(("id", endpoint_id.to_string()))
+            .await
+            .and_then(|r| r.check())
+            .map(|_| ())
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Queues `event` for each endpoint that wants it. A delivery is keyed
+    /// by endpoint and event, so queueing an event again adds nothing.
+    pub async fn queue_webhook_deliveries(&self, event: &DomainEvent, endpoints: &[WebhookEndpoint]) -> Result<usize, String> {
+        let mut queued = 0;
+        for endpoint in endpoints.iter().filter(|endpoint| endpoint.wants(&event.event_type)) {
+            self.db
+                .query(r#"
+                    IF record::exists(type::thing('webhook_deliveries', $id)) = false {
+                        CREATE type::thing('webhook_deliveries', $id) SET
+                            endpoint_id = $endpoint_id,
+                            event_id = $event_id,
+                            event_type = $event_type,
+                            status = $pending,
+                            attempts = 0,
+                            next_attempt_at = time::now(),
+                            created_at = time::now(),
+                            updated_at = time::now();
+                    };
+                "#)
+                .bind(("id", format!("{}_{}", endpoint.id, event.id)))
+                .bind(("endpoint_id", endpoint.id.clone()))
+                .bind(("event_id", event.id.clone()))
+                .bind(("event_type", event.event_type.clone()))
+                .bind(("pending", WebhookDeliveryStatus::Pending))
+                .await
+                .and_then(|r| r.check())
+                .map_err(|e| format!("Failed to queue delivery of {} to {}: {}", event.id, endpoint.id, e))?;
+            queued += 1;
+        }
+        Ok(queued)
+    }
+
+    /// The endpoint's deliveries, newest first.
+    pub async fn list_webhook_deliveries(&self, endpoint_id: &str, limit: u32) -> Result<Vec<WebhookDelivery>, String> {
+        let result: Result<Vec<WebhookDelivery>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM webhook_deliveries WHERE endpoint_id = $endpoint_id ORDER BY created_at DESC LIMIT $limit")
+            .bind(("endpoint_id", endpoint_id.to_string()))
+            .bind(("limit", limit))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Pending deliveries whose `next_attempt_at` has passed, oldest first.
+    pub async fn get_due_webhook_deliveries(&self, limit: u32) -> Result<Vec<WebhookDelivery>, String> {
+        let result: Result<Vec<WebhookDelivery>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM webhook_deliveries WHERE status = $pending AND next_attempt_at <= time::now() ORDER BY created_at ASC LIMIT $limit")
+            .bind(("pending", WebhookDeliveryStatus::Pending))
+            .bind(("limit", limit))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Counts an attempt and holds the delivery for `DELIVERY_LEASE_SECONDS`.
+    /// Returns false when another worker claimed it first.
+    pub async fn claim_webhook_delivery(&self, delivery_id: &str) -> Result<bool, String> {
+        let now = Utc::now();
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query(r#"
+                UPDATE type::thing('webhook_deliveries', $id) SET
+                    attempts += 1,
+                    next_attempt_at = $lease_until,
+                    updated_at = $now
+                WHERE status = $pending AND next_attempt_at <= $now
+                RETURN AFTER
+            "#)
+            .bind(("id", delivery_id.to_string()))
+            .bind(("pending", WebhookDeliveryStatus::Pending))
+            .bind(("now", now))
+            .bind(("lease_until", now + Duration::seconds(DELIVERY_LEASE_SECONDS)))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result
+            .map(|updated| !updated.is_empty())
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Records an attempt's result. A failed attempt is retried at
+    /// `retry_at`, or left failed when that is `None`.
+    pub async fn record_webhook_delivery(
+        &self,
+        delivery_id: &str,
+        response_status: Option<u16>,
+        error: Option<&str>,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<(), String> {
+        let status = match (error, retry_at) {
+            (None, _) => WebhookDeliveryStatus::Succeeded,
+            (Some(_), Some(_)) => WebhookDeliveryStatus::Pending,
+            (Some(_), None) => WebhookDeliveryStatus::Failed,
+        };
+        self.db
+            .query(r#"
+                UPDATE type::thing('webhook_deliveries', $id) SET
+                    status = $status,
+                    response_status = $response_status,
+                    last_error = $error,
+                    next_attempt_at = $retry_at ?? next_attempt_at,
+                    delivered_at = IF $status = $succeeded THEN time::now() ELSE NONE END,
+                    updated_at = time::now()
+            "#)
+            .bind(("id", delivery_id.to_string()))
+            .bind(("status", status))
+            .bind(("succeeded", WebhookDeliveryStatus::Succeeded))
+            .bind(("response_status", response_status))
+            .bind(("error", error.map(str::to_string)))
+            .bind(("retry_at", retry_at))
+            .await
+            .and_then(|r| r.check())
+            .map(|_| ())
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // ---------------------
+    // Merchant details
+    // ---------------------
+
+    /// Saved supplier details for invoices, if any have been saved.
+    pub async fn get_merchant_details(&self) -> Option<MerchantDetails> {
+        let result: Result<Vec<MerchantDetails>, _> = self.db
+            .query("SELECT * FROM type::thing('merchant_details', $id)")
+            .bind(("id", DEFAULT_MERCHANT_ID))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(details) => details.into_iter().next(),
+            Err(e) => {
+                error!("Failed to load merchant details: {}", e);
+                None
+            }
+        }
+    }
+
+    pub async fn set_merchant_details(&self, details: MerchantDetails) -> Result<MerchantDetails, String> {
+        let result: Result<Vec<MerchantDetails>, _> = self.db
+            .query(r#"
+                UPSERT type::thing('merchant_details', $id) SET
+                    legal_name = $legal_name,
+                    vat_number = $vat_number,
+                    registration_number = $registration_number,
+                    address = $address,
+                    updated_at = time::now()
+                RETURN AFTER
+            "#)
+            .bind(("id", DEFAULT_MERCHANT_ID))
+            .bind(("legal_name", details.legal_name))
+            .bind(("vat_number", details.vat_number))
+            .bind(("registration_number", details.registration_number))
+            .bind(("address", details.address))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result
+            .map_err(|e| format!("Database error: {}", e))?
+            .into_iter()
+            .next()
+            .ok_or_else(|| "Merchant details missing after update".to_string())
+    }
+
+    // ---------------------
+    // Message templates
+    // ---------------------
+
+    /// Every saved template override, across channels and locales.
+    pub async fn list_message_templates(&self) -> Result<Vec<MessageTemplate>, String> {
+        let result: Result<Vec<MessageTemplate>, _> = self.db
+            .query("SELECT * FROM message_templates ORDER BY channel, message_key, locale")
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Failed to load message templates: {}", e))
+    }
+
+    pub async fn set_message_template(
+        &self,
+        channel: TemplateChannel,
+        key: &str,
+        locale: &str,
+        body: String,
+    ) -> Result<MessageTemplate, String> {
+        let result: Result<Vec<MessageTemplate>, _> = self.db
+            .query(r#"
+                UPSERT type::thing('message_templates', [$channel, $key, $locale]) SET
+                    channel = $channel,
+                    message_key = $key,
+                    locale = $locale,
+                    body = $body,
+                    updated_at = time::now()
+                RETURN AFTER
+            "#)
+            .bind(("channel", channel))
+            .bind(("key", key.to_string()))
+            .bind(("locale", locale.to_string()))
+            .bind(("body", body))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result
+            .map_err(|e| format!("Database error: {}", e))?
+            .into_iter()
+            .next()
+            .ok_or_else(|| "Message template missing after update".to_string())
+    }
+
+    /// Removes an override so the message goes back to the built-in copy.
+    /// Returns whether there was one.
+    pub async fn delete_message_template(&self, channel: TemplateChannel, key: &str, locale: &str) -> Result<bool, String> {
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query("DELETE type::thing('message_templates', [$channel, $key, $locale]) RETURN BEFORE")
+            .bind(("channel", channel))
+            .bind(("key", key.to_string()))
+            .bind(("locale", locale.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result
+            .map(|deleted| !deleted.is_empty())
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // ---------------------
+    // Invoices
+    // ---------------------
+
+    /// Atomically increments and returns the named counter.
+    async fn next_counter_value(&self, name: &str) -> Result<u64, String> {
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query("UPSERT type::thing('counters', $name) SET value = (value ?? 0) + 1 RETURN AFTER")
+            .bind(("name", name.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result
+            .map_err(|e| format!("Database error: {}", e))?
+            .first()
+            .and_then(|row| row.get("value"))
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| format!("Counter {} returned no value", name))
+    }
+
+    /// Issues the invoice for a payment, or returns the existing one so
+    /// webhook retries and replays don't create duplicates.
+    pub async fn create_invoice(&self, new_invoice: NewInvoice) -> Result<Invoice, String> {
+        if let Some(existing) = self.get_invoice_by_payment_reference(&new_invoice.payment_reference).await {
+            return Ok(existing);
+        }
+
+        let invoice_id = Uuid::new_v4().simple().to_string();
+        let invoice_number = format!("INV-{:06}", self.next_counter_value("invoice").await?);
+
+        self.db
+            .query(r#"
+                CREATE type::thing('invoices', $id) SET
+                    invoice_number = $invoice_number,
+                    user_id = $user_id,
+                    subscription_id = $subscription_id,
+                    payment_reference = $payment_reference,
+                    seller_name = $seller_name,
+                    seller_vat_number = $seller_vat_number,
+                    seller_registration_number = $seller_registration_number,
+                    seller_address = $seller_address,
+                    customer_name = $customer_name,
+                    customer_email = $customer_email,
+                    customer_address = $customer_address,
+                    customer_vat_number = $customer_vat_number,
+                    line_items = $line_items,
+                    currency = $currency,
+                    subtotal = $subtotal,
+                    vat_rate_percent = $vat_rate_percent,
+                    vat_amount = $vat_amount,
+                    total = $total,
+                    issued_at = time::now()
+            "#)
+            .bind(("id", invoice_id.clone()))
+            .bind(("invoice_number", invoice_number.clone()))
+            .bind(("user_id", new_invoice.user_id))
+            .bind(("subscription_id", new_invoice.subscription_id))
+            .bind(("payment_reference", new_invoice.payment_reference))
+            .bind(("seller_name", new_invoice.seller_name))
+            .bind(("seller_vat_number", new_invoice.seller_vat_number))
+            .bind(("seller_registration_number", new_invoice.seller_registration_number))
+            .bind(("seller_address", new_invoice.seller_address))
+            .bind(("customer_name", new_invoice.customer_name))
+            .bind(("customer_email", new_invoice.customer_email))
+            .bind(("customer_address", new_invoice.customer_address))
+            .bind(("customer_vat_number", new_invoice.customer_vat_number))
+            .bind(("line_items", new_invoice.line_items))
+            .bind(("currency", new_invoice.currency))
+            .bind(("subtotal", new_invoice.subtotal))
+            .bind(("vat_rate_percent", new_invoice.vat_rate_percent))
+            .bind(("vat_amount", new_invoice.vat_amount))
+            .bind(("total", new_invoice.total))
+            .await
+            .map_err(|e| format!("Failed to create invoice: {}", e))?;
+
+        info!("Issued invoice {}", invoice_number);
+        self.get_invoice(&invoice_id).await.ok_or_else(|| format!("Invoice {} missing after create", invoice_id))
+    }
+
+    pub async fn get_invoice(&self, invoice_id: &str) -> Option<Invoice> {
+        let id_part = invoice_id.strip_prefix("invoices:").unwrap_or(invoice_id);
+
+        let result: Result<Vec<Invoice>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('invoices', $id)")
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|invoices| invoices.into_iter().next())
+    }
+
+    pub async fn get_invoice_by_payment_reference(&self, payment_reference: &str) -> Option<Invoice> {
+        let result: Result<Vec<Invoice>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM invoices WHERE payment_reference = $reference LIMIT 1")
+            .bind(("reference", payment_reference.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|invoices| invoices.into_iter().next())
+    }
+
+    pub async fn get_credit_note(&self, credit_note_id: &str) -> Option<CreditNote> {
+        let id_part = credit_note_id.strip_prefix("credit_notes:").unwrap_or(credit_note_id);
+
+        let result: Result<Vec<CreditNote>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('credit_notes', $id)")
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|notes| notes.into_iter().next())
+    }
+
+    // ---------------------
+    // Plan changes
+    // ---------------------
+
+    pub async fn create_plan_change(&self, change: &PlanChange) -> Result<(), String> {
+        self.db
+            .query(r#"
+                CREATE type::thing('plan_changes', $id) SET
+                    user_id = $user_id,
+                    subscription_id = $subscription_id,
+                    from_plan_id = $from_plan_id,
+                    from_plan_name = $from_plan_name,
+                    to_plan_id = $to_plan_id,
+                    to_plan_name = $to_plan_name,
+                    to_price = $to_price,
+                    proration = $proration,
+                    merchant_transaction_id = $merchant_transaction_id,
+                    status = $status,
+                    effective_at = $effective_at,
+                    created_at = $created_at
+            "#)
+            .bind(("id", change.id.clone()))
+            .bind(("user_id", change.user_id.clone()))
+            .bind(("subscription_id", change.subscription_id.clone()))
+            .bind(("from_plan_id", change.from_plan_id.clone()))
+            .bind(("from_plan_name", change.from_plan_name.clone()))
+            .bind(("to_plan_id", change.to_plan_id.clone()))
+            .bind(("to_plan_name", change.to_plan_name.clone()))
+            .bind(("to_price", change.to_price))
+            .bind(("proration", change.proration.clone()))
+            .bind(("merchant_transaction_id", change.merchant_transaction_id.clone()))
+            .bind(("status", format!("{:?}", change.status)))
+            .bind(("effective_at", change.effective_at))
+            .bind(("created_at", change.created_at))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| format!("Failed to create plan change: {}", e))?;
+
+        info!("Plan change {} created: {} -> {}", change.id, change.from_plan_name, change.to_plan_name);
+        Ok(())
+    }
+
+    pub async fn get_plan_change(&self, plan_change_id: &str) -> Option<PlanChange> {
+        let id_part = plan_change_id.strip_prefix("plan_changes:").unwrap_or(plan_change_id);
+
+        let result: Result<Vec<PlanChange>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('plan_changes', $id)")
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|changes| changes.into_iter().next())
+    }
+
+    pub async fn get_plan_change_by_transaction(&self, merchant_transaction_id: &str) -> Option<PlanChange> {
+        let result: Result<Vec<PlanChange>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM plan_changes WHERE merchant_transaction_id = $merchant_id LIMIT 1")
+            .bind(("merchant_id", merchant_transaction_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|changes| changes.into_iter().next())
+    }
+
+    /// The change waiting for the subscription's next renewal, if any.
+    pub async fn get_scheduled_plan_change(&self, subscription_id: &str) -> Option<PlanChange> {
+        let id_part = subscription_id.strip_prefix("subscriptions:").unwrap_or(subscription_id);
+
+        let result: Result<Vec<PlanChange>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM plan_changes WHERE subscription_id IN [$full_id, $id_part] AND status = 'Scheduled' ORDER BY created_at DESC LIMIT 1")
+            .bind(("full_id", format!("subscriptions:{}", id_part)))
+            .bind(("id_part", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|changes| changes.into_iter().next())
+    }
+
+    /// Records a change for the next renewal, replacing any change already
+    /// scheduled for the subscription.
+    pub async fn schedule_plan_change(&self, change: &PlanChange, subscription: &Subscription) -> Result<(), String> {
+        if let Some(previous) = self.get_scheduled_plan_change(&change.subscription_id).await {
+            self.cancel_scheduled_plan_change(&previous, subscription).await?;
+        }
+        self.create_plan_change(change).await?;
+        self.record_subscription_activity(
+            subscription,
+            "plan_change_scheduled",
+            &format!("Switch to {} scheduled for the next renewal", change.to_plan_name),
+        ).await;
+        Ok(())
+    }
+
+    /// Withdraws a scheduled change; the subscription renews on its current
+    /// plan. Returns false when the change was no longer scheduled.
+    pub async fn cancel_scheduled_plan_change(&self, change: &PlanChange, subscription: &Subscription) -> Result<bool, String> {
+        let cancelled: Vec<serde_json::Value> = self.db
+            .query("UPDATE type::thing('plan_changes', $change_id) SET status = 'Cancelled', completed_at = time::now() WHERE status = 'Scheduled' RETURN AFTER")
+            .bind(("change_id", change.id.clone()))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Failed to cancel plan change {}: {}", change.id, e))?;
+
+        if cancelled.is_empty() {
+            return Ok(false);
+        }
+        self.record_subscription_activity(
+            subscription,
+            "plan_change_cancelled",
+            &format!("Scheduled switch to {} cancelled", change.to_plan_name),
+        ).await;
+        info!("Scheduled plan change {} cancelled", change.id);
+        Ok(true)
+    }
+
+    /// Moves a subscription due for renewal onto the plan scheduled for it.
+    /// Returns the updated subscription, or `None` when no change was due.
+    pub async fn apply_scheduled_plan_change(&self, subscription: &Subscription, now: DateTime<Utc>) -> Result<Option<Subscription>, String> {
+        let change = match self.get_scheduled_plan_change(&subscription.id).await {
+            Some(change) if change.effective_at.is_none_or(|at| at <= now) => change,
+            _ => return Ok(None),
+        };
+
+        let updated = self.complete_plan_change(&change, None, None).await?;
+        if updated.status != PlanChangeStatus::Completed {
+            return Ok(None);
+        }
+        self.get_subscription(&subscription.id).await
+            .map(Some)
+            .ok_or_else(|| format!("Subscription {} missing after plan change", subscription.id))
+    }
+
+    /// Splits what's due on the subscription `payment` is for into `count`
+    /// monthly installments, `payment` being the first. The others wait for
+    /// it to be paid; installments of an earlier checkout are cancelled.
+    pub async fn create_installment_schedule(&self, payment: &Payment, count: u32) -> Result<Vec<Installment>, String> {
+        let subscription_id = payment.subscription_id.as_deref()
+            .ok_or_else(|| format!("Payment {} has no subscription", payment.merchant_transaction_id))?;
+        let id_part = subscription_id.strip_prefix("subscriptions:").unwrap_or(subscription_id);
+        let subscription = self.get_subscription(id_part).await
+            .ok_or_else(|| format!("Subscription not found: {}", id_part))?;
+        let schedule_id = Uuid::new_v4().simple().to_string();
+        let mut amounts = split_installments(subscription.outstanding_balance(), count);
+        amounts[0] = payment.amount;
+        let rows: Vec<serde_json::Value> = amounts.iter().enumerate()
+            .map(|(i, amount)| serde_json::json!({
+                "id": Uuid::new_v4().simple().to_string(),
+                "sequence": i + 1,
+                "amount": amount,
+            }))
+            .collect();
+
+        let query = r#"
+            BEGIN TRANSACTION;
+            UPDATE installments SET status = 'Cancelled', updated_at = $now
+                WHERE subscription_id IN [$full_id, $id_part] AND status IN ['Pending', 'Scheduled'];
+            FOR $row IN $rows {
+                CREATE type::thing('installments', $row.id) SET
+                    schedule_id = $schedule_id,
+                    user_id = $user_id,
+                    subscription_id = $id_part,
+                    sequence = $row.sequence,
+                    count = $count,
+                    amount = $row.amount,
+                    currency = $currency,
+                    status = 'Pending',
+                    attempts = 0,
+                    created_at = $now,
+                    updated_at = $now;
+            };
+            UPDATE installments SET merchant_transaction_id = $merchant_id
+                WHERE schedule_id = $schedule_id AND sequence = 1;
+            COMMIT TRANSACTION;
+        "#;
+        self.db
+            .query(query)
+            .bind(("full_id", format!("subscriptions:{}", id_part)))
+            .bind(("id_part", id_part.to_string()))
+            .bind(("rows", rows))
+            .bind(("schedule_id", schedule_id.clone()))
+            .bind(("user_id", payment.user_id.clone()))
+            .bind(("count", count))
+            .bind(("currency", subscription.currency.clone()))
+            .bind(("merchant_id", payment.merchant_transaction_id.clone()))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| format!("Failed to create installment schedule: {}", e))?;
+
+        info!("Installment schedule {} created for subscription {}: {} x {:?}", schedule_id, id_part, count, amounts);
+        self.get_installments(id_part).await
+    }
+
+    /// The installments of the subscription's latest schedule, in order.
+    pub async fn get_installments(&self, subscription_id: &str) -> Result<Vec<Installment>, String> {
+        let id_part = subscription_id.strip_prefix("subscriptions:").unwrap_or(subscription_id);
+
+        let installments: Vec<Installment> = self.db
+            .query("SELECT *, record::id(id) AS id FROM installments WHERE subscription_id IN [$full_id, $id_part] ORDER BY created_at DESC, sequence ASC")
+            .bind(("full_id", format!("subscriptions:{}", id_part)))
+            .bind(("id_part", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Failed to load installments: {}", e))?;
+
+        let latest = installments.first().map(|i| i.schedule_id.clone());
+        Ok(installments.into_iter().filter(|i| Some(&i.schedule_id) == latest.as_ref()).collect())
+    }
+
+    /// The installment a payment was made for: the checkout of a first
+    /// installment, or the latest charge of a later one.
+    pub async fn get_installment_by_transaction(&self, merchant_transaction_id: &str) -> Option<Installment> {
+        let result: Result<Vec<Installment>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM installments WHERE merchant_transaction_id = $merchant_id LIMIT 1")
+            .bind(("merchant_id", merchant_transaction_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|installments| installments.into_iter().next())
+    }
+
+    /// Marks a paid first installment and schedules the rest a month apart
+    /// from `paid_at`. Other payments, and schedules already started, are
+    /// left alone.
+    pub async fn start_installment_schedule(&self, merchant_transaction_id: &str, paid_at: DateTime<Utc>) -> Result<(), String> {
+        let first = match self.get_installment_by_transaction(merchant_transaction_id).await {
+            Some(first) if first.sequence == 1 && first.status == InstallmentStatus::Pending => first,
+            _ => return Ok(()),
+        };
+        let rows: Vec<InstallmentDue> = (2..=first.count)
+            .map(|sequence| InstallmentDue {
+                sequence,
+                due_at: billing_calendar::add_months(paid_at, sequence - 1, paid_at.day()),
+            })
+            .collect();
+
+        let query = r#"
+            BEGIN TRANSACTION;
+            LET $first = (UPDATE installments SET status = 'Paid', due_at = $paid_at, paid_at = $paid_at, updated_at = $now
+                WHERE schedule_id = $schedule_id AND sequence = 1 AND status = 'Pending' RETURN AFTER);
+            IF array::len($first) > 0 {
+                FOR $row IN $rows {
+                    UPDATE installments SET status = 'Scheduled', due_at = $row.due_at, updated_at = $now
+                        WHERE schedule_id = $schedule_id AND sequence = $row.sequence AND status = 'Pending';
+                };
+            };
+            COMMIT TRANSACTION;
+        "#;
+        self.db
+            .query(query)
+            .bind(("schedule_id", first.schedule_id.clone()))
+            .bind(("rows", rows))
+            .bind(("paid_at", paid_at))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| format!("Failed to start installment schedule {}: {}", first.schedule_id, e))?;
+
+        info!("First of {} installments paid for subscription {}", first.count, first.subscription_id);
+        if let Some(subscription) = self.get_subscription(&first.subscription_id).await {
+            self.record_subscription_activity(
+                &subscription,
+                "installments_started",
+                &format!("First of {} monthly installments paid", first.count),
+            ).await;
+        }
+        Ok(())
+    }
+
+    /// Scheduled installments whose due date has passed, oldest first.
+    pub async fn list_due_installments(&self, now: DateTime<Utc>) -> Result<Vec<Installment>, String> {
+        self.db
+            .query("SELECT *, record::id(id) AS id FROM installments WHERE status = 'Scheduled' AND due_at <= $now ORDER BY due_at ASC")
+            .bind(("now", now))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Failed to load due installments: {}", e))
+    }
+
+    /// Records an attempt at charging a scheduled installment under
+    /// `merchant_transaction_id`. Returns the installment as updated, or
+    /// `None` when it is no longer scheduled.
+    pub async fn begin_installment_charge(&self, installment: &Installment, merchant_transaction_id: &str) -> Result<Option<Installment>, String> {
+        let updated: Vec<serde_json::Value> = self.db
+            .query("UPDATE type::thing('installments', $id) SET merchant_transaction_id = $merchant_id, attempts = attempts + 1, updated_at = $now WHERE status = 'Scheduled' RETURN AFTER")
+            .bind(("id", installment.id.clone()))
+            .bind(("merchant_id", merchant_transaction_id.to_string()))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Failed to record installment charge: {}", e))?;
+        if updated.is_empty() {
+            return Ok(None);
+        }
+        Ok(self.get_installment_by_transaction(merchant_transaction_id).await)
+    }
+
+    /// Settles a scheduled installment from its charge: `Paid` on success,
+    /// otherwise `Failed` when `final_attempt`, else left scheduled for the
+    /// next run with the error recorded. Returns whether it changed.
+    pub async fn settle_installment(&self, installment: &Installment, error: Option<&str>, final_attempt: bool) -> Result<bool, String> {
+        let status = match error {
+            None => InstallmentStatus::Paid,
+            Some(_) if final_attempt => InstallmentStatus::Failed,
+            Some(_) => InstallmentStatus::Scheduled,
+        };
+        let now = Utc::now();
+        let updated: Vec<serde_json::Value> = self.db
+            .query("UPDATE type::thing('installments', $id) SET status = $status, last_error = $error, paid_at = $paid_at, updated_at = $now WHERE status = 'Scheduled' RETURN AFTER")
+            .bind(("id", installment.id.clone()))
+            .bind(("status", format!("{:?}", status)))
+            .bind(("error", error.map(|e| e.to_string())))
+            .bind(("paid_at", (status == InstallmentStatus::Paid).then_some(now)))
+            .bind(("now", now))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Failed to settle installment {}: {}", installment.id, e))?;
+        if updated.is_empty() {
+            return Ok(false);
+        }
+
+        if let Some(subscription) = self.get_subscription(&installment.subscription_id).await {
+            let (kind, description) = match status {
+                InstallmentStatus::Paid => ("installment_paid", format!("Installment {} of {} paid", installment.sequence, installment.count)),
+                InstallmentStatus::Failed => ("installment_failed", format!("Installment {} of {} could not be charged", installment.sequence, installment.count)),
+                _ => ("installment_declined", format!("Installment {} of {} was declined; it is retried tomorrow", installment.sequence, installment.count)),
+            };
+            self.record_subscription_activity(&subscription, kind, &description).await;
+        }
+        Ok(true)
+    }
+
+    /// Cancels the installments still to be charged for a subscription that
+    /// has ended.
+    pub async fn cancel_installments(&self, subscription_id: &str) -> Result<(), String> {
+        let id_part = subscription_id.strip_prefix("subscriptions:").unwrap_or(subscription_id);
+        self.db
+            .query("UPDATE installments SET status = 'Cancelled', updated_at = $now WHERE subscription_id IN [$full_id, $id_part] AND status IN ['Pending', 'Scheduled']")
+            .bind(("full_id", format!("subscriptions:{}", id_part)))
+            .bind(("id_part", id_part.to_string()))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| format!("Failed to cancel installments of {}: {}", id_part, e))?;
+        info!("Cancelled outstanding installments of subscription {}", id_part);
+        Ok(())
+    }
+
+    /// The pending payment for an installment charge, tied to the installment
+    /// by its `INSTALL_` merchant transaction id.
+    pub async fn create_installment_payment(&self, installment: &Installment, merchant_transaction_id: &str, tax: TaxBreakdown, gateway: &str) -> Result<Payment, String> {
+        let now = Utc::now();
+
+        let query = r#"
+            CREATE payments SET
+                merchant_transaction_id = $merchant_transaction_id,
+                subscription_id = $subscription_id,
+                amount = $amount,
+                currency = $currency,
+                amount_excl_vat = $amount_excl_vat,
+                vat_amount = $vat_amount,
+                vat_rate_percent = $vat_rate_percent,
+                payment_method = $payment_method,
+                gateway = $gateway,
+                user_id = $user_id,
+                status = 'Pending',
+                state_reason = $state_reason,
+                tenant_id = $tenant_id,
+                created_at = $now,
+                updated_at = $now
+        "#;
+
+        let created: Option<Payment> = self.db
+            .query(query)
+            .bind(("merchant_transaction_id", merchant_transaction_id.to_string()))
+            .bind(("subscription_id", installment.subscription_id.clone()))
+            .bind(("amount", tax.gross))
+            .bind(("currency", installment.currency.clone()))
+            .bind(("amount_excl_vat", tax.net))
+            .bind(("vat_amount", tax.vat))
+            .bind(("vat_rate_percent", tax.vat_rate_percent))
+            .bind(("payment_method", PaymentMethod::Card.to_string()))
+            .bind(("gateway", gateway.to_string()))
+            .bind(("user_id", installment.user_id.clone()))
+            .bind(("state_reason", state_reason::AWAITING_PAYMENT))
+            .bind(("tenant_id", self.owning_tenant(&installment.user_id).await))
+            .bind(("now", now))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Failed to create installment payment: {}", e))?;
+
+        let payment = created.ok_or_else(|| "Failed to create installment payment: no result returned".to_string())?;
+        self.audit_payment_change("payment.created", &payment.merchant_transaction_id, None).await;
+        Ok(payment)
+    }
+
+    /// The pending payment for an upgrade's proration charge, tied to the
+    /// plan change by its `PLANCHG_` merchant transaction id.
+    pub async fn create_proration_payment(&self, change: &PlanChange, tax: TaxBreakdown, gateway: &str) -> Result<Payment, String> {
+        let merchant_transaction_id = change.merchant_transaction_id.clone()
+            .ok_or_else(|| format!("Plan change {} has nothing to charge", change.id))?;
+        let now = Utc::now();
+
+        let query = r#"
+            CREATE payments SET
+                merchant_transaction_id = $merchant_transaction_id,
+                subscription_id = $subscription_id,
+                amount = $amount,
+                currency = $currency,
+                amount_excl_vat = $amount_excl_vat,
+                vat_amount = $vat_amount,
+                vat_rate_percent = $vat_rate_percent,
+                payment_method = $payment_method,
+                gateway = $gateway,
+                user_id = $user_id,
+                status = 'Pending',
+                state_reason = $state_reason,
+                tenant_id = $tenant_id,
+                created_at = $now,
+                updated_at = $now
+        "#;
+
+        let created: Option<Payment> = self.db
+            .query(query)
+            .bind(("merchant_transaction_id", merchant_transaction_id))
+            .bind(("subscription_id", change.subscription_id.clone()))
+            .bind(("amount", tax.gross))
+            .bind(("currency", change.proration.currency.clone()))
+            .bind(("amount_excl_vat", tax.net))
+            .bind(("vat_amount", tax.vat))
+            .bind(("vat_rate_percent", tax.vat_rate_percent))
+            .bind(("payment_method", PaymentMethod::Card.to_string()))
+            .bind(("gateway", gateway.to_string()))
+            .bind(("user_id", change.user_id.clone()))
+            .bind(("state_reason", state_reason::AWAITING_PAYMENT))
+            .bind(("tenant_id", self.owning_tenant(&change.user_id).await))
+            .bind(("now", now))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Failed to create proration payment: {}", e))?;
+
+        let payment = created.ok_or_else(|| "Failed to create proration payment: no result returned".to_string())?;
+        self.audit_payment_change("payment.created", &payment.merchant_transaction_id, None).await;
+        Ok(payment)
+    }
+
+    /// Completes a pending or scheduled plan change in one transaction: the
+    /// proration payment (if any) is marked completed, a credit note is issued
+    /// for `credit` (if any), the subscription moves to the new plan and any
+    /// other change scheduled for it is cancelled. A change that is no longer
+    /// pending or scheduled is returned unchanged.
+    pub async fn complete_plan_change(
+        &self,
+        change: &PlanChange,
+        gateway_reference: Option<&str>,
+        credit: Option<TaxBreakdown>,
+    ) -> Result<PlanChange, String> {
+        let now = Utc::now();
+        let subscription_id = change.subscription_id.strip_prefix("subscriptions:").unwrap_or(&change.subscription_id).to_string();
+
+        let credit_note = match credit {
+            Some(tax) => Some((
+                Uuid::new_v4().simple().to_string(),
+                format!("CN-{:06}", self.next_counter_value("credit_note").await?),
+                tax,
+            )),
+            None => None,
+        };
+        let credit_note_id = credit_note.as_ref().map(|(id, _, _)| id.clone());
+        let subscription_before = self.audit_snapshot("subscriptions", &subscription_id).await;
+        let payment_before = match &change.merchant_transaction_id {
+            Some(merchant_id) => self.audit_payment_snapshot(merchant_id).await,
+            None => None,
+        };
+
+        let query = r#"
+            BEGIN TRANSACTION;
+            LET $completed = (UPDATE type::thing('plan_changes', $change_id) SET
+                status = 'Completed',
+                credit_note_id = $credit_note_id,
+                completed_at = $now
+                WHERE status IN ['Pending', 'Scheduled']
+                RETURN AFTER);
+            IF array::len($completed) > 0 {
+                UPDATE plan_changes SET status = 'Cancelled', completed_at = $now
+                    WHERE subscription_id = $change_subscription_id AND status = 'Scheduled' AND id != type::thing('plan_changes', $change_id);
+                UPDATE payments SET
+                    status = 'Completed',
+                    state_reason = $payment_reason,
+                    peach_payment_id = $gateway_reference ?? peach_payment_id,
+                    updated_at = $now
+                    WHERE merchant_transaction_id = $merchant_id AND ($tenant = NONE OR tenant_id = $tenant);
+                UPDATE type::thing('subscriptions', $subscription_id) SET
+                    plan_id = $plan_id,
+                    plan_name = $plan_name,
+                    price = $price,
+                    usage_pricing = (SELECT VALUE usage_pricing FROM ONLY type::thing('plans', $plan_id)),
+                    state_reason = $subscription_reason,
+                    updated_at = $now
+                    WHERE ($tenant = NONE OR tenant_id = $tenant);
+                IF $credit_note_id != NONE {
+                    CREATE type::thing('credit_notes', $credit_note_id) SET
+                        credit_note_number = $credit_note_number,
+                        user_id = $user_id,
+                        subscription_id = $subscription_id,
+                        reason = $credit_reason,
+                        currency = $currency,
+                        subtotal = $credit_net,
+                        vat_rate_percent = $credit_vat_rate,
+                        vat_amount = $credit_vat,
+                        total = $credit_gross,
+                        issued_at = $now;
+                };
+            };
+            COMMIT TRANSACTION;
+        "#;
+
+        self.db
+            .query(query)
+            .bind(("change_id", change.id.clone()))
+            .bind(("merchant_id", change.merchant_transaction_id.clone()))
+            .bind(("gateway_reference", gateway_reference.map(|r| r.to_string())))
+            .bind(("subscription_id", subscription_id.clone()))
+            .bind(("change_subscription_id", change.subscription_id.clone()))
+            .bind(("plan_id", change.to_plan_id.clone()))
+            .bind(("plan_name", change.to_plan_name.clone()))
+            .bind(("price", change.to_price))
+            .bind(("credit_note_id", credit_note_id))
+            .bind(("credit_note_number", credit_note.as_ref().map(|(_, number, _)| number.clone())))
+            .bind(("user_id", change.user_id.clone()))
+            .bind(("credit_reason", format!("Unused {} plan time on switching to {}", change.from_plan_name, change.to_plan_name)))
+            .bind(("currency", change.proration.currency.clone()))
+            .bind(("credit_net", credit.map(|t| t.net)))
+            .bind(("credit_vat_rate", credit.map(|t| t.vat_rate_percent)))
+            .bind(("credit_vat", credit.map(|t| t.vat)))
+            .bind(("credit_gross", credit.map(|t| t.gross)))
+            .bind(("payment_reason", state_reason::PAYMENT_SUCCEEDED))
+            .bind(("subscription_reason", state_reason::PLAN_CHANGED))
+            .bind(("now", now))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| format!("Failed to complete plan change {}: {}", change.id, e))?;
+
+        let updated = self.get_plan_change(&change.id).await
+            .ok_or_else(|| format!("Plan change {} missing after update", change.id))?;
+
+        if updated.status == PlanChangeStatus::Completed && updated.completed_at == Some(now) {
+            if let Some(merchant_id) = &updated.merchant_transaction_id {
+                self.audit_payment_change("payment.completed", merchant_id, payment_before).await;
+                self.payment_events.publish(merchant_id, &PaymentStatus::Completed);
+            }
+            self.audit_change("subscription.plan_changed", "subscriptions", &subscription_id, subscription_before).await;
+            if let Some(subscription) = self.get_subscription(&updated.subscription_id).await {
+                self.record_subscription_activity(
+                    &subscription,
+                    "plan_changed",
+                    &format!("Plan changed from {} to {}", updated.from_plan_name, updated.to_plan_name),
+                ).await;
+            }
+            if let Some((credit_note_id, number, tax)) = &credit_note {
+                info!("Issued credit note {}", number);
+                if let Err(e) = self.credit_wallet(
+                    &updated.user_id,
+                    &updated.proration.currency,
+                    tax.gross,
+                    WalletEntrySource::Proration,
+                    credit_note_id,
+                ).await {
+                    error!("Failed to credit wallet for credit note {}: {}", number, e);
+                }
+            }
+            info!("Plan change {} completed", updated.id);
+        }
+        Ok(updated)
+    }
+
+    /// Marks a pending plan change and its proration payment failed; the
+    /// subscription keeps its plan.
+    pub async fn fail_plan_change(&self, change: &PlanChange, reason: &str) -> Result<(), String> {
+        let payment_before = match &change.merchant_transaction_id {
+            Some(merchant_id) => self.audit_payment_snapshot(merchant_id).await,
+            None => None,
+        };
+        let query = r#"
+            BEGIN TRANSACTION;
+            LET $failed = (UPDATE type::thing('plan_changes', $change_id) SET
+                status = 'Failed',
+                failure_reason = $reason
+                WHERE status = 'Pending'
+                RETURN AFTER);
+            IF array::len($failed) > 0 {
+                UPDATE payments SET status = 'Failed', state_reason = $payment_reason, updated_at = time::now()
+                    WHERE merchant_transaction_id = $merchant_id AND ($tenant = NONE OR tenant_id = $tenant) AND status = 'Pending';
+            };
+            COMMIT TRANSACTION;
+        "#;
+
+        self.db
+            .query(query)
+            .bind(("change_id", change.id.clone()))
+            .bind(("merchant_id", change.merchant_transaction_id.clone()))
+            .bind(("reason", reason.to_string()))
+            .bind(("payment_reason", state_reason::PAYMENT_FAILED))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| format!("Failed to record failed plan change {}: {}", change.id, e))?;
+
+        if let Some(merchant_id) = &change.merchant_transaction_id {
+            self.audit_payment_change("payment.status_changed", merchant_id, payment_before).await;
+            self.payment_events.publish(merchant_id, &PaymentStatus::Failed);
+        }
+        info!("Plan change {} failed: {}", change.id, reason);
+        Ok(())
+    }
+
+    // ---------------------
+    // Wallet
+    // ---------------------
+
+    pub async fn get_wallet_entry(&self, entry_id: &str) -> Option<WalletEntry> {
+        let result: Result<Vec<WalletEntry>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('wallet_entries', $id)")
+            .bind(("id", entry_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|entries| entries.into_iter().next())
+    }
+
+    /// Credit available to the user in `currency`.
+    pub async fn get_wallet_balance(&self, user_id: &str, currency: &str) -> f64 {
+        let result: Result<Vec<WalletBalance>, _> = self.db
+            .query("SELECT user_id, currency, balance, updated_at FROM type::thing('wallet_balances', [$user_id, $currency])")
+            .bind(("user_id", user_id.to_string()))
+            .bind(("currency", currency.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok()
+            .and_then(|balances| balances.into_iter().next())
+            .map(|b| b.balance)
+            .unwrap_or(0.0)
+    }
+
+    /// Balances in every currency the user has held credit in, and the
+    /// newest `limit` ledger entries.
+    pub async fn get_wallet(&self, user_id: &str, limit: u32) -> Result<Wallet, String> {
+        let mut response = self.db
+            .query("SELECT user_id, currency, balance, updated_at FROM wallet_balances WHERE user_id = $user_id ORDER BY currency ASC; \
+                    SELECT *, record::id(id) AS id FROM wallet_entries WHERE user_id = $user_id ORDER BY created_at DESC LIMIT $limit")
+            .bind(("user_id", user_id.to_string()))
+            .bind(("limit", limit))
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        let balances: Vec<WalletBalance> = response.take(0).map_err(|e| format!("Database error: {}", e))?;
+        let entries: Vec<WalletEntry> = response.take(1).map_err(|e| format!("Database error: {}", e))?;
+        Ok(Wallet {
+            user_id: user_id.to_string(),
+            balances,
+            entries,
+        })
+    }
+
+    /// Adds `amount` to the user's credit in `currency`. Crediting the same
+    /// source and reference again changes nothing and returns the first entry.
+    pub async fn credit_wallet(
+        &self,
+        user_id: &str,
+        currency: &str,
+        amount: f64,
+        source: WalletEntrySource,
+        reference: &str,
+    ) -> Result<WalletEntry, String> {
+        let entry_id = WalletEntry::key(source, reference);
+
+        let query = r#"
+            BEGIN TRANSACTION;
+            LET $wallet = (UPSERT type::thing('wallet_balances', [$user_id, $currency]) SET
+                user_id = $user_id,
+                currency = $currency,
+                balance = math::fixed((balance ?? 0) + $amount, 2),
+                updated_at = $now
+                RETURN AFTER);
+            CREATE type::thing('wallet_entries', $entry_id) SET
+                user_id = $user_id,
+                currency = $currency,
+                amount = $amount,
+                balance_after = $wallet[0].balance,
+                source = $source,
+                reference = $reference,
+                created_at = $now;
+            COMMIT TRANSACTION;
+        "#;
+
+        let result = self.db
+            .query(query)
+            .bind(("user_id", user_id.to_string()))
+            .bind(("currency", currency.to_string()))
+            .bind(("amount", (amount * 100.0).round() / 100.0))
+            .bind(("entry_id", entry_id.clone()))
+            .bind(("source", format!("{:?}", source)))
+            .bind(("reference", reference.to_string()))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|response| response.check());
+
+        // A repeated credit fails on the existing entry and rolls back
+        let entry = self.get_wallet_entry(&entry_id).await;
+        match (result, entry) {
+            (Ok(_), Some(entry)) => {
+                info!("Credited {} {} to wallet of {} ({:?} {})", entry.amount, currency, user_id, source, reference);
+                Ok(entry)
+            }
+            (Err(_), Some(entry)) => Ok(entry),
+            (Ok(_), None) => Err(format!("Wallet entry {} missing after credit", entry_id)),
+            (Err(e), None) => Err(format!("Failed to credit wallet of {}: {}", user_id, e)),
+        }
+    }
+
+    /// Spends up to `max_amount` of the user's credit in `currency`, returning
+    /// the debit entry, or `None` when there was no credit to spend. A
+    /// `Payment` debit also records the amount on the payment whose merchant
+    /// transaction id is `reference`, and moves that payment to the wallet
+    /// gateway when the credit covers all of it.
+    pub async fn debit_wallet(
+        &self,
+        user_id: &str,
+        currency: &str,
+        max_amount: f64,
+        source: WalletEntrySource,
+        reference: &str,
+    ) -> Result<Option<WalletEntry>, String> {
+        let entry_id = WalletEntry::key(source, reference);
+
+        let query = r#"
+            BEGIN TRANSACTION;
+            LET $available = (SELECT VALUE balance FROM type::thing('wallet_balances', [$user_id, $currency]))[0] ?? 0;
+            LET $applied = math::fixed(math::min([$available, $max_amount]), 2);
+            IF $applied > 0 {
+                LET $wallet = (UPDATE type::thing('wallet_balances', [$user_id, $currency]) SET
+                    balance = math::fixed(balance - $applied, 2),
+                    updated_at = $now
+                    RETURN AFTER);
+                CREATE type::thing('wallet_entries', $entry_id) SET
+                    user_id = $user_id,
+                    currency = $currency,
+                    amount = 0 - $applied,
+                    balance_after = $wallet[0].balance,
+                    source = $source,
+                    reference = $reference,
+                    created_at = $now;
+                IF $source = 'Payment' {
+                    UPDATE payments SET wallet_amount = $applied, updated_at = $now
+                        WHERE merchant_transaction_id = $reference AND ($tenant = NONE OR tenant_id = $tenant);
+                    UPDATE payments SET gateway = $wallet_gateway
+                        WHERE merchant_transaction_id = $reference AND ($tenant = NONE OR tenant_id = $tenant) AND amount <= $applied;
+                };
+            };
+            COMMIT TRANSACTION;
+        "#;
+
+        let result = self.db
+            .query(query)
+            .bind(("user_id", user_id.to_string()))
+            .bind(("currency", currency.to_string()))
+            .bind(("max_amount", (max_amount * 100.0).round() / 100.0))
+            .bind(("entry_id", entry_id.clone()))
+            .bind(("source", format!("{:?}", source)))
+            .bind(("reference", reference.to_string()))
+            .bind(("wallet_gateway", WALLET_GATEWAY))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|response| response.check());
+
+        let entry = self.get_wallet_entry(&entry_id).await;
+        match (result, entry) {
+            (Ok(_), Some(entry)) => {
+                info!("Spent {} {} from wallet of {} ({:?} {})", -entry.amount, currency, user_id, source, reference);
+                Ok(Some(entry))
+            }
+            (Ok(_), None) => Ok(None),
+            (Err(_), Some(entry)) => Ok(Some(entry)),
+            (Err(e), None) => Err(format!("Failed to debit wallet of {}: {}", user_id, e)),
+        }
+    }
+
+    /// Gives back wallet credit spent on a payment that then failed or was
+    /// cancelled.
+    async fn restore_wallet_credit(&self, payment: &Payment) {
+        if payment.wallet_amount <= 0.0 || !matches!(payment.status, PaymentStatus::Failed | PaymentStatus::Cancelled) {
+            return;
+        }
+        if let Err(e) = self.credit_wallet(
+            &payment.user_id,
+            &payment.currency,
+            payment.wallet_amount,
+            WalletEntrySource::Reversal,
+            &payment.merchant_transaction_id,
+        ).await {
+            error!("Failed to restore wallet credit for {}: {}", payment.merchant_transaction_id, e);
+        }
+    }
+
+    // ---------------------
+    // Usage
+    // ---------------------
+
+    pub async fn get_usage_record(&self, record_id: &str) -> Option<UsageRecord> {
+        let result: Result<Vec<UsageRecord>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('usage_records', $id)")
+            .bind(("id", record_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|records| records.into_iter().next())
+    }
+
+    /// Stores reported usage. With an idempotency key, reporting the same
+    /// key again returns the first record and `false` instead of adding one.
+    pub async fn record_usage(
+        &self,
+        subscription: &Subscription,
+        quantity: u64,
+        recorded_at: DateTime<Utc>,
+        idempotency_key: Option<&str>,
+    ) -> Result<(UsageRecord, bool), String> {
+        let record_id = match idempotency_key {
+            Some(key) => format!("{}_{}", subscription.id, key),
+            None => Uuid::new_v4().simple().to_string(),
+        };
+        if let Some(existing) = self.get_usage_record(&record_id).await {
+            return Ok((existing, false));
+        }
+
+        let result = self.db
+            .query("CREATE type::thing('usage_records', $id) SET subscription_id = $subscription_id, user_id = $user_id, quantity = $quantity, recorded_at = $recorded_at, created_at = $now RETURN NONE")
+            .bind(("id", record_id.clone()))
+            .bind(("subscription_id", subscription.id.clone()))
+            .bind(("user_id", subscription.user_id.clone()))
+            .bind(("quantity", quantity))
+            .bind(("recorded_at", recorded_at))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|response| response.check());
+
+        match result {
+            Ok(_) => self.get_usage_record(&record_id).await
+                .map(|record| (record, true))
+                .ok_or_else(|| format!("Usage record {} missing after create", record_id)),
+            // A concurrent report with the same key got there first
+            Err(e) => self.get_usage_record(&record_id).await
+                .map(|existing| (existing, false))
+                .ok_or_else(|| format!("Failed to record usage: {}", e)),
+        }
+    }
+
+    /// Units reported for the subscription with `recorded_at` in `[from, to)`.
+    pub async fn get_usage_total(&self, subscription_id: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<u64, String> {
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query("SELECT math::sum(quantity) AS total FROM usage_records WHERE subscription_id = $subscription_id AND recorded_at >= $from AND recorded_at < $to GROUP ALL")
+            .bind(("subscription_id", subscription_id.to_string()))
+            .bind(("from", from))
+            .bind(("to", to))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        // GROUP ALL returns no row when nothing matched
+        Ok(result
+            .map_err(|e| format!("Database error: {}", e))?
+            .first()
+            .and_then(|row| row.get("total"))
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0))
+    }
+
+    /// Records the charge for a metered renewal as a payment, so the usage
+    /// breakdown is kept alongside what was charged. The renewal task has
+    /// already applied the result, so `last_event_at` is set to make any
+    /// gateway webhook for the same charge stale.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create_renewal_payment(
+        &self,
+        subscription: &Subscription,
+        merchant_transaction_id: &str,
+        tax: TaxBreakdown,
+        gateway: &str,
+        wallet_amount: f64,
+        usage: &UsageCharge,
+        status: PaymentStatus,
+        reason: &str,
+    ) -> Result<Payment, String> {
+        let now = Utc::now();
+
+        let query = r#"
+            CREATE payments SET
+                merchant_transaction_id = $merchant_transaction_id,
+                subscription_id = $subscription_id,
+                amount = $amount,
+                currency = $currency,
+                amount_excl_vat = $amount_excl_vat,
+                vat_amount = $vat_amount,
+                vat_rate_percent = $vat_rate_percent,
+                payment_method = $payment_method,
+                gateway = $gateway,
+                user_id = $user_id,
+                wallet_amount = $wallet_amount,
+                usage = $usage,
+                status = $status,
+                state_reason = $state_reason,
+                tenant_id = $tenant_id,
+                last_event_at = $now,
+                created_at = $now,
+                updated_at = $now
+        "#;
+
+        let created: Option<Payment> = self.db
+            .query(query)
+            .bind(("merchant_transaction_id", merchant_transaction_id.to_string()))
+            .bind(("subscription_id", subscription.id.clone()))
+            .bind(("amount", tax.gross))
+            .bind(("currency", subscription.currency.clone()))
+            .bind(("amount_excl_vat", tax.net))
+            .bind(("vat_amount", tax.vat))
+            .bind(("vat_rate_percent", tax.vat_rate_percent))
+            .bind(("payment_method", PaymentMethod::Card.to_string()))
+            .bind(("gateway", gateway.to_string()))
+            .bind(("user_id", subscription.user_id.clone()))
+            .bind(("wallet_amount", wallet_amount))
+            .bind(("usage", usage.clone()))
+            .bind(("status", status))
+            .bind(("state_reason", reason.to_string()))
+            .bind(("tenant_id", subscription.tenant_id.clone()))
+            .bind(("now", now))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Failed to create renewal payment: {}", e))?;
+
+        let payment = created.ok_or_else(|| "Failed to create renewal payment: no result returned".to_string())?;
+        self.audit_payment_change("payment.created", merchant_transaction_id, None).await;
+        self.payment_events.publish(merchant_transaction_id, &payment.status);
+        Ok(payment)
+    }
+
+    // ---------------------
+    // Subscription members
+    // ---------------------
+
+    pub async fn get_subscription_member(&self, member_id: &str) -> Option<SubscriptionMember> {
+        let result: Result<Vec<SubscriptionMember>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('subscription_members', $id)")
+            .bind(("id", member_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|members| members.into_iter().next())
+    }
+
+    /// Oldest first.
+    pub async fn list_subscription_members(&self, subscription_id: &str) -> Result<Vec<SubscriptionMember>, String> {
+        self.db
+            .query("SELECT *, record::id(id) AS id FROM subscription_members WHERE subscription_id = $subscription_id ORDER BY created_at ASC")
+            .bind(("subscription_id", subscription_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Adds a member if a seat is free, checked in the same transaction so
+    /// concurrent invitations can't overfill the subscription.
+    pub async fn add_subscription_member(
+        &self,
+        subscription: &Subscription,
+        email: &str,
+        user_id: Option<String>,
+    ) -> Result<SubscriptionMember, String> {
+        let member_id = Uuid::new_v4().simple().to_string();
+        let query = r#"
+            BEGIN TRANSACTION;
+            LET $seat_count = (SELECT VALUE seat_count FROM ONLY type::thing('subscriptions', $subscription_id)) ?? 1;
+            LET $members = count(SELECT id FROM subscription_members WHERE subscription_id = $subscription_id);
+            IF $members + 1 >= $seat_count {
+                THROW "No seats left on this subscription";
+            };
+            CREATE type::thing('subscription_members', $id) SET
+                subscription_id = $subscription_id,
+                email = $email,
+                user_id = $user_id,
+                invited_by = $invited_by,
+                created_at = $now
+                RETURN NONE;
+            COMMIT TRANSACTION;
+        "#;
+
+        self.db
+            .query(query)
+            .bind(("id", member_id.clone()))
+            .bind(("subscription_id", subscription.id.clone()))
+            .bind(("email", email.to_string()))
+            .bind(("user_id", user_id))
+            .bind(("invited_by", subscription.user_id.clone()))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| {
+                let e = e.to_string();
+                if e.contains("subscription_members_email") {
+                    format!("{} is already a member of this subscription", email)
+                } else if e.contains("No seats left") {
+                    "No seats left on this subscription".to_string()
+                } else {
+                    format!("Failed to add member: {}", e)
+                }
+            })?;
+
+        let member = self.get_subscription_member(&member_id).await
+            .ok_or_else(|| "Failed to add member: no result returned".to_string())?;
+        info!("Added {} to subscription {}", member.email, subscription.id);
+        Ok(member)
+    }
+
+    /// Frees the member's seat. Returns whether the member existed.
+    pub async fn remove_subscription_member(&self, subscription_id: &str, member_id: &str) -> Result<bool, String> {
+        let removed: Vec<serde_json::Value> = self.db
+            .query("DELETE type::thing('subscription_members', $id) WHERE subscription_id = $subscription_id RETURN BEFORE")
+            .bind(("id", member_id.to_string()))
+            .bind(("subscription_id", subscription_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Failed to remove member: {}", e))?;
+
+        if !removed.is_empty() {
+            info!("Removed member {} from subscription {}", member_id, subscription_id);
+        }
+        Ok(!removed.is_empty())
+    }
+
+    /// Changes how many seats the next renewal bills for. Refused when the
+    /// current members wouldn't fit.
+    pub async fn update_seat_count(&self, subscription_id: &str, seat_count: u32) -> Result<Subscription, String> {
+        let before = self.audit_snapshot("subscriptions", subscription_id).await;
+        let query = r#"
+            BEGIN TRANSACTION;
+            LET $members = count(SELECT id FROM subscription_members WHERE subscription_id = $subscription_id);
+            IF $members + 1 > $seat_count {
+                THROW "Remove members before reducing seats";
+            };
+            UPDATE type::thing('subscriptions', $subscription_id) SET seat_count = $seat_count, updated_at = $now WHERE ($tenant = NONE OR tenant_id = $tenant) RETURN NONE;
+            COMMIT TRANSACTION;
+        "#;
+
+        self.db
+            .query(query)
+            .bind(("subscription_id", subscription_id.to_string()))
+            .bind(("seat_count", seat_count))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| {
+                let e = e.to_string();
+                if e.contains("Remove members") {
+                    "Remove members before reducing seats".to_string()
+                } else {
+                    format!("Failed to update seats: {}", e)
+                }
+            })?;
+
+        info!("Subscription {} now has {} seat(s)", subscription_id, seat_count);
+        self.audit_change("subscription.seats_updated", "subscriptions", subscription_id, before).await;
+        self.get_subscription(subscription_id).await
+            .ok_or_else(|| format!("Subscription {} not found", subscription_id))
+    }
+
+    // ---------------------
+    // Token migrations
+    // ---------------------
+
+    /// Starts a migration and flags every active card with it, including
+    /// cards still pending in an earlier migration. Returns the flagged cards
+    /// so their users can be prompted.
+    pub async fn start_token_migration(
+        &self,
+        dto: CreateTokenMigrationDto,
+    ) -> Result<(TokenMigration, Vec<RecurringPayment>), String> {
+        let migration_id = Uuid::new_v4().simple().to_string();
+        let query = r#"
+            BEGIN TRANSACTION;
+            CREATE type::thing('token_migrations', $id) SET
+                target = $target,
+                reason = $reason,
+                created_at = $now
+                RETURN NONE;
+            UPDATE recurring_payments SET migration_id = $id, updated_at = $now
+                WHERE status = 'Active' RETURN NONE;
+            COMMIT TRANSACTION;
+        "#;
+
+        self.db
+            .query(query)
+            .bind(("id", migration_id.clone()))
+            .bind(("target", dto.target.trim().to_string()))
+            .bind(("reason", dto.reason))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| format!("Failed to start token migration: {}", e))?;
+
+        let flagged: Vec<RecurringPayment> = self.db
+            .query("SELECT *, record::id(id) AS id FROM recurring_payments WHERE migration_id = $id AND status = 'Active'")
+            .bind(("id", migration_id.clone()))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Failed to read flagged cards: {}", e))?;
+
+        let migration = self.get_token_migration(&migration_id).await
+            .ok_or_else(|| "Failed to start token migration: no result returned".to_string())?;
+        info!("Token migration {} to {} flagged {} card(s)", migration.id, migration.target, flagged.len());
+        Ok((migration, flagged))
+    }
+
+    pub async fn get_token_migration(&self, migration_id: &str) -> Option<TokenMigration> {
+        let result: Result<Vec<TokenMigration>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('token_migrations', $id)")
+            .bind(("id", migration_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|migrations| migrations.into_iter().next())
+    }
+
+    /// Newest first.
+    pub async fn list_token_migrations(&self) -> Result<Vec<TokenMigration>, String> {
+        self.db
+            .query("SELECT *, record::id(id) AS id FROM token_migrations ORDER BY created_at DESC")
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn get_token_migration_progress(&self, migration: TokenMigration) -> Result<TokenMigrationProgress, String> {
+        let rows: Vec<serde_json::Value> = self.db
+            .query("SELECT status, count() AS count FROM recurring_payments WHERE migration_id = $id GROUP BY status")
+            .bind(("id", migration.id.clone()))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        let (mut migrated, mut pending, mut dropped) = (0, 0, 0);
+        for row in rows {
+            let count = row.get("count").and_then(|v| v.as_u64()).unwrap_or(0);
+            match row.get("status").and_then(|v| v.as_str()) {
+                Some("Replaced") => migrated += count,
+                Some("Active") => pending += count,
+                _ => dropped += count,
             }
-            Err(e) => Err(format!("Database error: {}", e)),
         }
+        Ok(TokenMigrationProgress::new(migration, migrated, pending, dropped))
     }
 
-    pub async fn acknowledge_notification(&self, notification_id: String) -> Result<(), String> {
-        let query = "UPDATE notification SET acknowledged = true WHERE id = $notification_id";
-        
-        let notification_id_clone = notification_id.clone();
-        match self.db.query(query).bind(("notification_id", notification_id)).await {
-            Ok(_) => {
-                println!("✅ Notification {} marked as acknowledged", notification_id_clone);
-                Ok(())
-            }
+    /// Whether an active card is still waiting to be re-authorised.
+    pub async fn token_needs_migration(&self, token: &str) -> bool {
+        let result: Result<Vec<String>, _> = self.db
+            .query("SELECT VALUE migration_id FROM recurring_payments WHERE recurring_token = $token AND status = 'Active' AND migration_id != NONE LIMIT 1")
+            .bind(("token", token.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.is_ok_and(|ids| !ids.is_empty())
+    }
+
+    // ---------------------
+    // Bulk operations
+    // ---------------------
+
+    /// Ids of up to `limit` subscriptions that `filter` matches and `action`
+    /// can apply to.
+    pub async fn find_bulk_targets(&self, action: &BulkAction, filter: &BulkFilter, limit: usize) -> Result<Vec<String>, String> {
+        self.db
+            .query(format!("SELECT VALUE record::id(id) FROM subscriptions WHERE {} LIMIT $limit", BULK_TARGET_CONDITIONS))
+            .bind(BulkTargetParams::new(action, filter))
+            .bind(("limit", limit))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Stores a previewed operation over `subscription_ids`; nothing changes
+    /// until it is confirmed.
+    pub async fn create_bulk_operation(&self, dto: CreateBulkOperationDto, subscription_ids: Vec<String>) -> Result<BulkOperation, String> {
+        let operation_id = Uuid::new_v4().simple().to_string();
+        let now = Utc::now();
+
+        self.db
+            .query("CREATE type::thing('bulk_operations', $id) SET action = $action, filter = $filter, status = 'Previewed', subscription_ids = $subscription_ids, matched = $matched, processed = 0, succeeded = 0, skipped = 0, failures = [], created_at = $now, expires_at = $expires_at RETURN NONE")
+            .bind(("id", operation_id.clone()))
+            .bind(("action", dto.action))
+            .bind(("filter", dto.filter))
+            .bind(("matched", subscription_ids.len()))
+            .bind(("subscription_ids", subscription_ids))
+            .bind(("now", now))
+            .bind(("expires_at", now + Duration::minutes(BULK_PREVIEW_TTL_MINUTES)))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| format!("Failed to create bulk operation: {}", e))?;
+
+        self.get_bulk_operation(&operation_id).await
+            .ok_or_else(|| "Failed to create bulk operation: no result returned".to_string())
+    }
+
+    pub async fn get_bulk_operation(&self, operation_id: &str) -> Option<BulkOperation> {
+        let result: Result<Vec<BulkOperation>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('bulk_operations', $id)")
+            .bind(("id", operation_id.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|operations| operations.into_iter().next())
+    }
+
+    /// Moves a previewed operation that hasn't expired to `Running`. Returns
+    /// `None` if it was already confirmed or its preview expired.
+    pub async fn confirm_bulk_operation(&self, operation_id: &str) -> Result<Option<BulkOperation>, String> {
+        let now = Utc::now();
+        self.db
+            .query("UPDATE type::thing('bulk_operations', $id) SET status = 'Running', confirmed_at = $now WHERE status = 'Previewed' AND expires_at > $now RETURN NONE")
+            .bind(("id", operation_id.to_string()))
+            .bind(("now", now))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| format!("Failed to confirm bulk operation {}: {}", operation_id, e))?;
+
+        // Only the request that won the update sees its own timestamp
+        Ok(self.get_bulk_operation(operation_id).await
+            .filter(|operation| operation.confirmed_at == Some(now)))
+    }
+
+    /// Applies a bulk action to one subscription, provided it still matches
+    /// the operation's filter. Returns whether it changed.
+    pub async fn apply_bulk_action(&self, subscription_id: &str, action: &BulkAction, filter: &BulkFilter) -> Result<bool, String> {
+        let id_part = subscription_id.strip_prefix("subscriptions:").unwrap_or(subscription_id).to_string();
+        let before = self.audit_snapshot("subscriptions", &id_part).await;
+        let (changes, days, reason) = match action {
+            BulkAction::ExtendPeriod { days } => (
+                "end_date = end_date + duration::from::days($days), grace_end_date = IF grace_end_date != NONE THEN grace_end_date + duration::from::days($days) ELSE NONE END",
+                *days,
+                None,
+            ),
+            BulkAction::Cancel { reason } => (
+                "status = 'Cancelled', cancel_at_period_end = false, cancelled_at = $now, cancellation_effective_at = $now, cancellation_reason = $reason, state_reason = $state_reason, next_renewal_attempt_at = NONE, skip_next_renewal = false",
+                0,
+                reason.clone(),
+            ),
+        };
+
+        let result: Result<Vec<Subscription>, _> = self.db
+            .query(format!("UPDATE type::thing('subscriptions', $id) SET {}, updated_at = $now WHERE {} RETURN AFTER", changes, BULK_TARGET_CONDITIONS))
+            .bind(BulkTargetParams::new(action, filter))
+            .bind(("id", id_part.clone()))
+            .bind(("days", days))
+            .bind(("reason", reason))
+            .bind(("state_reason", state_reason::CANCELLED_BY_ADMIN))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        let subscriptions = result.map_err(|e| format!("Database error: {}", e))?;
+        let Some(updated) = subscriptions.first() else { return Ok(false) };
+        let (kind, description) = match action {
+            BulkAction::ExtendPeriod { days } => ("subscription_extended", format!("Period extended by {} day(s)", days)),
+            BulkAction::Cancel { .. } => ("subscription_cancelled", "Subscription cancelled by an administrator".to_string()),
+        };
+        let action_name = match action {
+            BulkAction::ExtendPeriod { .. } => "subscription.period_extended",
+            BulkAction::Cancel { .. } => "subscription.cancelled",
+        };
+        self.audit_change(action_name, "subscriptions", &id_part, before).await;
+        self.record_subscription_activity(updated, kind, &description).await;
+        Ok(true)
+    }
+
+    /// Writes back how far a running operation has got.
+    pub async fn save_bulk_progress(&self, operation: &BulkOperation) -> Result<(), String> {
+        self.db
+            .query("UPDATE type::thing('bulk_operations', $id) SET status = $status, processed = $processed, succeeded = $succeeded, skipped = $skipped, failures = $failures, completed_at = $completed_at RETURN NONE")
+            .bind(("id", operation.id.clone()))
+            .bind(("status", format!("{:?}", operation.status)))
+            .bind(("processed", operation.processed))
+            .bind(("succeeded", operation.succeeded))
+            .bind(("skipped", operation.skipped))
+            .bind(("failures", operation.failures.clone()))
+            .bind(("completed_at", operation.completed_at))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| format!("Failed to save progress of bulk operation {}: {}", operation.id, e))?;
+        Ok(())
+    }
+
+    // ---------------------
+    // Rate limits
+    // ---------------------
+
+    /// Counts one request against the window counter `key`, returning the
+    /// count so far. The increment is atomic across instances.
+    pub async fn hit_rate_limit(&self, key: &str, expires_at: DateTime<Utc>) -> Result<u32, String> {
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query("UPSERT type::thing('rate_limits', $key) SET count = (count ?? 0) + 1, expires_at = $expires_at RETURN AFTER")
+            .bind(("key", key.to_string()))
+            .bind(("expires_at", expires_at))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result
+            .map_err(|e| format!("Database error: {}", e))?
+            .first()
+            .and_then(|row| row.get("count"))
+            .and_then(|v| v.as_u64())
+            .map(|count| count as u32)
+            .ok_or_else(|| format!("Rate limit counter {} returned no count", key))
+    }
+
+    pub async fn purge_expired_rate_limits(&self) -> Result<(), String> {
+        self.db
+            .query("DELETE rate_limits WHERE expires_at < time::now()")
+            .await
+            .and_then(|response| response.check())
+            .map(|_| ())
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Remembers a verified request signature until `expires_at`. Returns
+    /// false if it was already seen, on this or any other instance.
+    pub async fn record_request_signature(&self, key: &str, expires_at: DateTime<Utc>) -> Result<bool, String> {
+        let result = self.db
+            .query("CREATE type::thing('request_signatures', $key) SET expires_at = $expires_at RETURN NONE")
+            .bind(("key", key.to_string()))
+            .bind(("expires_at", expires_at))
+            .await
+            .and_then(|response| response.check());
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if e.to_string().contains("already exists") => Ok(false),
             Err(e) => Err(format!("Database error: {}", e)),
         }
     }
 
-    pub async fn create_test_notification(&self, user_id: String, message: String) -> Result<(), String> {
-        let notification_id = Uuid::new_v4().simple().to_string();
-        let query = format!(r#"
-            CREATE notification:{} SET
-                user_id = $user_id,
-                subscription_id = "test-subscription",
-                message = $message,
-                acknowledged = false,
-                created_at = time::now()
-        "#, notification_id);
+    pub async fn purge_expired_request_signatures(&self) -> Result<(), String> {
+        self.db
+            .query("DELETE request_signatures WHERE expires_at < time::now()")
+            .await
+            .and_then(|response| response.check())
+            .map(|_| ())
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // ---------------------
+    // Reports
+    // ---------------------
+
+    /// Aggregates one UTC day's billing activity in a single round trip.
+    pub async fn get_daily_summary(&self, date: NaiveDate, fx: &FxService) -> Result<DailySummary, String> {
+        let from = date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+        let to = from + Duration::days(1);
+
+        let query = r#"
+            SELECT currency, count() AS count, math::sum(amount) AS total FROM payments WHERE status = 'Completed' AND (value_date ?? updated_at) >= $from AND (value_date ?? updated_at) < $to AND ($tenant = NONE OR tenant_id = $tenant) GROUP BY currency;
+            SELECT count() AS count FROM payments WHERE status = 'Failed' AND updated_at >= $from AND updated_at < $to AND ($tenant = NONE OR tenant_id = $tenant) GROUP ALL;
+            SELECT currency, count() AS count, math::sum(amount) AS total FROM refunds WHERE status = 'Completed' AND updated_at >= $from AND updated_at < $to GROUP BY currency;
+            SELECT count() AS count FROM activity_events WHERE kind = 'subscription_renewed' AND created_at >= $from AND created_at < $to GROUP ALL;
+            SELECT count() AS count FROM activity_events WHERE kind = 'renewal_failed' AND created_at >= $from AND created_at < $to GROUP ALL;
+            SELECT count() AS count FROM subscriptions WHERE created_at >= $from AND created_at < $to AND ($tenant = NONE OR tenant_id = $tenant) GROUP ALL;
+            SELECT count() AS count FROM subscriptions WHERE status = 'Cancelled' AND updated_at >= $from AND updated_at < $to AND ($tenant = NONE OR tenant_id = $tenant) GROUP ALL;
+            SELECT count() AS count FROM activity_events WHERE kind = 'subscription_suspended' AND created_at >= $from AND created_at < $to GROUP ALL;
+            SELECT count() AS count FROM webhook_events WHERE outcome IN ['Failed', 'Rejected'] AND created_at >= $from AND created_at < $to GROUP ALL;
+        "#;
+
+        let mut response = self.db
+            .query(query)
+            .bind(("from", from))
+            .bind(("to", to))
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        // Money queries return one row per currency; GROUP ALL returns no row
+        // at all when nothing matched
+        let mut rows = |index: usize| -> Result<(u64, BTreeMap<String, f64>), String> {
+            let rows: Vec<serde_json::Value> = response
+                .take(index)
+                .map_err(|e| format!("Database error: {}", e))?;
+            let mut count = 0;
+            let mut totals = BTreeMap::new();
+            for row in rows {
+                count += row.get("count").and_then(|v| v.as_u64()).unwrap_or(0);
+                if let Some(total) = row.get("total").and_then(|v| v.as_f64()) {
+                    let currency = row.get("currency").and_then(|v| v.as_str()).unwrap_or(DEFAULT_CURRENCY);
+                    *totals.entry(currency.to_string()).or_insert(0.0) += total;
+                }
+            }
+            Ok((count, totals))
+        };
+        let (payments_succeeded, revenue_by_currency) = rows(0)?;
+        let (payments_failed, _) = rows(1)?;
+        let (_, refunded_by_currency) = rows(2)?;
+        let (renewals_succeeded, _) = rows(3)?;
+        let (renewals_failed, _) = rows(4)?;
+        let (new_subscriptions, _) = rows(5)?;
+        let (cancelled_subscriptions, _) = rows(6)?;
+        let (suspended_subscriptions, _) = rows(7)?;
+        let (webhook_errors, _) = rows(8)?;
+
+        let mut unconverted_currencies = Vec::new();
+        let mut to_base = |totals: &BTreeMap<String, f64>| -> f64 {
+            let mut sum = 0.0;
+            for (currency, total) in totals {
+                match fx.to_base(*total, currency) {
+                    Some(converted) => sum += converted,
+                    None if !unconverted_currencies.contains(currency) => unconverted_currencies.push(currency.clone()),
+                    None => {}
+                }
+            }
+            sum
+        };
+        let revenue = to_base(&revenue_by_currency);
+        let refunded = to_base(&refunded_by_currency);
+
+        Ok(DailySummary {
+            date,
+            from,
+            to,
+            payments_succeeded,
+            payments_failed,
+            currency: fx.base_currency().to_string(),
+            revenue,
+            refunded,
+            revenue_by_currency,
+            refunded_by_currency,
+            unconverted_currencies,
+            renewals_succeeded,
+            renewals_failed,
+            new_subscriptions,
+            cancelled_subscriptions,
+            suspended_subscriptions,
+            webhook_errors,
+        })
+    }
+
+    /// Subscriptions that were paying at some point in `[from, to)`, for
+    /// metrics. Ones that stopped before `from` were last updated then too,
+    /// so `updated_at` rules them out.
+    pub async fn get_subscriptions_paying_between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<Subscription>, String> {
+        let query = r#"
+            SELECT *, record::id(id) AS id FROM subscriptions
+            WHERE start_date != NONE AND start_date < $to
+                AND (status IN ['Active', 'Grace', 'Pending'] OR updated_at >= $from)
+                AND ($tenant = NONE OR tenant_id = $tenant)
+        "#;
 
         self.db
             .query(query)
-            .bind(("user_id", user_id.clone()))
-            .bind(("message", message.clone()))
+            .bind(("from", from))
+            .bind(("to", to))
             .await
-            .map_err(|e| format!("Failed to create test notification: {}", e))?;
-        
-        println!("📝 Test notification created for user {}: {}", user_id, message);
-        Ok(())
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Payments that completed or failed in `[from, to)`.
+    pub async fn get_payment_outcomes(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<PaymentOutcome>, String> {
+        let query = r#"
+            SELECT status = 'Completed' AS succeeded, (value_date ?? updated_at) AS settled_at FROM payments
+            WHERE status IN ['Completed', 'Failed']
+                AND (value_date ?? updated_at) >= $from AND (value_date ?? updated_at) < $to
+                AND ($tenant = NONE OR tenant_id = $tenant)
+        "#;
+
+        self.db
+            .query(query)
+            .bind(("from", from))
+            .bind(("to", to))
+            .await
+            .and_then(|mut response| response.take(0))
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    // ---------------------
+    // Data exports
+    // ---------------------
+
+    /// One page of `table` rows whose `timestamp_field` falls in
+    /// `(after, until]`, oldest first, as raw JSON.
+    pub async fn get_export_rows(
+        &self,
+        table: &str,
+        timestamp_field: &str,
+        after: Option<DateTime<Utc>>,
+        until: DateTime<Utc>,
+        start: u32,
+        limit: u32,
+    ) -> Result<Vec<serde_json::Value>, String> {
+        // `table` and `timestamp_field` are our own names, never user input
+        let query = format!(
+            "SELECT *, record::id(id) AS id FROM {table} WHERE ($after = NONE OR {field} > $after) AND {field} <= $until ORDER BY {field} ASC, id ASC LIMIT $limit START $start",
+            table = table,
+            field = timestamp_field,
+        );
+        let result: Result<Vec<serde_json::Value>, _> = self.db
+            .query(query)
+            .bind(("after", after))
+            .bind(("until", until))
+            .bind(("limit", limit))
+            .bind(("start", start))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// The most recent completed export, whose window end the next run starts from.
+    pub async fn get_last_completed_export(&self) -> Result<Option<ExportRun>, String> {
+        let result: Result<Vec<ExportRun>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM export_runs WHERE status = $completed ORDER BY window_end DESC LIMIT 1")
+            .bind(("completed", ExportStatus::Completed))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result
+            .map(|runs| runs.into_iter().next())
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn record_export_run(&self, run: &ExportRun) -> Result<(), String> {
+        self.db
+            .query(r#"
+                CREATE type::thing('export_runs', $id) SET
+                    status = $status,
+                    window_start = $window_start,
+                    window_end = $window_end,
+                    manifest_key = $manifest_key,
+                    files = $files,
+                    error = $error,
+                    started_at = $started_at,
+                    completed_at = $completed_at
+            "#)
+            .bind(("id", run.id.clone()))
+            .bind(("status", run.status))
+            .bind(("window_start", run.window_start))
+            .bind(("window_end", run.window_end))
+            .bind(("manifest_key", run.manifest_key.clone()))
+            .bind(("files", run.files.clone()))
+            .bind(("error", run.error.clone()))
+            .bind(("started_at", run.started_at))
+            .bind(("completed_at", run.completed_at))
+            .await
+            .and_then(|r| r.check())
+            .map(|_| ())
+            .map_err(|e| format!("Failed to record export run: {}", e))
+    }
+
+    /// Export runs, newest first.
+    pub async fn list_export_runs(&self, limit: u32) -> Result<Vec<ExportRun>, String> {
+        let result: Result<Vec<ExportRun>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM export_runs ORDER BY started_at DESC LIMIT $limit")
+            .bind(("limit", limit))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    // ---------------------
+    // Reconciliation
+    // ---------------------
+
+    /// Our payments for the gateway's merchant transaction ids.
+    pub async fn get_payments_by_merchant_ids(&self, merchant_transaction_ids: Vec<String>) -> Result<Vec<Payment>, String> {
+        let result: Result<Vec<Payment>, _> = self.db
+            .query("SELECT * FROM payments WHERE merchant_transaction_id IN $merchant_ids AND ($tenant = NONE OR tenant_id = $tenant)")
+            .bind(("merchant_ids", merchant_transaction_ids))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Payments created in `[from, to)` that `gateway` charged or reserved
+    /// money for, and so should appear in its report. Tenants in
+    /// `other_accounts` have their own gateway account and are left out.
+    pub async fn get_gateway_payments_between(
+        &self,
+        gateway: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        other_accounts: Vec<String>,
+    ) -> Result<Vec<Payment>, String> {
+        let query = r#"
+            SELECT * FROM payments
+            WHERE status IN ['Authorized', 'Completed', 'PartiallyRefunded', 'Refunded']
+                AND created_at >= $from AND created_at < $to
+                AND (gateway = NONE OR gateway = $gateway)
+                AND amount > wallet_amount
+                AND ($tenant = NONE OR tenant_id = $tenant)
+                AND (tenant_id = NONE OR tenant_id NOT IN $other_accounts)
+        "#;
+        let result: Result<Vec<Payment>, _> = self.db
+            .query(query)
+            .bind(("gateway", gateway.to_string()))
+            .bind(("from", from))
+            .bind(("to", to))
+            .bind(("other_accounts", other_accounts))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn record_reconciliation_report(&self, report: &ReconciliationReport) -> Result<(), String> {
+        self.db
+            .query(r#"
+                CREATE type::thing('reconciliation_reports', $id) SET
+                    source = $source,
+                    tenant_id = $tenant_id,
+                    window_start = $window_start,
+                    window_end = $window_end,
+                    records = $records,
+                    matched = $matched,
+                    mismatches = $mismatches,
+                    created_at = $created_at
+            "#)
+            .bind(("id", report.id.clone()))
+            .bind(("source", report.source))
+            .bind(("tenant_id", report.tenant_id.clone()))
+            .bind(("window_start", report.window_start))
+            .bind(("window_end", report.window_end))
+            .bind(("records", report.records))
+            .bind(("matched", report.matched))
+            .bind(("mismatches", report.mismatches.clone()))
+            .bind(("created_at", report.created_at))
+            .await
+            .and_then(|r| r.check())
+            .map(|_| ())
+            .map_err(|e| format!("Failed to record reconciliation report: {}", e))
+    }
+
+    /// Reconciliation reports, newest first.
+    pub async fn list_reconciliation_reports(&self, limit: u32) -> Result<Vec<ReconciliationReport>, String> {
+        let result: Result<Vec<ReconciliationReport>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM reconciliation_reports WHERE ($tenant = NONE OR tenant_id = $tenant) ORDER BY created_at DESC LIMIT $limit")
+            .bind(("limit", limit))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn get_reconciliation_report(&self, report_id: &str) -> Option<ReconciliationReport> {
+        let id_part = report_id.strip_prefix("reconciliation_reports:").unwrap_or(report_id);
+        let result: Result<Vec<ReconciliationReport>, _> = self.db
+            .query("SELECT *, record::id(id) AS id FROM type::thing('reconciliation_reports', $id) WHERE ($tenant = NONE OR tenant_id = $tenant)")
+            .bind(("id", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.ok().and_then(|reports| reports.into_iter().next())
+    }
+
+    // ---------------------
+    // Billing consistency
+    // ---------------------
+
+    pub async fn get_billed_subscriptions(&self) -> Result<Vec<Subscription>, String> {
+        let result: Result<Vec<Subscription>, _> = self.db
+            .query("SELECT * FROM subscriptions WHERE status IN ['Active', 'Grace', 'Suspended'] AND ($tenant = NONE OR tenant_id = $tenant)")
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Payments reference subscriptions by either the full record id or its bare key.
+    pub async fn get_payments_by_subscription(&self, subscription_id: &str) -> Vec<Payment> {
+        let id_part = subscription_id.strip_prefix("subscriptions:").unwrap_or(subscription_id);
+
+        let result: Result<Vec<Payment>, _> = self.db
+            .query("SELECT * FROM payments WHERE subscription_id IN [$full_id, $id_part] AND ($tenant = NONE OR tenant_id = $tenant) ORDER BY created_at DESC")
+            .bind(("full_id", format!("subscriptions:{}", id_part)))
+            .bind(("id_part", id_part.to_string()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        result.unwrap_or_default()
+    }
+
+    pub async fn set_subscription_billing_dates(
+        &self,
+        subscription_id: &str,
+        start_date: DateTime<Utc>,
+        end_date: DateTime<Utc>,
+        grace_end_date: DateTime<Utc>,
+    ) -> Result<Subscription, String> {
+        let id_part = subscription_id.strip_prefix("subscriptions:").unwrap_or(subscription_id);
+        let before = self.audit_snapshot("subscriptions", id_part).await;
+
+        let result: Result<Vec<Subscription>, _> = self.db
+            .query("UPDATE type::thing('subscriptions', $id) SET start_date = $start, end_date = $end, grace_end_date = $grace_end, renewal_reminder_sent_for = NONE, updated_at = $now WHERE ($tenant = NONE OR tenant_id = $tenant) RETURN AFTER")
+            .bind(("id", id_part.to_string()))
+            .bind(("start", start_date))
+            .bind(("end", end_date))
+            .bind(("grace_end", grace_end_date))
+            .bind(("now", Utc::now()))
+            .await
+            .and_then(|mut response| response.take(0));
+
+        match result {
+            Ok(mut subscriptions) if !subscriptions.is_empty() => {
+                let subscription = subscriptions.remove(0);
+                info!("Recomputed billing dates for subscription {}", subscription_id);
+                self.audit_change("subscription.billing_dates_set", "subscriptions", id_part, before).await;
+                self.record_subscription_activity(&subscription, "billing_dates_recomputed", "Billing dates recomputed by an administrator").await;
+                Ok(subscription)
+            }
+            Ok(_) => Err(format!("Subscription not found: {}", subscription_id)),
+            Err(e) => Err(format!("Database error: {}", e)),
+        }
     }
 
-        
     // ---------------------
     // Debug utilities (converted to async)
     // ---------------------
     pub async fn debug_list_payments(&self) -> Vec<Payment> {
         let result: Result<Vec<Payment>, _> = self.db
-            .query("SELECT * FROM payments")
+            .query("SELECT * FROM payments WHERE ($tenant = NONE OR tenant_id = $tenant)")
             .await
             .and_then(|mut response| response.take(0));
                     
@@ -790,7 +6475,7 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
 
     pub async fn debug_list_subscriptions(&self) -> Vec<Subscription> {
         let result: Result<Vec<Subscription>, _> = self.db
-            .query("SELECT * FROM subscriptions")
+            .query("SELECT * FROM subscriptions WHERE ($tenant = NONE OR tenant_id = $tenant)")
             .await
             .and_then(|mut response| response.take(0));
                     
@@ -799,9 +6484,9 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
 
     pub async fn debug_print_all_payments(&self) {
         let payments = self.debug_list_payments().await;
-        println!("🔍 All payments ({} total):", payments.len());
+        debug!("All payments ({} total):", payments.len());
         for (i, payment) in payments.iter().enumerate() {
-            println!(
+            info!(
                 "{}. ID: {}, MerchantTxnId: {}, Status: {:?}, Amount: {}, CheckoutId: {:?}",
                 i + 1,
                 payment.id,
@@ -815,7 +6500,7 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
 
     pub async fn get_payment_count(&self) -> usize {
         let result: Result<Vec<serde_json::Value>, _> = self.db
-            .query("SELECT count() FROM payments GROUP ALL")
+            .query("SELECT count() FROM payments WHERE ($tenant = NONE OR tenant_id = $tenant) GROUP ALL")
             .await
             .and_then(|mut response| response.take(0));
                     
@@ -824,7 +6509,7 @@ pub async fn create_payment(&self, payment_dto: CreatePaymentDto) -> Result<Paym
 
     pub async fn get_subscription_count(&self) -> usize {
         let result: Result<Vec<serde_json::Value>, _> = self.db
-            .query("SELECT count() FROM subscriptions GROUP ALL")
+            .query("SELECT count() FROM subscriptions WHERE ($tenant = NONE OR tenant_id = $tenant) GROUP ALL")
             .await
             .and_then(|mut response| response.take(0));
                     
@@ -836,6 +6521,82 @@ impl Default for DatabaseService {
     fn default() -> Self {
         // Note: This will panic if called synchronously.         
         // Consider removing Default implementation or using a different approach        
-        panic!("Use DatabaseService::new().await instead of default()")
+        panic!("Use DatabaseService::new(payment_events).await instead of default()")
+    }
+}
+
+/// A pause length for activity descriptions, e.g. `3d 4h`.
+fn format_paused(duration: Duration) -> String {
+    let hours = duration.num_hours();
+    match (hours / 24, hours % 24) {
+        (0, 0) => format!("{}m", duration.num_minutes()),
+        (0, h) => format!("{}h", h),
+        (d, 0) => format!("{}d", d),
+        (d, h) => format!("{}d {}h", d, h),
+    }
+}
+
+/// Most notifications `create_notifications` writes per query.
+const NOTIFICATION_BATCH_SIZE: usize = 500;
+
+/// Which subscriptions a bulk operation may touch, given `BulkTargetParams`.
+/// Extensions need a period to extend.
+const BULK_TARGET_CONDITIONS: &str = "status NOT IN ['Cancelled', 'Expired'] \
+    AND ($tenant = NONE OR tenant_id = $tenant) \
+    AND ($needs_period = false OR end_date != NONE) \
+    AND ($status = NONE OR status = $status) \
+    AND ($plan_id = NONE OR plan_id = $plan_id) \
+    AND ($tag = NONE OR tags CONTAINS $tag) \
+    AND ($created_before = NONE OR created_at < $created_before)";
+
+/// Bound as query parameters for `BULK_TARGET_CONDITIONS`.
+/// When one of a schedule's later installments falls due.
+#[derive(serde::Serialize)]
+struct InstallmentDue {
+    sequence: u32,
+    due_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct BulkTargetParams {
+    needs_period: bool,
+    status: Option<String>,
+    plan_id: Option<String>,
+    tag: Option<String>,
+    created_before: Option<DateTime<Utc>>,
+}
+
+impl BulkTargetParams {
+    fn new(action: &BulkAction, filter: &BulkFilter) -> Self {
+        Self {
+            needs_period: matches!(action, BulkAction::ExtendPeriod { .. }),
+            status: filter.status.as_ref().map(|status| format!("{:?}", status)),
+            plan_id: filter.plan_id.clone(),
+            tag: filter.tag.clone(),
+            created_before: filter.created_more_than_days_ago.map(|days| Utc::now() - Duration::days(days as i64)),
+        }
+    }
+}
+
+/// A saved `notification_preferences` row.
+#[derive(Debug, serde::Deserialize)]
+struct SavedEventPreference {
+    user_id: String,
+    event: NotificationEvent,
+    email: bool,
+    push: bool,
+    in_app: bool,
+    sms: bool,
+}
+
+impl SavedEventPreference {
+    fn preference(&self) -> EventPreference {
+        EventPreference {
+            event: self.event,
+            email: self.email,
+            push: self.push,
+            in_app: self.in_app,
+            sms: self.sms,
+        }
     }
 }
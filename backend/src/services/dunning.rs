@@ -0,0 +1,126 @@
+use chrono::{DateTime, Duration, Utc};
+use crate::config::AppConfig;
+
+/// Retry policy for failed auto-renewal charges.
+#[derive(Debug, Clone)]
+pub struct DunningPolicy {
+    /// Delay before each retry, indexed by the number of failures so far.
+    pub retry_schedule_days: Vec<i64>,
+    /// Total charge attempts (initial + retries) before the subscription is suspended.
+    pub max_attempts: u32,
+    /// When set, exhausted subscriptions are downgraded to this plan instead.
+    pub fallback_plan_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DunningAction {
+    RetryAt(DateTime<Utc>),
+    Suspend,
+    /// Retries ran out; move the subscription to the fallback plan.
+    Downgrade { plan_id: String },
+    /// The stored card can't succeed again; stop retrying and ask for a new one.
+    RequireNewCard,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailureClass {
+    /// Card closed, lost, stolen, expired or otherwise permanently unusable.
+    HardDecline,
+    /// Insufficient funds, limits, issuer or network trouble — worth retrying.
+    SoftDecline,
+}
+
+impl FailureClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureClass::HardDecline => "hard_decline",
+            FailureClass::SoftDecline => "soft_decline",
+        }
+    }
+}
+
+/// Result codes (Peach/OPP and Stripe) that mean retrying the same token is pointless.
+const HARD_DECLINE_CODES: &[&str] = &[
+    "100.100.101", // invalid card or account number
+    "100.100.303", // card expired
+    "100.150.200", // registration does not exist
+    "100.150.202", // registration has been deregistered
+    "800.100.151", // invalid card number
+    "800.100.153", // invalid CVV
+    "800.100.157", // wrong expiry date
+    "800.100.158", // suspected manipulation
+    "800.100.159", // stolen card
+    "800.100.160", // card blocked
+    "800.100.161", // too many invalid tries
+    "800.100.165", // card lost
+    "800.100.168", // restricted card
+    "800.100.170", // transaction not permitted
+    "800.100.171", // pick up card
+    // Stripe decline codes
+    "expired_card",
+    "incorrect_number",
+    "invalid_account",
+    "lost_card",
+    "pickup_card",
+    "restricted_card",
+    "stolen_card",
+    "resource_missing", // payment method no longer attached
+];
+
+/// Classifies a failed recurring charge. Unknown codes are treated as soft so
+/// they follow the retry schedule rather than dropping the card prematurely.
+pub fn classify_failure(result_code: &str) -> FailureClass {
+    if HARD_DECLINE_CODES.contains(&result_code)
+        || result_code.starts_with("100.150.")
+        || result_code.starts_with("800.120.")
+    {
+        FailureClass::HardDecline
+    } else {
+        FailureClass::SoftDecline
+    }
+}
+
+/// Short snake_case reason for a declined charge, used in `state_reason`.
+pub fn decline_reason(result_code: &str) -> &'static str {
+    match result_code {
+        "800.100.155" | "800.100.203" | "insufficient_funds" => "insufficient_funds",
+        "100.100.303" | "expired_card" => "card_expired",
+        "800.100.159" | "800.100.165" | "800.100.171" | "lost_card" | "stolen_card" | "pickup_card" => "card_lost_or_stolen",
+        "800.100.162" | "800.100.163" | "card_velocity_exceeded" => "limit_exceeded",
+        // 3-D Secure: the shopper failed the challenge or the 3DS system rejected it
+        code if code.starts_with("100.380.4") || code.starts_with("100.390.") => "authentication_failed",
+        "authentication_required" | "payment_intent_authentication_failure" => "authentication_failed",
+        _ => match classify_failure(result_code) {
+            FailureClass::HardDecline => "card_declined",
+            FailureClass::SoftDecline => "declined",
+        },
+    }
+}
+
+impl DunningPolicy {
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            retry_schedule_days: config.renewal_retry_schedule_days.clone(),
+            max_attempts: config.max_renewal_attempts,
+            fallback_plan_id: config.dunning_fallback_plan_id.clone(),
+        }
+    }
+
+    /// Decides what happens after a failure. `failed_attempts` includes the
+    /// attempt that just failed.
+    pub fn next_action(&self, failed_attempts: u32, class: FailureClass, now: DateTime<Utc>) -> DunningAction {
+        if class == FailureClass::HardDecline {
+            return DunningAction::RequireNewCard;
+        }
+
+        if failed_attempts >= self.max_attempts || self.retry_schedule_days.is_empty() {
+            return match &self.fallback_plan_id {
+                Some(plan_id) => DunningAction::Downgrade { plan_id: plan_id.clone() },
+                None => DunningAction::Suspend,
+            };
+        }
+
+        let idx = (failed_attempts.saturating_sub(1) as usize).min(self.retry_schedule_days.len() - 1);
+        DunningAction::RetryAt(now + Duration::days(self.retry_schedule_days[idx]))
+    }
+}
@@ -0,0 +1,491 @@
+use std::env;
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use reqwest::Client;
+use serde::Serialize;
+use utoipa::ToSchema;
+use serde_json::json;
+use tracing::{error, info, warn};
+use crate::models::invoice::Invoice;
+use crate::models::notification::{NotificationChannel, NotificationEvent};
+use crate::models::template::TemplateChannel;
+use crate::models::report::DailySummary;
+use crate::services::database::DatabaseService;
+use crate::services::formatting::{Formatting, Locale};
+use crate::services::i18n;
+use crate::services::invoicing::{customer_lines, seller_lines};
+use crate::services::templates::{substitute, TemplateRegistry, DEFAULT_LOCALE};
+
+/// Events that trigger an email to the subscriber.
+#[derive(Debug, Clone)]
+pub enum EmailEvent {
+    /// `descriptor` is the card statement text the charge was sent with;
+    /// `invoice`, when issued, is included as the tax invoice.
+    PaymentSucceeded {
+        plan: String,
+        amount: f64,
+        currency: String,
+        reference: String,
+        descriptor: Option<String>,
+        invoice: Option<Box<Invoice>>,
+    },
+    PaymentFailed { plan: String, amount: f64, currency: String, reference: String },
+    UpcomingRenewal { plan: String, amount: f64, currency: String, renewal_date: DateTime<Utc> },
+    SubscriptionSuspended { plan: String },
+    SubscriptionDowngraded { plan: String },
+    /// Dunning ran out and the subscription now grants `fallback_plan` instead.
+    SubscriptionDowngradedToFallback { plan: String, fallback_plan: String },
+    /// Access ends at `ends_at`, which is now for immediate cancellations.
+    SubscriptionCancelled { plan: String, ends_at: DateTime<Utc> },
+    /// `card` describes the new card, e.g. "VISA ending in 4242".
+    CardUpdated { card: String },
+    /// `expiry` is the card's expiry month, e.g. `05/2027`.
+    CardExpiring { card: String, expiry: String },
+    /// Sent to operators, not subscribers.
+    DailySummary(DailySummary),
+    /// Sent to operators when a gateway's webhooks stop arriving.
+    WebhookSilence { gateway: String, last_processed_at: Option<DateTime<Utc>>, silent_hours: i64 },
+}
+
+impl EmailEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EmailEvent::PaymentSucceeded { .. } => "payment_succeeded",
+            EmailEvent::PaymentFailed { .. } => "payment_failed",
+            EmailEvent::UpcomingRenewal { .. } => "renewal_upcoming",
+            EmailEvent::SubscriptionSuspended { .. } => "subscription_suspended",
+            EmailEvent::SubscriptionDowngraded { .. } => "subscription_downgraded",
+            EmailEvent::SubscriptionDowngradedToFallback { .. } => "subscription_downgraded_to_fallback",
+            EmailEvent::SubscriptionCancelled { .. } => "subscription_cancelled",
+            EmailEvent::CardUpdated { .. } => "card_updated",
+            EmailEvent::CardExpiring { .. } => "card_expiring",
+            EmailEvent::DailySummary(_) => "daily_summary",
+            EmailEvent::WebhookSilence { .. } => "webhook_silence",
+        }
+    }
+
+    /// The preference that decides whether the subscriber gets this email;
+    /// `None` for operator emails.
+    pub fn notification_event(&self) -> Option<NotificationEvent> {
+        match self {
+            EmailEvent::PaymentSucceeded { .. } => Some(NotificationEvent::PaymentSucceeded),
+            EmailEvent::PaymentFailed { .. } => Some(NotificationEvent::PaymentFailed),
+            EmailEvent::UpcomingRenewal { .. } => Some(NotificationEvent::RenewalDue),
+            EmailEvent::SubscriptionSuspended { .. }
+            | EmailEvent::SubscriptionDowngraded { .. }
+            | EmailEvent::SubscriptionDowngradedToFallback { .. }
+            | EmailEvent::SubscriptionCancelled { .. } => Some(NotificationEvent::SubscriptionChanged),
+            EmailEvent::CardUpdated { .. } | EmailEvent::CardExpiring { .. } => Some(NotificationEvent::CardAction),
+            EmailEvent::DailySummary(_) | EmailEvent::WebhookSilence { .. } => None,
+        }
+    }
+
+    fn variables(&self, fmt: &Formatting) -> Vec<(&'static str, String)> {
+        match self {
+            EmailEvent::PaymentSucceeded { plan, amount, currency, reference, descriptor, invoice } => vec![
+                ("plan", plan.clone()),
+                ("amount", fmt.amount(*amount, currency)),
+                ("reference", reference.clone()),
+                ("statement_line", descriptor
+                    .as_ref()
+                    .map(|d| format!(
+                        "{}\n",
+                        i18n::translate_with(
+                            fmt.locale,
+                            "It will appear on your card statement as \"{{descriptor}}\".",
+                            &[("descriptor", d.clone())],
+                        ),
+                    ))
+                    .unwrap_or_default()),
+                ("tax_invoice", invoice
+                    .as_ref()
+                    .map(|invoice| format!("{}\n", render_tax_invoice(invoice, fmt)))
+                    .unwrap_or_default()),
+            ],
+            EmailEvent::PaymentFailed { plan, amount, currency, reference } => vec![
+                ("plan", plan.clone()),
+                ("amount", fmt.amount(*amount, currency)),
+                ("reference", reference.clone()),
+            ],
+            EmailEvent::UpcomingRenewal { plan, amount, currency, renewal_date } => vec![
+                ("plan", plan.clone()),
+                ("amount", fmt.amount(*amount, currency)),
+                ("renewal_date", fmt.date(renewal_date)),
+            ],
+            EmailEvent::SubscriptionSuspended { plan }
+            | EmailEvent::SubscriptionDowngraded { plan } => vec![("plan", plan.clone())],
+            EmailEvent::SubscriptionDowngradedToFallback { plan, fallback_plan } => vec![
+                ("plan", plan.clone()),
+                ("fallback_plan", fallback_plan.clone()),
+            ],
+            EmailEvent::SubscriptionCancelled { plan, ends_at } => vec![
+                ("plan", plan.clone()),
+                ("ends_at", fmt.date(ends_at)),
+            ],
+            EmailEvent::CardUpdated { card } => vec![("card", card.clone())],
+            EmailEvent::CardExpiring { card, expiry } => vec![
+                ("card", card.clone()),
+                ("expiry", expiry.clone()),
+            ],
+            EmailEvent::DailySummary(summary) => vec![
+                ("date", summary.date.to_string()),
+                ("payments_succeeded", summary.payments_succeeded.to_string()),
+                ("payments_failed", summary.payments_failed.to_string()),
+                ("revenue", fmt.amount(summary.revenue, &summary.currency)),
+                ("refunded", fmt.amount(summary.refunded, &summary.currency)),
+                ("net_revenue", fmt.amount(summary.net_revenue(), &summary.currency)),
+                ("renewals_succeeded", summary.renewals_succeeded.to_string()),
+                ("renewals_failed", summary.renewals_failed.to_string()),
+                ("new_subscriptions", summary.new_subscriptions.to_string()),
+                ("cancelled_subscriptions", summary.cancelled_subscriptions.to_string()),
+                ("suspended_subscriptions", summary.suspended_subscriptions.to_string()),
+                ("webhook_errors", summary.webhook_errors.to_string()),
+            ],
+            EmailEvent::WebhookSilence { gateway, last_processed_at, silent_hours } => vec![
+                ("gateway", gateway.clone()),
+                ("last_processed_at", last_processed_at.map(|at| fmt.datetime(&at)).unwrap_or_else(|| "never".to_string())),
+                ("silent_hours", silent_hours.to_string()),
+            ],
+        }
+    }
+}
+
+/// Splits rendered text whose first line is `Subject: ...` into subject and body.
+fn split_subject(text: &str) -> (String, String) {
+    let (first_line, rest) = text.split_once('\n').unwrap_or((text, ""));
+    let subject = first_line.strip_prefix("Subject:").unwrap_or(first_line).trim().to_string();
+    (subject, rest.trim_start_matches('\n').to_string())
+}
+
+/// The plain-text tax invoice included in receipts, with the fields SARS
+/// requires of a full tax invoice.
+fn render_tax_invoice(invoice: &Invoice, fmt: &Formatting) -> String {
+    let currency = invoice.currency.as_str();
+    let block = |name: &str, lines: Vec<String>| {
+        std::iter::once(name.to_string()).chain(lines).map(|line| format!("{}\n", line)).collect::<String>()
+    };
+    let line_items = invoice.line_items
+        .iter()
+        .map(|item| format!(
+            "{} x {} at {} = {}\n",
+            item.quantity,
+            item.description,
+            fmt.amount(item.unit_price, currency),
+            fmt.amount(item.amount, currency),
+        ))
+        .collect::<String>();
+
+    let template = match fmt.locale {
+        Locale::AfZa => include_str!("../../templates/email/af/tax_invoice.txt"),
+        Locale::EnZa | Locale::EnGb | Locale::EnUs => include_str!("../../templates/email/tax_invoice.txt"),
+    };
+    substitute(template, &[
+        ("invoice_number", invoice.invoice_number.clone()),
+        ("issued_at", fmt.date(&invoice.issued_at)),
+        ("seller", block(&invoice.seller_name, seller_lines(invoice, fmt.locale))),
+        ("customer", block(&invoice.customer_name, customer_lines(invoice, fmt.locale))),
+        ("line_items", line_items),
+        ("subtotal", fmt.amount(invoice.subtotal, currency)),
+        ("vat_rate_percent", invoice.vat_rate_percent.to_string()),
+        ("vat_amount", fmt.amount(invoice.vat_amount, currency)),
+        ("total", fmt.amount(invoice.total, currency)),
+    ])
+}
+
+/// A rendered email, as handed to the transport and kept by the in-memory one.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SentEmail {
+    pub to_address: String,
+    pub to_name: String,
+    /// `EmailEvent::name`, e.g. `renewal_upcoming`.
+    pub event: String,
+    pub subject: String,
+    pub body: String,
+    pub sent_at: DateTime<Utc>,
+}
+
+/// Where rendered emails go: a mail server, an email API, the log or an
+/// in-memory outbox.
+#[async_trait]
+pub trait EmailTransport: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// Sends `email` from `from_name <from_address>`.
+    async fn deliver(&self, from_address: &str, from_name: &str, email: &SentEmail) -> Result<(), String>;
+}
+
+struct SmtpTransport(AsyncSmtpTransport<Tokio1Executor>);
+
+#[async_trait]
+impl EmailTransport for SmtpTransport {
+    fn name(&self) -> &'static str {
+        "smtp"
+    }
+
+    async fn deliver(&self, from_address: &str, from_name: &str, email: &SentEmail) -> Result<(), String> {
+        let message = Message::builder()
+            .from(format!("{} <{}>", from_name, from_address).parse().map_err(|e| format!("Invalid from address: {}", e))?)
+            .to(format!("{} <{}>", email.to_name, email.to_address).parse().map_err(|e| format!("Invalid recipient address: {}", e))?)
+            .subject(email.subject.clone())
+            .header(ContentType::TEXT_PLAIN)
+            .body(email.body.clone())
+            .map_err(|e| format!("Failed to build email: {}", e))?;
+
+        self.0.send(message).await.map_err(|e| format!("SMTP send failed: {}", e))?;
+        Ok(())
+    }
+}
+
+struct SendGridTransport {
+    client: Client,
+    api_key: String,
+}
+
+#[async_trait]
+impl EmailTransport for SendGridTransport {
+    fn name(&self) -> &'static str {
+        "sendgrid"
+    }
+
+    async fn deliver(&self, from_address: &str, from_name: &str, email: &SentEmail) -> Result<(), String> {
+        let payload = json!({
+            "personalizations": [{ "to": [{ "email": email.to_address, "name": email.to_name }] }],
+            "from": { "email": from_address, "name": from_name },
+            "subject": email.subject,
+            "content": [{ "type": "text/plain", "value": email.body }]
+        });
+
+        let response = self.client
+            .post("https://api.sendgrid.com/v3/mail/send")
+            .bearer_auth(&self.api_key)
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| format!("SendGrid request failed: {}", e))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(format!("SendGrid error: Status {}, Body: {}", status, text));
+        }
+        Ok(())
+    }
+}
+
+/// Prints emails instead of sending them (local development).
+struct LogTransport;
+
+#[async_trait]
+impl EmailTransport for LogTransport {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    async fn deliver(&self, _from_address: &str, _from_name: &str, email: &SentEmail) -> Result<(), String> {
+        info!("[email:log] To: {} <{}>\nSubject: {}\n\n{}", email.to_name, email.to_address, email.subject, email.body);
+        Ok(())
+    }
+}
+
+/// Keeps emails in memory so tests can assert on them.
+#[derive(Default)]
+pub struct MemoryTransport {
+    outbox: Mutex<Vec<SentEmail>>,
+}
+
+#[async_trait]
+impl EmailTransport for MemoryTransport {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn deliver(&self, _from_address: &str, _from_name: &str, email: &SentEmail) -> Result<(), String> {
+        self.outbox.lock().map_err(|_| "In-memory outbox lock poisoned".to_string())?.push(email.clone());
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct EmailService {
+    transport: Arc<dyn EmailTransport>,
+    /// Set when the transport is the in-memory one, whose emails can be read back.
+    outbox: Option<Arc<MemoryTransport>>,
+    from_address: String,
+    from_name: String,
+}
+
+impl EmailService {
+    /// Reads `EMAIL_PROVIDER` (`smtp`, `sendgrid`, `log` or `memory`) and its settings.
+    pub fn from_env() -> Result<Self, String> {
+        let from_address = env::var("EMAIL_FROM_ADDRESS").unwrap_or_else(|_| "billing@example.com".to_string());
+        let from_name = env::var("EMAIL_FROM_NAME").unwrap_or_else(|_| "Billing".to_string());
+
+        let transport: Arc<dyn EmailTransport> = match env::var("EMAIL_PROVIDER").unwrap_or_else(|_| "log".to_string()).to_lowercase().as_str() {
+            "smtp" => {
+                let host = env::var("SMTP_HOST").map_err(|_| "SMTP_HOST must be set for EMAIL_PROVIDER=smtp")?;
+                let port = env::var("SMTP_PORT").ok().and_then(|p| p.parse().ok()).unwrap_or(587);
+                let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)
+                    .map_err(|e| format!("Invalid SMTP host {}: {}", host, e))?
+                    .port(port);
+                if let (Ok(user), Ok(pass)) = (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD")) {
+                    builder = builder.credentials(Credentials::new(user, pass));
+                }
+                Arc::new(SmtpTransport(builder.build()))
+            }
+            "sendgrid" => Arc::new(SendGridTransport {
+                client: Client::new(),
+                api_key: env::var("SENDGRID_API_KEY").map_err(|_| "SENDGRID_API_KEY must be set for EMAIL_PROVIDER=sendgrid")?,
+            }),
+            "log" => Arc::new(LogTransport),
+            "memory" => return Ok(Self { from_address, from_name, ..Self::in_memory() }),
+            other => return Err(format!("Unknown EMAIL_PROVIDER: {}", other)),
+        };
+
+        Ok(Self::with_transport(transport, from_address, from_name))
+    }
+
+    /// Sends through `transport`, e.g. a test double.
+    pub fn with_transport(transport: Arc<dyn EmailTransport>, from_address: String, from_name: String) -> Self {
+        Self { transport, outbox: None, from_address, from_name }
+    }
+
+    /// Keeps every email in an outbox readable with `sent_emails`, for tests.
+    pub fn in_memory() -> Self {
+        let outbox = Arc::new(MemoryTransport::default());
+        Self {
+            transport: outbox.clone(),
+            outbox: Some(outbox),
+            from_address: "billing@example.com".to_string(),
+            from_name: "Billing".to_string(),
+        }
+    }
+
+    /// Whether emails are kept in memory rather than sent, which is when the
+    /// outbox endpoints are mounted.
+    pub fn is_in_memory(&self) -> bool {
+        self.outbox.is_some()
+    }
+
+    /// Sends with the built-in English copy.
+    pub async fn send(&self, to_address: &str, to_name: &str, event: &EmailEvent) -> Result<(), String> {
+        self.send_with_templates(to_address, to_name, event, &TemplateRegistry::builtin(), DEFAULT_LOCALE).await
+    }
+
+    /// Sends in `locale` with the merchant's copy from `templates` where they
+    /// saved one.
+    pub async fn send_with_templates(
+        &self,
+        to_address: &str,
+        to_name: &str,
+        event: &EmailEvent,
+        templates: &TemplateRegistry,
+        locale: Locale,
+    ) -> Result<(), String> {
+        let fmt = Formatting::new(locale);
+        let mut vars = event.variables(&fmt);
+        vars.push(("name", to_name.to_string()));
+        let text = templates
+            .render(TemplateChannel::Email, event.name(), fmt.locale, &vars)
+            .ok_or_else(|| format!("No email template for {}", event.name()))?;
+        let (subject, body) = split_subject(&text);
+
+        let email = SentEmail {
+            to_address: to_address.to_string(),
+            to_name: to_name.to_string(),
+            event: event.name().to_string(),
+            subject,
+            body,
+            sent_at: Utc::now(),
+        };
+        self.transport.deliver(&self.from_address, &self.from_name, &email).await?;
+
+        info!("Sent {} email to {} via {}", event.name(), to_address, self.transport.name());
+        Ok(())
+    }
+
+    /// Emails captured by the in-memory transport, oldest first, optionally
+    /// only those sent to `to_address`. `None` for transports that really send.
+    pub fn sent_emails(&self, to_address: Option<&str>) -> Option<Vec<SentEmail>> {
+        let outbox = self.outbox.as_ref()?.outbox.lock().map(|sent| sent.clone()).unwrap_or_default();
+        Some(match to_address {
+            Some(address) => outbox.into_iter().filter(|e| e.to_address.eq_ignore_ascii_case(address)).collect(),
+            None => outbox,
+        })
+    }
+
+    /// Empties the in-memory outbox between test cases.
+    pub fn clear_sent_emails(&self) {
+        if let Some(Ok(mut sent)) = self.outbox.as_ref().map(|outbox| outbox.outbox.lock()) {
+            sent.clear();
+        }
+    }
+
+    /// Looks up the user and sends, unless they turned off email for the
+    /// event; failures are logged rather than returned because email must
+    /// never block billing flows.
+    pub async fn notify_user(&self, db: &DatabaseService, user_id: &str, event: EmailEvent) {
+        if let Err(e) = self.try_notify_user(db, user_id, event).await {
+            error!("Failed to email user {}: {}", user_id, e);
+        }
+    }
+
+    /// Like `notify_user`, but returns failures so queued jobs can retry them.
+    /// An email the user opted out of counts as sent.
+    pub async fn try_notify_user(&self, db: &DatabaseService, user_id: &str, event: EmailEvent) -> Result<(), String> {
+        let user = db.get_user(user_id).await
+            .ok_or_else(|| format!("Cannot email user {}: user not found", user_id))?;
+        if user.deleted_at.is_some() {
+            return Ok(());
+        }
+        if !self.email_wanted(db, user_id, &event).await {
+            info!("Not sending {} email to {}: turned off in their preferences", event.name(), user_id);
+            return Ok(());
+        }
+        let templates = TemplateRegistry::load(db).await;
+        let locale = user.locale.as_deref().and_then(Locale::from_tag).unwrap_or(DEFAULT_LOCALE);
+        self.send_with_templates(&user.email, &user.name, &event, &templates, locale).await
+    }
+
+    /// Whether the user accepts this email. If preferences can't be loaded
+    /// the email is sent, as with in-app notifications.
+    async fn email_wanted(&self, db: &DatabaseService, user_id: &str, event: &EmailEvent) -> bool {
+        let Some(kind) = event.notification_event() else {
+            return true;
+        };
+        match db.get_notification_preferences(user_id).await {
+            Ok(preferences) => preferences.allows(kind, NotificationChannel::Email),
+            Err(e) => {
+                warn!("Emailing {} without checking preferences: {}", user_id, e);
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn in_memory_keeps_sent_emails() {
+        let email = EmailService::in_memory();
+        let event = EmailEvent::UpcomingRenewal {
+            plan: "Premium".to_string(),
+            amount: 99.0,
+            currency: "ZAR".to_string(),
+            renewal_date: Utc::now(),
+        };
+        email.send("sam@example.com", "Sam", &event).await.unwrap();
+
+        let sent = email.sent_emails(Some("SAM@example.com")).unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].event, "renewal_upcoming");
+        assert!(sent[0].subject.contains("Premium"));
+
+        email.clear_sent_emails();
+        assert!(email.sent_emails(None).unwrap().is_empty());
+    }
+}
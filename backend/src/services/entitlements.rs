@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use actix_web::HttpResponse;
+use crate::models::entitlement::{FeatureEntitlement, UserEntitlements, FREE_TIER_PLAN_ID};
+use crate::models::subscription::AccessLevel;
+use crate::services::database::DatabaseService;
+
+/// Why a feature check failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeatureCheckError {
+    /// None of the user's plans include the feature.
+    NotEntitled { key: String },
+    /// The feature is included, but `usage` is over the plan's limit.
+    LimitExceeded { key: String, limit: u64, usage: u64 },
+    Database(String),
+}
+
+impl FeatureCheckError {
+    /// 403 for entitlement failures so callers can prompt an upgrade.
+    pub fn to_response(&self) -> HttpResponse {
+        match self {
+            FeatureCheckError::NotEntitled { key } => HttpResponse::Forbidden().json(serde_json::json!({
+                "error": "Feature not included in your plan",
+                "feature": key,
+            })),
+            FeatureCheckError::LimitExceeded { key, limit, usage } => HttpResponse::Forbidden().json(serde_json::json!({
+                "error": "Plan limit reached",
+                "feature": key,
+                "limit": limit,
+                "usage": usage,
+            })),
+            FeatureCheckError::Database(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to check entitlements",
+                "details": e,
+            })),
+        }
+    }
+}
+
+fn access_rank(access: AccessLevel) -> u8 {
+    match access {
+        AccessLevel::Full => 2,
+        AccessLevel::Free => 1,
+        AccessLevel::None => 0,
+    }
+}
+
+/// Features the user currently holds. Active subscriptions grant their plan's
+/// features and downgraded ones their fallback plan's (the free tier's unless
+/// dunning chose another); when several grant the same
+/// feature the most generous limit wins.
+pub async fn user_entitlements(db: &DatabaseService, user_id: &str) -> Result<UserEntitlements, String> {
+    let mut access = AccessLevel::None;
+    let mut plan_ids: Vec<String> = Vec::new();
+
+    for subscription in db.get_subscriptions_by_user(user_id).await {
+        let level = subscription.access_level();
+        let plan_id = match level {
+            AccessLevel::Full => match subscription.plan_id {
+                Some(plan_id) => plan_id,
+                None => continue,
+            },
+            AccessLevel::Free => subscription.fallback_plan_id.unwrap_or_else(|| FREE_TIER_PLAN_ID.to_string()),
+            AccessLevel::None => continue,
+        };
+        if access_rank(level) > access_rank(access) {
+            access = level;
+        }
+        if !plan_ids.contains(&plan_id) {
+            plan_ids.push(plan_id);
+        }
+    }
+
+    let mut features: BTreeMap<String, Option<u64>> = BTreeMap::new();
+    if !plan_ids.is_empty() {
+        for feature in db.get_plan_features(&plan_ids).await? {
+            features
+                .entry(feature.feature_key)
+                .and_modify(|limit| {
+                    *limit = match (*limit, feature.limit) {
+                        (Some(a), Some(b)) => Some(a.max(b)),
+                        _ => None,
+                    }
+                })
+                .or_insert(feature.limit);
+        }
+    }
+
+    Ok(UserEntitlements {
+        user_id: user_id.to_string(),
+        access,
+        plan_ids,
+        features: features
+            .into_iter()
+            .map(|(key, limit)| FeatureEntitlement { key, limit })
+            .collect(),
+    })
+}
+
+/// Returns the user's entitlement to `key`, or `NotEntitled` when no plan of
+/// theirs includes it. Endpoints gating a feature call this first.
+pub async fn check_feature(db: &DatabaseService, user_id: &str, key: &str) -> Result<FeatureEntitlement, FeatureCheckError> {
+    let entitlements = user_entitlements(db, user_id).await.map_err(FeatureCheckError::Database)?;
+    entitlements
+        .features
+        .into_iter()
+        .find(|feature| feature.key == key)
+        .ok_or_else(|| FeatureCheckError::NotEntitled { key: key.to_string() })
+}
+
+/// Like `check_feature`, but also fails when `usage` (the total the user would
+/// hold after the action, e.g. projects including the new one) is over the limit.
+pub async fn check_feature_limit(
+    db: &DatabaseService,
+    user_id: &str,
+    key: &str,
+    usage: u64,
+) -> Result<FeatureEntitlement, FeatureCheckError> {
+    let feature = check_feature(db, user_id, key).await?;
+    match feature.limit {
+        Some(limit) if !feature.allows(usage) => Err(FeatureCheckError::LimitExceeded {
+            key: key.to_string(),
+            limit,
+            usage,
+        }),
+        _ => Ok(feature),
+    }
+}
@@ -0,0 +1,191 @@
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use async_trait::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use sha2::Sha256;
+use tokio::io::AsyncWriteExt;
+use tokio::net::lookup_host;
+use crate::config::AppConfig;
+use crate::models::domain_event::DomainEvent;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Somewhere domain events are published to. An event counts as published
+/// once every configured sink has accepted it; a failure sends it to all of
+/// them again, so sinks must tolerate repeats.
+#[async_trait]
+pub trait EventSink: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn publish(&self, event: &DomainEvent) -> Result<(), String>;
+}
+
+/// POSTs each event's envelope as JSON. With a secret, `X-Event-Signature`
+/// is `sha256=` and the hex HMAC-SHA256 of `{X-Event-Timestamp}.{body}`.
+pub struct WebhookSink {
+    client: Client,
+    url: String,
+    secret: Option<Vec<u8>>,
+}
+
+impl WebhookSink {
+    pub fn new(url: String, secret: Option<String>) -> Self {
+        Self {
+            client: event_client(),
+            url,
+            secret: secret.map(String::into_bytes),
+        }
+    }
+}
+
+#[async_trait]
+impl EventSink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn publish(&self, event: &DomainEvent) -> Result<(), String> {
+        post_event(&self.client, &self.url, self.secret.as_deref(), event).await.1
+    }
+}
+
+/// POSTs the event's envelope to `url`, signed when there is a secret.
+/// Returns the response status, if a response came, and whether it was 2xx.
+pub async fn post_event(
+    client: &Client,
+    url: &str,
+    secret: Option<&[u8]>,
+    event: &DomainEvent,
+) -> (Option<u16>, Result<(), String>) {
+    let body = match serde_json::to_vec(&event.envelope()) {
+        Ok(body) => body,
+        Err(e) => return (None, Err(format!("Failed to encode event: {}", e))),
+    };
+    let timestamp = Utc::now().timestamp().to_string();
+
+    let mut request = client
+        .post(url)
+        .header("Content-Type", "application/json")
+        .header("X-Event-Id", &event.id)
+        .header("X-Event-Type", &event.event_type)
+        .header("X-Event-Timestamp", &timestamp);
+    if let Some(secret) = secret {
+        let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts any key length");
+        mac.update(timestamp.as_bytes());
+        mac.update(b".");
+        mac.update(&body);
+        request = request.header("X-Event-Signature", format!("sha256={}", hex::encode(mac.finalize().into_bytes())));
+    }
+
+    match request.body(body).send().await {
+        Ok(response) if response.status().is_success() => (Some(response.status().as_u16()), Ok(())),
+        Ok(response) if response.status().is_redirection() => (
+            Some(response.status().as_u16()),
+            Err(format!("Endpoint answered {}; redirects are not followed", response.status())),
+        ),
+        Ok(response) => (Some(response.status().as_u16()), Err(format!("Endpoint answered {}", response.status()))),
+        Err(e) => (None, Err(format!("Request failed: {}", e))),
+    }
+}
+
+/// Client for event deliveries; a slow receiver can't hold up the queue.
+/// Redirects aren't followed, so an endpoint can't send the request on to
+/// a host it wasn't checked against.
+pub fn event_client() -> Client {
+    Client::builder()
+        .timeout(Duration::from_secs(10))
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap_or_default()
+}
+
+/// Refuses a URL whose host is, or resolves to, a loopback, private,
+/// link-local or unspecified address, so a merchant's endpoint can't aim
+/// deliveries at the server's own network.
+pub async fn check_public_host(url: &str) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url.trim()).map_err(|_| format!("{} is not a valid URL", url))?;
+    let host = parsed.host_str().ok_or_else(|| format!("{} has no host", url))?;
+    let port = parsed.port_or_known_default().unwrap_or(443);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    let addresses: Vec<IpAddr> = lookup_host((host, port))
+        .await
+        .map_err(|e| format!("Could not resolve {}: {}", host, e))?
+        .map(|address| address.ip())
+        .collect();
+    if addresses.is_empty() {
+        return Err(format!("Could not resolve {}", host));
+    }
+    match addresses.into_iter().find(|ip| !is_public(*ip)) {
+        Some(ip) => Err(format!("{} resolves to {}, which is not a public address", host, ip)),
+        None => Ok(()),
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => !(ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified()),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public(IpAddr::V4(mapped)),
+            None => {
+                let first = ip.segments()[0];
+                // fc00::/7 is unique local, fe80::/10 link-local
+                !(ip.is_loopback() || ip.is_unspecified() || first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Appends each event's envelope as one JSON line to a spool file, for a
+/// forwarder to tail into a message queue.
+pub struct QueueFileSink {
+    path: PathBuf,
+}
+
+impl QueueFileSink {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+#[async_trait]
+impl EventSink for QueueFileSink {
+    fn name(&self) -> &'static str {
+        "queue"
+    }
+
+    async fn publish(&self, event: &DomainEvent) -> Result<(), String> {
+        let mut line = serde_json::to_vec(&event.envelope()).map_err(|e| format!("Failed to encode event: {}", e))?;
+        line.push(b'\n');
+
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .map_err(|e| format!("Failed to create event queue directory: {}", e))?;
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|e| format!("Failed to open event queue file: {}", e))?;
+        // One write per line, so concurrent appends don't interleave
+        file.write_all(&line)
+            .await
+            .map_err(|e| format!("Failed to append to event queue file: {}", e))
+    }
+}
+
+/// The sinks turned on in config; empty when none are.
+pub fn configured_sinks(config: &AppConfig) -> Vec<Box<dyn EventSink>> {
+    let mut sinks: Vec<Box<dyn EventSink>> = Vec::new();
+    if let Some(url) = &config.event_webhook_url {
+        sinks.push(Box::new(WebhookSink::new(url.clone(), config.event_webhook_secret.clone())));
+    }
+    if let Some(path) = &config.event_queue_file {
+        sinks.push(Box::new(QueueFileSink::new(PathBuf::from(path))));
+    }
+    sinks
+}
@@ -0,0 +1,166 @@
+use actix_web::HttpRequest;
+use chrono::{DateTime, Datelike, Utc};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Locale {
+    #[serde(rename = "en-ZA")]
+    EnZa,
+    #[serde(rename = "en-GB")]
+    EnGb,
+    #[serde(rename = "en-US")]
+    EnUs,
+    #[serde(rename = "af-ZA")]
+    AfZa,
+}
+
+impl Locale {
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let tag = tag.trim().to_lowercase().replace('_', "-");
+        match tag.as_str() {
+            "en-za" => Some(Locale::EnZa),
+            "en-gb" => Some(Locale::EnGb),
+            "en-us" => Some(Locale::EnUs),
+            "af" | "af-za" => Some(Locale::AfZa),
+            "en" => Some(Locale::EnZa),
+            _ => None,
+        }
+    }
+
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Locale::EnZa => "en-ZA",
+            Locale::EnGb => "en-GB",
+            Locale::EnUs => "en-US",
+            Locale::AfZa => "af-ZA",
+        }
+    }
+}
+
+/// Produces display strings for amounts and dates so every frontend renders
+/// them the same way. Raw ISO timestamps and numeric amounts stay in the
+/// responses; these strings are supplementary.
+#[derive(Debug, Clone, Copy)]
+pub struct Formatting {
+    pub locale: Locale,
+}
+
+impl Default for Formatting {
+    fn default() -> Self {
+        Self { locale: Locale::EnZa }
+    }
+}
+
+impl Formatting {
+    pub fn new(locale: Locale) -> Self {
+        Self { locale }
+    }
+
+    /// Picks the highest-weighted supported language from Accept-Language,
+    /// falling back to en-ZA.
+    pub fn from_request(req: &HttpRequest) -> Self {
+        req.headers()
+            .get("Accept-Language")
+            .and_then(|v| v.to_str().ok())
+            .and_then(Self::parse_accept_language)
+            .map(Self::new)
+            .unwrap_or_default()
+    }
+
+    /// The language saved on the caller's profile, falling back to
+    /// Accept-Language when they haven't chosen one.
+    pub fn for_profile(profile_locale: Option<&str>, req: &HttpRequest) -> Self {
+        profile_locale
+            .and_then(Locale::from_tag)
+            .map(Self::new)
+            .unwrap_or_else(|| Self::from_request(req))
+    }
+
+    fn parse_accept_language(header: &str) -> Option<Locale> {
+        let mut candidates: Vec<(f32, Locale)> = header
+            .split(',')
+            .filter_map(|part| {
+                let mut pieces = part.split(';');
+                let tag = pieces.next()?.trim();
+                let weight = pieces
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                // q=0 marks a language as not acceptable
+                if weight.is_nan() || weight <= 0.0 {
+                    return None;
+                }
+                let locale = Locale::from_tag(tag)
+                    .or_else(|| tag.split('-').next().and_then(Locale::from_tag))?;
+                Some((weight, locale))
+            })
+            .collect();
+
+        candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+        candidates.into_iter().next().map(|(_, locale)| locale)
+    }
+
+    fn separators(&self) -> (&'static str, &'static str) {
+        match self.locale {
+            Locale::EnZa | Locale::AfZa => ("\u{a0}", ","),
+            Locale::EnGb | Locale::EnUs => (",", "."),
+        }
+    }
+
+    pub fn amount(&self, amount: f64, currency: &str) -> String {
+        let symbol = match currency.to_uppercase().as_str() {
+            "ZAR" => "R".to_string(),
+            "USD" => "$".to_string(),
+            "EUR" => "€".to_string(),
+            "GBP" => "£".to_string(),
+            other => format!("{} ", other),
+        };
+
+        let (thousands, decimal) = self.separators();
+        let cents = (amount.abs() * 100.0).round() as u64;
+        let whole = (cents / 100).to_string();
+        let fraction = cents % 100;
+
+        let mut grouped = String::new();
+        for (i, ch) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i) % 3 == 0 {
+                grouped.push_str(thousands);
+            }
+            grouped.push(ch);
+        }
+
+        let sign = if amount < 0.0 { "-" } else { "" };
+        format!("{}{}{}{}{:02}", sign, symbol, grouped, decimal, fraction)
+    }
+
+    pub fn date(&self, dt: &DateTime<Utc>) -> String {
+        let month = self.month_name(dt.month());
+        match self.locale {
+            Locale::EnUs => format!("{} {}, {}", month, dt.day(), dt.year()),
+            _ => format!("{} {} {}", dt.day(), month, dt.year()),
+        }
+    }
+
+    pub fn datetime(&self, dt: &DateTime<Utc>) -> String {
+        match self.locale {
+            Locale::EnUs => format!("{} {} UTC", self.date(dt), dt.format("%-I:%M %p")),
+            _ => format!("{} {} UTC", self.date(dt), dt.format("%H:%M")),
+        }
+    }
+
+    fn month_name(&self, month: u32) -> &'static str {
+        const EN: [&str; 12] = [
+            "January", "February", "March", "April", "May", "June",
+            "July", "August", "September", "October", "November", "December",
+        ];
+        const AF: [&str; 12] = [
+            "Januarie", "Februarie", "Maart", "April", "Mei", "Junie",
+            "Julie", "Augustus", "September", "Oktober", "November", "Desember",
+        ];
+        let idx = (month.saturating_sub(1) as usize).min(11);
+        match self.locale {
+            Locale::AfZa => AF[idx],
+            _ => EN[idx],
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::{Decimal, RoundingStrategy};
+use crate::models::payment::{normalize_currency, DEFAULT_CURRENCY};
+
+/// Source of exchange rates into the reporting base currency.
+pub trait ExchangeRateProvider: Send + Sync {
+    /// Units of the base currency bought by one unit of `currency`.
+    fn rate_to_base(&self, currency: &str) -> Option<Decimal>;
+}
+
+/// Fixed rates configured by operators, e.g. `FX_RATES=USD:18.20,EUR:19.75`.
+/// Good enough for reporting, which only needs approximate base totals.
+pub struct StaticRates {
+    rates: HashMap<String, Decimal>,
+}
+
+impl StaticRates {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut rates = HashMap::new();
+        for entry in spec.split(',') {
+            let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let (code, rate) = entry
+                .split_once(':')
+                .ok_or_else(|| format!("FX_RATES entry must be CODE:rate, got {}", entry))?;
+            let code = normalize_currency(code)?;
+            let rate = Decimal::from_str(rate.trim())
+                .map_err(|e| format!("Invalid FX rate for {}: {}", code, e))?;
+            if rate <= Decimal::ZERO {
+                return Err(format!("FX rate for {} must be positive", code));
+            }
+            rates.insert(code, rate);
+        }
+        Ok(Self { rates })
+    }
+}
+
+impl ExchangeRateProvider for StaticRates {
+    fn rate_to_base(&self, currency: &str) -> Option<Decimal> {
+        self.rates.get(currency).copied()
+    }
+}
+
+/// Converts amounts into the base currency that reports are expressed in.
+#[derive(Clone)]
+pub struct FxService {
+    base_currency: String,
+    provider: Arc<dyn ExchangeRateProvider>,
+}
+
+impl FxService {
+    pub fn new(base_currency: String, provider: Arc<dyn ExchangeRateProvider>) -> Self {
+        Self { base_currency, provider }
+    }
+
+    /// Reads `FX_BASE_CURRENCY` (default ZAR) and static `FX_RATES`.
+    pub fn from_env() -> Result<Self, String> {
+        let base_currency = normalize_currency(
+            &env::var("FX_BASE_CURRENCY").unwrap_or_else(|_| DEFAULT_CURRENCY.to_string()),
+        )?;
+        let rates = StaticRates::parse(&env::var("FX_RATES").unwrap_or_default())?;
+        Ok(Self::new(base_currency, Arc::new(rates)))
+    }
+
+    pub fn base_currency(&self) -> &str {
+        &self.base_currency
+    }
+
+    /// `amount` in the base currency, rounded to cents, or `None` when no
+    /// rate is known for `currency`.
+    pub fn to_base(&self, amount: f64, currency: &str) -> Option<f64> {
+        let currency = currency.to_uppercase();
+        let rate = if currency == self.base_currency {
+            Decimal::ONE
+        } else {
+            self.provider.rate_to_base(&currency)?
+        };
+
+        (Decimal::from_f64(amount)? * rate)
+            .round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero)
+            .to_f64()
+    }
+}
@@ -0,0 +1,255 @@
+use std::error::Error;
+use actix_web::http::header::HeaderMap;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+use serde_json::Value;
+use crate::models::payment::{PaymentMethod, PaymentStatus};
+use crate::models::reconciliation::SettlementRecord;
+use crate::models::recurring_payment::CardExpiry;
+use crate::models::refund::RefundStatus;
+
+pub type GatewayResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// A hosted checkout for the first payment on a subscription. Gateways are
+/// asked to tokenize the card so renewals can be charged with `charge_token`.
+#[derive(Debug, Clone)]
+pub struct CheckoutRequest<'a> {
+    pub user_id: &'a str,
+    pub subscription_id: &'a str,
+    pub amount: f64,
+    pub currency: &'a str,
+    pub merchant_transaction_id: &'a str,
+    /// Method the shopper picked, for gateways that can preselect it.
+    pub payment_method: &'a PaymentMethod,
+    /// Card statement text; `None` leaves the gateway account's default.
+    pub descriptor: Option<&'a str>,
+    /// Only reserve the amount; it is taken later with `capture`. Ignored by
+    /// gateways that don't `supports_authorization`.
+    pub authorize_only: bool,
+}
+
+/// A registration-only checkout that saves a new card without charging it.
+#[derive(Debug, Clone)]
+pub struct CardRegistrationRequest<'a> {
+    pub user_id: &'a str,
+    pub subscription_id: &'a str,
+    pub currency: &'a str,
+    pub merchant_transaction_id: &'a str,
+    /// The card being replaced, for gateways that attach cards to a customer.
+    pub current_token: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckoutSession {
+    pub checkout_id: String,
+    /// Hosted payment page, for gateways that redirect rather than embed a widget.
+    pub redirect_url: Option<String>,
+    pub registration_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+pub enum ChargeStatus {
+    Succeeded,
+    Pending,
+    /// Waiting on the shopper to pass a 3-D Secure challenge; see
+    /// `GatewayTransaction::authentication_url`.
+    AwaitingAuthentication,
+    Failed,
+}
+
+impl ChargeStatus {
+    /// Not decided yet, whether or not the shopper still has to act.
+    pub fn is_pending(&self) -> bool {
+        matches!(self, ChargeStatus::Pending | ChargeStatus::AwaitingAuthentication)
+    }
+
+    pub fn payment_status(&self) -> PaymentStatus {
+        match self {
+            ChargeStatus::Succeeded => PaymentStatus::Completed,
+            ChargeStatus::Pending => PaymentStatus::Pending,
+            ChargeStatus::AwaitingAuthentication => PaymentStatus::AwaitingAuthentication,
+            ChargeStatus::Failed => PaymentStatus::Failed,
+        }
+    }
+
+    pub fn refund_status(&self) -> RefundStatus {
+        match self {
+            ChargeStatus::Succeeded => RefundStatus::Completed,
+            ChargeStatus::Pending | ChargeStatus::AwaitingAuthentication => RefundStatus::Pending,
+            ChargeStatus::Failed => RefundStatus::Failed,
+        }
+    }
+}
+
+/// Gateway-neutral view of a charge, refund or checkout.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct GatewayTransaction {
+    pub status: ChargeStatus,
+    /// The gateway's own result or decline code, kept for logs and dunning.
+    pub code: String,
+    pub description: Option<String>,
+    /// The gateway's id for this transaction, used to refund it later.
+    pub gateway_reference: Option<String>,
+    pub merchant_transaction_id: Option<String>,
+    pub payment_brand: Option<String>,
+    /// Stored card token created by this transaction, if any.
+    pub registration_id: Option<String>,
+    pub card_last4: Option<String>,
+    pub card_expiry: Option<CardExpiry>,
+    /// 3-D Secure challenge page the shopper must be sent to, when the
+    /// status is `AwaitingAuthentication`.
+    pub authentication_url: Option<String>,
+    /// Only reserved the amount (an authorisation); it still has to be
+    /// captured before the money moves.
+    pub authorized_only: bool,
+    /// Untouched gateway response, for support and debugging.
+    pub raw: Value,
+}
+
+/// What the browser needs to load a provider's embedded checkout.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CheckoutWidget {
+    pub entity_id: String,
+    pub script_url: String,
+    /// `sandbox` or `live`.
+    pub environment: String,
+    /// Brands the widget offers, e.g. `VISA`, `MASTER`, `SCANTOPAY`.
+    pub payment_brands: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum WebhookKind {
+    Payment,
+    Refund,
+    /// The cardholder disputed a payment; `gateway_reference` is the
+    /// disputed payment's gateway id.
+    Chargeback,
+    /// Notifications we don't act on (checkout opened, customer updated, ...).
+    Other,
+}
+
+/// A parsed webhook, ready for `process_webhook`.
+#[derive(Debug, Clone)]
+pub struct WebhookNotification {
+    pub kind: WebhookKind,
+    pub transaction: GatewayTransaction,
+    pub subscription_id: Option<String>,
+    /// When the gateway says the event happened, if it says.
+    pub occurred_at: Option<DateTime<Utc>>,
+    pub signature: Option<String>,
+    /// Flattened fields recorded on the stored webhook event.
+    pub fields: Value,
+}
+
+/// Everything the handlers and background tasks need from a payment provider.
+#[async_trait]
+pub trait PaymentGateway: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Currencies the configured merchant account can charge in.
+    fn supported_currencies(&self) -> &[String];
+
+    fn supports_currency(&self, currency: &str) -> bool {
+        self.supported_currencies().iter().any(|c| c.eq_ignore_ascii_case(currency))
+    }
+
+    /// Whether charges can carry a per-charge card statement descriptor.
+    fn supports_statement_descriptor(&self) -> bool {
+        false
+    }
+
+    /// Whether checkouts can reserve the amount now and `capture` or `void`
+    /// it later.
+    fn supports_authorization(&self) -> bool {
+        false
+    }
+
+    /// Whether charges move real money. Providers that can't tell are
+    /// assumed live, so test-mode guards err on the safe side.
+    fn is_live(&self) -> bool {
+        true
+    }
+
+    /// Settings for the provider's embeddable checkout; `None` for
+    /// hosted-page providers.
+    fn checkout_widget(&self) -> Option<CheckoutWidget> {
+        None
+    }
+
+    /// Cheap authenticated call made at startup to catch bad credentials or
+    /// an unreachable provider early. Providers without one report healthy.
+    async fn health_check(&self) -> GatewayResult<()> {
+        Ok(())
+    }
+
+    async fn initiate_checkout(&self, request: &CheckoutRequest<'_>) -> GatewayResult<CheckoutSession>;
+
+    /// Starts a checkout that only stores a card. Its webhook arrives as a
+    /// `Payment` for `merchant_transaction_id` carrying the new `registration_id`.
+    async fn initiate_card_registration(&self, _request: &CardRegistrationRequest<'_>) -> GatewayResult<CheckoutSession> {
+        Err(format!("{} cannot store cards", self.name()).into())
+    }
+
+    async fn check_status(&self, checkout_id: &str) -> GatewayResult<GatewayTransaction>;
+
+    /// Merchant-initiated charge against a stored card token. `descriptor`
+    /// is the card statement text, where the gateway supports setting it.
+    async fn charge_token(
+        &self,
+        token: &str,
+        amount: f64,
+        currency: &str,
+        merchant_transaction_id: &str,
+        descriptor: Option<&str>,
+    ) -> GatewayResult<GatewayTransaction>;
+
+    /// Refunds (fully or partially) a captured payment. `merchant_transaction_id`
+    /// identifies the refund itself so its webhook can be matched.
+    async fn refund(
+        &self,
+        gateway_reference: &str,
+        amount: f64,
+        currency: &str,
+        merchant_transaction_id: &str,
+    ) -> GatewayResult<GatewayTransaction>;
+
+    /// Takes `amount` of an authorisation made by an `authorize_only`
+    /// checkout. The result's `gateway_reference` is what later refunds go
+    /// against.
+    async fn capture(&self, _gateway_reference: &str, _amount: f64, _currency: &str) -> GatewayResult<GatewayTransaction> {
+        Err(format!("{} cannot capture authorisations", self.name()).into())
+    }
+
+    /// Releases an authorisation that won't be captured.
+    async fn void(&self, _gateway_reference: &str, _currency: &str) -> GatewayResult<GatewayTransaction> {
+        Err(format!("{} cannot void authorisations", self.name()).into())
+    }
+
+    /// Whether `transaction_report` can list the account's payments, so the
+    /// daily reconciliation can run against it.
+    fn supports_transaction_reports(&self) -> bool {
+        false
+    }
+
+    /// Payments (debits and authorisations, not refunds) made on the account
+    /// in `[from, to)`.
+    async fn transaction_report(&self, _from: DateTime<Utc>, _to: DateTime<Utc>) -> GatewayResult<Vec<SettlementRecord>> {
+        Err(format!("{} has no transaction reports", self.name()).into())
+    }
+
+    /// The signature a webhook was sent with, from its headers or body.
+    fn webhook_signature(&self, headers: &HeaderMap, body: &[u8]) -> Option<String>;
+
+    fn validate_webhook(&self, body: &[u8], signature: &str) -> bool;
+
+    /// Decrypts a webhook the gateway sent encrypted, authenticated by its
+    /// `signature`; the plaintext is what `parse_webhook` reads. `None` when
+    /// the body isn't encrypted.
+    fn decrypt_webhook(&self, _body: &[u8], _signature: &str) -> Option<Result<Vec<u8>, String>> {
+        None
+    }
+
+    fn parse_webhook(&self, body: &[u8]) -> Result<WebhookNotification, String>;
+}
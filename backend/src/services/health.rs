@@ -0,0 +1,151 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration as StdDuration, Instant};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+use crate::services::database::DatabaseService;
+use crate::services::gateway::PaymentGateway;
+
+/// How long a gateway probe result is reused. Load balancers poll readiness
+/// every few seconds and most probes call the provider.
+const GATEWAY_PROBE_TTL: StdDuration = StdDuration::from_secs(60);
+
+/// The renewal task runs every 5 minutes; three missed passes means it is
+/// stuck or has died.
+const RENEWAL_HEARTBEAT_MAX_AGE_MINUTES: i64 = 15;
+
+/// When a background task last completed a pass. Cloned into the task, which
+/// calls `beat`, and read by readiness checks.
+#[derive(Debug, Clone, Default)]
+pub struct TaskHeartbeat(Arc<Mutex<Option<DateTime<Utc>>>>);
+
+impl TaskHeartbeat {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records wall-clock time rather than the task's `Clock`, so a fixed
+    /// test clock doesn't make the task look stalled.
+    pub fn beat(&self) {
+        if let Ok(mut last) = self.0.lock() {
+            *last = Some(Utc::now());
+        }
+    }
+
+    /// `None` until the first pass finishes.
+    pub fn last(&self) -> Option<DateTime<Utc>> {
+        self.0.lock().ok().and_then(|last| *last)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeStatus {
+    Up,
+    /// Serving, but a dependency that only some endpoints need is unhealthy.
+    Degraded,
+    Down,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DependencyHealth {
+    pub status: ProbeStatus,
+    /// Why the dependency isn't up, or when the task last ran.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub checked_at: DateTime<Utc>,
+}
+
+impl DependencyHealth {
+    fn up(detail: Option<String>) -> Self {
+        Self { status: ProbeStatus::Up, detail, checked_at: Utc::now() }
+    }
+
+    fn down(detail: String) -> Self {
+        Self { status: ProbeStatus::Down, detail: Some(detail), checked_at: Utc::now() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ReadinessReport {
+    /// `down` when the database is unreachable, `degraded` when only the
+    /// gateway or renewal task is unhealthy.
+    pub status: ProbeStatus,
+    pub database: DependencyHealth,
+    pub gateway: DependencyHealth,
+    pub renewal_task: DependencyHealth,
+}
+
+/// Readiness probes for the dependencies the API and renewals need.
+pub struct HealthService {
+    gateway: Arc<dyn PaymentGateway>,
+    renewal_heartbeat: TaskHeartbeat,
+    started_at: DateTime<Utc>,
+    gateway_probe: tokio::sync::Mutex<Option<(Instant, DependencyHealth)>>,
+}
+
+impl HealthService {
+    pub fn new(gateway: Arc<dyn PaymentGateway>, renewal_heartbeat: TaskHeartbeat) -> Self {
+        Self {
+            gateway,
+            renewal_heartbeat,
+            started_at: Utc::now(),
+            gateway_probe: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    pub async fn readiness(&self, db: &DatabaseService) -> ReadinessReport {
+        let database = match db.health_check().await {
+            Ok(()) => DependencyHealth::up(None),
+            Err(e) => DependencyHealth::down(e),
+        };
+        let gateway = self.gateway_health().await;
+        let renewal_task = self.renewal_task_health(Utc::now());
+
+        // Without the database nothing works; the gateway and renewals only
+        // affect some endpoints, so the instance stays in rotation
+        let status = if database.status == ProbeStatus::Down {
+            ProbeStatus::Down
+        } else if gateway.status != ProbeStatus::Up || renewal_task.status != ProbeStatus::Up {
+            ProbeStatus::Degraded
+        } else {
+            ProbeStatus::Up
+        };
+
+        ReadinessReport { status, database, gateway, renewal_task }
+    }
+
+    /// The last gateway probe if it is recent enough, otherwise a new one.
+    /// Holding the lock across the probe keeps concurrent checks from each
+    /// calling the gateway.
+    async fn gateway_health(&self) -> DependencyHealth {
+        let mut cached = self.gateway_probe.lock().await;
+        if let Some((probed_at, health)) = cached.as_ref() {
+            if probed_at.elapsed() < GATEWAY_PROBE_TTL {
+                return health.clone();
+            }
+        }
+
+        let health = match self.gateway.health_check().await {
+            Ok(()) => DependencyHealth::up(Some(self.gateway.name().to_string())),
+            Err(e) => DependencyHealth::down(format!("{}: {}", self.gateway.name(), e)),
+        };
+        *cached = Some((Instant::now(), health.clone()));
+        health
+    }
+
+    /// Down when the renewal task hasn't finished a pass recently. Before the
+    /// first pass, the age is measured from startup.
+    fn renewal_task_health(&self, now: DateTime<Utc>) -> DependencyHealth {
+        let last = self.renewal_heartbeat.last();
+        let age = now - last.unwrap_or(self.started_at);
+        if age <= Duration::minutes(RENEWAL_HEARTBEAT_MAX_AGE_MINUTES) {
+            return DependencyHealth::up(last.map(|at| format!("last pass at {}", at.to_rfc3339())));
+        }
+
+        DependencyHealth::down(match last {
+            Some(at) => format!("no pass since {}", at.to_rfc3339()),
+            None => format!("no pass since startup at {}", self.started_at.to_rfc3339()),
+        })
+    }
+}
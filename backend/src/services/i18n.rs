@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use crate::services::formatting::Locale;
+use crate::services::templates::substitute;
+
+/// Messages are written in English and looked up by their English text, as
+/// with gettext, so error responses are translated without handlers having
+/// to change.
+const AF_CATALOG: &str = include_str!("../../locales/af.po");
+
+static AF: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+fn catalog(locale: Locale) -> Option<&'static HashMap<String, String>> {
+    match locale {
+        Locale::AfZa => Some(AF.get_or_init(|| parse_po(AF_CATALOG))),
+        Locale::EnZa | Locale::EnGb | Locale::EnUs => None,
+    }
+}
+
+/// The translation of `message`, if the locale's catalog has one.
+pub fn lookup(locale: Locale, message: &str) -> Option<&'static str> {
+    catalog(locale)?.get(message).map(String::as_str)
+}
+
+/// `message` in the locale's language, or as it is when there's no translation.
+pub fn translate<'a>(locale: Locale, message: &'a str) -> &'a str {
+    lookup(locale, message).unwrap_or(message)
+}
+
+/// Translates, then fills in `{{var}}` placeholders.
+pub fn translate_with(locale: Locale, message: &str, vars: &[(&str, String)]) -> String {
+    substitute(translate(locale, message), vars)
+}
+
+/// Reads `msgid`/`msgstr` pairs from a `.po` file. Comments, the header
+/// entry and untranslated entries are skipped.
+fn parse_po(source: &str) -> HashMap<String, String> {
+    let mut messages = HashMap::new();
+    let mut msgid: Option<String> = None;
+    let mut msgstr: Option<String> = None;
+
+    let mut flush = |msgid: &mut Option<String>, msgstr: &mut Option<String>| {
+        if let (Some(id), Some(text)) = (msgid.take(), msgstr.take()) {
+            if !id.is_empty() && !text.is_empty() {
+                messages.insert(id, text);
+            }
+        }
+    };
+
+    for line in source.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("msgid ") {
+            flush(&mut msgid, &mut msgstr);
+            msgid = Some(unquote(rest));
+        } else if let Some(rest) = line.strip_prefix("msgstr ") {
+            msgstr = Some(unquote(rest));
+        } else if line.starts_with('"') {
+            // Continuation of whichever string came last
+            if let Some(text) = msgstr.as_mut().or(msgid.as_mut()) {
+                text.push_str(&unquote(line));
+            }
+        }
+    }
+    flush(&mut msgid, &mut msgstr);
+    messages
+}
+
+fn unquote(quoted: &str) -> String {
+    let quoted = quoted.trim();
+    let inner = quoted.strip_prefix('"').unwrap_or(quoted);
+    let inner = inner.strip_suffix('"').unwrap_or(inner);
+
+    let mut text = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            text.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => text.push('\n'),
+            Some('t') => text.push('\t'),
+            Some(escaped) => text.push(escaped),
+            None => {}
+        }
+    }
+    text
+}
@@ -0,0 +1,212 @@
+//! Installments after the first: charged to the card saved at checkout once
+//! due, retried daily, and settled from the charge or its webhook.
+
+use chrono::Utc;
+use tracing::{error, info, warn};
+use crate::config::AppConfig;
+use crate::models::installment::{Installment, InstallmentStatus, INSTALLMENT_PREFIX, MAX_INSTALLMENT_ATTEMPTS};
+use crate::models::payment::PaymentStatus;
+use crate::models::state_reason;
+use crate::models::subscription::SubscriptionStatus;
+use crate::models::webhook_event::WebhookOutcome;
+use crate::services::database::DatabaseService;
+use crate::services::email::{EmailEvent, EmailService};
+use crate::services::gateway::{ChargeStatus, GatewayTransaction, PaymentGateway};
+use crate::services::invoicing::issue_invoice;
+use crate::services::tax::TaxBreakdown;
+use crate::services::tickets;
+
+/// Charges one due installment to the user's saved card. Installments of a
+/// subscription that has ended are cancelled instead, and one whose last
+/// charge is still pending is left for its webhook.
+pub async fn charge_installment(
+    db: &DatabaseService,
+    gateway: &dyn PaymentGateway,
+    config: &AppConfig,
+    email: &EmailService,
+    installment: &Installment,
+) {
+    let subscription = db.get_subscription(&installment.subscription_id).await;
+    let plan_id = match &subscription {
+        Some(s) if !matches!(s.status, SubscriptionStatus::Cancelled | SubscriptionStatus::Expired) => s.plan_id.clone(),
+        _ => {
+            if let Err(e) = db.cancel_installments(&installment.subscription_id).await {
+                error!("{}", e);
+            }
+            return;
+        }
+    };
+    if let Some(previous) = &installment.merchant_transaction_id {
+        if db.get_payment_by_merchant_id(previous).await.is_some_and(|p| !p.status.is_final()) {
+            info!("Installment {} still has charge {} pending", installment.id, previous);
+            return;
+        }
+    }
+
+    let Some(token) = db.get_recurring_token_by_user(&installment.user_id).await else {
+        warn!("No saved card for installment {} of subscription {}", installment.id, installment.subscription_id);
+        if let Err(e) = db.settle_installment(installment, Some("No saved card"), true).await {
+            error!("{}", e);
+        }
+        notify_failed(db, email, installment, None, state_reason::PAYMENT_FAILED).await;
+        return;
+    };
+
+    let merchant_transaction_id = format!("{}{}", INSTALLMENT_PREFIX, uuid::Uuid::new_v4().simple());
+    let installment = match db.begin_installment_charge(installment, &merchant_transaction_id).await {
+        Ok(Some(installment)) => installment,
+        Ok(None) => return,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+    let tax = TaxBreakdown::from_inclusive(installment.amount, config.vat_rate_percent);
+    if let Err(e) = db.create_installment_payment(&installment, &merchant_transaction_id, tax, gateway.name()).await {
+        error!("{}", e);
+        return;
+    }
+
+    info!(
+        "Charging installment {} of {} for sub {} (attempt {})",
+        installment.sequence, installment.count, installment.subscription_id, installment.attempts
+    );
+    let descriptor = if gateway.supports_statement_descriptor() {
+        db.statement_descriptor(plan_id.as_deref(), config.statement_descriptor.as_deref()).await
+    } else {
+        None
+    };
+    match gateway.charge_token(&token, installment.amount, &installment.currency, &merchant_transaction_id, descriptor.as_deref()).await {
+        Ok(transaction) => apply_charge_result(db, config, email, &installment, &transaction).await,
+        Err(e) => {
+            // Says nothing about the card; retried on the next run
+            error!("Installment charge {} failed: {}", merchant_transaction_id, e);
+            fail_installment(db, email, &installment, &e.to_string(), state_reason::PAYMENT_FAILED_GATEWAY_ERROR).await;
+        }
+    }
+}
+
+/// Settles an installment from the result of its charge, invoicing and
+/// emailing the user when it went through. Pending charges wait for their
+/// webhook.
+async fn apply_charge_result(
+    db: &DatabaseService,
+    config: &AppConfig,
+    email: &EmailService,
+    installment: &Installment,
+    transaction: &GatewayTransaction,
+) {
+    let merchant_transaction_id = installment.merchant_transaction_id.clone().unwrap_or_default();
+    match transaction.status {
+        ChargeStatus::Succeeded => {}
+        ChargeStatus::Failed => {
+            let reason = state_reason::payment_failed(&transaction.code);
+            fail_installment(db, email, installment, &format!("declined with code {}", transaction.code), &reason).await;
+            return;
+        }
+        _ => {
+            info!("Installment charge {} pending", merchant_transaction_id);
+            return;
+        }
+    }
+
+    if let Err(e) = db.apply_payment_event(&merchant_transaction_id, &PaymentStatus::Completed, state_reason::PAYMENT_SUCCEEDED, Utc::now()).await {
+        error!("{}", e);
+    }
+    match db.settle_installment(installment, None, false).await {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    }
+
+    let plan = db.get_subscription(&installment.subscription_id).await.map(|s| s.plan_name).unwrap_or_default();
+    let invoice = match issue_invoice(
+        db,
+        config,
+        &installment.user_id,
+        Some(&installment.subscription_id),
+        &merchant_transaction_id,
+        &installment.currency,
+        TaxBreakdown::from_inclusive(installment.amount, config.vat_rate_percent),
+    ).await {
+        Ok(invoice) => Some(Box::new(invoice)),
+        Err(e) => {
+            error!("Failed to issue invoice for installment {}: {}", merchant_transaction_id, e);
+            None
+        }
+    };
+    email.notify_user(db, &installment.user_id, EmailEvent::PaymentSucceeded {
+        plan,
+        amount: installment.amount,
+        currency: installment.currency.clone(),
+        reference: merchant_transaction_id,
+        descriptor: None,
+        invoice,
+    }).await;
+}
+
+/// Records a failed charge; after `MAX_INSTALLMENT_ATTEMPTS` the installment
+/// fails for good and the user and support are told.
+async fn fail_installment(db: &DatabaseService, email: &EmailService, installment: &Installment, error: &str, reason: &str) {
+    let merchant_transaction_id = installment.merchant_transaction_id.clone().unwrap_or_default();
+    if let Err(e) = db.apply_payment_event(&merchant_transaction_id, &PaymentStatus::Failed, reason, Utc::now()).await {
+        error!("{}", e);
+    }
+    let final_attempt = installment.attempts >= MAX_INSTALLMENT_ATTEMPTS;
+    match db.settle_installment(installment, Some(error), final_attempt).await {
+        Ok(true) if final_attempt => notify_failed(db, email, installment, Some(&merchant_transaction_id), reason).await,
+        Ok(_) => {}
+        Err(e) => error!("{}", e),
+    }
+}
+
+async fn notify_failed(
+    db: &DatabaseService,
+    email: &EmailService,
+    installment: &Installment,
+    merchant_transaction_id: Option<&str>,
+    reason: &str,
+) {
+    let reference = merchant_transaction_id.unwrap_or(&installment.id);
+    tickets::open_failed_payment_ticket(db, &installment.user_id, Some(&installment.subscription_id), reference, reason).await;
+    let plan = db.get_subscription(&installment.subscription_id).await.map(|s| s.plan_name).unwrap_or_default();
+    email.notify_user(db, &installment.user_id, EmailEvent::PaymentFailed {
+        plan,
+        amount: installment.amount,
+        currency: installment.currency.clone(),
+        reference: reference.to_string(),
+    }).await;
+}
+
+/// Handles the webhook for an installment charge that was still pending when
+/// it was made.
+pub async fn process_installment_webhook(
+    db: &DatabaseService,
+    config: &AppConfig,
+    email: &EmailService,
+    transaction: &GatewayTransaction,
+) -> Result<WebhookOutcome, String> {
+    let merchant_transaction_id = transaction.merchant_transaction_id.clone().unwrap_or_default();
+    let installment = db.get_installment_by_transaction(&merchant_transaction_id).await
+        .ok_or_else(|| format!("No installment found for merchantTransactionId: {}", merchant_transaction_id))?;
+
+    if installment.status != InstallmentStatus::Scheduled {
+        info!("Installment {} already {:?}", installment.id, installment.status);
+        return Ok(WebhookOutcome::Ignored);
+    }
+    if transaction.status.is_pending() {
+        info!("Installment charge {} still pending", merchant_transaction_id);
+        return Ok(WebhookOutcome::Ignored);
+    }
+    // The charge's own response already settled it
+    if db.get_payment_by_merchant_id(&merchant_transaction_id).await.is_some_and(|p| p.status.is_final()) {
+        info!("Installment charge {} already settled", merchant_transaction_id);
+        return Ok(WebhookOutcome::Ignored);
+    }
+
+    apply_charge_result(db, config, email, &installment, transaction).await;
+    Ok(WebhookOutcome::Processed)
+}
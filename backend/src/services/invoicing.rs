@@ -0,0 +1,247 @@
+use crate::config::AppConfig;
+use crate::models::invoice::{Invoice, InvoiceLineItem, NewInvoice};
+use crate::models::merchant::MerchantDetails;
+use crate::services::database::DatabaseService;
+use crate::services::formatting::{Formatting, Locale};
+use crate::services::i18n;
+use crate::services::tax::TaxBreakdown;
+
+/// The supplier details invoices are issued under: those saved through the
+/// admin API, else the `INVOICE_SELLER_*` settings.
+pub async fn merchant_details(db: &DatabaseService, config: &AppConfig) -> MerchantDetails {
+    match db.get_merchant_details().await {
+        Some(details) => details,
+        None => MerchantDetails {
+            legal_name: config.invoice_seller_name.clone(),
+            vat_number: config.invoice_seller_vat_number.clone(),
+            registration_number: config.invoice_seller_registration_number.clone(),
+            address: config.invoice_seller_address.clone(),
+            updated_at: None,
+        },
+    }
+}
+
+/// Issues the invoice for a successful charge of `tax.gross`. Safe to call
+/// more than once for the same payment reference.
+pub async fn issue_invoice(
+    db: &DatabaseService,
+    config: &AppConfig,
+    user_id: &str,
+    subscription_id: Option<&str>,
+    payment_reference: &str,
+    currency: &str,
+    tax: TaxBreakdown,
+) -> Result<Invoice, String> {
+    let user = db.get_user(user_id).await
+        .ok_or_else(|| format!("Cannot invoice unknown user {}", user_id))?;
+    let plan_name = match subscription_id {
+        Some(id) => db.get_subscription(id).await.map(|s| s.plan_name),
+        None => None,
+    };
+
+    let description = match plan_name {
+        Some(plan) => format!("{} plan subscription", plan),
+        None => "Subscription payment".to_string(),
+    };
+    let merchant = merchant_details(db, config).await;
+    db.create_invoice(NewInvoice {
+        user_id: user_id.to_string(),
+        subscription_id: subscription_id.map(str::to_string),
+        payment_reference: payment_reference.to_string(),
+        seller_name: merchant.legal_name,
+        seller_vat_number: merchant.vat_number,
+        seller_registration_number: merchant.registration_number,
+        seller_address: merchant.address,
+        customer_name: user.name,
+        customer_email: user.email,
+        customer_address: user.billing_address,
+        customer_vat_number: user.vat_number,
+        line_items: vec![InvoiceLineItem {
+            description,
+            quantity: 1,
+            unit_price: tax.gross,
+            amount: tax.gross,
+        }],
+        currency: currency.to_string(),
+        subtotal: tax.net,
+        vat_rate_percent: tax.vat_rate_percent,
+        vat_amount: tax.vat,
+        total: tax.gross,
+    }).await
+}
+
+/// Renders a single-page A4 PDF using the built-in Helvetica fonts, so no
+/// font files or PDF libraries are needed.
+pub fn render_invoice_pdf(invoice: &Invoice, fmt: &Formatting) -> Vec<u8> {
+    let mut page = PdfPage::new();
+    let currency = invoice.currency.as_str();
+    let label = |message: &str| i18n::translate(fmt.locale, message).to_string();
+
+    page.text(50.0, 780.0, 20.0, true, &label("Tax Invoice"));
+    page.text(50.0, 750.0, 11.0, true, &invoice.seller_name);
+    let mut y: f32 = 735.0;
+    for line in seller_lines(invoice, fmt.locale) {
+        page.text(50.0, y, 10.0, false, &line);
+        y -= 15.0;
+    }
+
+    page.text(350.0, 750.0, 10.0, false, &i18n::translate_with(
+        fmt.locale,
+        "Invoice number: {{invoice_number}}",
+        &[("invoice_number", invoice.invoice_number.clone())],
+    ));
+    page.text(350.0, 735.0, 10.0, false, &i18n::translate_with(
+        fmt.locale,
+        "Date: {{date}}",
+        &[("date", fmt.date(&invoice.issued_at))],
+    ));
+    page.text(350.0, 720.0, 10.0, false, &i18n::translate_with(
+        fmt.locale,
+        "Reference: {{reference}}",
+        &[("reference", invoice.payment_reference.clone())],
+    ));
+
+    y = y.min(705.0) - 10.0;
+    page.text(50.0, y, 10.0, true, &label("Bill to"));
+    y -= 15.0;
+    page.text(50.0, y, 10.0, false, &invoice.customer_name);
+    for line in customer_lines(invoice, fmt.locale) {
+        y -= 15.0;
+        page.text(50.0, y, 10.0, false, &line);
+    }
+
+    y -= 40.0;
+    page.text(50.0, y, 10.0, true, &label("Description"));
+    page.text(330.0, y, 10.0, true, &label("Qty"));
+    page.text(380.0, y, 10.0, true, &label("Unit price"));
+    page.text(470.0, y, 10.0, true, &label("Amount"));
+    page.line(50.0, y - 5.0, 545.0, y - 5.0);
+
+    for item in &invoice.line_items {
+        y -= 20.0;
+        page.text(50.0, y, 10.0, false, &item.description);
+        page.text(330.0, y, 10.0, false, &item.quantity.to_string());
+        page.text(380.0, y, 10.0, false, &fmt.amount(item.unit_price, currency));
+        page.text(470.0, y, 10.0, false, &fmt.amount(item.amount, currency));
+    }
+
+    y -= 15.0;
+    page.line(50.0, y, 545.0, y);
+    y -= 20.0;
+    page.text(330.0, y, 10.0, false, &label("Subtotal (excl. VAT)"));
+    page.text(470.0, y, 10.0, false, &fmt.amount(invoice.subtotal, currency));
+    y -= 15.0;
+    page.text(330.0, y, 10.0, false, &i18n::translate_with(
+        fmt.locale,
+        "VAT ({{vat_rate_percent}}%)",
+        &[("vat_rate_percent", invoice.vat_rate_percent.to_string())],
+    ));
+    page.text(470.0, y, 10.0, false, &fmt.amount(invoice.vat_amount, currency));
+    y -= 15.0;
+    page.text(330.0, y, 11.0, true, &label("Total"));
+    page.text(470.0, y, 11.0, true, &fmt.amount(invoice.total, currency));
+
+    page.text(50.0, 60.0, 9.0, false, &label("Paid in full. Thank you for your business."));
+
+    page.finish()
+}
+
+/// Address and registration lines printed under the supplier's name.
+pub fn seller_lines(invoice: &Invoice, locale: Locale) -> Vec<String> {
+    let mut lines: Vec<String> = invoice.seller_address.iter().flat_map(|a| a.lines()).map(str::to_string).collect();
+    if let Some(vat_number) = &invoice.seller_vat_number {
+        lines.push(i18n::translate_with(locale, "VAT number: {{vat_number}}", &[("vat_number", vat_number.clone())]));
+    }
+    if let Some(registration_number) = &invoice.seller_registration_number {
+        lines.push(i18n::translate_with(
+            locale,
+            "Registration number: {{registration_number}}",
+            &[("registration_number", registration_number.clone())],
+        ));
+    }
+    lines
+}
+
+/// Contact, address and VAT lines printed under the customer's name.
+pub fn customer_lines(invoice: &Invoice, locale: Locale) -> Vec<String> {
+    let mut lines = vec![invoice.customer_email.clone()];
+    lines.extend(invoice.customer_address.iter().flat_map(|a| a.lines()).map(str::to_string));
+    if let Some(vat_number) = &invoice.customer_vat_number {
+        lines.push(i18n::translate_with(locale, "VAT number: {{vat_number}}", &[("vat_number", vat_number.clone())]));
+    }
+    lines
+}
+
+/// Minimal PDF writer: one page, text and lines only.
+struct PdfPage {
+    content: Vec<u8>,
+}
+
+impl PdfPage {
+    fn new() -> Self {
+        Self { content: Vec::new() }
+    }
+
+    fn text(&mut self, x: f32, y: f32, size: f32, bold: bool, text: &str) {
+        let font = if bold { "F2" } else { "F1" };
+        self.content.extend_from_slice(format!("BT /{} {} Tf {} {} Td (", font, size, x, y).as_bytes());
+        self.content.extend_from_slice(&pdf_string(text));
+        self.content.extend_from_slice(b") Tj ET\n");
+    }
+
+    fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32) {
+        self.content.extend_from_slice(format!("0.5 w {} {} m {} {} l S\n", x1, y1, x2, y2).as_bytes());
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let objects: Vec<Vec<u8>> = vec![
+            b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+            b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_vec(),
+            b"<< /Type /Page /Parent 2 0 R /MediaBox [0 0 595 842] /Resources << /Font << /F1 4 0 R /F2 5 0 R >> >> /Contents 6 0 R >>".to_vec(),
+            b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_vec(),
+            b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>".to_vec(),
+            [
+                format!("<< /Length {} >>\nstream\n", self.content.len()).into_bytes(),
+                self.content,
+                b"\nendstream".to_vec(),
+            ].concat(),
+        ];
+
+        let mut out = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+            out.extend_from_slice(object);
+            out.extend_from_slice(b"\nendobj\n");
+        }
+
+        let xref_offset = out.len();
+        out.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+        for offset in offsets {
+            out.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        out.extend_from_slice(
+            format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref_offset).as_bytes(),
+        );
+        out
+    }
+}
+
+/// Escapes a PDF literal string, mapping text to WinAnsi bytes. Characters
+/// outside Latin-1 (other than €) become `?`.
+fn pdf_string(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '(' | ')' | '\\' => {
+                bytes.push(b'\\');
+                bytes.push(ch as u8);
+            }
+            '€' => bytes.push(0x80),
+            c if (c as u32) < 0x100 => bytes.push(c as u32 as u8),
+            _ => bytes.push(b'?'),
+        }
+    }
+    bytes
+}
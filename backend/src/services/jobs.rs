@@ -0,0 +1,89 @@
+use tracing::{error, info};
+use crate::config::AppConfig;
+use crate::models::invoice::Invoice;
+use crate::models::job::JobPayload;
+use crate::models::payment::Payment;
+use crate::services::database::DatabaseService;
+use crate::services::email::{EmailEvent, EmailService};
+use crate::services::invoicing::issue_invoice;
+use crate::services::tickets;
+
+/// Queues a side-effect for the job worker. Failing to queue is logged;
+/// the payment event that caused it has already been applied.
+pub async fn enqueue(db: &DatabaseService, payload: JobPayload) {
+    let kind = payload.kind();
+    let reference = payload.reference().to_string();
+    match db.enqueue_job(payload).await {
+        Ok(job) => info!("Queued {} job {} for {}", kind, job.id, reference),
+        Err(e) => error!("Failed to queue {} job for {}: {}", kind, reference, e),
+    }
+}
+
+/// Runs one job. Errors are returned so the worker can retry it.
+pub async fn run_job(
+    db: &DatabaseService,
+    email: &EmailService,
+    config: &AppConfig,
+    payload: &JobPayload,
+) -> Result<(), String> {
+    let merchant_transaction_id = payload.reference();
+    let payment = db.get_payment_by_merchant_id(merchant_transaction_id).await
+        .ok_or_else(|| format!("No payment found for merchantTransactionId: {}", merchant_transaction_id))?;
+
+    match payload {
+        JobPayload::PaymentSucceededEmail { .. } => {
+            // The receipt carries the tax invoice; issuing is idempotent, so
+            // this also covers an invoice job that hasn't run yet
+            let invoice = issue_payment_invoice(db, config, &payment).await?;
+            let plan = plan_name_for(db, payment.subscription_id.as_deref()).await;
+            email.try_notify_user(db, &payment.user_id, EmailEvent::PaymentSucceeded {
+                plan,
+                amount: payment.amount,
+                currency: payment.currency.clone(),
+                reference: payment.merchant_transaction_id.clone(),
+                descriptor: payment.statement_descriptor.clone(),
+                invoice: Some(Box::new(invoice)),
+            }).await
+        }
+        JobPayload::PaymentFailedEmail { .. } => {
+            let plan = plan_name_for(db, payment.subscription_id.as_deref()).await;
+            email.try_notify_user(db, &payment.user_id, EmailEvent::PaymentFailed {
+                plan,
+                amount: payment.amount,
+                currency: payment.currency.clone(),
+                reference: payment.merchant_transaction_id.clone(),
+            }).await
+        }
+        JobPayload::IssueInvoice { .. } => issue_payment_invoice(db, config, &payment).await.map(|_| ()),
+        JobPayload::OpenFailedPaymentTicket { reason, .. } => {
+            let ticket = tickets::failed_payment_ticket(
+                &payment.user_id,
+                payment.subscription_id.as_deref(),
+                &payment.merchant_transaction_id,
+                reason,
+            );
+            db.open_ticket(ticket).await.map(|_| ())
+        }
+    }
+}
+
+async fn issue_payment_invoice(db: &DatabaseService, config: &AppConfig, payment: &Payment) -> Result<Invoice, String> {
+    issue_invoice(
+        db,
+        config,
+        &payment.user_id,
+        payment.subscription_id.as_deref(),
+        &payment.merchant_transaction_id,
+        &payment.currency,
+        payment.tax(config.vat_rate_percent),
+    ).await
+}
+
+async fn plan_name_for(db: &DatabaseService, subscription_id: Option<&str>) -> String {
+    match subscription_id {
+        Some(id) => db.get_subscription(id).await
+            .map(|s| s.plan_name)
+            .unwrap_or_else(|| "subscription".to_string()),
+        None => "subscription".to_string(),
+    }
+}
@@ -0,0 +1,122 @@
+use chrono::{DateTime, Utc};
+use crate::models::metrics::{
+    MetricsBucket, MrrPoint, MrrSeries, PaymentMetricsPoint, PaymentMetricsSeries, PaymentOutcome,
+    SubscriptionMetricsPoint, SubscriptionMetricsSeries,
+};
+use crate::models::subscription::{Subscription, SubscriptionStatus};
+use crate::services::fx::FxService;
+
+type BucketRange = (DateTime<Utc>, DateTime<Utc>);
+
+/// When a subscription started paying and, if it has, when it stopped.
+/// Subscriptions are treated as paying from their first period until they
+/// were cancelled, paused, or lapsed at the end of grace; earlier pauses
+/// that have since been resumed aren't recorded and so aren't subtracted.
+fn paying_span(sub: &Subscription) -> Option<(DateTime<Utc>, Option<DateTime<Utc>>)> {
+    let started = sub.start_date?;
+    let ended = match sub.status {
+        SubscriptionStatus::Pending | SubscriptionStatus::Active | SubscriptionStatus::Grace => None,
+        SubscriptionStatus::Cancelled => Some(sub.cancels_at().unwrap_or(sub.updated_at)),
+        SubscriptionStatus::Paused => Some(sub.paused_at.unwrap_or(sub.updated_at)),
+        SubscriptionStatus::Suspended | SubscriptionStatus::Expired | SubscriptionStatus::Downgraded => {
+            Some(sub.grace_ends_at().unwrap_or(sub.updated_at).min(sub.updated_at))
+        }
+    };
+    Some((started, ended))
+}
+
+fn is_paying_at(span: &(DateTime<Utc>, Option<DateTime<Utc>>), at: DateTime<Utc>) -> bool {
+    span.0 <= at && span.1.map_or(true, |ended| ended > at)
+}
+
+fn ratio(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        return 0.0;
+    }
+    ((part as f64 / whole as f64) * 10_000.0).round() / 10_000.0
+}
+
+/// MRR at the end of each bucket, in the FX base currency.
+pub fn mrr_series(subscriptions: &[Subscription], ranges: &[BucketRange], bucket: MetricsBucket, fx: &FxService) -> MrrSeries {
+    let spans: Vec<_> = subscriptions.iter().filter_map(|sub| paying_span(sub).map(|span| (sub, span))).collect();
+    let mut unconverted_currencies = Vec::new();
+
+    let points = ranges
+        .iter()
+        .map(|&(start, end)| {
+            let mut mrr = 0.0;
+            let mut paying_subscriptions = 0;
+            for (sub, span) in &spans {
+                // The bucket's last second, so ones starting at the next midnight aren't counted yet
+                if !is_paying_at(span, end - chrono::Duration::seconds(1)) {
+                    continue;
+                }
+                paying_subscriptions += 1;
+                let monthly = sub.total_price() * 30.0 / sub.billing_period_days.max(1) as f64;
+                match fx.to_base(monthly, &sub.currency) {
+                    Some(converted) => mrr += converted,
+                    None if !unconverted_currencies.contains(&sub.currency) => unconverted_currencies.push(sub.currency.clone()),
+                    None => {}
+                }
+            }
+            MrrPoint { start, end, mrr: (mrr * 100.0).round() / 100.0, paying_subscriptions }
+        })
+        .collect();
+
+    MrrSeries {
+        bucket,
+        currency: fx.base_currency().to_string(),
+        unconverted_currencies,
+        points,
+    }
+}
+
+/// New subscriptions, cancellations and churn per bucket.
+pub fn subscription_series(subscriptions: &[Subscription], ranges: &[BucketRange], bucket: MetricsBucket) -> SubscriptionMetricsSeries {
+    let spans: Vec<_> = subscriptions.iter().filter_map(|sub| paying_span(sub).map(|span| (sub, span))).collect();
+    let in_range = |at: DateTime<Utc>, (start, end): BucketRange| at >= start && at < end;
+
+    let points = ranges
+        .iter()
+        .map(|&(start, end)| {
+            let active_at_start = spans.iter().filter(|(_, span)| is_paying_at(span, start)).count() as u64;
+            let new_subscriptions = spans.iter().filter(|(_, span)| in_range(span.0, (start, end))).count() as u64;
+            let cancellations = spans
+                .iter()
+                .filter(|(sub, span)| {
+                    sub.status == SubscriptionStatus::Cancelled && span.1.is_some_and(|ended| in_range(ended, (start, end)))
+                })
+                .count() as u64;
+            SubscriptionMetricsPoint {
+                start,
+                end,
+                active_at_start,
+                new_subscriptions,
+                cancellations,
+                churn_rate: ratio(cancellations, active_at_start),
+            }
+        })
+        .collect();
+
+    SubscriptionMetricsSeries { bucket, points }
+}
+
+/// Succeeded and failed payments per bucket.
+pub fn payment_series(outcomes: &[PaymentOutcome], ranges: &[BucketRange], bucket: MetricsBucket) -> PaymentMetricsSeries {
+    let points = ranges
+        .iter()
+        .map(|&(start, end)| {
+            let (mut succeeded, mut failed) = (0, 0);
+            for outcome in outcomes.iter().filter(|o| o.settled_at >= start && o.settled_at < end) {
+                if outcome.succeeded {
+                    succeeded += 1;
+                } else {
+                    failed += 1;
+                }
+            }
+            PaymentMetricsPoint { start, end, succeeded, failed, failed_rate: ratio(failed, succeeded + failed) }
+        })
+        .collect();
+
+    PaymentMetricsSeries { bucket, points }
+}
@@ -1,3 +1,44 @@
 pub mod database;
+pub mod gateway;
 pub mod peach;
-pub mod subscription;
\ No newline at end of file
+pub mod stripe;
+pub mod ozow;
+pub mod qr;
+pub mod subscription;
+pub mod formatting;
+pub mod dunning;
+pub mod billing_calendar;
+pub mod email;
+pub mod consistency;
+pub mod request_signing;
+pub mod invoicing;
+pub mod tax;
+pub mod fx;
+pub mod entitlements;
+pub mod payment_events;
+pub mod storage;
+pub mod attachments;
+pub mod clock;
+pub mod schema;
+pub mod sandbox;
+pub mod billing_run;
+pub mod rate_limit;
+pub mod operator_alerts;
+pub mod bulk;
+pub mod metrics;
+pub mod tickets;
+pub mod jobs;
+pub mod export;
+pub mod event_sinks;
+pub mod scenario;
+pub mod health;
+pub mod shutdown;
+pub mod resilience;
+pub mod privacy;
+pub mod templates;
+pub mod i18n;
+pub mod audit;
+pub mod tenant;
+pub mod tenant_gateway;
+pub mod installments;
+pub mod reconciliation;
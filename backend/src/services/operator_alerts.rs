@@ -0,0 +1,34 @@
+use reqwest::Client;
+use tracing::error;
+use crate::config::AppConfig;
+use crate::services::email::{EmailEvent, EmailService};
+
+/// Whether any operator channel is configured.
+pub fn has_operator_channels(config: &AppConfig) -> bool {
+    !config.operator_emails.is_empty() || config.slack_webhook_url.is_some()
+}
+
+/// Emails `event` to every operator address and posts `slack_text` to the
+/// Slack webhook. Failures are logged; one channel failing doesn't stop the other.
+pub async fn notify_operators(
+    config: &AppConfig,
+    email: &EmailService,
+    client: &Client,
+    event: &EmailEvent,
+    slack_text: &str,
+) {
+    for address in &config.operator_emails {
+        if let Err(e) = email.send(address, "Operations", event).await {
+            error!("Failed to email {} to {}: {}", event.name(), address, e);
+        }
+    }
+
+    if let Some(url) = &config.slack_webhook_url {
+        let payload = serde_json::json!({ "text": slack_text });
+        match client.post(url).json(&payload).send().await {
+            Ok(response) if response.status().is_success() => {}
+            Ok(response) => error!("Slack rejected {}: Status {}", event.name(), response.status()),
+            Err(e) => error!("Failed to post {} to Slack: {}", event.name(), e),
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+use std::env;
+use actix_web::http::header::HeaderMap;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha512};
+use tracing::debug;
+use crate::services::gateway::{
+    ChargeStatus, CheckoutRequest, CheckoutSession, GatewayResult, GatewayTransaction, PaymentGateway,
+    WebhookKind, WebhookNotification,
+};
+
+const OZOW_API_URL: &str = "https://api.ozow.com";
+
+/// Ozow instant EFT. The customer is redirected to Ozow to pay from their
+/// bank account, so there is no stored token and no merchant-initiated charge.
+///
+/// Ozow looks transactions up by our reference, so the merchant transaction id
+/// doubles as the checkout id.
+#[derive(Clone)]
+pub struct OzowPaymentService {
+    client: Client,
+    api_url: String,
+    site_code: String,
+    api_key: String,
+    private_key: String,
+    success_url: String,
+    cancel_url: String,
+    error_url: String,
+    notify_url: String,
+    is_test: bool,
+    supported_currencies: Vec<String>,
+}
+
+impl OzowPaymentService {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        site_code: String,
+        api_key: String,
+        private_key: String,
+        success_url: String,
+        cancel_url: String,
+        error_url: String,
+        notify_url: String,
+        is_test: bool,
+        supported_currencies: Vec<String>,
+    ) -> Self {
+        Self {
+            client: Client::new(),
+            api_url: OZOW_API_URL.to_string(),
+            site_code,
+            api_key,
+            private_key,
+            success_url,
+            cancel_url,
+            error_url,
+            notify_url,
+            is_test,
+            supported_currencies,
+        }
+    }
+
+    /// Builds the service from `OZOW_*` settings. Returns `None` when
+    /// `OZOW_SITE_CODE` is unset, in which case EFT stays on the main gateway.
+    pub fn from_env(supported_currencies: Vec<String>) -> Result<Option<Self>, String> {
+        let site_code = match env::var("OZOW_SITE_CODE") {
+            Ok(code) if !code.trim().is_empty() => code,
+            _ => return Ok(None),
+        };
+        let required = |key: &str| env::var(key).map_err(|_| format!("{} must be set when OZOW_SITE_CODE is", key));
+        let result_url = env::var("OZOW_RESULT_URL")
+            .unwrap_or_else(|_| "http://127.0.0.1:8080/payment-result.html".to_string());
+
+        Ok(Some(Self::new(
+            site_code,
+            required("OZOW_API_KEY")?,
+            required("OZOW_PRIVATE_KEY")?,
+            result_url.clone(),
+            result_url.clone(),
+            result_url,
+            required("OZOW_NOTIFY_URL")?,
+            env::var("OZOW_IS_TEST").map(|v| v.eq_ignore_ascii_case("true")).unwrap_or(false),
+            supported_currencies,
+        )))
+    }
+
+    /// Ozow's `HashCheck`: SHA-512 of the values in field order followed by
+    /// the private key, all lower-cased.
+    fn hash(&self, values: &[&str]) -> String {
+        let mut input = values.concat();
+        input.push_str(&self.private_key);
+        hex::encode(Sha512::digest(input.to_lowercase().as_bytes()))
+    }
+
+    fn is_test_flag(&self) -> &'static str {
+        if self.is_test { "true" } else { "false" }
+    }
+}
+
+/// Ozow statuses: `Complete` succeeded, `Pending` and `PendingInvestigation`
+/// are unresolved, and `Cancelled`, `Error` and `Abandoned` failed.
+pub fn charge_status(status: &str) -> ChargeStatus {
+    match status {
+        "Complete" => ChargeStatus::Succeeded,
+        "Pending" | "PendingInvestigation" => ChargeStatus::Pending,
+        _ => ChargeStatus::Failed,
+    }
+}
+
+/// Fields, in order, that Ozow hashes on a notification (before the private key).
+const NOTIFICATION_HASH_FIELDS: &[&str] = &[
+    "SiteCode",
+    "TransactionId",
+    "TransactionReference",
+    "Amount",
+    "Status",
+    "Optional1",
+    "Optional2",
+    "Optional3",
+    "Optional4",
+    "Optional5",
+    "CurrencyCode",
+    "IsTest",
+    "StatusMessage",
+];
+
+#[async_trait]
+impl PaymentGateway for OzowPaymentService {
+    fn name(&self) -> &'static str {
+        "ozow"
+    }
+
+    fn supported_currencies(&self) -> &[String] {
+        &self.supported_currencies
+    }
+
+    fn is_live(&self) -> bool {
+        !self.is_test
+    }
+
+    async fn initiate_checkout(&self, request: &CheckoutRequest<'_>) -> GatewayResult<CheckoutSession> {
+        let amount = format!("{:.2}", request.amount);
+        let currency = request.currency.to_uppercase();
+        let is_test = self.is_test_flag();
+
+        let hash_check = self.hash(&[
+            self.site_code.as_str(),
+            "ZA",
+            currency.as_str(),
+            amount.as_str(),
+            request.merchant_transaction_id,
+            request.merchant_transaction_id,
+            request.subscription_id,
+            request.user_id,
+            self.cancel_url.as_str(),
+            self.error_url.as_str(),
+            self.success_url.as_str(),
+            self.notify_url.as_str(),
+            is_test,
+        ]);
+
+        let body = json!({
+            "siteCode": self.site_code,
+            "countryCode": "ZA",
+            "currencyCode": currency,
+            "amount": amount,
+            "transactionReference": request.merchant_transaction_id,
+            "bankReference": request.merchant_transaction_id,
+            "optional1": request.subscription_id,
+            "optional2": request.user_id,
+            "cancelUrl": self.cancel_url,
+            "errorUrl": self.error_url,
+            "successUrl": self.success_url,
+            "notifyUrl": self.notify_url,
+            "isTest": self.is_test,
+            "hashCheck": hash_check,
+        });
+
+        let response = self.client
+            .post(format!("{}/postpaymentrequest", self.api_url))
+            .header("ApiKey", &self.api_key)
+            .header("Accept", "application/json")
+            .json(&body)
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body_text = response.text().await?;
+        debug!("Ozow payment request response status: {}", status);
+
+        if !status.is_success() {
+            return Err(format!("Ozow API error: Status {}, Body: {}", status, body_text).into());
+        }
+
+        let response: Value = serde_json::from_str(&body_text)?;
+        if let Some(error) = response.get("errorMessage").and_then(|e| e.as_str()).filter(|e| !e.is_empty()) {
+            return Err(format!("Ozow rejected the payment request: {}", error).into());
+        }
+        let url = response
+            .get("url")
+            .and_then(|u| u.as_str())
+            .ok_or_else(|| format!("Ozow response missing 'url': {}", response))?;
+
+        Ok(CheckoutSession {
+            checkout_id: request.merchant_transaction_id.to_string(),
+            redirect_url: Some(url.to_string()),
+            registration_id: None,
+        })
+    }
+
+    async fn check_status(&self, checkout_id: &str) -> GatewayResult<GatewayTransaction> {
+        let response = self.client
+            .get(format!("{}/GetTransactionByReference", self.api_url))
+            .query(&[
+                ("siteCode", self.site_code.as_str()),
+                ("transactionReference", checkout_id),
+                ("isTest", self.is_test_flag()),
+            ])
+            .header("ApiKey", &self.api_key)
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body_text = response.text().await?;
+        if !status.is_success() {
+            return Err(format!("Ozow status API error: Status {}, Body: {}", status, body_text).into());
+        }
+        let body: Value = serde_json::from_str(&body_text)?;
+
+        // One reference can have several attempts; the latest decides
+        let latest = body
+            .as_array()
+            .and_then(|attempts| attempts.last())
+            .cloned();
+        let text = |key: &str| latest.as_ref().and_then(|t| t.get(key)).and_then(|v| v.as_str()).map(|s| s.to_string());
+
+        let code = text("status").unwrap_or_else(|| "Pending".to_string());
+        Ok(GatewayTransaction {
+            status: charge_status(&code),
+            description: text("statusMessage"),
+            gateway_reference: text("transactionId"),
+            merchant_transaction_id: Some(checkout_id.to_string()),
+            payment_brand: Some("ozow".to_string()),
+            registration_id: None,
+            card_last4: None,
+            card_expiry: None,
+            authentication_url: None,
+            authorized_only: false,
+            code,
+            raw: body,
+        })
+    }
+
+    async fn charge_token(
+        &self,
+        _token: &str,
+        _amount: f64,
+        _currency: &str,
+        _merchant_transaction_id: &str,
+        _descriptor: Option<&str>,
+    ) -> GatewayResult<GatewayTransaction> {
+        Err("Ozow EFT payments cannot be charged without the customer".into())
+    }
+
+    async fn refund(
+        &self,
+        _gateway_reference: &str,
+        _amount: f64,
+        _currency: &str,
+        _merchant_transaction_id: &str,
+    ) -> GatewayResult<GatewayTransaction> {
+        Err("Ozow refunds must be issued from the Ozow merchant dashboard".into())
+    }
+
+    fn webhook_signature(&self, _headers: &HeaderMap, body: &[u8]) -> Option<String> {
+        let form_map: HashMap<String, String> = serde_urlencoded::from_bytes(body).ok()?;
+        form_map.get("Hash").filter(|s| !s.is_empty()).cloned()
+    }
+
+    fn validate_webhook(&self, body: &[u8], signature: &str) -> bool {
+        let form_map: HashMap<String, String> = match serde_urlencoded::from_bytes(body) {
+            Ok(map) => map,
+            Err(_) => return false,
+        };
+        if form_map.get("SiteCode") != Some(&self.site_code) {
+            return false;
+        }
+
+        let values: Vec<&str> = NOTIFICATION_HASH_FIELDS
+            .iter()
+            .map(|key| form_map.get(*key).map(|v| v.as_str()).unwrap_or(""))
+            .collect();
+        self.hash(&values).eq_ignore_ascii_case(signature)
+    }
+
+    fn parse_webhook(&self, body: &[u8]) -> Result<WebhookNotification, String> {
+        let form_map: HashMap<String, String> = serde_urlencoded::from_bytes(body)
+            .map_err(|e| format!("Invalid form data: {}", e))?;
+        let field = |key: &str| form_map.get(key).filter(|v| !v.is_empty()).cloned();
+        let code = field("Status").unwrap_or_default();
+
+        Ok(WebhookNotification {
+            kind: WebhookKind::Payment,
+            transaction: GatewayTransaction {
+                status: charge_status(&code),
+                description: field("StatusMessage"),
+                gateway_reference: field("TransactionId"),
+                merchant_transaction_id: field("TransactionReference"),
+                payment_brand: Some("ozow".to_string()),
+                registration_id: None,
+                card_last4: None,
+                card_expiry: None,
+                authentication_url: None,
+                authorized_only: false,
+                code,
+                raw: serde_json::to_value(&form_map).unwrap_or_default(),
+            },
+            subscription_id: field("Optional1"),
+            // Ozow notifications carry no event time
+            occurred_at: None,
+            signature: field("Hash"),
+            fields: serde_json::to_value(&form_map).unwrap_or_default(),
+        })
+    }
+}
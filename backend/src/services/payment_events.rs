@@ -0,0 +1,45 @@
+use tokio::sync::broadcast;
+use crate::models::payment::PaymentStatus;
+
+/// How many unread status changes a slow listener may fall behind by before
+/// it starts missing them (and has to re-read the database instead).
+const PAYMENT_EVENT_BUFFER: usize = 256;
+
+#[derive(Debug, Clone)]
+pub struct PaymentStatusChanged {
+    pub merchant_transaction_id: String,
+    pub status: PaymentStatus,
+}
+
+/// In-process feed of payment status changes, so long-polling requests wake
+/// as soon as a webhook or status check settles a payment. Changes made by
+/// other instances are not seen; listeners should still re-check now and then.
+#[derive(Clone)]
+pub struct PaymentEvents {
+    sender: broadcast::Sender<PaymentStatusChanged>,
+}
+
+impl PaymentEvents {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(PAYMENT_EVENT_BUFFER);
+        Self { sender }
+    }
+
+    pub fn publish(&self, merchant_transaction_id: &str, status: &PaymentStatus) {
+        // No listeners is the common case and not an error
+        let _ = self.sender.send(PaymentStatusChanged {
+            merchant_transaction_id: merchant_transaction_id.to_string(),
+            status: status.clone(),
+        });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PaymentStatusChanged> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for PaymentEvents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,9 +1,31 @@
-use reqwest::Client;
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
 use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use actix_web::http::header::HeaderMap;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use hmac::{Hmac, Mac};
 use sha2::Sha256;
+use tokio::sync::{Mutex, RwLock};
+use tokio::time::sleep;
 use uuid::Uuid;
+use tracing::{debug, info, warn};
+use crate::models::payment::{PaymentMethod, PaymentStatus};
+use crate::models::reconciliation::SettlementRecord;
+use crate::models::recurring_payment::CardExpiry;
+use crate::models::tenant::PeachCredentials;
+use crate::models::webhook_event::ResultCodeCategory;
+use crate::telemetry::{current_request_id, REQUEST_ID_HEADER};
+use crate::services::resilience::{CircuitBreaker, HttpSettings};
+use crate::services::gateway::{
+    CardRegistrationRequest, ChargeStatus, CheckoutRequest, CheckoutSession, CheckoutWidget, GatewayResult, GatewayTransaction, PaymentGateway,
+    WebhookKind, WebhookNotification,
+};
 
 #[derive(Clone)]
 pub struct PeachPaymentService {
@@ -17,8 +39,114 @@ pub struct PeachPaymentService {
     notification_url: String,
     shopper_result_url: String,
     webhook_secret_key: String,
+    supported_currencies: Vec<String>,
+    /// Embedded checkout script; follows the environment unless overridden.
+    checkout_script_url: Option<String>,
+    payment_brands: Vec<String>,
+    /// AES-256 key for encrypted webhooks; plain form webhooks need none.
+    webhook_decryption_key: Option<[u8; 32]>,
+    environment: PeachEnvironment,
+    /// Shared by clones, so every handler reuses one token until it nears expiry.
+    access_token: Arc<RwLock<Option<AccessToken>>>,
+    /// Held while fetching a token, so concurrent callers wait for one
+    /// request instead of each calling the auth endpoint.
+    token_refresh: Arc<Mutex<()>>,
+    http: HttpSettings,
+    breaker: Arc<CircuitBreaker>,
 }
 
+#[derive(Debug, Clone)]
+struct AccessToken {
+    value: String,
+    /// When to stop using the token: its expiry less `TOKEN_REFRESH_MARGIN`.
+    refresh_at: Instant,
+}
+
+/// Used when the auth response doesn't say how long its token lasts.
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(60 * 60);
+/// Tokens are replaced this long before they expire, so one never lapses
+/// mid-request.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// Which Peach account the credentials belong to. Picks the default
+/// endpoints and checkout script, and is checked against explicit URLs so
+/// test settings can't end up pointing at live ones or vice versa.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeachEnvironment {
+    Sandbox,
+    Live,
+}
+
+impl PeachEnvironment {
+    /// `sandbox` or `live`; `PEACH_ENVIRONMENT` takes the same values.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "sandbox" => Ok(PeachEnvironment::Sandbox),
+            "live" => Ok(PeachEnvironment::Live),
+            other => Err(format!("unknown Peach environment '{}'; expected sandbox or live", other)),
+        }
+    }
+
+    /// What a URL points at, judged by Peach's test host names.
+    fn of_url(url: &str) -> Self {
+        if url.contains("sandbox") || url.contains("testsecure") {
+            PeachEnvironment::Sandbox
+        } else {
+            PeachEnvironment::Live
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PeachEnvironment::Sandbox => "sandbox",
+            PeachEnvironment::Live => "live",
+        }
+    }
+
+    pub fn default_auth_url(&self) -> &'static str {
+        match self {
+            PeachEnvironment::Sandbox => "https://sandbox-dashboard.peachpayments.com/api/oauth/token",
+            PeachEnvironment::Live => "https://dashboard.peachpayments.com/api/oauth/token",
+        }
+    }
+
+    pub fn default_checkout_url(&self) -> &'static str {
+        match self {
+            PeachEnvironment::Sandbox => "https://testsecure.peachpayments.com/v2/checkout",
+            PeachEnvironment::Live => "https://secure.peachpayments.com/v2/checkout",
+        }
+    }
+
+    fn checkout_script_url(&self) -> &'static str {
+        match self {
+            PeachEnvironment::Sandbox => "https://sandbox-checkout.peachpayments.com/js/checkout.js",
+            PeachEnvironment::Live => "https://checkout.peachpayments.com/js/checkout.js",
+        }
+    }
+
+    /// Rejects a URL belonging to the other environment.
+    pub fn check_url(&self, key: &str, url: &str) -> Result<(), String> {
+        let points_at = Self::of_url(url);
+        if points_at != *self {
+            return Err(format!(
+                "{} points at Peach {} but PEACH_ENVIRONMENT is {}",
+                key,
+                points_at.as_str(),
+                self.as_str()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Brands the embedded checkout offers unless `with_checkout_widget` says otherwise.
+pub const DEFAULT_PAYMENT_BRANDS: &[&str] = &["VISA", "MASTER"];
+
+/// Headers carrying the AES-GCM nonce and authentication tag of an
+/// encrypted webhook, both hex.
+const IV_HEADER: &str = "X-Initialization-Vector";
+const AUTH_TAG_HEADER: &str = "X-Authentication-Tag";
+
 impl PeachPaymentService {
     pub fn new(
         v2_auth_url: String,
@@ -30,11 +158,12 @@ impl PeachPaymentService {
         notification_url: String,
         shopper_result_url: String,
         webhook_secret_key: String, // For webhook HMAC
+        supported_currencies: Vec<String>,
     ) -> Self {
+        let http = HttpSettings::default();
         Self {
-            client: Client::new(),
+            client: http.client(),
             v2_auth_url,
-            v2_checkout_url,
             v2_entity_id,
             client_id,
             client_secret,
@@ -42,27 +171,140 @@ impl PeachPaymentService {
             notification_url,
             shopper_result_url,
             webhook_secret_key,
+            supported_currencies,
+            checkout_script_url: None,
+            payment_brands: DEFAULT_PAYMENT_BRANDS.iter().map(|b| b.to_string()).collect(),
+            webhook_decryption_key: None,
+            access_token: Arc::new(RwLock::new(None)),
+            token_refresh: Arc::new(Mutex::new(())),
+            breaker: Arc::new(CircuitBreaker::new("peach", &http)),
+            http,
+            environment: PeachEnvironment::of_url(&v2_checkout_url),
+            v2_checkout_url,
         }
     }
 
+    /// Sets the environment explicitly instead of inferring it from the
+    /// checkout endpoint.
+    pub fn with_environment(mut self, environment: PeachEnvironment) -> Self {
+        self.environment = environment;
+        self
+    }
+
+    /// Replaces the default timeouts, retries and circuit breaker limits.
+    pub fn with_http_settings(mut self, http: HttpSettings) -> Self {
+        self.client = http.client();
+        self.breaker = Arc::new(CircuitBreaker::new("peach", &http));
+        self.http = http;
+        self
+    }
+
+    /// Sends `request` through the circuit breaker. Timeouts, connection
+    /// errors and 5xx responses count against it and are retried up to
+    /// `retries` times, so only idempotent requests should ask for any.
+    async fn send(&self, request: RequestBuilder, retries: u32) -> Result<Response, Box<dyn std::error::Error + Send + Sync>> {
+        let mut attempt = 0;
+        loop {
+            self.breaker.check()?;
+            let this_try = request.try_clone().ok_or("Peach request body cannot be resent")?;
+            let outcome = this_try.send().await;
+            let failed = match &outcome {
+                Ok(response) => response.status().is_server_error(),
+                Err(_) => true,
+            };
+            if !failed {
+                self.breaker.record_success();
+            } else {
+                self.breaker.record_failure();
+            }
+
+            let retriable = failed || matches!(&outcome, Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS);
+            if !retriable || attempt >= retries {
+                return Ok(outcome?);
+            }
+            attempt += 1;
+            debug!("Retrying Peach request (attempt {} of {})", attempt + 1, retries + 1);
+            sleep(HttpSettings::retry_delay(attempt)).await;
+        }
+    }
+
+    /// Sends `request` with the cached bearer token. Peach can revoke a token
+    /// before it expires, so on a 401 the token is dropped and the request
+    /// sent once more with a fresh one.
+    async fn send_authorized(&self, request: RequestBuilder, retries: u32) -> Result<Response, Box<dyn std::error::Error + Send + Sync>> {
+        let token = self.get_oauth_token().await?;
+        let retry = request.try_clone().ok_or("Peach request body cannot be resent")?;
+        let response = self.send(request.bearer_auth(&token), retries).await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+
+        warn!("Peach rejected the access token; requesting a new one");
+        self.discard_token(&token).await;
+        let token = self.get_oauth_token().await?;
+        self.send(retry.bearer_auth(token), retries).await
+    }
+
+    /// Accepts encrypted webhooks, decrypted with `key`.
+    pub fn with_webhook_decryption_key(mut self, key: Option<[u8; 32]>) -> Self {
+        self.webhook_decryption_key = key;
+        self
+    }
+
+    /// Overrides the embedded checkout script and the brands it offers; an
+    /// empty brand list keeps the defaults.
+    pub fn with_checkout_widget(mut self, script_url: Option<String>, payment_brands: Vec<String>) -> Self {
+        self.checkout_script_url = script_url;
+        if !payment_brands.is_empty() {
+            self.payment_brands = payment_brands;
+        }
+        self
+    }
+
+    /// The same endpoints and settings for another merchant account. It
+    /// gets its own access token but shares the circuit breaker, which
+    /// tracks Peach itself rather than any one account.
+    pub fn with_credentials(&self, credentials: &PeachCredentials) -> Self {
+        Self {
+            v2_entity_id: credentials.entity_id.clone(),
+            client_id: credentials.client_id.clone(),
+            client_secret: credentials.client_secret.clone(),
+            merchant_id: credentials.merchant_id.clone(),
+            webhook_secret_key: credentials.webhook_secret_key.clone(),
+            notification_url: credentials.notification_url.clone().unwrap_or_else(|| self.notification_url.clone()),
+            shopper_result_url: credentials.shopper_result_url.clone().unwrap_or_else(|| self.shopper_result_url.clone()),
+            access_token: Arc::new(RwLock::new(None)),
+            token_refresh: Arc::new(Mutex::new(())),
+            ..self.clone()
+        }
+    }
+
+    pub fn environment(&self) -> PeachEnvironment {
+        self.environment
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub async fn initiate_checkout_api_v2_with_tokenization(
         &self,
         user_id: &str,
         subscription_id: &str,
         amount: f64,
+        currency: &str,
         merchant_transaction_id: &str,
+        default_payment_method: Option<&str>,
+        descriptor: Option<&str>,
+        payment_type: &str,
     ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
-        let token = self.get_oauth_token().await?;
 
         let nonce = Uuid::new_v4().to_string();
-let payload = json!({
+let mut payload = json!({
     "authentication": {
         "entityId": self.v2_entity_id,
     },
     "amount": amount,
-    "currency": "ZAR",
+    "currency": currency,
     "merchantTransactionId": merchant_transaction_id,
-    "paymentType": "DB",
+    "paymentType": payment_type,
     "nonce": nonce,
     "customer": {
         "merchantCustomerId": user_id
@@ -75,23 +317,68 @@ let payload = json!({
     "notificationUrl": self.notification_url,
     "shopperResultUrl": self.shopper_result_url
 });
-        println!("Initiate Checkout V2 Payload: {}", payload);
+        if let Some(method) = default_payment_method {
+            payload["defaultPaymentMethod"] = json!(method);
+            payload["forceDefaultMethod"] = json!(true);
+        }
+        if let Some(descriptor) = descriptor {
+            payload["descriptor"] = json!(descriptor);
+        }
+        self.post_checkout_v2(&payload).await
+    }
+
+    /// Zero-amount pre-authorisation that only stores the card, used to
+    /// replace a saved card without a new purchase.
+    pub async fn initiate_registration_checkout(
+        &self,
+        user_id: &str,
+        subscription_id: &str,
+        currency: &str,
+        merchant_transaction_id: &str,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+
+        let payload = json!({
+            "authentication": {
+                "entityId": self.v2_entity_id,
+            },
+            "amount": "0.00",
+            "currency": currency,
+            "merchantTransactionId": merchant_transaction_id,
+            "paymentType": "PA",
+            "nonce": Uuid::new_v4().to_string(),
+            "customer": {
+                "merchantCustomerId": user_id
+            },
+            "createRegistration": true,
+            "customParameters": {
+                "subscription_id": subscription_id,
+                "user_id": user_id
+            },
+            "notificationUrl": self.notification_url,
+            "shopperResultUrl": self.shopper_result_url
+        });
+        self.post_checkout_v2(&payload).await
+    }
 
-        let response = self.client
+    async fn post_checkout_v2(
+        &self,
+        payload: &Value,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        debug!("Initiate Checkout V2 Payload: {}", payload);
+
+        let request = self.client
             .post(&self.v2_checkout_url)
+            .headers(correlation_headers())
             .header("Content-Type", "application/json")
             .header("Origin", "http://127.0.0.1:8001")
-
-            .bearer_auth(token)
-            .json(&payload)
-            .send()
-            .await?;
+            .json(payload);
+        let response = self.send_authorized(request, 0).await?;
 
         let status = response.status();
         let body_text = response.text().await?;
 
-        println!("Checkout API response status: {}", status);
-        println!("Checkout API response body: {}", body_text);
+        debug!("Checkout API response status: {}", status);
+        debug!("Checkout API response body: {}", body_text);
 
         if !status.is_success() {
             return Err(format!("Checkout API error: Status {}, Body: {}", status, body_text).into());
@@ -105,45 +392,169 @@ let payload = json!({
         &self,
         registration_id: &str,
         amount: f64,
+        currency: &str,
         initial_transaction_id: &str,
+        descriptor: Option<&str>,
     ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
         let url = format!("{}/registrations/{}/payments", self.v2_checkout_url, registration_id);
 
-        let payload = [
+        let amount = amount.to_string();
+        let mut payload = vec![
             ("entityId", self.v2_entity_id.as_str()),
-            ("amount", &amount.to_string()),
-            ("currency", "ZAR"),
+            ("amount", amount.as_str()),
+            ("currency", currency),
             ("paymentType", "PA"),
             ("standingInstruction.mode", "REPEATED"),
             ("standingInstruction.type", "RECURRING"),
             ("standingInstruction.source", "MIT"),
             ("standingInstruction.initialTransactionId", initial_transaction_id),
         ];
+        if let Some(descriptor) = descriptor {
+            payload.push(("descriptor", descriptor));
+        }
 
-        let response = self.client
+        let request = self.client
             .post(&url)
-            .form(&payload)
-            .send()
-            .await?
+            .headers(correlation_headers())
+            .form(&payload);
+        let response = self.send(request, 0).await?
             .json::<Value>()
             .await?;
 
         Ok(response)
     }
 
+    /// Refunds (fully or partially) a previously captured payment.
+    /// `merchant_transaction_id` identifies the refund itself so its webhook can be matched.
+    pub async fn process_refund(
+        &self,
+        peach_payment_id: &str,
+        amount: f64,
+        currency: &str,
+        merchant_transaction_id: &str,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let url = format!("{}/payments/{}", self.v2_checkout_url, peach_payment_id);
+
+        let amount_str = format!("{:.2}", amount);
+        let payload = [
+            ("entityId", self.v2_entity_id.as_str()),
+            ("amount", amount_str.as_str()),
+            ("currency", currency),
+            ("paymentType", "RF"),
+            ("merchantTransactionId", merchant_transaction_id),
+            ("notificationUrl", self.notification_url.as_str()),
+        ];
+
+        let request = self.client
+            .post(&url)
+            .headers(correlation_headers())
+            .form(&payload);
+        let response = self.send_authorized(request, 0).await?;
+
+        let status = response.status();
+        let body_text = response.text().await?;
+
+        debug!("Refund API response status: {}", status);
+        debug!("Refund API response body: {}", body_text);
+
+        if !status.is_success() {
+            return Err(format!("Refund API error: Status {}, Body: {}", status, body_text).into());
+        }
+
+        let body: Value = serde_json::from_str(&body_text)?;
+        Ok(body)
+    }
+
+    /// Back-office operation on an earlier payment: `CP` captures a
+    /// pre-authorisation, `RV` reverses (voids) it.
+    pub async fn process_backoffice_operation(
+        &self,
+        peach_payment_id: &str,
+        payment_type: &str,
+        amount: Option<f64>,
+        currency: &str,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let url = format!("{}/payments/{}", self.v2_checkout_url, peach_payment_id);
+
+        let amount_str = amount.map(|a| format!("{:.2}", a));
+        let mut payload = vec![
+            ("entityId", self.v2_entity_id.as_str()),
+            ("paymentType", payment_type),
+            ("currency", currency),
+        ];
+        if let Some(amount) = amount_str.as_deref() {
+            payload.push(("amount", amount));
+        }
+
+        let request = self.client
+            .post(&url)
+            .headers(correlation_headers())
+            .form(&payload);
+        let response = self.send_authorized(request, 0).await?;
+
+        let status = response.status();
+        let body_text = response.text().await?;
+
+        debug!("{} API response status: {}", payment_type, status);
+        debug!("{} API response body: {}", payment_type, body_text);
+
+        if !status.is_success() {
+            return Err(format!("{} API error: Status {}, Body: {}", payment_type, status, body_text).into());
+        }
+
+        let body: Value = serde_json::from_str(&body_text)?;
+        Ok(body)
+    }
+
+    /// A bearer token for the Checkout and Payments APIs, reused until shortly
+    /// before it expires.
     pub async fn get_oauth_token(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(token) = self.cached_token().await {
+            return Ok(token);
+        }
+
+        let _refreshing = self.token_refresh.lock().await;
+        // Whoever held the lock before may have just fetched one
+        if let Some(token) = self.cached_token().await {
+            return Ok(token);
+        }
+
+        let token = self.request_oauth_token().await?;
+        let value = token.value.clone();
+        *self.access_token.write().await = Some(token);
+        Ok(value)
+    }
+
+    async fn cached_token(&self) -> Option<String> {
+        self.access_token
+            .read()
+            .await
+            .as_ref()
+            .filter(|token| Instant::now() < token.refresh_at)
+            .map(|token| token.value.clone())
+    }
+
+    /// Forgets `rejected` unless another request has already replaced it.
+    async fn discard_token(&self, rejected: &str) {
+        let mut current = self.access_token.write().await;
+        if current.as_ref().is_some_and(|token| token.value == rejected) {
+            *current = None;
+        }
+    }
+
+    async fn request_oauth_token(&self) -> Result<AccessToken, Box<dyn std::error::Error + Send + Sync>> {
         let payload = json!({
             "clientId": self.client_id,
             "clientSecret": self.client_secret,
             "merchantId": self.merchant_id
         });
 
-        let response = self.client
+        let request = self.client
             .post(&self.v2_auth_url)
+            .headers(correlation_headers())
             .header("Content-Type", "application/json")
-            .json(&payload)
-            .send()
-            .await?;
+            .json(&payload);
+        let response = self.send(request, 0).await?;
 
         let status = response.status();
         let response_text = response.text().await?;
@@ -153,12 +564,20 @@ let payload = json!({
         }
 
         let body: Value = serde_json::from_str(&response_text)?;
-        let token = body["access_token"]
+        let value = body["access_token"]
             .as_str()
             .ok_or("No access_token in response")?
             .to_string();
+        let lifetime = body["expires_in"]
+            .as_u64()
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TOKEN_LIFETIME);
+        debug!("Fetched Peach access token valid for {}s", lifetime.as_secs());
 
-        Ok(token)
+        Ok(AccessToken {
+            value,
+            refresh_at: Instant::now() + lifetime.saturating_sub(TOKEN_REFRESH_MARGIN),
+        })
     }
 
     /// Calculates the HMAC-SHA256 signature for webhook validation
@@ -178,6 +597,27 @@ let payload = json!({
         calculated == signature
     }
 
+    /// Decrypts an encrypted webhook's `encryptedBody`. `signature` is the
+    /// hex IV and authentication tag joined by `:`, as `webhook_signature`
+    /// returns them; a body that fails authentication is an error.
+    pub fn decrypt_webhook_body(&self, ciphertext: &[u8], signature: &str) -> Result<Vec<u8>, String> {
+        let key = self.webhook_decryption_key.as_ref()
+            .ok_or("Encrypted webhook received but PEACH_WEBHOOK_DECRYPTION_KEY is not set")?;
+        let (iv, tag) = signature.split_once(':').ok_or("Missing initialization vector or authentication tag")?;
+        let iv = hex::decode(iv).map_err(|_| "Initialization vector is not hex")?;
+        let tag = hex::decode(tag).map_err(|_| "Authentication tag is not hex")?;
+        if iv.len() != 12 || tag.len() != 16 {
+            return Err("Initialization vector must be 12 bytes and authentication tag 16".to_string());
+        }
+
+        let cipher = Aes256Gcm::new_from_slice(key).map_err(|e| e.to_string())?;
+        let mut sealed = ciphertext.to_vec();
+        sealed.extend_from_slice(&tag);
+        cipher
+            .decrypt(Nonce::from_slice(&iv), sealed.as_slice())
+            .map_err(|_| "Webhook failed decryption; wrong key or tampered body".to_string())
+    }
+
     /// Optional: Validate required config fields
     pub fn validate_config(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         if self.client_id.is_empty()
@@ -192,24 +632,18 @@ let payload = json!({
             return Err("Missing Peach config values".into());
         }
 
-        println!("✓ PeachPaymentService config validated");
+        info!("PeachPaymentService config validated");
         Ok(())
     }
 
-    pub async fn check_payment_status(&self, checkout_id: &str) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
-        // TODO: Implement querying Peach Payments status endpoint
-        unimplemented!()
-    }
-
     pub async fn get_checkout_status(&self, checkout_id: &str) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
-    let token = self.get_oauth_token().await?;
     let url = format!("{}/checkouts/{}", self.v2_checkout_url, checkout_id);
 
-    let response = self.client
+    // Status checks are safe to repeat, so transient failures are retried
+    let request = self.client
         .get(&url)
-        .bearer_auth(token)
-        .send()
-        .await?;
+        .headers(correlation_headers());
+    let response = self.send_authorized(request, self.http.status_retries).await?;
 
     let status = response.status();
     let body_text = response.text().await?;
@@ -222,4 +656,435 @@ let payload = json!({
     Ok(body)
 }
 
+    /// Transactions on the entity in `[from, to)` from Peach's transaction
+    /// reports (query) API, as `{"records": [...]}`.
+    pub async fn query_transactions(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let url = format!("{}/query", self.v2_checkout_url);
+
+        let from = from.format("%Y-%m-%d %H:%M:%S").to_string();
+        let to = to.format("%Y-%m-%d %H:%M:%S").to_string();
+        let request = self.client
+            .get(&url)
+            .headers(correlation_headers())
+            .query(&[
+                ("entityId", self.v2_entity_id.as_str()),
+                ("date.from", from.as_str()),
+                ("date.to", to.as_str()),
+            ]);
+        let response = self.send_authorized(request, self.http.status_retries).await?;
+
+        let status = response.status();
+        let body_text = response.text().await?;
+
+        if !status.is_success() {
+            return Err(format!("Transaction query API error: Status {}, Body: {}", status, body_text).into());
+        }
+
+        let body: Value = serde_json::from_str(&body_text)?;
+        Ok(body)
+    }
+}
+
+/// Peach/OPP result codes: `000.000.x` and `000.100.x` succeeded, `000.200.x`
+/// is still pending, anything else failed.
+pub fn charge_status(code: &str) -> ChargeStatus {
+    if code.starts_with("000.000") || code.starts_with("000.100") {
+        ChargeStatus::Succeeded
+    } else if code.starts_with("000.200") {
+        ChargeStatus::Pending
+    } else {
+        ChargeStatus::Failed
+    }
+}
+
+/// `charge_status`, except that a pending result carrying a redirect is a
+/// 3-D Secure challenge waiting on the shopper.
+fn transaction_status(code: &str, authentication_url: Option<&str>) -> ChargeStatus {
+    match charge_status(code) {
+        ChargeStatus::Pending if authentication_url.is_some() => ChargeStatus::AwaitingAuthentication,
+        status => status,
+    }
+}
+
+/// Result code groups Peach documents as rejections (bank, risk, validation
+/// and system errors).
+const DECLINED_CODE_PREFIXES: &[&str] = &[
+    "000.400.030", "000.400.1", "000.400.2",
+    "100.100", "100.150", "100.2", "100.3", "100.400.0", "100.400.1", "100.400.2", "100.400.3",
+    "100.50", "100.55", "100.57", "100.600.500", "100.700", "100.800", "100.900",
+    "200.1", "200.2", "200.3", "300.100.100", "500.1", "500.2", "600.1", "600.2", "600.3",
+    "700.100", "700.150", "700.300", "700.350", "700.400", "700.450", "700.500", "700.550",
+    "800.100", "800.110", "800.120", "800.121", "800.130", "800.140", "800.150", "800.160",
+    "800.2", "800.3", "800.400.1", "800.400.2", "800.5", "800.6", "800.700", "800.800", "800.900",
+    "900.100", "900.200", "900.300", "900.400", "999.",
+];
+
+/// Cancelled or abandoned by the shopper or merchant rather than declined.
+const CANCELLED_CODES: &[&str] = &["100.396.101", "100.396.106", "100.396.201"];
+
+/// How we handle `code`, following `charge_status`. Failure codes outside
+/// Peach's documented rejection groups are `Unhandled` so they can be found
+/// in the webhook metrics before they show up as support tickets.
+pub fn result_code_category(code: &str) -> ResultCodeCategory {
+    match charge_status(code) {
+        ChargeStatus::Succeeded => ResultCodeCategory::Success,
+        ChargeStatus::Pending | ChargeStatus::AwaitingAuthentication => ResultCodeCategory::Pending,
+        ChargeStatus::Failed if CANCELLED_CODES.contains(&code) => ResultCodeCategory::Cancelled,
+        ChargeStatus::Failed if DECLINED_CODE_PREFIXES.iter().any(|prefix| code.starts_with(prefix)) => {
+            ResultCodeCategory::Declined
+        }
+        ChargeStatus::Failed => ResultCodeCategory::Unhandled,
+    }
+}
+
+/// Forwards the current correlation ID to Peach so its request logs can be
+/// matched with ours.
+fn correlation_headers() -> reqwest::header::HeaderMap {
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(value) = current_request_id().and_then(|id| reqwest::header::HeaderValue::from_str(&id).ok()) {
+        headers.insert(REQUEST_ID_HEADER, value);
+    }
+    headers
+}
+
+/// Maps a Peach JSON response (checkout status, recurring charge or refund).
+fn transaction_from_json(body: Value) -> GatewayTransaction {
+    let text = |value: Option<&Value>| value.and_then(|v| v.as_str()).map(|s| s.to_string());
+    let code = text(body.get("result").and_then(|r| r.get("code"))).unwrap_or_default();
+    let authentication_url = text(body.get("redirect").and_then(|r| r.get("url")));
+
+    GatewayTransaction {
+        status: transaction_status(&code, authentication_url.as_deref()),
+        description: text(body.get("result").and_then(|r| r.get("description"))),
+        gateway_reference: text(body.get("id")),
+        merchant_transaction_id: text(body.get("merchantTransactionId")),
+        payment_brand: text(body.get("paymentBrand")),
+        registration_id: text(body.get("registrationId")),
+        card_last4: text(body.get("card").and_then(|c| c.get("last4Digits"))),
+        card_expiry: CardExpiry::parse(
+            &text(body.get("card").and_then(|c| c.get("expiryMonth"))).unwrap_or_default(),
+            &text(body.get("card").and_then(|c| c.get("expiryYear"))).unwrap_or_default(),
+        ),
+        authentication_url,
+        authorized_only: text(body.get("paymentType")).as_deref() == Some("PA"),
+        code,
+        raw: body,
+    }
+}
+
+/// Maps the debits and pre-authorisations in a transaction query response;
+/// refunds, reversals and captures refer back to them and are left out.
+fn settlement_records(body: &Value) -> Vec<SettlementRecord> {
+    let text = |value: Option<&Value>| value.and_then(|v| v.as_str()).map(|s| s.to_string());
+    let records = body.get("records").and_then(|r| r.as_array()).cloned().unwrap_or_default();
+
+    records
+        .iter()
+        .filter_map(|record| {
+            let payment_type = text(record.get("paymentType"))?;
+            if payment_type != "DB" && payment_type != "PA" {
+                return None;
+            }
+            let code = text(record.get("result").and_then(|r| r.get("code"))).unwrap_or_default();
+            let status = match charge_status(&code) {
+                ChargeStatus::Succeeded if payment_type == "PA" => PaymentStatus::Authorized,
+                status => status.payment_status(),
+            };
+            Some(SettlementRecord {
+                merchant_transaction_id: text(record.get("merchantTransactionId"))?,
+                gateway_reference: text(record.get("id")),
+                amount: text(record.get("amount"))?.parse().ok()?,
+                currency: text(record.get("currency")),
+                status,
+            })
+        })
+        .collect()
+}
+
+/// Peach Checkout method to force. ScanToPay skips the method picker so the
+/// checkout link can be rendered as a QR code; everything else shows it.
+fn checkout_payment_method(method: &PaymentMethod) -> Option<&'static str> {
+    match method {
+        PaymentMethod::ScanToPay => Some("SCANTOPAY"),
+        _ => None,
+    }
+}
+
+fn checkout_session(response: Value) -> GatewayResult<CheckoutSession> {
+    let checkout_id = response
+        .get("checkoutId")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| format!("Peach Payments response missing 'checkoutId': {}", response))?;
+
+    Ok(CheckoutSession {
+        checkout_id: checkout_id.to_string(),
+        redirect_url: response.get("redirectUrl").and_then(|v| v.as_str()).map(|s| s.to_string()),
+        registration_id: response.get("registrationId").and_then(|v| v.as_str()).map(|s| s.to_string()),
+    })
+}
+
+/// Peach signs webhooks over every form field except `signature`, sorted by
+/// key and concatenated as key+value with no separators.
+pub fn create_signature_payload(form_data: &HashMap<String, String>) -> String {
+    let mut params: Vec<(&String, &String)> = form_data
+        .iter()
+        .filter(|(key, _)| *key != "signature")
+        .collect();
+    params.sort_by(|a, b| a.0.cmp(b.0));
+
+    params
+        .into_iter()
+        .map(|(key, value)| format!("{}{}", key, value))
+        .collect::<Vec<_>>()
+        .join("")
+}
+
+/// Reads PEACH_WEBHOOK_DECRYPTION_KEY, the 64 hex characters Peach shows
+/// for the webhook in its dashboard.
+pub fn parse_webhook_decryption_key(hex_key: &str) -> Result<[u8; 32], String> {
+    hex::decode(hex_key.trim())
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(|| "PEACH_WEBHOOK_DECRYPTION_KEY must be 64 hex characters".to_string())
+}
+
+/// The ciphertext of an encrypted webhook, sent as `{"encryptedBody": "<hex>"}`;
+/// `None` for a form-encoded one.
+fn encrypted_body(body: &[u8]) -> Option<Vec<u8>> {
+    let envelope: Value = serde_json::from_slice(body).ok()?;
+    hex::decode(envelope.get("encryptedBody")?.as_str()?).ok()
+}
+
+/// The webhook's fields, keyed as in a form-encoded webhook. Decrypted
+/// webhooks are JSON, `{"type": ..., "payload": {...}}`; their nested objects
+/// are flattened to the form keys, e.g. `result.code` and
+/// `customParameters[subscription_id]`.
+fn webhook_fields(body: &[u8]) -> Result<HashMap<String, String>, String> {
+    if body.iter().find(|b| !b.is_ascii_whitespace()) != Some(&b'{') {
+        return serde_urlencoded::from_bytes(body).map_err(|e| format!("Invalid form data: {}", e));
+    }
+    let notification: Value = serde_json::from_slice(body).map_err(|e| format!("Invalid JSON webhook: {}", e))?;
+    let mut fields = HashMap::new();
+    flatten_fields("", notification.get("payload").unwrap_or(&notification), &mut fields);
+    Ok(fields)
+}
+
+fn flatten_fields(prefix: &str, value: &Value, fields: &mut HashMap<String, String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let name = match prefix {
+                    "" => key.clone(),
+                    "customParameters" => format!("customParameters[{}]", key),
+                    _ => format!("{}.{}", prefix, key),
+                };
+                flatten_fields(&name, value, fields);
+            }
+        }
+        Value::Null => {}
+        Value::String(s) => {
+            fields.insert(prefix.to_string(), s.clone());
+        }
+        other => {
+            fields.insert(prefix.to_string(), other.to_string());
+        }
+    }
+}
+
+/// When Peach says the event happened. Peach sends `timestamp` as
+/// `2024-05-14 10:22:33+0000`; RFC 3339 is accepted as well.
+pub fn webhook_event_timestamp(form_map: &HashMap<String, String>) -> Option<DateTime<Utc>> {
+    let raw = form_map.get("timestamp")?;
+    DateTime::parse_from_str(raw, "%Y-%m-%d %H:%M:%S%.f%z")
+        .or_else(|_| DateTime::parse_from_rfc3339(raw))
+        .map(|t| t.with_timezone(&Utc))
+        .ok()
+}
+
+#[async_trait]
+impl PaymentGateway for PeachPaymentService {
+    fn name(&self) -> &'static str {
+        "peach"
+    }
+
+    fn supported_currencies(&self) -> &[String] {
+        &self.supported_currencies
+    }
+
+    fn supports_statement_descriptor(&self) -> bool {
+        true
+    }
+
+    fn supports_authorization(&self) -> bool {
+        true
+    }
+
+    fn supports_transaction_reports(&self) -> bool {
+        true
+    }
+
+    fn is_live(&self) -> bool {
+        self.environment == PeachEnvironment::Live
+    }
+
+    fn checkout_widget(&self) -> Option<CheckoutWidget> {
+        let script_url = self
+            .checkout_script_url
+            .clone()
+            .unwrap_or_else(|| self.environment.checkout_script_url().to_string());
+
+        Some(CheckoutWidget {
+            entity_id: self.v2_entity_id.clone(),
+            script_url,
+            environment: self.environment.as_str().to_string(),
+            payment_brands: self.payment_brands.clone(),
+        })
+    }
+
+    async fn health_check(&self) -> GatewayResult<()> {
+        self.get_oauth_token().await.map(|_| ())
+    }
+
+    async fn initiate_checkout(&self, request: &CheckoutRequest<'_>) -> GatewayResult<CheckoutSession> {
+        let response = self
+            .initiate_checkout_api_v2_with_tokenization(
+                request.user_id,
+                request.subscription_id,
+                request.amount,
+                request.currency,
+                request.merchant_transaction_id,
+                checkout_payment_method(request.payment_method),
+                request.descriptor,
+                if request.authorize_only { "PA" } else { "DB" },
+            )
+            .await?;
+
+        checkout_session(response)
+    }
+
+    async fn initiate_card_registration(&self, request: &CardRegistrationRequest<'_>) -> GatewayResult<CheckoutSession> {
+        let response = self
+            .initiate_registration_checkout(
+                request.user_id,
+                request.subscription_id,
+                request.currency,
+                request.merchant_transaction_id,
+            )
+            .await?;
+        checkout_session(response)
+    }
+
+    async fn check_status(&self, checkout_id: &str) -> GatewayResult<GatewayTransaction> {
+        Ok(transaction_from_json(self.get_checkout_status(checkout_id).await?))
+    }
+
+    async fn charge_token(
+        &self,
+        token: &str,
+        amount: f64,
+        currency: &str,
+        merchant_transaction_id: &str,
+        descriptor: Option<&str>,
+    ) -> GatewayResult<GatewayTransaction> {
+        let response = self.execute_recurring_payment(token, amount, currency, merchant_transaction_id, descriptor).await?;
+        Ok(transaction_from_json(response))
+    }
+
+    async fn refund(
+        &self,
+        gateway_reference: &str,
+        amount: f64,
+        currency: &str,
+        merchant_transaction_id: &str,
+    ) -> GatewayResult<GatewayTransaction> {
+        let response = self.process_refund(gateway_reference, amount, currency, merchant_transaction_id).await?;
+        Ok(transaction_from_json(response))
+    }
+
+    async fn capture(&self, gateway_reference: &str, amount: f64, currency: &str) -> GatewayResult<GatewayTransaction> {
+        let response = self.process_backoffice_operation(gateway_reference, "CP", Some(amount), currency).await?;
+        Ok(transaction_from_json(response))
+    }
+
+    async fn void(&self, gateway_reference: &str, currency: &str) -> GatewayResult<GatewayTransaction> {
+        let response = self.process_backoffice_operation(gateway_reference, "RV", None, currency).await?;
+        Ok(transaction_from_json(response))
+    }
+
+    async fn transaction_report(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> GatewayResult<Vec<SettlementRecord>> {
+        Ok(settlement_records(&self.query_transactions(from, to).await?))
+    }
+
+    /// Form webhooks carry an HMAC `signature` field. Encrypted ones are
+    /// authenticated by AES-GCM instead, so their IV and tag stand in for it.
+    fn webhook_signature(&self, headers: &HeaderMap, body: &[u8]) -> Option<String> {
+        if encrypted_body(body).is_some() {
+            let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim).filter(|v| !v.is_empty());
+            return Some(format!("{}:{}", header(IV_HEADER)?, header(AUTH_TAG_HEADER)?));
+        }
+        let form_map: HashMap<String, String> = serde_urlencoded::from_bytes(body).ok()?;
+        form_map.get("signature").filter(|s| !s.is_empty()).cloned()
+    }
+
+    fn validate_webhook(&self, body: &[u8], signature: &str) -> bool {
+        if let Some(ciphertext) = encrypted_body(body) {
+            return self.decrypt_webhook_body(&ciphertext, signature).is_ok();
+        }
+        match serde_urlencoded::from_bytes::<HashMap<String, String>>(body) {
+            Ok(form_map) => self.validate_webhook_signature(create_signature_payload(&form_map).as_bytes(), signature),
+            Err(_) => false,
+        }
+    }
+
+    fn decrypt_webhook(&self, body: &[u8], signature: &str) -> Option<Result<Vec<u8>, String>> {
+        let ciphertext = encrypted_body(body)?;
+        Some(self.decrypt_webhook_body(&ciphertext, signature))
+    }
+
+    fn parse_webhook(&self, body: &[u8]) -> Result<WebhookNotification, String> {
+        let form_map = webhook_fields(body)?;
+        let field = |key: &str| form_map.get(key).cloned();
+        let code = field("result.code").unwrap_or_default();
+        let authentication_url = field("redirect.url");
+
+        let kind = match form_map.get("paymentType").map(|t| t.as_str()) {
+            Some("RF") => WebhookKind::Refund,
+            Some("CB") => WebhookKind::Chargeback,
+            // Captures and voids are started from the admin API, which
+            // applies their result from Peach's synchronous response
+            Some("CP") | Some("RV") => WebhookKind::Other,
+            _ => WebhookKind::Payment,
+        };
+        // Chargebacks point at the disputed payment through `referencedId`
+        let gateway_reference = match kind {
+            WebhookKind::Chargeback => field("referencedId").or_else(|| field("id")),
+            _ => field("id"),
+        };
+
+        Ok(WebhookNotification {
+            kind,
+            transaction: GatewayTransaction {
+                status: transaction_status(&code, authentication_url.as_deref()),
+                description: field("result.description"),
+                gateway_reference,
+                merchant_transaction_id: field("merchantTransactionId"),
+                payment_brand: field("paymentBrand"),
+                registration_id: field("registrationId"),
+                card_last4: field("card.last4Digits"),
+                card_expiry: CardExpiry::parse(
+                    &field("card.expiryMonth").unwrap_or_default(),
+                    &field("card.expiryYear").unwrap_or_default(),
+                ),
+                authentication_url,
+                authorized_only: field("paymentType").as_deref() == Some("PA"),
+                code,
+                raw: serde_json::to_value(&form_map).unwrap_or_default(),
+            },
+            subscription_id: field("customParameters[subscription_id]")
+                .or_else(|| field("customParameters%5Bsubscription_id%5D")),
+            occurred_at: webhook_event_timestamp(&form_map),
+            signature: field("signature"),
+            fields: serde_json::to_value(&form_map).unwrap_or_default(),
+        })
+    }
 }
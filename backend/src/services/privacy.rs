@@ -0,0 +1,79 @@
+use std::collections::BTreeMap;
+use chrono::Utc;
+use crate::models::user::{User, UserDataExport};
+use crate::models::subscription::SubscriptionStatus;
+use crate::services::database::DatabaseService;
+
+/// A table with a `user_id` column, included in data exports. `omit` lists
+/// credentials and one-time links, which aren't data about the user.
+struct PersonalDataTable {
+    name: &'static str,
+    omit: &'static [&'static str],
+}
+
+const PERSONAL_DATA_TABLES: &[PersonalDataTable] = &[
+    PersonalDataTable { name: "subscriptions", omit: &[] },
+    PersonalDataTable { name: "payments", omit: &["recurring_token", "checkout_url", "authentication_url"] },
+    PersonalDataTable { name: "recurring_payments", omit: &["recurring_token"] },
+    PersonalDataTable { name: "card_updates", omit: &[] },
+    PersonalDataTable { name: "invoices", omit: &[] },
+    PersonalDataTable { name: "credit_notes", omit: &[] },
+    PersonalDataTable { name: "plan_changes", omit: &[] },
+    PersonalDataTable { name: "installments", omit: &[] },
+    PersonalDataTable { name: "renewal_skips", omit: &[] },
+    PersonalDataTable { name: "coupon_redemptions", omit: &[] },
+    PersonalDataTable { name: "usage_records", omit: &[] },
+    PersonalDataTable { name: "wallet_balances", omit: &[] },
+    PersonalDataTable { name: "wallet_entries", omit: &[] },
+    PersonalDataTable { name: "subscription_members", omit: &[] },
+    PersonalDataTable { name: "notification", omit: &[] },
+    PersonalDataTable { name: "notification_preferences", omit: &[] },
+    PersonalDataTable { name: "activity_events", omit: &[] },
+    PersonalDataTable { name: "support_notes", omit: &[] },
+    PersonalDataTable { name: "tickets", omit: &[] },
+    PersonalDataTable { name: "webhook_endpoints", omit: &["secret"] },
+];
+
+/// Subscription states that still bill or grant access; the account can't be
+/// deleted until they are cancelled or have expired.
+const OPEN_SUBSCRIPTION_STATUSES: &[SubscriptionStatus] = &[
+    SubscriptionStatus::Pending,
+    SubscriptionStatus::Active,
+    SubscriptionStatus::Grace,
+    SubscriptionStatus::Suspended,
+    SubscriptionStatus::Downgraded,
+    SubscriptionStatus::Paused,
+];
+
+/// Why a user can't be deleted.
+#[derive(Debug)]
+pub enum DeleteUserError {
+    /// Subscriptions still open, by id.
+    OpenSubscriptions(Vec<String>),
+    Failed(String),
+}
+
+pub async fn export_user_data(db: &DatabaseService, user: User) -> Result<UserDataExport, String> {
+    let mut records = BTreeMap::new();
+    for table in PERSONAL_DATA_TABLES {
+        let rows = db.get_user_rows(table.name, table.omit, &user.id).await?;
+        records.insert(table.name.to_string(), rows);
+    }
+
+    Ok(UserDataExport { exported_at: Utc::now(), user, records })
+}
+
+/// Deletes the account once nothing is left to bill, keeping the
+/// accounting records under the anonymized user.
+pub async fn delete_user(db: &DatabaseService, user_id: &str) -> Result<User, DeleteUserError> {
+    let open: Vec<String> = db.get_subscriptions_by_user(user_id).await
+        .into_iter()
+        .filter(|s| OPEN_SUBSCRIPTION_STATUSES.contains(&s.status))
+        .map(|s| s.id)
+        .collect();
+    if !open.is_empty() {
+        return Err(DeleteUserError::OpenSubscriptions(open));
+    }
+
+    db.anonymize_user(user_id).await.map_err(DeleteUserError::Failed)
+}
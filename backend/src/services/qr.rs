@@ -0,0 +1,69 @@
+use std::io::Cursor;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use image::{ImageFormat, Luma};
+use qrcode::render::svg;
+use qrcode::QrCode;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat {
+    #[default]
+    Svg,
+    Png,
+}
+
+impl QrFormat {
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            QrFormat::Svg => "image/svg+xml",
+            QrFormat::Png => "image/png",
+        }
+    }
+}
+
+/// A rendered QR code, base64-encoded so it can be embedded in JSON.
+#[derive(Debug, Clone, Serialize)]
+pub struct QrImage {
+    pub format: QrFormat,
+    pub mime_type: &'static str,
+    pub data: String,
+    /// `data:` URI the PWA can use directly as an `<img src>`.
+    pub data_uri: String,
+}
+
+/// Smallest module size, in pixels, for PNG output; phones struggle below this.
+const PNG_MODULE_PIXELS: u32 = 8;
+
+pub fn render_qr(content: &str, format: QrFormat) -> Result<QrImage, String> {
+    let code = QrCode::new(content.as_bytes()).map_err(|e| format!("Failed to encode QR code: {}", e))?;
+
+    let bytes = match format {
+        QrFormat::Svg => code
+            .render::<svg::Color>()
+            .min_dimensions(256, 256)
+            .build()
+            .into_bytes(),
+        QrFormat::Png => {
+            let image = code
+                .render::<Luma<u8>>()
+                .module_dimensions(PNG_MODULE_PIXELS, PNG_MODULE_PIXELS)
+                .build();
+            let mut png = Cursor::new(Vec::new());
+            image
+                .write_to(&mut png, ImageFormat::Png)
+                .map_err(|e| format!("Failed to encode QR PNG: {}", e))?;
+            png.into_inner()
+        }
+    };
+
+    let data = BASE64.encode(bytes);
+    Ok(QrImage {
+        format,
+        mime_type: format.mime_type(),
+        data_uri: format!("data:{};base64,{}", format.mime_type(), data),
+        data,
+    })
+}
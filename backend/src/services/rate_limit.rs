@@ -0,0 +1,91 @@
+use chrono::{DateTime, Duration, Utc};
+use crate::config::AppConfig;
+use crate::services::database::DatabaseService;
+
+/// Endpoints sharing a request budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitScope {
+    /// Starting checkouts, which call the gateway and create payments.
+    PaymentInitiation,
+    /// Polling payment status, which may query the gateway or hold a request open.
+    PaymentStatus,
+}
+
+impl RateLimitScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RateLimitScope::PaymentInitiation => "payment_initiation",
+            RateLimitScope::PaymentStatus => "payment_status",
+        }
+    }
+}
+
+/// At most `max_requests` per fixed window of `window_secs`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub max_requests: u32,
+    pub window_secs: i64,
+}
+
+impl RateLimit {
+    /// Parses `<requests>/<seconds>`, e.g. `10/60`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (requests, seconds) = value
+            .split_once('/')
+            .ok_or_else(|| format!("expected <requests>/<seconds>, got '{}'", value))?;
+        let max_requests = requests.trim().parse().map_err(|_| format!("invalid request count '{}'", requests))?;
+        let window_secs = seconds.trim().parse().map_err(|_| format!("invalid window '{}'", seconds))?;
+        Ok(Self { max_requests, window_secs })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateDecision {
+    Allowed,
+    /// Over the limit until the current window ends.
+    Limited { retry_after_secs: i64 },
+}
+
+/// Per-user fixed-window limits. Counters live in the database so every
+/// instance shares them and they survive restarts.
+pub struct RateLimiter {
+    db: DatabaseService,
+    payment_initiation: RateLimit,
+    payment_status: RateLimit,
+}
+
+impl RateLimiter {
+    pub fn new(db: DatabaseService, config: &AppConfig) -> Self {
+        Self {
+            db,
+            payment_initiation: config.payment_initiation_rate_limit,
+            payment_status: config.payment_status_rate_limit,
+        }
+    }
+
+    fn limit(&self, scope: RateLimitScope) -> RateLimit {
+        match scope {
+            RateLimitScope::PaymentInitiation => self.payment_initiation,
+            RateLimitScope::PaymentStatus => self.payment_status,
+        }
+    }
+
+    /// Counts a request by `user_id` against `scope`.
+    pub async fn check(&self, scope: RateLimitScope, user_id: &str, now: DateTime<Utc>) -> Result<RateDecision, String> {
+        let limit = self.limit(scope);
+        let window_start = now.timestamp() - now.timestamp().rem_euclid(limit.window_secs);
+        let window_end = DateTime::<Utc>::from_timestamp(window_start + limit.window_secs, 0)
+            .unwrap_or(now + Duration::seconds(limit.window_secs));
+
+        let key = format!("{}:{}:{}", scope.as_str(), user_id, window_start);
+        let count = self.db.hit_rate_limit(&key, window_end).await?;
+
+        if count > limit.max_requests {
+            Ok(RateDecision::Limited {
+                retry_after_secs: (window_end - now).num_seconds().max(1),
+            })
+        } else {
+            Ok(RateDecision::Allowed)
+        }
+    }
+}
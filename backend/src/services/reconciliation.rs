@@ -0,0 +1,261 @@
+//! Reconciliation: the gateway's record of our payments, from its
+//! transaction reports or an uploaded settlement file, compared with the
+//! payments table by merchant transaction id.
+
+use std::collections::{HashMap, HashSet};
+use chrono::{DateTime, Utc};
+use tracing::{info, warn};
+use uuid::Uuid;
+use crate::models::card_update::CARD_UPDATE_PREFIX;
+use crate::models::payment::{Payment, PaymentStatus};
+use crate::models::reconciliation::{
+    MismatchKind, ReconciliationMismatch, ReconciliationReport, ReconciliationSource, SettlementRecord,
+};
+use crate::services::database::DatabaseService;
+use crate::services::peach::charge_status;
+use crate::services::tenant;
+
+/// Reads a settlement CSV with a header line. Columns are matched ignoring
+/// case, spaces and punctuation: `merchantTransactionId` and `amount` are
+/// required, plus `status` or a Peach `result.code`; `currency`, `id` and
+/// `paymentType` are optional. Rows of other payment types than `DB` and
+/// `PA` (refunds, reversals) are skipped.
+pub fn parse_settlement_csv(text: &str) -> Result<Vec<SettlementRecord>, String> {
+    let mut lines = text.trim_start_matches('\u{feff}').lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let (_, header) = lines.next().ok_or("The file is empty")?;
+    let columns: HashMap<String, usize> = csv_fields(header)
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (name.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_ascii_lowercase(), i))
+        .collect();
+    let column = |names: &[&str]| names.iter().find_map(|name| columns.get(*name).copied());
+
+    let merchant_id_column = column(&["merchanttransactionid"]).ok_or("No merchantTransactionId column")?;
+    let amount_column = column(&["amount"]).ok_or("No amount column")?;
+    let code_column = column(&["resultcode", "code"]);
+    let status_column = column(&["status"]);
+    if code_column.is_none() && status_column.is_none() {
+        return Err("No status or result code column".to_string());
+    }
+    let currency_column = column(&["currency"]);
+    let reference_column = column(&["id", "uniqueid", "gatewayreference"]);
+    let type_column = column(&["paymenttype"]);
+
+    let mut records = Vec::new();
+    for (index, line) in lines {
+        let line_number = index + 1;
+        let fields = csv_fields(line);
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|i| fields.get(i))
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        if field(type_column).is_some_and(|t| t != "DB" && t != "PA") {
+            continue;
+        }
+        let merchant_transaction_id = field(Some(merchant_id_column))
+            .ok_or_else(|| format!("Line {}: no merchantTransactionId", line_number))?;
+        let amount = field(Some(amount_column))
+            .and_then(|a| a.parse::<f64>().ok())
+            .ok_or_else(|| format!("Line {}: invalid amount", line_number))?;
+        let status = match (field(code_column), field(status_column)) {
+            (Some(code), _) => match charge_status(&code).payment_status() {
+                PaymentStatus::Completed if field(type_column).as_deref() == Some("PA") => PaymentStatus::Authorized,
+                status => status,
+            },
+            (None, Some(status)) => parse_status(&status)
+                .ok_or_else(|| format!("Line {}: unknown status '{}'", line_number, status))?,
+            (None, None) => return Err(format!("Line {}: no status or result code", line_number)),
+        };
+
+        records.push(SettlementRecord {
+            merchant_transaction_id,
+            gateway_reference: field(reference_column),
+            amount,
+            currency: field(currency_column),
+            status,
+        });
+    }
+    Ok(records)
+}
+
+/// Splits one RFC 4180 line; quoted cells may hold commas and doubled quotes.
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Status words used in settlement exports.
+fn parse_status(value: &str) -> Option<PaymentStatus> {
+    match value.to_ascii_lowercase().as_str() {
+        "completed" | "succeeded" | "success" | "successful" | "approved" | "settled" | "ack" => Some(PaymentStatus::Completed),
+        "authorized" | "authorised" => Some(PaymentStatus::Authorized),
+        "pending" => Some(PaymentStatus::Pending),
+        "failed" | "declined" | "rejected" | "nok" | "cancelled" | "canceled" => Some(PaymentStatus::Failed),
+        _ => None,
+    }
+}
+
+/// Whether our status is one the gateway's can lead to: a completed debit
+/// may since have been refunded, and an authorisation captured.
+fn statuses_agree(local: &PaymentStatus, gateway: &PaymentStatus) -> bool {
+    match gateway {
+        PaymentStatus::Completed => matches!(local, PaymentStatus::Completed | PaymentStatus::PartiallyRefunded | PaymentStatus::Refunded),
+        PaymentStatus::Authorized => matches!(
+            local,
+            PaymentStatus::Authorized | PaymentStatus::Completed | PaymentStatus::PartiallyRefunded | PaymentStatus::Refunded
+        ),
+        PaymentStatus::Pending => matches!(local, PaymentStatus::Pending | PaymentStatus::AwaitingAuthentication),
+        PaymentStatus::Failed => matches!(local, PaymentStatus::Failed | PaymentStatus::Cancelled),
+        other => local == other,
+    }
+}
+
+/// One record per merchant transaction id. A checkout retried after a
+/// decline is reported once per attempt; the one furthest along counts.
+/// Card registrations have no payment row and are left out.
+fn latest_attempts(records: Vec<SettlementRecord>) -> Vec<SettlementRecord> {
+    let mut latest: Vec<SettlementRecord> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for record in records {
+        if record.merchant_transaction_id.starts_with(CARD_UPDATE_PREFIX) {
+            continue;
+        }
+        match positions.get(&record.merchant_transaction_id) {
+            Some(&i) if record.status.stage() > latest[i].status.stage() => latest[i] = record,
+            Some(_) => {}
+            None => {
+                positions.insert(record.merchant_transaction_id.clone(), latest.len());
+                latest.push(record);
+            }
+        }
+    }
+    latest
+}
+
+/// Compares the gateway's records with our `payments` for them, and flags
+/// the `expected` payments it doesn't list. Returns how many records agreed
+/// and the mismatches.
+fn compare(records: &[SettlementRecord], payments: &[Payment], expected: &[Payment]) -> (u32, Vec<ReconciliationMismatch>) {
+    let by_id: HashMap<&str, &Payment> = payments.iter().map(|p| (p.merchant_transaction_id.as_str(), p)).collect();
+    let mut matched = 0;
+    let mut mismatches = Vec::new();
+
+    for record in records {
+        let mismatch = |kind: MismatchKind, payment: Option<&Payment>| ReconciliationMismatch {
+            merchant_transaction_id: record.merchant_transaction_id.clone(),
+            kind,
+            local_amount: payment.map(|p| p.card_amount()),
+            gateway_amount: Some(record.amount),
+            currency: record.currency.clone().or_else(|| payment.map(|p| p.currency.clone())),
+            local_status: payment.map(|p| p.status.clone()),
+            gateway_status: Some(record.status.clone()),
+        };
+        let Some(payment) = by_id.get(record.merchant_transaction_id.as_str()).copied() else {
+            mismatches.push(mismatch(MismatchKind::MissingLocally, None));
+            continue;
+        };
+
+        let amount_differs = (payment.card_amount() - record.amount).abs() >= 0.005;
+        let status_differs = !statuses_agree(&payment.status, &record.status);
+        if amount_differs {
+            mismatches.push(mismatch(MismatchKind::AmountDiffers, Some(payment)));
+        }
+        if status_differs {
+            mismatches.push(mismatch(MismatchKind::StatusDiffers, Some(payment)));
+        }
+        if !amount_differs && !status_differs {
+            matched += 1;
+        }
+    }
+
+    let reported: HashSet<&str> = records.iter().map(|r| r.merchant_transaction_id.as_str()).collect();
+    mismatches.extend(
+        expected
+            .iter()
+            .filter(|p| !reported.contains(p.merchant_transaction_id.as_str()))
+            .map(|p| ReconciliationMismatch {
+                merchant_transaction_id: p.merchant_transaction_id.clone(),
+                kind: MismatchKind::MissingAtGateway,
+                local_amount: Some(p.card_amount()),
+                gateway_amount: None,
+                currency: Some(p.currency.clone()),
+                local_status: Some(p.status.clone()),
+                gateway_status: None,
+            }),
+    );
+    (matched, mismatches)
+}
+
+/// Tenants charged through their own Peach account rather than the current
+/// one: every tenant with credentials, when reconciling the deployment's own.
+async fn other_accounts(db: &DatabaseService) -> Result<Vec<String>, String> {
+    if tenant::current_id().is_some() {
+        return Ok(Vec::new());
+    }
+    Ok(db.list_tenants().await?
+        .into_iter()
+        .filter(|t| t.peach.is_some())
+        .map(|t| t.id)
+        .collect())
+}
+
+/// Reconciles `records` from `gateway` for the current tenant's account and
+/// stores the report. With a `window`, payments we took in it that the
+/// records leave out are flagged too.
+pub async fn reconcile(
+    db: &DatabaseService,
+    gateway: &str,
+    source: ReconciliationSource,
+    records: Vec<SettlementRecord>,
+    window: Option<(DateTime<Utc>, DateTime<Utc>)>,
+) -> Result<ReconciliationReport, String> {
+    let records = latest_attempts(records);
+    let merchant_ids = records.iter().map(|r| r.merchant_transaction_id.clone()).collect();
+    let payments = db.get_payments_by_merchant_ids(merchant_ids).await?;
+    let expected = match window {
+        Some((from, to)) => db.get_gateway_payments_between(gateway, from, to, other_accounts(db).await?).await?,
+        None => Vec::new(),
+    };
+
+    let (matched, mismatches) = compare(&records, &payments, &expected);
+    let report = ReconciliationReport {
+        id: Uuid::new_v4().simple().to_string(),
+        source,
+        tenant_id: tenant::current_id(),
+        window_start: window.map(|(from, _)| from),
+        window_end: window.map(|(_, to)| to),
+        records: records.len() as u32,
+        matched,
+        mismatches,
+        created_at: Utc::now(),
+    };
+    db.record_reconciliation_report(&report).await?;
+
+    if report.mismatches.is_empty() {
+        info!("Reconciliation {}: all {} gateway records match", report.id, report.records);
+    } else {
+        warn!(
+            "Reconciliation {}: {} mismatch(es) across {} gateway records",
+            report.id, report.mismatches.len(), report.records
+        );
+    }
+    Ok(report)
+}
@@ -0,0 +1,166 @@
+use std::env;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Timeouts, status-check retries and circuit breaker limits for one
+/// provider's HTTP calls, read from `<PREFIX>_*` settings.
+#[derive(Debug, Clone, Copy)]
+pub struct HttpSettings {
+    /// Time allowed to open a connection.
+    pub connect_timeout: Duration,
+    /// Time allowed for a whole request, response body included.
+    pub request_timeout: Duration,
+    /// Extra attempts for idempotent status checks that time out or get a 5xx.
+    pub status_retries: u32,
+    /// Consecutive failures that open the circuit.
+    pub breaker_failure_threshold: u32,
+    /// How long an open circuit rejects calls before letting one through.
+    pub breaker_open_for: Duration,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(30),
+            status_retries: 2,
+            breaker_failure_threshold: 5,
+            breaker_open_for: Duration::from_secs(30),
+        }
+    }
+}
+
+impl HttpSettings {
+    /// Reads `<prefix>_CONNECT_TIMEOUT_SECS`, `<prefix>_REQUEST_TIMEOUT_SECS`,
+    /// `<prefix>_STATUS_RETRIES`, `<prefix>_BREAKER_FAILURES` and
+    /// `<prefix>_BREAKER_OPEN_SECS`, keeping the default for any unset.
+    pub fn from_env(prefix: &str) -> Result<Self, String> {
+        let defaults = Self::default();
+        let number = |suffix: &str, default: u64| -> Result<u64, String> {
+            let key = format!("{}_{}", prefix, suffix);
+            match env::var(&key) {
+                Ok(value) if !value.trim().is_empty() => value
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("{} must be a whole number, got '{}'", key, value)),
+                _ => Ok(default),
+            }
+        };
+        let settings = Self {
+            connect_timeout: Duration::from_secs(number("CONNECT_TIMEOUT_SECS", defaults.connect_timeout.as_secs())?),
+            request_timeout: Duration::from_secs(number("REQUEST_TIMEOUT_SECS", defaults.request_timeout.as_secs())?),
+            status_retries: number("STATUS_RETRIES", defaults.status_retries.into())? as u32,
+            breaker_failure_threshold: number("BREAKER_FAILURES", defaults.breaker_failure_threshold.into())? as u32,
+            breaker_open_for: Duration::from_secs(number("BREAKER_OPEN_SECS", defaults.breaker_open_for.as_secs())?),
+        };
+        if settings.connect_timeout.is_zero() || settings.request_timeout.is_zero() {
+            return Err(format!("{}_CONNECT_TIMEOUT_SECS and {}_REQUEST_TIMEOUT_SECS must be at least 1", prefix, prefix));
+        }
+        if settings.breaker_failure_threshold == 0 {
+            return Err(format!("{}_BREAKER_FAILURES must be at least 1", prefix));
+        }
+        Ok(settings)
+    }
+
+    pub fn client(&self) -> reqwest::Client {
+        reqwest::Client::builder()
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.request_timeout)
+            .build()
+            .unwrap_or_else(|e| {
+                warn!("Falling back to an HTTP client without timeouts: {}", e);
+                reqwest::Client::new()
+            })
+    }
+
+    /// Wait before retry `attempt` (1-based): 250ms, 500ms, 1s, ... capped at 4s.
+    pub fn retry_delay(attempt: u32) -> Duration {
+        Duration::from_millis(250 * 2u64.pow(attempt.saturating_sub(1).min(4)))
+    }
+}
+
+/// Returned instead of calling a provider whose circuit is open. Handlers
+/// downcast to it to answer 503 with `Retry-After`, or with stored data.
+#[derive(Debug, Clone)]
+pub struct GatewayUnavailable {
+    pub gateway: &'static str,
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for GatewayUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is unavailable after repeated failures; retrying in {}s",
+            self.gateway,
+            self.retry_after.as_secs().max(1)
+        )
+    }
+}
+
+impl std::error::Error for GatewayUnavailable {}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// Stops calling a provider after `failure_threshold` consecutive failures,
+/// so requests fail fast instead of each waiting out a timeout. Once
+/// `open_for` has passed calls go through again; one success closes the
+/// circuit, one failure opens it for another `open_for`.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    gateway: &'static str,
+    failure_threshold: u32,
+    open_for: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(gateway: &'static str, settings: &HttpSettings) -> Self {
+        Self {
+            gateway,
+            failure_threshold: settings.breaker_failure_threshold,
+            open_for: settings.breaker_open_for,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// `Err` while the circuit is open.
+    pub fn check(&self) -> Result<(), GatewayUnavailable> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match state.open_until {
+            Some(until) if Instant::now() < until => Err(GatewayUnavailable {
+                gateway: self.gateway,
+                retry_after: until - Instant::now(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.open_until.take().is_some() {
+            info!("{} circuit closed; calls are going through again", self.gateway);
+        }
+        state.consecutive_failures = 0;
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= self.failure_threshold {
+            if state.open_until.is_none() {
+                warn!(
+                    "{} circuit opened after {} consecutive failures",
+                    self.gateway, state.consecutive_failures
+                );
+            }
+            state.open_until = Some(Instant::now() + self.open_for);
+        }
+    }
+}
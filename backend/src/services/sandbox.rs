@@ -0,0 +1,176 @@
+use actix_web::http::header::HeaderMap;
+use async_trait::async_trait;
+use std::sync::Arc;
+use crate::services::gateway::{
+    CardRegistrationRequest, ChargeStatus, CheckoutRequest, CheckoutSession, GatewayResult,
+    CheckoutWidget, GatewayTransaction, PaymentGateway, WebhookNotification,
+};
+
+/// Prefix of the mock card tokens understood by `SandboxGateway`.
+pub const SANDBOX_TOKEN_PREFIX: &str = "sandbox_";
+
+/// How a mock token's charges end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SandboxCard {
+    Approve,
+    /// Insufficient funds: retried under the dunning schedule.
+    SoftDecline,
+    /// Expired card: the token is retired.
+    HardDecline,
+}
+
+impl SandboxCard {
+    pub fn token(&self, id: &str) -> String {
+        let kind = match self {
+            SandboxCard::Approve => "ok",
+            SandboxCard::SoftDecline => "soft",
+            SandboxCard::HardDecline => "hard",
+        };
+        format!("{}{}_{}", SANDBOX_TOKEN_PREFIX, kind, id)
+    }
+
+    fn from_token(token: &str) -> Option<Self> {
+        let rest = token.strip_prefix(SANDBOX_TOKEN_PREFIX)?;
+        if rest.starts_with("ok_") {
+            Some(SandboxCard::Approve)
+        } else if rest.starts_with("soft_") {
+            Some(SandboxCard::SoftDecline)
+        } else if rest.starts_with("hard_") {
+            Some(SandboxCard::HardDecline)
+        } else {
+            None
+        }
+    }
+
+    /// Peach result code returned for a charge, so dunning classifies it as
+    /// it would a real one.
+    fn result(&self) -> (ChargeStatus, &'static str, &'static str) {
+        match self {
+            SandboxCard::Approve => (ChargeStatus::Succeeded, "000.100.110", "Request successfully processed"),
+            SandboxCard::SoftDecline => (ChargeStatus::Failed, "800.100.155", "Amount exceeds available funds"),
+            SandboxCard::HardDecline => (ChargeStatus::Failed, "100.100.303", "Card expired"),
+        }
+    }
+}
+
+/// Wraps the configured gateway when `SANDBOX_MODE` is on. Charges against
+/// mock tokens (see `SandboxCard`) are answered locally without a network
+/// call; everything else goes to the real gateway. Installed for the whole
+/// app, so the renewal task retries synthetic subscriptions the same way.
+pub struct SandboxGateway {
+    inner: Arc<dyn PaymentGateway>,
+}
+
+impl SandboxGateway {
+    pub fn new(inner: Arc<dyn PaymentGateway>) -> Self {
+        Self { inner }
+    }
+}
+
+#[async_trait]
+impl PaymentGateway for SandboxGateway {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn supported_currencies(&self) -> &[String] {
+        self.inner.supported_currencies()
+    }
+
+    fn supports_statement_descriptor(&self) -> bool {
+        self.inner.supports_statement_descriptor()
+    }
+
+    fn supports_authorization(&self) -> bool {
+        self.inner.supports_authorization()
+    }
+
+    fn is_live(&self) -> bool {
+        self.inner.is_live()
+    }
+
+    fn checkout_widget(&self) -> Option<CheckoutWidget> {
+        self.inner.checkout_widget()
+    }
+
+    async fn health_check(&self) -> GatewayResult<()> {
+        self.inner.health_check().await
+    }
+
+    async fn initiate_checkout(&self, request: &CheckoutRequest<'_>) -> GatewayResult<CheckoutSession> {
+        self.inner.initiate_checkout(request).await
+    }
+
+    async fn initiate_card_registration(&self, request: &CardRegistrationRequest<'_>) -> GatewayResult<CheckoutSession> {
+        self.inner.initiate_card_registration(request).await
+    }
+
+    async fn check_status(&self, checkout_id: &str) -> GatewayResult<GatewayTransaction> {
+        self.inner.check_status(checkout_id).await
+    }
+
+    async fn charge_token(
+        &self,
+        token: &str,
+        amount: f64,
+        currency: &str,
+        merchant_transaction_id: &str,
+        descriptor: Option<&str>,
+    ) -> GatewayResult<GatewayTransaction> {
+        let card = match SandboxCard::from_token(token) {
+            Some(card) => card,
+            None => return self.inner.charge_token(token, amount, currency, merchant_transaction_id, descriptor).await,
+        };
+
+        let (status, code, description) = card.result();
+        Ok(GatewayTransaction {
+            status,
+            code: code.to_string(),
+            description: Some(description.to_string()),
+            gateway_reference: Some(format!("sandbox-{}", uuid::Uuid::new_v4().simple())),
+            merchant_transaction_id: Some(merchant_transaction_id.to_string()),
+            payment_brand: Some("VISA".to_string()),
+            registration_id: Some(token.to_string()),
+            card_last4: Some("4242".to_string()),
+            card_expiry: None,
+            authentication_url: None,
+            authorized_only: false,
+            raw: serde_json::json!({
+                "sandbox": true,
+                "amount": amount,
+                "currency": currency,
+                "result": { "code": code, "description": description },
+            }),
+        })
+    }
+
+    async fn refund(
+        &self,
+        gateway_reference: &str,
+        amount: f64,
+        currency: &str,
+        merchant_transaction_id: &str,
+    ) -> GatewayResult<GatewayTransaction> {
+        self.inner.refund(gateway_reference, amount, currency, merchant_transaction_id).await
+    }
+
+    async fn capture(&self, gateway_reference: &str, amount: f64, currency: &str) -> GatewayResult<GatewayTransaction> {
+        self.inner.capture(gateway_reference, amount, currency).await
+    }
+
+    async fn void(&self, gateway_reference: &str, currency: &str) -> GatewayResult<GatewayTransaction> {
+        self.inner.void(gateway_reference, currency).await
+    }
+
+    fn webhook_signature(&self, headers: &HeaderMap, body: &[u8]) -> Option<String> {
+        self.inner.webhook_signature(headers, body)
+    }
+
+    fn validate_webhook(&self, body: &[u8], signature: &str) -> bool {
+        self.inner.validate_webhook(body, signature)
+    }
+
+    fn parse_webhook(&self, body: &[u8]) -> Result<WebhookNotification, String> {
+        self.inner.parse_webhook(body)
+    }
+}
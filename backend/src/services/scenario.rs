@@ -0,0 +1,349 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Instant;
+use chrono::{DateTime, Duration, Utc};
+use tokio::sync::Mutex;
+use tokio::time::{sleep, Duration as TokioDuration};
+use tracing::{error, info};
+use uuid::Uuid;
+use crate::config::AppConfig;
+use crate::handlers::payment::{process_webhook, webhook_event_update};
+use crate::models::payment::{CreatePaymentDto, PaymentMethod, PaymentStatus, DEFAULT_CURRENCY};
+use crate::models::scenario::{ScenarioAction, ScenarioDto, ScenarioReport, ScenarioStep, ScenarioStepResult, SimulatedResult};
+use crate::models::subscription::{CreateSubscriptionDto, SubscriptionStatus};
+use crate::models::user::CreateUserDto;
+use crate::models::webhook_event::{WebhookEventUpdate, WebhookOutcome};
+use crate::services::database::DatabaseService;
+use crate::services::email::EmailService;
+use crate::services::gateway::{ChargeStatus, GatewayTransaction, WebhookKind, WebhookNotification};
+
+const SCENARIO_TAG: &str = "scenario";
+/// Gateway name on scenario payments and their stored webhook events.
+const SCENARIO_GATEWAY: &str = "scenario";
+const SCENARIO_BILLING_PERIOD_DAYS: u32 = 30;
+const DEFAULT_SCENARIO_AMOUNT: f64 = 99.0;
+
+impl SimulatedResult {
+    /// Peach result code for the simulated outcome, as the sandbox cards use.
+    fn transaction_result(&self) -> (ChargeStatus, &'static str, &'static str) {
+        match self {
+            SimulatedResult::Success => (ChargeStatus::Succeeded, "000.100.110", "Request successfully processed"),
+            SimulatedResult::Pending => (ChargeStatus::Pending, "000.200.000", "Transaction pending"),
+            SimulatedResult::Failure => (ChargeStatus::Failed, "800.100.155", "Amount exceeds available funds"),
+        }
+    }
+}
+
+/// A payment initiated by the scenario.
+struct ScenarioPayment {
+    merchant_transaction_id: String,
+    /// Opened by the first refund webhook step.
+    refund_transaction_id: Option<String>,
+}
+
+#[derive(Default)]
+struct StepOutput {
+    merchant_transaction_id: Option<String>,
+    outcome: Option<WebhookOutcome>,
+    detail: Option<String>,
+}
+
+struct ScenarioRun {
+    db: DatabaseService,
+    email: Arc<EmailService>,
+    config: Arc<AppConfig>,
+    run_id: String,
+    started_at: DateTime<Utc>,
+    start: Instant,
+    payments: Mutex<HashMap<String, ScenarioPayment>>,
+}
+
+/// Runs a validated scenario: steps are grouped by `at_ms`, each group waits
+/// for its time and then runs its steps concurrently. Webhooks are built in
+/// the gateway-neutral shape and go through `process_webhook`, as signed
+/// deliveries do; they're stored with gateway `scenario`. The records the
+/// run creates are left in place, tagged with the run id.
+pub async fn run_scenario(
+    db: DatabaseService,
+    email: Arc<EmailService>,
+    config: Arc<AppConfig>,
+    dto: ScenarioDto,
+) -> ScenarioReport {
+    let run = Arc::new(ScenarioRun {
+        db,
+        email,
+        config,
+        run_id: Uuid::new_v4().simple().to_string()[..12].to_string(),
+        started_at: Utc::now(),
+        start: Instant::now(),
+        payments: Mutex::new(HashMap::new()),
+    });
+    info!("Starting scenario {} ({:?}, {} steps)", run.run_id, dto.name, dto.steps.len());
+
+    let step_count = dto.steps.len();
+    let mut groups: BTreeMap<u64, Vec<(usize, ScenarioStep)>> = BTreeMap::new();
+    for (index, step) in dto.steps.into_iter().enumerate() {
+        groups.entry(step.at_ms).or_default().push((index, step));
+    }
+
+    let mut results = Vec::with_capacity(step_count);
+    for (at_ms, steps) in groups {
+        let elapsed = run.start.elapsed().as_millis() as u64;
+        if at_ms > elapsed {
+            sleep(TokioDuration::from_millis(at_ms - elapsed)).await;
+        }
+
+        // Spawned on the request's worker thread, like the handler itself
+        let running: Vec<_> = steps
+            .into_iter()
+            .map(|(index, step)| {
+                let run = run.clone();
+                actix_rt::spawn(async move { run.run_step(index, step).await })
+            })
+            .collect();
+        for handle in running {
+            match handle.await {
+                Ok(result) => results.push(result),
+                Err(e) => error!("Scenario {} step panicked: {}", run.run_id, e),
+            }
+        }
+    }
+    results.sort_by_key(|result| result.index);
+
+    let passed = results.len() == step_count && results.iter().all(|result| result.passed);
+    info!("Scenario {} finished: {}", run.run_id, if passed { "passed" } else { "failed" });
+    ScenarioReport {
+        run_id: run.run_id.clone(),
+        name: dto.name,
+        started_at: run.started_at,
+        steps: results,
+        passed,
+    }
+}
+
+impl ScenarioRun {
+    async fn run_step(&self, index: usize, step: ScenarioStep) -> ScenarioStepResult {
+        let outcome = match &step.action {
+            ScenarioAction::InitiatePayment { label, amount } => {
+                self.initiate_payment(index, label, amount.unwrap_or(DEFAULT_SCENARIO_AMOUNT)).await
+            }
+            ScenarioAction::PaymentWebhook { payment, result, occurred_offset_ms } => {
+                self.payment_webhook(payment, *result, *occurred_offset_ms).await
+            }
+            ScenarioAction::RefundWebhook { payment, result, amount } => {
+                self.refund_webhook(payment, *result, *amount).await
+            }
+            ScenarioAction::ChargebackWebhook { payment } => self.chargeback_webhook(payment).await,
+            ScenarioAction::Expect { payment, payment_status, subscription_status } => {
+                self.expect(payment, payment_status.as_ref(), subscription_status.as_ref()).await
+            }
+        };
+
+        let (passed, output) = match outcome {
+            Ok(output) => (true, output),
+            Err(e) => {
+                info!("Scenario {} step {} ({}) failed: {}", self.run_id, index, step.action.name(), e);
+                let merchant_transaction_id = self.merchant_transaction_id(step.action.label()).await.ok();
+                (false, StepOutput { merchant_transaction_id, detail: Some(e), ..Default::default() })
+            }
+        };
+        ScenarioStepResult {
+            index,
+            action: step.action.name().to_string(),
+            at_ms: step.at_ms,
+            finished_ms: self.start.elapsed().as_millis() as u64,
+            merchant_transaction_id: output.merchant_transaction_id,
+            outcome: output.outcome,
+            passed,
+            detail: output.detail,
+        }
+    }
+
+    async fn merchant_transaction_id(&self, label: &str) -> Result<String, String> {
+        self.payments.lock().await
+            .get(label)
+            .map(|payment| payment.merchant_transaction_id.clone())
+            .ok_or_else(|| format!("Payment {} hasn't been initiated", label))
+    }
+
+    async fn initiate_payment(&self, index: usize, label: &str, amount: f64) -> Result<StepOutput, String> {
+        let user = self.db.create_user(CreateUserDto {
+            email: format!("scenario+{}-{}@example.invalid", self.run_id, index),
+            name: format!("Scenario {} {}", self.run_id, label),
+        }).await?;
+
+        let subscription = self.db.create_subscription(CreateSubscriptionDto {
+            user_id: user.id.clone(),
+            plan_id: None,
+            plan_name: "Scenario".to_string(),
+            price: amount,
+            currency: DEFAULT_CURRENCY.to_string(),
+            payment_method: Some(PaymentMethod::Card),
+            grace_period_days: self.config.grace_period_days,
+            billing_period_days: SCENARIO_BILLING_PERIOD_DAYS,
+            billing_period_months: None,
+            suspension_policy: self.config.suspension_policy,
+            usage_pricing: None,
+            seat_count: 1,
+        }).await?;
+        self.db
+            .set_subscription_tags(&subscription.id, vec![SCENARIO_TAG.to_string(), format!("{}-{}", SCENARIO_TAG, self.run_id)])
+            .await?;
+
+        let payment = self.db.create_payment(CreatePaymentDto {
+            user_id: user.id,
+            subscription_id: subscription.id,
+            amount,
+            currency: None,
+            payment_method: Some(PaymentMethod::Card),
+            region: None,
+            installments: None,
+        }, self.config.vat_rate_percent, SCENARIO_GATEWAY).await?;
+
+        self.payments.lock().await.insert(label.to_string(), ScenarioPayment {
+            merchant_transaction_id: payment.merchant_transaction_id.clone(),
+            refund_transaction_id: None,
+        });
+        Ok(StepOutput {
+            merchant_transaction_id: Some(payment.merchant_transaction_id),
+            ..Default::default()
+        })
+    }
+
+    async fn payment_webhook(&self, label: &str, result: SimulatedResult, occurred_offset_ms: Option<i64>) -> Result<StepOutput, String> {
+        let merchant_transaction_id = self.merchant_transaction_id(label).await?;
+        let occurred_at = occurred_offset_ms.map(|ms| self.started_at + Duration::milliseconds(ms));
+        let transaction = simulated_transaction(result, &merchant_transaction_id, &merchant_transaction_id, "DB");
+        self.deliver(WebhookKind::Payment, transaction, occurred_at, merchant_transaction_id).await
+    }
+
+    async fn refund_webhook(&self, label: &str, result: SimulatedResult, amount: Option<f64>) -> Result<StepOutput, String> {
+        // Held while the refund is opened, so concurrent steps share one
+        let mut payments = self.payments.lock().await;
+        let scenario_payment = payments.get_mut(label).ok_or_else(|| format!("Payment {} hasn't been initiated", label))?;
+        let merchant_transaction_id = scenario_payment.merchant_transaction_id.clone();
+        let refund_transaction_id = match &scenario_payment.refund_transaction_id {
+            Some(id) => id.clone(),
+            None => {
+                let payment = self.db.get_payment_by_merchant_id(&merchant_transaction_id).await
+                    .ok_or_else(|| format!("No payment found for merchantTransactionId: {}", merchant_transaction_id))?;
+                let amount = amount.unwrap_or(payment.amount);
+                let refund = self.db.create_refund(&payment, amount, Some(format!("Scenario {}", self.run_id))).await?;
+                scenario_payment.refund_transaction_id = Some(refund.refund_transaction_id.clone());
+                refund.refund_transaction_id
+            }
+        };
+        drop(payments);
+
+        let transaction = simulated_transaction(result, &refund_transaction_id, &merchant_transaction_id, "RF");
+        self.deliver(WebhookKind::Refund, transaction, None, merchant_transaction_id).await
+    }
+
+    async fn chargeback_webhook(&self, label: &str) -> Result<StepOutput, String> {
+        let merchant_transaction_id = self.merchant_transaction_id(label).await?;
+        let transaction = simulated_transaction(SimulatedResult::Success, &merchant_transaction_id, &merchant_transaction_id, "CB");
+        self.deliver(WebhookKind::Chargeback, transaction, None, merchant_transaction_id).await
+    }
+
+    /// Stores and processes a simulated webhook. Processing errors are
+    /// reported on the step, which still passes; `expect` steps check the
+    /// effect.
+    async fn deliver(
+        &self,
+        kind: WebhookKind,
+        transaction: GatewayTransaction,
+        occurred_at: Option<DateTime<Utc>>,
+        merchant_transaction_id: String,
+    ) -> Result<StepOutput, String> {
+        let notification = WebhookNotification {
+            kind,
+            fields: transaction.raw.clone(),
+            transaction,
+            subscription_id: None,
+            occurred_at,
+            signature: None,
+        };
+        let event_id = self.db.create_webhook_event(&notification.fields.to_string(), SCENARIO_GATEWAY).await?;
+
+        let (outcome, error) = match process_webhook(&self.db, &self.email, &self.config, &notification, Utc::now()).await {
+            Ok(outcome) => (outcome, None),
+            Err(e) => (WebhookOutcome::Failed, Some(e)),
+        };
+        let update = WebhookEventUpdate {
+            error: error.clone(),
+            ..webhook_event_update(&notification)
+        };
+        if let Err(e) = self.db.update_webhook_event(&event_id, outcome.clone(), update).await {
+            error!("Failed to record scenario webhook outcome for {}: {}", event_id, e);
+        }
+
+        Ok(StepOutput {
+            merchant_transaction_id: Some(merchant_transaction_id),
+            outcome: Some(outcome),
+            detail: error,
+        })
+    }
+
+    async fn expect(
+        &self,
+        label: &str,
+        payment_status: Option<&PaymentStatus>,
+        subscription_status: Option<&SubscriptionStatus>,
+    ) -> Result<StepOutput, String> {
+        let merchant_transaction_id = self.merchant_transaction_id(label).await?;
+        let payment = self.db.get_payment_by_merchant_id(&merchant_transaction_id).await
+            .ok_or_else(|| format!("No payment found for merchantTransactionId: {}", merchant_transaction_id))?;
+
+        let mut mismatches = Vec::new();
+        if let Some(expected) = payment_status {
+            if &payment.status != expected {
+                mismatches.push(format!("payment is {:?}, expected {:?}", payment.status, expected));
+            }
+        }
+        if let Some(expected) = subscription_status {
+            let subscription = match payment.subscription_id.as_deref() {
+                Some(id) => self.db.get_subscription(id).await,
+                None => None,
+            };
+            match subscription {
+                Some(subscription) if &subscription.status == expected => {}
+                Some(subscription) => mismatches.push(format!("subscription is {:?}, expected {:?}", subscription.status, expected)),
+                None => mismatches.push("payment has no subscription".to_string()),
+            }
+        }
+
+        if mismatches.is_empty() {
+            Ok(StepOutput {
+                merchant_transaction_id: Some(merchant_transaction_id),
+                ..Default::default()
+            })
+        } else {
+            Err(mismatches.join("; "))
+        }
+    }
+}
+
+/// A gateway transaction for `transaction_id` with the simulated result, as
+/// a gateway webhook would report it.
+fn simulated_transaction(result: SimulatedResult, transaction_id: &str, payment_id: &str, payment_type: &str) -> GatewayTransaction {
+    let (status, code, description) = result.transaction_result();
+    GatewayTransaction {
+        status,
+        code: code.to_string(),
+        description: Some(description.to_string()),
+        gateway_reference: Some(format!("scenario-{}", payment_id)),
+        merchant_transaction_id: Some(transaction_id.to_string()),
+        payment_brand: Some("VISA".to_string()),
+        registration_id: None,
+        card_last4: Some("4242".to_string()),
+        card_expiry: None,
+        authentication_url: None,
+        authorized_only: false,
+        raw: serde_json::json!({
+            "scenario": true,
+            "paymentType": payment_type,
+            "merchantTransactionId": transaction_id,
+            "result": { "code": code, "description": description },
+        }),
+    }
+}
@@ -0,0 +1,835 @@
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use serde::Serialize;
+use surrealdb::{Surreal, engine::remote::http::Client};
+use tracing::{error, info, warn};
+
+/// Bump whenever `SCHEMA` changes, so `schema_meta` shows which version a
+/// database was last brought up to.
+pub const SCHEMA_VERSION: u32 = 44;
+
+/// The schema the models expect, applied in order: every table is defined
+/// before its fields and indexes.
+pub const SCHEMA: &[&str] = &[
+    // Users table
+    "DEFINE TABLE users SCHEMAFULL;",
+    "DEFINE FIELD id ON users TYPE string;",
+    "DEFINE FIELD email ON users TYPE string;",
+    "DEFINE FIELD name ON users TYPE string;",
+    "DEFINE FIELD tags ON users TYPE array<string> DEFAULT [];",
+    "DEFINE FIELD billing_address ON users TYPE option<string>;",
+    "DEFINE FIELD vat_number ON users TYPE option<string>;",
+    "DEFINE FIELD locale ON users TYPE option<string>;",
+    "DEFINE FIELD deleted_at ON users TYPE option<datetime>;",
+    "DEFINE FIELD tenant_id ON users TYPE option<string>;",
+    "DEFINE FIELD created_at ON users TYPE datetime;",
+    "DEFINE FIELD updated_at ON users TYPE datetime;",
+    // Emails are unique per tenant
+    "DEFINE INDEX unique_tenant_email ON users COLUMNS tenant_id, email UNIQUE;",
+    
+    // Payments table
+    "DEFINE TABLE payments SCHEMAFULL;",
+    "DEFINE FIELD id ON payments TYPE string;",
+    "DEFINE FIELD user_id ON payments TYPE string;",
+    "DEFINE FIELD subscription_id ON payments TYPE option<string>;",
+    "DEFINE FIELD amount ON payments TYPE number;",
+    "DEFINE FIELD currency ON payments TYPE string DEFAULT 'ZAR';",
+    "DEFINE FIELD amount_excl_vat ON payments TYPE option<number>;",
+    "DEFINE FIELD vat_amount ON payments TYPE option<number>;",
+    "DEFINE FIELD vat_rate_percent ON payments TYPE option<int>;",
+    "DEFINE FIELD recurring_token ON payments TYPE option<string>;",
+    "DEFINE FIELD status ON payments TYPE string;",
+    "DEFINE FIELD payment_method ON payments TYPE string;",
+    "DEFINE FIELD gateway ON payments TYPE option<string>;",
+    "DEFINE FIELD merchant_transaction_id ON payments TYPE string;",
+    "DEFINE FIELD checkout_id ON payments TYPE option<string>;",
+    "DEFINE FIELD checkout_url ON payments TYPE option<string>;",
+    "DEFINE FIELD peach_payment_id ON payments TYPE option<string>;",
+    "DEFINE FIELD authentication_url ON payments TYPE option<string>;",
+    "DEFINE FIELD last_event_at ON payments TYPE option<datetime>;",
+    "DEFINE FIELD value_date ON payments TYPE option<datetime>;",
+    "DEFINE FIELD external_reference ON payments TYPE option<string>;",
+    "DEFINE FIELD proof_attachment ON payments TYPE option<string>;",
+    "DEFINE FIELD wallet_amount ON payments TYPE number DEFAULT 0;",
+    "DEFINE FIELD statement_descriptor ON payments TYPE option<string>;",
+    "DEFINE FIELD usage ON payments FLEXIBLE TYPE option<object>;",
+    "DEFINE FIELD state_reason ON payments TYPE option<string>;",
+    "DEFINE FIELD tenant_id ON payments TYPE option<string>;",
+    "DEFINE FIELD created_at ON payments TYPE datetime;",
+    "DEFINE FIELD updated_at ON payments TYPE datetime;",
+    "DEFINE INDEX unique_merchant_txn ON payments COLUMNS merchant_transaction_id UNIQUE;",
+    "DEFINE INDEX payments_user_created ON payments COLUMNS user_id, created_at;",
+    "DEFINE INDEX payments_user_status ON payments COLUMNS user_id, status;",
+    "DEFINE INDEX payments_tenant ON payments COLUMNS tenant_id;",
+    
+    // Subscriptions table
+    "DEFINE TABLE subscriptions SCHEMAFULL;",
+    "DEFINE FIELD id ON subscriptions TYPE string;",
+    "DEFINE FIELD user_id ON subscriptions TYPE string;",
+    "DEFINE FIELD plan_name ON subscriptions TYPE string;",
+    "DEFINE FIELD price ON subscriptions TYPE number;",
+    "DEFINE FIELD currency ON subscriptions TYPE string DEFAULT 'ZAR';",
+    "DEFINE FIELD status ON subscriptions TYPE string;",
+    "DEFINE FIELD payment_method ON subscriptions TYPE option<string>;",
+    "DEFINE FIELD payment_brand ON subscriptions TYPE option<string>;",
+    "DEFINE FIELD start_date ON subscriptions TYPE option<datetime>;",
+    "DEFINE FIELD end_date ON subscriptions TYPE option<datetime>;",
+    "DEFINE FIELD grace_period_days ON subscriptions TYPE option<int>;",
+    "DEFINE FIELD billing_period_days ON subscriptions TYPE option<int>;",
+    "DEFINE FIELD billing_period_months ON subscriptions TYPE option<int>;",
+    "DEFINE FIELD billing_anchor_day ON subscriptions TYPE option<int>;",
+    "DEFINE FIELD plan_id ON subscriptions TYPE option<string>;",
+    "DEFINE FIELD tags ON subscriptions TYPE array<string> DEFAULT [];",
+    "DEFINE FIELD grace_end_date ON subscriptions TYPE option<datetime>;",
+    "DEFINE FIELD suspension_policy ON subscriptions FLEXIBLE TYPE option<object>;",
+    "DEFINE FIELD renewal_attempts ON subscriptions TYPE int DEFAULT 0;",
+    "DEFINE FIELD last_renewal_attempt_at ON subscriptions TYPE option<datetime>;",
+    "DEFINE FIELD next_renewal_attempt_at ON subscriptions TYPE option<datetime>;",
+    "DEFINE FIELD last_renewal_error ON subscriptions TYPE option<string>;",
+    "DEFINE FIELD renewal_reminder_sent_for ON subscriptions TYPE option<datetime>;",
+    "DEFINE FIELD last_event_at ON subscriptions TYPE option<datetime>;",
+    "DEFINE FIELD cancel_at_period_end ON subscriptions TYPE bool DEFAULT false;",
+    "DEFINE FIELD cancelled_at ON subscriptions TYPE option<datetime>;",
+    "DEFINE FIELD cancellation_effective_at ON subscriptions TYPE option<datetime>;",
+    "DEFINE FIELD cancellation_reason ON subscriptions TYPE option<string>;",
+    "DEFINE FIELD paused_at ON subscriptions TYPE option<datetime>;",
+    "DEFINE FIELD pause_duration_secs ON subscriptions TYPE int DEFAULT 0;",
+    "DEFINE FIELD usage_pricing ON subscriptions FLEXIBLE TYPE option<object>;",
+    "DEFINE FIELD seat_count ON subscriptions TYPE int DEFAULT 1;",
+    "DEFINE FIELD skip_next_renewal ON subscriptions TYPE bool DEFAULT false;",
+    "DEFINE FIELD state_reason ON subscriptions TYPE option<string>;",
+    "DEFINE FIELD fallback_plan_id ON subscriptions TYPE option<string>;",
+    "DEFINE FIELD at_risk_reason ON subscriptions TYPE option<string>;",
+    "DEFINE FIELD amount_due ON subscriptions TYPE option<number>;",
+    "DEFINE FIELD amount_paid ON subscriptions TYPE number DEFAULT 0;",
+    "DEFINE FIELD part_payments ON subscriptions TYPE array<string> DEFAULT [];",
+    "DEFINE FIELD tenant_id ON subscriptions TYPE option<string>;",
+    "DEFINE FIELD created_at ON subscriptions TYPE datetime;",
+    "DEFINE FIELD updated_at ON subscriptions TYPE datetime;",
+    "DEFINE INDEX subscriptions_tenant ON subscriptions COLUMNS tenant_id;",
+    
+    // Recurring payments table
+    "DEFINE TABLE recurring_payments SCHEMAFULL;",
+    "DEFINE FIELD id ON recurring_payments TYPE string;",
+    "DEFINE FIELD user_id ON recurring_payments TYPE string;",
+    "DEFINE FIELD subscription_id ON recurring_payments TYPE string;",
+    "DEFINE FIELD recurring_token ON recurring_payments TYPE string;",
+    "DEFINE FIELD card_last_four ON recurring_payments TYPE option<string>;",
+    "DEFINE FIELD card_brand ON recurring_payments TYPE option<string>;",
+    "DEFINE FIELD status ON recurring_payments TYPE string;",
+    "DEFINE FIELD migration_id ON recurring_payments TYPE option<string>;",
+    "DEFINE FIELD expiry_month ON recurring_payments TYPE option<int>;",
+    "DEFINE FIELD expiry_year ON recurring_payments TYPE option<int>;",
+    "DEFINE FIELD expiry_notified_at ON recurring_payments TYPE option<datetime>;",
+    "DEFINE FIELD created_at ON recurring_payments TYPE datetime;",
+    "DEFINE FIELD updated_at ON recurring_payments TYPE datetime;",
+    "DEFINE INDEX recurring_payments_migration ON recurring_payments COLUMNS migration_id, status;",
+    
+    // Notifications table
+    "DEFINE TABLE notification SCHEMAFULL;",
+    "DEFINE FIELD id ON notification TYPE string;",
+    "DEFINE FIELD user_id ON notification TYPE string;",
+    "DEFINE FIELD subscription_id ON notification TYPE string;",
+    "DEFINE FIELD message ON notification TYPE string;",
+    "DEFINE FIELD acknowledged ON notification TYPE bool;",
+    "DEFINE FIELD action_type ON notification TYPE option<string>;",
+    "DEFINE FIELD action_payload ON notification FLEXIBLE TYPE option<object>;",
+    "DEFINE FIELD event ON notification TYPE option<string>;",
+    "DEFINE FIELD created_at ON notification TYPE datetime;",
+    "DEFINE INDEX notification_user ON notification COLUMNS user_id, created_at;",
+    "DEFINE INDEX notification_user_unread ON notification COLUMNS user_id, acknowledged;",
+
+    // Activity events table
+    "DEFINE TABLE activity_events SCHEMAFULL;",
+    "DEFINE FIELD user_id ON activity_events TYPE string;",
+    "DEFINE FIELD category ON activity_events TYPE string;",
+    "DEFINE FIELD kind ON activity_events TYPE string;",
+    "DEFINE FIELD description ON activity_events TYPE string;",
+    "DEFINE FIELD reference_id ON activity_events TYPE option<string>;",
+    "DEFINE FIELD created_at ON activity_events TYPE datetime;",
+    "DEFINE INDEX activity_user_created ON activity_events COLUMNS user_id, created_at;",
+
+    // Webhook events table
+    "DEFINE TABLE webhook_events SCHEMAFULL;",
+    "DEFINE FIELD raw_body ON webhook_events TYPE string;",
+    "DEFINE FIELD gateway ON webhook_events TYPE option<string>;",
+    "DEFINE FIELD signature ON webhook_events TYPE option<string>;",
+    "DEFINE FIELD parsed ON webhook_events FLEXIBLE TYPE option<object>;",
+    "DEFINE FIELD result_code ON webhook_events TYPE option<string>;",
+    "DEFINE FIELD merchant_transaction_id ON webhook_events TYPE option<string>;",
+    "DEFINE FIELD outcome ON webhook_events TYPE string;",
+    "DEFINE FIELD error ON webhook_events TYPE option<string>;",
+    "DEFINE FIELD event_timestamp ON webhook_events TYPE option<datetime>;",
+    "DEFINE FIELD replay_count ON webhook_events TYPE int;",
+    "DEFINE FIELD created_at ON webhook_events TYPE datetime;",
+    "DEFINE FIELD updated_at ON webhook_events TYPE datetime;",
+    "DEFINE INDEX webhook_events_created ON webhook_events COLUMNS created_at;",
+    // Last processed webhook per gateway, watched for silence
+    "DEFINE TABLE webhook_heartbeats SCHEMAFULL;",
+    "DEFINE FIELD gateway ON webhook_heartbeats TYPE string;",
+    "DEFINE FIELD last_processed_at ON webhook_heartbeats TYPE datetime;",
+    // Verified Peach webhooks per result code, one record per code
+    "DEFINE TABLE webhook_result_codes SCHEMAFULL;",
+    "DEFINE FIELD code ON webhook_result_codes TYPE string;",
+    "DEFINE FIELD category ON webhook_result_codes TYPE string;",
+    "DEFINE FIELD count ON webhook_result_codes TYPE int;",
+    "DEFINE FIELD first_seen_at ON webhook_result_codes TYPE datetime;",
+    "DEFINE FIELD last_seen_at ON webhook_result_codes TYPE datetime;",
+    "DEFINE INDEX webhook_result_codes_category ON webhook_result_codes COLUMNS category, last_seen_at;",
+
+    // Refunds table
+    "DEFINE TABLE refunds SCHEMAFULL;",
+    "DEFINE FIELD payment_id ON refunds TYPE string;",
+    "DEFINE FIELD payment_merchant_transaction_id ON refunds TYPE string;",
+    "DEFINE FIELD refund_transaction_id ON refunds TYPE string;",
+    "DEFINE FIELD amount ON refunds TYPE number;",
+    "DEFINE FIELD currency ON refunds TYPE string DEFAULT 'ZAR';",
+    "DEFINE FIELD reason ON refunds TYPE option<string>;",
+    "DEFINE FIELD status ON refunds TYPE string;",
+    "DEFINE FIELD peach_refund_id ON refunds TYPE option<string>;",
+    "DEFINE FIELD result_code ON refunds TYPE option<string>;",
+    "DEFINE FIELD created_at ON refunds TYPE datetime;",
+    "DEFINE FIELD updated_at ON refunds TYPE datetime;",
+    "DEFINE INDEX unique_refund_txn ON refunds COLUMNS refund_transaction_id UNIQUE;",
+    "DEFINE INDEX refunds_payment ON refunds COLUMNS payment_merchant_transaction_id;",
+
+    // Card updates table
+    "DEFINE TABLE card_updates SCHEMAFULL;",
+    "DEFINE FIELD user_id ON card_updates TYPE string;",
+    "DEFINE FIELD subscription_id ON card_updates TYPE string;",
+    "DEFINE FIELD merchant_transaction_id ON card_updates TYPE string;",
+    "DEFINE FIELD gateway ON card_updates TYPE string;",
+    "DEFINE FIELD status ON card_updates TYPE string;",
+    "DEFINE FIELD result_code ON card_updates TYPE option<string>;",
+    "DEFINE FIELD created_at ON card_updates TYPE datetime;",
+    "DEFINE FIELD updated_at ON card_updates TYPE datetime;",
+    "DEFINE INDEX unique_card_update_txn ON card_updates COLUMNS merchant_transaction_id UNIQUE;",
+
+    // Attachments; the bytes live in the attachment store
+    "DEFINE TABLE attachments SCHEMAFULL;",
+    "DEFINE FIELD owner_type ON attachments TYPE option<string>;",
+    "DEFINE FIELD owner_id ON attachments TYPE option<string>;",
+    "DEFINE FIELD filename ON attachments TYPE string;",
+    "DEFINE FIELD content_type ON attachments TYPE string;",
+    "DEFINE FIELD size_bytes ON attachments TYPE int;",
+    "DEFINE FIELD sha256 ON attachments TYPE string;",
+    "DEFINE FIELD storage_key ON attachments TYPE string;",
+    "DEFINE FIELD created_at ON attachments TYPE datetime;",
+    "DEFINE INDEX attachments_owner ON attachments COLUMNS owner_type, owner_id;",
+
+    // Plans table
+    "DEFINE TABLE plans SCHEMAFULL;",
+    "DEFINE FIELD name ON plans TYPE string;",
+    "DEFINE FIELD price ON plans TYPE number;",
+    "DEFINE FIELD prices_include_vat ON plans TYPE bool DEFAULT true;",
+    "DEFINE FIELD currency ON plans TYPE string;",
+    "DEFINE FIELD interval ON plans TYPE string;",
+    "DEFINE FIELD interval_days ON plans TYPE option<int>;",
+    "DEFINE FIELD trial_days ON plans TYPE int DEFAULT 0;",
+    "DEFINE FIELD grace_period_days ON plans TYPE option<int>;",
+    "DEFINE FIELD suspension_policy ON plans FLEXIBLE TYPE option<object>;",
+    "DEFINE FIELD statement_descriptor ON plans TYPE option<string>;",
+    "DEFINE FIELD usage_pricing ON plans FLEXIBLE TYPE option<object>;",
+    "DEFINE FIELD max_skips_per_year ON plans TYPE int DEFAULT 0;",
+    "DEFINE FIELD created_at ON plans TYPE datetime;",
+    "DEFINE FIELD updated_at ON plans TYPE datetime;",
+
+    // Plan features; record ids are [plan_id, feature_key]
+    "DEFINE TABLE plan_features SCHEMAFULL;",
+    "DEFINE FIELD plan_id ON plan_features TYPE string;",
+    "DEFINE FIELD feature_key ON plan_features TYPE string;",
+    "DEFINE FIELD limit ON plan_features TYPE option<int>;",
+    "DEFINE FIELD created_at ON plan_features TYPE datetime;",
+    "DEFINE FIELD updated_at ON plan_features TYPE datetime;",
+    "DEFINE INDEX plan_features_plan ON plan_features COLUMNS plan_id;",
+
+    // Support notes table
+    "DEFINE TABLE support_notes SCHEMAFULL;",
+    "DEFINE FIELD target_type ON support_notes TYPE string;",
+    "DEFINE FIELD target_id ON support_notes TYPE string;",
+    "DEFINE FIELD user_id ON support_notes TYPE string;",
+    "DEFINE FIELD body ON support_notes TYPE string;",
+    "DEFINE FIELD author ON support_notes TYPE option<string>;",
+    "DEFINE FIELD created_at ON support_notes TYPE datetime;",
+    "DEFINE FIELD updated_at ON support_notes TYPE datetime;",
+    "DEFINE INDEX support_notes_target ON support_notes COLUMNS target_type, target_id;",
+    "DEFINE INDEX support_notes_user ON support_notes COLUMNS user_id, created_at;",
+
+    // Support tickets opened by billing events or support
+    "DEFINE TABLE tickets SCHEMAFULL;",
+    "DEFINE FIELD user_id ON tickets TYPE string;",
+    "DEFINE FIELD source ON tickets TYPE string;",
+    "DEFINE FIELD status ON tickets TYPE string;",
+    "DEFINE FIELD subject ON tickets TYPE string;",
+    "DEFINE FIELD description ON tickets TYPE option<string>;",
+    "DEFINE FIELD payment_id ON tickets TYPE option<string>;",
+    "DEFINE FIELD subscription_id ON tickets TYPE option<string>;",
+    "DEFINE FIELD assignee ON tickets TYPE option<string>;",
+    "DEFINE FIELD occurrences ON tickets TYPE int DEFAULT 1;",
+    "DEFINE FIELD resolved_at ON tickets TYPE option<datetime>;",
+    "DEFINE FIELD created_at ON tickets TYPE datetime;",
+    "DEFINE FIELD updated_at ON tickets TYPE datetime;",
+    "DEFINE INDEX tickets_user ON tickets COLUMNS user_id, updated_at;",
+    "DEFINE INDEX tickets_status ON tickets COLUMNS status, updated_at;",
+    "DEFINE INDEX tickets_payment ON tickets COLUMNS payment_id;",
+    "DEFINE INDEX tickets_subscription ON tickets COLUMNS subscription_id;",
+    // Queued side-effects of payment events
+    "DEFINE TABLE jobs SCHEMAFULL;",
+    "DEFINE FIELD kind ON jobs TYPE string;",
+    "DEFINE FIELD payload ON jobs FLEXIBLE TYPE object;",
+    "DEFINE FIELD status ON jobs TYPE string;",
+    "DEFINE FIELD reference ON jobs TYPE option<string>;",
+    "DEFINE FIELD attempts ON jobs TYPE int DEFAULT 0;",
+    "DEFINE FIELD max_attempts ON jobs TYPE int;",
+    "DEFINE FIELD run_at ON jobs TYPE datetime;",
+    "DEFINE FIELD last_error ON jobs TYPE option<string>;",
+    "DEFINE FIELD started_at ON jobs TYPE option<datetime>;",
+    "DEFINE FIELD completed_at ON jobs TYPE option<datetime>;",
+    "DEFINE FIELD created_at ON jobs TYPE datetime;",
+    "DEFINE FIELD updated_at ON jobs TYPE datetime;",
+    "DEFINE INDEX jobs_due ON jobs COLUMNS status, run_at;",
+    "DEFINE INDEX jobs_reference ON jobs COLUMNS reference;",
+    // Outbox of domain events, written with the change they describe
+    "DEFINE TABLE domain_events SCHEMAFULL;",
+    "DEFINE FIELD event_type ON domain_events TYPE string;",
+    "DEFINE FIELD aggregate_id ON domain_events TYPE string;",
+    "DEFINE FIELD payload ON domain_events FLEXIBLE TYPE object;",
+    "DEFINE FIELD status ON domain_events TYPE string;",
+    "DEFINE FIELD attempts ON domain_events TYPE int DEFAULT 0;",
+    "DEFINE FIELD next_attempt_at ON domain_events TYPE datetime;",
+    "DEFINE FIELD last_error ON domain_events TYPE option<string>;",
+    "DEFINE FIELD published_at ON domain_events TYPE option<datetime>;",
+    "DEFINE FIELD created_at ON domain_events TYPE datetime;",
+    "DEFINE INDEX domain_events_due ON domain_events COLUMNS status, next_attempt_at;",
+    "DEFINE INDEX domain_events_type ON domain_events COLUMNS event_type;",
+    // Webhook endpoints registered by API consumers, and what was sent to them
+    "DEFINE TABLE webhook_endpoints SCHEMAFULL;",
+    "DEFINE FIELD user_id ON webhook_endpoints TYPE string;",
+    "DEFINE FIELD url ON webhook_endpoints TYPE string;",
+    "DEFINE FIELD secret ON webhook_endpoints TYPE string;",
+    "DEFINE FIELD event_types ON webhook_endpoints TYPE array<string> DEFAULT [];",
+    "DEFINE FIELD created_at ON webhook_endpoints TYPE datetime;",
+    "DEFINE INDEX webhook_endpoints_user ON webhook_endpoints COLUMNS user_id;",
+    "DEFINE TABLE webhook_deliveries SCHEMAFULL;",
+    "DEFINE FIELD endpoint_id ON webhook_deliveries TYPE string;",
+    "DEFINE FIELD event_id ON webhook_deliveries TYPE string;",
+    "DEFINE FIELD event_type ON webhook_deliveries TYPE string;",
+    "DEFINE FIELD status ON webhook_deliveries TYPE string;",
+    "DEFINE FIELD attempts ON webhook_deliveries TYPE int DEFAULT 0;",
+    "DEFINE FIELD next_attempt_at ON webhook_deliveries TYPE datetime;",
+    "DEFINE FIELD response_status ON webhook_deliveries TYPE option<int>;",
+    "DEFINE FIELD last_error ON webhook_deliveries TYPE option<string>;",
+    "DEFINE FIELD delivered_at ON webhook_deliveries TYPE option<datetime>;",
+    "DEFINE FIELD created_at ON webhook_deliveries TYPE datetime;",
+    "DEFINE FIELD updated_at ON webhook_deliveries TYPE datetime;",
+    "DEFINE INDEX webhook_deliveries_due ON webhook_deliveries COLUMNS status, next_attempt_at;",
+    "DEFINE INDEX webhook_deliveries_endpoint ON webhook_deliveries COLUMNS endpoint_id, created_at;",
+    // Where each payment method is offered, keyed by method and region
+    "DEFINE TABLE payment_method_rules SCHEMAFULL;",
+    "DEFINE FIELD payment_method ON payment_method_rules TYPE string;",
+    "DEFINE FIELD region ON payment_method_rules TYPE string;",
+    "DEFINE FIELD enabled ON payment_method_rules TYPE bool DEFAULT true;",
+    "DEFINE FIELD currencies ON payment_method_rules TYPE array<string> DEFAULT [];",
+    "DEFINE FIELD min_amount ON payment_method_rules TYPE option<number>;",
+    "DEFINE FIELD max_amount ON payment_method_rules TYPE option<number>;",
+    "DEFINE FIELD created_at ON payment_method_rules TYPE datetime;",
+    "DEFINE FIELD updated_at ON payment_method_rules TYPE datetime;",
+    // Supplier details for tax invoices, one record per merchant
+    "DEFINE TABLE merchant_details SCHEMAFULL;",
+    "DEFINE FIELD legal_name ON merchant_details TYPE string;",
+    "DEFINE FIELD vat_number ON merchant_details TYPE option<string>;",
+    "DEFINE FIELD registration_number ON merchant_details TYPE option<string>;",
+    "DEFINE FIELD address ON merchant_details TYPE option<string>;",
+    "DEFINE FIELD updated_at ON merchant_details TYPE option<datetime>;",
+    // Nightly data-lake exports; completed runs set the next run's window
+    "DEFINE TABLE export_runs SCHEMAFULL;",
+    "DEFINE FIELD status ON export_runs TYPE string;",
+    "DEFINE FIELD window_start ON export_runs TYPE option<datetime>;",
+    "DEFINE FIELD window_end ON export_runs TYPE datetime;",
+    "DEFINE FIELD manifest_key ON export_runs TYPE option<string>;",
+    "DEFINE FIELD files ON export_runs FLEXIBLE TYPE array<object> DEFAULT [];",
+    "DEFINE FIELD error ON export_runs TYPE option<string>;",
+    "DEFINE FIELD started_at ON export_runs TYPE datetime;",
+    "DEFINE FIELD completed_at ON export_runs TYPE datetime;",
+    "DEFINE INDEX export_runs_window ON export_runs COLUMNS status, window_end;",
+
+    // Invoices table; numbers come from the counters table
+    "DEFINE TABLE invoices SCHEMAFULL;",
+    "DEFINE FIELD invoice_number ON invoices TYPE string;",
+    "DEFINE FIELD user_id ON invoices TYPE string;",
+    "DEFINE FIELD subscription_id ON invoices TYPE option<string>;",
+    "DEFINE FIELD payment_reference ON invoices TYPE string;",
+    "DEFINE FIELD seller_name ON invoices TYPE string;",
+    "DEFINE FIELD seller_vat_number ON invoices TYPE option<string>;",
+    "DEFINE FIELD seller_registration_number ON invoices TYPE option<string>;",
+    "DEFINE FIELD seller_address ON invoices TYPE option<string>;",
+    "DEFINE FIELD customer_name ON invoices TYPE string;",
+    "DEFINE FIELD customer_email ON invoices TYPE string;",
+    "DEFINE FIELD customer_address ON invoices TYPE option<string>;",
+    "DEFINE FIELD customer_vat_number ON invoices TYPE option<string>;",
+    "DEFINE FIELD line_items ON invoices FLEXIBLE TYPE array<object>;",
+    "DEFINE FIELD currency ON invoices TYPE string;",
+    "DEFINE FIELD subtotal ON invoices TYPE number;",
+    "DEFINE FIELD vat_rate_percent ON invoices TYPE int;",
+    "DEFINE FIELD vat_amount ON invoices TYPE number;",
+    "DEFINE FIELD total ON invoices TYPE number;",
+    "DEFINE FIELD issued_at ON invoices TYPE datetime;",
+    "DEFINE INDEX unique_invoice_number ON invoices COLUMNS invoice_number UNIQUE;",
+    "DEFINE INDEX unique_invoice_payment ON invoices COLUMNS payment_reference UNIQUE;",
+
+    // Credit notes, numbered from the counters table like invoices
+    "DEFINE TABLE credit_notes SCHEMAFULL;",
+    "DEFINE FIELD credit_note_number ON credit_notes TYPE string;",
+    "DEFINE FIELD user_id ON credit_notes TYPE string;",
+    "DEFINE FIELD subscription_id ON credit_notes TYPE option<string>;",
+    "DEFINE FIELD reason ON credit_notes TYPE string;",
+    "DEFINE FIELD currency ON credit_notes TYPE string;",
+    "DEFINE FIELD subtotal ON credit_notes TYPE number;",
+    "DEFINE FIELD vat_rate_percent ON credit_notes TYPE int;",
+    "DEFINE FIELD vat_amount ON credit_notes TYPE number;",
+    "DEFINE FIELD total ON credit_notes TYPE number;",
+    "DEFINE FIELD issued_at ON credit_notes TYPE datetime;",
+    "DEFINE INDEX unique_credit_note_number ON credit_notes COLUMNS credit_note_number UNIQUE;",
+
+    // Plan changes table
+    "DEFINE TABLE plan_changes SCHEMAFULL;",
+    "DEFINE FIELD user_id ON plan_changes TYPE string;",
+    "DEFINE FIELD subscription_id ON plan_changes TYPE string;",
+    "DEFINE FIELD from_plan_id ON plan_changes TYPE option<string>;",
+    "DEFINE FIELD from_plan_name ON plan_changes TYPE string;",
+    "DEFINE FIELD to_plan_id ON plan_changes TYPE string;",
+    "DEFINE FIELD to_plan_name ON plan_changes TYPE string;",
+    "DEFINE FIELD to_price ON plan_changes TYPE number;",
+    "DEFINE FIELD proration ON plan_changes FLEXIBLE TYPE object;",
+    "DEFINE FIELD merchant_transaction_id ON plan_changes TYPE option<string>;",
+    "DEFINE FIELD credit_note_id ON plan_changes TYPE option<string>;",
+    "DEFINE FIELD status ON plan_changes TYPE string;",
+    "DEFINE FIELD failure_reason ON plan_changes TYPE option<string>;",
+    "DEFINE FIELD effective_at ON plan_changes TYPE option<datetime>;",
+    "DEFINE FIELD created_at ON plan_changes TYPE datetime;",
+    "DEFINE FIELD completed_at ON plan_changes TYPE option<datetime>;",
+    "DEFINE INDEX plan_changes_subscription ON plan_changes COLUMNS subscription_id, created_at;",
+    "DEFINE INDEX plan_changes_txn ON plan_changes COLUMNS merchant_transaction_id;",
+    "DEFINE TABLE counters SCHEMALESS;",
+    // Account credit: one balance per user and currency, and its ledger
+    "DEFINE TABLE wallet_balances SCHEMAFULL;",
+    "DEFINE FIELD user_id ON wallet_balances TYPE string;",
+    "DEFINE FIELD currency ON wallet_balances TYPE string;",
+    "DEFINE FIELD balance ON wallet_balances TYPE number DEFAULT 0;",
+    "DEFINE FIELD updated_at ON wallet_balances TYPE datetime;",
+    "DEFINE INDEX wallet_balances_user ON wallet_balances COLUMNS user_id;",
+    "DEFINE TABLE wallet_entries SCHEMAFULL;",
+    "DEFINE FIELD user_id ON wallet_entries TYPE string;",
+    "DEFINE FIELD currency ON wallet_entries TYPE string;",
+    "DEFINE FIELD amount ON wallet_entries TYPE number;",
+    "DEFINE FIELD balance_after ON wallet_entries TYPE number;",
+    "DEFINE FIELD source ON wallet_entries TYPE string;",
+    "DEFINE FIELD reference ON wallet_entries TYPE string;",
+    "DEFINE FIELD created_at ON wallet_entries TYPE datetime;",
+    "DEFINE INDEX wallet_entries_user ON wallet_entries COLUMNS user_id, created_at;",
+    // Per-user request counters, one record per scope, user and window
+    "DEFINE TABLE rate_limits SCHEMAFULL;",
+    "DEFINE FIELD count ON rate_limits TYPE int DEFAULT 0;",
+    "DEFINE FIELD expires_at ON rate_limits TYPE datetime;",
+    "DEFINE INDEX rate_limits_expiry ON rate_limits COLUMNS expires_at;",
+    // Verified request signatures, kept until their timestamp leaves the window
+    "DEFINE TABLE request_signatures SCHEMAFULL;",
+    "DEFINE FIELD expires_at ON request_signatures TYPE datetime;",
+    "DEFINE INDEX request_signatures_expiry ON request_signatures COLUMNS expires_at;",
+    // Moves of stored card tokens to a new gateway entity
+    "DEFINE TABLE token_migrations SCHEMAFULL;",
+    "DEFINE FIELD target ON token_migrations TYPE string;",
+    "DEFINE FIELD reason ON token_migrations TYPE option<string>;",
+    "DEFINE FIELD created_at ON token_migrations TYPE datetime;",
+    // Metered usage reported against subscriptions
+    "DEFINE TABLE usage_records SCHEMAFULL;",
+    "DEFINE FIELD subscription_id ON usage_records TYPE string;",
+    "DEFINE FIELD user_id ON usage_records TYPE string;",
+    "DEFINE FIELD quantity ON usage_records TYPE int;",
+    "DEFINE FIELD recorded_at ON usage_records TYPE datetime;",
+    "DEFINE FIELD created_at ON usage_records TYPE datetime;",
+    "DEFINE INDEX usage_records_subscription ON usage_records COLUMNS subscription_id, recorded_at;",
+    // Team members sharing a subscription's seats
+    "DEFINE TABLE subscription_members SCHEMAFULL;",
+    "DEFINE FIELD subscription_id ON subscription_members TYPE string;",
+    "DEFINE FIELD email ON subscription_members TYPE string;",
+    "DEFINE FIELD user_id ON subscription_members TYPE option<string>;",
+    "DEFINE FIELD invited_by ON subscription_members TYPE string;",
+    "DEFINE FIELD created_at ON subscription_members TYPE datetime;",
+    "DEFINE INDEX subscription_members_email ON subscription_members COLUMNS subscription_id, email UNIQUE;",
+    // Billing periods users skipped instead of paying for
+    "DEFINE TABLE renewal_skips SCHEMAFULL;",
+    "DEFINE FIELD subscription_id ON renewal_skips TYPE string;",
+    "DEFINE FIELD user_id ON renewal_skips TYPE string;",
+    "DEFINE FIELD period_start ON renewal_skips TYPE datetime;",
+    "DEFINE FIELD period_end ON renewal_skips TYPE datetime;",
+    "DEFINE FIELD created_at ON renewal_skips TYPE datetime;",
+    "DEFINE INDEX renewal_skips_subscription ON renewal_skips COLUMNS subscription_id, created_at;",
+    // Admin changes to many subscriptions at once, previewed before they run
+    "DEFINE TABLE bulk_operations SCHEMAFULL;",
+    "DEFINE FIELD action ON bulk_operations FLEXIBLE TYPE object;",
+    "DEFINE FIELD filter ON bulk_operations FLEXIBLE TYPE object;",
+    "DEFINE FIELD status ON bulk_operations TYPE string;",
+    "DEFINE FIELD subscription_ids ON bulk_operations TYPE array<string>;",
+    "DEFINE FIELD matched ON bulk_operations TYPE int;",
+    "DEFINE FIELD processed ON bulk_operations TYPE int DEFAULT 0;",
+    "DEFINE FIELD succeeded ON bulk_operations TYPE int DEFAULT 0;",
+    "DEFINE FIELD skipped ON bulk_operations TYPE int DEFAULT 0;",
+    "DEFINE FIELD failures ON bulk_operations FLEXIBLE TYPE array<object> DEFAULT [];",
+    "DEFINE FIELD created_at ON bulk_operations TYPE datetime;",
+    "DEFINE FIELD expires_at ON bulk_operations TYPE datetime;",
+    "DEFINE FIELD confirmed_at ON bulk_operations TYPE option<datetime>;",
+    "DEFINE FIELD completed_at ON bulk_operations TYPE option<datetime>;",
+    // Channels each user wants per kind of notification, keyed by [user_id, event]
+    "DEFINE TABLE notification_preferences SCHEMAFULL;",
+    "DEFINE FIELD user_id ON notification_preferences TYPE string;",
+    "DEFINE FIELD event ON notification_preferences TYPE string;",
+    "DEFINE FIELD email ON notification_preferences TYPE bool;",
+    "DEFINE FIELD push ON notification_preferences TYPE bool;",
+    "DEFINE FIELD in_app ON notification_preferences TYPE bool;",
+    "DEFINE FIELD sms ON notification_preferences TYPE bool;",
+    "DEFINE FIELD updated_at ON notification_preferences TYPE datetime;",
+    "DEFINE INDEX notification_preferences_user ON notification_preferences COLUMNS user_id;",
+    // Merchant copy for emails and in-app notifications, keyed by [channel, key, locale]
+    "DEFINE TABLE message_templates SCHEMAFULL;",
+    "DEFINE FIELD channel ON message_templates TYPE string;",
+    "DEFINE FIELD message_key ON message_templates TYPE string;",
+    "DEFINE FIELD locale ON message_templates TYPE string;",
+    "DEFINE FIELD body ON message_templates TYPE string;",
+    "DEFINE FIELD updated_at ON message_templates TYPE datetime;",
+    // Who changed a user, subscription or payment, with the record before and after
+    "DEFINE TABLE audit_log SCHEMAFULL;",
+    "DEFINE FIELD actor_type ON audit_log TYPE string;",
+    "DEFINE FIELD actor_id ON audit_log TYPE option<string>;",
+    "DEFINE FIELD action ON audit_log TYPE string;",
+    "DEFINE FIELD entity_type ON audit_log TYPE string;",
+    "DEFINE FIELD entity_id ON audit_log TYPE string;",
+    "DEFINE FIELD old_value ON audit_log FLEXIBLE TYPE option<object>;",
+    "DEFINE FIELD new_value ON audit_log FLEXIBLE TYPE option<object>;",
+    "DEFINE FIELD ip_address ON audit_log TYPE option<string>;",
+    "DEFINE FIELD user_agent ON audit_log TYPE option<string>;",
+    "DEFINE FIELD request_id ON audit_log TYPE option<string>;",
+    "DEFINE FIELD impersonation_session_id ON audit_log TYPE option<string>;",
+    "DEFINE FIELD created_at ON audit_log TYPE datetime;",
+    "DEFINE INDEX audit_log_entity ON audit_log COLUMNS entity_type, entity_id, created_at;",
+    "DEFINE INDEX audit_log_created ON audit_log COLUMNS created_at;",
+    "DEFINE INDEX audit_log_impersonation ON audit_log COLUMNS impersonation_session_id;",
+    // Server-to-server credentials; secrets are stored hashed
+    "DEFINE TABLE api_keys SCHEMAFULL;",
+    "DEFINE FIELD name ON api_keys TYPE string;",
+    "DEFINE FIELD prefix ON api_keys TYPE string;",
+    "DEFINE FIELD scopes ON api_keys TYPE array<string>;",
+    "DEFINE FIELD key_hash ON api_keys TYPE string;",
+    "DEFINE FIELD previous_key_hash ON api_keys TYPE option<string>;",
+    "DEFINE FIELD previous_expires_at ON api_keys TYPE option<datetime>;",
+    "DEFINE FIELD last_used_at ON api_keys TYPE option<datetime>;",
+    "DEFINE FIELD rotated_at ON api_keys TYPE option<datetime>;",
+    "DEFINE FIELD revoked_at ON api_keys TYPE option<datetime>;",
+    "DEFINE FIELD tenant_id ON api_keys TYPE option<string>;",
+    "DEFINE FIELD created_at ON api_keys TYPE datetime;",
+    "DEFINE INDEX api_keys_hash ON api_keys COLUMNS key_hash UNIQUE;",
+    "DEFINE INDEX api_keys_previous_hash ON api_keys COLUMNS previous_key_hash;",
+    // Support staff acting as a user; the token is stored hashed
+    "DEFINE TABLE impersonation_sessions SCHEMAFULL;",
+    "DEFINE FIELD user_id ON impersonation_sessions TYPE string;",
+    "DEFINE FIELD reason ON impersonation_sessions TYPE string;",
+    "DEFINE FIELD scope ON impersonation_sessions TYPE string;",
+    "DEFINE FIELD token_hash ON impersonation_sessions TYPE string;",
+    "DEFINE FIELD expires_at ON impersonation_sessions TYPE datetime;",
+    "DEFINE FIELD revoked_at ON impersonation_sessions TYPE option<datetime>;",
+    "DEFINE FIELD created_at ON impersonation_sessions TYPE datetime;",
+    "DEFINE INDEX impersonation_sessions_token ON impersonation_sessions COLUMNS token_hash UNIQUE;",
+    "DEFINE INDEX impersonation_sessions_user ON impersonation_sessions COLUMNS user_id;",
+    // Merchants served by this deployment; Peach secrets are stored as given
+    "DEFINE TABLE tenants SCHEMAFULL;",
+    "DEFINE FIELD name ON tenants TYPE string;",
+    "DEFINE FIELD subdomain ON tenants TYPE string;",
+    "DEFINE FIELD peach ON tenants FLEXIBLE TYPE option<object>;",
+    "DEFINE FIELD disabled_at ON tenants TYPE option<datetime>;",
+    "DEFINE FIELD created_at ON tenants TYPE datetime;",
+    "DEFINE FIELD updated_at ON tenants TYPE datetime;",
+    "DEFINE INDEX tenants_subdomain ON tenants COLUMNS subdomain UNIQUE;",
+    // Monthly charges a subscription period is paid in, the first at checkout
+    "DEFINE TABLE installments SCHEMAFULL;",
+    "DEFINE FIELD schedule_id ON installments TYPE string;",
+    "DEFINE FIELD user_id ON installments TYPE string;",
+    "DEFINE FIELD subscription_id ON installments TYPE string;",
+    "DEFINE FIELD sequence ON installments TYPE int;",
+    "DEFINE FIELD count ON installments TYPE int;",
+    "DEFINE FIELD amount ON installments TYPE number;",
+    "DEFINE FIELD currency ON installments TYPE string;",
+    "DEFINE FIELD status ON installments TYPE string;",
+    "DEFINE FIELD due_at ON installments TYPE option<datetime>;",
+    "DEFINE FIELD merchant_transaction_id ON installments TYPE option<string>;",
+    "DEFINE FIELD attempts ON installments TYPE int DEFAULT 0;",
+    "DEFINE FIELD last_error ON installments TYPE option<string>;",
+    "DEFINE FIELD paid_at ON installments TYPE option<datetime>;",
+    "DEFINE FIELD created_at ON installments TYPE datetime;",
+    "DEFINE FIELD updated_at ON installments TYPE datetime;",
+    "DEFINE INDEX installments_subscription ON installments COLUMNS subscription_id, sequence;",
+    "DEFINE INDEX installments_due ON installments COLUMNS status, due_at;",
+    "DEFINE INDEX installments_txn ON installments COLUMNS merchant_transaction_id;",
+    // Gateway reports and settlement files compared with the payments table
+    "DEFINE TABLE reconciliation_reports SCHEMAFULL;",
+    "DEFINE FIELD source ON reconciliation_reports TYPE string;",
+    "DEFINE FIELD tenant_id ON reconciliation_reports TYPE option<string>;",
+    "DEFINE FIELD window_start ON reconciliation_reports TYPE option<datetime>;",
+    "DEFINE FIELD window_end ON reconciliation_reports TYPE option<datetime>;",
+    "DEFINE FIELD records ON reconciliation_reports TYPE int;",
+    "DEFINE FIELD matched ON reconciliation_reports TYPE int;",
+    "DEFINE FIELD mismatches ON reconciliation_reports FLEXIBLE TYPE array<object> DEFAULT [];",
+    "DEFINE FIELD created_at ON reconciliation_reports TYPE datetime;",
+    "DEFINE INDEX reconciliation_reports_created ON reconciliation_reports COLUMNS tenant_id, created_at;",
+    // Win-back coupons used, keyed by [user_id, code] so each works once per user
+    "DEFINE TABLE coupon_redemptions SCHEMAFULL;",
+    "DEFINE FIELD user_id ON coupon_redemptions TYPE string;",
+    "DEFINE FIELD code ON coupon_redemptions TYPE string;",
+    "DEFINE FIELD subscription_id ON coupon_redemptions TYPE string;",
+    "DEFINE FIELD created_at ON coupon_redemptions TYPE datetime;",
+    "DEFINE INDEX coupon_redemptions_user ON coupon_redemptions COLUMNS user_id;",
+    "DEFINE TABLE schema_meta SCHEMALESS;",
+];
+
+/// What startup does about drift, from `SCHEMA_DRIFT`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SchemaDriftMode {
+    /// Define whatever is missing (the default). Changed definitions are only
+    /// reported; redefining them could reject existing records.
+    Apply,
+    /// Report drift and start anyway.
+    Warn,
+    /// Refuse to start while anything is missing or differs.
+    Fail,
+}
+
+impl SchemaDriftMode {
+    pub fn from_env() -> Self {
+        match env::var("SCHEMA_DRIFT").unwrap_or_default().trim() {
+            "warn" => SchemaDriftMode::Warn,
+            "fail" => SchemaDriftMode::Fail,
+            _ => SchemaDriftMode::Apply,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DefinitionKind {
+    Table,
+    Field,
+    Index,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "problem", rename_all = "snake_case")]
+pub enum DriftProblem {
+    Missing,
+    /// The field exists with a different type.
+    TypeMismatch { expected: String, actual: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaDrift {
+    pub kind: DefinitionKind,
+    pub table: String,
+    /// Field or index name; the table name for tables.
+    pub name: String,
+    #[serde(flatten)]
+    pub problem: DriftProblem,
+    /// The expected definition.
+    pub statement: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaReport {
+    pub expected_version: u32,
+    /// Version recorded the last time the schema was fully applied.
+    pub live_version: Option<u32>,
+    pub drift: Vec<SchemaDrift>,
+    /// Definitions applied during this run.
+    pub applied: Vec<String>,
+}
+
+impl SchemaReport {
+    pub fn is_clean(&self) -> bool {
+        self.drift.is_empty()
+    }
+}
+
+/// An expected definition, parsed from `DEFINE <KIND> <name> ON <table> ...`.
+struct Definition {
+    kind: DefinitionKind,
+    table: String,
+    name: String,
+    field_type: Option<String>,
+    statement: &'static str,
+}
+
+fn parse_definition(statement: &'static str) -> Option<Definition> {
+    let words: Vec<&str> = statement.trim_end_matches(';').split_whitespace().collect();
+    let kind = match words.get(1).copied()? {
+        "TABLE" => DefinitionKind::Table,
+        "FIELD" => DefinitionKind::Field,
+        "INDEX" => DefinitionKind::Index,
+        _ => return None,
+    };
+    let name = words.get(2)?.to_string();
+    let table = match kind {
+        DefinitionKind::Table => name.clone(),
+        _ if words.get(3) == Some(&"ON") => words.get(4)?.to_string(),
+        _ => return None,
+    };
+
+    Some(Definition {
+        kind,
+        table,
+        name,
+        field_type: field_type(statement),
+        statement,
+    })
+}
+
+/// The `TYPE` of a field definition, as written or as SurrealDB renders it.
+fn field_type(definition: &str) -> Option<String> {
+    let mut words = definition.trim_end_matches(';').split_whitespace();
+    words.find(|w| *w == "TYPE")?;
+    words.next().map(|t| t.to_lowercase())
+}
+
+/// Names and definitions under `key` in an `INFO FOR ...` result.
+fn info_map(info: &serde_json::Value, key: &str) -> BTreeMap<String, String> {
+    info.get(key)
+        .and_then(|v| v.as_object())
+        .map(|map| {
+            map.iter()
+                .map(|(name, def)| (name.clone(), def.as_str().unwrap_or_default().to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+async fn info(db: &Surreal<Client>, query: &str) -> Result<serde_json::Value, String> {
+    let result: Option<serde_json::Value> = db
+        .query(query)
+        .await
+        .and_then(|mut response| response.take(0))
+        .map_err(|e| format!("Failed to run {}: {}", query, e))?;
+    Ok(result.unwrap_or_default())
+}
+
+/// Compares the live database against `SCHEMA` without changing anything.
+pub async fn inspect(db: &Surreal<Client>) -> Result<SchemaReport, String> {
+    let live_tables = info_map(&info(db, "INFO FOR DB").await?, "tables");
+
+    let mut live_fields: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+    let mut live_indexes: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+    for table in live_tables.keys() {
+        let table_info = info(db, &format!("INFO FOR TABLE {}", table)).await?;
+        live_fields.insert(table.clone(), info_map(&table_info, "fields"));
+        live_indexes.insert(table.clone(), info_map(&table_info, "indexes"));
+    }
+
+    let mut drift = Vec::new();
+    for definition in SCHEMA.iter().filter_map(|s| parse_definition(s)) {
+        let problem = match definition.kind {
+            DefinitionKind::Table => (!live_tables.contains_key(&definition.table)).then_some(DriftProblem::Missing),
+            DefinitionKind::Index => {
+                let exists = live_indexes.get(&definition.table).is_some_and(|i| i.contains_key(&definition.name));
+                (!exists).then_some(DriftProblem::Missing)
+            }
+            DefinitionKind::Field => match live_fields.get(&definition.table).and_then(|f| f.get(&definition.name)) {
+                None => Some(DriftProblem::Missing),
+                Some(live) => match (&definition.field_type, field_type(live)) {
+                    (Some(expected), Some(actual)) if *expected != actual => Some(DriftProblem::TypeMismatch {
+                        expected: expected.clone(),
+                        actual,
+                    }),
+                    _ => None,
+                },
+            },
+        };
+
+        if let Some(problem) = problem {
+            drift.push(SchemaDrift {
+                kind: definition.kind,
+                table: definition.table,
+                name: definition.name,
+                problem,
+                statement: definition.statement.to_string(),
+            });
+        }
+    }
+
+    let live_version: Option<u32> = db
+        .query("SELECT VALUE version FROM ONLY schema_meta:current")
+        .await
+        .and_then(|mut response| response.take(0))
+        .ok()
+        .flatten();
+
+    Ok(SchemaReport {
+        expected_version: SCHEMA_VERSION,
+        live_version,
+        drift,
+        applied: Vec::new(),
+    })
+}
+
+/// Startup check: inspects the schema, defines what is missing when `mode`
+/// allows it and records `SCHEMA_VERSION` once nothing is left to report.
+pub async fn reconcile(db: &Surreal<Client>, mode: SchemaDriftMode) -> Result<SchemaReport, String> {
+    let report = inspect(db).await?;
+
+    let report = if mode == SchemaDriftMode::Apply && report.drift.iter().any(|d| matches!(d.problem, DriftProblem::Missing)) {
+        let missing: HashSet<&str> = report.drift
+            .iter()
+            .filter(|d| matches!(d.problem, DriftProblem::Missing))
+            .map(|d| d.statement.as_str())
+            .collect();
+
+        let mut applied = Vec::new();
+        // Statements in SCHEMA order, so tables come before their fields
+        for statement in SCHEMA.iter().filter(|s| missing.contains(**s)) {
+            match db.query(*statement).await.and_then(|response| response.check()) {
+                Ok(_) => {
+                    info!("Executed: {}", statement);
+                    applied.push(statement.to_string());
+                }
+                Err(e) => error!("Failed to execute {}: {}", statement, e),
+            }
+        }
+
+        SchemaReport { applied, ..inspect(db).await? }
+    } else {
+        report
+    };
+
+    for drift in &report.drift {
+        match &drift.problem {
+            DriftProblem::Missing => warn!("Schema drift: {:?} {}.{} is missing", drift.kind, drift.table, drift.name),
+            DriftProblem::TypeMismatch { expected, actual } => warn!(
+                "Schema drift: field {}.{} is {} but the models expect {}",
+                drift.table, drift.name, actual, expected
+            ),
+        }
+    }
+
+    if report.is_clean() {
+        if report.live_version != Some(SCHEMA_VERSION) {
+            db.query("UPSERT schema_meta:current SET version = $version, applied_at = time::now()")
+                .bind(("version", SCHEMA_VERSION))
+                .await
+                .and_then(|response| response.check())
+                .map_err(|e| format!("Failed to record schema version: {}", e))?;
+        }
+        info!("Database schema matches version {}", SCHEMA_VERSION);
+    } else if mode == SchemaDriftMode::Fail {
+        return Err(format!(
+            "Database schema has {} difference(s) from version {}; see the log above or GET /api/v1/admin/schema",
+            report.drift.len(),
+            SCHEMA_VERSION
+        ));
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,42 @@
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::time::{sleep, Duration};
+
+/// Held by the container; triggered once the HTTP server has drained.
+#[derive(Clone)]
+pub struct ShutdownTrigger(Arc<watch::Sender<bool>>);
+
+/// Cloned into each background task. Tasks finish the batch they're on and
+/// exit at their next wait.
+#[derive(Clone)]
+pub struct ShutdownSignal(watch::Receiver<bool>);
+
+pub fn channel() -> (ShutdownTrigger, ShutdownSignal) {
+    let (sender, receiver) = watch::channel(false);
+    (ShutdownTrigger(Arc::new(sender)), ShutdownSignal(receiver))
+}
+
+impl ShutdownTrigger {
+    pub fn trigger(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+impl ShutdownSignal {
+    pub fn is_triggered(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Waits `duration` between passes. Returns `true`, early if need be, when
+    /// the task should stop instead of starting another pass.
+    pub async fn sleep(&mut self, duration: Duration) -> bool {
+        if self.is_triggered() {
+            return true;
+        }
+        tokio::select! {
+            _ = sleep(duration) => false,
+            // A dropped trigger means the container is gone; stop as well
+            _ = self.0.changed() => true,
+        }
+    }
+}
@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use tracing::info;
+use uuid::Uuid;
+use crate::models::payment::{CreatePaymentDto, Payment, PaymentStatus};
+use crate::models::state_reason;
+use crate::models::subscription::{CreateSubscriptionDto, Subscription, SubscriptionStatus};
+use crate::models::user::{CreateUserDto, User};
+use crate::services::database::DatabaseService;
+use crate::services::tenant;
+
+// Storage traits for the core records. Handlers written against these rather
+// than `DatabaseService` don't depend on SurrealDB, so another backend (or an
+// in-memory one) can stand in. Errors stay `String`, as in `DatabaseService`.
+
+#[async_trait]
+pub trait UserRepo: Send + Sync {
+    async fn create_user(&self, dto: CreateUserDto) -> Result<User, String>;
+    async fn get_user(&self, user_id: &str) -> Option<User>;
+    async fn get_user_by_email(&self, email: &str) -> Option<User>;
+}
+
+#[async_trait]
+pub trait PaymentRepo: Send + Sync {
+    async fn create_payment(&self, dto: CreatePaymentDto, vat_rate_percent: u32, gateway: &str) -> Result<Payment, String>;
+    async fn get_payment(&self, payment_id: &str) -> Option<Payment>;
+    async fn get_payment_by_merchant_id(&self, merchant_transaction_id: &str) -> Option<Payment>;
+    async fn get_payments_by_user(&self, user_id: &str) -> Vec<Payment>;
+    async fn update_payment_status(&self, merchant_transaction_id: &str, status: &PaymentStatus) -> Result<(), String>;
+}
+
+#[async_trait]
+pub trait SubscriptionRepo: Send + Sync {
+    async fn create_subscription(&self, dto: CreateSubscriptionDto) -> Result<Subscription, String>;
+    async fn get_subscription(&self, subscription_id: &str) -> Option<Subscription>;
+    async fn get_subscriptions_by_user(&self, user_id: &str) -> Vec<Subscription>;
+    async fn activate_subscription(&self, subscription_id: &str) -> Result<(), String>;
+    async fn update_subscription_status(&self, subscription_id: &str, status: SubscriptionStatus) -> Result<(), String>;
+}
+
+/// Everything a shared handler may need; register as `Data<dyn Storage>`.
+pub trait Storage: UserRepo + PaymentRepo + SubscriptionRepo {}
+
+impl<T: UserRepo + PaymentRepo + SubscriptionRepo> Storage for T {}
+
+#[async_trait]
+impl UserRepo for DatabaseService {
+    async fn create_user(&self, dto: CreateUserDto) -> Result<User, String> {
+        DatabaseService::create_user(self, dto).await
+    }
+
+    async fn get_user(&self, user_id: &str) -> Option<User> {
+        DatabaseService::get_user(self, user_id).await
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Option<User> {
+        DatabaseService::get_user_by_email(self, email).await
+    }
+}
+
+#[async_trait]
+impl PaymentRepo for DatabaseService {
+    async fn create_payment(&self, dto: CreatePaymentDto, vat_rate_percent: u32, gateway: &str) -> Result<Payment, String> {
+        DatabaseService::create_payment(self, dto, vat_rate_percent, gateway).await
+    }
+
+    async fn get_payment(&self, payment_id: &str) -> Option<Payment> {
+        DatabaseService::get_payment(self, payment_id).await
+    }
+
+    async fn get_payment_by_merchant_id(&self, merchant_transaction_id: &str) -> Option<Payment> {
+        DatabaseService::get_payment_by_merchant_id(self, merchant_transaction_id).await
+    }
+
+    async fn get_payments_by_user(&self, user_id: &str) -> Vec<Payment> {
+        DatabaseService::get_payments_by_user(self, user_id).await
+    }
+
+    async fn update_payment_status(&self, merchant_transaction_id: &str, status: &PaymentStatus) -> Result<(), String> {
+        DatabaseService::update_payment_status(self, merchant_transaction_id, status).await
+    }
+}
+
+#[async_trait]
+impl SubscriptionRepo for DatabaseService {
+    async fn create_subscription(&self, dto: CreateSubscriptionDto) -> Result<Subscription, String> {
+        DatabaseService::create_subscription(self, dto).await
+    }
+
+    async fn get_subscription(&self, subscription_id: &str) -> Option<Subscription> {
+        DatabaseService::get_subscription(self, subscription_id).await
+    }
+
+    async fn get_subscriptions_by_user(&self, user_id: &str) -> Vec<Subscription> {
+        DatabaseService::get_subscriptions_by_user(self, user_id).await
+    }
+
+    async fn activate_subscription(&self, subscription_id: &str) -> Result<(), String> {
+        DatabaseService::activate_subscription(self, subscription_id).await
+    }
+
+    async fn update_subscription_status(&self, subscription_id: &str, status: SubscriptionStatus) -> Result<(), String> {
+        DatabaseService::update_subscription_status(self, subscription_id, status).await
+    }
+}
+
+/// Keeps records in memory, for tests of handlers that take `Data<dyn Storage>`.
+/// Follows `DatabaseService`'s rules for duplicate emails and tenant scoping,
+/// but records no audit entries or activity.
+#[derive(Default)]
+pub struct MemoryStorage {
+    users: Mutex<HashMap<String, User>>,
+    payments: Mutex<HashMap<String, Payment>>,
+    subscriptions: Mutex<HashMap<String, Subscription>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn new_key() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+#[async_trait]
+impl UserRepo for MemoryStorage {
+    async fn create_user(&self, dto: CreateUserDto) -> Result<User, String> {
+        let tenant_id = tenant::current_id();
+        let mut users = self.users.lock().map_err(|_| "User store unavailable")?;
+        if users.values().any(|u| u.email == dto.email && u.tenant_id == tenant_id) {
+            return Err("User with this email already exists".to_string());
+        }
+
+        let now = Utc::now();
+        let user = User {
+            id: new_key(),
+            email: dto.email,
+            name: dto.name,
+            tags: Vec::new(),
+            billing_address: None,
+            vat_number: None,
+            locale: None,
+            deleted_at: None,
+            tenant_id,
+            created_at: now,
+            updated_at: now,
+        };
+        users.insert(user.id.clone(), user.clone());
+        info!("Created user: {} ({})", user.name, user.id);
+        Ok(user)
+    }
+
+    async fn get_user(&self, user_id: &str) -> Option<User> {
+        let users = self.users.lock().ok()?;
+        users
+            .get(user_id.strip_prefix("users:").unwrap_or(user_id))
+            .filter(|user| tenant::visible(user.tenant_id.as_deref()))
+            .cloned()
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Option<User> {
+        let users = self.users.lock().ok()?;
+        users
+            .values()
+            .find(|user| user.email == email && tenant::visible(user.tenant_id.as_deref()))
+            .cloned()
+    }
+}
+
+#[async_trait]
+impl PaymentRepo for MemoryStorage {
+    async fn create_payment(&self, dto: CreatePaymentDto, vat_rate_percent: u32, gateway: &str) -> Result<Payment, String> {
+        let tenant_id = match tenant::current_id() {
+            Some(id) => Some(id),
+            None => self.get_user(&dto.user_id).await.and_then(|user| user.tenant_id),
+        };
+        let mut payment = Payment::pending(dto, vat_rate_percent, gateway, tenant_id);
+        payment.id = new_key();
+
+        let mut payments = self.payments.lock().map_err(|_| "Payment store unavailable")?;
+        payments.insert(payment.id.clone(), payment.clone());
+        Ok(payment)
+    }
+
+    async fn get_payment(&self, payment_id: &str) -> Option<Payment> {
+        let payments = self.payments.lock().ok()?;
+        payments
+            .get(payment_id.strip_prefix("payments:").unwrap_or(payment_id))
+            .filter(|payment| tenant::visible(payment.tenant_id.as_deref()))
+            .cloned()
+    }
+
+    async fn get_payment_by_merchant_id(&self, merchant_transaction_id: &str) -> Option<Payment> {
+        let payments = self.payments.lock().ok()?;
+        payments
+            .values()
+            .find(|p| p.merchant_transaction_id == merchant_transaction_id && tenant::visible(p.tenant_id.as_deref()))
+            .cloned()
+    }
+
+    async fn get_payments_by_user(&self, user_id: &str) -> Vec<Payment> {
+        let Ok(payments) = self.payments.lock() else {
+            return Vec::new();
+        };
+        payments
+            .values()
+            .filter(|p| p.user_id == user_id && tenant::visible(p.tenant_id.as_deref()))
+            .cloned()
+            .collect()
+    }
+
+    async fn update_payment_status(&self, merchant_transaction_id: &str, status: &PaymentStatus) -> Result<(), String> {
+        let mut payments = self.payments.lock().map_err(|_| "Payment store unavailable")?;
+        let payment = payments
+            .values_mut()
+            .find(|p| p.merchant_transaction_id == merchant_transaction_id && tenant::visible(p.tenant_id.as_deref()))
+            .ok_or_else(|| format!("Payment not found for merchant_transaction_id: {}", merchant_transaction_id))?;
+        payment.status = status.clone();
+        payment.state_reason = Some(state_reason::for_payment_status(status).to_string());
+        payment.authentication_url = None;
+        payment.updated_at = Utc::now();
+        Ok(())
+    }
+}
+
+impl MemoryStorage {
+    fn update_subscription<F>(&self, subscription_id: &str, change: F) -> Result<(), String>
+    where
+        F: FnOnce(&mut Subscription),
+    {
+        let mut subscriptions = self.subscriptions.lock().map_err(|_| "Subscription store unavailable")?;
+        let subscription = subscriptions
+            .get_mut(subscription_id.strip_prefix("subscriptions:").unwrap_or(subscription_id))
+            .filter(|s| tenant::visible(s.tenant_id.as_deref()))
+            .ok_or_else(|| format!("Subscription not found: {}", subscription_id))?;
+        change(subscription);
+        subscription.updated_at = Utc::now();
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SubscriptionRepo for MemoryStorage {
+    async fn create_subscription(&self, dto: CreateSubscriptionDto) -> Result<Subscription, String> {
+        let tenant_id = match tenant::current_id() {
+            Some(id) => Some(id),
+            None => self.get_user(&dto.user_id).await.and_then(|user| user.tenant_id),
+        };
+        let mut subscription = Subscription::pending(dto, tenant_id);
+        subscription.id = new_key();
+
+        let mut subscriptions = self.subscriptions.lock().map_err(|_| "Subscription store unavailable")?;
+        subscriptions.insert(subscription.id.clone(), subscription.clone());
+        Ok(subscription)
+    }
+
+    async fn get_subscription(&self, subscription_id: &str) -> Option<Subscription> {
+        let subscriptions = self.subscriptions.lock().ok()?;
+        subscriptions
+            .get(subscription_id.strip_prefix("subscriptions:").unwrap_or(subscription_id))
+            .filter(|s| tenant::visible(s.tenant_id.as_deref()))
+            .cloned()
+    }
+
+    async fn get_subscriptions_by_user(&self, user_id: &str) -> Vec<Subscription> {
+        let Ok(subscriptions) = self.subscriptions.lock() else {
+            return Vec::new();
+        };
+        subscriptions
+            .values()
+            .filter(|s| s.user_id == user_id && tenant::visible(s.tenant_id.as_deref()))
+            .cloned()
+            .collect()
+    }
+
+    async fn activate_subscription(&self, subscription_id: &str) -> Result<(), String> {
+        let now = Utc::now();
+        self.update_subscription(subscription_id, |subscription| {
+            let (end, anchor_day) = subscription.period_from(now);
+            subscription.status = SubscriptionStatus::Active;
+            subscription.start_date = Some(now);
+            subscription.end_date = Some(end);
+            subscription.billing_anchor_day = anchor_day;
+            subscription.grace_end_date = Some(end + Duration::days(subscription.grace_period_days as i64));
+            subscription.cancel_at_period_end = false;
+            subscription.cancelled_at = None;
+            subscription.cancellation_effective_at = None;
+            subscription.cancellation_reason = None;
+            subscription.pause_duration_secs = 0;
+            subscription.amount_due = None;
+            subscription.amount_paid = 0.0;
+            subscription.part_payments.clear();
+            subscription.state_reason = Some(state_reason::ACTIVATED.to_string());
+        })
+    }
+
+    async fn update_subscription_status(&self, subscription_id: &str, status: SubscriptionStatus) -> Result<(), String> {
+        self.update_subscription(subscription_id, |subscription| subscription.status = status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::subscription::SuspensionPolicy;
+
+    fn subscription_dto(user_id: &str) -> CreateSubscriptionDto {
+        CreateSubscriptionDto {
+            user_id: user_id.to_string(),
+            plan_id: None,
+            plan_name: "Premium".to_string(),
+            price: 99.0,
+            currency: "ZAR".to_string(),
+            payment_method: None,
+            grace_period_days: 3,
+            billing_period_days: 30,
+            billing_period_months: None,
+            suspension_policy: SuspensionPolicy::default(),
+            usage_pricing: None,
+            seat_count: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn memory_storage_refuses_a_duplicate_email() {
+        let storage = MemoryStorage::new();
+        let dto = || CreateUserDto {
+            email: "sam@example.com".to_string(),
+            name: "Sam".to_string(),
+        };
+
+        let user = storage.create_user(dto()).await.unwrap();
+        assert!(storage.create_user(dto()).await.is_err());
+        assert_eq!(storage.get_user_by_email("sam@example.com").await.unwrap().id, user.id);
+        assert!(storage.get_user(&format!("users:{}", user.id)).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn memory_storage_tracks_payment_status() {
+        let storage = MemoryStorage::new();
+        let payment = storage.create_payment(CreatePaymentDto {
+            user_id: "user1".to_string(),
+            subscription_id: "sub1".to_string(),
+            amount: 115.0,
+            currency: None,
+            payment_method: None,
+            region: None,
+            installments: None,
+        }, 15, "peach").await.unwrap();
+        assert_eq!(payment.status, PaymentStatus::Pending);
+        assert_eq!(payment.vat_amount, Some(15.0));
+
+        storage.update_payment_status(&payment.merchant_transaction_id, &PaymentStatus::Completed).await.unwrap();
+        let stored = storage.get_payment_by_merchant_id(&payment.merchant_transaction_id).await.unwrap();
+        assert_eq!(stored.status, PaymentStatus::Completed);
+        assert_eq!(storage.get_payments_by_user("user1").await.len(), 1);
+        assert!(storage.update_payment_status("TXN_MISSING", &PaymentStatus::Failed).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn memory_storage_activates_a_subscription_for_one_period() {
+        let storage = MemoryStorage::new();
+        let subscription = storage.create_subscription(subscription_dto("user1")).await.unwrap();
+        assert_eq!(subscription.status, SubscriptionStatus::Pending);
+
+        storage.activate_subscription(&subscription.id).await.unwrap();
+        let active = storage.get_subscription(&subscription.id).await.unwrap();
+        assert_eq!(active.status, SubscriptionStatus::Active);
+        let (start, end) = (active.start_date.unwrap(), active.end_date.unwrap());
+        assert_eq!((end - start).num_days(), 30);
+        assert_eq!(active.grace_end_date, Some(end + Duration::days(3)));
+    }
+}
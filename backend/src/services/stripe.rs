@@ -0,0 +1,495 @@
+use std::env;
+use actix_web::http::header::HeaderMap;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde_json::Value;
+use sha2::Sha256;
+use tracing::debug;
+use crate::models::recurring_payment::CardExpiry;
+use crate::services::gateway::{
+    CardRegistrationRequest, ChargeStatus, CheckoutRequest, CheckoutSession, GatewayResult, GatewayTransaction, PaymentGateway,
+    WebhookKind, WebhookNotification,
+};
+
+const STRIPE_API_URL: &str = "https://api.stripe.com/v1";
+
+/// Stripe Checkout and PaymentIntents. Cards are saved for off-session use,
+/// and the stored token is `customer_id|payment_method_id` since Stripe needs
+/// both to charge a renewal.
+#[derive(Clone)]
+pub struct StripePaymentService {
+    client: Client,
+    api_url: String,
+    secret_key: String,
+    webhook_secret: String,
+    success_url: String,
+    cancel_url: String,
+    supported_currencies: Vec<String>,
+}
+
+impl StripePaymentService {
+    pub fn new(
+        secret_key: String,
+        webhook_secret: String,
+        success_url: String,
+        cancel_url: String,
+        supported_currencies: Vec<String>,
+    ) -> Self {
+        Self {
+            client: Client::new(),
+            api_url: STRIPE_API_URL.to_string(),
+            secret_key,
+            webhook_secret,
+            success_url,
+            cancel_url,
+            supported_currencies,
+        }
+    }
+
+    /// Reads `STRIPE_SECRET_KEY`, `STRIPE_WEBHOOK_SECRET`, `STRIPE_SUCCESS_URL`
+    /// and `STRIPE_CANCEL_URL`. The success URL defaults to the shared
+    /// `/payments/callback` so the result page works the same as with Peach.
+    pub fn from_env(supported_currencies: Vec<String>) -> Result<Self, String> {
+        let required = |key: &str| env::var(key).map_err(|_| format!("{} must be set", key));
+        Ok(Self::new(
+            required("STRIPE_SECRET_KEY")?,
+            required("STRIPE_WEBHOOK_SECRET")?,
+            env::var("STRIPE_SUCCESS_URL").unwrap_or_else(|_| {
+                "http://127.0.0.1:8080/api/v1/payments/callback?resource_path=/checkouts/{CHECKOUT_SESSION_ID}".to_string()
+            }),
+            required("STRIPE_CANCEL_URL")?,
+            supported_currencies,
+        ))
+    }
+
+    async fn post(&self, path: &str, form: &[(&str, String)]) -> GatewayResult<Value> {
+        let response = self.client
+            .post(format!("{}{}", self.api_url, path))
+            .basic_auth(&self.secret_key, None::<&str>)
+            .form(form)
+            .send()
+            .await?;
+        Self::read_body(response).await
+    }
+
+    async fn get(&self, path: &str) -> GatewayResult<Value> {
+        let response = self.client
+            .get(format!("{}{}", self.api_url, path))
+            .basic_auth(&self.secret_key, None::<&str>)
+            .send()
+            .await?;
+        Self::read_body(response).await
+    }
+
+    /// Card declines come back as HTTP 402 with an `error` object; those are
+    /// returned as a body so callers see a failed transaction, not an error.
+    async fn read_body(response: reqwest::Response) -> GatewayResult<Value> {
+        let status = response.status();
+        let body_text = response.text().await?;
+        debug!("Stripe API response status: {}", status);
+
+        if !status.is_success() && status.as_u16() != 402 {
+            return Err(format!("Stripe API error: Status {}, Body: {}", status, body_text).into());
+        }
+        Ok(serde_json::from_str(&body_text)?)
+    }
+}
+
+/// Stripe amounts are integers in the currency's minor unit.
+fn minor_units(amount: f64) -> String {
+    Decimal::from_f64(amount)
+        .map(|a| (a * Decimal::ONE_HUNDRED).round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero))
+        .and_then(|a| a.to_i64())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn text(value: Option<&Value>) -> Option<String> {
+    value.and_then(|v| v.as_str()).map(|s| s.to_string())
+}
+
+/// Expanded objects come back as objects, unexpanded ones as bare ids.
+fn object_id(value: Option<&Value>) -> Option<String> {
+    match value {
+        Some(Value::String(id)) => Some(id.clone()),
+        Some(object) => text(object.get("id")),
+        None => None,
+    }
+}
+
+/// Stripe reports expiry as numbers (`exp_month`, `exp_year`).
+fn card_expiry(card: Option<&Value>) -> Option<CardExpiry> {
+    let card = card?;
+    let month = card.get("exp_month")?.as_u64()?;
+    let year = card.get("exp_year")?.as_i64()?;
+    CardExpiry::parse(&month.to_string(), &year.to_string())
+}
+
+/// 3-D Secure page of a PaymentIntent or SetupIntent in `requires_action`.
+fn authentication_url(intent: &Value) -> Option<String> {
+    text(intent.get("next_action").and_then(|a| a.get("redirect_to_url")).and_then(|r| r.get("url")))
+}
+
+/// Maps a PaymentIntent (or a 402 error carrying one).
+fn payment_intent_transaction(body: Value) -> GatewayTransaction {
+    let error = body.get("error").cloned();
+    let intent = error
+        .as_ref()
+        .and_then(|e| e.get("payment_intent"))
+        .cloned()
+        .unwrap_or_else(|| body.clone());
+
+    let status = match intent.get("status").and_then(|s| s.as_str()) {
+        Some("succeeded") => ChargeStatus::Succeeded,
+        Some("processing") | Some("requires_capture") => ChargeStatus::Pending,
+        Some("requires_action") => ChargeStatus::AwaitingAuthentication,
+        _ => ChargeStatus::Failed,
+    };
+    let last_error = error.or_else(|| intent.get("last_payment_error").cloned());
+    let code = last_error
+        .as_ref()
+        .and_then(|e| text(e.get("decline_code")).or_else(|| text(e.get("code"))))
+        .unwrap_or_else(|| text(intent.get("status")).unwrap_or_default());
+
+    let payment_method = intent.get("payment_method");
+    let card = payment_method.and_then(|pm| pm.get("card"));
+    let registration_id = match (object_id(intent.get("customer")), object_id(payment_method)) {
+        (Some(customer), Some(method)) => Some(format!("{}|{}", customer, method)),
+        _ => None,
+    };
+
+    GatewayTransaction {
+        status,
+        code,
+        description: last_error.as_ref().and_then(|e| text(e.get("message"))),
+        gateway_reference: text(intent.get("id")),
+        merchant_transaction_id: text(intent.get("metadata").and_then(|m| m.get("merchant_transaction_id"))),
+        payment_brand: text(card.and_then(|c| c.get("brand"))),
+        registration_id,
+        card_last4: text(card.and_then(|c| c.get("last4"))),
+        card_expiry: card_expiry(card),
+        authentication_url: authentication_url(&intent),
+        authorized_only: false,
+        raw: body,
+    }
+}
+
+/// Maps a Dispute. Disputes reference the PaymentIntent they are about, which
+/// is the `gateway_reference` our payment was stored with.
+fn dispute_transaction(body: Value) -> GatewayTransaction {
+    GatewayTransaction {
+        status: ChargeStatus::Failed,
+        code: text(body.get("reason")).unwrap_or_else(|| "dispute".to_string()),
+        description: text(body.get("status")),
+        gateway_reference: object_id(body.get("payment_intent")),
+        merchant_transaction_id: text(body.get("metadata").and_then(|m| m.get("merchant_transaction_id"))),
+        payment_brand: None,
+        registration_id: None,
+        card_last4: None,
+        card_expiry: None,
+        authentication_url: None,
+        authorized_only: false,
+        raw: body,
+    }
+}
+
+/// Maps a SetupIntent from a card-update checkout. The saved card becomes the
+/// new `customer_id|payment_method_id` token.
+fn setup_intent_transaction(body: Value) -> GatewayTransaction {
+    let status = match body.get("status").and_then(|s| s.as_str()) {
+        Some("succeeded") => ChargeStatus::Succeeded,
+        Some("processing") => ChargeStatus::Pending,
+        Some("requires_action") => ChargeStatus::AwaitingAuthentication,
+        _ => ChargeStatus::Failed,
+    };
+    let last_error = body.get("last_setup_error");
+    let payment_method = body.get("payment_method");
+    let card = payment_method.and_then(|pm| pm.get("card"));
+    let registration_id = match (object_id(body.get("customer")), object_id(payment_method)) {
+        (Some(customer), Some(method)) if status == ChargeStatus::Succeeded => Some(format!("{}|{}", customer, method)),
+        _ => None,
+    };
+
+    GatewayTransaction {
+        status,
+        code: last_error
+            .and_then(|e| text(e.get("decline_code")).or_else(|| text(e.get("code"))))
+            .unwrap_or_else(|| text(body.get("status")).unwrap_or_default()),
+        description: last_error.and_then(|e| text(e.get("message"))),
+        gateway_reference: text(body.get("id")),
+        merchant_transaction_id: text(body.get("metadata").and_then(|m| m.get("merchant_transaction_id"))),
+        payment_brand: text(card.and_then(|c| c.get("brand"))),
+        registration_id,
+        card_last4: text(card.and_then(|c| c.get("last4"))),
+        card_expiry: card_expiry(card),
+        authentication_url: authentication_url(&body),
+        authorized_only: false,
+        raw: body,
+    }
+}
+
+fn refund_transaction(body: Value) -> GatewayTransaction {
+    let status = match body.get("status").and_then(|s| s.as_str()) {
+        Some("succeeded") => ChargeStatus::Succeeded,
+        Some("pending") | Some("requires_action") => ChargeStatus::Pending,
+        _ => ChargeStatus::Failed,
+    };
+
+    GatewayTransaction {
+        status,
+        code: text(body.get("failure_reason"))
+            .or_else(|| text(body.get("status")))
+            .unwrap_or_default(),
+        description: None,
+        gateway_reference: text(body.get("id")),
+        merchant_transaction_id: text(body.get("metadata").and_then(|m| m.get("merchant_transaction_id"))),
+        payment_brand: None,
+        registration_id: None,
+        card_last4: None,
+        card_expiry: None,
+        authentication_url: None,
+        authorized_only: false,
+        raw: body,
+    }
+}
+
+#[async_trait]
+impl PaymentGateway for StripePaymentService {
+    fn name(&self) -> &'static str {
+        "stripe"
+    }
+
+    fn supported_currencies(&self) -> &[String] {
+        &self.supported_currencies
+    }
+
+    fn supports_statement_descriptor(&self) -> bool {
+        true
+    }
+
+    /// Test-mode keys are `sk_test_...`/`rk_test_...`.
+    fn is_live(&self) -> bool {
+        !self.secret_key.contains("_test_")
+    }
+
+    async fn health_check(&self) -> GatewayResult<()> {
+        self.get("/v1/balance").await.map(|_| ())
+    }
+
+    async fn initiate_checkout(&self, request: &CheckoutRequest<'_>) -> GatewayResult<CheckoutSession> {
+        let mut form = vec![
+            ("mode", "payment".to_string()),
+            ("success_url", self.success_url.clone()),
+            ("cancel_url", self.cancel_url.clone()),
+            ("client_reference_id", request.merchant_transaction_id.to_string()),
+            ("customer_creation", "always".to_string()),
+            ("line_items[0][quantity]", "1".to_string()),
+            ("line_items[0][price_data][currency]", request.currency.to_lowercase()),
+            ("line_items[0][price_data][unit_amount]", minor_units(request.amount)),
+            ("line_items[0][price_data][product_data][name]", "Subscription".to_string()),
+            ("payment_intent_data[setup_future_usage]", "off_session".to_string()),
+            ("payment_intent_data[metadata][merchant_transaction_id]", request.merchant_transaction_id.to_string()),
+            ("payment_intent_data[metadata][subscription_id]", request.subscription_id.to_string()),
+            ("payment_intent_data[metadata][user_id]", request.user_id.to_string()),
+        ];
+        // Card charges only take a suffix to the account's statement prefix
+        if let Some(descriptor) = request.descriptor {
+            form.push(("payment_intent_data[statement_descriptor_suffix]", descriptor.to_string()));
+        }
+        let session = self.post("/checkout/sessions", &form).await?;
+
+        let checkout_id = text(session.get("id"))
+            .ok_or_else(|| format!("Stripe response missing session id: {}", session))?;
+        Ok(CheckoutSession {
+            checkout_id,
+            redirect_url: text(session.get("url")),
+            registration_id: None,
+        })
+    }
+
+    async fn initiate_card_registration(&self, request: &CardRegistrationRequest<'_>) -> GatewayResult<CheckoutSession> {
+        // Setup-mode sessions can't create a customer, so reuse the one behind
+        // the current card or create one up front
+        let customer = match request.current_token.and_then(|t| t.split_once('|')) {
+            Some((customer, _)) => customer.to_string(),
+            None => {
+                let customer = self
+                    .post("/customers", &[("metadata[user_id]", request.user_id.to_string())])
+                    .await?;
+                text(customer.get("id")).ok_or_else(|| format!("Stripe response missing customer id: {}", customer))?
+            }
+        };
+
+        let form = [
+            ("mode", "setup".to_string()),
+            ("success_url", self.success_url.clone()),
+            ("cancel_url", self.cancel_url.clone()),
+            ("client_reference_id", request.merchant_transaction_id.to_string()),
+            ("customer", customer),
+            ("currency", request.currency.to_lowercase()),
+            ("setup_intent_data[metadata][merchant_transaction_id]", request.merchant_transaction_id.to_string()),
+            ("setup_intent_data[metadata][subscription_id]", request.subscription_id.to_string()),
+            ("setup_intent_data[metadata][user_id]", request.user_id.to_string()),
+        ];
+        let session = self.post("/checkout/sessions", &form).await?;
+
+        let checkout_id = text(session.get("id"))
+            .ok_or_else(|| format!("Stripe response missing session id: {}", session))?;
+        Ok(CheckoutSession {
+            checkout_id,
+            redirect_url: text(session.get("url")),
+            registration_id: None,
+        })
+    }
+
+    async fn check_status(&self, checkout_id: &str) -> GatewayResult<GatewayTransaction> {
+        let session = self
+            .get(&format!("/checkout/sessions/{}?expand[]=payment_intent.payment_method", checkout_id))
+            .await?;
+
+        let mut transaction = match session.get("payment_intent").filter(|pi| pi.is_object()) {
+            Some(intent) => payment_intent_transaction(intent.clone()),
+            None => GatewayTransaction {
+                status: ChargeStatus::Pending,
+                code: text(session.get("status")).unwrap_or_default(),
+                description: None,
+                gateway_reference: None,
+                merchant_transaction_id: None,
+                payment_brand: None,
+                registration_id: None,
+                card_last4: None,
+                card_expiry: None,
+                authentication_url: None,
+                authorized_only: false,
+                raw: Value::Null,
+            },
+        };
+        // An abandoned session never gets a payment attempt
+        if session.get("status").and_then(|s| s.as_str()) == Some("expired") {
+            transaction.status = ChargeStatus::Failed;
+        }
+        transaction.merchant_transaction_id = transaction
+            .merchant_transaction_id
+            .or_else(|| text(session.get("client_reference_id")));
+        transaction.raw = session;
+        Ok(transaction)
+    }
+
+    async fn charge_token(
+        &self,
+        token: &str,
+        amount: f64,
+        currency: &str,
+        merchant_transaction_id: &str,
+        descriptor: Option<&str>,
+    ) -> GatewayResult<GatewayTransaction> {
+        let (customer, payment_method) = token
+            .split_once('|')
+            .ok_or("Stripe token must be customer_id|payment_method_id")?;
+
+        let mut form = vec![
+            ("amount", minor_units(amount)),
+            ("currency", currency.to_lowercase()),
+            ("customer", customer.to_string()),
+            ("payment_method", payment_method.to_string()),
+            ("off_session", "true".to_string()),
+            ("confirm", "true".to_string()),
+            ("metadata[merchant_transaction_id]", merchant_transaction_id.to_string()),
+        ];
+        if let Some(descriptor) = descriptor {
+            form.push(("statement_descriptor_suffix", descriptor.to_string()));
+        }
+        Ok(payment_intent_transaction(self.post("/payment_intents", &form).await?))
+    }
+
+    async fn refund(
+        &self,
+        gateway_reference: &str,
+        amount: f64,
+        _currency: &str,
+        merchant_transaction_id: &str,
+    ) -> GatewayResult<GatewayTransaction> {
+        let form = [
+            ("payment_intent", gateway_reference.to_string()),
+            ("amount", minor_units(amount)),
+            ("metadata[merchant_transaction_id]", merchant_transaction_id.to_string()),
+        ];
+        Ok(refund_transaction(self.post("/refunds", &form).await?))
+    }
+
+    fn webhook_signature(&self, headers: &HeaderMap, _body: &[u8]) -> Option<String> {
+        headers
+            .get("Stripe-Signature")
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string())
+    }
+
+    /// `Stripe-Signature` is `t=<unix>,v1=<hex>[,v1=...]`, signed over `t.body`.
+    fn validate_webhook(&self, body: &[u8], signature: &str) -> bool {
+        let mut timestamp = None;
+        let mut candidates = Vec::new();
+        for part in signature.split(',') {
+            match part.trim().split_once('=') {
+                Some(("t", t)) => timestamp = Some(t),
+                Some(("v1", v)) => candidates.push(v),
+                _ => {}
+            }
+        }
+        let Some(timestamp) = timestamp else { return false };
+
+        let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(self.webhook_secret.as_bytes()) else {
+            return false;
+        };
+        mac.update(timestamp.as_bytes());
+        mac.update(b".");
+        mac.update(body);
+        let expected = hex::encode(mac.finalize().into_bytes());
+        candidates.iter().any(|c| *c == expected)
+    }
+
+    fn parse_webhook(&self, body: &[u8]) -> Result<WebhookNotification, String> {
+        let event: Value = serde_json::from_slice(body).map_err(|e| format!("Invalid JSON: {}", e))?;
+        let event_type = text(event.get("type")).unwrap_or_default();
+        let object = event
+            .get("data")
+            .and_then(|d| d.get("object"))
+            .cloned()
+            .unwrap_or(Value::Null);
+
+        let subscription_id = text(object.get("metadata").and_then(|m| m.get("subscription_id")));
+        let (kind, transaction) = match event_type.as_str() {
+            "payment_intent.succeeded" | "payment_intent.payment_failed" | "payment_intent.processing" => {
+                (WebhookKind::Payment, payment_intent_transaction(object))
+            }
+            "setup_intent.succeeded" | "setup_intent.setup_failed" => {
+                (WebhookKind::Payment, setup_intent_transaction(object))
+            }
+            "refund.created" | "refund.updated" | "refund.failed" => (WebhookKind::Refund, refund_transaction(object)),
+            "charge.dispute.created" => (WebhookKind::Chargeback, dispute_transaction(object)),
+            _ => (WebhookKind::Other, refund_transaction(object)),
+        };
+
+        Ok(WebhookNotification {
+            kind,
+            subscription_id,
+            occurred_at: event
+                .get("created")
+                .and_then(|c| c.as_i64())
+                .and_then(|secs| DateTime::<Utc>::from_timestamp(secs, 0)),
+            signature: None,
+            fields: serde_json::json!({
+                "id": event.get("id"),
+                "type": event_type,
+                "object_id": transaction.gateway_reference,
+            }),
+            transaction: GatewayTransaction {
+                code: if kind == WebhookKind::Other { event_type.clone() } else { transaction.code.clone() },
+                ..transaction
+            },
+        })
+    }
+}
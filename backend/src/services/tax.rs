@@ -0,0 +1,58 @@
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Serialize;
+
+/// VAT split of a single amount. Worked out in decimal and rounded to cents
+/// half away from zero, so the parts always add back up to the gross amount.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TaxBreakdown {
+    pub vat_rate_percent: u32,
+    /// Amount excluding VAT.
+    pub net: f64,
+    pub vat: f64,
+    /// Amount including VAT, i.e. what the customer pays.
+    pub gross: f64,
+}
+
+impl TaxBreakdown {
+    /// Splits a VAT-inclusive amount.
+    pub fn from_inclusive(gross: f64, vat_rate_percent: u32) -> Self {
+        let gross = to_cents(gross);
+        let rate = Decimal::from(vat_rate_percent);
+        let vat = round_cents(gross * rate / (Decimal::ONE_HUNDRED + rate));
+        Self::from_parts(vat_rate_percent, gross - vat, vat, gross)
+    }
+
+    /// Adds VAT to a VAT-exclusive amount.
+    pub fn from_exclusive(net: f64, vat_rate_percent: u32) -> Self {
+        let net = to_cents(net);
+        let vat = round_cents(net * Decimal::from(vat_rate_percent) / Decimal::ONE_HUNDRED);
+        Self::from_parts(vat_rate_percent, net, vat, net + vat)
+    }
+
+    pub fn for_price(price: f64, prices_include_vat: bool, vat_rate_percent: u32) -> Self {
+        if prices_include_vat {
+            Self::from_inclusive(price, vat_rate_percent)
+        } else {
+            Self::from_exclusive(price, vat_rate_percent)
+        }
+    }
+
+    fn from_parts(vat_rate_percent: u32, net: Decimal, vat: Decimal, gross: Decimal) -> Self {
+        Self {
+            vat_rate_percent,
+            net: net.to_f64().unwrap_or_default(),
+            vat: vat.to_f64().unwrap_or_default(),
+            gross: gross.to_f64().unwrap_or_default(),
+        }
+    }
+}
+
+/// Amounts are stored as floats; bring them back to whole cents before any maths.
+fn to_cents(amount: f64) -> Decimal {
+    round_cents(Decimal::from_f64(amount).unwrap_or_default())
+}
+
+fn round_cents(amount: Decimal) -> Decimal {
+    amount.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero)
+}
@@ -0,0 +1,252 @@
+use tracing::warn;
+use crate::models::template::{MessageTemplate, TemplateChannel, MAX_TEMPLATE_LEN};
+use crate::services::database::DatabaseService;
+use crate::services::formatting::Locale;
+
+/// Overrides for this locale are the fallback for locales with neither their
+/// own copy nor a built-in translation.
+pub const DEFAULT_LOCALE: Locale = Locale::EnZa;
+
+/// A message merchants can customise: the `{{variables}}` it is rendered
+/// with and the text used until they save their own.
+pub struct TemplateSpec {
+    pub channel: TemplateChannel,
+    pub key: &'static str,
+    pub variables: &'static [&'static str],
+    pub default_body: &'static str,
+    /// Built-in text in other languages; locales missing here use `default_body`.
+    pub translations: &'static [(Locale, &'static str)],
+}
+
+impl TemplateSpec {
+    /// The built-in text for `locale`, if it's been translated.
+    pub fn translation(&self, locale: Locale) -> Option<&'static str> {
+        self.translations.iter().find(|(l, _)| *l == locale).map(|(_, body)| *body)
+    }
+}
+
+pub const TEMPLATES: &[TemplateSpec] = &[
+    TemplateSpec {
+        channel: TemplateChannel::Email,
+        key: "payment_succeeded",
+        variables: &["name", "plan", "amount", "reference", "statement_line", "tax_invoice"],
+        default_body: include_str!("../../templates/email/payment_succeeded.txt"),
+        translations: &[(Locale::AfZa, include_str!("../../templates/email/af/payment_succeeded.txt"))],
+    },
+    TemplateSpec {
+        channel: TemplateChannel::Email,
+        key: "payment_failed",
+        variables: &["name", "plan", "amount", "reference"],
+        default_body: include_str!("../../templates/email/payment_failed.txt"),
+        translations: &[(Locale::AfZa, include_str!("../../templates/email/af/payment_failed.txt"))],
+    },
+    TemplateSpec {
+        channel: TemplateChannel::Email,
+        key: "renewal_upcoming",
+        variables: &["name", "plan", "amount", "renewal_date"],
+        default_body: include_str!("../../templates/email/renewal_upcoming.txt"),
+        translations: &[(Locale::AfZa, include_str!("../../templates/email/af/renewal_upcoming.txt"))],
+    },
+    TemplateSpec {
+        channel: TemplateChannel::Email,
+        key: "subscription_suspended",
+        variables: &["name", "plan"],
+        default_body: include_str!("../../templates/email/subscription_suspended.txt"),
+        translations: &[(Locale::AfZa, include_str!("../../templates/email/af/subscription_suspended.txt"))],
+    },
+    TemplateSpec {
+        channel: TemplateChannel::Email,
+        key: "subscription_downgraded",
+        variables: &["name", "plan"],
+        default_body: include_str!("../../templates/email/subscription_downgraded.txt"),
+        translations: &[(Locale::AfZa, include_str!("../../templates/email/af/subscription_downgraded.txt"))],
+    },
+    TemplateSpec {
+        channel: TemplateChannel::Email,
+        key: "subscription_downgraded_to_fallback",
+        variables: &["name", "plan", "fallback_plan"],
+        default_body: include_str!("../../templates/email/subscription_downgraded_to_fallback.txt"),
+        translations: &[(Locale::AfZa, include_str!("../../templates/email/af/subscription_downgraded_to_fallback.txt"))],
+    },
+    TemplateSpec {
+        channel: TemplateChannel::Email,
+        key: "subscription_cancelled",
+        variables: &["name", "plan", "ends_at"],
+        default_body: include_str!("../../templates/email/subscription_cancelled.txt"),
+        translations: &[(Locale::AfZa, include_str!("../../templates/email/af/subscription_cancelled.txt"))],
+    },
+    TemplateSpec {
+        channel: TemplateChannel::Email,
+        key: "card_updated",
+        variables: &["name", "card"],
+        default_body: include_str!("../../templates/email/card_updated.txt"),
+        translations: &[(Locale::AfZa, include_str!("../../templates/email/af/card_updated.txt"))],
+    },
+    TemplateSpec {
+        channel: TemplateChannel::Email,
+        key: "card_expiring",
+        variables: &["name", "card", "expiry"],
+        default_body: include_str!("../../templates/email/card_expiring.txt"),
+        translations: &[(Locale::AfZa, include_str!("../../templates/email/af/card_expiring.txt"))],
+    },
+    TemplateSpec {
+        channel: TemplateChannel::Email,
+        key: "daily_summary",
+        variables: &[
+            "name", "date", "payments_succeeded", "payments_failed", "revenue", "refunded", "net_revenue",
+            "renewals_succeeded", "renewals_failed", "new_subscriptions", "cancelled_subscriptions",
+            "suspended_subscriptions", "webhook_errors",
+        ],
+        default_body: include_str!("../../templates/email/daily_summary.txt"),
+        translations: &[],
+    },
+    TemplateSpec {
+        channel: TemplateChannel::Email,
+        key: "webhook_silence",
+        variables: &["name", "gateway", "last_processed_at", "silent_hours"],
+        default_body: include_str!("../../templates/email/webhook_silence.txt"),
+        translations: &[],
+    },
+    TemplateSpec {
+        channel: TemplateChannel::InApp,
+        key: "manual_renewal",
+        variables: &["subscription_id"],
+        default_body: include_str!("../../templates/notification/manual_renewal.txt"),
+        translations: &[(Locale::AfZa, include_str!("../../templates/notification/af/manual_renewal.txt"))],
+    },
+    TemplateSpec {
+        channel: TemplateChannel::InApp,
+        key: "downgrade",
+        variables: &["subscription_id", "plan", "fallback_plan"],
+        default_body: include_str!("../../templates/notification/downgrade.txt"),
+        translations: &[(Locale::AfZa, include_str!("../../templates/notification/af/downgrade.txt"))],
+    },
+    TemplateSpec {
+        channel: TemplateChannel::InApp,
+        key: "card_update",
+        variables: &["subscription_id"],
+        default_body: include_str!("../../templates/notification/card_update.txt"),
+        translations: &[(Locale::AfZa, include_str!("../../templates/notification/af/card_update.txt"))],
+    },
+    TemplateSpec {
+        channel: TemplateChannel::InApp,
+        key: "card_expiring",
+        variables: &["subscription_id", "expiry"],
+        default_body: include_str!("../../templates/notification/card_expiring.txt"),
+        translations: &[(Locale::AfZa, include_str!("../../templates/notification/af/card_expiring.txt"))],
+    },
+    TemplateSpec {
+        channel: TemplateChannel::InApp,
+        key: "card_migration",
+        variables: &["subscription_id"],
+        default_body: include_str!("../../templates/notification/card_migration.txt"),
+        translations: &[(Locale::AfZa, include_str!("../../templates/notification/af/card_migration.txt"))],
+    },
+    TemplateSpec {
+        channel: TemplateChannel::InApp,
+        key: "member_added",
+        variables: &["plan"],
+        default_body: include_str!("../../templates/notification/member_added.txt"),
+        translations: &[(Locale::AfZa, include_str!("../../templates/notification/af/member_added.txt"))],
+    },
+];
+
+pub fn spec(channel: TemplateChannel, key: &str) -> Option<&'static TemplateSpec> {
+    TEMPLATES.iter().find(|t| t.channel == channel && t.key == key)
+}
+
+/// Replaces `{{var}}` placeholders; unknown ones are left as they are.
+pub fn substitute(template: &str, vars: &[(&str, String)]) -> String {
+    let mut text = template.to_string();
+    for (key, value) in vars {
+        text = text.replace(&format!("{{{{{}}}}}", key), value);
+    }
+    text
+}
+
+/// Placeholder names used in `body`, in order of appearance.
+fn placeholders(body: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = body;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) => {
+                names.push(after[..end].trim());
+                rest = &after[end + 2..];
+            }
+            None => break,
+        }
+    }
+    names
+}
+
+/// Checks a merchant's body for `spec`: not empty or too long, only known
+/// variables, and a `Subject:` line first for emails.
+pub fn validate(spec: &TemplateSpec, body: &str) -> Result<String, String> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err("body must not be empty".to_string());
+    }
+    if body.chars().count() > MAX_TEMPLATE_LEN {
+        return Err(format!("body must be at most {} characters", MAX_TEMPLATE_LEN));
+    }
+    if spec.channel == TemplateChannel::Email && !body.starts_with("Subject:") {
+        return Err("Email templates must start with a \"Subject:\" line".to_string());
+    }
+    if let Some(unknown) = placeholders(body).into_iter().find(|name| !spec.variables.contains(name)) {
+        return Err(format!(
+            "Unknown variable {{{{{}}}}}; {} can use {}",
+            unknown,
+            spec.key,
+            spec.variables.join(", "),
+        ));
+    }
+    Ok(body.to_string())
+}
+
+/// The saved overrides, loaded once for a send or a batch of notifications.
+#[derive(Debug, Default)]
+pub struct TemplateRegistry {
+    overrides: Vec<MessageTemplate>,
+}
+
+impl TemplateRegistry {
+    /// Built-in text only.
+    pub fn builtin() -> Self {
+        Self::default()
+    }
+
+    /// Falls back to the built-in text if the overrides can't be read, so a
+    /// database hiccup never stops a message going out.
+    pub async fn load(db: &DatabaseService) -> Self {
+        match db.list_message_templates().await {
+            Ok(overrides) => Self { overrides },
+            Err(e) => {
+                warn!("Using built-in message templates: {}", e);
+                Self::builtin()
+            }
+        }
+    }
+
+    /// The override for `locale`, else the built-in translation for it, else
+    /// the override for `DEFAULT_LOCALE`, else the built-in English text.
+    /// `None` for keys with no template.
+    pub fn body(&self, channel: TemplateChannel, key: &str, locale: Locale) -> Option<&str> {
+        let saved = |locale: Locale| {
+            self.overrides
+                .iter()
+                .find(|t| t.channel == channel && t.key == key && t.locale == locale.tag())
+                .map(|t| t.body.as_str())
+        };
+        let spec = spec(channel, key)?;
+        saved(locale)
+            .or(spec.translation(locale))
+            .or_else(|| saved(DEFAULT_LOCALE))
+            .or(Some(spec.default_body))
+    }
+
+    pub fn render(&self, channel: TemplateChannel, key: &str, locale: Locale, vars: &[(&str, String)]) -> Option<String> {
+        self.body(channel, key, locale).map(|body| substitute(body, vars))
+    }
+}
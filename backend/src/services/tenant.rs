@@ -0,0 +1,43 @@
+use std::future::Future;
+use crate::models::tenant::Tenant;
+
+tokio::task_local! {
+    static CURRENT_TENANT: Tenant;
+}
+
+/// The tenant the request being handled was resolved to by the `tenant`
+/// middleware; `None` outside tenants, including background work that spans
+/// all of them.
+pub fn current() -> Option<Tenant> {
+    CURRENT_TENANT.try_with(|tenant| tenant.clone()).ok()
+}
+
+pub fn current_id() -> Option<String> {
+    CURRENT_TENANT.try_with(|tenant| tenant.id.clone()).ok()
+}
+
+/// Whether a record belonging to `tenant_id` may be seen: always outside a
+/// tenant, else only the tenant's own.
+pub fn visible(tenant_id: Option<&str>) -> bool {
+    match CURRENT_TENANT.try_with(|tenant| tenant_id == Some(tenant.id.as_str())) {
+        Ok(own) => own,
+        Err(_) => true,
+    }
+}
+
+/// Runs `fut` as `tenant`, or unchanged when there's none.
+pub async fn with_tenant<F: Future>(tenant: Option<Tenant>, fut: F) -> F::Output {
+    match tenant {
+        Some(tenant) => CURRENT_TENANT.scope(tenant, fut).await,
+        None => fut.await,
+    }
+}
+
+/// The single label `host` adds in front of `base_domain`, e.g. `acme` for
+/// `acme.pay.example.com:443` under `pay.example.com`.
+pub fn subdomain(host: &str, base_domain: &str) -> Option<String> {
+    let host = host.split(':').next()?.to_ascii_lowercase();
+    let base = base_domain.trim().trim_start_matches('.').to_ascii_lowercase();
+    let label = host.strip_suffix(&base)?.strip_suffix('.')?;
+    (!label.is_empty() && !label.contains('.')).then(|| label.to_string())
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use actix_web::http::header::HeaderMap;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use crate::models::reconciliation::SettlementRecord;
+use crate::services::gateway::{
+    CardRegistrationRequest, CheckoutRequest, CheckoutSession, CheckoutWidget, GatewayResult,
+    GatewayTransaction, PaymentGateway, WebhookNotification,
+};
+use crate::services::peach::PeachPaymentService;
+use crate::services::tenant;
+
+/// Wraps the Peach gateway so each call uses the Peach account of the
+/// current tenant (see `services::tenant`), falling back to the
+/// deployment's own for tenants without credentials and outside tenants.
+pub struct TenantPeachGateway {
+    default: Arc<PeachPaymentService>,
+    /// Per tenant id, with the tenant's `updated_at` so changed credentials
+    /// replace the entry.
+    tenants: RwLock<HashMap<String, (DateTime<Utc>, Arc<PeachPaymentService>)>>,
+}
+
+impl TenantPeachGateway {
+    pub fn new(default: PeachPaymentService) -> Self {
+        Self {
+            default: Arc::new(default),
+            tenants: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn current(&self) -> Arc<PeachPaymentService> {
+        let Some(tenant) = tenant::current() else {
+            return self.default.clone();
+        };
+        let Some(credentials) = &tenant.peach else {
+            return self.default.clone();
+        };
+
+        if let Ok(tenants) = self.tenants.read() {
+            if let Some((updated_at, gateway)) = tenants.get(&tenant.id) {
+                if *updated_at == tenant.updated_at {
+                    return gateway.clone();
+                }
+            }
+        }
+        let gateway = Arc::new(self.default.with_credentials(credentials));
+        if let Ok(mut tenants) = self.tenants.write() {
+            tenants.insert(tenant.id.clone(), (tenant.updated_at, gateway.clone()));
+        }
+        gateway
+    }
+}
+
+#[async_trait]
+impl PaymentGateway for TenantPeachGateway {
+    fn name(&self) -> &'static str {
+        self.default.name()
+    }
+
+    fn supported_currencies(&self) -> &[String] {
+        self.default.supported_currencies()
+    }
+
+    fn supports_statement_descriptor(&self) -> bool {
+        self.default.supports_statement_descriptor()
+    }
+
+    fn supports_authorization(&self) -> bool {
+        self.default.supports_authorization()
+    }
+
+    fn is_live(&self) -> bool {
+        self.default.is_live()
+    }
+
+    fn supports_transaction_reports(&self) -> bool {
+        self.default.supports_transaction_reports()
+    }
+
+    fn checkout_widget(&self) -> Option<CheckoutWidget> {
+        self.current().checkout_widget()
+    }
+
+    async fn health_check(&self) -> GatewayResult<()> {
+        self.current().health_check().await
+    }
+
+    async fn initiate_checkout(&self, request: &CheckoutRequest<'_>) -> GatewayResult<CheckoutSession> {
+        self.current().initiate_checkout(request).await
+    }
+
+    async fn initiate_card_registration(&self, request: &CardRegistrationRequest<'_>) -> GatewayResult<CheckoutSession> {
+        self.current().initiate_card_registration(request).await
+    }
+
+    async fn check_status(&self, checkout_id: &str) -> GatewayResult<GatewayTransaction> {
+        self.current().check_status(checkout_id).await
+    }
+
+    async fn charge_token(
+        &self,
+        token: &str,
+        amount: f64,
+        currency: &str,
+        merchant_transaction_id: &str,
+        descriptor: Option<&str>,
+    ) -> GatewayResult<GatewayTransaction> {
+        self.current().charge_token(token, amount, currency, merchant_transaction_id, descriptor).await
+    }
+
+    async fn refund(
+        &self,
+        gateway_reference: &str,
+        amount: f64,
+        currency: &str,
+        merchant_transaction_id: &str,
+    ) -> GatewayResult<GatewayTransaction> {
+        self.current().refund(gateway_reference, amount, currency, merchant_transaction_id).await
+    }
+
+    async fn capture(&self, gateway_reference: &str, amount: f64, currency: &str) -> GatewayResult<GatewayTransaction> {
+        self.current().capture(gateway_reference, amount, currency).await
+    }
+
+    async fn void(&self, gateway_reference: &str, currency: &str) -> GatewayResult<GatewayTransaction> {
+        self.current().void(gateway_reference, currency).await
+    }
+
+    async fn transaction_report(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> GatewayResult<Vec<SettlementRecord>> {
+        self.current().transaction_report(from, to).await
+    }
+
+    fn webhook_signature(&self, headers: &HeaderMap, body: &[u8]) -> Option<String> {
+        self.current().webhook_signature(headers, body)
+    }
+
+    fn validate_webhook(&self, body: &[u8], signature: &str) -> bool {
+        self.current().validate_webhook(body, signature)
+    }
+
+    fn decrypt_webhook(&self, body: &[u8], signature: &str) -> Option<Result<Vec<u8>, String>> {
+        self.current().decrypt_webhook(body, signature)
+    }
+
+    fn parse_webhook(&self, body: &[u8]) -> Result<WebhookNotification, String> {
+        self.current().parse_webhook(body)
+    }
+}
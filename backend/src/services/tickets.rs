@@ -0,0 +1,89 @@
+use tracing::{error, info};
+use crate::models::activity::ActivityCategory;
+use crate::models::ticket::{NewTicket, TicketSource};
+use crate::models::webhook_event::WebhookOutcome;
+use crate::services::database::DatabaseService;
+use crate::services::gateway::GatewayTransaction;
+
+/// The failed-payment ticket for a subscription, or for the payment alone
+/// when it has none.
+pub fn failed_payment_ticket(
+    user_id: &str,
+    subscription_id: Option<&str>,
+    payment_id: &str,
+    reason: &str,
+) -> NewTicket {
+    let subject = match subscription_id {
+        Some(subscription_id) => format!("Payment failed for subscription {}", subscription_id),
+        None => format!("Payment {} failed", payment_id),
+    };
+
+    NewTicket {
+        user_id: user_id.to_string(),
+        source: TicketSource::FailedPayment,
+        subject,
+        description: Some(format!("Payment {} failed: {}", payment_id, reason)),
+        payment_id: Some(payment_id.to_string()),
+        subscription_id: subscription_id.map(str::to_string),
+        assignee: None,
+    }
+}
+
+/// Opens (or adds to) the failed-payment ticket. Failures are logged; the
+/// payment flow carries on regardless.
+pub async fn open_failed_payment_ticket(
+    db: &DatabaseService,
+    user_id: &str,
+    subscription_id: Option<&str>,
+    payment_id: &str,
+    reason: &str,
+) {
+    let ticket = failed_payment_ticket(user_id, subscription_id, payment_id, reason);
+    if let Err(e) = db.open_ticket(ticket).await {
+        error!("Failed to open ticket for failed payment {}: {}", payment_id, e);
+    }
+}
+
+/// Handles chargeback webhooks: opens a dispute ticket on the disputed
+/// payment and notes the dispute on the user's activity.
+pub async fn process_dispute_webhook(
+    db: &DatabaseService,
+    transaction: &GatewayTransaction,
+) -> Result<WebhookOutcome, String> {
+    let payment = match &transaction.merchant_transaction_id {
+        Some(merchant_transaction_id) => db.get_payment_by_merchant_id(merchant_transaction_id).await,
+        None => None,
+    };
+    let payment = match (payment, &transaction.gateway_reference) {
+        (Some(payment), _) => Some(payment),
+        (None, Some(reference)) => db.get_payment_by_gateway_reference(reference).await,
+        (None, None) => None,
+    };
+    let payment = payment.ok_or_else(|| {
+        format!(
+            "No payment found for dispute (merchantTransactionId {:?}, reference {:?})",
+            transaction.merchant_transaction_id, transaction.gateway_reference
+        )
+    })?;
+
+    let ticket = db.open_ticket(NewTicket {
+        user_id: payment.user_id.clone(),
+        source: TicketSource::Dispute,
+        subject: format!("Chargeback on payment {}", payment.merchant_transaction_id),
+        description: Some(format!("Dispute reason: {}", transaction.code)),
+        payment_id: Some(payment.merchant_transaction_id.clone()),
+        subscription_id: payment.subscription_id.clone(),
+        assignee: None,
+    }).await?;
+
+    db.record_activity(
+        &payment.user_id,
+        ActivityCategory::Payment,
+        "payment_disputed",
+        format!("Payment of {:.2} {} was disputed with the card issuer", payment.amount, payment.currency),
+        Some(payment.merchant_transaction_id.clone()),
+    ).await;
+
+    info!("Dispute on {} recorded as ticket {}", payment.merchant_transaction_id, ticket.id);
+    Ok(WebhookOutcome::Processed)
+}
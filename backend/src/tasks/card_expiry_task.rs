@@ -0,0 +1,78 @@
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use tokio::time::sleep;
+use tracing::{error, info};
+use crate::models::notification::CreateNotificationDto;
+use crate::models::recurring_payment::RecurringPayment;
+use crate::models::state_reason;
+use crate::services::clock::Clock;
+use crate::services::database::DatabaseService;
+use crate::services::email::{EmailEvent, EmailService};
+use crate::tasks::daily_summary_task::next_run_at;
+
+/// 09:00 South African time.
+const CARD_EXPIRY_CHECK_HOUR_UTC: u32 = 7;
+
+/// Once a day, asks users whose saved card expires within
+/// `CARD_EXPIRY_NOTICE_DAYS` to add a new one, and flags their subscriptions
+/// as at risk so a renewal doesn't fail without warning.
+pub async fn start_card_expiry_task(
+    db: Arc<DatabaseService>,
+    email: Arc<EmailService>,
+    clock: Arc<dyn Clock>,
+) {
+    tokio::spawn(async move {
+        loop {
+            let now = clock.now();
+            let run_at = next_run_at(now, CARD_EXPIRY_CHECK_HOUR_UTC);
+            info!("Next card expiry check at {}", run_at);
+            sleep((run_at - now).to_std().unwrap_or_default()).await;
+
+            notify_expiring_cards(&db, &email, clock.now()).await;
+        }
+    });
+}
+
+async fn notify_expiring_cards(db: &DatabaseService, email: &EmailService, now: DateTime<Utc>) {
+    let cards = match db.list_cards_needing_expiry_notice(now).await {
+        Ok(cards) => cards,
+        Err(e) => {
+            error!("Error fetching expiring cards: {}", e);
+            return;
+        }
+    };
+
+    let mut notifications = Vec::new();
+    for card in &cards {
+        // Flagged first so a failed email isn't retried every day
+        if let Err(e) = db.flag_card_expiring(card).await {
+            error!("{}", e);
+            continue;
+        }
+        let expiry = card.expiry().map(|e| e.label()).unwrap_or_default();
+        notifications.extend(
+            db.get_subscriptions_by_user(&card.user_id).await
+                .into_iter()
+                .filter(|s| s.at_risk_reason.as_deref() == Some(state_reason::CARD_EXPIRING))
+                .map(|s| CreateNotificationDto::card_expiring(card.user_id.clone(), s.id, &expiry)),
+        );
+        email.notify_user(db, &card.user_id, EmailEvent::CardExpiring { card: card_label(card), expiry }).await;
+    }
+
+    if !notifications.is_empty() {
+        if let Err(e) = db.create_notifications(notifications).await {
+            error!("Failed to create card expiry notifications: {}", e);
+        }
+    }
+    if !cards.is_empty() {
+        info!("Told the owners of {} expiring card(s) to replace them", cards.len());
+    }
+}
+
+fn card_label(card: &RecurringPayment) -> String {
+    match (&card.card_brand, &card.card_last_four) {
+        (Some(brand), Some(last4)) => format!("Your {} ending in {}", brand, last4),
+        (None, Some(last4)) => format!("Your card ending in {}", last4),
+        _ => "Your saved card".to_string(),
+    }
+}
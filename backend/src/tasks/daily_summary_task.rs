@@ -0,0 +1,101 @@
+use std::sync::Arc;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use reqwest::Client;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+use crate::config::AppConfig;
+use crate::services::clock::Clock;
+use crate::models::report::DailySummary;
+use crate::services::database::DatabaseService;
+use crate::services::email::{EmailEvent, EmailService};
+use crate::services::formatting::Formatting;
+use crate::services::fx::FxService;
+use crate::services::operator_alerts::{has_operator_channels, notify_operators};
+
+pub async fn start_daily_summary_task(
+    db: Arc<DatabaseService>,
+    config: Arc<AppConfig>,
+    email: Arc<EmailService>,
+    fx: Arc<FxService>,
+    clock: Arc<dyn Clock>,
+) {
+    if !has_operator_channels(&config) {
+        warn!("No OPERATOR_EMAILS or SLACK_WEBHOOK_URL set; daily billing summary disabled");
+        return;
+    }
+
+    tokio::spawn(async move {
+        let client = Client::new();
+        loop {
+            let now = clock.now();
+            let run_at = next_run_at(now, config.daily_summary_hour_utc);
+            info!("Next daily billing summary at {}", run_at);
+            sleep((run_at - now).to_std().unwrap_or_default()).await;
+
+            send_daily_summary(&db, &config, &email, &fx, &client, run_at.date_naive()).await;
+        }
+    });
+}
+
+/// The next occurrence of `hour`:00 UTC strictly after `now`.
+pub(crate) fn next_run_at(now: DateTime<Utc>, hour: u32) -> DateTime<Utc> {
+    let today = now
+        .date_naive()
+        .and_hms_opt(hour, 0, 0)
+        .unwrap_or_default()
+        .and_utc();
+    if today > now { today } else { today + Duration::days(1) }
+}
+
+async fn send_daily_summary(
+    db: &DatabaseService,
+    config: &AppConfig,
+    email: &EmailService,
+    fx: &FxService,
+    client: &Client,
+    date: NaiveDate,
+) {
+    let summary = match db.get_daily_summary(date, fx).await {
+        Ok(summary) => summary,
+        Err(e) => {
+            error!("Failed to compile daily billing summary for {}: {}", date, e);
+            return;
+        }
+    };
+
+    let text = slack_text(&summary);
+    notify_operators(config, email, client, &EmailEvent::DailySummary(summary), &text).await;
+
+    info!("Sent daily billing summary for {}", date);
+}
+
+fn slack_text(summary: &DailySummary) -> String {
+    let fmt = Formatting::default();
+    let mut text = format!(
+        "*Billing summary for {}*\n\
+         Payments: {} succeeded, {} failed\n\
+         Revenue: {} (refunded {}, net {})\n\
+         Renewals: {} succeeded, {} failed\n\
+         Subscriptions: {} new, {} cancelled, {} suspended\n\
+         Webhook errors: {}",
+        summary.date,
+        summary.payments_succeeded,
+        summary.payments_failed,
+        fmt.amount(summary.revenue, &summary.currency),
+        fmt.amount(summary.refunded, &summary.currency),
+        fmt.amount(summary.net_revenue(), &summary.currency),
+        summary.renewals_succeeded,
+        summary.renewals_failed,
+        summary.new_subscriptions,
+        summary.cancelled_subscriptions,
+        summary.suspended_subscriptions,
+        summary.webhook_errors,
+    );
+    if !summary.unconverted_currencies.is_empty() {
+        text.push_str(&format!(
+            "\n_No exchange rate for {}; excluded from revenue_",
+            summary.unconverted_currencies.join(", ")
+        ));
+    }
+    text
+}
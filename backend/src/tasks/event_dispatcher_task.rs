@@ -0,0 +1,95 @@
+use std::sync::Arc;
+use chrono::Utc;
+use tokio::time::Duration as TokioDuration;
+use tracing::{error, info, warn};
+use crate::config::AppConfig;
+use crate::models::domain_event::{DomainEvent, MAX_EVENT_ATTEMPTS};
+use crate::models::job::retry_delay;
+use crate::services::database::DatabaseService;
+use crate::services::shutdown::ShutdownSignal;
+use crate::services::event_sinks::{configured_sinks, EventSink};
+
+const POLL_INTERVAL_SECONDS: u64 = 5;
+const BATCH_SIZE: u32 = 50;
+
+/// Publishes domain events from the outbox to the configured sinks and
+/// queues them for the owning user's webhook endpoints, oldest first,
+/// retrying failures with backoff.
+pub async fn start_event_dispatcher_task(
+    db: Arc<DatabaseService>,
+    config: Arc<AppConfig>,
+    mut shutdown: ShutdownSignal,
+) {
+    let sinks = configured_sinks(&config);
+    if sinks.is_empty() {
+        info!("No event sinks configured; domain events only go to merchant webhook endpoints");
+    } else {
+        let names: Vec<&str> = sinks.iter().map(|sink| sink.name()).collect();
+        info!("Publishing domain events to: {}", names.join(", "));
+    }
+
+    loop {
+        let due = match db.get_due_domain_events(BATCH_SIZE).await {
+            Ok(events) => events,
+            Err(e) => {
+                warn!("Error fetching due domain events: {}", e);
+                vec![]
+            }
+        };
+        for event in due {
+            dispatch(&db, &sinks, event).await;
+        }
+
+        if shutdown.sleep(TokioDuration::from_secs(POLL_INTERVAL_SECONDS)).await {
+            info!("Event dispatcher stopped for shutdown");
+            return;
+        }
+    }
+}
+
+#[tracing::instrument(name = "domain_event", skip_all, fields(event_id = %event.id, event_type = %event.event_type))]
+async fn dispatch(db: &DatabaseService, sinks: &[Box<dyn EventSink>], event: DomainEvent) {
+    match db.claim_domain_event(&event.id).await {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            warn!("Error claiming domain event {}: {}", event.id, e);
+            return;
+        }
+    }
+    let attempt = event.attempts + 1;
+
+    let mut failures = Vec::new();
+    if let Some(user_id) = event.payload.get("user_id").and_then(|v| v.as_str()) {
+        let queued = match db.list_webhook_endpoints(user_id).await {
+            Ok(endpoints) => db.queue_webhook_deliveries(&event, &endpoints).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = queued {
+            failures.push(format!("merchant webhooks: {}", e));
+        }
+    }
+    for sink in sinks {
+        if let Err(e) = sink.publish(&event).await {
+            failures.push(format!("{}: {}", sink.name(), e));
+        }
+    }
+
+    let recorded = if failures.is_empty() {
+        info!("Published {} event {} for {}", event.event_type, event.id, event.aggregate_id);
+        db.mark_domain_event_published(&event.id).await
+    } else {
+        let error = failures.join("; ");
+        if attempt < MAX_EVENT_ATTEMPTS {
+            let retry_at = Utc::now() + retry_delay(attempt);
+            warn!("Publishing event {} failed on attempt {}, retrying at {}: {}", event.id, attempt, retry_at, error);
+            db.fail_domain_event(&event.id, &error, Some(retry_at)).await
+        } else {
+            error!("Publishing event {} failed after {} attempts: {}", event.id, attempt, error);
+            db.fail_domain_event(&event.id, &error, None).await
+        }
+    };
+    if let Err(e) = recorded {
+        error!("Failed to record delivery of event {}: {}", event.id, e);
+    }
+}
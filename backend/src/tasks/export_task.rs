@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::time::sleep;
+use tracing::{error, info};
+use crate::config::AppConfig;
+use crate::services::attachments::LocalAttachmentStore;
+use crate::services::clock::Clock;
+use crate::services::database::DatabaseService;
+use crate::services::export::run_export;
+use crate::tasks::daily_summary_task::next_run_at;
+
+/// Writes the nightly data-lake export to `DATA_EXPORT_DIR` at
+/// `DATA_EXPORT_HOUR_UTC`.
+pub async fn start_export_task(
+    db: Arc<DatabaseService>,
+    config: Arc<AppConfig>,
+    clock: Arc<dyn Clock>,
+) {
+    let dir = match &config.data_export_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            info!("DATA_EXPORT_DIR not set; data-lake export disabled");
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        let store = LocalAttachmentStore::new(dir);
+        loop {
+            let now = clock.now();
+            let run_at = next_run_at(now, config.data_export_hour_utc);
+            info!("Next data-lake export at {}", run_at);
+            sleep((run_at - now).to_std().unwrap_or_default()).await;
+
+            if let Err(e) = run_export(&db, &store, clock.now()).await {
+                error!("Failed to record data-lake export: {}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,54 @@
+use std::sync::Arc;
+use tokio::time::sleep;
+use tracing::{error, info};
+use crate::config::AppConfig;
+use crate::telemetry;
+use crate::services::clock::Clock;
+use crate::services::database::DatabaseService;
+use crate::services::email::EmailService;
+use crate::services::gateway::PaymentGateway;
+use crate::services::installments::charge_installment;
+use crate::services::tenant;
+use crate::tasks::daily_summary_task::next_run_at;
+
+/// 08:00 South African time.
+const INSTALLMENT_CHARGE_HOUR_UTC: u32 = 6;
+
+/// Once a day, charges every installment that has fallen due, including
+/// earlier declines still within `MAX_INSTALLMENT_ATTEMPTS`.
+pub async fn start_installment_task(
+    db: Arc<DatabaseService>,
+    gateway: Arc<dyn PaymentGateway>,
+    config: Arc<AppConfig>,
+    email: Arc<EmailService>,
+    clock: Arc<dyn Clock>,
+) {
+    tokio::spawn(async move {
+        loop {
+            let now = clock.now();
+            let run_at = next_run_at(now, INSTALLMENT_CHARGE_HOUR_UTC);
+            info!("Next installment run at {}", run_at);
+            sleep((run_at - now).to_std().unwrap_or_default()).await;
+
+            let due = match db.list_due_installments(clock.now()).await {
+                Ok(due) => due,
+                Err(e) => {
+                    error!("Error fetching due installments: {}", e);
+                    continue;
+                }
+            };
+            for installment in &due {
+                // Charged as the subscription's tenant, like a renewal
+                let owner = match db.get_subscription(&installment.subscription_id).await.and_then(|s| s.tenant_id) {
+                    Some(tenant_id) => db.get_tenant(&tenant_id).await,
+                    None => None,
+                };
+                let charge = charge_installment(&db, gateway.as_ref(), &config, &email, installment);
+                telemetry::with_request_id(uuid::Uuid::new_v4().to_string(), tenant::with_tenant(owner, charge)).await;
+            }
+            if !due.is_empty() {
+                info!("Charged {} due installment(s)", due.len());
+            }
+        }
+    });
+}
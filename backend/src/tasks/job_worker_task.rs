@@ -0,0 +1,87 @@
+use std::sync::Arc;
+use chrono::{Duration, Utc};
+use tokio::time::Duration as TokioDuration;
+use tracing::{error, info, warn};
+use crate::config::AppConfig;
+use crate::telemetry;
+use crate::models::job::{retry_delay, Job, JOB_LEASE_MINUTES};
+use crate::services::database::DatabaseService;
+use crate::services::shutdown::ShutdownSignal;
+use crate::services::email::EmailService;
+use crate::services::jobs::run_job;
+
+const POLL_INTERVAL_SECONDS: u64 = 5;
+const BATCH_SIZE: u32 = 20;
+
+/// Runs queued payment side-effects as they fall due, retrying failures with
+/// backoff until the job's attempts run out.
+pub async fn start_job_worker_task(
+    db: Arc<DatabaseService>,
+    config: Arc<AppConfig>,
+    email: Arc<EmailService>,
+    mut shutdown: ShutdownSignal,
+) {
+    loop {
+        let claimed_before = Utc::now() - Duration::minutes(JOB_LEASE_MINUTES);
+        match db.requeue_stale_jobs(claimed_before).await {
+            Ok(0) => {}
+            Ok(count) => warn!("Requeued {} job(s) abandoned mid-run", count),
+            Err(e) => warn!("Error requeueing stale jobs: {}", e),
+        }
+
+        let due = match db.get_due_jobs(BATCH_SIZE).await {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                warn!("Error fetching due jobs: {}", e);
+                vec![]
+            }
+        };
+        for job in due {
+            // Each job gets its own correlation ID, as a request would
+            let run = run_claimed_job(&db, &config, &email, job);
+            telemetry::with_request_id(uuid::Uuid::new_v4().to_string(), run).await;
+        }
+
+        if shutdown.sleep(TokioDuration::from_secs(POLL_INTERVAL_SECONDS)).await {
+            info!("Job worker stopped for shutdown");
+            return;
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "job",
+    skip_all,
+    fields(job_id = %job.id, kind = %job.kind, merchant_transaction_id = tracing::field::Empty)
+)]
+async fn run_claimed_job(db: &DatabaseService, config: &AppConfig, email: &EmailService, job: Job) {
+    match db.claim_job(&job.id).await {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            warn!("Error claiming job {}: {}", job.id, e);
+            return;
+        }
+    }
+    telemetry::record_payment(job.payload.reference());
+    let attempt = job.attempts + 1;
+
+    let recorded = match run_job(db, email, config, &job.payload).await {
+        Ok(()) => {
+            info!("Job {} succeeded on attempt {}", job.id, attempt);
+            db.complete_job(&job.id).await
+        }
+        Err(e) if attempt < job.max_attempts => {
+            let retry_at = Utc::now() + retry_delay(attempt);
+            warn!("Job {} failed on attempt {}, retrying at {}: {}", job.id, attempt, retry_at, e);
+            db.fail_job(&job.id, &e, Some(retry_at)).await
+        }
+        Err(e) => {
+            error!("Job {} failed after {} attempts: {}", job.id, attempt, e);
+            db.fail_job(&job.id, &e, None).await
+        }
+    };
+    if let Err(e) = recorded {
+        error!("Failed to record result of job {}: {}", job.id, e);
+    }
+}
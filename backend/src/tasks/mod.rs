@@ -1 +1,10 @@
-pub mod renewal_task;
\ No newline at end of file
+pub mod renewal_task;
+pub mod daily_summary_task;
+pub mod webhook_watchdog_task;
+pub mod job_worker_task;
+pub mod export_task;
+pub mod event_dispatcher_task;
+pub mod webhook_delivery_task;
+pub mod card_expiry_task;
+pub mod installment_task;
+pub mod reconciliation_task;
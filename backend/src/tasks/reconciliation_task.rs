@@ -0,0 +1,67 @@
+use std::sync::Arc;
+use chrono::{DateTime, Duration, Utc};
+use tokio::time::sleep;
+use tracing::{error, info};
+use crate::telemetry;
+use crate::models::reconciliation::ReconciliationSource;
+use crate::services::clock::Clock;
+use crate::services::database::DatabaseService;
+use crate::services::gateway::PaymentGateway;
+use crate::services::reconciliation::reconcile;
+use crate::services::tenant;
+use crate::tasks::daily_summary_task::next_run_at;
+
+/// 04:00 South African time, once the previous day has settled.
+const RECONCILIATION_HOUR_UTC: u32 = 2;
+
+/// Once a day, compares the gateway's transactions for the previous UTC day
+/// with the payments table, for the deployment's own account and for each
+/// tenant with its own. Mismatches are kept in the reconciliation reports.
+pub async fn start_reconciliation_task(
+    db: Arc<DatabaseService>,
+    gateway: Arc<dyn PaymentGateway>,
+    clock: Arc<dyn Clock>,
+) {
+    if !gateway.supports_transaction_reports() {
+        info!("{} has no transaction reports; daily reconciliation disabled", gateway.name());
+        return;
+    }
+
+    tokio::spawn(async move {
+        loop {
+            let now = clock.now();
+            let run_at = next_run_at(now, RECONCILIATION_HOUR_UTC);
+            info!("Next reconciliation at {}", run_at);
+            sleep((run_at - now).to_std().unwrap_or_default()).await;
+
+            let to = clock.now().date_naive().and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+            let from = to - Duration::days(1);
+
+            let mut accounts = vec![None];
+            match db.list_tenants().await {
+                Ok(tenants) => accounts.extend(
+                    tenants.into_iter().filter(|t| t.peach.is_some() && t.disabled_at.is_none()).map(Some),
+                ),
+                Err(e) => error!("Error listing tenants for reconciliation: {}", e),
+            }
+            for account in accounts {
+                let run = reconcile_account(&db, gateway.as_ref(), from, to);
+                telemetry::with_request_id(uuid::Uuid::new_v4().to_string(), tenant::with_tenant(account, run)).await;
+            }
+        }
+    });
+}
+
+async fn reconcile_account(db: &DatabaseService, gateway: &dyn PaymentGateway, from: DateTime<Utc>, to: DateTime<Utc>) {
+    let account = tenant::current_id().unwrap_or_else(|| "default".to_string());
+    let records = match gateway.transaction_report(from, to).await {
+        Ok(records) => records,
+        Err(e) => {
+            error!("Error fetching {} transaction report for {}: {}", gateway.name(), account, e);
+            return;
+        }
+    };
+    if let Err(e) = reconcile(db, gateway.name(), ReconciliationSource::Gateway, records, Some((from, to))).await {
+        error!("Error reconciling {} for {}: {}", gateway.name(), account, e);
+    }
+}
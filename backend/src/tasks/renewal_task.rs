@@ -1,106 +1,568 @@
 use std::sync::Arc;
-use chrono::Utc;
-use tokio::time::{sleep, Duration as TokioDuration};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+use tokio::time::Duration as TokioDuration;
+use tracing::{error, info, warn};
+use crate::config::AppConfig;
+use crate::telemetry;
+use crate::services::clock::Clock;
 use crate::services::database::DatabaseService;
-use crate::services::peach::PeachPaymentService;
-use crate::models::subscription::SubscriptionStatus;
-use crate::models::payment::{PaymentMethod, CreatePaymentDto};
+use crate::services::dunning::{classify_failure, DunningAction, DunningPolicy, FailureClass};
+use crate::services::gateway::{ChargeStatus, PaymentGateway};
+use crate::services::health::TaskHeartbeat;
+use crate::services::shutdown::ShutdownSignal;
+use crate::services::email::{EmailEvent, EmailService};
+use crate::services::invoicing::issue_invoice;
+use crate::services::tax::TaxBreakdown;
+use crate::services::tenant;
+use crate::services::tickets;
+use crate::models::entitlement::FREE_TIER_PLAN_ID;
+use crate::models::notification::CreateNotificationDto;
+use crate::models::state_reason;
+use crate::models::subscription::{Subscription, SuspensionPolicy};
+use crate::models::payment::{PaymentMethod, PaymentStatus};
+use crate::models::usage::UsageCharge;
+use crate::models::wallet::{WalletEntrySource, WALLET_GATEWAY};
 
 pub async fn start_renewal_task(
     db: Arc<DatabaseService>,
-    peach: Arc<PeachPaymentService>,
+    gateway: Arc<dyn PaymentGateway>,
+    config: Arc<AppConfig>,
+    email: Arc<EmailService>,
+    clock: Arc<dyn Clock>,
+    heartbeat: TaskHeartbeat,
+    mut shutdown: ShutdownSignal,
 ) {
-    tokio::spawn(async move {
-        loop {
-            println!("⏰ Running renewal task at {}", Utc::now());
-            
-            // Get subscriptions due for renewal
-            let due_subs = match db.get_due_subscriptions().await {  // ✅ Added .await
-                Ok(list) => list,
-                Err(e) => {
-                    eprintln!("⚠️ Error fetching due subscriptions: {}", e);
-                    vec![]
-                }
+    let policy = DunningPolicy::from_config(&config);
+
+    loop {
+        let now = clock.now();
+        info!("Running renewal task at {}", now);
+
+        send_renewal_reminders(&db, &email, config.notification_days).await;
+
+        // Subscriptions cancelled for the end of their period end instead of renewing
+        if let Err(e) = db.complete_period_end_cancellations().await {
+            warn!("Error completing scheduled cancellations: {}", e);
+        }
+
+        // Get subscriptions due for renewal (including scheduled retries)
+        let due_subs = match db.get_due_subscriptions().await {  // ✅ Added .await
+            Ok(list) => list,
+            Err(e) => {
+                warn!("Error fetching due subscriptions: {}", e);
+                vec![]
+            }
+        };
+
+        let mut notifications = Vec::new();
+        for sub in due_subs {
+            // Each renewal gets its own correlation ID, as a request would, and
+            // runs as the subscription's tenant so its Peach account is charged
+            let owner = match &sub.tenant_id {
+                Some(tenant_id) => db.get_tenant(tenant_id).await,
+                None => None,
             };
-            
-            for sub in due_subs {
-                let user_id = sub.user_id;
-                let sub_id = sub.id;
-                let token_opt = db.get_recurring_token_by_user(&user_id).await;
-                
-                match token_opt {
-                    Some(token) => {
-                        // Automatically charge
-                        println!("💳 Attempting auto-debit for sub {} with token {}", sub_id, token);
-                        
-                        let transaction_id = format!("RENEWAL_{}", uuid::Uuid::new_v4().simple());
-                        let charge_result = peach
-                            .execute_recurring_payment(&token, sub.price, &transaction_id)
-                            .await;
-                        
-                        match charge_result {
-                            Ok(response) => {
-                                // Check if the payment was actually successful
-                                let result_code = response
-                                    .get("result")
-                                    .and_then(|r| r.get("code"))
-                                    .and_then(|c| c.as_str())
-                                    .unwrap_or_default();
-                                
-                                if result_code.starts_with("000.000") || result_code.starts_with("000.100") {
-                                    // Payment successful
-                                    if let Err(e) = db.mark_subscription_renewed(&sub_id).await {  // ✅ Added .await
-                                        eprintln!("❌ Failed to mark subscription {} as renewed: {}", sub_id, e);
-                                    } else {
-                                        println!("✅ Auto-renewal succeeded for sub {}", sub_id);
-                                    }
-                                } else {
-                                    eprintln!("❌ Auto-renewal payment failed for sub {}: {}", sub_id, result_code);
-                                    // Send manual renewal notification
-                                    if let Err(e) = db.create_manual_renewal_notification(user_id, sub_id).await {  // ✅ Added .await
-                                        eprintln!("❌ Failed to create renewal notification: {}", e);
-                                    }
-                                }
-                            }
-                            Err(err) => {
-                                eprintln!("❌ Auto-renewal failed for sub {}: {}", sub_id, err);
-                                // Send manual renewal notification
-                                if let Err(e) = db.create_manual_renewal_notification(user_id, sub_id).await {  // ✅ Added .await
-                                    eprintln!("❌ Failed to create renewal notification: {}", e);
-                                }
-                            }
-                        }
-                    }
-                    None => {
-                        // No recurring token found - check payment method
-                        let method = sub.payment_method.clone().unwrap_or(PaymentMethod::Card);
-                        if method != PaymentMethod::Card {
-                            println!("📣 No token found for manual method {:?}. Sending reminder.", method);
-                        } else {
-                            println!("⚠️ No token found for CARD method. Cannot auto-renew for sub {}", sub_id);
-                        }
-                        
-                        // Send manual renewal notification regardless of method
-                        if let Err(e) = db.create_manual_renewal_notification(user_id, sub_id).await {  // ✅ Added .await
-                            eprintln!("❌ Failed to create renewal notification: {}", e);
+            let renewal = renew_due_subscription(&db, gateway.as_ref(), &config, &email, &policy, &sub, now, &mut notifications);
+            telemetry::with_request_id(uuid::Uuid::new_v4().to_string(), tenant::with_tenant(owner, renewal)).await;
+        }
+        flush_notifications(&db, notifications).await;
+
+        // Whatever is still unpaid after this run's attempts keeps access in
+        // grace, and is charged again on later runs until its policy applies
+        if let Err(e) = db.start_grace_periods().await {
+            warn!("Error starting grace periods: {}", e);
+        }
+
+        // Apply the suspension policy to subscriptions past grace that aren't in dunning
+        let expired = db.get_expired_unpaid_subscriptions().await.unwrap_or_default();  // ✅ Added .await
+        for sub in expired {
+            lapse_subscription(&db, &email, &sub, false).await;
+        }
+
+        if let Err(e) = db.purge_expired_rate_limits().await {
+            warn!("Error purging expired rate limit windows: {}", e);
+        }
+        if let Err(e) = db.purge_expired_request_signatures().await {
+            warn!("Error purging expired request signatures: {}", e);
+        }
+        heartbeat.beat();
+
+        // Wait 5 minutes for testing (change to 24 hours in production).
+        // A shutdown only ends the loop here, so a batch is never cut short
+        if shutdown.sleep(TokioDuration::from_secs(60 * 5)).await {
+            info!("Renewal task stopped for shutdown");
+            return;
+        }
+        // For production, use: shutdown.sleep(TokioDuration::from_secs(60 * 60 * 24))
+    }
+}
+
+/// How a single renewal attempt ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RenewalOutcome {
+    Renewed,
+    /// The gateway declined the charge; dunning decided what happens next.
+    Declined,
+    /// The charge errored before the gateway decided; treated as a soft decline.
+    GatewayError,
+    /// No stored card, so the user was asked to pay manually.
+    NoToken,
+    /// The user skipped this renewal; the period moved on without a charge.
+    Skipped,
+    /// Usage couldn't be totalled, a skip couldn't be applied, or the charge
+    /// succeeded but the subscription couldn't be updated.
+    Failed,
+}
+
+/// What one renewal attempt charges: the plan price for every seat plus any
+/// metered usage.
+struct RenewalCharge {
+    transaction_id: String,
+    amount: f64,
+    wallet_amount: f64,
+    usage: Option<UsageCharge>,
+}
+
+/// Usage overage for the period being renewed; `None` unless the
+/// subscription is metered.
+async fn period_usage(db: &DatabaseService, sub: &Subscription) -> Result<Option<UsageCharge>, String> {
+    let (Some(pricing), Some(from), Some(to)) = (&sub.usage_pricing, sub.period_start(), sub.end_date) else {
+        return Ok(None);
+    };
+    let units = db.get_usage_total(&sub.id, from, to).await?;
+    Ok(Some(pricing.charge(units, from, to)))
+}
+
+/// Charges one due subscription for its price plus any metered usage,
+/// spending wallet credit first and the stored card for the rest, and applies
+/// the result: renewal, invoice and receipt on success, dunning on failure, a
+/// manual-payment reminder without a card. A skipped renewal isn't charged.
+/// In-app notifications for the user are added to `notifications`, for the
+/// caller to write with the rest of the run's.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "renewal",
+    skip_all,
+    fields(subscription_id = %sub.id, merchant_transaction_id = tracing::field::Empty)
+)]
+pub async fn renew_due_subscription(
+    db: &DatabaseService,
+    gateway: &dyn PaymentGateway,
+    config: &AppConfig,
+    email: &EmailService,
+    policy: &DunningPolicy,
+    sub: &Subscription,
+    now: DateTime<Utc>,
+    notifications: &mut Vec<CreateNotificationDto>,
+) -> RenewalOutcome {
+    let user_id = sub.user_id.clone();
+    let sub_id = sub.id.clone();
+
+    if sub.skip_next_renewal {
+        return match db.skip_renewal(sub).await {
+            Ok(_) => RenewalOutcome::Skipped,
+            Err(e) => {
+                error!("{}", e);
+                RenewalOutcome::Failed
+            }
+        };
+    }
+
+    let token_opt = db.get_recurring_token_by_user(&user_id).await;
+    let transaction_id = format!("RENEWAL_{}", uuid::Uuid::new_v4().simple());
+    telemetry::record_payment(&transaction_id);
+
+    let usage = match period_usage(db, sub).await {
+        Ok(usage) => usage,
+        Err(e) => {
+            // Renewing without the usage would under-charge; try again next run
+            error!("Failed to total usage for sub {}: {}", sub_id, e);
+            return RenewalOutcome::Failed;
+        }
+    };
+
+    // A change scheduled for this renewal switches the plan before the new
+    // period is charged; the usage above is still priced on the old plan
+    let switched = match db.apply_scheduled_plan_change(sub, now).await {
+        Ok(switched) => switched,
+        Err(e) => {
+            error!("Failed to apply scheduled plan change to sub {}: {}", sub_id, e);
+            return RenewalOutcome::Failed;
+        }
+    };
+    let sub = switched.as_ref().unwrap_or(sub);
+    let amount = ((sub.total_price() + usage.as_ref().map_or(0.0, |u| u.amount)) * 100.0).round() / 100.0;
+    if let Some(usage) = &usage {
+        info!("Sub {} used {} {} this period; {} billable", sub_id, usage.units, usage.unit, usage.billable_units);
+    }
+
+    // Without a card, credit is only spent when it pays for the whole renewal
+    let credit = db.get_wallet_balance(&user_id, &sub.currency).await;
+    let wallet_amount = if credit > 0.0 && (token_opt.is_some() || credit + 0.005 >= amount) {
+        match db.debit_wallet(&user_id, &sub.currency, amount, WalletEntrySource::Renewal, &transaction_id).await {
+            Ok(entry) => entry.map(|e| -e.amount).unwrap_or(0.0),
+            Err(e) => {
+                warn!("Renewing sub {} without wallet credit: {}", sub_id, e);
+                0.0
+            }
+        }
+    } else {
+        0.0
+    };
+    let charge = RenewalCharge { transaction_id, amount, wallet_amount, usage };
+    let card_amount = (((amount - wallet_amount) * 100.0).round() / 100.0).max(0.0);
+    if card_amount <= 0.0 {
+        info!("Renewal of sub {} paid from wallet credit", sub_id);
+        return complete_renewal(db, config, email, sub, &charge, WALLET_GATEWAY, None).await;
+    }
+
+    match token_opt {
+        Some(token) => {
+            // Automatically charge
+            info!("Attempting auto-debit for sub {} (attempt {})", sub_id, sub.renewal_attempts + 1);
+
+            let descriptor = if gateway.supports_statement_descriptor() {
+                db.statement_descriptor(sub.plan_id.as_deref(), config.statement_descriptor.as_deref()).await
+            } else {
+                None
+            };
+            let charge_result = gateway
+                .charge_token(&token, card_amount, &sub.currency, &charge.transaction_id, descriptor.as_deref())
+                .await;
+
+            match charge_result {
+                Ok(transaction) => {
+                    let result_code = transaction.code.as_str();
+
+                    if transaction.status == ChargeStatus::Succeeded {
+                        // The old token still works mid-migration; remind the
+                        // user to re-authorise before it is switched off
+                        if db.token_needs_migration(&token).await {
+                            notifications.push(CreateNotificationDto::card_migration(sub.user_id.clone(), sub.id.clone()));
                         }
+                        complete_renewal(db, config, email, sub, &charge, gateway.name(), descriptor).await
+                    } else {
+                        let class = classify_failure(result_code);
+                        let reason_code = state_reason::payment_failed(result_code);
+                        error!("Auto-renewal payment failed for sub {}: {} ({})", sub_id, result_code, class.as_str());
+                        restore_wallet_credit(db, sub, &charge).await;
+                        record_metered_payment(db, config, sub, &charge, gateway.name(), PaymentStatus::Failed, &reason_code).await;
+                        tickets::open_failed_payment_ticket(db, &sub.user_id, Some(&sub.id), &charge.transaction_id, &reason_code).await;
+                        handle_renewal_failure(db, email, policy, sub, &token, class, &format!("{} code {}", gateway.name(), result_code), &reason_code, now, notifications).await;
+                        RenewalOutcome::Declined
                     }
                 }
-            }
-            
-            // Suspend expired subscriptions with 3+ days grace period
-            let expired = db.get_expired_unpaid_subscriptions().await.unwrap_or_default();  // ✅ Added .await
-            for sub in expired {
-                if let Err(e) = db.suspend_subscription(&sub.id).await {  // ✅ Added .await
-                    eprintln!("❌ Failed to suspend expired subscription {}: {}", sub.id, e);
-                } else {
-                    println!("🛑 Suspended expired subscription: {}", sub.id);
+                Err(err) => {
+                    // Transport/gateway errors say nothing about the card; retry them
+                    error!("Auto-renewal failed for sub {}: {}", sub_id, err);
+                    restore_wallet_credit(db, sub, &charge).await;
+                    record_metered_payment(db, config, sub, &charge, gateway.name(), PaymentStatus::Failed, state_reason::PAYMENT_FAILED_GATEWAY_ERROR).await;
+                    tickets::open_failed_payment_ticket(db, &sub.user_id, Some(&sub.id), &charge.transaction_id, state_reason::PAYMENT_FAILED_GATEWAY_ERROR).await;
+                    handle_renewal_failure(db, email, policy, sub, &token, FailureClass::SoftDecline, &err.to_string(), state_reason::PAYMENT_FAILED_GATEWAY_ERROR, now, notifications).await;
+                    RenewalOutcome::GatewayError
                 }
             }
-            
-            // Wait 5 minutes for testing (change to 24 hours in production)
-            sleep(TokioDuration::from_secs(60 * 5)).await;
-            // For production, use: sleep(TokioDuration::from_secs(60 * 60 * 24)).await;
         }
-    });
+        None => {
+            // No recurring token found - check payment method
+            let method = sub.payment_method.clone().unwrap_or(PaymentMethod::Card);
+            if method != PaymentMethod::Card {
+                info!("No token found for manual method {:?}. Sending reminder.", method);
+            } else {
+                warn!("No token found for CARD method. Cannot auto-renew for sub {}", sub_id);
+            }
+
+            // Send manual renewal notification regardless of method
+            notifications.push(CreateNotificationDto::manual_renewal(user_id, sub_id));
+            RenewalOutcome::NoToken
+        }
+    }
+}
+
+/// Starts the next period of a paid renewal, then invoices and emails the
+/// user for the full amount, usage included.
+async fn complete_renewal(
+    db: &DatabaseService,
+    config: &AppConfig,
+    email: &EmailService,
+    sub: &Subscription,
+    charge: &RenewalCharge,
+    gateway: &str,
+    descriptor: Option<String>,
+) -> RenewalOutcome {
+    let transaction_id = charge.transaction_id.as_str();
+    // Payment successful; this also clears any dunning state
+    if let Err(e) = db.mark_subscription_renewed(sub).await {  // ✅ Added .await
+        error!("Failed to mark subscription {} as renewed: {}", sub.id, e);
+        return RenewalOutcome::Failed;
+    }
+
+    info!("Auto-renewal succeeded for sub {}", sub.id);
+    record_metered_payment(db, config, sub, charge, gateway, PaymentStatus::Completed, state_reason::PAYMENT_SUCCEEDED).await;
+    let invoice = match issue_invoice(db, config, &sub.user_id, Some(&sub.id), transaction_id, &sub.currency, TaxBreakdown::from_inclusive(charge.amount, config.vat_rate_percent)).await {
+        Ok(invoice) => Some(Box::new(invoice)),
+        Err(e) => {
+            error!("Failed to issue invoice for renewal {}: {}", transaction_id, e);
+            None
+        }
+    };
+    email.notify_user(db, &sub.user_id, EmailEvent::PaymentSucceeded {
+        plan: sub.plan_name.clone(),
+        amount: charge.amount,
+        currency: sub.currency.clone(),
+        reference: transaction_id.to_string(),
+        descriptor,
+        invoice,
+    }).await;
+    RenewalOutcome::Renewed
+}
+
+/// Gives back wallet credit spent on a renewal whose card charge failed.
+async fn restore_wallet_credit(db: &DatabaseService, sub: &Subscription, charge: &RenewalCharge) {
+    if charge.wallet_amount <= 0.0 {
+        return;
+    }
+    if let Err(e) = db.credit_wallet(&sub.user_id, &sub.currency, charge.wallet_amount, WalletEntrySource::Reversal, &charge.transaction_id).await {
+        error!("Failed to restore wallet credit for renewal {}: {}", charge.transaction_id, e);
+    }
+}
+
+/// Metered renewals are recorded as payments carrying the usage breakdown;
+/// flat renewals only get an invoice.
+async fn record_metered_payment(
+    db: &DatabaseService,
+    config: &AppConfig,
+    sub: &Subscription,
+    charge: &RenewalCharge,
+    gateway: &str,
+    status: PaymentStatus,
+    reason: &str,
+) {
+    let Some(usage) = &charge.usage else { return };
+    let tax = TaxBreakdown::from_inclusive(charge.amount, config.vat_rate_percent);
+    if let Err(e) = db.create_renewal_payment(sub, &charge.transaction_id, tax, gateway, charge.wallet_amount, usage, status, reason).await {
+        error!("Failed to record payment for metered renewal {}: {}", charge.transaction_id, e);
+    }
+}
+
+/// Records a failed renewal charge. Soft declines schedule the next retry or,
+/// once retries are exhausted, suspend the subscription (or move it to the
+/// configured fallback plan). Hard declines retire
+/// the stored card and ask the user for a new one. `reason_code` is the
+/// `state_reason` left on the subscription while it waits.
+#[allow(clippy::too_many_arguments)]
+async fn handle_renewal_failure(
+    db: &DatabaseService,
+    email: &EmailService,
+    policy: &DunningPolicy,
+    sub: &Subscription,
+    token: &str,
+    class: FailureClass,
+    reason: &str,
+    reason_code: &str,
+    now: DateTime<Utc>,
+    notifications: &mut Vec<CreateNotificationDto>,
+) {
+    let attempts = sub.renewal_attempts + 1;
+    let reason = format!("{}: {}", class.as_str(), reason);
+
+    email.notify_user(db, &sub.user_id, EmailEvent::PaymentFailed {
+        plan: sub.plan_name.clone(),
+        amount: sub.total_price(),
+        currency: sub.currency.clone(),
+        reference: sub.id.clone(),
+    }).await;
+
+    match policy.next_action(attempts, class, now) {
+        DunningAction::RetryAt(next_at) => {
+            if let Err(e) = db.record_renewal_failure(&sub.id, attempts, Some(next_at), &reason, reason_code).await {
+                error!("Failed to record renewal failure for {}: {}", sub.id, e);
+            }
+            info!("Renewal retry {} for sub {} scheduled at {}", attempts + 1, sub.id, next_at);
+        }
+        DunningAction::Suspend => {
+            if let Err(e) = db.record_renewal_failure(&sub.id, attempts, None, &reason, reason_code).await {
+                error!("Failed to record renewal failure for {}: {}", sub.id, e);
+            }
+            info!("Sub {} exhausted {} renewal attempts", sub.id, attempts);
+            lapse_subscription(db, email, sub, true).await;
+        }
+        DunningAction::Downgrade { plan_id } => {
+            if let Err(e) = db.record_renewal_failure(&sub.id, attempts, None, &reason, reason_code).await {
+                error!("Failed to record renewal failure for {}: {}", sub.id, e);
+            }
+            info!("Sub {} exhausted {} renewal attempts; moving it to plan {}", sub.id, attempts, plan_id);
+            downgrade_to_fallback(db, email, sub, &plan_id, notifications).await;
+            return;
+        }
+        DunningAction::RequireNewCard => {
+            if let Err(e) = db.record_renewal_failure(&sub.id, attempts, None, &reason, reason_code).await {
+                error!("Failed to record renewal failure for {}: {}", sub.id, e);
+            }
+            // Without an active token the sub follows the manual-renewal path and
+            // is suspended when its grace period runs out.
+            if let Err(e) = db.mark_recurring_token_failed(token).await {
+                error!("Failed to retire recurring token for {}: {}", sub.id, e);
+            }
+            notifications.push(CreateNotificationDto::card_update(sub.user_id.clone(), sub.id.clone()));
+            info!("Hard decline for sub {}; asked user for a new card", sub.id);
+            return;
+        }
+    }
+
+    // Let the user know so they can pay manually before the next attempt
+    notifications.push(CreateNotificationDto::manual_renewal(sub.user_id.clone(), sub.id.clone()));
+}
+
+/// Suspends or downgrades an unpaid subscription according to its policy,
+/// either once its grace period is over or when dunning retries ran out.
+async fn lapse_subscription(db: &DatabaseService, email: &EmailService, sub: &Subscription, retries_exhausted: bool) {
+    let plan = sub.plan_name.clone();
+    match sub.suspension_policy {
+        SuspensionPolicy::Suspend { .. } => {
+            let reason = if retries_exhausted {
+                state_reason::SUSPENDED_AFTER_FAILED_RETRIES
+            } else {
+                state_reason::SUSPENDED_AFTER_GRACE
+            };
+            if let Err(e) = db.suspend_subscription(&sub.id, reason).await {
+                error!("Failed to suspend subscription {}: {}", sub.id, e);
+            } else {
+                info!("Suspended unpaid subscription: {}", sub.id);
+                email.notify_user(db, &sub.user_id, EmailEvent::SubscriptionSuspended { plan }).await;
+            }
+        }
+        SuspensionPolicy::Downgrade => {
+            let reason = if retries_exhausted {
+                state_reason::DOWNGRADED_AFTER_FAILED_RETRIES
+            } else {
+                state_reason::DOWNGRADED_AFTER_GRACE
+            };
+            if let Err(e) = db.downgrade_subscription(&sub.id, reason, None).await {
+                error!("Failed to downgrade subscription {}: {}", sub.id, e);
+            } else {
+                info!("Downgraded unpaid subscription: {}", sub.id);
+                email.notify_user(db, &sub.user_id, EmailEvent::SubscriptionDowngraded { plan }).await;
+            }
+        }
+    }
+}
+
+/// Moves a subscription whose retries ran out to the dunning fallback plan and
+/// tells the user. A fallback plan that no longer exists is logged and the
+/// subscription lapses under its own policy instead.
+async fn downgrade_to_fallback(
+    db: &DatabaseService,
+    email: &EmailService,
+    sub: &Subscription,
+    plan_id: &str,
+    notifications: &mut Vec<CreateNotificationDto>,
+) {
+    let fallback_plan = if plan_id == FREE_TIER_PLAN_ID {
+        "the free tier".to_string()
+    } else {
+        match db.get_plan(plan_id).await {
+            Some(plan) => format!("the {} plan", plan.name),
+            None => {
+                error!("Dunning fallback plan {} not found; lapsing subscription {} instead", plan_id, sub.id);
+                lapse_subscription(db, email, sub, true).await;
+                return;
+            }
+        }
+    };
+
+    if let Err(e) = db.downgrade_subscription(&sub.id, state_reason::DOWNGRADED_AFTER_FAILED_RETRIES, Some(plan_id)).await {
+        error!("Failed to downgrade subscription {}: {}", sub.id, e);
+        return;
+    }
+
+    email.notify_user(db, &sub.user_id, EmailEvent::SubscriptionDowngradedToFallback {
+        plan: sub.plan_name.clone(),
+        fallback_plan: fallback_plan.clone(),
+    }).await;
+    notifications.push(CreateNotificationDto::downgrade(sub.user_id.clone(), sub.id.clone(), &sub.plan_name, &fallback_plan));
+}
+
+/// Writes the in-app notifications a run collected in one batch.
+pub async fn flush_notifications(db: &DatabaseService, notifications: Vec<CreateNotificationDto>) {
+    if notifications.is_empty() {
+        return;
+    }
+    match db.create_notifications(notifications).await {
+        Ok(created) => info!("Created {} renewal notifications", created),
+        Err(e) => error!("Failed to create renewal notifications: {}", e),
+    }
+}
+
+/// Emails users whose subscription ends within `notification_days`, once per billing period.
+async fn send_renewal_reminders(db: &DatabaseService, email: &EmailService, notification_days: u32) {
+    let upcoming = match db.get_subscriptions_needing_renewal_reminder(notification_days).await {
+        Ok(list) => list,
+        Err(e) => {
+            warn!("Error fetching subscriptions for renewal reminders: {}", e);
+            return;
+        }
+    };
+
+    for sub in upcoming {
+        let Some(renewal_date) = sub.end_date else { continue };
+
+        email.notify_user(db, &sub.user_id, EmailEvent::UpcomingRenewal {
+            plan: sub.plan_name.clone(),
+            amount: sub.total_price(),
+            currency: sub.currency.clone(),
+            renewal_date,
+        }).await;
+
+        if let Err(e) = db.mark_renewal_reminder_sent(&sub.id).await {
+            error!("Failed to record renewal reminder for {}: {}", sub.id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::subscription::CreateSubscriptionDto;
+    use crate::models::user::CreateUserDto;
+    use crate::services::payment_events::PaymentEvents;
+
+    #[tokio::test]
+    #[ignore = "needs SurrealDB on 127.0.0.1:8000, and writes to it"]
+    async fn renewal_reminder_is_emailed_to_the_subscriber() {
+        let db = DatabaseService::new(PaymentEvents::new()).await.expect("SurrealDB should be running");
+        let email = EmailService::in_memory();
+        let address = format!("renewal+{}@example.invalid", uuid::Uuid::new_v4().simple());
+
+        let user = db.create_user(CreateUserDto {
+            email: address.clone(),
+            name: "Renewal Test".to_string(),
+        }).await.unwrap();
+        // A one-day period ends within the reminder window as soon as it starts
+        let subscription = db.create_subscription(CreateSubscriptionDto {
+            user_id: user.id.clone(),
+            plan_id: None,
+            plan_name: "Reminder Test".to_string(),
+            price: 10.0,
+            currency: "ZAR".to_string(),
+            payment_method: None,
+            grace_period_days: 0,
+            billing_period_days: 1,
+            billing_period_months: None,
+            suspension_policy: SuspensionPolicy::default(),
+            usage_pricing: None,
+            seat_count: 1,
+        }).await.unwrap();
+        db.activate_subscription(&subscription.id).await.unwrap();
+
+        send_renewal_reminders(&db, &email, 3).await;
+
+        let sent = email.sent_emails(Some(&address)).unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].event, "renewal_upcoming");
+        assert!(sent[0].subject.contains("Reminder Test"));
+    }
 }
@@ -0,0 +1,89 @@
+use std::sync::Arc;
+use chrono::Utc;
+use reqwest::Client;
+use tokio::time::Duration as TokioDuration;
+use tracing::{error, info, warn};
+use crate::models::job::retry_delay;
+use crate::models::webhook_endpoint::{WebhookDelivery, MAX_DELIVERY_ATTEMPTS};
+use crate::services::database::DatabaseService;
+use crate::services::shutdown::ShutdownSignal;
+use crate::services::event_sinks::{check_public_host, event_client, post_event};
+
+const POLL_INTERVAL_SECONDS: u64 = 5;
+const BATCH_SIZE: u32 = 50;
+
+/// Sends queued events to merchant webhook endpoints, signed with each
+/// endpoint's secret, retrying failed deliveries with backoff.
+pub async fn start_webhook_delivery_task(db: Arc<DatabaseService>, mut shutdown: ShutdownSignal) {
+    info!("Merchant webhook delivery started");
+    let client = event_client();
+
+    loop {
+        let due = match db.get_due_webhook_deliveries(BATCH_SIZE).await {
+            Ok(deliveries) => deliveries,
+            Err(e) => {
+                warn!("Error fetching due webhook deliveries: {}", e);
+                vec![]
+            }
+        };
+        for delivery in due {
+            deliver(&db, &client, delivery).await;
+        }
+
+        if shutdown.sleep(TokioDuration::from_secs(POLL_INTERVAL_SECONDS)).await {
+            info!("Webhook delivery stopped for shutdown");
+            return;
+        }
+    }
+}
+
+#[tracing::instrument(name = "webhook_delivery", skip_all, fields(delivery_id = %delivery.id, endpoint_id = %delivery.endpoint_id))]
+async fn deliver(db: &DatabaseService, client: &Client, delivery: WebhookDelivery) {
+    match db.claim_webhook_delivery(&delivery.id).await {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            warn!("Error claiming webhook delivery {}: {}", delivery.id, e);
+            return;
+        }
+    }
+    let attempt = delivery.attempts + 1;
+
+    let Some(endpoint) = db.get_webhook_endpoint(&delivery.endpoint_id).await else {
+        if let Err(e) = db.record_webhook_delivery(&delivery.id, None, Some("Endpoint removed"), None).await {
+            error!("Failed to record webhook delivery {}: {}", delivery.id, e);
+        }
+        return;
+    };
+    let Some(event) = db.get_domain_event(&delivery.event_id).await else {
+        if let Err(e) = db.record_webhook_delivery(&delivery.id, None, Some("Event not found"), None).await {
+            error!("Failed to record webhook delivery {}: {}", delivery.id, e);
+        }
+        return;
+    };
+
+    // Checked again on every attempt: the host's DNS may have changed since
+    // the endpoint was registered.
+    let (response_status, result) = match check_public_host(&endpoint.url).await {
+        Ok(()) => post_event(client, &endpoint.url, Some(endpoint.secret.as_bytes()), &event).await,
+        Err(e) => (None, Err(e)),
+    };
+    let recorded = match result {
+        Ok(()) => {
+            info!("Delivered {} event {} to endpoint {}", event.event_type, event.id, endpoint.id);
+            db.record_webhook_delivery(&delivery.id, response_status, None, None).await
+        }
+        Err(e) if attempt < MAX_DELIVERY_ATTEMPTS => {
+            let retry_at = Utc::now() + retry_delay(attempt);
+            warn!("Delivery {} failed on attempt {}, retrying at {}: {}", delivery.id, attempt, retry_at, e);
+            db.record_webhook_delivery(&delivery.id, response_status, Some(e.as_str()), Some(retry_at)).await
+        }
+        Err(e) => {
+            error!("Delivery {} failed after {} attempts: {}", delivery.id, attempt, e);
+            db.record_webhook_delivery(&delivery.id, response_status, Some(e.as_str()), None).await
+        }
+    };
+    if let Err(e) = recorded {
+        error!("Failed to record webhook delivery {}: {}", delivery.id, e);
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::Arc;
+use chrono::{DateTime, Datelike, Duration, Timelike, Utc, Weekday};
+use reqwest::Client;
+use tokio::time::{sleep, Duration as TokioDuration};
+use tracing::{info, warn};
+use crate::config::AppConfig;
+use crate::services::clock::Clock;
+use crate::services::database::DatabaseService;
+use crate::services::email::{EmailEvent, EmailService};
+use crate::services::operator_alerts::{has_operator_channels, notify_operators};
+
+const CHECK_INTERVAL_MINUTES: u64 = 15;
+
+/// Dead-man's switch for the gateway's notification URL: alerts operators
+/// once when no webhook from `gateway` has been processed for
+/// `WEBHOOK_SILENCE_ALERT_HOURS`, and again only after webhooks have resumed
+/// and stopped once more. Alerts wait for business hours, when quiet
+/// periods are unusual.
+pub async fn start_webhook_watchdog_task(
+    db: Arc<DatabaseService>,
+    config: Arc<AppConfig>,
+    email: Arc<EmailService>,
+    clock: Arc<dyn Clock>,
+    gateway: String,
+) {
+    if config.webhook_silence_alert_hours == 0 {
+        info!("WEBHOOK_SILENCE_ALERT_HOURS not set; webhook silence alerts disabled");
+        return;
+    }
+    if !has_operator_channels(&config) {
+        warn!("No OPERATOR_EMAILS or SLACK_WEBHOOK_URL set; webhook silence alerts disabled");
+        return;
+    }
+
+    tokio::spawn(async move {
+        let client = Client::new();
+        let threshold = Duration::hours(config.webhook_silence_alert_hours as i64);
+        // A fresh install has never had a webhook; measure from startup instead
+        let started_at = clock.now();
+        let mut alerted = false;
+
+        loop {
+            sleep(TokioDuration::from_secs(CHECK_INTERVAL_MINUTES * 60)).await;
+
+            let now = clock.now();
+            let last_processed_at = match db.last_webhook_processed_at(&gateway).await {
+                Ok(last) => last,
+                Err(e) => {
+                    warn!("Error reading last {} webhook time: {}", gateway, e);
+                    continue;
+                }
+            };
+
+            let silent_for = now - last_processed_at.unwrap_or(started_at);
+            if silent_for < threshold {
+                if alerted {
+                    info!("{} webhooks are arriving again", gateway);
+                    alerted = false;
+                }
+                continue;
+            }
+            if alerted || !in_business_hours(now, config.business_hours_utc) {
+                continue;
+            }
+
+            let silent_hours = silent_for.num_hours();
+            warn!("No {} webhook processed for {} hours", gateway, silent_hours);
+            let text = format!(
+                "*No {} webhooks for {} hours*\nLast processed: {}. Check the notification URL configured with the gateway.",
+                gateway,
+                silent_hours,
+                last_processed_at.map(|at| at.to_rfc3339()).unwrap_or_else(|| "never".to_string()),
+            );
+            let event = EmailEvent::WebhookSilence {
+                gateway: gateway.clone(),
+                last_processed_at,
+                silent_hours,
+            };
+            notify_operators(&config, &email, &client, &event, &text).await;
+            alerted = true;
+        }
+    });
+}
+
+/// Weekdays between the configured UTC hours.
+fn in_business_hours(now: DateTime<Utc>, (start, end): (u32, u32)) -> bool {
+    let weekday = !matches!(now.weekday(), Weekday::Sat | Weekday::Sun);
+    weekday && (start..end).contains(&now.hour())
+}
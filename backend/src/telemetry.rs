@@ -0,0 +1,42 @@
+use std::future::Future;
+use tracing_subscriber::EnvFilter;
+
+/// Header carrying the correlation ID on requests, responses and outgoing
+/// gateway calls.
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Installs the global subscriber. Logs are JSON lines by default so a
+/// payment can be followed by `request_id` / `merchant_transaction_id`;
+/// set `LOG_FORMAT=pretty` for human-readable output while developing.
+/// `RUST_LOG` filters as before (default `info`), and `log` records from
+/// actix are forwarded.
+pub fn init() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    if std::env::var("LOG_FORMAT").map(|f| f.eq_ignore_ascii_case("pretty")).unwrap_or(false) {
+        builder.init();
+    } else {
+        builder.json().flatten_event(true).with_current_span(true).with_span_list(false).init();
+    }
+}
+
+/// Correlation ID of the request or background job being handled, if any.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Runs `fut` with `id` as its correlation ID.
+pub async fn with_request_id<F: Future>(id: String, fut: F) -> F::Output {
+    REQUEST_ID.scope(id, fut).await
+}
+
+/// Tags the current request or job span with the payment it is about, so
+/// webhook, status-check and renewal logs for one payment can be joined.
+pub fn record_payment(merchant_transaction_id: &str) {
+    tracing::Span::current().record("merchant_transaction_id", merchant_transaction_id);
+}
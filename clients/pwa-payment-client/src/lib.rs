@@ -0,0 +1,960 @@
+//! Typed client for the PWA payment API.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), pwa_payment_client::Error> {
+//! let client = pwa_payment_client::Client::new("http://127.0.0.1:8080/api/v1");
+//! let plans = client.get_plans().await?;
+//! println!("{} plans (catalog v{})", plans.plans.len(), plans.version);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod models;
+
+use models::*;
+use hmac::{Hmac, Mac};
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug)]
+pub enum Error {
+    /// The request never produced a response (connection, TLS, timeout).
+    Transport(reqwest::Error),
+    /// The server answered with a non-success status.
+    Api { status: StatusCode, body: String },
+    /// The response body didn't match the expected shape.
+    Decode(serde_json::Error),
+    /// The request body couldn't be serialized for signing.
+    Encode(serde_json::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Transport(e) => write!(f, "transport error: {}", e),
+            Error::Api { status, body } => write!(f, "API error {}: {}", status, body),
+            Error::Decode(e) => write!(f, "decode error: {}", e),
+            Error::Encode(e) => write!(f, "encode error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    /// The per-field problems when the server refused the request body with 422.
+    pub fn validation(&self) -> Option<ValidationErrorResponse> {
+        match self {
+            Error::Api { status, body } if *status == StatusCode::UNPROCESSABLE_ENTITY => serde_json::from_str(body).ok(),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Transport(e)
+    }
+}
+
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    admin_token: Option<String>,
+    signing_key: Option<(String, String)>,
+    language: Option<String>,
+    impersonation_token: Option<String>,
+    api_key: Option<String>,
+}
+
+impl Client {
+    /// `base_url` includes the API prefix, e.g. `https://api.example.com/api/v1`.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self::with_http_client(reqwest::Client::new(), base_url)
+    }
+
+    pub fn with_http_client(http: reqwest::Client, base_url: impl Into<String>) -> Self {
+        Self {
+            http,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            admin_token: None,
+            signing_key: None,
+            language: None,
+            impersonation_token: None,
+            api_key: None,
+        }
+    }
+
+    /// Sets the `X-Admin-Token` sent on admin endpoints.
+    pub fn with_admin_token(mut self, token: impl Into<String>) -> Self {
+        self.admin_token = Some(token.into());
+        self
+    }
+
+    /// Signs refunds and recurring charges with HMAC (`X-Key-Id`, `X-Timestamp`,
+    /// `X-Signature`), required once the server has signing keys configured.
+    pub fn with_signing_key(mut self, key_id: impl Into<String>, secret: impl Into<String>) -> Self {
+        self.signing_key = Some((key_id.into(), secret.into()));
+        self
+    }
+
+    /// Sends `Accept-Language`, e.g. `af-ZA`, so errors come back in that
+    /// language for users who haven't chosen one on their profile.
+    pub fn with_language(mut self, language: impl Into<String>) -> Self {
+        self.language = Some(language.into());
+        self
+    }
+
+    /// Acts as the user of an impersonation session from
+    /// `admin_start_impersonation`. The server takes the user from the
+    /// session, so the user ids passed to methods must match it.
+    pub fn with_impersonation_token(mut self, token: impl Into<String>) -> Self {
+        self.impersonation_token = Some(token.into());
+        self
+    }
+
+    /// Sends `X-Api-Key` on every request, for merchant backends calling
+    /// server-to-server. Keys with the `admin` scope also work for admin
+    /// endpoints in place of `with_admin_token`.
+    pub fn with_api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = Some(key.into());
+        self
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let builder = self.http.request(method, format!("{}{}", self.base_url, path));
+        let builder = match &self.language {
+            Some(language) => builder.header("Accept-Language", language),
+            None => builder,
+        };
+        let builder = match &self.api_key {
+            Some(key) => builder.header("X-Api-Key", key),
+            None => builder,
+        };
+        match &self.impersonation_token {
+            Some(token) => builder.header("X-Impersonation-Token", token),
+            None => builder,
+        }
+    }
+
+    fn admin_request(&self, method: Method, path: &str) -> RequestBuilder {
+        let builder = self.request(method, path);
+        match &self.admin_token {
+            Some(token) => builder.header("X-Admin-Token", token),
+            None => builder,
+        }
+    }
+
+    /// Attaches a JSON body and, when a signing key is set, the signature headers.
+    fn signed_json<B: Serialize>(&self, builder: RequestBuilder, method: &Method, path: &str, body: &B) -> Result<RequestBuilder, Error> {
+        let bytes = serde_json::to_vec(body).map_err(Error::Encode)?;
+        let builder = builder.header("Content-Type", "application/json");
+
+        let Some((key_id, secret)) = &self.signing_key else {
+            return Ok(builder.body(bytes));
+        };
+
+        // The server signs the full request path, including the API prefix
+        let path_and_query = reqwest::Url::parse(&format!("{}{}", self.base_url, path))
+            .map(|url| match url.query() {
+                Some(query) => format!("{}?{}", url.path(), query),
+                None => url.path().to_string(),
+            })
+            .unwrap_or_else(|_| path.to_string());
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        let canonical = format!(
+            "{}\n{}\n{}\n{}",
+            timestamp,
+            method.as_str(),
+            path_and_query,
+            hex::encode(Sha256::digest(&bytes))
+        );
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+        mac.update(canonical.as_bytes());
+        let signature = hex::encode(mac.finalize().into_bytes());
+
+        Ok(builder
+            .header("X-Key-Id", key_id)
+            .header("X-Timestamp", timestamp.to_string())
+            .header("X-Signature", signature)
+            .body(bytes))
+    }
+
+    async fn send<T: DeserializeOwned>(&self, builder: RequestBuilder) -> Result<T, Error> {
+        let response = builder.send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+
+        if !status.is_success() {
+            return Err(Error::Api { status, body });
+        }
+        serde_json::from_str(&body).map_err(Error::Decode)
+    }
+
+    async fn send_no_content(&self, builder: RequestBuilder) -> Result<(), Error> {
+        let response = builder.send().await?;
+        let status = response.status();
+
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(Error::Api { status, body });
+        }
+        Ok(())
+    }
+
+    // Users
+
+    pub async fn register_user(&self, req: &RegisterUserRequest) -> Result<UserResponse, Error> {
+        self.send(self.request(Method::POST, "/users/register").json(req)).await
+    }
+
+    pub async fn get_user(&self, user_id: &str) -> Result<UserResponse, Error> {
+        self.send(self.request(Method::GET, &format!("/users/{}", user_id))).await
+    }
+
+    pub async fn get_user_by_email(&self, email: &str) -> Result<UserResponse, Error> {
+        self.send(self.request(Method::GET, &format!("/users/email/{}", email))).await
+    }
+
+    pub async fn update_user(&self, user_id: &str, req: &UpdateUserRequest) -> Result<UserResponse, Error> {
+        let builder = self.request(Method::PATCH, &format!("/users/{}", user_id))
+            .header("X-User-Id", user_id)
+            .json(req);
+        self.send(builder).await
+    }
+
+    /// Deletes the account and erases its personal data. Fails with 409
+    /// while the user has open subscriptions.
+    pub async fn delete_user(&self, user_id: &str) -> Result<(), Error> {
+        let builder = self.request(Method::DELETE, &format!("/users/{}", user_id))
+            .header("X-User-Id", user_id);
+        self.send_no_content(builder).await
+    }
+
+    /// Everything stored about the user, as raw JSON.
+    pub async fn export_user_data(&self, user_id: &str) -> Result<serde_json::Value, Error> {
+        let builder = self.request(Method::GET, &format!("/users/{}/export", user_id))
+            .header("X-User-Id", user_id);
+        self.send(builder).await
+    }
+
+    /// The user's credit balances and recent wallet ledger.
+    pub async fn get_wallet(&self, user_id: &str) -> Result<Wallet, Error> {
+        let builder = self.request(Method::GET, &format!("/users/{}/wallet", user_id))
+            .header("X-User-Id", user_id);
+        self.send(builder).await
+    }
+
+    /// A page of the user's payments.
+    pub async fn get_payment_history(&self, user_id: &str, query: &PaymentHistoryQuery) -> Result<PaginatedResponse<Payment>, Error> {
+        let builder = self.request(Method::GET, &format!("/users/{}/payments", user_id))
+            .header("X-User-Id", user_id)
+            .query(query);
+        self.send(builder).await
+    }
+
+    // Plans
+
+    pub async fn get_plans(&self) -> Result<PlanCatalog, Error> {
+        self.send(self.request(Method::GET, "/plans")).await
+    }
+
+    // Entitlements
+
+    pub async fn get_user_features(&self, user_id: &str) -> Result<UserEntitlements, Error> {
+        self.send(self.request(Method::GET, &format!("/entitlements/{}/features", user_id))).await
+    }
+
+    /// Fails with a 403 API error when the user's plans don't include the
+    /// feature, or when `usage` is over its limit.
+    pub async fn check_user_feature(&self, user_id: &str, feature_key: &str, usage: Option<u64>) -> Result<FeatureEntitlement, Error> {
+        let mut builder = self.request(Method::GET, &format!("/entitlements/{}/features/{}", user_id, feature_key));
+        if let Some(usage) = usage {
+            builder = builder.query(&[("usage", usage)]);
+        }
+        self.send(builder).await
+    }
+
+    // Subscriptions
+
+    pub async fn create_subscription(&self, req: &CreateSubscriptionRequest) -> Result<SubscriptionResponse, Error> {
+        self.send(self.request(Method::POST, "/subscriptions/create").json(req)).await
+    }
+
+    pub async fn get_subscription(&self, subscription_id: &str) -> Result<SubscriptionResponse, Error> {
+        self.send(self.request(Method::GET, &format!("/subscriptions/{}", subscription_id))).await
+    }
+
+    /// `user_id`'s next renewals, soonest first; `count` defaults to 3 (at most 12).
+    pub async fn get_upcoming_invoices(&self, user_id: &str, subscription_id: &str, count: Option<usize>) -> Result<Vec<UpcomingInvoice>, Error> {
+        let mut builder = self.request(Method::GET, &format!("/subscriptions/{}/upcoming-invoices", subscription_id))
+            .header("X-User-Id", user_id);
+        if let Some(count) = count {
+            builder = builder.query(&[("count", count)]);
+        }
+        self.send(builder).await
+    }
+
+    pub async fn renew_subscription(&self, subscription_id: &str) -> Result<serde_json::Value, Error> {
+        self.send(self.request(Method::POST, &format!("/subscriptions/{}/renew", subscription_id))).await
+    }
+
+    /// Cancels `user_id`'s subscription, by default at the end of the paid period.
+    pub async fn cancel_subscription(&self, user_id: &str, subscription_id: &str, req: &CancelSubscriptionRequest) -> Result<SubscriptionResponse, Error> {
+        let builder = self.request(Method::POST, &format!("/subscriptions/{}/cancel", subscription_id))
+            .header("X-User-Id", user_id)
+            .json(req);
+        self.send(builder).await
+    }
+
+    /// Undoes a cancellation scheduled for the end of the paid period.
+    pub async fn reactivate_subscription(&self, user_id: &str, subscription_id: &str) -> Result<SubscriptionResponse, Error> {
+        let builder = self.request(Method::POST, &format!("/subscriptions/{}/reactivate", subscription_id))
+            .header("X-User-Id", user_id);
+        self.send(builder).await
+    }
+
+    /// Starts a new period for an expired or cancelled subscription. The
+    /// saved card is charged when there is one; otherwise the response
+    /// carries a checkout to complete.
+    pub async fn restart_subscription(&self, user_id: &str, subscription_id: &str, req: &RestartSubscriptionRequest) -> Result<InitiatePaymentResponse, Error> {
+        let builder = self.request(Method::POST, &format!("/subscriptions/{}/restart", subscription_id))
+            .header("X-User-Id", user_id)
+            .json(req);
+        self.send(builder).await
+    }
+
+    /// Puts `user_id`'s active subscription on hold.
+    pub async fn pause_subscription(&self, user_id: &str, subscription_id: &str) -> Result<SubscriptionResponse, Error> {
+        let builder = self.request(Method::POST, &format!("/subscriptions/{}/pause", subscription_id))
+            .header("X-User-Id", user_id);
+        self.send(builder).await
+    }
+
+    /// Resumes a paused subscription; its end date moves back by the time paused.
+    pub async fn resume_subscription(&self, user_id: &str, subscription_id: &str) -> Result<SubscriptionResponse, Error> {
+        let builder = self.request(Method::POST, &format!("/subscriptions/{}/resume", subscription_id))
+            .header("X-User-Id", user_id);
+        self.send(builder).await
+    }
+
+    /// Prorated cost or credit of moving to another plan now; changes nothing.
+    pub async fn preview_plan_change(&self, user_id: &str, subscription_id: &str, req: &ChangePlanRequest) -> Result<PlanChangePreview, Error> {
+        let builder = self.request(Method::POST, &format!("/subscriptions/{}/change-plan/preview", subscription_id))
+            .header("X-User-Id", user_id)
+            .json(req);
+        self.send(builder).await
+    }
+
+    /// Switches plan. Upgrades charge the saved card first; a declined charge
+    /// is an error and leaves the plan unchanged. Changes at the next renewal
+    /// come back `Scheduled`.
+    pub async fn change_plan(&self, user_id: &str, subscription_id: &str, req: &ChangePlanRequest) -> Result<PlanChangeResponse, Error> {
+        let builder = self.request(Method::POST, &format!("/subscriptions/{}/change-plan", subscription_id))
+            .header("X-User-Id", user_id)
+            .json(req);
+        self.send(builder).await
+    }
+
+    /// The plan change waiting for the next renewal; 404 when none is.
+    pub async fn get_scheduled_plan_change(&self, user_id: &str, subscription_id: &str) -> Result<PlanChange, Error> {
+        let builder = self.request(Method::GET, &format!("/subscriptions/{}/change-plan", subscription_id))
+            .header("X-User-Id", user_id);
+        self.send(builder).await
+    }
+
+    /// Withdraws the plan change waiting for the next renewal.
+    pub async fn cancel_scheduled_plan_change(&self, user_id: &str, subscription_id: &str) -> Result<PlanChange, Error> {
+        let builder = self.request(Method::DELETE, &format!("/subscriptions/{}/change-plan", subscription_id))
+            .header("X-User-Id", user_id);
+        self.send(builder).await
+    }
+
+    /// Where the subscription's installments stand; 404 when it isn't paid
+    /// in installments.
+    pub async fn get_installments(&self, user_id: &str, subscription_id: &str) -> Result<InstallmentSchedule, Error> {
+        let builder = self.request(Method::GET, &format!("/subscriptions/{}/installments", subscription_id))
+            .header("X-User-Id", user_id);
+        self.send(builder).await
+    }
+
+    /// Reports usage on a metered subscription; needs `with_api_key`.
+    /// Resending with the same idempotency key returns the original record.
+    pub async fn report_usage(&self, subscription_id: &str, req: &ReportUsageRequest) -> Result<UsageRecord, Error> {
+        let path = format!("/subscriptions/{}/usage", subscription_id);
+        let builder = self.signed_json(self.request(Method::POST, &path), &Method::POST, &path, req)?;
+        self.send(builder).await
+    }
+
+    /// Adds someone to a free seat of `user_id`'s subscription.
+    pub async fn invite_member(&self, user_id: &str, subscription_id: &str, req: &InviteMemberRequest) -> Result<SubscriptionMember, Error> {
+        let builder = self.request(Method::POST, &format!("/subscriptions/{}/members", subscription_id))
+            .header("X-User-Id", user_id)
+            .json(req);
+        self.send(builder).await
+    }
+
+    pub async fn list_members(&self, user_id: &str, subscription_id: &str) -> Result<SubscriptionMembers, Error> {
+        let builder = self.request(Method::GET, &format!("/subscriptions/{}/members", subscription_id))
+            .header("X-User-Id", user_id);
+        self.send(builder).await
+    }
+
+    pub async fn remove_member(&self, user_id: &str, subscription_id: &str, member_id: &str) -> Result<(), Error> {
+        let builder = self.request(Method::DELETE, &format!("/subscriptions/{}/members/{}", subscription_id, member_id))
+            .header("X-User-Id", user_id);
+        self.send_no_content(builder).await
+    }
+
+    /// Changes the seat count, billed from the next renewal.
+    pub async fn update_seats(&self, user_id: &str, subscription_id: &str, req: &UpdateSeatsRequest) -> Result<SubscriptionResponse, Error> {
+        let builder = self.request(Method::PUT, &format!("/subscriptions/{}/seats", subscription_id))
+            .header("X-User-Id", user_id)
+            .json(req);
+        self.send(builder).await
+    }
+
+    /// Skips the next billing cycle of `user_id`'s subscription, if the plan
+    /// allows another skip this year.
+    pub async fn skip_next_renewal(&self, user_id: &str, req: &SkipRenewalRequest) -> Result<SubscriptionResponse, Error> {
+        let builder = self.request(Method::POST, "/me/subscription/skip-next")
+            .header("X-User-Id", user_id)
+            .json(req);
+        self.send(builder).await
+    }
+
+    pub async fn cancel_skip_next_renewal(&self, user_id: &str, req: &SkipRenewalRequest) -> Result<SubscriptionResponse, Error> {
+        let builder = self.request(Method::DELETE, "/me/subscription/skip-next")
+            .header("X-User-Id", user_id)
+            .json(req);
+        self.send(builder).await
+    }
+
+    /// Starts the catch-up payment that brings a subscription suspended for
+    /// non-payment back; it's active again once the checkout is paid.
+    pub async fn reactivate_after_suspension(&self, user_id: &str, req: &ReactivateSubscriptionRequest) -> Result<InitiatePaymentResponse, Error> {
+        let builder = self.request(Method::POST, "/me/subscription/reactivate-after-suspension")
+            .header("X-User-Id", user_id)
+            .json(req);
+        self.send(builder).await
+    }
+
+    /// Reports one of `user_id`'s payments as unrecognised, opening a support ticket.
+    pub async fn report_unrecognized_charge(&self, user_id: &str, merchant_transaction_id: &str, req: &ReportChargeRequest) -> Result<ChargeReport, Error> {
+        let builder = self.request(Method::POST, &format!("/me/payments/{}/report", merchant_transaction_id))
+            .header("X-User-Id", user_id)
+            .json(req);
+        self.send(builder).await
+    }
+
+    /// Sets the address and VAT number printed on `user_id`'s future invoices.
+    pub async fn update_billing_details(&self, user_id: &str, req: &BillingDetailsRequest) -> Result<UserResponse, Error> {
+        let builder = self.request(Method::PUT, "/me/billing-details")
+            .header("X-User-Id", user_id)
+            .json(req);
+        self.send(builder).await
+    }
+
+    /// Registers a URL for `user_id`'s events. Keep the returned secret to
+    /// check each delivery's `X-Event-Signature`.
+    pub async fn create_webhook_endpoint(&self, user_id: &str, req: &CreateWebhookEndpointRequest) -> Result<WebhookEndpointCreated, Error> {
+        let builder = self.request(Method::POST, "/me/webhook-endpoints")
+            .header("X-User-Id", user_id)
+            .json(req);
+        self.send(builder).await
+    }
+
+    pub async fn list_webhook_endpoints(&self, user_id: &str) -> Result<Vec<WebhookEndpoint>, Error> {
+        let builder = self.request(Method::GET, "/me/webhook-endpoints")
+            .header("X-User-Id", user_id);
+        self.send(builder).await
+    }
+
+    pub async fn delete_webhook_endpoint(&self, user_id: &str, endpoint_id: &str) -> Result<(), Error> {
+        let builder = self.request(Method::DELETE, &format!("/me/webhook-endpoints/{}", endpoint_id))
+            .header("X-User-Id", user_id);
+        self.send_no_content(builder).await
+    }
+
+    /// The endpoint's deliveries, newest first.
+    pub async fn list_webhook_deliveries(&self, user_id: &str, endpoint_id: &str, limit: Option<u32>) -> Result<Vec<WebhookDelivery>, Error> {
+        let mut builder = self.request(Method::GET, &format!("/me/webhook-endpoints/{}/deliveries", endpoint_id))
+            .header("X-User-Id", user_id);
+        if let Some(limit) = limit {
+            builder = builder.query(&[("limit", limit)]);
+        }
+        self.send(builder).await
+    }
+
+    // Payments
+
+    /// Entity id, script URL and brands for the embedded checkout; a 404 API
+    /// error when the gateway only has a hosted payment page.
+    pub async fn get_checkout_config(&self) -> Result<CheckoutConfig, Error> {
+        self.send(self.request(Method::GET, "/payments/checkout-config")).await
+    }
+
+    /// Payment methods the shopper can use; unavailable ones carry a reason.
+    pub async fn get_payment_methods(&self, query: &PaymentMethodQuery) -> Result<Vec<MethodAvailability>, Error> {
+        self.send(self.request(Method::GET, "/payments/methods").query(query)).await
+    }
+
+    pub async fn validate_payment(&self, req: &InitiatePaymentRequest) -> Result<PaymentValidation, Error> {
+        self.send(self.request(Method::POST, "/payments/validate").json(req)).await
+    }
+
+    pub async fn initiate_payment(&self, req: &InitiatePaymentRequest) -> Result<InitiatePaymentResponse, Error> {
+        self.send(self.request(Method::POST, "/payments/initiate").json(req)).await
+    }
+
+    /// The payment's status; `balance` (see `SubscriptionBalance`) shows what
+    /// is left to pay while its subscription is paid for in parts.
+    pub async fn get_payment_status(&self, merchant_transaction_id: &str) -> Result<serde_json::Value, Error> {
+        self.send(self.request(Method::GET, &format!("/payments/status/{}", merchant_transaction_id))).await
+    }
+
+    /// Blocks until the payment's status changes or `timeout_seconds` (at
+    /// most 60) elapse; check `timed_out` on the result. A payment moving to
+    /// `AwaitingAuthentication` returns early with its `authentication_url`.
+    pub async fn wait_for_payment(&self, merchant_transaction_id: &str, timeout_seconds: u64) -> Result<PaymentWaitResponse, Error> {
+        let builder = self.request(Method::GET, &format!("/payments/{}/wait", merchant_transaction_id))
+            .query(&[("timeout", format!("{}s", timeout_seconds))])
+            .timeout(std::time::Duration::from_secs(timeout_seconds + 10));
+        self.send(builder).await
+    }
+
+    /// QR code for a pending ScanToPay checkout, to render inline while polling.
+    pub async fn get_payment_qr(&self, merchant_transaction_id: &str, format: QrFormat) -> Result<PaymentQrResponse, Error> {
+        let builder = self.request(Method::GET, &format!("/payments/qr/{}", merchant_transaction_id))
+            .query(&[("format", format)]);
+        self.send(builder).await
+    }
+
+    pub async fn charge_recurring(&self, req: &RecurringChargeRequest) -> Result<serde_json::Value, Error> {
+        let path = "/payments/charge-recurring";
+        let builder = self.signed_json(self.request(Method::POST, path), &Method::POST, path, req)?;
+        self.send(builder).await
+    }
+
+    /// Starts a checkout that replaces the user's saved card without a charge.
+    pub async fn start_card_update(&self, req: &CardUpdateRequest) -> Result<CardUpdateResponse, Error> {
+        self.send(self.request(Method::POST, "/payments/card-update").json(req)).await
+    }
+
+    pub async fn get_card_update(&self, merchant_transaction_id: &str) -> Result<serde_json::Value, Error> {
+        self.send(self.request(Method::GET, &format!("/payments/card-update/{}", merchant_transaction_id))).await
+    }
+
+    pub async fn refund_payment(&self, payment_id: &str, req: &RefundRequest) -> Result<RefundResponse, Error> {
+        let path = format!("/payments/{}/refund", payment_id);
+        let builder = self.signed_json(self.admin_request(Method::POST, &path), &Method::POST, &path, req)?;
+        self.send(builder).await
+    }
+
+    /// Captures a payment that was only authorised at signup and activates
+    /// its subscription.
+    pub async fn capture_payment(&self, payment_id: &str) -> Result<serde_json::Value, Error> {
+        let path = format!("/payments/{}/capture", payment_id);
+        let builder = self.signed_json(self.admin_request(Method::POST, &path), &Method::POST, &path, &serde_json::json!({}))?;
+        self.send(builder).await
+    }
+
+    /// Releases a payment that was only authorised at signup.
+    pub async fn void_payment(&self, payment_id: &str) -> Result<serde_json::Value, Error> {
+        let path = format!("/payments/{}/void", payment_id);
+        let builder = self.signed_json(self.admin_request(Method::POST, &path), &Method::POST, &path, &serde_json::json!({}))?;
+        self.send(builder).await
+    }
+
+    /// Records a payment received outside the gateways and extends the
+    /// subscription it pays for. Returns the payment and updated subscription.
+    pub async fn admin_record_manual_payment(&self, req: &ManualPaymentRequest) -> Result<serde_json::Value, Error> {
+        let path = "/admin/payments/manual";
+        let builder = self.signed_json(self.admin_request(Method::POST, path), &Method::POST, path, req)?;
+        self.send(builder).await
+    }
+
+    /// Uploads a PDF, PNG or JPEG (at most 5 MiB). Pass `payment` as the owner
+    /// with a merchant transaction id to link it now, or leave both out and
+    /// reference the id later, e.g. as a manual payment's `proof_attachment`.
+    pub async fn admin_upload_attachment(
+        &self,
+        filename: &str,
+        content_type: &str,
+        bytes: Vec<u8>,
+        owner: Option<(&str, &str)>,
+    ) -> Result<AttachmentResponse, Error> {
+        let mut query = vec![("filename", filename)];
+        if let Some((owner_type, owner_id)) = owner {
+            query.push(("owner_type", owner_type));
+            query.push(("owner_id", owner_id));
+        }
+        let builder = self.admin_request(Method::POST, "/admin/attachments")
+            .query(&query)
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .body(bytes);
+        self.send(builder).await
+    }
+
+    pub async fn admin_get_attachment(&self, attachment_id: &str) -> Result<AttachmentResponse, Error> {
+        self.send(self.admin_request(Method::GET, &format!("/admin/attachments/{}", attachment_id))).await
+    }
+
+    pub async fn admin_list_payment_attachments(&self, merchant_transaction_id: &str) -> Result<Vec<AttachmentResponse>, Error> {
+        self.send(self.admin_request(Method::GET, &format!("/admin/payments/{}/attachments", merchant_transaction_id))).await
+    }
+
+    pub async fn get_payment_refunds(&self, payment_id: &str) -> Result<Vec<serde_json::Value>, Error> {
+        self.send(self.admin_request(Method::GET, &format!("/payments/{}/refunds", payment_id))).await
+    }
+
+    // Notifications
+
+    /// A page of the user's notifications, newest first.
+    pub async fn get_notifications(&self, user_id: &str, query: &NotificationsQuery) -> Result<PaginatedResponse<NotificationResponse>, Error> {
+        let builder = self.request(Method::GET, &format!("/notifications/user/{}", user_id))
+            .query(query);
+        self.send(builder).await
+    }
+
+    pub async fn get_unread_count(&self, user_id: &str) -> Result<UnreadCountResponse, Error> {
+        let builder = self.request(Method::GET, "/notifications/unread-count")
+            .header("X-User-Id", user_id);
+        self.send(builder).await
+    }
+
+    pub async fn acknowledge_all_notifications(&self, user_id: &str) -> Result<AcknowledgeAllResponse, Error> {
+        let builder = self.request(Method::POST, "/notifications/acknowledge-all")
+            .header("X-User-Id", user_id);
+        self.send(builder).await
+    }
+
+    pub async fn acknowledge_notification(&self, notification_id: &str) -> Result<MessageResponse, Error> {
+        self.send(self.request(Method::POST, &format!("/notifications/{}/acknowledge", notification_id))).await
+    }
+
+    pub async fn get_notification_preferences(&self, user_id: &str) -> Result<NotificationPreferences, Error> {
+        let builder = self.request(Method::GET, "/notifications/preferences")
+            .header("X-User-Id", user_id);
+        self.send(builder).await
+    }
+
+    pub async fn update_notification_preferences(&self, user_id: &str, req: &UpdateNotificationPreferencesRequest) -> Result<NotificationPreferences, Error> {
+        let builder = self.request(Method::PUT, "/notifications/preferences")
+            .header("X-User-Id", user_id)
+            .json(req);
+        self.send(builder).await
+    }
+
+    pub async fn create_test_notification(&self, req: &TestNotificationRequest) -> Result<MessageResponse, Error> {
+        self.send(self.request(Method::POST, "/notifications/test").json(req)).await
+    }
+
+    // Webhook administration
+
+    pub async fn list_webhook_events(&self, outcome: Option<&str>, limit: Option<u32>) -> Result<Vec<serde_json::Value>, Error> {
+        let mut builder = self.admin_request(Method::GET, "/webhooks/events");
+        if let Some(outcome) = outcome {
+            builder = builder.query(&[("outcome", outcome)]);
+        }
+        if let Some(limit) = limit {
+            builder = builder.query(&[("limit", limit)]);
+        }
+        self.send(builder).await
+    }
+
+    pub async fn replay_webhook_event(&self, event_id: &str) -> Result<serde_json::Value, Error> {
+        self.send(self.admin_request(Method::POST, &format!("/webhooks/events/{}/replay", event_id))).await
+    }
+
+    /// Peach webhook counts per result code category, with recently seen
+    /// codes the server doesn't handle.
+    pub async fn get_webhook_result_codes(&self, limit: Option<u32>) -> Result<WebhookCodeMetrics, Error> {
+        let mut builder = self.admin_request(Method::GET, "/webhooks/result-codes");
+        if let Some(limit) = limit {
+            builder = builder.query(&[("limit", limit)]);
+        }
+        self.send(builder).await
+    }
+
+    // Plan administration
+
+    pub async fn admin_list_plans(&self) -> Result<Vec<Plan>, Error> {
+        self.send(self.admin_request(Method::GET, "/admin/plans")).await
+    }
+
+    pub async fn admin_create_plan(&self, req: &CreatePlanRequest) -> Result<Plan, Error> {
+        self.send(self.admin_request(Method::POST, "/admin/plans").json(req)).await
+    }
+
+    pub async fn admin_update_plan(&self, plan_id: &str, req: &UpdatePlanRequest) -> Result<Plan, Error> {
+        self.send(self.admin_request(Method::PUT, &format!("/admin/plans/{}", plan_id)).json(req)).await
+    }
+
+    pub async fn admin_delete_plan(&self, plan_id: &str) -> Result<(), Error> {
+        self.send_no_content(self.admin_request(Method::DELETE, &format!("/admin/plans/{}", plan_id))).await
+    }
+
+    pub async fn admin_list_plan_features(&self, plan_id: &str) -> Result<Vec<PlanFeature>, Error> {
+        self.send(self.admin_request(Method::GET, &format!("/admin/plans/{}/features", plan_id))).await
+    }
+
+    pub async fn admin_set_plan_feature(&self, plan_id: &str, feature_key: &str, req: &SetPlanFeatureRequest) -> Result<PlanFeature, Error> {
+        self.send(self.admin_request(Method::PUT, &format!("/admin/plans/{}/features/{}", plan_id, feature_key)).json(req)).await
+    }
+
+    pub async fn admin_delete_plan_feature(&self, plan_id: &str, feature_key: &str) -> Result<(), Error> {
+        self.send_no_content(self.admin_request(Method::DELETE, &format!("/admin/plans/{}/features/{}", plan_id, feature_key))).await
+    }
+
+    pub async fn admin_list_payment_method_rules(&self) -> Result<Vec<PaymentMethodRule>, Error> {
+        self.send(self.admin_request(Method::GET, "/admin/payment-methods/rules")).await
+    }
+
+    /// `region` is a country code, or `*` for regions without their own rule.
+    pub async fn admin_set_payment_method_rule(&self, method: &PaymentMethod, region: &str, req: &SetPaymentMethodRuleRequest) -> Result<PaymentMethodRule, Error> {
+        self.send(self.admin_request(Method::PUT, &format!("/admin/payment-methods/{:?}/regions/{}", method, region)).json(req)).await
+    }
+
+    pub async fn admin_delete_payment_method_rule(&self, method: &PaymentMethod, region: &str) -> Result<(), Error> {
+        self.send_no_content(self.admin_request(Method::DELETE, &format!("/admin/payment-methods/{:?}/regions/{}", method, region))).await
+    }
+
+    // Test outbox (EMAIL_PROVIDER=memory)
+
+    pub async fn admin_sent_emails(&self, to_address: Option<&str>) -> Result<Vec<SentEmail>, Error> {
+        let mut builder = self.admin_request(Method::GET, "/admin/outbox/emails");
+        if let Some(to) = to_address {
+            builder = builder.query(&[("to", to)]);
+        }
+        self.send(builder).await
+    }
+
+    pub async fn admin_clear_sent_emails(&self) -> Result<(), Error> {
+        self.send_no_content(self.admin_request(Method::DELETE, "/admin/outbox/emails")).await
+    }
+
+    // Token migrations
+
+    /// Flags every active card for re-authorisation and prompts their users.
+    pub async fn admin_start_token_migration(&self, req: &CreateTokenMigrationRequest) -> Result<TokenMigrationProgress, Error> {
+        self.send(self.admin_request(Method::POST, "/admin/token-migrations").json(req)).await
+    }
+
+    pub async fn admin_list_token_migrations(&self) -> Result<Vec<TokenMigrationProgress>, Error> {
+        self.send(self.admin_request(Method::GET, "/admin/token-migrations")).await
+    }
+
+    pub async fn admin_get_token_migration(&self, migration_id: &str) -> Result<TokenMigrationProgress, Error> {
+        self.send(self.admin_request(Method::GET, &format!("/admin/token-migrations/{}", migration_id))).await
+    }
+
+    // Bulk operations
+
+    /// Lists the subscriptions a bulk change would touch; nothing changes
+    /// until `admin_confirm_bulk_operation`.
+    pub async fn admin_preview_bulk_operation(&self, req: &CreateBulkOperationRequest) -> Result<BulkOperation, Error> {
+        self.send(self.admin_request(Method::POST, "/admin/bulk").json(req)).await
+    }
+
+    /// Starts a previewed operation; poll `admin_get_bulk_operation` for progress.
+    pub async fn admin_confirm_bulk_operation(&self, operation_id: &str) -> Result<BulkOperation, Error> {
+        self.send(self.admin_request(Method::POST, &format!("/admin/bulk/{}/confirm", operation_id))).await
+    }
+
+    pub async fn admin_get_bulk_operation(&self, operation_id: &str) -> Result<BulkOperation, Error> {
+        self.send(self.admin_request(Method::GET, &format!("/admin/bulk/{}", operation_id))).await
+    }
+
+    // Audit log
+
+    /// A page of changes to users, subscriptions and payments, newest first.
+    pub async fn admin_list_audit_log(&self, query: &AuditLogQuery) -> Result<PaginatedResponse<AuditEntry>, Error> {
+        self.send(self.admin_request(Method::GET, "/admin/audit-log").query(query)).await
+    }
+
+    // Impersonation
+
+    /// Opens a short-lived session to act as the user; pass the returned
+    /// token to `with_impersonation_token`.
+    pub async fn admin_start_impersonation(&self, user_id: &str, req: &StartImpersonationRequest) -> Result<ImpersonationStarted, Error> {
+        self.send(self.admin_request(Method::POST, &format!("/admin/impersonate/{}", user_id)).json(req)).await
+    }
+
+    pub async fn admin_end_impersonation(&self, session_id: &str) -> Result<(), Error> {
+        self.send_no_content(self.admin_request(Method::DELETE, &format!("/admin/impersonate/sessions/{}", session_id))).await
+    }
+
+    // API keys
+
+    /// The key set with `with_api_key`, to check it and its scopes.
+    pub async fn get_current_api_key(&self) -> Result<ApiKey, Error> {
+        self.send(self.request(Method::GET, "/api-keys/current")).await
+    }
+
+    pub async fn admin_list_api_keys(&self) -> Result<Vec<ApiKey>, Error> {
+        self.send(self.admin_request(Method::GET, "/admin/api-keys")).await
+    }
+
+    /// The secret is only in this response.
+    pub async fn admin_create_api_key(&self, req: &CreateApiKeyRequest) -> Result<ApiKeyCreated, Error> {
+        self.send(self.admin_request(Method::POST, "/admin/api-keys").json(req)).await
+    }
+
+    /// Issues a new secret; the old one keeps working for 24 hours.
+    pub async fn admin_rotate_api_key(&self, key_id: &str) -> Result<ApiKeyCreated, Error> {
+        self.send(self.admin_request(Method::POST, &format!("/admin/api-keys/{}/rotate", key_id))).await
+    }
+
+    pub async fn admin_revoke_api_key(&self, key_id: &str) -> Result<(), Error> {
+        self.send_no_content(self.admin_request(Method::DELETE, &format!("/admin/api-keys/{}", key_id))).await
+    }
+
+    // Tenants
+
+    pub async fn admin_list_tenants(&self) -> Result<Vec<Tenant>, Error> {
+        self.send(self.admin_request(Method::GET, "/admin/tenants")).await
+    }
+
+    pub async fn admin_create_tenant(&self, req: &CreateTenantRequest) -> Result<Tenant, Error> {
+        self.send(self.admin_request(Method::POST, "/admin/tenants").json(req)).await
+    }
+
+    pub async fn admin_get_tenant(&self, tenant_id: &str) -> Result<Tenant, Error> {
+        self.send(self.admin_request(Method::GET, &format!("/admin/tenants/{}", tenant_id))).await
+    }
+
+    /// Only the fields set are changed; `disabled: Some(true)` refuses the
+    /// tenant's requests.
+    pub async fn admin_update_tenant(&self, tenant_id: &str, req: &UpdateTenantRequest) -> Result<Tenant, Error> {
+        self.send(self.admin_request(Method::PATCH, &format!("/admin/tenants/{}", tenant_id)).json(req)).await
+    }
+
+    // Metrics
+
+    pub async fn admin_get_mrr_metrics(&self, query: &MetricsQuery) -> Result<MrrSeries, Error> {
+        self.send(self.admin_request(Method::GET, "/admin/metrics/mrr").query(query)).await
+    }
+
+    pub async fn admin_get_subscription_metrics(&self, query: &MetricsQuery) -> Result<SubscriptionMetricsSeries, Error> {
+        self.send(self.admin_request(Method::GET, "/admin/metrics/subscriptions").query(query)).await
+    }
+
+    pub async fn admin_get_payment_metrics(&self, query: &MetricsQuery) -> Result<PaymentMetricsSeries, Error> {
+        self.send(self.admin_request(Method::GET, "/admin/metrics/payments").query(query)).await
+    }
+
+    // Support tickets
+
+    pub async fn admin_list_tickets(&self, query: &TicketListQuery) -> Result<Vec<Ticket>, Error> {
+        self.send(self.admin_request(Method::GET, "/admin/tickets").query(query)).await
+    }
+
+    pub async fn admin_get_ticket(&self, ticket_id: &str) -> Result<Ticket, Error> {
+        self.send(self.admin_request(Method::GET, &format!("/admin/tickets/{}", ticket_id))).await
+    }
+
+    pub async fn admin_create_ticket(&self, req: &CreateTicketRequest) -> Result<Ticket, Error> {
+        self.send(self.admin_request(Method::POST, "/admin/tickets").json(req)).await
+    }
+
+    pub async fn admin_update_ticket(&self, ticket_id: &str, req: &UpdateTicketRequest) -> Result<Ticket, Error> {
+        self.send(self.admin_request(Method::PUT, &format!("/admin/tickets/{}", ticket_id)).json(req)).await
+    }
+
+    // Merchant details
+
+    pub async fn admin_get_merchant_details(&self) -> Result<MerchantDetails, Error> {
+        self.send(self.admin_request(Method::GET, "/admin/merchant-details")).await
+    }
+
+    pub async fn admin_update_merchant_details(&self, req: &UpdateMerchantDetailsRequest) -> Result<MerchantDetails, Error> {
+        self.send(self.admin_request(Method::PUT, "/admin/merchant-details").json(req)).await
+    }
+
+    // Domain events
+
+    pub async fn admin_list_events(&self, query: &DomainEventListQuery) -> Result<Vec<DomainEvent>, Error> {
+        self.send(self.admin_request(Method::GET, "/admin/events").query(query)).await
+    }
+
+    pub async fn admin_get_event(&self, event_id: &str) -> Result<DomainEvent, Error> {
+        self.send(self.admin_request(Method::GET, &format!("/admin/events/{}", event_id))).await
+    }
+
+    /// Requeues a failed event; the server answers 409 for any other status.
+    pub async fn admin_redeliver_event(&self, event_id: &str) -> Result<DomainEvent, Error> {
+        self.send(self.admin_request(Method::POST, &format!("/admin/events/{}/redeliver", event_id))).await
+    }
+
+    // Data exports
+
+    /// Most recent export runs first.
+    pub async fn admin_list_export_runs(&self, limit: Option<u32>) -> Result<Vec<ExportRun>, Error> {
+        let mut request = self.admin_request(Method::GET, "/admin/reports/exports");
+        if let Some(limit) = limit {
+            request = request.query(&[("limit", limit)]);
+        }
+        self.send(request).await
+    }
+
+    // Reconciliation
+
+    /// Reconciles a settlement CSV. With `window` (`from`, `to` as RFC 3339),
+    /// payments taken in it that the file leaves out are flagged too.
+    pub async fn admin_upload_settlement_file(&self, csv: String, window: Option<(&str, &str)>) -> Result<ReconciliationReport, Error> {
+        let mut request = self.admin_request(Method::POST, "/admin/reconciliation/upload")
+            .header(reqwest::header::CONTENT_TYPE, "text/csv")
+            .body(csv);
+        if let Some((from, to)) = window {
+            request = request.query(&[("from", from), ("to", to)]);
+        }
+        self.send(request).await
+    }
+
+    /// Most recent reconciliation reports first.
+    pub async fn admin_list_reconciliation_reports(&self, limit: Option<u32>) -> Result<Vec<ReconciliationReport>, Error> {
+        let mut request = self.admin_request(Method::GET, "/admin/reconciliation/reports");
+        if let Some(limit) = limit {
+            request = request.query(&[("limit", limit)]);
+        }
+        self.send(request).await
+    }
+
+    pub async fn admin_get_reconciliation_report(&self, report_id: &str) -> Result<ReconciliationReport, Error> {
+        self.send(self.admin_request(Method::GET, &format!("/admin/reconciliation/reports/{}", report_id))).await
+    }
+
+    // Background jobs
+
+    pub async fn admin_list_jobs(&self, query: &JobListQuery) -> Result<Vec<Job>, Error> {
+        self.send(self.admin_request(Method::GET, "/admin/jobs").query(query)).await
+    }
+
+    pub async fn admin_get_job(&self, job_id: &str) -> Result<Job, Error> {
+        self.send(self.admin_request(Method::GET, &format!("/admin/jobs/{}", job_id))).await
+    }
+
+    /// Requeues a failed job; the server answers 409 for any other status.
+    pub async fn admin_retry_job(&self, job_id: &str) -> Result<Job, Error> {
+        self.send(self.admin_request(Method::POST, &format!("/admin/jobs/{}/retry", job_id))).await
+    }
+
+
+    /// Runs the billing smoke test; the server answers 403 outside sandbox mode.
+    pub async fn admin_run_billing_smoke_test(&self, req: &BillingRunRequest) -> Result<BillingRunReport, Error> {
+        self.send(self.admin_request(Method::POST, "/admin/sandbox/billing-run").json(req)).await
+    }
+
+    /// Runs a QA scenario as written in a scenario file (`name` and `steps`);
+    /// the server answers 403 outside sandbox mode.
+    pub async fn admin_run_scenario(&self, scenario: &serde_json::Value) -> Result<ScenarioReport, Error> {
+        self.send(self.admin_request(Method::POST, "/admin/sandbox/scenarios").json(scenario)).await
+    }
+}
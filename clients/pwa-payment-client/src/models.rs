@@ -0,0 +1,1696 @@
+//! Request and response shapes mirroring the server DTOs in `backend/src`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum PaymentMethod {
+    Card,
+    EFT,
+    Voucher,
+    ScanToPay,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RegisterUserRequest {
+    pub email: String,
+    pub name: String,
+}
+
+/// Profile changes; `None` fields are left as they are.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UpdateUserRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// `en-ZA`, `en-GB`, `en-US` or `af-ZA`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserResponse {
+    pub id: String,
+    pub email: String,
+    pub name: String,
+    /// Language for emails, notifications, invoices and errors, e.g. `af-ZA`.
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// Printed on tax invoices; only returned by `update_billing_details`.
+    #[serde(default)]
+    pub billing_address: Option<String>,
+    #[serde(default)]
+    pub vat_number: Option<String>,
+}
+
+/// Replaces the caller's invoicing details; unset fields are cleared.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BillingDetailsRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub billing_address: Option<String>,
+    /// South African VAT number (10 digits starting with 4).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vat_number: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateSubscriptionRequest {
+    pub user_id: String,
+    pub plan_id: String,
+    /// Defaults to one seat, the owner's.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seat_count: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscriptionDisplay {
+    pub locale: String,
+    pub price: String,
+    #[serde(default)]
+    pub total_price: String,
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    /// "Cancels on …", set while a cancellation is scheduled.
+    #[serde(default)]
+    pub cancels_on: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscriptionResponse {
+    pub id: String,
+    pub user_id: String,
+    pub plan_name: String,
+    /// Per seat.
+    pub price: f64,
+    #[serde(default = "default_seat_count")]
+    pub seat_count: u32,
+    /// `price` for every seat; what each period is billed.
+    #[serde(default)]
+    pub total_price: f64,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub status: String,
+    /// `full`, `free` (downgraded after non-payment, to the free tier or
+    /// `fallback_plan_id`) or `none`.
+    #[serde(default)]
+    pub access: String,
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    /// Day of the month monthly, quarterly and annual plans renew on (the
+    /// last day of shorter months).
+    #[serde(default)]
+    pub billing_anchor_day: Option<u32>,
+    /// False once a cancellation is scheduled or has taken effect.
+    #[serde(default)]
+    pub auto_renew: bool,
+    /// Still active, but ends at `cancellation_effective_at` instead of
+    /// renewing; `reactivate_subscription` undoes this until then.
+    #[serde(default)]
+    pub cancel_at_period_end: bool,
+    /// When the subscription was cancelled.
+    #[serde(default)]
+    pub cancelled_at: Option<String>,
+    /// When the cancellation takes (or took) effect.
+    #[serde(default)]
+    pub cancellation_effective_at: Option<String>,
+    #[serde(default)]
+    pub cancellation_reason: Option<String>,
+    /// Set while the subscription is `Paused`.
+    #[serde(default)]
+    pub paused_at: Option<String>,
+    /// The next renewal extends the period without a charge.
+    #[serde(default)]
+    pub skip_next_renewal: bool,
+    /// Why the subscription has its current status, e.g. `paused_by_user`.
+    #[serde(default)]
+    pub state_reason: Option<String>,
+    /// Plan whose features a downgraded subscription still grants; `None`
+    /// means the free tier.
+    #[serde(default)]
+    pub fallback_plan_id: Option<String>,
+    /// Why the next renewal is likely to fail, e.g. `card_expiring` when the
+    /// saved card expires soon; prompt the user to add a new card.
+    #[serde(default)]
+    pub at_risk_reason: Option<String>,
+    pub display: SubscriptionDisplay,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpcomingInvoiceDisplay {
+    pub billed_on: String,
+    pub amount: String,
+}
+
+/// A renewal the subscription will be charged, and the period it pays for.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpcomingInvoice {
+    pub billed_on: String,
+    pub period_start: String,
+    pub period_end: String,
+    /// Every seat at the current price; metered usage is added when billed.
+    pub amount: f64,
+    pub currency: String,
+    #[serde(default)]
+    pub skipped: bool,
+    pub display: UpcomingInvoiceDisplay,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CancelAt {
+    /// Keep access until the paid period ends.
+    #[default]
+    PeriodEnd,
+    Immediately,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CancelSubscriptionRequest {
+    pub when: CancelAt,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// When a plan change takes effect.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeAt {
+    /// Switch now, prorating the rest of the period.
+    Immediately,
+    /// Keep the current plan until the paid period ends.
+    NextRenewal,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangePlanRequest {
+    pub plan_id: String,
+    /// Omit to upgrade immediately and downgrade at the next renewal.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub when: Option<ChangeAt>,
+}
+
+/// Metered usage to bill with the subscription's next renewal.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportUsageRequest {
+    pub quantity: u64,
+    /// RFC 3339; defaults to now on the server.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recorded_at: Option<String>,
+    /// Reporting the same key twice only counts the usage once.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UsageRecord {
+    pub id: String,
+    pub subscription_id: String,
+    pub user_id: String,
+    pub quantity: u64,
+    pub recorded_at: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SkipRenewalRequest {
+    /// Needed only when the user has more than one active subscription.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subscription_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReactivateSubscriptionRequest {
+    /// Needed only when the user has more than one suspended subscription.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subscription_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payment_method: Option<PaymentMethod>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RestartSubscriptionRequest {
+    /// Win-back coupon taking a percentage off the first new period.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coupon: Option<String>,
+    /// Pay through a new checkout even when a card is saved.
+    pub new_card: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payment_method: Option<PaymentMethod>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InviteMemberRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateSeatsRequest {
+    pub seat_count: u32,
+}
+
+/// Someone sharing a subscription's seats.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscriptionMember {
+    pub id: String,
+    pub subscription_id: String,
+    pub email: String,
+    /// Set when the email belongs to a registered user.
+    #[serde(default)]
+    pub user_id: Option<String>,
+    pub invited_by: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscriptionMembers {
+    pub seat_count: u32,
+    /// Seats left after the owner's and the members'.
+    pub seats_available: u32,
+    pub members: Vec<SubscriptionMember>,
+}
+
+/// Body for `admin_start_token_migration`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateTokenMigrationRequest {
+    /// Where tokens are moving to, e.g. the new Peach entity id.
+    pub target: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenMigration {
+    pub id: String,
+    pub target: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+    pub created_at: String,
+}
+
+/// How many of a migration's flagged cards have been re-authorised.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenMigrationProgress {
+    pub migration: TokenMigration,
+    pub flagged: u64,
+    pub migrated: u64,
+    pub pending: u64,
+    pub dropped: u64,
+    pub percent_complete: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BulkAction {
+    /// Push the end of the current period back by `days`, without a charge.
+    ExtendPeriod { days: u32 },
+    Cancel {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+    },
+}
+
+/// Subscriptions a bulk operation applies to; set at least one condition.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BulkFilter {
+    /// e.g. `Active` or `Pending`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plan_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_more_than_days_ago: Option<u32>,
+}
+
+/// Body for `admin_preview_bulk_operation`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateBulkOperationRequest {
+    pub action: BulkAction,
+    pub filter: BulkFilter,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BulkFailure {
+    pub subscription_id: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BulkOperation {
+    pub id: String,
+    pub action: BulkAction,
+    pub filter: BulkFilter,
+    /// `Previewed`, `Running` or `Completed`.
+    pub status: String,
+    /// Subscriptions the preview matched.
+    pub subscription_ids: Vec<String>,
+    pub matched: u64,
+    pub processed: u64,
+    pub succeeded: u64,
+    /// No longer matched the filter when the operation reached them.
+    pub skipped: u64,
+    pub failures: Vec<BulkFailure>,
+    pub created_at: String,
+    /// Confirm before this.
+    pub expires_at: String,
+    #[serde(default)]
+    pub confirmed_at: Option<String>,
+    #[serde(default)]
+    pub completed_at: Option<String>,
+}
+
+/// VAT-inclusive price difference for switching plans mid-period.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProrationCalculation {
+    pub from_price: f64,
+    pub to_price: f64,
+    pub currency: String,
+    pub period_start: String,
+    pub period_end: String,
+    pub calculated_at: String,
+    pub remaining_fraction: f64,
+    pub unused_credit: f64,
+    pub new_plan_charge: f64,
+    /// Charged to the saved card on upgrade.
+    pub amount_due: f64,
+    /// Issued as a credit note on downgrade.
+    pub credit_amount: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlanChangePreview {
+    pub plan: Plan,
+    pub proration: ProrationCalculation,
+    /// Set when the change would wait for the next renewal.
+    pub effective_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlanChange {
+    pub id: String,
+    pub subscription_id: String,
+    pub from_plan_id: Option<String>,
+    pub from_plan_name: String,
+    pub to_plan_id: String,
+    pub to_plan_name: String,
+    pub to_price: f64,
+    pub proration: ProrationCalculation,
+    pub merchant_transaction_id: Option<String>,
+    pub credit_note_id: Option<String>,
+    /// `Pending` while the proration charge awaits confirmation, then
+    /// `Completed` or `Failed`. Changes at the next renewal are `Scheduled`
+    /// until then, or `Cancelled`.
+    pub status: String,
+    pub failure_reason: Option<String>,
+    #[serde(default)]
+    pub effective_at: Option<String>,
+    pub created_at: String,
+    pub completed_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreditNote {
+    pub id: String,
+    pub credit_note_number: String,
+    pub subscription_id: Option<String>,
+    pub reason: String,
+    pub currency: String,
+    pub subtotal: f64,
+    pub vat_rate_percent: u32,
+    pub vat_amount: f64,
+    pub total: f64,
+    pub issued_at: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlanChangeResponse {
+    pub plan_change: PlanChange,
+    /// Still on the old plan while the change is pending.
+    pub subscription: SubscriptionResponse,
+    pub credit_note: Option<CreditNote>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InitiatePaymentRequest {
+    pub user_id: String,
+    pub subscription_id: String,
+    /// Up to what's still due; less pays part of the period, which starts
+    /// once the rest is paid.
+    pub amount: f64,
+    /// Defaults to the subscription's currency when omitted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payment_method: Option<PaymentMethod>,
+    /// Shopper's country code; selects the region's payment method rules.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    /// Pays a card checkout in 2 to 12 monthly installments. `amount` is
+    /// then the first installment; the rest are charged to the saved card.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub installments: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct InitiatePaymentResponse {
+    /// Gateway handling the checkout, e.g. `ozow` for routed EFT payments.
+    #[serde(default)]
+    pub gateway: Option<String>,
+    /// `None` when wallet credit paid for everything and there is no checkout.
+    #[serde(rename = "checkoutId")]
+    pub checkout_id: Option<String>,
+    #[serde(rename = "merchantTransactionId")]
+    pub merchant_transaction_id: String,
+    #[serde(rename = "registrationId")]
+    pub registration_id: Option<String>,
+    /// Hosted payment page to send the user to, for gateways without a widget.
+    #[serde(rename = "redirectUrl", default)]
+    pub redirect_url: Option<String>,
+    /// Total charged, wallet credit included.
+    #[serde(default)]
+    pub amount: Option<f64>,
+    /// Part of the amount paid from wallet credit; the checkout charges the rest.
+    #[serde(rename = "walletAmount", default)]
+    pub wallet_amount: f64,
+    /// Text the charge will show on the card statement, when one is set.
+    #[serde(rename = "statementDescriptor", default)]
+    pub statement_descriptor: Option<String>,
+    /// `Completed` when wallet credit or the saved card already paid for
+    /// everything; `Pending` while a saved-card charge awaits the gateway.
+    #[serde(default)]
+    pub status: Option<String>,
+    /// Test-mode notice; absent when the gateway charges real money.
+    #[serde(default)]
+    pub banner: Option<String>,
+}
+
+/// Filters and order for `get_payment_history`; unset fields are left out.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PaymentHistoryQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    /// e.g. `Completed` or `Failed`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    /// `YYYY-MM-DD`, inclusive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// `YYYY-MM-DD`, inclusive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    /// `newest_first` (default), `oldest_first`, `largest_first` or `smallest_first`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PaginatedResponse<T> {
+    pub items: Vec<T>,
+    pub page: u32,
+    pub limit: u32,
+    pub total: u64,
+    pub has_more: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Payment {
+    pub id: String,
+    pub user_id: String,
+    pub subscription_id: Option<String>,
+    pub amount: f64,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub status: String,
+    #[serde(default)]
+    pub state_reason: Option<String>,
+    pub payment_method: String,
+    #[serde(default)]
+    pub gateway: Option<String>,
+    pub merchant_transaction_id: String,
+    #[serde(default)]
+    pub wallet_amount: f64,
+    #[serde(default)]
+    pub value_date: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PaymentWaitResponse {
+    pub merchant_transaction_id: String,
+    pub subscription_id: Option<String>,
+    pub status: String,
+    /// Why the payment has its current status, e.g.
+    /// `payment_failed_insufficient_funds`.
+    #[serde(default)]
+    pub state_reason: Option<String>,
+    /// 3-D Secure page to send the shopper to while `status` is
+    /// `AwaitingAuthentication`.
+    #[serde(default)]
+    pub authentication_url: Option<String>,
+    /// Whether the payment has been decided.
+    #[serde(rename = "final")]
+    pub is_final: bool,
+    pub timed_out: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat {
+    #[default]
+    Svg,
+    Png,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct QrImage {
+    pub format: QrFormat,
+    pub mime_type: String,
+    /// Base64-encoded image bytes.
+    pub data: String,
+    pub data_uri: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PaymentQrPoll {
+    pub status_url: String,
+    pub interval_seconds: u32,
+    /// RFC 3339; stop polling and start a new checkout after this.
+    pub expires_at: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PaymentQrResponse {
+    pub merchant_transaction_id: String,
+    /// The checkout link the QR code encodes.
+    pub content: String,
+    pub qr: QrImage,
+    pub poll: PaymentQrPoll,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CardUpdateRequest {
+    pub user_id: String,
+    pub subscription_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CardUpdateResponse {
+    pub gateway: String,
+    #[serde(rename = "checkoutId")]
+    pub checkout_id: String,
+    #[serde(rename = "merchantTransactionId")]
+    pub merchant_transaction_id: String,
+    #[serde(rename = "redirectUrl", default)]
+    pub redirect_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CheckoutFeatures {
+    pub save_card: bool,
+    pub scan_to_pay: bool,
+    pub ozow_eft: bool,
+    pub sandbox_mode: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CheckoutConfig {
+    pub gateway: String,
+    pub entity_id: String,
+    pub script_url: String,
+    /// `sandbox` or `live`.
+    pub environment: String,
+    pub payment_brands: Vec<String>,
+    pub supported_currencies: Vec<String>,
+    pub features: CheckoutFeatures,
+    /// Test-mode notice to show above the checkout; absent when live.
+    #[serde(default)]
+    pub banner: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PaymentValidationIssue {
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PaymentValidationCharge {
+    pub subscription_id: String,
+    pub plan_name: String,
+    /// What's still due for the period.
+    pub amount: f64,
+    pub currency: String,
+    pub display_amount: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PaymentValidation {
+    pub valid: bool,
+    pub errors: Vec<PaymentValidationIssue>,
+    pub warnings: Vec<PaymentValidationIssue>,
+    pub charge: Option<PaymentValidationCharge>,
+}
+
+/// The `balance` of a payment status while its subscription is part-paid.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscriptionBalance {
+    pub amount_due: f64,
+    pub amount_paid: f64,
+    pub outstanding_balance: f64,
+    pub currency: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Installment {
+    pub id: String,
+    pub schedule_id: String,
+    pub subscription_id: String,
+    /// 1 for the installment paid at checkout.
+    pub sequence: u32,
+    pub count: u32,
+    pub amount: f64,
+    pub currency: String,
+    /// `Pending` until the first installment is paid, `Scheduled` until
+    /// charged, then `Paid` or `Failed`. Replaced schedules are `Cancelled`.
+    pub status: String,
+    pub due_at: Option<String>,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub paid_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct InstallmentSchedule {
+    pub schedule_id: String,
+    pub subscription_id: String,
+    pub currency: String,
+    pub total_amount: f64,
+    pub paid_amount: f64,
+    pub remaining_amount: f64,
+    pub remaining_count: u32,
+    pub next_due_at: Option<String>,
+    pub installments: Vec<Installment>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecurringChargeRequest {
+    pub user_id: String,
+    pub amount: f64,
+    pub currency: String,
+    pub initial_transaction_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct RefundRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Credit the user's wallet instead of refunding to the card.
+    pub to_wallet: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ManualPaymentRequest {
+    pub subscription_id: String,
+    /// Defaults to the subscription's price.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    /// `YYYY-MM-DD` the funds cleared; may be back- or forward-dated.
+    pub value_date: String,
+    pub reference: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proof_attachment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payment_method: Option<PaymentMethod>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RefundResponse {
+    pub refund_transaction_id: String,
+    pub merchant_transaction_id: String,
+    pub amount: f64,
+    pub status: String,
+    /// Gateway result; absent for refunds to the wallet.
+    #[serde(default)]
+    pub result_code: Option<String>,
+    #[serde(default)]
+    pub to_wallet: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Plan {
+    pub id: String,
+    pub name: String,
+    pub price: f64,
+    /// When false, VAT at the catalog's `vat_rate_percent` is added to `price`.
+    #[serde(default = "default_true")]
+    pub prices_include_vat: bool,
+    pub currency: String,
+    pub interval: String,
+    /// Length of a `Custom` interval.
+    #[serde(default)]
+    pub interval_days: Option<u32>,
+    #[serde(default)]
+    pub trial_days: u32,
+    pub grace_period_days: Option<u32>,
+    #[serde(default)]
+    pub suspension_policy: Option<SuspensionPolicy>,
+    /// Card statement text for charges on this plan (5-22 characters).
+    #[serde(default)]
+    pub statement_descriptor: Option<String>,
+    /// Set on metered plans, which also bill usage beyond `included_units`.
+    #[serde(default)]
+    pub usage_pricing: Option<UsagePricing>,
+    /// Renewals a subscriber may skip in any 365 days; 0 disables skipping.
+    #[serde(default)]
+    pub max_skips_per_year: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UsagePricing {
+    pub unit: String,
+    #[serde(default)]
+    pub included_units: u64,
+    /// VAT-inclusive price per unit beyond `included_units`.
+    pub unit_price: f64,
+}
+
+/// What happens to an unpaid subscription after grace.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum SuspensionPolicy {
+    Suspend {
+        days_after_grace: u32,
+        /// Off one period's price when a suspended user pays to reactivate.
+        #[serde(default)]
+        reactivation_discount_percent: u32,
+    },
+    Downgrade,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlanFeature {
+    pub plan_id: String,
+    pub feature_key: String,
+    /// `None` grants the feature without a limit.
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SetPlanFeatureRequest {
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct FeatureEntitlement {
+    pub key: String,
+    /// `None` means unlimited.
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserEntitlements {
+    pub user_id: String,
+    /// `full`, `free` or `none`.
+    pub access: String,
+    pub plan_ids: Vec<String>,
+    pub features: Vec<FeatureEntitlement>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PaymentMethodRule {
+    pub payment_method: PaymentMethod,
+    /// Country code, or `*` for regions without their own rule.
+    pub region: String,
+    pub enabled: bool,
+    #[serde(default)]
+    pub currencies: Vec<String>,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SetPaymentMethodRuleRequest {
+    pub enabled: bool,
+    /// Empty accepts any currency.
+    pub currencies: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_amount: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_amount: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PaymentMethodQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MethodAvailability {
+    pub payment_method: PaymentMethod,
+    pub available: bool,
+    /// Why the method can't be used, when it can't.
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub min_amount: Option<f64>,
+    #[serde(default)]
+    pub max_amount: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CreatePlanRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub name: String,
+    pub price: f64,
+    pub prices_include_vat: bool,
+    pub currency: String,
+    /// `Weekly`, `Monthly`, `Quarterly`, `Annual` or `Custom`.
+    pub interval: String,
+    /// Required with, and only allowed with, a `Custom` interval.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval_days: Option<u32>,
+    pub trial_days: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grace_period_days: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suspension_policy: Option<SuspensionPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statement_descriptor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage_pricing: Option<UsagePricing>,
+    pub max_skips_per_year: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UpdatePlanRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prices_include_vat: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval_days: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trial_days: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grace_period_days: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suspension_policy: Option<SuspensionPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statement_descriptor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage_pricing: Option<UsagePricing>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_skips_per_year: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlanCatalog {
+    pub version: String,
+    #[serde(default)]
+    pub vat_rate_percent: u32,
+    pub plans: Vec<Plan>,
+}
+
+fn default_seat_count() -> u32 {
+    1
+}
+
+fn default_currency() -> String {
+    "ZAR".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NotificationResponse {
+    pub id: String,
+    pub user_id: String,
+    pub subscription_id: String,
+    pub message: String,
+    pub acknowledged: bool,
+    pub action_type: Option<String>,
+    pub action_payload: Option<serde_json::Value>,
+    /// e.g. `renewal_due`; `None` for test notifications.
+    #[serde(default)]
+    pub event: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NotificationsQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    /// Only notifications that haven't been acknowledged.
+    pub unread: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UnreadCountResponse {
+    pub unread: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AcknowledgeAllResponse {
+    pub acknowledged: usize,
+}
+
+/// The channels one kind of notification is delivered on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventPreference {
+    /// e.g. `payment_succeeded` or `renewal_due`.
+    pub event: String,
+    pub email: bool,
+    pub push: bool,
+    pub in_app: bool,
+    pub sms: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NotificationPreferences {
+    pub user_id: String,
+    pub events: Vec<EventPreference>,
+}
+
+/// Replaces the choices for the listed events only.
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateNotificationPreferencesRequest {
+    pub events: Vec<EventPreference>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TestNotificationRequest {
+    pub user_id: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MessageResponse {
+    pub message: String,
+}
+
+/// An email captured by a server running with `EMAIL_PROVIDER=memory`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SentEmail {
+    pub to_address: String,
+    pub to_name: String,
+    pub event: String,
+    pub subject: String,
+    pub body: String,
+    pub sent_at: String,
+}
+
+/// Metadata for an uploaded file such as a proof of payment.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Attachment {
+    pub id: String,
+    /// `payment` when linked; unlinked uploads have neither owner field.
+    pub owner_type: Option<String>,
+    pub owner_id: Option<String>,
+    pub filename: String,
+    pub content_type: String,
+    pub size_bytes: u64,
+    pub sha256: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AttachmentResponse {
+    pub attachment: Attachment,
+    /// Signed, short-lived link that needs no credentials.
+    pub download_url: String,
+}
+
+/// Body of a sandbox billing run; unset fields take the server defaults
+/// (50 subscriptions, 10% soft and 5% hard declines).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BillingRunRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub soft_decline_percent: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hard_decline_percent: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BillingRunFailure {
+    pub subscription_id: Option<String>,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BillingRunReport {
+    pub run_id: String,
+    pub tag: String,
+    pub requested: u32,
+    pub processed: usize,
+    pub duration_ms: u64,
+    pub throughput_per_sec: f64,
+    /// Renewals per outcome: `renewed`, `declined`, `gateway_error`, `no_token`, `failed`.
+    pub outcomes: BTreeMap<String, usize>,
+    pub final_statuses: BTreeMap<String, usize>,
+    pub failures: Vec<BillingRunFailure>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScenarioStepResult {
+    pub index: usize,
+    /// e.g. `initiate_payment`, `payment_webhook` or `expect`.
+    pub action: String,
+    pub at_ms: u64,
+    pub finished_ms: u64,
+    #[serde(default)]
+    pub merchant_transaction_id: Option<String>,
+    /// How a delivered webhook was handled, e.g. `Processed` or `Skipped`.
+    #[serde(default)]
+    pub outcome: Option<String>,
+    pub passed: bool,
+    #[serde(default)]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScenarioReport {
+    pub run_id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub started_at: String,
+    pub steps: Vec<ScenarioStepResult>,
+    pub passed: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WalletBalance {
+    pub currency: String,
+    pub balance: f64,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WalletEntry {
+    pub id: String,
+    pub currency: String,
+    /// Positive for credit, negative when credit was spent.
+    pub amount: f64,
+    pub balance_after: f64,
+    /// `Proration`, `Refund`, `Payment`, `Renewal` or `Reversal`.
+    pub source: String,
+    pub reference: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Wallet {
+    pub user_id: String,
+    pub balances: Vec<WalletBalance>,
+    /// Newest first.
+    pub entries: Vec<WalletEntry>,
+}
+
+/// Window for the admin metrics endpoints; unset fields take the server
+/// defaults (daily buckets over the last 30 days).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MetricsQuery {
+    /// `YYYY-MM-DD`, widened back to the start of its bucket.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// `YYYY-MM-DD`, inclusive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    /// `day` (default), `week` or `month`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bucket: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MrrPoint {
+    pub start: String,
+    pub end: String,
+    pub mrr: f64,
+    pub paying_subscriptions: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MrrSeries {
+    pub bucket: String,
+    pub currency: String,
+    /// Currencies with no exchange rate; left out of `mrr`.
+    pub unconverted_currencies: Vec<String>,
+    pub points: Vec<MrrPoint>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscriptionMetricsPoint {
+    pub start: String,
+    pub end: String,
+    pub active_at_start: u64,
+    pub new_subscriptions: u64,
+    pub cancellations: u64,
+    pub churn_rate: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscriptionMetricsSeries {
+    pub bucket: String,
+    pub points: Vec<SubscriptionMetricsPoint>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PaymentMetricsPoint {
+    pub start: String,
+    pub end: String,
+    pub succeeded: u64,
+    pub failed: u64,
+    pub failed_rate: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PaymentMetricsSeries {
+    pub bucket: String,
+    pub points: Vec<PaymentMetricsPoint>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResultCodeStat {
+    pub code: String,
+    /// `Success`, `Pending`, `Cancelled`, `Declined` or `Unhandled`.
+    pub category: String,
+    pub count: u64,
+    pub first_seen_at: String,
+    pub last_seen_at: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResultCodeCounts {
+    pub success: u64,
+    pub pending: u64,
+    pub cancelled: u64,
+    pub declined: u64,
+    pub unhandled: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookCodeMetrics {
+    pub counts: ResultCodeCounts,
+    pub unhandled_codes: u64,
+    /// Most recently seen first.
+    pub recent_unhandled: Vec<ResultCodeStat>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Ticket {
+    pub id: String,
+    pub user_id: String,
+    /// `failed_payment`, `dispute`, `unrecognized_charge` or `manual`.
+    pub source: String,
+    /// `open`, `in_progress`, `resolved` or `closed`.
+    pub status: String,
+    pub subject: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Merchant transaction id of the payment concerned.
+    #[serde(default)]
+    pub payment_id: Option<String>,
+    #[serde(default)]
+    pub subscription_id: Option<String>,
+    #[serde(default)]
+    pub assignee: Option<String>,
+    /// Billing events folded into the ticket, the first included.
+    #[serde(default)]
+    pub occurrences: u32,
+    #[serde(default)]
+    pub resolved_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Filters for the admin ticket list; unset fields match everything.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TicketListQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assignee: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateTicketRequest {
+    pub user_id: String,
+    pub subject: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payment_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subscription_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assignee: Option<String>,
+}
+
+/// Unset fields are left alone; an empty `assignee` unassigns the ticket.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UpdateTicketRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assignee: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReportChargeRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChargeReport {
+    pub ticket_id: String,
+    pub status: String,
+}
+
+/// A queued payment side-effect (receipt email, invoice, support ticket).
+#[derive(Debug, Clone, Deserialize)]
+pub struct Job {
+    pub id: String,
+    /// e.g. `issue_invoice` or `payment_succeeded_email`.
+    pub kind: String,
+    /// The job's arguments, tagged with `type` (the same as `kind`).
+    pub payload: serde_json::Value,
+    /// `pending`, `running`, `succeeded` or `failed`.
+    pub status: String,
+    /// Merchant transaction id of the payment the job is about.
+    #[serde(default)]
+    pub reference: Option<String>,
+    #[serde(default)]
+    pub attempts: u32,
+    pub max_attempts: u32,
+    pub run_at: String,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub started_at: Option<String>,
+    #[serde(default)]
+    pub completed_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Filters for the admin job list; unset fields match everything.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JobListQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+}
+
+/// A payment or subscription change published to the event sinks.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DomainEvent {
+    pub id: String,
+    /// e.g. `payment.completed`, `subscription.renewed` or `subscription.suspended`.
+    pub event_type: String,
+    /// Id of the payment or subscription the event is about.
+    pub aggregate_id: String,
+    pub payload: serde_json::Value,
+    /// `pending`, `published` or `failed`.
+    pub status: String,
+    #[serde(default)]
+    pub attempts: u32,
+    pub next_attempt_at: String,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub published_at: Option<String>,
+    pub created_at: String,
+}
+
+/// Filters for the admin event list; unset fields match everything.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DomainEventListQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateWebhookEndpointRequest {
+    /// Must be https outside sandbox mode.
+    pub url: String,
+    /// Empty for every event type.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub event_types: Vec<String>,
+}
+
+/// A URL registered to receive the user's payment and subscription events.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookEndpoint {
+    pub id: String,
+    pub user_id: String,
+    pub url: String,
+    #[serde(default)]
+    pub event_types: Vec<String>,
+    pub created_at: String,
+}
+
+/// A newly registered endpoint and the secret its deliveries are signed
+/// with; the server doesn't return the secret again.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookEndpointCreated {
+    #[serde(flatten)]
+    pub endpoint: WebhookEndpoint,
+    pub secret: String,
+}
+
+/// One event sent, or being sent, to a webhook endpoint.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub endpoint_id: String,
+    pub event_id: String,
+    pub event_type: String,
+    /// `pending`, `succeeded` or `failed`.
+    pub status: String,
+    #[serde(default)]
+    pub attempts: u32,
+    pub next_attempt_at: String,
+    #[serde(default)]
+    pub response_status: Option<u16>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub delivered_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// One CSV file written by a data-lake export run.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExportFile {
+    pub table: String,
+    /// Storage key of the file.
+    pub key: String,
+    pub rows: u64,
+    pub columns: Vec<String>,
+    /// Hex SHA-256 of the file contents.
+    pub sha256: String,
+}
+
+/// A nightly data-lake export run.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExportRun {
+    pub id: String,
+    /// `completed` or `failed`.
+    pub status: String,
+    /// Unset on the first, full export.
+    #[serde(default)]
+    pub window_start: Option<String>,
+    pub window_end: String,
+    #[serde(default)]
+    pub manifest_key: Option<String>,
+    #[serde(default)]
+    pub files: Vec<ExportFile>,
+    #[serde(default)]
+    pub error: Option<String>,
+    pub started_at: String,
+    pub completed_at: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReconciliationMismatch {
+    pub merchant_transaction_id: String,
+    /// `missing_locally`, `missing_at_gateway`, `amount_differs` or
+    /// `status_differs`.
+    pub kind: String,
+    #[serde(default)]
+    pub local_amount: Option<f64>,
+    #[serde(default)]
+    pub gateway_amount: Option<f64>,
+    #[serde(default)]
+    pub currency: Option<String>,
+    #[serde(default)]
+    pub local_status: Option<String>,
+    #[serde(default)]
+    pub gateway_status: Option<String>,
+}
+
+/// Payments as the gateway reports them, compared with the server's.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReconciliationReport {
+    pub id: String,
+    /// `gateway` for the daily run, `upload` for a settlement file.
+    pub source: String,
+    #[serde(default)]
+    pub tenant_id: Option<String>,
+    #[serde(default)]
+    pub window_start: Option<String>,
+    #[serde(default)]
+    pub window_end: Option<String>,
+    pub records: u32,
+    pub matched: u32,
+    #[serde(default)]
+    pub mismatches: Vec<ReconciliationMismatch>,
+    pub created_at: String,
+}
+
+/// Supplier details printed on tax invoices.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MerchantDetails {
+    pub legal_name: String,
+    #[serde(default)]
+    pub vat_number: Option<String>,
+    #[serde(default)]
+    pub registration_number: Option<String>,
+    #[serde(default)]
+    pub address: Option<String>,
+    /// Unset while the details still come from server config.
+    #[serde(default)]
+    pub updated_at: Option<String>,
+}
+
+/// Replaces the merchant details; unset optional fields are cleared.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UpdateMerchantDetailsRequest {
+    pub legal_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vat_number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub registration_number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+}
+
+/// Filters for `admin_list_audit_log`; unset fields match everything.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AuditLogQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    /// `user`, `admin`, `anonymous` or `system`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actor_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actor_id: Option<String>,
+    /// e.g. `subscription.cancelled`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    /// `users`, `subscriptions` or `payments`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entity_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entity_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub impersonation_session_id: Option<String>,
+    /// `YYYY-MM-DD`, inclusive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// `YYYY-MM-DD`, inclusive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuditEntry {
+    pub id: String,
+    pub actor_type: String,
+    #[serde(default)]
+    pub actor_id: Option<String>,
+    pub action: String,
+    pub entity_type: String,
+    pub entity_id: String,
+    #[serde(default)]
+    pub old_value: Option<serde_json::Value>,
+    #[serde(default)]
+    pub new_value: Option<serde_json::Value>,
+    #[serde(default)]
+    pub ip_address: Option<String>,
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub request_id: Option<String>,
+    #[serde(default)]
+    pub impersonation_session_id: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StartImpersonationRequest {
+    pub reason: String,
+    /// `read_only` (default) or `full`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// 15 by default, at most 60.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_minutes: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImpersonationStarted {
+    pub id: String,
+    pub user_id: String,
+    pub reason: String,
+    pub scope: String,
+    pub expires_at: String,
+    pub created_at: String,
+    /// Only shown here.
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub prefix: String,
+    /// `read_only`, `payments` or `admin`.
+    pub scopes: Vec<String>,
+    #[serde(default)]
+    pub previous_expires_at: Option<String>,
+    #[serde(default)]
+    pub last_used_at: Option<String>,
+    #[serde(default)]
+    pub rotated_at: Option<String>,
+    #[serde(default)]
+    pub revoked_at: Option<String>,
+    #[serde(default)]
+    pub tenant_id: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    /// `read_only`, `payments` or `admin`.
+    pub scopes: Vec<String>,
+    /// Issues the key for a tenant.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiKeyCreated {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    /// Only shown here.
+    pub key: String,
+}
+
+/// The secrets are write-only: `Tenant` never includes them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PeachCredentials {
+    pub entity_id: String,
+    pub client_id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub client_secret: String,
+    pub merchant_id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub webhook_secret_key: String,
+    /// Required when sending credentials; must be on the tenant's subdomain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notification_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shopper_result_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Tenant {
+    pub id: String,
+    pub name: String,
+    pub subdomain: String,
+    #[serde(default)]
+    pub peach: Option<PeachCredentials>,
+    #[serde(default)]
+    pub disabled_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CreateTenantRequest {
+    pub name: String,
+    pub subdomain: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peach: Option<PeachCredentials>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UpdateTenantRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peach: Option<PeachCredentials>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disabled: Option<bool>,
+}
+
+/// Body of a 422 for a request body that failed validation.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ValidationErrorResponse {
+    pub error: String,
+    /// Problems by field; nested fields are dotted, e.g. `peach.client_id`.
+    pub fields: BTreeMap<String, Vec<String>>,
+}
@@ -3,7 +3,8 @@
 // Configuration - can be overridden by environment
 const config = {
     apiBaseUrl: window.ENV?.API_BASE_URL || 'http://127.0.0.1:8080/api/v1',
-    peachEntityId: window.ENV?.PEACH_ENTITY_ID || "8ac7a4c8961da56701961e61c57a0241"
+    // Filled in from /payments/checkout-config unless set explicitly
+    peachEntityId: window.ENV?.PEACH_ENTITY_ID || null
 };
 
 const API_BASE_URL = config.apiBaseUrl; 
@@ -18,6 +19,53 @@ let currentSubscriptionStatus = localStorage.getItem('currentSubscriptionStatus'
 let activeTimeouts = new Set();
 
 
+// --- Checkout widget ---
+let checkoutLoader = null;
+
+// Loads the embedded checkout script for the backend's environment once,
+// so sandbox and live URLs aren't hard-coded here
+function ensureCheckoutLoaded() {
+    if (typeof Checkout !== 'undefined' && config.peachEntityId) {
+        return Promise.resolve();
+    }
+    if (!checkoutLoader) {
+        checkoutLoader = fetch(`${API_BASE_URL}/payments/checkout-config`)
+            .then(response => {
+                if (!response.ok) {
+                    throw new Error('Embedded checkout is not available');
+                }
+                return response.json();
+            })
+            .then(checkoutConfig => new Promise((resolve, reject) => {
+                config.peachEntityId = config.peachEntityId || checkoutConfig.entity_id;
+                showTestModeBanner(checkoutConfig.banner);
+                const script = document.createElement('script');
+                script.src = checkoutConfig.script_url;
+                script.onload = resolve;
+                script.onerror = () => reject(new Error('Peach Payments checkout not loaded'));
+                document.head.appendChild(script);
+            }))
+            .catch(error => {
+                checkoutLoader = null;
+                throw error;
+            });
+    }
+    return checkoutLoader;
+}
+
+// Warns above the checkout when the backend isn't charging real money
+function showTestModeBanner(text) {
+    const container = document.getElementById('checkout-container');
+    if (!text || !container || document.getElementById('testModeBanner')) {
+        return;
+    }
+    const banner = document.createElement('div');
+    banner.id = 'testModeBanner';
+    banner.className = 'message info';
+    banner.textContent = text;
+    container.parentNode.insertBefore(banner, container);
+}
+
 // --- Utility Functions ---
 function validateEmail(email) {
     const emailRegex = /^[^\s@]+@[^\s@]+\.[^\s@]+$/;
@@ -34,7 +82,7 @@ function sanitizeInput(input) {
 }
 
 function handleApiError(response, defaultMessage) {
-    if (response.status === 400) {
+    if (response.status === 400 || response.status === 422) {
         return 'Invalid input. Please check your data and try again.';
     } else if (response.status === 404) {
         return 'Resource not found. Please verify the information.';
@@ -192,34 +240,12 @@ async function createSubscription() {
     }
 
     const selectElement = document.getElementById('subscriptionPlan');
-    const planName = selectElement.value;
-    
-    let price;
-    try {
-        const selectedOption = selectElement.options[selectElement.selectedIndex];
-        const priceMatch = selectedOption.text.match(/\((R\d+)\)/);
-        
-        if (!priceMatch) {
-            showMessage('subscriptionMessage', 'Could not extract price from selected plan.', 'error');
-            return;
-        }
-        
-        price = parseFloat(priceMatch[1].substring(1));
-        
-        if (isNaN(price) || price <= 0) {
-            showMessage('subscriptionMessage', 'Invalid price detected.', 'error');
-            return;
-        }
-    } catch (error) {
-        console.error('Error extracting price:', error);
-        showMessage('subscriptionMessage', 'Error processing subscription plan.', 'error');
-        return;
-    }
+    const planId = selectElement.value;
 
+    // The server derives price and terms from the plan
     const requestData = {
         user_id: currentUserId,
-        plan_name: planName,
-        price: price
+        plan_id: planId
     };
 
     try {
@@ -282,14 +308,27 @@ async function initiatePayment() {
         const container = document.getElementById('checkout-container');
         container.innerHTML = '';
 
+        const paymentRequest = JSON.stringify({
+            user_id: currentUserId,
+            subscription_id: currentSubscriptionId,
+            amount: amount,
+        });
+
+        // Surface problems before the checkout widget is loaded
+        const validation = await fetch(`${API_BASE_URL}/payments/validate`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: paymentRequest
+        }).then(res => res.json());
+
+        if (!validation.valid) {
+            throw new Error(validation.errors.map(e => e.message).join(' '));
+        }
+
         const response = await fetch(`${API_BASE_URL}/payments/initiate`, {
             method: 'POST',
             headers: { 'Content-Type': 'application/json' },
-            body: JSON.stringify({
-                user_id: currentUserId,
-                subscription_id: currentSubscriptionId,
-                amount: amount,
-            })
+            body: paymentRequest
         });
         
         const data = await response.json();
@@ -311,12 +350,16 @@ async function initiatePayment() {
         };
         localStorage.setItem('currentPayment', JSON.stringify(window.currentPayment));
 
+        // Hosted-page gateways (e.g. Stripe) take over from here
+        if (data.redirectUrl) {
+            showMessage('paymentInitiateMessage', 'Redirecting to secure payment page...', 'info');
+            window.location.href = data.redirectUrl;
+            return;
+        }
+
         showMessage('paymentInitiateMessage', 'Loading payment form...', 'info');
 
-        // Verify Checkout is available
-        if (typeof Checkout === 'undefined') {
-            throw new Error('Peach Payments checkout not loaded');
-        }
+        await ensureCheckoutLoaded();
 
         const entityId = config.peachEntityId;
         
@@ -417,7 +460,8 @@ async function checkNotifications() {
 
         const data = await response.json();
         if (response.ok) {
-            displayNotifications(data);
+            displayNotifications(data.items);
+            updateUnreadBadge();
         } else {
             showMessage('notificationMessage', `Error: ${data.message || 'Failed to fetch notifications'}`, 'error');
         }
@@ -465,6 +509,15 @@ function displayNotifications(notifications) {
         notificationDiv.appendChild(dateP);
         notificationDiv.appendChild(statusP);
         
+        const actionLabel = NOTIFICATION_ACTION_LABELS[notification.action_type];
+        if (actionLabel) {
+            const actionBtn = document.createElement('button');
+            actionBtn.className = 'action-btn';
+            actionBtn.textContent = actionLabel;
+            actionBtn.onclick = () => handleNotificationAction(notification);
+            notificationDiv.appendChild(actionBtn);
+        }
+        
         if (!notification.acknowledged) {
             const ackBtn = document.createElement('button');
             ackBtn.className = 'ack-btn';
@@ -480,6 +533,102 @@ function displayNotifications(notifications) {
     showMessage('notificationMessage', `Found ${notifications.length} notifications`, 'success');
 }
 
+const NOTIFICATION_ACTION_LABELS = {
+    open_renewal: 'Renew now',
+    update_card: 'Update card',
+    view_payment: 'View payment'
+};
+
+// Route a notification tap to the screen its action_type points at
+function handleNotificationAction(notification) {
+    const payload = notification.action_payload || {};
+
+    if (payload.subscription_id) {
+        currentSubscriptionId = payload.subscription_id;
+        localStorage.setItem('currentSubscriptionId', currentSubscriptionId);
+    }
+
+    let targetId = null;
+    switch (notification.action_type) {
+        case 'open_renewal':
+            targetId = 'renewSubscriptionBtn';
+            break;
+        case 'update_card':
+            startCardUpdate();
+            return;
+        case 'view_payment':
+            if (payload.merchant_transaction_id) {
+                window.location.href = `payment-result.html?id=${encodeURIComponent(payload.merchant_transaction_id)}`;
+                return;
+            }
+            break;
+    }
+
+    const target = targetId && document.getElementById(targetId);
+    if (target) {
+        target.scrollIntoView({ behavior: 'smooth', block: 'center' });
+        target.focus();
+    }
+}
+
+// Save a new card for renewals without buying anything; the old card stays
+// in use until the gateway confirms the new one
+async function startCardUpdate() {
+    hideMessage('paymentInitiateMessage');
+
+    if (!currentUserId || !currentSubscriptionId) {
+        showMessage('paymentInitiateMessage', 'Please log in and select a subscription first.', 'error');
+        return;
+    }
+
+    try {
+        const response = await fetch(`${API_BASE_URL}/payments/card-update`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({
+                user_id: currentUserId,
+                subscription_id: currentSubscriptionId
+            })
+        });
+        const data = await response.json();
+
+        if (!response.ok) {
+            throw new Error(data.message || 'Failed to start card update');
+        }
+
+        if (data.redirectUrl) {
+            window.location.href = data.redirectUrl;
+            return;
+        }
+
+        await ensureCheckoutLoaded();
+
+        const container = document.getElementById('checkout-container');
+        container.innerHTML = '';
+        showMessage('paymentInitiateMessage', 'Enter your new card details. You will not be charged.', 'info');
+
+        const checkout = Checkout.initiate({
+            key: config.peachEntityId,
+            checkoutId: data.checkoutId,
+            events: {
+                onCompleted: () => {
+                    showMessage('paymentInitiateMessage', 'Card submitted. We will email you once it has been verified.', 'success');
+                },
+                onCancelled: () => {
+                    showMessage('paymentInitiateMessage', 'Card update cancelled; your current card is unchanged.', 'error');
+                },
+                onExpired: () => {
+                    showMessage('paymentInitiateMessage', 'Card update session expired', 'error');
+                },
+            },
+        });
+        checkout.render("#checkout-container");
+    } catch (error) {
+        console.error('Card update error:', error);
+        showMessage('paymentInitiateMessage', `Error: ${error.message}`, 'error');
+    }
+}
+
 async function acknowledgeNotification(notificationId) {
     try {
         const response = await fetch(`${API_BASE_URL}/notifications/${notificationId}/acknowledge`, {
@@ -500,6 +649,47 @@ async function acknowledgeNotification(notificationId) {
     }
 }
 
+async function updateUnreadBadge() {
+    const badge = document.getElementById('unreadBadge');
+    try {
+        const response = await fetch(`${API_BASE_URL}/notifications/unread-count`, {
+            headers: { 'X-User-Id': currentUserId }
+        });
+        if (response.ok) {
+            const data = await response.json();
+            badge.textContent = data.unread > 0 ? `(${data.unread})` : '';
+        }
+    } catch (error) {
+        console.error('Error counting notifications:', error);
+    }
+}
+
+async function acknowledgeAllNotifications() {
+    if (!currentUserId) {
+        showMessage('notificationMessage', 'Please log in to check notifications.', 'error');
+        return;
+    }
+
+    try {
+        const response = await fetch(`${API_BASE_URL}/notifications/acknowledge-all`, {
+            method: 'POST',
+            headers: { 'X-User-Id': currentUserId }
+        });
+
+        if (response.ok) {
+            const data = await response.json();
+            showMessage('notificationMessage', `${data.acknowledged} notifications marked as read`, 'success');
+            checkNotifications();
+        } else {
+            const data = await response.json();
+            showMessage('notificationMessage', `Error: ${data.error || 'Failed to acknowledge notifications'}`, 'error');
+        }
+    } catch (error) {
+        console.error('Error acknowledging notifications:', error);
+        showMessage('notificationMessage', 'An error occurred while updating notifications.', 'error');
+    }
+}
+
 // Add subscription renewal function
 async function renewSubscription() {
     if (!currentSubscriptionId) {